@@ -0,0 +1,75 @@
+//! Graphs and gossip shared by the benchmarks
+
+use rand::seq::SliceRandom;
+use rand_chacha::{rand_core::SeedableRng, ChaCha8Rng};
+use rust_hashgraph::algorithm::{
+    datastructure::Graph,
+    event::{self, SignedEvent},
+    IncrementalClock, MockSigner, Signer,
+};
+
+pub type BenchEvent = SignedEvent<(), (), usize>;
+
+/// Geneses followed by `n_events` regular events with random authors and
+/// other parents. The same for the same arguments.
+pub fn generate_events(n_peers: usize, n_events: usize) -> Vec<BenchEvent> {
+    let author_ids: Vec<_> = (0..n_peers).collect();
+    // for reproducibility use seed
+    let mut pseudo_rng = ChaCha8Rng::seed_from_u64(1);
+    let mock_signer = MockSigner::<usize, ()>::new();
+    let mut events = vec![];
+    let mut tips = vec![];
+    for author_id in author_ids.iter() {
+        // Same as the one created by `Graph::new`
+        let genesis = SignedEvent::new((), event::Kind::Genesis(()), *author_id, 0, |h| {
+            mock_signer.sign(h)
+        })
+        .expect("Failed to create event");
+        tips.push(genesis.hash().clone());
+        events.push(genesis);
+    }
+    for timestamp in 1..=n_events {
+        let author = *author_ids.choose(&mut pseudo_rng).unwrap();
+        let from_author = *author_ids.choose(&mut pseudo_rng).unwrap();
+        let parents = event::Parents {
+            self_parent: tips[author].clone(),
+            other_parent: tips[from_author].clone(),
+        };
+        let new_event = SignedEvent::new(
+            (),
+            event::Kind::Regular(parents),
+            author,
+            timestamp as u128,
+            |h| mock_signer.sign(h),
+        )
+        .expect("Failed to create event");
+        tips[author] = new_event.hash().clone();
+        events.push(new_event);
+    }
+    events
+}
+
+/// Graph of peer 0 with only its genesis. The signer has to sign like
+/// [`MockSigner`] for the generated events to be accepted.
+pub fn empty_graph<TSigner>() -> Graph<(), (), usize, TSigner, IncrementalClock>
+where
+    TSigner: Signer<(), SignerIdentity = usize> + Default,
+{
+    Graph::new(0, (), (), 999, TSigner::default(), IncrementalClock::new())
+}
+
+pub fn push_all<TSigner>(
+    graph: &mut Graph<(), (), usize, TSigner, IncrementalClock>,
+    events: &[BenchEvent],
+) where
+    TSigner: Signer<(), SignerIdentity = usize>,
+{
+    for event in events {
+        if graph.event(event.hash()).is_some() {
+            // Own genesis
+            continue;
+        }
+        let (unsigned, signature) = event.clone().into_parts();
+        graph.push_event(unsigned, signature).unwrap();
+    }
+}
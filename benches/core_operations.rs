@@ -0,0 +1,166 @@
+use criterion::{
+    black_box, criterion_group, criterion_main, measurement::WallTime, BatchSize, BenchmarkGroup,
+    BenchmarkId, Criterion, SamplingMode, Throughput,
+};
+use rust_hashgraph::algorithm::{datastructure::Graph, IncrementalClock, MockSigner};
+
+mod common;
+use common::{generate_events, push_all, BenchEvent};
+
+type BenchGraph = Graph<(), (), usize, MockSigner<usize, ()>, IncrementalClock>;
+
+const N_PEERS: usize = 4;
+
+/// Graph sizes (in regular events)
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+
+/// Operations on graphs of 100k events take long, so they get the fewest
+/// samples criterion allows, with the same number of iterations in each
+fn large_samples(group: &mut BenchmarkGroup<'_, WallTime>, n_events: usize) {
+    if n_events >= 100_000 {
+        group.sample_size(10).sampling_mode(SamplingMode::Flat);
+    }
+}
+
+fn empty_graph() -> BenchGraph {
+    common::empty_graph()
+}
+
+fn build_graph(events: &[BenchEvent]) -> BenchGraph {
+    let mut graph = empty_graph();
+    push_all(&mut graph, events);
+    graph
+}
+
+fn push_event(c: &mut Criterion) {
+    let mut group = c.benchmark_group("push_event");
+    group.sample_size(10);
+    for n_events in SIZES {
+        large_samples(&mut group, n_events);
+        let events = generate_events(N_PEERS, n_events);
+        group.throughput(Throughput::Elements(n_events as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(n_events),
+            &events,
+            |b, events| {
+                b.iter_batched(
+                    empty_graph,
+                    |mut graph| push_all(&mut graph, black_box(events)),
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+fn strongly_see(c: &mut Criterion) {
+    let mut group = c.benchmark_group("strongly_see");
+    for n_events in SIZES {
+        large_samples(&mut group, n_events);
+        let events = generate_events(N_PEERS, n_events);
+        let graph = build_graph(&events);
+        // Latest event and one a few rounds behind it
+        let observer = events.last().unwrap().hash();
+        let target = events[events.len() - 10 * N_PEERS].hash();
+        group.bench_function(BenchmarkId::from_parameter(n_events), |b| {
+            b.iter(|| graph.strongly_see(black_box(observer), black_box(target)))
+        });
+    }
+    group.finish();
+}
+
+/// Pushing the events that make the next round decided, which is dominated
+/// by the fame election
+fn fame_election(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fame_election");
+    group.sample_size(10);
+    for n_events in SIZES {
+        large_samples(&mut group, n_events);
+        let events = generate_events(N_PEERS, n_events + 50 * N_PEERS);
+        let (base, extra) = events.split_at(N_PEERS + n_events);
+        let graph = build_graph(base);
+        let decided = graph.last_decided_round();
+        let mut probe = graph.fork();
+        let needed = extra
+            .iter()
+            .position(|event| {
+                push_all(&mut probe, std::slice::from_ref(event));
+                probe.last_decided_round() != decided
+            })
+            .expect("no round got decided with the extra events");
+        let deciding = &extra[..=needed];
+        group.bench_function(BenchmarkId::from_parameter(n_events), |b| {
+            b.iter_batched(
+                || graph.fork(),
+                |mut graph| push_all(&mut graph, black_box(deciding)),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// Sync of a peer that knows the first half of the events
+fn jobs(c: &mut Criterion) {
+    let mut generate = c.benchmark_group("jobs_generate");
+    generate.sample_size(10);
+    let mut inputs = vec![];
+    for n_events in SIZES {
+        large_samples(&mut generate, n_events);
+        let events = generate_events(N_PEERS, n_events);
+        let graph = build_graph(&events);
+        let behind = build_graph(&events[..N_PEERS + n_events / 2]);
+        let request = behind.sync_request();
+        generate.throughput(Throughput::Elements((n_events - n_events / 2) as u64));
+        generate.bench_function(BenchmarkId::from_parameter(n_events), |b| {
+            b.iter(|| {
+                graph
+                    .generate_sync_for_request(black_box(&request))
+                    .unwrap()
+            })
+        });
+        let jobs = graph.generate_sync_for_request(&request).unwrap();
+        inputs.push((n_events, behind, jobs));
+    }
+    generate.finish();
+
+    let mut apply = c.benchmark_group("jobs_apply");
+    apply.sample_size(10);
+    for (n_events, behind, jobs) in inputs {
+        large_samples(&mut apply, n_events);
+        apply.throughput(Throughput::Elements(jobs.as_linear().len() as u64));
+        apply.bench_function(BenchmarkId::from_parameter(n_events), |b| {
+            b.iter_batched(
+                || (behind.fork(), jobs.clone()),
+                |(mut graph, jobs)| graph.apply_sync_jobs(jobs).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    apply.finish();
+}
+
+/// Decoding a large batch of events, as received in a sync. Doesn't need
+/// a graph, so always runs on 100k events.
+fn deserialize(c: &mut Criterion) {
+    let n_events = 100_000;
+    let bytes = bincode::serialize(&generate_events(N_PEERS, n_events)).unwrap();
+    let mut group = c.benchmark_group("deserialize");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(n_events as u64));
+    group.bench_function(BenchmarkId::from_parameter(n_events), |b| {
+        b.iter(|| bincode::deserialize::<Vec<BenchEvent>>(black_box(&bytes)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    push_event,
+    strongly_see,
+    fame_election,
+    jobs,
+    deserialize
+);
+criterion_main!(benches);
@@ -0,0 +1,156 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::seq::SliceRandom;
+use rand_chacha::{rand_core::SeedableRng, ChaCha8Rng};
+use rust_hashgraph::algorithm::{
+    core::{self, EventTable},
+    datastructure::Graph,
+    event::{self, SignedEvent},
+    IncrementalClock, MockSigner, RoundNum, Signer,
+};
+
+/// Counts allocations, to compare the decisions with and without the
+/// graph's scratch buffers
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+type BenchGraph = Graph<(), (), usize, MockSigner<usize, ()>, IncrementalClock>;
+
+const N_PEERS: usize = 4;
+const N_EVENTS: usize = 1_000;
+/// Elections of the witnesses of this many last decided rounds
+const ELECTED_ROUNDS: usize = 5;
+
+/// The graph without its scratch buffers
+struct Unpooled<'a>(&'a BenchGraph);
+
+impl EventTable for Unpooled<'_> {
+    type PeerId = usize;
+
+    fn entry(&self, event: &event::Hash) -> Option<core::Entry<'_, usize>> {
+        self.0.entry(event)
+    }
+
+    fn round(&self, event: &event::Hash) -> Option<RoundNum> {
+        self.0.round(event)
+    }
+
+    fn round_events(&self, round: RoundNum) -> Option<&HashSet<event::Hash>> {
+        self.0.round_events(round)
+    }
+
+    fn is_witness(&self, event: &event::Hash) -> bool {
+        self.0.is_witness(event)
+    }
+}
+
+/// Graph of random gossip with `n_events` regular events
+fn build_graph(n_peers: usize, n_events: usize) -> BenchGraph {
+    let mut pseudo_rng = ChaCha8Rng::seed_from_u64(1);
+    let signer = MockSigner::<usize, ()>::new();
+    let mut graph = Graph::new(0, (), (), 999, signer.clone(), IncrementalClock::new());
+    let mut tips = vec![graph.peer_latest_event(&0).unwrap().clone()];
+    for author in 1..n_peers {
+        let genesis = SignedEvent::new((), event::Kind::Genesis(()), author, 0, |h| signer.sign(h))
+            .expect("Failed to create event");
+        tips.push(genesis.hash().clone());
+        let (unsigned, signature) = genesis.into_parts();
+        graph.push_event(unsigned, signature).unwrap();
+    }
+    let authors: Vec<_> = (0..n_peers).collect();
+    for timestamp in 1..=n_events {
+        let author = *authors.choose(&mut pseudo_rng).unwrap();
+        let from_author = *authors.choose(&mut pseudo_rng).unwrap();
+        let parents = event::Parents {
+            self_parent: tips[author].clone(),
+            other_parent: tips[from_author].clone(),
+        };
+        let new_event = SignedEvent::new(
+            (),
+            event::Kind::Regular(parents),
+            author,
+            timestamp as u128,
+            |h| signer.sign(h),
+        )
+        .expect("Failed to create event");
+        tips[author] = new_event.hash().clone();
+        let (unsigned, signature) = new_event.into_parts();
+        graph.push_event(unsigned, signature).unwrap();
+    }
+    graph
+}
+
+fn elect_all<T: EventTable>(table: &T, witnesses: &[event::Hash]) {
+    let voting = core::Voting {
+        coin_frequency: 999,
+        coin_seed: None,
+        coin: core::CoinStrategy::MiddleBit,
+    };
+    for witness in witnesses {
+        black_box(core::fame(table, &N_PEERS, voting, witness).unwrap());
+    }
+}
+
+fn allocations_of(run: impl Fn()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    run();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+/// Fame elections of recent witnesses, which walk ancestors many times
+fn fame_elections(c: &mut Criterion) {
+    let graph = build_graph(N_PEERS, N_EVENTS);
+    let last_decided = graph.last_decided_round().expect("some rounds are decided");
+    let first = last_decided.saturating_sub(ELECTED_ROUNDS - 1);
+    let witnesses: Vec<_> = (first..=last_decided)
+        .flat_map(|round| graph.round_events(round).unwrap())
+        .filter(|event| graph.is_witness(event))
+        .cloned()
+        .collect();
+
+    // Warm the pool up first, as it is in a running graph
+    elect_all(&graph, &witnesses);
+    let pooled = allocations_of(|| elect_all(&graph, &witnesses));
+    let unpooled = allocations_of(|| elect_all(&Unpooled(&graph), &witnesses));
+    println!(
+        "Allocations for {} elections: {} with scratch buffers, {} without",
+        witnesses.len(),
+        pooled,
+        unpooled
+    );
+
+    let mut group = c.benchmark_group("fame_elections");
+    group.bench_function(BenchmarkId::new("scratch", N_EVENTS), |b| {
+        b.iter(|| elect_all(&graph, black_box(&witnesses)))
+    });
+    group.bench_function(BenchmarkId::new("no_scratch", N_EVENTS), |b| {
+        b.iter(|| elect_all(&Unpooled(&graph), black_box(&witnesses)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, fame_elections);
+criterion_main!(benches);
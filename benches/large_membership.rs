@@ -0,0 +1,78 @@
+//! Operations whose cost depends on the number of members, at 128 and 512
+//! members. The graphs have the same number of events per member.
+
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
+use rust_hashgraph::algorithm::{datastructure::Graph, IncrementalClock, MockSigner};
+
+mod common;
+use common::{generate_events, push_all, BenchEvent};
+
+type BenchGraph = Graph<(), (), usize, MockSigner<usize, ()>, IncrementalClock>;
+
+const MEMBERS: [usize; 2] = [128, 512];
+const EVENTS_PER_MEMBER: usize = 8;
+
+fn empty_graph() -> BenchGraph {
+    common::empty_graph()
+}
+
+fn inputs() -> Vec<(usize, Vec<BenchEvent>)> {
+    MEMBERS
+        .iter()
+        .map(|&members| {
+            (
+                members,
+                generate_events(members, EVENTS_PER_MEMBER * members),
+            )
+        })
+        .collect()
+}
+
+fn push_event(c: &mut Criterion) {
+    let mut group = c.benchmark_group("members_push_event");
+    group.sample_size(10);
+    for (members, events) in inputs() {
+        group.throughput(Throughput::Elements(events.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(members),
+            &events,
+            |b, events| {
+                b.iter_batched(
+                    empty_graph,
+                    |mut graph| push_all(&mut graph, black_box(events)),
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Queries scanning all members
+fn member_scans(c: &mut Criterion) {
+    let mut group = c.benchmark_group("members_scans");
+    group.sample_size(10);
+    for (members, events) in inputs() {
+        let mut graph = empty_graph();
+        push_all(&mut graph, &events);
+        group.bench_function(BenchmarkId::new("peer_candidates", members), |b| {
+            b.iter(|| black_box(&graph).peer_candidates().len())
+        });
+        group.bench_function(BenchmarkId::new("peer_order", members), |b| {
+            b.iter(|| black_box(&graph).peer_order().len())
+        });
+        group.bench_function(BenchmarkId::new("quota_violations", members), |b| {
+            b.iter(|| black_box(&graph).quota_violations().len())
+        });
+        let request = graph.sync_request();
+        group.bench_function(BenchmarkId::new("knowledge_from_summary", members), |b| {
+            b.iter(|| graph.knowledge_from_summary(black_box(&request.summary)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, push_event, member_scans);
+criterion_main!(benches);
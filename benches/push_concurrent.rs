@@ -0,0 +1,89 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rust_hashgraph::algorithm::{
+    datastructure::Graph,
+    event::{Hash, Signature},
+    IncrementalClock, MockSigner, Signer,
+};
+
+mod common;
+use common::{generate_events, push_all};
+
+/// [`MockSigner`] that takes about as long as a real signature check to
+/// verify, which is what the threads share
+#[derive(Clone, Default)]
+struct SlowSigner(MockSigner<usize, ()>);
+
+const VERIFY_ROUNDS: usize = 1_000;
+
+impl Signer<()> for SlowSigner {
+    type SignerIdentity = usize;
+
+    fn sign(&self, event_hash: &Hash) -> Signature {
+        self.0.sign(event_hash)
+    }
+
+    fn verify(
+        &self,
+        event_hash: &Hash,
+        signature: &Signature,
+        identity: &usize,
+        genesis_payload: &(),
+    ) -> bool {
+        let mut valid = true;
+        for _ in 0..VERIFY_ROUNDS {
+            valid &= self
+                .0
+                .verify(event_hash, signature, identity, genesis_payload);
+        }
+        valid
+    }
+}
+
+type BenchGraph = Graph<(), (), usize, SlowSigner, IncrementalClock>;
+
+const N_PEERS: usize = 8;
+const N_EVENTS: usize = 1_000;
+
+fn empty_graph() -> BenchGraph {
+    common::empty_graph()
+}
+
+/// Catching up on a large sync response, sequentially and with a growing
+/// number of threads
+fn ingest_batch(c: &mut Criterion) {
+    let mut source = empty_graph();
+    push_all(&mut source, &generate_events(N_PEERS, N_EVENTS));
+    let jobs = source
+        .generate_sync_for_request(&empty_graph().sync_request())
+        .unwrap();
+    let mut group = c.benchmark_group("ingest_batch");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(jobs.as_linear().len() as u64));
+    group.bench_function("apply_sync_jobs", |b| {
+        b.iter_batched(
+            || (empty_graph(), jobs.clone()),
+            |(mut graph, jobs)| graph.apply_sync_jobs(jobs).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut threads = 1;
+    while threads <= cores.max(2) {
+        group.bench_with_input(
+            BenchmarkId::new("push_concurrent", threads),
+            &threads,
+            |b, &threads| {
+                b.iter_batched(
+                    || (empty_graph(), jobs.clone()),
+                    |(mut graph, jobs)| graph.push_concurrent(jobs, threads).unwrap(),
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+        threads *= 2;
+    }
+    group.finish();
+}
+
+criterion_group!(benches, ingest_batch);
+criterion_main!(benches);
@@ -23,11 +23,11 @@ fn push_events_into_empty_graph(n_peers: usize, n_events: usize) {
         mock_signer.clone(),
         IncrementalClock::new(),
     );
-    for i in 1..n_peers {
+    for author_id in author_ids.iter().skip(1) {
         let next_genesis = SignedEvent::new(
             (),
             event::Kind::Genesis(()),
-            author_ids[i],
+            *author_id,
             clock.current_timestamp(),
             |h| mock_signer.sign(h),
         )
@@ -46,7 +46,7 @@ fn push_events_into_empty_graph(n_peers: usize, n_events: usize) {
         let new_event = SignedEvent::new(
             (),
             event::Kind::Regular(parents),
-            author.clone(),
+            *author,
             clock.current_timestamp(),
             |h| mock_signer.sign(h),
         )
@@ -0,0 +1,22 @@
+//! Arbitrary bytes decoded as a single event
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_hashgraph::algorithm::event::SignedEvent;
+
+type Event = SignedEvent<Vec<u8>, Vec<u8>, u64>;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(event) = bincode::deserialize::<Event>(data) else {
+        return;
+    };
+    // Decoded hash is arbitrary, checking it must not fail
+    event
+        .unsigned()
+        .hash_matches()
+        .expect("decoded events are encodable");
+    let encoded = bincode::serialize(&event).expect("decoded events are encodable");
+    let decoded: Event = bincode::deserialize(&encoded).expect("encoded events are decodable");
+    assert_eq!(event, decoded);
+});
@@ -0,0 +1,31 @@
+//! Arbitrary bytes decoded as each of the messages peers exchange
+
+#![no_main]
+
+use std::fmt::Debug;
+
+use libfuzzer_sys::fuzz_target;
+use rust_hashgraph::algorithm::datastructure::sync::wire::WireMessage;
+use rust_hashgraph::algorithm::datastructure::sync::{Jobs, Summary, SyncRequest};
+use rust_hashgraph::net::handshake::Handshake;
+use rust_hashgraph::net::protocol::Message;
+
+/// Whatever is accepted must survive re-encoding (in the current version)
+fn round_trip<T: WireMessage + PartialEq + Debug>(data: &[u8]) {
+    let Ok(message) = T::from_wire(data) else {
+        return;
+    };
+    let encoded = message.to_wire().expect("decoded messages are encodable");
+    let decoded = T::from_wire(&encoded).expect("encoded messages are decodable");
+    assert_eq!(message, decoded);
+}
+
+fuzz_target!(|data: &[u8]| {
+    round_trip::<Jobs<Vec<u8>, Vec<u8>, u64>>(data);
+    round_trip::<SyncRequest<Vec<u8>, Vec<u8>, u64>>(data);
+    round_trip::<Summary<u64>>(data);
+    round_trip::<Message<Vec<u8>, Vec<u8>, u64>>(data);
+    if let Ok(handshake) = Handshake::decode(data) {
+        let _ = handshake.negotiate(&handshake);
+    }
+});
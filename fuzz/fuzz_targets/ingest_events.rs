@@ -0,0 +1,109 @@
+//! Hostile events pushed into a graph of a few honest members. Events are
+//! built from structured input so that most of them get past decoding and
+//! reach validation and consensus.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use rust_hashgraph::algorithm::datastructure::sync::wire::WireMessage;
+use rust_hashgraph::algorithm::datastructure::sync::Jobs;
+use rust_hashgraph::algorithm::datastructure::{Graph, PushOutcome};
+use rust_hashgraph::algorithm::event::{self, Parents, Signature, SignedEvent};
+use rust_hashgraph::algorithm::{IncrementalClock, MockSigner, Signer};
+
+type FuzzGraph = Graph<Vec<u8>, Vec<u8>, u64, MockSigner<u64, Vec<u8>>, IncrementalClock>;
+
+/// Members known from the start, other authors are unknown
+const MEMBERS: u64 = 4;
+
+#[derive(Arbitrary, Debug)]
+enum Parent {
+    /// Index into the events inserted so far
+    Known(u16),
+    Unknown([u8; 64]),
+}
+
+#[derive(Arbitrary, Debug)]
+struct HostileEvent {
+    author: u8,
+    /// `None` for a genesis
+    parents: Option<(Parent, Parent)>,
+    payload: Vec<u8>,
+    timestamp: u128,
+    /// Signature is valid if `None`
+    forged_signature: Option<[u8; 64]>,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    events: Vec<HostileEvent>,
+    /// Applied as a sync response after the events
+    encoded_jobs: Vec<u8>,
+}
+
+fn parent_hash(parent: &Parent, known: &[event::Hash]) -> event::Hash {
+    match parent {
+        Parent::Known(index) => known[*index as usize % known.len()].clone(),
+        Parent::Unknown(bytes) => event::Hash::from_array(*bytes),
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let signer = MockSigner::new();
+    let mut graph: FuzzGraph = Graph::new(
+        0,
+        vec![],
+        vec![],
+        10,
+        signer.clone(),
+        IncrementalClock::new(),
+    );
+    graph.set_pending_pool(Some(64));
+    graph.set_max_clock_skew(Some(1_000_000));
+    let mut known = vec![graph.self_tip().unwrap().clone()];
+    for member in 1..MEMBERS {
+        let genesis = SignedEvent::new(vec![], event::Kind::Genesis(vec![]), member, 0, |h| {
+            signer.sign(h)
+        })
+        .unwrap();
+        known.push(genesis.hash().clone());
+        let (unsigned, signature) = genesis.into_parts();
+        graph.push_event(unsigned, signature).unwrap();
+    }
+
+    for hostile in input.events {
+        let kind = match &hostile.parents {
+            None => event::Kind::Genesis(vec![]),
+            Some((self_parent, other_parent)) => event::Kind::Regular(Parents {
+                self_parent: parent_hash(self_parent, &known),
+                other_parent: parent_hash(other_parent, &known),
+            }),
+        };
+        let Ok(event) = SignedEvent::new(
+            hostile.payload,
+            kind,
+            hostile.author as u64,
+            hostile.timestamp,
+            |h| match hostile.forged_signature {
+                Some(bytes) => Signature(event::Hash::from_array(bytes)),
+                None => signer.sign(h),
+            },
+        ) else {
+            continue;
+        };
+        let hash = event.hash().clone();
+        let (unsigned, signature) = event.into_parts();
+        if let Ok(PushOutcome::Inserted) = graph.push_or_buffer(unsigned, signature) {
+            known.push(hash);
+        }
+        while let Some(orphan) = graph.next_resolved_orphan() {
+            known.push(orphan.hash().clone());
+        }
+    }
+
+    if let Ok(jobs) = Jobs::from_wire(&input.encoded_jobs) {
+        let _ = graph.apply_sync_jobs(jobs);
+    }
+    while graph.next_finalized_event().is_some() {}
+});
@@ -0,0 +1,133 @@
+//! Integer arithmetic of votes.
+//!
+//! Members must reach the same decisions on any platform, so vote counting
+//! never uses floats or `usize` arithmetic that may overflow differently.
+//! Votes are [`Weight`]s, sums of the voters' [`Stake`]s, both `u64` with
+//! checked operations. Divisions round towards zero, and the supermajority
+//! test compares `3 * weight` with `2 * total` exactly instead of dividing.
+//! Members' stakes are carried in epoch summaries
+//! ([`Graph::set_stake`](crate::algorithm::datastructure::Graph::set_stake)),
+//! votes don't weigh them yet: each voter counts with [`Stake::UNIT`].
+//!
+//! Both types serialize as a fixed-size little-endian `u64`.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Stake of a single member
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Stake(pub u64);
+
+impl Stake {
+    /// Stake of each member while all members are equal
+    pub const UNIT: Stake = Stake(1);
+}
+
+/// Sum of stakes, e.g. of the voters for a witness or of all members
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct Weight(pub u64);
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithError {
+    #[error("Weight doesn't fit in 64 bits")]
+    Overflow,
+    #[error("Weight would be negative")]
+    Underflow,
+    #[error("Division by zero")]
+    DivisionByZero,
+}
+
+impl Weight {
+    pub const ZERO: Weight = Weight(0);
+
+    /// Weight of `count` members of [unit](Stake::UNIT) stake
+    pub fn count(count: usize) -> Self {
+        Self(u64::try_from(count).expect("counts fit in 64 bits"))
+    }
+
+    /// Sum of the stakes
+    pub fn total(stakes: impl IntoIterator<Item = Stake>) -> Result<Self, ArithError> {
+        stakes
+            .into_iter()
+            .try_fold(Self::ZERO, |sum, stake| sum.checked_add(stake))
+    }
+
+    pub fn checked_add(self, stake: Stake) -> Result<Self, ArithError> {
+        self.0
+            .checked_add(stake.0)
+            .map(Self)
+            .ok_or(ArithError::Overflow)
+    }
+
+    pub fn checked_sub(self, other: Weight) -> Result<Self, ArithError> {
+        self.0
+            .checked_sub(other.0)
+            .map(Self)
+            .ok_or(ArithError::Underflow)
+    }
+
+    /// `self * numerator / denominator`, rounded towards zero
+    pub fn mul_div_floor(self, numerator: u64, denominator: u64) -> Result<Self, ArithError> {
+        if denominator == 0 {
+            return Err(ArithError::DivisionByZero);
+        }
+        let product = self.0 as u128 * numerator as u128;
+        u64::try_from(product / denominator as u128)
+            .map(Self)
+            .map_err(|_| ArithError::Overflow)
+    }
+
+    /// More than two thirds of `total`
+    pub fn is_supermajority_of(self, total: Weight) -> bool {
+        3 * self.0 as u128 > 2 * total.0 as u128
+    }
+
+    /// Least weight that is a supermajority of `total`
+    pub fn supermajority_threshold(total: Weight) -> Self {
+        let two_thirds = total
+            .mul_div_floor(2, 3)
+            .expect("two thirds of a weight fit in it");
+        Self(two_thirds.0 + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checked_and_exact() {
+        for total in 0..=30 {
+            let total = Weight(total);
+            let threshold = Weight::supermajority_threshold(total);
+            assert!(threshold.is_supermajority_of(total));
+            assert!(!Weight(threshold.0 - 1).is_supermajority_of(total));
+        }
+        // No intermediate overflow near the limit
+        let max = Weight(u64::MAX);
+        assert!(!Weight(u64::MAX / 3 * 2).is_supermajority_of(max));
+        assert!(Weight(u64::MAX / 3 * 2 + 1).is_supermajority_of(max));
+        assert_eq!(max.mul_div_floor(2, 3), Ok(Weight(u64::MAX / 3 * 2)));
+        assert_eq!(max.mul_div_floor(3, 2), Err(ArithError::Overflow));
+        assert_eq!(max.mul_div_floor(1, 0), Err(ArithError::DivisionByZero));
+        assert_eq!(max.checked_add(Stake::UNIT), Err(ArithError::Overflow));
+        assert_eq!(
+            Weight::ZERO.checked_sub(Weight(1)),
+            Err(ArithError::Underflow)
+        );
+        assert_eq!(Weight(7).mul_div_floor(1, 2), Ok(Weight(3)));
+
+        let stakes = [Stake(5), Stake(u64::MAX - 5)];
+        assert_eq!(Weight::total(stakes), Ok(max));
+        assert_eq!(
+            Weight::total(stakes.into_iter().chain([Stake::UNIT])),
+            Err(ArithError::Overflow)
+        );
+        assert_eq!(
+            bincode::serialize(&Weight(258)).unwrap(),
+            [2, 1, 0, 0, 0, 0, 0, 0]
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! When to author own events.
+//!
+//! Each event costs memory and bandwidth, while transactions wait in the
+//! queue until the next own event carries them. Authoring after every sync
+//! gives the lowest latency and the biggest graph, authoring rarely gives
+//! big batches and a small graph. A [`Cadence`] keeps the queue of
+//! submitted transactions and asks its [`CadencePolicy`] whether an event
+//! is due, e.g. after every sync ([`EverySync`]), once enough transactions
+//! are queued ([`QueuedTransactions`]) or some time after the previous
+//! event ([`Interval`]). Policies are combined with [`FirstOf`].
+//!
+//! The queue is bounded: [`Cadence::submit`] hands the transaction back
+//! when it is full, so the application slows down instead of the queue
+//! growing without limit when events are authored too rarely.
+
+use std::collections::VecDeque;
+
+use thiserror::Error;
+
+use crate::Timestamp;
+
+/// What happened since the previous own event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CadenceState {
+    pub queued: usize,
+    pub syncs: usize,
+    /// Events received in these syncs
+    pub new_events: usize,
+    /// Time since the previous own event (or since the start)
+    pub elapsed: Timestamp,
+}
+
+pub trait CadencePolicy {
+    /// Whether to author an event now
+    fn due(&mut self, state: &CadenceState) -> bool;
+}
+
+/// After each sync, so that the received events get a descendant quickly
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EverySync;
+
+impl CadencePolicy for EverySync {
+    fn due(&mut self, state: &CadenceState) -> bool {
+        state.syncs > 0
+    }
+}
+
+/// Once at least this many transactions are queued
+#[derive(Debug, Clone, Copy)]
+pub struct QueuedTransactions(pub usize);
+
+impl CadencePolicy for QueuedTransactions {
+    fn due(&mut self, state: &CadenceState) -> bool {
+        state.queued >= self.0.max(1)
+    }
+}
+
+/// Once this much time passed since the previous event, in the units of
+/// the time given to [`Cadence::poll`]
+#[derive(Debug, Clone, Copy)]
+pub struct Interval(pub Timestamp);
+
+impl CadencePolicy for Interval {
+    fn due(&mut self, state: &CadenceState) -> bool {
+        state.elapsed >= self.0
+    }
+}
+
+/// Due when any of the policies is. All of them are asked each time.
+pub struct FirstOf(pub Vec<Box<dyn CadencePolicy + Send>>);
+
+impl CadencePolicy for FirstOf {
+    fn due(&mut self, state: &CadenceState) -> bool {
+        let mut due = false;
+        for policy in &mut self.0 {
+            due |= policy.due(state);
+        }
+        due
+    }
+}
+
+impl<P: CadencePolicy + ?Sized> CadencePolicy for Box<P> {
+    fn due(&mut self, state: &CadenceState) -> bool {
+        (**self).due(state)
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Transaction queue is full")]
+pub struct QueueFull<T>(pub T);
+
+/// See the [module docs](self)
+pub struct Cadence<T, P> {
+    policy: P,
+    queue: VecDeque<T>,
+    max_queued: usize,
+    max_batch: usize,
+    syncs: usize,
+    new_events: usize,
+    last_event: Timestamp,
+}
+
+impl<T, P: CadencePolicy> Cadence<T, P> {
+    /// Up to `max_queued` transactions wait in the queue, events carry up
+    /// to `max_batch` of them. `now` is the start of the first interval.
+    pub fn new(policy: P, max_queued: usize, max_batch: usize, now: Timestamp) -> Self {
+        Self {
+            policy,
+            queue: VecDeque::new(),
+            max_queued,
+            max_batch: max_batch.max(1),
+            syncs: 0,
+            new_events: 0,
+            last_event: now,
+        }
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.queue.len() >= self.max_queued
+    }
+
+    pub fn submit(&mut self, transaction: T) -> Result<(), QueueFull<T>> {
+        if self.is_full() {
+            return Err(QueueFull(transaction));
+        }
+        self.queue.push_back(transaction);
+        Ok(())
+    }
+
+    pub fn record_sync(&mut self, new_events: usize) {
+        self.syncs += 1;
+        self.new_events += new_events;
+    }
+
+    pub fn state(&self, now: Timestamp) -> CadenceState {
+        CadenceState {
+            queued: self.queue.len(),
+            syncs: self.syncs,
+            new_events: self.new_events,
+            elapsed: now.saturating_sub(self.last_event),
+        }
+    }
+
+    /// Transactions for an event if one is due (possibly none), the oldest
+    /// first. The caller is expected to author the event right away.
+    pub fn poll(&mut self, now: Timestamp) -> Option<Vec<T>> {
+        let state = self.state(now);
+        if !self.policy.due(&state) {
+            return None;
+        }
+        self.syncs = 0;
+        self.new_events = 0;
+        self.last_event = now;
+        let taken = self.queue.len().min(self.max_batch);
+        Some(self.queue.drain(..taken).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies_trigger_events() {
+        let mut every_sync = Cadence::new(EverySync, 10, 10, 0);
+        every_sync.submit(1).unwrap();
+        assert_eq!(every_sync.poll(5), None);
+        every_sync.record_sync(3);
+        assert_eq!(every_sync.state(5).new_events, 3);
+        assert_eq!(every_sync.poll(5), Some(vec![1]));
+        assert_eq!(every_sync.poll(5), None);
+        every_sync.record_sync(0);
+        assert_eq!(every_sync.poll(5), Some(vec![]));
+        assert_eq!(every_sync.poll(5), None);
+
+        let mut batched = Cadence::new(QueuedTransactions(3), 10, 2, 0);
+        for transaction in 0..3 {
+            assert_eq!(batched.poll(0), None);
+            batched.submit(transaction).unwrap();
+        }
+        // Limited by the batch size, the rest waits for more
+        assert_eq!(batched.poll(0), Some(vec![0, 1]));
+        assert_eq!(batched.queued(), 1);
+        assert_eq!(batched.poll(0), None);
+
+        let mut combined = Cadence::new(
+            FirstOf(vec![
+                Box::new(Interval(100)),
+                Box::new(QueuedTransactions(2)),
+            ]),
+            10,
+            10,
+            50,
+        );
+        assert_eq!(combined.poll(149), None);
+        assert_eq!(combined.poll(150), Some(vec![]));
+        combined.submit(7).unwrap();
+        combined.submit(8).unwrap();
+        assert_eq!(combined.state(160).elapsed, 10);
+        assert_eq!(combined.poll(160), Some(vec![7, 8]));
+        assert_eq!(combined.poll(259), None);
+    }
+
+    #[test]
+    fn full_queue_pushes_back() {
+        let mut cadence = Cadence::new(Interval(10), 2, 10, 0);
+        cadence.submit(1).unwrap();
+        cadence.submit(2).unwrap();
+        assert!(cadence.is_full());
+        assert_eq!(cadence.submit(3), Err(QueueFull(3)));
+        assert_eq!(cadence.poll(10), Some(vec![1, 2]));
+        assert_eq!(cadence.submit(3), Ok(()));
+    }
+}
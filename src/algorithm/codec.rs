@@ -0,0 +1,207 @@
+//! Encoding of user payloads. See [`PayloadCodec`] for details.
+
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum PayloadCodecError {
+    #[error("Failed to encode payload: {0}")]
+    Encode(String),
+    #[error("Failed to decode payload: {0}")]
+    Decode(String),
+    #[error("Encoded payload is {size} bytes long, but at most {limit} bytes are allowed")]
+    TooLarge { size: usize, limit: usize },
+    #[error("Payload is invalid: {0}")]
+    Invalid(String),
+    #[error("Payload was dropped, the event can't be encoded anymore")]
+    Dropped,
+}
+
+impl From<PayloadCodecError> for bincode::Error {
+    fn from(value: PayloadCodecError) -> Self {
+        Box::new(bincode::ErrorKind::Custom(value.to_string()))
+    }
+}
+
+/// Conversion of user payload to bytes and back.
+///
+/// The bytes are what event hash is computed from and what is sent over
+/// the wire, so the encoding must be deterministic. Consensus itself never
+/// looks inside the payload.
+///
+/// Any `serde` type gets bincode encoding for free. Other types (e.g. ones
+/// generated by protobuf or borsh) can implement the trait directly.
+pub trait PayloadCodec: Sized {
+    /// Upper bound on the encoded payload size. Checked on both encoding and
+    /// decoding, `None` means no limit.
+    const MAX_ENCODED_SIZE: Option<usize> = None;
+
+    fn encode(&self) -> Result<Vec<u8>, PayloadCodecError>;
+
+    fn decode(bytes: &[u8]) -> Result<Self, PayloadCodecError>;
+
+    /// Application-level check of the payload contents. Called before encoding
+    /// and right after decoding, so invalid payloads never make it into events.
+    fn validate(&self) -> Result<(), PayloadCodecError> {
+        Ok(())
+    }
+}
+
+impl<T> PayloadCodec for T
+where
+    T: Serialize + DeserializeOwned,
+{
+    fn encode(&self) -> Result<Vec<u8>, PayloadCodecError> {
+        bincode::serialize(self).map_err(|e| PayloadCodecError::Encode(e.to_string()))
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, PayloadCodecError> {
+        bincode::deserialize(bytes).map_err(|e| PayloadCodecError::Decode(e.to_string()))
+    }
+}
+
+fn check_size<T: PayloadCodec>(size: usize) -> Result<(), PayloadCodecError> {
+    match T::MAX_ENCODED_SIZE {
+        Some(limit) if size > limit => Err(PayloadCodecError::TooLarge { size, limit }),
+        _ => Ok(()),
+    }
+}
+
+/// Validate and encode the payload, respecting the size limit.
+pub fn encode_payload<T: PayloadCodec>(payload: &T) -> Result<Vec<u8>, PayloadCodecError> {
+    payload.validate()?;
+    let bytes = payload.encode()?;
+    check_size::<T>(bytes.len())?;
+    Ok(bytes)
+}
+
+/// Decode and validate the payload. Size limit is checked before decoding.
+pub fn decode_payload<T: PayloadCodec>(bytes: &[u8]) -> Result<T, PayloadCodecError> {
+    check_size::<T>(bytes.len())?;
+    let payload = T::decode(bytes)?;
+    payload.validate()?;
+    Ok(payload)
+}
+
+/// For use in `#[serde(with = "...")]` on payload fields.
+pub(crate) mod serde_payload {
+    use super::*;
+
+    pub fn serialize<T, S>(payload: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: PayloadCodec,
+        S: Serializer,
+    {
+        let bytes = encode_payload(payload).map_err(serde::ser::Error::custom)?;
+        serializer.serialize_bytes(&bytes)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: PayloadCodec,
+        D: Deserializer<'de>,
+    {
+        let bytes = <Vec<u8>>::deserialize(deserializer)?;
+        decode_payload(&bytes).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Like [`serde_payload`], for payloads that a graph may have dropped.
+/// Encoded the same way, dropped ones can't be encoded.
+pub(crate) mod serde_kept_payload {
+    use super::*;
+
+    pub fn serialize<T, S>(payload: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: PayloadCodec,
+        S: Serializer,
+    {
+        match payload {
+            Some(payload) => serde_payload::serialize(payload, serializer),
+            None => Err(serde::ser::Error::custom(PayloadCodecError::Dropped)),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: PayloadCodec,
+        D: Deserializer<'de>,
+    {
+        serde_payload::deserialize(deserializer).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::event::{Kind, SignedEvent};
+
+    /// Payload with its own encoding that only accepts even numbers
+    #[derive(Debug, PartialEq, Eq, Hash, Clone)]
+    struct EvenNumbers(Vec<u8>);
+
+    impl PayloadCodec for EvenNumbers {
+        const MAX_ENCODED_SIZE: Option<usize> = Some(4);
+
+        fn encode(&self) -> Result<Vec<u8>, PayloadCodecError> {
+            Ok(self.0.clone())
+        }
+
+        fn decode(bytes: &[u8]) -> Result<Self, PayloadCodecError> {
+            Ok(Self(bytes.to_vec()))
+        }
+
+        fn validate(&self) -> Result<(), PayloadCodecError> {
+            match self.0.iter().find(|n| *n % 2 != 0) {
+                Some(odd) => Err(PayloadCodecError::Invalid(format!("{odd} is odd"))),
+                None => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn serde_payload_matches_bincode() {
+        let payload = (1u32, "some payload".to_owned());
+        assert_eq!(
+            encode_payload(&payload).unwrap(),
+            bincode::serialize(&payload).unwrap()
+        );
+        let decoded: (u32, String) = decode_payload(&encode_payload(&payload).unwrap()).unwrap();
+        assert_eq!(decoded, payload);
+    }
+
+    #[test]
+    fn custom_codec_roundtrips_in_events() {
+        let event =
+            SignedEvent::new_fakely_signed(EvenNumbers(vec![2, 4]), Kind::Genesis(()), 0u64, 0)
+                .unwrap();
+        let bytes = bincode::serialize(&event).unwrap();
+        let decoded: SignedEvent<EvenNumbers, (), u64> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(decoded, event);
+    }
+
+    #[test]
+    fn custom_codec_rejects_invalid() {
+        assert!(matches!(
+            SignedEvent::new_fakely_signed(EvenNumbers(vec![2, 3]), Kind::Genesis(()), 0u64, 0),
+            Err(e) if e.to_string().contains("3 is odd")
+        ));
+        assert!(matches!(
+            encode_payload(&EvenNumbers(vec![2; 5])),
+            Err(PayloadCodecError::TooLarge { size: 5, limit: 4 })
+        ));
+        assert!(matches!(
+            decode_payload::<EvenNumbers>(&[2, 4, 1]),
+            Err(PayloadCodecError::Invalid(_))
+        ));
+
+        // Sneak the invalid payload through the wire by encoding a valid one first
+        let event =
+            SignedEvent::new_fakely_signed(EvenNumbers(vec![2, 4]), Kind::Genesis(()), 0u64, 0)
+                .unwrap();
+        let mut bytes = bincode::serialize(&event).unwrap();
+        // payload bytes go right after their u64 length
+        bytes[9] = 5;
+        assert!(bincode::deserialize::<SignedEvent<EvenNumbers, (), u64>>(&bytes).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+//! Payloads compressed per event.
+//!
+//! Applications trading CPU for bandwidth use [`Compressed`] payloads. The
+//! author picks a [`PayloadCompression`] for each event, and the encoded
+//! payload is its id followed by the compressed bytes. The event hash thus
+//! covers the compressed bytes and the choice of compression. Events travel
+//! further than the first peer, so the set of compressions is a parameter
+//! of the whole network, checked in the [handshake](crate::net::handshake)
+//! like the network id, see
+//! [`Handshake::payload_compressions`](crate::net::handshake::Handshake::payload_compressions).
+//!
+//! Decompressed sizes are bounded per payload, and by
+//! [`MAX_DECOMPRESSED_PER_MESSAGE`] in total for all payloads of a
+//! [wire message](crate::algorithm::datastructure::sync::wire), so a frame
+//! full of small bombs can't take more memory than a single large payload.
+//!
+//! The compressed bytes are kept as received, so an event hashes the same
+//! even if another compressor would compress its payload differently.
+//! LZ4 needs the feature `compression`, without it such payloads fail to
+//! decode.
+
+use std::cell::Cell;
+
+use super::codec::{self, PayloadCodec, PayloadCodecError};
+
+/// Limit on the decompressed size of payloads without
+/// [`PayloadCodec::MAX_ENCODED_SIZE`]
+pub const MAX_DECOMPRESSED_SIZE: usize = 16 << 20;
+
+/// Limit on the total decompressed size of payloads in one wire message
+pub const MAX_DECOMPRESSED_PER_MESSAGE: usize = 64 << 20;
+
+thread_local! {
+    /// Bytes the payloads being decoded may still decompress to, `None`
+    /// outside of [`with_decompression_budget`]
+    static BUDGET: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Run `decode`, failing decompressions of [`Compressed`] payloads once
+/// they exceed `budget` bytes in total. Nested calls share the outer
+/// budget.
+pub(crate) fn with_decompression_budget<R>(budget: usize, decode: impl FnOnce() -> R) -> R {
+    struct Reset;
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            BUDGET.set(None);
+        }
+    }
+
+    if BUDGET.get().is_some() {
+        return decode();
+    }
+    BUDGET.set(Some(budget));
+    let _reset = Reset;
+    decode()
+}
+
+/// Take `size` bytes from the budget, if there is one
+fn spend_budget(size: usize) -> Result<(), PayloadCodecError> {
+    match BUDGET.get() {
+        Some(left) if size > left => Err(PayloadCodecError::TooLarge { size, limit: left }),
+        Some(left) => {
+            BUDGET.set(Some(left - size));
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[repr(u8)]
+pub enum PayloadCompression {
+    #[default]
+    None = 0,
+    /// LZ4 block with the decompressed size prepended (`u32`, little endian)
+    Lz4 = 1,
+}
+
+impl PayloadCompression {
+    /// Id recorded in the encoded payload
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::None),
+            1 => Some(Self::Lz4),
+            _ => None,
+        }
+    }
+
+    /// Name in the handshake
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Lz4 => "lz4",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::None, Self::Lz4]
+            .into_iter()
+            .find(|c| c.name() == name)
+    }
+
+    /// Compressions this build can decode, the preferred first
+    pub fn supported() -> Vec<Self> {
+        let mut supported = vec![];
+        if cfg!(feature = "compression") {
+            supported.push(Self::Lz4);
+        }
+        supported.push(Self::None);
+        supported
+    }
+
+    fn compress(self, raw: &[u8]) -> Result<Vec<u8>, PayloadCodecError> {
+        match self {
+            Self::None => Ok(raw.to_vec()),
+            #[cfg(feature = "compression")]
+            Self::Lz4 => Ok(lz4_flex::block::compress_prepend_size(raw)),
+            #[cfg(not(feature = "compression"))]
+            Self::Lz4 => Err(PayloadCodecError::Encode(LZ4_UNSUPPORTED.to_owned())),
+        }
+    }
+
+    fn decompress(self, bytes: &[u8], limit: usize) -> Result<Vec<u8>, PayloadCodecError> {
+        match self {
+            Self::None => Ok(bytes.to_vec()),
+            Self::Lz4 => {
+                let Some((size, block)) = bytes.split_first_chunk::<4>() else {
+                    return Err(PayloadCodecError::Decode("lz4 size is missing".to_owned()));
+                };
+                let size = u32::from_le_bytes(*size) as usize;
+                if size > limit {
+                    return Err(PayloadCodecError::TooLarge { size, limit });
+                }
+                spend_budget(size)?;
+                decompress_lz4(block, size)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "compression")]
+fn decompress_lz4(block: &[u8], size: usize) -> Result<Vec<u8>, PayloadCodecError> {
+    match lz4_flex::block::decompress(block, size) {
+        Ok(raw) if raw.len() == size => Ok(raw),
+        Ok(raw) => Err(PayloadCodecError::Decode(format!(
+            "lz4 gave {} bytes instead of {size}",
+            raw.len()
+        ))),
+        Err(e) => Err(PayloadCodecError::Decode(e.to_string())),
+    }
+}
+
+#[cfg(not(feature = "compression"))]
+const LZ4_UNSUPPORTED: &str = "lz4 needs the feature `compression`";
+
+#[cfg(not(feature = "compression"))]
+fn decompress_lz4(_block: &[u8], _size: usize) -> Result<Vec<u8>, PayloadCodecError> {
+    Err(PayloadCodecError::Decode(LZ4_UNSUPPORTED.to_owned()))
+}
+
+/// Payload encoded with [`PayloadCodec`] of `T`, then compressed
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Compressed<T> {
+    value: T,
+    compression: PayloadCompression,
+    /// As in the encoded payload, after the id
+    bytes: Vec<u8>,
+}
+
+impl<T: PayloadCodec> Compressed<T> {
+    /// Fails if `T` fails to encode or the compression is not supported
+    pub fn new(value: T, compression: PayloadCompression) -> Result<Self, PayloadCodecError> {
+        let bytes = compression.compress(&codec::encode_payload(&value)?)?;
+        Ok(Self {
+            value,
+            compression,
+            bytes,
+        })
+    }
+}
+
+impl<T> Compressed<T> {
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn into_value(self) -> T {
+        self.value
+    }
+
+    pub fn compression(&self) -> PayloadCompression {
+        self.compression
+    }
+
+    /// Size of the encoded payload, including the id
+    pub fn encoded_len(&self) -> usize {
+        1 + self.bytes.len()
+    }
+}
+
+impl<T: PayloadCodec> PayloadCodec for Compressed<T> {
+    fn encode(&self) -> Result<Vec<u8>, PayloadCodecError> {
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        bytes.push(self.compression.id());
+        bytes.extend_from_slice(&self.bytes);
+        Ok(bytes)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, PayloadCodecError> {
+        let Some((&id, bytes)) = bytes.split_first() else {
+            return Err(PayloadCodecError::Decode(
+                "compression id is missing".to_owned(),
+            ));
+        };
+        let compression = PayloadCompression::from_id(id)
+            .ok_or_else(|| PayloadCodecError::Decode(format!("unknown compression {id}")))?;
+        let limit = T::MAX_ENCODED_SIZE.unwrap_or(MAX_DECOMPRESSED_SIZE);
+        let raw = compression.decompress(bytes, limit)?;
+        Ok(Self {
+            value: codec::decode_payload(&raw)?,
+            compression,
+            bytes: bytes.to_vec(),
+        })
+    }
+
+    fn validate(&self) -> Result<(), PayloadCodecError> {
+        self.value.validate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::event::{Kind, SignedEvent};
+
+    type Payload = Compressed<Vec<String>>;
+
+    fn transactions() -> Vec<String> {
+        vec!["transfer 10 from alice to bob".to_owned(); 50]
+    }
+
+    #[test]
+    fn compression_in_hash() {
+        let plain = Payload::new(transactions(), PayloadCompression::None).unwrap();
+        let event = |payload: &Payload| {
+            SignedEvent::new_fakely_signed(payload.clone(), Kind::Genesis(()), 0u64, 0).unwrap()
+        };
+        assert_eq!(
+            plain.encoded_len(),
+            1 + codec::encode_payload(&transactions()).unwrap().len()
+        );
+        let decoded: Payload = codec::decode_payload(&plain.encode().unwrap()).unwrap();
+        assert_eq!(decoded, plain);
+        assert_eq!(decoded.into_value(), transactions());
+
+        let lz4 = Payload::new(transactions(), PayloadCompression::Lz4);
+        if !cfg!(feature = "compression") {
+            assert!(lz4.is_err());
+            return;
+        }
+        let lz4 = lz4.unwrap();
+        assert!(lz4.encoded_len() < plain.encoded_len() / 4);
+        // Same transactions, another event
+        assert_ne!(event(&lz4).hash(), event(&plain).hash());
+        let bytes = bincode::serialize(&event(&lz4)).unwrap();
+        let decoded: SignedEvent<Payload, (), u64> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(decoded, event(&lz4));
+        assert!(decoded.unsigned().hash_matches().unwrap());
+        assert_eq!(
+            decoded.unsigned().fields().user_payload().unwrap().value(),
+            &transactions()
+        );
+    }
+
+    #[test]
+    fn malformed_rejected() {
+        assert!(Payload::decode(&[]).is_err());
+        assert!(matches!(
+            Payload::decode(&[7, 0]),
+            Err(PayloadCodecError::Decode(e)) if e.contains("unknown compression 7")
+        ));
+        // Decompressed size over the limit, checked before decompressing
+        let mut bomb = vec![PayloadCompression::Lz4.id()];
+        bomb.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            Payload::decode(&bomb),
+            Err(PayloadCodecError::TooLarge { .. })
+        ));
+        if cfg!(feature = "compression") {
+            let mut lying = Payload::new(transactions(), PayloadCompression::Lz4)
+                .unwrap()
+                .encode()
+                .unwrap();
+            lying[1] ^= 1;
+            assert!(Payload::decode(&lying).is_err());
+        }
+        assert_eq!(
+            PayloadCompression::supported().last(),
+            Some(&PayloadCompression::None)
+        );
+        assert_eq!(
+            PayloadCompression::from_name("lz4"),
+            Some(PayloadCompression::Lz4)
+        );
+    }
+
+    #[test]
+    fn decompression_budget_shared() {
+        // Sizes are checked before decompressing, no real payloads needed
+        let bomb = |size: u32| {
+            let mut bytes = vec![PayloadCompression::Lz4.id()];
+            bytes.extend_from_slice(&size.to_le_bytes());
+            bytes
+        };
+        let size = MAX_DECOMPRESSED_SIZE as u32;
+        let too_large =
+            |result: Result<Payload, _>| matches!(result, Err(PayloadCodecError::TooLarge { .. }));
+        with_decompression_budget(3 * MAX_DECOMPRESSED_SIZE / 2, || {
+            assert!(!too_large(Payload::decode(&bomb(size))));
+            with_decompression_budget(usize::MAX, || {
+                assert!(too_large(Payload::decode(&bomb(size))));
+            });
+            assert!(too_large(Payload::decode(&bomb(size / 2 + 1))));
+            assert!(!too_large(Payload::decode(&bomb(size / 2))));
+        });
+        assert!(!too_large(Payload::decode(&bomb(size))));
+    }
+}
@@ -0,0 +1,1049 @@
+//! Round and fame decisions as pure functions.
+//!
+//! Everything here reads an [`EventTable`] (events with the rounds and
+//! witnesses determined so far) and a [`Membership`], and nothing is
+//! cached or mutated. [`Graph`](super::datastructure::Graph) keeps the
+//! table up to date and memoizes the answers, while model checkers can
+//! run the same functions over small hand-made tables.
+//!
+//! The walks over ancestors and the votes of elections need short-lived
+//! sets and maps, many of them per decision. A table can lend a [`Scratch`]
+//! pool to keep their allocations between calls.
+//!
+//! Supermajorities are counted over sets of authors. Tables that number
+//! their peers densely (see [`Entry::slot`]) let these sets be bitsets of
+//! [`AUTHOR_BITS`] bits, so collecting authors is setting bits and counting
+//! them is a popcount. Slots past the width go to bits allocated as needed,
+//! so large memberships stay on bitsets as well. Only tables that don't
+//! number peers fall back to a hash set.
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::sync::Mutex;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::arith::{Stake, Weight};
+use super::datastructure::{UnknownEvent, WitnessFamousness};
+use super::event::{self, Parents};
+use super::RoundNum;
+
+/// Event as seen by the consensus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry<'a, TPeerId> {
+    /// Hash of the event, borrowed from the table
+    pub hash: &'a event::Hash,
+    pub author: &'a TPeerId,
+    /// Number of the author, unique among all peers the table ever knew
+    /// and small (they are numbered from 0 as they join). `None` if the
+    /// table doesn't number peers
+    pub slot: Option<usize>,
+    /// `None` for geneses. Parents may be unknown if they were pruned
+    pub parents: Option<&'a Parents>,
+}
+
+/// Immutable input of the decisions
+pub trait EventTable {
+    type PeerId: Eq + Hash;
+
+    /// `None` if the event is unknown
+    fn entry(&self, event: &event::Hash) -> Option<Entry<'_, Self::PeerId>>;
+    /// `None` if the round of the event is not determined yet
+    fn round(&self, event: &event::Hash) -> Option<RoundNum>;
+    /// Events with determined round `round`, `None` if there are none yet
+    fn round_events(&self, round: RoundNum) -> Option<&HashSet<event::Hash>>;
+    /// Whether the event is known to be a witness
+    fn is_witness(&self, event: &event::Hash) -> bool;
+    /// Index of the peer in an order all members agree on (see
+    /// [`peer_order`](super::datastructure::peer_order)), `None` if the
+    /// table doesn't have one
+    fn canonical_index(&self, _peer: &Self::PeerId) -> Option<usize> {
+        None
+    }
+    /// Buffers to reuse, `None` to allocate new ones each time
+    fn scratch(&self) -> Option<&Scratch> {
+        None
+    }
+}
+
+/// Pool of the buffers used by the decisions
+#[derive(Debug, Default)]
+pub struct Scratch {
+    sets: Mutex<Vec<HashSet<event::Hash>>>,
+    stacks: Mutex<Vec<Vec<event::Hash>>>,
+    votes: Mutex<Vec<HashMap<event::Hash, bool>>>,
+}
+
+/// Buffers held by the pool of each kind. Decisions nest a few walks, so
+/// only a handful are in use at once.
+const SCRATCH_POOL_LEN: usize = 16;
+
+impl Scratch {
+    /// Drop the pooled buffers, e.g. when their sizes no longer fit the
+    /// rounds being decided
+    pub fn release(&self) {
+        self.sets.lock().unwrap().clear();
+        self.stacks.lock().unwrap().clear();
+        self.votes.lock().unwrap().clear();
+    }
+
+    fn take_set(&self) -> HashSet<event::Hash> {
+        self.sets.lock().unwrap().pop().unwrap_or_default()
+    }
+
+    fn put_set(&self, mut set: HashSet<event::Hash>) {
+        set.clear();
+        put(&self.sets, set);
+    }
+
+    fn take_stack(&self) -> Vec<event::Hash> {
+        self.stacks.lock().unwrap().pop().unwrap_or_default()
+    }
+
+    fn put_stack(&self, mut stack: Vec<event::Hash>) {
+        stack.clear();
+        put(&self.stacks, stack);
+    }
+
+    fn take_votes(&self) -> HashMap<event::Hash, bool> {
+        self.votes.lock().unwrap().pop().unwrap_or_default()
+    }
+
+    fn put_votes(&self, mut votes: HashMap<event::Hash, bool>) {
+        votes.clear();
+        put(&self.votes, votes);
+    }
+}
+
+fn put<T>(pool: &Mutex<Vec<T>>, buffer: T) {
+    let mut pool = pool.lock().unwrap();
+    if pool.len() < SCRATCH_POOL_LEN {
+        pool.push(buffer);
+    }
+}
+
+/// Number of members voting in each round
+pub trait Membership {
+    fn size(&self, round: RoundNum) -> usize;
+}
+
+/// Same members in all rounds
+impl Membership for usize {
+    fn size(&self, _round: RoundNum) -> usize {
+        *self
+    }
+}
+
+/// How voters without a supermajority pick their vote in coin rounds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CoinStrategy {
+    /// Each voter takes a bit of its own hash, see [`coin`]. Voters rarely
+    /// agree, so an adversary splitting the votes may delay the decision
+    /// for several coin rounds.
+    #[default]
+    MiddleBit,
+    /// All voters of a round take the same bit, derived from `seed`, the
+    /// round and the elected witness. Honest voters agree after one coin
+    /// round, but all members must use the same seed and an adversary
+    /// knowing it can predict the coins.
+    InjectedCoin { seed: u64 },
+    /// Voters take the bit of the round's leader: the witness of the
+    /// previous round whose author's [canonical
+    /// index](EventTable::canonical_index) is the round number modulo the
+    /// number of members. Voters that don't strongly see the leader, and
+    /// tables without the index, fall back to [`MiddleBit`](Self::MiddleBit).
+    RoundRobinLeaderBit,
+}
+
+/// Parameters of fame elections
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Voting {
+    /// Every `coin_frequency`th round of an election is a coin round
+    pub coin_frequency: usize,
+    /// See [`coin`]
+    pub coin_seed: Option<u64>,
+    pub coin: CoinStrategy,
+}
+
+/// Result of [`fame`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Election {
+    pub fame: WitnessFamousness,
+    /// Round of the witnesses that decided, `None` if undecided
+    pub decided_at: Option<RoundNum>,
+}
+
+pub(crate) fn supermajority(count: usize, members: usize) -> bool {
+    Weight::count(count).is_supermajority_of(Weight::count(members))
+}
+
+/// Authors with smaller [slots](Entry::slot) are kept in bitsets without
+/// allocating
+pub const AUTHOR_BITS: usize = 256;
+const AUTHOR_WORDS: usize = AUTHOR_BITS / 64;
+
+/// Set of the authors of events, see the [module docs](self)
+struct AuthorSet<'a, TPeerId> {
+    bits: [u64; AUTHOR_WORDS],
+    /// Slots from [`AUTHOR_BITS`] on
+    wide: Vec<u64>,
+    /// Authors without a slot
+    others: HashSet<&'a TPeerId>,
+}
+
+impl<'a, TPeerId: Eq + Hash> AuthorSet<'a, TPeerId> {
+    fn insert(&mut self, entry: Entry<'a, TPeerId>) {
+        match entry.slot {
+            Some(slot) if slot < AUTHOR_BITS => self.bits[slot / 64] |= 1 << (slot % 64),
+            Some(slot) => {
+                let word = (slot - AUTHOR_BITS) / 64;
+                if word >= self.wide.len() {
+                    self.wide.resize(word + 1, 0);
+                }
+                self.wide[word] |= 1 << (slot % 64);
+            }
+            None => {
+                self.others.insert(entry.author);
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        let numbered: u32 = self
+            .bits
+            .iter()
+            .chain(&self.wide)
+            .map(|word| word.count_ones())
+            .sum();
+        numbered as usize + self.others.len()
+    }
+}
+
+impl<'a, TPeerId: Eq + Hash> FromIterator<Entry<'a, TPeerId>> for AuthorSet<'a, TPeerId> {
+    fn from_iter<I: IntoIterator<Item = Entry<'a, TPeerId>>>(entries: I) -> Self {
+        let mut set = Self {
+            bits: [0; AUTHOR_WORDS],
+            wide: vec![],
+            others: HashSet::new(),
+        };
+        for entry in entries {
+            set.insert(entry);
+        }
+        set
+    }
+}
+
+fn witnesses<T: EventTable + ?Sized>(
+    table: &T,
+    round: RoundNum,
+) -> impl Iterator<Item = &event::Hash> {
+    table
+        .round_events(round)
+        .into_iter()
+        .flatten()
+        .filter(move |e| table.is_witness(e))
+}
+
+/// Iterator over the event and its ancestors whose round number is
+/// `>= min_round` (events with undetermined round are always included).
+/// `None` if the event is unknown.
+pub fn ancestors<'a, T: EventTable + ?Sized>(
+    table: &'a T,
+    event: &'a event::Hash,
+    min_round: RoundNum,
+) -> Option<Ancestors<'a, T>> {
+    bounded_ancestors(table, event, MinRound(min_round))
+}
+
+/// Iterator over the event and its ancestors admitted by `bound`. The walk
+/// doesn't go past events that are not admitted, so their ancestors are
+/// skipped as well. `None` if the event is unknown.
+pub fn bounded_ancestors<'a, T: EventTable + ?Sized, B: Bound<T>>(
+    table: &'a T,
+    event: &'a event::Hash,
+    bound: B,
+) -> Option<Ancestors<'a, T, B>> {
+    table.entry(event)?;
+    let scratch = table.scratch();
+    let mut iter = Ancestors {
+        table,
+        stack: scratch.map(Scratch::take_stack).unwrap_or_default(),
+        visited: scratch.map(Scratch::take_set).unwrap_or_default(),
+        scratch,
+        bound,
+    };
+    iter.push_self_ancestors(event);
+    Some(iter)
+}
+
+/// Which events a walk over ancestors expands
+pub trait Bound<T: ?Sized> {
+    fn admits(&self, table: &T, event: &event::Hash) -> bool;
+}
+
+/// Events of the round or later ones, and events with undetermined round
+#[derive(Debug, Clone, Copy)]
+pub struct MinRound(pub RoundNum);
+
+impl<T: EventTable + ?Sized> Bound<T> for MinRound {
+    fn admits(&self, table: &T, event: &event::Hash) -> bool {
+        !matches!(table.round(event), Some(r) if r < self.0)
+    }
+}
+
+impl<T: ?Sized, F: Fn(&T, &event::Hash) -> bool> Bound<T> for F {
+    fn admits(&self, table: &T, event: &event::Hash) -> bool {
+        self(table, event)
+    }
+}
+
+pub struct Ancestors<'a, T: ?Sized, B = MinRound> {
+    table: &'a T,
+    /// Owned, so the buffer can go back to the [`Scratch`] pool. The
+    /// references handed out are taken from the table's entries.
+    stack: Vec<event::Hash>,
+    visited: HashSet<event::Hash>,
+    scratch: Option<&'a Scratch>,
+    bound: B,
+}
+
+impl<'a, T: ?Sized, B> Drop for Ancestors<'a, T, B> {
+    fn drop(&mut self) {
+        if let Some(scratch) = self.scratch {
+            scratch.put_stack(std::mem::take(&mut self.stack));
+            scratch.put_set(std::mem::take(&mut self.visited));
+        }
+    }
+}
+
+impl<'a, T: EventTable + ?Sized, B: Bound<T>> Ancestors<'a, T, B> {
+    /// Not visited yet, admitted by the bound and known (pruned ancestors
+    /// are unknown, the walk ends there)
+    fn expands(&self, event: &event::Hash) -> bool {
+        !self.visited.contains(event)
+            && self.bound.admits(self.table, event)
+            && self.table.entry(event).is_some()
+    }
+
+    fn push_self_ancestors(&mut self, mut event: &'a event::Hash) {
+        if !self.expands(event) {
+            return;
+        }
+        loop {
+            self.stack.push(event.clone());
+            self.visited.insert(event.clone());
+            let entry = self.table.entry(event).expect("Checked before pushing");
+            match entry.parents {
+                // All self ancestors are visited, out of the bound or
+                // pruned otherwise
+                Some(Parents { self_parent, .. }) if self.expands(self_parent) => {
+                    event = self_parent
+                }
+                _ => break,
+            }
+        }
+    }
+}
+
+impl<'a, T: EventTable + ?Sized, B: Bound<T>> Iterator for Ancestors<'a, T, B> {
+    type Item = &'a event::Hash;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.stack.pop()?;
+        let entry = self.table.entry(&event).expect("Pushed events are known");
+        if let Some(Parents { other_parent, .. }) = entry.parents {
+            self.push_self_ancestors(other_parent);
+        }
+        Some(entry.hash)
+    }
+}
+
+/// True if `target` is an ancestor of `observer` (or the same event).
+/// Errors if any of them is unknown or the round of `target` is not
+/// determined.
+pub fn see<T: EventTable + ?Sized>(
+    table: &T,
+    observer: &event::Hash,
+    target: &event::Hash,
+) -> Result<bool, UnknownEvent> {
+    table
+        .entry(target)
+        .ok_or_else(|| UnknownEvent(target.clone()))?;
+    // Rounds never decrease from parents to children, so ancestors of
+    // smaller rounds can't be `target` or its descendants. The walk stops
+    // at them right away.
+    let target_round = table
+        .round(target)
+        .ok_or_else(|| UnknownEvent(target.clone()))?;
+    Ok(bounded_ancestors(table, observer, MinRound(target_round))
+        .ok_or_else(|| UnknownEvent(observer.clone()))?
+        .any(|e| e == target))
+}
+
+/// True if `observer` sees events of a supermajority of members that see
+/// `target`
+pub fn strongly_see<T: EventTable + ?Sized>(
+    table: &T,
+    members: &impl Membership,
+    observer: &event::Hash,
+    target: &event::Hash,
+) -> Result<bool, UnknownEvent> {
+    table
+        .entry(target)
+        .ok_or_else(|| UnknownEvent(target.clone()))?;
+    let target_round = table
+        .round(target)
+        .ok_or_else(|| UnknownEvent(target.clone()))?;
+    let authors_seen: AuthorSet<_> = seeing_ancestors(table, observer, target, target_round)
+        .ok_or_else(|| UnknownEvent(observer.clone()))?
+        .into_iter()
+        .map(|e| table.entry(e).expect("Ancestors are known"))
+        .collect();
+    Ok(supermajority(
+        authors_seen.len(),
+        members.size(target_round),
+    ))
+}
+
+/// Value of each ancestor of `observer` of rounds `>= min_round`, computed
+/// from the values of its parents in the walk (ancestors of earlier rounds
+/// are left out). Each ancestor is looked at once. `None` if `observer` is
+/// unknown.
+fn fold_ancestors<'a, T: EventTable + ?Sized, V>(
+    table: &'a T,
+    observer: &'a event::Hash,
+    min_round: RoundNum,
+    mut value: impl FnMut(&'a event::Hash, &[&V]) -> V,
+) -> Option<(Vec<&'a event::Hash>, HashMap<&'a event::Hash, V>)> {
+    let walked: Vec<_> = ancestors(table, observer, min_round)?.collect();
+    let within: HashSet<_> = walked.iter().copied().collect();
+    let mut values: HashMap<&event::Hash, V> = HashMap::with_capacity(walked.len());
+    let mut stack = vec![];
+    for &start in &walked {
+        stack.push(start);
+        while let Some(&event) = stack.last() {
+            if values.contains_key(event) {
+                stack.pop();
+                continue;
+            }
+            let parents: Vec<_> = table
+                .entry(event)
+                .expect("Ancestors are known")
+                .parents
+                .map(|p| [&p.self_parent, &p.other_parent])
+                .into_iter()
+                .flatten()
+                .filter(|p| within.contains(p))
+                .collect();
+            let pending: Vec<_> = parents
+                .iter()
+                .copied()
+                .filter(|p| !values.contains_key(p))
+                .collect();
+            if pending.is_empty() {
+                let of_parents: Vec<_> = parents.iter().map(|p| &values[p]).collect();
+                let computed = value(event, &of_parents);
+                values.insert(event, computed);
+                stack.pop();
+            } else {
+                stack.extend(pending);
+            }
+        }
+    }
+    Some((walked, values))
+}
+
+/// Ancestors of `observer` that see `target`, which is of `target_round`:
+/// `target` and the ancestors with a parent that sees it. `None` if
+/// `observer` is unknown.
+fn seeing_ancestors<'a, T: EventTable + ?Sized>(
+    table: &'a T,
+    observer: &'a event::Hash,
+    target: &event::Hash,
+    target_round: RoundNum,
+) -> Option<Vec<&'a event::Hash>> {
+    let (walked, sees) = fold_ancestors(table, observer, target_round, |event, parents| {
+        event == target || parents.iter().any(|&&sees| sees)
+    })?;
+    Some(walked.into_iter().filter(|e| sees[e]).collect())
+}
+
+/// Witnesses of round `round` (other than `observer`) that `observer`
+/// strongly sees. Same as [`strongly_see`] for each of them, but in a
+/// single walk: every ancestor gets a bitset of the witnesses it sees, and
+/// every author the union of the bitsets of its events.
+fn strongly_seen_witnesses<'a, T: EventTable + ?Sized>(
+    table: &'a T,
+    members: &impl Membership,
+    observer: &'a event::Hash,
+    round: RoundNum,
+) -> Option<Vec<&'a event::Hash>> {
+    let candidates: Vec<_> = witnesses(table, round).filter(|w| *w != observer).collect();
+    let position: HashMap<_, _> = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, w)| (*w, i))
+        .collect();
+    let words = candidates.len().div_ceil(64);
+    let (walked, seen) = fold_ancestors(table, observer, round, |event, parents: &[&Vec<u64>]| {
+        let mut bits = vec![0u64; words];
+        for parent in parents {
+            bits.iter_mut()
+                .zip(parent.iter())
+                .for_each(|(b, p)| *b |= p);
+        }
+        if let Some(i) = position.get(event) {
+            bits[i / 64] |= 1 << (i % 64);
+        }
+        bits
+    })?;
+    let mut by_author: HashMap<&T::PeerId, Vec<u64>> = HashMap::new();
+    for event in walked {
+        let author = table.entry(event).expect("Ancestors are known").author;
+        let bits = by_author.entry(author).or_insert_with(|| vec![0; words]);
+        bits.iter_mut().zip(&seen[event]).for_each(|(b, s)| *b |= s);
+    }
+    Some(
+        candidates
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| {
+                let authors = by_author
+                    .values()
+                    .filter(|bits| bits[i / 64] & (1 << (i % 64)) != 0)
+                    .count();
+                supermajority(authors, members.size(round))
+            })
+            .map(|(_, w)| *w)
+            .collect(),
+    )
+}
+
+/// Round of the event: the max of its parents' rounds, +1 if it strongly
+/// sees witnesses of a supermajority in that round. Parents must have
+/// their rounds determined.
+pub fn determine_round<T: EventTable + ?Sized>(
+    table: &T,
+    members: &impl Membership,
+    event: &event::Hash,
+) -> Result<RoundNum, UnknownEvent> {
+    let entry = table
+        .entry(event)
+        .ok_or_else(|| UnknownEvent(event.clone()))?;
+    if let Some(r) = table.round(event) {
+        return Ok(r);
+    }
+    let Some(Parents {
+        self_parent,
+        other_parent,
+    }) = entry.parents
+    else {
+        return Ok(0);
+    };
+    let parent_round = |parent| {
+        determine_round(table, members, parent).expect("Parents of known events must be known")
+    };
+    let r = std::cmp::max(parent_round(self_parent), parent_round(other_parent));
+    let authors_strongly_seen: AuthorSet<_> = strongly_seen_witnesses(table, members, event, r)
+        .expect("The event must be known")
+        .into_iter()
+        .map(|w| table.entry(w).expect("Witnesses must be known"))
+        .collect();
+    if supermajority(authors_strongly_seen.len(), members.size(r)) {
+        Ok(r + 1)
+    } else {
+        Ok(r)
+    }
+}
+
+/// Whether the event is the first of its author in its round (geneses are
+/// always witnesses). The rounds of the event and its self parent must be
+/// determined.
+pub fn determine_witness<T: EventTable + ?Sized>(
+    table: &T,
+    event: &event::Hash,
+) -> Result<bool, UnknownEvent> {
+    if table.is_witness(event) {
+        return Ok(true);
+    }
+    let entry = table
+        .entry(event)
+        .ok_or_else(|| UnknownEvent(event.clone()))?;
+    match entry.parents {
+        None => Ok(true),
+        Some(Parents { self_parent, .. }) => {
+            let round = |e| table.round(e).expect("Rounds must be determined");
+            Ok(round(event) > round(self_parent))
+        }
+    }
+}
+
+/// Vote of `voter` in a coin round: a bit of its hash, or a random bit
+/// derived from the hash and `seed`
+pub fn coin(voter: &event::Hash, seed: Option<u64>) -> bool {
+    // TODO: use actual signature, not sure if makes a diff tho
+    let y_sig = voter.as_ref();
+    if let Some(seed) = seed {
+        let mut voter_bytes = [0; 8];
+        voter_bytes.copy_from_slice(&y_sig[..8]);
+        return StdRng::seed_from_u64(seed ^ u64::from_le_bytes(voter_bytes)).gen();
+    }
+    let middle_bit_index = y_sig.len() * 8 / 2;
+    let middle_byte_index = middle_bit_index / 8;
+    let middle_byte = y_sig[middle_byte_index];
+    let middle_bit_index = middle_bit_index % 8;
+    (middle_byte >> middle_bit_index & 1) != 0
+}
+
+/// Vote of `voter` of round `round` in a coin round of the election of
+/// `witness`, according to [`Voting::coin`]
+fn coin_vote<T: EventTable + ?Sized>(
+    table: &T,
+    members: &impl Membership,
+    voting: Voting,
+    witness: &event::Hash,
+    round: RoundNum,
+    voter: &event::Hash,
+) -> bool {
+    match voting.coin {
+        CoinStrategy::MiddleBit => coin(voter, voting.coin_seed),
+        CoinStrategy::InjectedCoin { seed } => {
+            let mut witness_bytes = [0; 8];
+            witness_bytes.copy_from_slice(&witness.as_ref()[..8]);
+            let round = (round as u64).rotate_left(32);
+            StdRng::seed_from_u64(seed ^ round ^ u64::from_le_bytes(witness_bytes)).gen()
+        }
+        CoinStrategy::RoundRobinLeaderBit => {
+            let leader_index = round % members.size(round);
+            // Several with forks, the smallest hash wins
+            let leader = witnesses(table, round - 1)
+                .filter(|h| {
+                    table.entry(h).and_then(|e| table.canonical_index(e.author))
+                        == Some(leader_index)
+                })
+                .filter(|h| {
+                    strongly_see(table, members, voter, h)
+                        .expect("Witnesses from index must be known")
+                })
+                .min();
+            coin(leader.unwrap_or(voter), voting.coin_seed)
+        }
+    }
+}
+
+/// Fame of the witness according to the elections held by witnesses of
+/// the following rounds. A witness is famous if witnesses of the next round
+/// seeing it win the vote.
+pub fn fame<T: EventTable + ?Sized>(
+    table: &T,
+    members: &impl Membership,
+    voting: Voting,
+    witness: &event::Hash,
+) -> Result<Election, UnknownEvent> {
+    fame_by(table, members, voting, witness, |_| true, None)
+}
+
+/// [`fame`] as decided by the voters for which `counted` is true, of
+/// rounds up to `last_round`. Other voters still vote, but their decisions
+/// are not taken. E.g. counting only famous voters gives an outcome all
+/// members agree on, as they agree on the famous witnesses.
+pub fn fame_by<T: EventTable + ?Sized>(
+    table: &T,
+    members: &impl Membership,
+    voting: Voting,
+    witness: &event::Hash,
+    counted: impl Fn(&event::Hash) -> bool,
+    last_round: Option<RoundNum>,
+) -> Result<Election, UnknownEvent> {
+    let undecided = Election {
+        fame: WitnessFamousness::Undecided,
+        decided_at: None,
+    };
+    table
+        .entry(witness)
+        .ok_or_else(|| UnknownEvent(witness.clone()))?;
+    let r = table.round(witness).expect("Round of the witness is known");
+
+    // first round of the election
+    if table.round_events(r + 1).is_none() {
+        return Ok(undecided);
+    }
+    let scratch = table.scratch();
+    let mut prev_round_votes = scratch.map(Scratch::take_votes).unwrap_or_default();
+    let mut this_round_votes = scratch.map(Scratch::take_votes).unwrap_or_default();
+    let election = elect(
+        table,
+        members,
+        voting,
+        witness,
+        (&counted, last_round),
+        &mut prev_round_votes,
+        &mut this_round_votes,
+    );
+    if let Some(scratch) = scratch {
+        scratch.put_votes(prev_round_votes);
+        scratch.put_votes(this_round_votes);
+    }
+    election
+}
+
+/// Voters whose decisions are taken, and the last round of voters
+type Counted<'a> = (&'a dyn Fn(&event::Hash) -> bool, Option<RoundNum>);
+
+/// Rounds of the election of [`fame_by`] for the witness, with empty vote
+/// maps given
+fn elect<T: EventTable + ?Sized>(
+    table: &T,
+    members: &impl Membership,
+    voting: Voting,
+    witness: &event::Hash,
+    (counted, last_round): Counted<'_>,
+    prev_round_votes: &mut HashMap<event::Hash, bool>,
+    this_round_votes: &mut HashMap<event::Hash, bool>,
+) -> Result<Election, UnknownEvent> {
+    let r = table.round(witness).expect("Round of the witness is known");
+    for y_hash in witnesses(table, r + 1) {
+        prev_round_votes.insert(y_hash.clone(), see(table, y_hash, witness)?);
+    }
+
+    let mut voter_round = r + 2;
+    while table.round_events(voter_round).is_some() && last_round.is_none_or(|l| voter_round <= l) {
+        let d = voter_round - r;
+        let n = Weight::count(members.size(voter_round));
+        for y_hash in witnesses(table, voter_round) {
+            // The set of witness events in round (y.round-1) that y can strongly see
+            let s = witnesses(table, voter_round - 1).filter(|h| {
+                strongly_see(table, members, y_hash, h).expect("Witnesses from index must be known")
+            });
+            // count votes, see `arith`
+            let add_vote = |weight: Weight| {
+                weight
+                    .checked_add(Stake::UNIT)
+                    .expect("votes of witnesses of a round fit in a weight")
+            };
+            let (votes_for, votes_against) = s.fold(
+                (Weight::ZERO, Weight::ZERO),
+                |(yes, no), prev_round_witness| {
+                    match prev_round_votes.get(prev_round_witness) {
+                        Some(true) => (add_vote(yes), no),
+                        Some(false) => (yes, add_vote(no)),
+                        // Should not happen but don't just panic, maybe return error later
+                        None => (yes, no),
+                    }
+                },
+            );
+            // majority vote in s ( is TRUE for a tie )
+            let v = votes_for >= votes_against;
+            // number of events in s with a vote of v
+            let t = std::cmp::max(votes_for, votes_against);
+
+            if !d.is_multiple_of(voting.coin_frequency) {
+                // Normal round: decide on supermajority
+                if t.is_supermajority_of(n) && counted(y_hash) {
+                    let fame = match v {
+                        true => WitnessFamousness::Yes,
+                        false => WitnessFamousness::No,
+                    };
+                    return Ok(Election {
+                        fame,
+                        decided_at: Some(voter_round),
+                    });
+                }
+                this_round_votes.insert(y_hash.clone(), v);
+            } else {
+                // Coin round: keep the supermajority vote, flip a coin otherwise
+                let vote = if t.is_supermajority_of(n) {
+                    v
+                } else {
+                    coin_vote(table, members, voting, witness, voter_round, y_hash)
+                };
+                this_round_votes.insert(y_hash.clone(), vote);
+            }
+        }
+        std::mem::swap(prev_round_votes, this_round_votes);
+        this_round_votes.clear();
+        voter_round += 1;
+    }
+    Ok(Election {
+        fame: WitnessFamousness::Undecided,
+        decided_at: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::datastructure::export::json::JsonFame;
+    use crate::testing::fixture;
+
+    /// Table filled only through the functions of this module
+    #[derive(Default)]
+    struct Table {
+        entries: HashMap<event::Hash, (u64, Option<Parents>)>,
+        rounds: HashMap<event::Hash, RoundNum>,
+        round_events: Vec<HashSet<event::Hash>>,
+        witnesses: HashSet<event::Hash>,
+        scratch: Option<Scratch>,
+        /// Give authors slots and canonical indices equal to their ids
+        numbered: bool,
+    }
+
+    impl EventTable for Table {
+        type PeerId = u64;
+
+        fn entry(&self, event: &event::Hash) -> Option<Entry<'_, u64>> {
+            let (hash, (author, parents)) = self.entries.get_key_value(event)?;
+            Some(Entry {
+                hash,
+                author,
+                slot: self.numbered.then_some(*author as usize),
+                parents: parents.as_ref(),
+            })
+        }
+
+        fn round(&self, event: &event::Hash) -> Option<RoundNum> {
+            self.rounds.get(event).copied()
+        }
+
+        fn canonical_index(&self, peer: &u64) -> Option<usize> {
+            self.numbered.then_some(*peer as usize)
+        }
+
+        fn round_events(&self, round: RoundNum) -> Option<&HashSet<event::Hash>> {
+            self.round_events.get(round)
+        }
+
+        fn is_witness(&self, event: &event::Hash) -> bool {
+            self.witnesses.contains(event)
+        }
+
+        fn scratch(&self) -> Option<&Scratch> {
+            self.scratch.as_ref()
+        }
+    }
+
+    impl Table {
+        fn insert(&mut self, hash: event::Hash, author: u64, parents: Option<Parents>) {
+            let members = 4;
+            self.entries.insert(hash.clone(), (author, parents));
+            let round = determine_round(self, &members, &hash).unwrap();
+            self.rounds.insert(hash.clone(), round);
+            if self.round_events.len() <= round {
+                self.round_events.push(HashSet::new());
+            }
+            self.round_events[round].insert(hash.clone());
+            if determine_witness(self, &hash).unwrap() {
+                self.witnesses.insert(hash);
+            }
+        }
+    }
+
+    fn check_decisions(mut table: Table) -> Table {
+        let scenario = fixture::detailed_example();
+        let built = scenario.build().unwrap();
+        let names = scenario
+            .peers
+            .iter()
+            .map(|p| format!("GENESIS_{}", p))
+            .chain(scenario.events.iter().map(|e| e.name.clone()));
+        for name in names {
+            let info = built.graph.event_info(built.hash(&name)).unwrap();
+            table.insert(info.hash, info.author, info.parents);
+        }
+        let voting = Voting {
+            coin_frequency: scenario.coin_frequency,
+            coin_seed: None,
+            coin: CoinStrategy::MiddleBit,
+        };
+        for (name, expected_round) in &scenario.expected.rounds {
+            assert_eq!(
+                table.round(built.hash(name)),
+                Some(*expected_round),
+                "{}",
+                name
+            );
+        }
+        for (name, expected_fame) in &scenario.expected.witnesses {
+            let hash = built.hash(name);
+            assert!(table.is_witness(hash), "{}", name);
+            let election = fame(&table, &4, voting, hash).unwrap();
+            assert_eq!(&JsonFame::from(&election.fame), expected_fame, "{}", name);
+            assert_eq!(
+                election.decided_at.is_some(),
+                election.fame != WitnessFamousness::Undecided
+            );
+        }
+        table
+    }
+
+    #[test]
+    fn decisions_match_graph() {
+        check_decisions(Table {
+            numbered: true,
+            ..Default::default()
+        });
+        // Authors hashed instead
+        check_decisions(Table::default());
+    }
+
+    #[test]
+    fn witnesses_strongly_seen_in_one_walk() {
+        let scenario = fixture::random_gossip();
+        let built = scenario.build().unwrap();
+        let graph = &built.graph;
+        let members = scenario.peers.len();
+        for event in scenario.events.iter().map(|e| built.hash(&e.name)) {
+            let round = graph.round(event).unwrap();
+            for r in round.saturating_sub(1)..=round {
+                let mut expected: Vec<_> = witnesses(graph, r)
+                    .filter(|w| *w != event && strongly_see(graph, &members, event, w).unwrap())
+                    .collect();
+                let mut seen = strongly_seen_witnesses(graph, &members, event, r).unwrap();
+                expected.sort();
+                seen.sort();
+                assert_eq!(seen, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn authors_counted_past_bitset() {
+        let authors: Vec<u64> = (0..2 * AUTHOR_BITS as u64).collect();
+        let hash = event::Hash::from_array([0; 64]);
+        let entry = |author: &u64, numbered: bool| Entry {
+            hash: &hash,
+            author: &authors[*author as usize],
+            slot: numbered.then_some(*author as usize),
+            parents: None,
+        };
+        for numbered in [true, false] {
+            let set: AuthorSet<_> = authors
+                .iter()
+                .chain(&authors[..10])
+                .chain(&authors[AUTHOR_BITS..AUTHOR_BITS + 10])
+                .map(|a| entry(a, numbered))
+                .collect();
+            assert_eq!(set.len(), authors.len());
+            let (expected_wide, expected_hashed) = if numbered {
+                (AUTHOR_WORDS, 0)
+            } else {
+                (0, 2 * AUTHOR_BITS)
+            };
+            assert_eq!(set.wide.len(), expected_wide);
+            assert_eq!(set.others.len(), expected_hashed);
+        }
+    }
+
+    #[test]
+    fn scratch_buffers_reused() {
+        let table = check_decisions(Table {
+            scratch: Some(Scratch::default()),
+            ..Default::default()
+        });
+        let scratch = table.scratch.unwrap();
+        // Everything taken was given back
+        let sets = scratch.sets.lock().unwrap();
+        assert!(!sets.is_empty() && sets.len() <= SCRATCH_POOL_LEN);
+        assert!(sets.iter().all(|set| set.is_empty() && set.capacity() > 0));
+        let stacks = scratch.stacks.lock().unwrap();
+        assert!(!stacks.is_empty());
+        assert!(stacks
+            .iter()
+            .all(|stack| stack.is_empty() && stack.capacity() > 0));
+        drop(stacks);
+        assert!(!scratch.votes.lock().unwrap().is_empty());
+        drop(sets);
+        scratch.release();
+        assert!(scratch.sets.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn bounded_walks_stop_at_excluded_events() {
+        let table = check_decisions(Table::default());
+        for observer in table.entries.keys() {
+            let author = table.entry(observer).unwrap().author;
+            // Only the author's own events: the walk can't leave its chain
+            let own: Vec<_> = bounded_ancestors(&table, observer, |t: &Table, e: &event::Hash| {
+                t.entry(e).unwrap().author == author
+            })
+            .unwrap()
+            .collect();
+            let mut chain = vec![observer];
+            while let Some(parents) = table.entry(chain.last().unwrap()).unwrap().parents {
+                chain.push(&parents.self_parent);
+            }
+            assert_eq!(own.len(), chain.len());
+            assert!(chain.iter().all(|e| own.contains(e)));
+
+            let round = table.round(observer).unwrap();
+            let by_round: HashSet<_> =
+                bounded_ancestors(&table, observer, |t: &Table, e: &event::Hash| {
+                    t.round(e) >= Some(round)
+                })
+                .unwrap()
+                .collect();
+            assert_eq!(
+                by_round,
+                ancestors(&table, observer, round).unwrap().collect()
+            );
+        }
+    }
+
+    #[test]
+    fn injected_coin_shared_by_voters() {
+        let table = Table::default();
+        let voting = Voting {
+            coin_frequency: 2,
+            coin_seed: None,
+            coin: CoinStrategy::InjectedCoin { seed: 5 },
+        };
+        let witness = event::Hash::from_array([1; 64]);
+        let flips: Vec<Vec<bool>> = (2..34)
+            .map(|round| {
+                (0..8u8)
+                    .map(|v| {
+                        let voter = event::Hash::from_array([v; 64]);
+                        coin_vote(&table, &4, voting, &witness, round, &voter)
+                    })
+                    .collect()
+            })
+            .collect();
+        // Same within a round, but not the same in all rounds
+        assert!(flips
+            .iter()
+            .all(|round| round.iter().all(|&f| f == round[0])));
+        assert!(flips.iter().any(|round| round[0]) && flips.iter().any(|round| !round[0]));
+    }
+
+    #[test]
+    fn unknown_events_reported() {
+        let table = Table::default();
+        let hash = event::Hash::from_array([1; 64]);
+        assert_eq!(
+            determine_round(&table, &1, &hash),
+            Err(UnknownEvent(hash.clone()))
+        );
+        assert!(ancestors(&table, &hash, 0).is_none());
+        assert!(fame(
+            &table,
+            &1,
+            Voting {
+                coin_frequency: 999,
+                coin_seed: None,
+                coin: CoinStrategy::MiddleBit,
+            },
+            &hash
+        )
+        .is_err());
+
+        // Known, but without a round
+        let mut table = Table::default();
+        table.entries.insert(hash.clone(), (1, None));
+        assert_eq!(see(&table, &hash, &hash), Err(UnknownEvent(hash.clone())));
+        assert_eq!(
+            strongly_see(&table, &4, &hash, &hash),
+            Err(UnknownEvent(hash.clone()))
+        );
+    }
+}
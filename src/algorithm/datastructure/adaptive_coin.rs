@@ -0,0 +1,186 @@
+//! Coin frequency following the observed elections.
+//!
+//! Coin rounds (every `coin_frequency`th round of an election) never
+//! decide, so frequent coins delay elections that would have been decided
+//! anyway, while rare coins let an adversary splitting the votes stall an
+//! election for longer. With [`Graph::set_adaptive_coin`] the frequency of
+//! the elections of each round follows the elections of earlier rounds: it
+//! grows by one while they are decided before their first coin round, and
+//! halves (down to [`AdaptiveCoin::min`]) as soon as one of them is not. It
+//! never exceeds [`AdaptiveCoin::max`], so coin rounds keep coming and
+//! elections still terminate under attack.
+//!
+//! All members must use the same frequencies, so they are a pure function
+//! of what all members agree on. The frequency of round `r` looks at the
+//! elections of the famous witnesses of `window` rounds ending `lag` rounds
+//! before `r`, as decided by the famous voters of rounds before `r`.
+//! Elections of round `r` are therefore held only after round `r - 1` is
+//! decided.
+
+use std::hash::Hash;
+
+use super::{Graph, WitnessFamousness};
+use crate::algorithm::{core, RoundNum};
+
+/// Parameters of the adaptive coin frequency, see the [module docs](self)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptiveCoin {
+    /// Lowest frequency, at least 3. With 2 the coin comes in the first
+    /// voting round, so no election would be fast and the frequency would
+    /// never grow again.
+    pub min: usize,
+    /// Highest frequency, also used until there is enough history
+    pub max: usize,
+    /// Rounds whose elections set the frequency of a round
+    pub window: usize,
+    /// Rounds between the window and the round whose frequency it sets.
+    /// Elections lasting `lag` rounds or more count as slow.
+    pub lag: usize,
+}
+
+impl Default for AdaptiveCoin {
+    fn default() -> Self {
+        Self {
+            min: 3,
+            max: 10,
+            window: 4,
+            lag: 6,
+        }
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + Hash,
+{
+    /// Adjust the coin frequency to the observed elections instead of
+    /// using the one given at creation. All members must use the same
+    /// parameters, and set them before the elections are held.
+    ///
+    /// Panics if `min` is below 3 or above `max`, or `lag` is below 3 (no
+    /// election is decided in less than 2 rounds).
+    pub fn set_adaptive_coin(&mut self, adaptive: Option<AdaptiveCoin>) {
+        if let Some(adaptive) = adaptive {
+            assert!(
+                3 <= adaptive.min && adaptive.min <= adaptive.max,
+                "Frequencies must be in 3..=max"
+            );
+            assert!(adaptive.lag >= 3, "Lag must be at least 3 rounds");
+        }
+        self.adaptive_coin = adaptive;
+        self.coin_schedule.get_mut().unwrap().clear();
+    }
+
+    /// Coin frequency of the elections of the witnesses of `round`. `None`
+    /// if it is adaptive and the previous round is not decided yet.
+    pub fn coin_frequency_at(&self, round: RoundNum) -> Option<usize> {
+        let Some(adaptive) = self.adaptive_coin else {
+            return Some(self.coin_frequency);
+        };
+        loop {
+            let next = {
+                let schedule = self.coin_schedule.lock().unwrap();
+                if let Some(frequency) = schedule.get(round) {
+                    return Some(*frequency);
+                }
+                schedule.len()
+            };
+            let frequency = self.next_coin_frequency(adaptive, next)?;
+            let mut schedule = self.coin_schedule.lock().unwrap();
+            // Elections looked at may have filled it in the meantime
+            if schedule.len() == next {
+                schedule.push(frequency);
+            }
+        }
+    }
+
+    /// Frequency of `round`, the ones of earlier rounds are known
+    fn next_coin_frequency(&self, adaptive: AdaptiveCoin, round: RoundNum) -> Option<usize> {
+        let Some(first) = (round + 1).checked_sub(adaptive.lag + adaptive.window) else {
+            return Some(adaptive.max);
+        };
+        if self.last_known_decided_round.is_none_or(|d| d + 1 < round) {
+            return None;
+        }
+        let previous = self.coin_schedule.lock().unwrap()[round - 1];
+        let fast = (first..=round - adaptive.lag).all(|q| self.elections_fast(adaptive, q));
+        Some(match fast {
+            true => (previous + 1).min(adaptive.max),
+            false => (previous / 2).max(adaptive.min),
+        })
+    }
+
+    /// Whether famous voters decided the elections of all famous witnesses
+    /// of the round before the first coin round and within the lag. The
+    /// round and the lag rounds after it must be decided.
+    fn elections_fast(&self, adaptive: AdaptiveCoin, round: RoundNum) -> bool {
+        let frequency = self.coin_schedule.lock().unwrap()[round];
+        let voting = core::Voting {
+            coin_frequency: frequency,
+            coin_seed: self.coin_seed,
+            coin: self.coin_strategy,
+        };
+        let famous =
+            |hash: &_| self.witnesses.lock().unwrap().get(hash) == Some(&WitnessFamousness::Yes);
+        let last_voters = round + frequency.min(adaptive.lag) - 1;
+        let members = self.members_count();
+        self.witnesses_between(round, round)
+            .filter(|(_, witness)| famous(witness))
+            .all(|(_, witness)| {
+                let election =
+                    core::fame_by(self, &members, voting, witness, famous, Some(last_voters))
+                        .expect("witness is known");
+                election.decided_at.is_some()
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fixture, replay, GraphBuilder};
+
+    const ADAPTIVE: AdaptiveCoin = AdaptiveCoin {
+        min: 3,
+        max: 6,
+        window: 2,
+        lag: 3,
+    };
+
+    #[test]
+    fn frequency_stays_with_fast_elections() {
+        let example = fixture::random_gossip();
+        let reference = example.build().unwrap().graph;
+        let mut graph = GraphBuilder::new(&example.peers[0], 0u64, (), example.coin_frequency)
+            .build()
+            .unwrap()
+            .graph;
+        graph.set_adaptive_coin(Some(ADAPTIVE));
+        replay(&reference, &mut graph);
+
+        // Elections of honest random gossip end in 2 rounds, before the
+        // first coin round, so the frequency stays at the maximum
+        let decided = graph.last_decided_round().unwrap();
+        let schedule: Vec<_> = (0..=decided + 1)
+            .map(|round| graph.coin_frequency_at(round).unwrap())
+            .collect();
+        assert_eq!(schedule, vec![ADAPTIVE.max; decided + 2]);
+        // Not known before the previous round is decided
+        assert_eq!(graph.coin_frequency_at(decided + 2), None);
+        assert_eq!(graph.last_decided_round(), reference.last_decided_round());
+        assert!(graph.ordering.ordered().eq(reference.ordering.ordered()));
+
+        graph.set_adaptive_coin(None);
+        assert_eq!(graph.coin_frequency_at(decided + 2), Some(999));
+    }
+
+    #[test]
+    #[should_panic(expected = "Frequencies must be in 3..=max")]
+    fn coin_right_after_the_vote_rejected() {
+        // Elections with the coin in their first voting round are never
+        // fast, the frequency couldn't grow again
+        let mut graph = fixture::random_gossip().build().unwrap().graph;
+        graph.set_adaptive_coin(Some(AdaptiveCoin { min: 2, ..ADAPTIVE }));
+    }
+}
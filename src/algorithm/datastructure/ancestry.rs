@@ -0,0 +1,185 @@
+//! Common history of two events.
+//!
+//! [`Graph::common_ancestors`] finds the latest events both have as
+//! ancestors, e.g. to show where the histories known to two members meet,
+//! or up to where both can be pruned.
+//! [`Graph::lowest_common_self_ancestor`] finds where the chains of two
+//! events of one author meet, which for two branches of a fork is the event
+//! they forked from. It uses the sequence numbers of the author's events, so
+//! it only walks the two chains from there.
+
+use std::collections::HashSet;
+
+use super::{Graph, UnknownEvent};
+use crate::algorithm::event;
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash,
+{
+    /// Latest events that are ancestors of both `a` and `b` (an event is
+    /// its own ancestor): every common ancestor is an ancestor of one of
+    /// them. Sorted by the [canonical order](super::peer_order) of authors,
+    /// then by sequence number. Pruned ancestors are not looked at.
+    pub fn common_ancestors(
+        &self,
+        a: &event::Hash,
+        b: &event::Hash,
+    ) -> Result<Vec<event::Hash>, UnknownEvent> {
+        if !self.headers.contains_key(b) {
+            return Err(UnknownEvent(b.clone()));
+        }
+        let of_a = self.ancestors(a)?;
+        // Ancestors of a common ancestor are common too, so the walk stops
+        // at the first common ones
+        let mut candidates = vec![];
+        let mut visited = HashSet::new();
+        let mut to_visit = vec![b];
+        while let Some(hash) = to_visit.pop() {
+            if !visited.insert(hash) {
+                continue;
+            }
+            if of_a.contains(hash) {
+                candidates.push(hash);
+                continue;
+            }
+            if let Some(parents) = self.headers.get(hash).and_then(|h| h.parents.as_ref()) {
+                to_visit.extend([&parents.self_parent, &parents.other_parent]);
+            }
+        }
+        let mut latest = vec![];
+        'candidates: for &candidate in &candidates {
+            for &other in &candidates {
+                if other != candidate && self.is_ancestor(other, candidate)? {
+                    continue 'candidates;
+                }
+            }
+            let header = &self.headers[candidate];
+            latest.push((
+                self.canonical_index(&header.author),
+                header.sequence,
+                candidate.clone(),
+            ));
+        }
+        latest.sort();
+        Ok(latest.into_iter().map(|(_, _, hash)| hash).collect())
+    }
+
+    /// Latest event on the self-parent chains of both `a` and `b` (an event
+    /// is its own self-ancestor). `None` if they have different authors or
+    /// the chains meet below the pruned events.
+    pub fn lowest_common_self_ancestor(
+        &self,
+        a: &event::Hash,
+        b: &event::Hash,
+    ) -> Result<Option<event::Hash>, UnknownEvent> {
+        let header = |hash: &event::Hash| {
+            self.headers
+                .get(hash)
+                .ok_or_else(|| UnknownEvent(hash.clone()))
+        };
+        let (header_a, header_b) = (header(a)?, header(b)?);
+        if header_a.author != header_b.author {
+            return Ok(None);
+        }
+        let self_parent = |hash: &event::Hash| {
+            let parent = &self.headers.get(hash)?.parents.as_ref()?.self_parent;
+            self.headers.contains_key(parent).then(|| parent.clone())
+        };
+        let (mut a, mut b) = (a.clone(), b.clone());
+        // Down to the same sequence number, then in lockstep
+        for _ in header_b.sequence..header_a.sequence {
+            let Some(parent) = self_parent(&a) else {
+                return Ok(None);
+            };
+            a = parent;
+        }
+        for _ in header_a.sequence..header_b.sequence {
+            let Some(parent) = self_parent(&b) else {
+                return Ok(None);
+            };
+            b = parent;
+        }
+        while a != b {
+            let (Some(parent_a), Some(parent_b)) = (self_parent(&a), self_parent(&b)) else {
+                return Ok(None);
+            };
+            (a, b) = (parent_a, parent_b);
+        }
+        Ok(Some(a))
+    }
+
+    /// `hash` and all of its known ancestors
+    fn ancestors(&self, hash: &event::Hash) -> Result<HashSet<&event::Hash>, UnknownEvent> {
+        let start = self.headers.get_key_value(hash).map(|(key, _)| key);
+        let start = start.ok_or_else(|| UnknownEvent(hash.clone()))?;
+        let mut ancestors = HashSet::new();
+        let mut to_visit = vec![start];
+        while let Some(hash) = to_visit.pop() {
+            if !ancestors.insert(hash) {
+                continue;
+            }
+            if let Some(parents) = self.headers.get(hash).and_then(|h| h.parents.as_ref()) {
+                to_visit.extend(
+                    [&parents.self_parent, &parents.other_parent]
+                        .into_iter()
+                        .filter(|parent| self.headers.contains_key(*parent)),
+                );
+            }
+        }
+        Ok(ancestors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture;
+
+    #[test]
+    fn histories_meet() {
+        let built = fixture::fork().build().unwrap();
+        let graph = &built.graph;
+        let hash = |name| built.hash(name).clone();
+
+        // The branches meet at the genesis they forked from
+        assert_eq!(
+            graph
+                .lowest_common_self_ancestor(&hash("m2"), &hash("m2_fork"))
+                .unwrap(),
+            Some(hash("GENESIS_m"))
+        );
+        assert_eq!(
+            graph
+                .lowest_common_self_ancestor(&hash("m4"), &hash("m2_1"))
+                .unwrap(),
+            Some(hash("m2_1"))
+        );
+        assert_eq!(
+            graph
+                .lowest_common_self_ancestor(&hash("m2"), &hash("a2"))
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            graph
+                .common_ancestors(&hash("m2"), &hash("m2_fork"))
+                .unwrap(),
+            vec![hash("a1_1")]
+        );
+        assert_eq!(
+            graph.common_ancestors(&hash("a4"), &hash("m3")).unwrap(),
+            vec![hash("m3")]
+        );
+        assert_eq!(
+            graph.common_ancestors(&hash("m2"), &hash("a2")).unwrap(),
+            vec![hash("m2")]
+        );
+        let unknown = event::Hash::from_array([0; 64]);
+        assert_eq!(
+            graph.common_ancestors(&hash("a4"), &unknown),
+            Err(UnknownEvent(unknown))
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! Local notes of the application about events.
+//!
+//! Annotations (e.g. "executed", "rejected") are key-value strings attached
+//! to known events. They are not part of the events, so they are never
+//! hashed, signed or sent to other members, and they don't affect the
+//! consensus. They go away with their events when pruning and are copied
+//! by [`Graph::fork`].
+
+use std::collections::{BTreeMap, HashMap};
+
+use super::{Graph, UnknownEvent};
+use crate::algorithm::event;
+
+/// Annotations of a single event, by key
+pub type Annotations = BTreeMap<String, String>;
+
+pub(super) type AnnotationIndex = HashMap<event::Hash, Annotations>;
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+{
+    /// Set annotation `key` of the event, returns the previous value
+    pub fn annotate(
+        &mut self,
+        event: &event::Hash,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<Option<String>, UnknownEvent> {
+        if !self.all_events.contains_key(event) {
+            return Err(UnknownEvent(event.clone()));
+        }
+        Ok(self
+            .annotations
+            .entry(event.clone())
+            .or_default()
+            .insert(key.into(), value.into()))
+    }
+
+    pub fn annotation(&self, event: &event::Hash, key: &str) -> Option<&str> {
+        self.annotations.get(event)?.get(key).map(String::as_str)
+    }
+
+    /// All annotations of the event, `None` if there are none
+    pub fn annotations(&self, event: &event::Hash) -> Option<&Annotations> {
+        self.annotations.get(event)
+    }
+
+    pub fn remove_annotation(&mut self, event: &event::Hash, key: &str) -> Option<String> {
+        let annotations = self.annotations.get_mut(event)?;
+        let removed = annotations.remove(key);
+        if annotations.is_empty() {
+            self.annotations.remove(event);
+        }
+        removed
+    }
+
+    /// Events that have annotation `key`, with its values. In no
+    /// particular order.
+    pub fn annotated_events<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> impl Iterator<Item = (&'a event::Hash, &'a str)> + 'a {
+        self.annotations
+            .iter()
+            .filter_map(move |(hash, a)| Some((hash, a.get(key)?.as_str())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture;
+
+    #[test]
+    fn annotations_follow_events() {
+        let mut built = fixture::detailed_example().build().unwrap();
+        let [a1_1, d1_2] = ["a1_1", "d1_2"].map(|name| built.hash(name).clone());
+        let graph = &mut built.graph;
+        assert_eq!(graph.annotate(&a1_1, "state", "executed"), Ok(None));
+        assert_eq!(
+            graph.annotate(&a1_1, "state", "rejected"),
+            Ok(Some("executed".to_owned()))
+        );
+        graph.annotate(&d1_2, "state", "executed").unwrap();
+        graph.annotate(&d1_2, "note", "slow").unwrap();
+        let unknown = event::Hash::from_array([0; 64]);
+        assert_eq!(
+            graph.annotate(&unknown, "state", "executed"),
+            Err(UnknownEvent(unknown))
+        );
+
+        assert_eq!(graph.annotation(&a1_1, "state"), Some("rejected"));
+        assert_eq!(graph.event_info(&d1_2).unwrap().annotations.len(), 2);
+        let mut states: Vec<_> = graph.annotated_events("state").collect();
+        states.sort();
+        let mut expected = vec![(&a1_1, "rejected"), (&d1_2, "executed")];
+        expected.sort();
+        assert_eq!(states, expected);
+
+        assert_eq!(
+            graph.remove_annotation(&a1_1, "state"),
+            Some("rejected".to_owned())
+        );
+        assert_eq!(graph.annotations(&a1_1), None);
+
+        let fork = graph.fork();
+        assert_eq!(fork.annotations(&d1_2), graph.annotations(&d1_2));
+    }
+
+    #[test]
+    fn pruned_with_events() {
+        let example = fixture::random_gossip();
+        let reference = example.build().unwrap();
+        let mut graph = example.build_geneses().unwrap().graph;
+        graph.set_epoch_length(Some(2));
+        fixture::replay(&reference, &example.events, &mut graph, |graph, event| {
+            let hash = reference.hash(&event.name);
+            graph.annotate(hash, "name", &event.name).unwrap();
+        });
+        while graph.next_finalized_event().is_some() {}
+        let annotated = graph.annotations.len();
+        let pruned = graph.prune();
+        assert!(pruned > 0);
+        assert_eq!(graph.annotations.len(), annotated - pruned);
+        assert!(graph.annotations.keys().all(|h| graph.event(h).is_some()));
+    }
+}
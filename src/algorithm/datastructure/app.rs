@@ -0,0 +1,385 @@
+//! Replicated applications on top of the consensus.
+//!
+//! An application implements [`AppStateMachine`]: it applies payloads of
+//! finalized events one by one and reports the root of its state after each.
+//! [`Graph::drive`] feeds it the events finalized since the last call, so
+//! every replica goes through the same sequence of states.
+//!
+//! Errors are split in two (see [`ApplyError`]). A rejection depends only on
+//! the state and the transaction, so all replicas reject it alike: the state
+//! stays unchanged and applying goes on. A failure is local (e.g. the disk
+//! is full) and stops applying, the same transaction is retried by the next
+//! call.
+//!
+//! [`AppDriver::snapshot`] combines the application's own snapshot with the
+//! position in the consensus order, so a replica can be restored and catch
+//! up from there ([`AppDriver::restore`]).
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::Graph;
+use crate::algorithm::event;
+use crate::light;
+
+/// Version of the [`AppSnapshot`] encoding
+pub const APP_SNAPSHOT_VERSION: u16 = 1;
+
+/// Payload of a finalized event with its place in the consensus order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderedTransaction<'a, TPayload, TPeerId> {
+    /// Among all finalized events, starting from 0
+    pub position: usize,
+    pub event: &'a event::Hash,
+    pub author: &'a TPeerId,
+    pub payload: &'a TPayload,
+}
+
+#[derive(Error, Debug)]
+pub enum ApplyError<E> {
+    /// The transaction is invalid in the current state, e.g. spends more than
+    /// there is. Must not change the state.
+    #[error("Transaction rejected: {0}")]
+    Rejected(String),
+    /// Local failure, the transaction is applied again later
+    #[error("Application failed: {0}")]
+    Failed(E),
+}
+
+/// Deterministic state machine replicated by the consensus. All methods must
+/// depend only on the state and the arguments.
+pub trait AppStateMachine<TPayload, TPeerId> {
+    type Error;
+
+    /// Apply the transaction, returning the new state root
+    fn apply(
+        &mut self,
+        transaction: OrderedTransaction<'_, TPayload, TPeerId>,
+    ) -> Result<event::Hash, ApplyError<Self::Error>>;
+    fn state_root(&self) -> event::Hash;
+    fn snapshot(&self) -> Result<Vec<u8>, Self::Error>;
+    /// Replace the state with the one in the snapshot
+    fn restore(&mut self, snapshot: &[u8]) -> Result<(), Self::Error>;
+}
+
+#[derive(Error, Debug)]
+pub enum AppError<E> {
+    #[error("Application failed on event #{position}: {error}")]
+    Failed { position: usize, error: E },
+    #[error("Event #{0} was pruned before it was applied")]
+    Pruned(usize),
+    #[error("Snapshot of an unsupported version {0}")]
+    UnsupportedVersion(u16),
+    #[error("Restored state has root {restored}, the snapshot says {expected}")]
+    RootMismatch {
+        restored: event::Hash,
+        expected: event::Hash,
+    },
+    #[error("Snapshot was taken on a different history of events")]
+    HistoryMismatch,
+    #[error("Snapshot can't be encoded: {0}")]
+    Encoding(#[from] bincode::Error),
+}
+
+/// Application snapshot with the position in the consensus order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppSnapshot {
+    pub version: u16,
+    /// Number of applied events
+    pub applied: usize,
+    pub rejected: usize,
+    /// Root of the applied events, as in a [`light::Checkpoint`]
+    pub events_root: event::Hash,
+    pub state_root: event::Hash,
+    pub app: Vec<u8>,
+}
+
+impl AppSnapshot {
+    pub fn encode(&self) -> bincode::Result<Vec<u8>> {
+        bincode::serialize(self)
+    }
+
+    pub fn decode(bytes: &[u8]) -> bincode::Result<Self> {
+        bincode::deserialize(bytes)
+    }
+}
+
+/// Application being driven by a graph, see the [module docs](self)
+pub struct AppDriver<A> {
+    app: A,
+    applied: usize,
+    rejected: usize,
+    /// Events before `applied` must have this root, checked on the next
+    /// [`Graph::drive`] after a restore
+    unverified_root: Option<event::Hash>,
+}
+
+impl<A> AppDriver<A> {
+    /// Application with the initial state, nothing is applied yet
+    pub fn new(app: A) -> Self {
+        Self {
+            app,
+            applied: 0,
+            rejected: 0,
+            unverified_root: None,
+        }
+    }
+
+    pub fn app(&self) -> &A {
+        &self.app
+    }
+
+    /// Number of applied finalized events, rejected ones included
+    pub fn applied(&self) -> usize {
+        self.applied
+    }
+
+    pub fn rejected(&self) -> usize {
+        self.rejected
+    }
+
+    pub fn into_inner(self) -> A {
+        self.app
+    }
+
+    /// Combined snapshot of the application and the driver. `events_root`
+    /// is taken from the graph the application was driven by.
+    pub fn snapshot<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>(
+        &self,
+        graph: &Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+    ) -> Result<AppSnapshot, AppError<A::Error>>
+    where
+        A: AppStateMachine<TPayload, TPeerId>,
+    {
+        let app = self.app.snapshot().map_err(|error| AppError::Failed {
+            position: self.applied,
+            error,
+        })?;
+        Ok(AppSnapshot {
+            version: APP_SNAPSHOT_VERSION,
+            applied: self.applied,
+            rejected: self.rejected,
+            events_root: light::events_root(graph.ordering.ordered().take(self.applied)),
+            state_root: self.app.state_root(),
+            app,
+        })
+    }
+
+    /// Restore `app` from the snapshot. The history of the snapshot is
+    /// checked against the graph by the first [`Graph::drive`].
+    pub fn restore<TPayload, TPeerId>(
+        mut app: A,
+        snapshot: &AppSnapshot,
+    ) -> Result<Self, AppError<A::Error>>
+    where
+        A: AppStateMachine<TPayload, TPeerId>,
+    {
+        if snapshot.version != APP_SNAPSHOT_VERSION {
+            return Err(AppError::UnsupportedVersion(snapshot.version));
+        }
+        app.restore(&snapshot.app)
+            .map_err(|error| AppError::Failed {
+                position: snapshot.applied,
+                error,
+            })?;
+        let restored = app.state_root();
+        if restored != snapshot.state_root {
+            return Err(AppError::RootMismatch {
+                restored,
+                expected: snapshot.state_root.clone(),
+            });
+        }
+        Ok(Self {
+            app,
+            applied: snapshot.applied,
+            rejected: snapshot.rejected,
+            unverified_root: Some(snapshot.events_root.clone()),
+        })
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+{
+    /// Apply events finalized since the last call to the application.
+    /// Returns the number of applied (or rejected) events.
+    ///
+    /// Independent of [`next_finalized_event`](Self::next_finalized_event).
+    /// Events must be applied before they are [pruned](Self::prune).
+    pub fn drive<A>(&self, driver: &mut AppDriver<A>) -> Result<usize, AppError<A::Error>>
+    where
+        A: AppStateMachine<TPayload, TPeerId>,
+    {
+        if let Some(expected) = &driver.unverified_root {
+            if self.ordering.len() < driver.applied {
+                // Not finalized that far yet
+                return Ok(0);
+            }
+            if light::events_root(self.ordering.ordered().take(driver.applied)) != *expected {
+                return Err(AppError::HistoryMismatch);
+            }
+            driver.unverified_root = None;
+        }
+        let start = driver.applied;
+        for hash in self.ordering.ordered().skip(start) {
+            let event = self
+                .all_events
+                .get(hash)
+                .ok_or(AppError::Pruned(driver.applied))?;
+            let transaction = OrderedTransaction {
+                position: driver.applied,
+                event: hash,
+                author: event.author(),
+                payload: event.payload().ok_or(AppError::Pruned(driver.applied))?,
+            };
+            match driver.app.apply(transaction) {
+                Ok(_) => (),
+                Err(ApplyError::Rejected(_)) => driver.rejected += 1,
+                Err(ApplyError::Failed(error)) => {
+                    return Err(AppError::Failed {
+                        position: driver.applied,
+                        error,
+                    })
+                }
+            }
+            driver.applied += 1;
+        }
+        Ok(driver.applied - start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blake2::{Blake2b512, Digest};
+
+    use super::*;
+    use crate::testing::fixture;
+
+    /// Hash chain of applied events, rejecting every third one. Fails
+    /// once on the event at `fail_at`.
+    #[derive(Default)]
+    struct Chain {
+        root: Vec<u8>,
+        fail_at: Option<usize>,
+    }
+
+    impl Chain {
+        fn root_hash(&self) -> event::Hash {
+            let mut bytes = [0; 64];
+            bytes[..self.root.len()].copy_from_slice(&self.root);
+            event::Hash::from_array(bytes)
+        }
+    }
+
+    impl AppStateMachine<(), u64> for Chain {
+        type Error = String;
+
+        fn apply(
+            &mut self,
+            transaction: OrderedTransaction<'_, (), u64>,
+        ) -> Result<event::Hash, ApplyError<String>> {
+            if self
+                .fail_at
+                .take_if(|p| *p == transaction.position)
+                .is_some()
+            {
+                return Err(ApplyError::Failed("disk full".to_owned()));
+            }
+            if transaction.position.is_multiple_of(3) {
+                return Err(ApplyError::Rejected("third".to_owned()));
+            }
+            let mut hasher = Blake2b512::new();
+            hasher.update(&self.root);
+            hasher.update(transaction.event.as_ref());
+            hasher.update(transaction.author.to_le_bytes());
+            self.root = hasher.finalize().to_vec();
+            Ok(self.root_hash())
+        }
+
+        fn state_root(&self) -> event::Hash {
+            self.root_hash()
+        }
+
+        fn snapshot(&self) -> Result<Vec<u8>, String> {
+            Ok(self.root.clone())
+        }
+
+        fn restore(&mut self, snapshot: &[u8]) -> Result<(), String> {
+            self.root = snapshot.to_vec();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn replicas_agree_through_failures_and_restores() {
+        let graph = fixture::random_gossip().build().unwrap().graph;
+        let finalized = graph.ordering.len();
+        assert!(finalized > 10);
+        let mut reference = AppDriver::new(Chain::default());
+        assert_eq!(graph.drive(&mut reference).unwrap(), finalized);
+        assert_eq!(reference.rejected(), finalized.div_ceil(3));
+        assert_eq!(graph.drive(&mut reference).unwrap(), 0);
+
+        // Failure stops applying, the next call retries
+        let mut failing = AppDriver::new(Chain {
+            fail_at: Some(5),
+            ..Default::default()
+        });
+        assert!(matches!(
+            graph.drive(&mut failing),
+            Err(AppError::Failed { position: 5, .. })
+        ));
+        assert_eq!(failing.applied(), 5);
+        let snapshot = failing.snapshot(&graph).unwrap();
+        graph.drive(&mut failing).unwrap();
+        assert_eq!(failing.app().state_root(), reference.app().state_root());
+
+        let bytes = snapshot.encode().unwrap();
+        let mut restored =
+            AppDriver::restore(Chain::default(), &AppSnapshot::decode(&bytes).unwrap()).unwrap();
+        assert_eq!(graph.drive(&mut restored).unwrap(), finalized - 5);
+        assert_eq!(restored.app().state_root(), reference.app().state_root());
+        assert_eq!(restored.rejected(), reference.rejected());
+    }
+
+    #[test]
+    fn foreign_snapshots_rejected() {
+        let graph = fixture::random_gossip().build().unwrap().graph;
+        let other = fixture::detailed_example().build().unwrap().graph;
+        let mut driver = AppDriver::new(Chain::default());
+        graph.drive(&mut driver).unwrap();
+        let snapshot = driver.snapshot(&graph).unwrap();
+        let mut restored = AppDriver::restore(Chain::default(), &snapshot).unwrap();
+        // The other graph hasn't finalized that much yet
+        assert_eq!(other.drive(&mut restored).unwrap(), 0);
+
+        let mut early = AppDriver::new(Chain {
+            fail_at: Some(2),
+            ..Default::default()
+        });
+        assert!(graph.drive(&mut early).is_err());
+        let early = early.snapshot(&graph).unwrap();
+        let mut restored = AppDriver::restore(Chain::default(), &early).unwrap();
+        assert!(matches!(
+            other.drive(&mut restored),
+            Err(AppError::HistoryMismatch)
+        ));
+
+        let tampered = AppSnapshot {
+            state_root: event::Hash::from_array([1; 64]),
+            ..snapshot.clone()
+        };
+        assert!(matches!(
+            AppDriver::restore(Chain::default(), &tampered),
+            Err(AppError::RootMismatch { .. })
+        ));
+        let future = AppSnapshot {
+            version: APP_SNAPSHOT_VERSION + 1,
+            ..snapshot
+        };
+        assert!(matches!(
+            AppDriver::restore(Chain::default(), &future),
+            Err(AppError::UnsupportedVersion(_))
+        ));
+    }
+}
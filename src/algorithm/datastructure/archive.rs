@@ -0,0 +1,576 @@
+//! Compact encoding of events for storage.
+//!
+//! Most of a [`SignedEvent`] is hashes: its own and its parents'. In an
+//! archive of a whole segment of the graph none of them are needed:
+//! - the event hash is recomputed from the fields on load;
+//! - parents are referenced as (creator, sequence number) within the
+//!   archive. Parents outside of it (e.g. pruned) are stored in full;
+//! - authors are indices into a member table and timestamps are deltas from
+//!   the author's previous event;
+//! - payloads are stored once in a dictionary of distinct encodings shared
+//!   by the segment, events refer to them by index.
+//!
+//! Integers are LEB128 varints. The archive ends with a digest of all event
+//! hashes in order, so corruption that still decodes is detected on load.
+//! Signatures are kept as is and checked again when the events are pushed
+//! (see [`Graph::restore_archive`]). Archives are about 4x smaller than
+//! bincode of the events.
+//!
+//! Layout (version [`ARCHIVE_VERSION`]):
+//! ```text
+//! version: u16 LE
+//! members: n, n × (len, bincode of peer id)
+//! payloads: n, n × (len, encoded payload)
+//! events: n, n × event
+//! digest: 64 bytes
+//!
+//! event: author index, 0 + (len, bincode of genesis payload)
+//!                    | 1 + self parent ref + other parent ref,
+//!        zigzag timestamp delta, payload index, signature (64 bytes)
+//! ref: 0 + creator index + sequence number | 1 + hash (64 bytes)
+//! ```
+//!
+//! Periodic backups don't need to store the whole graph each time.
+//! [`Graph::archive_delta`] encodes only the events that are new since an
+//! [`ArchivePoint`] taken at the previous backup (geneses of new members
+//! included, fame decisions follow from the events), together with the
+//! [state hashes](Graph::state_hash) before and after:
+//! ```text
+//! version: u16 LE
+//! base state hash: 64 bytes
+//! state hash: 64 bytes
+//! archive of the new events
+//! ```
+//! [`Graph::apply_archive_delta`] checks that it is applied on top of the
+//! right state and that the events reproduce the new one.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use blake2::{Blake2b512, Digest};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{sync, Graph};
+use crate::algorithm::codec::{self, PayloadCodec};
+use crate::algorithm::event::{self, Signature, SignedEvent};
+use crate::algorithm::{Clock, PushError, Signer};
+use crate::Timestamp;
+
+/// Version written by this crate
+pub const ARCHIVE_VERSION: u16 = 1;
+
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+    #[error("Archive is truncated")]
+    Truncated,
+    #[error("Archive version {0} is not supported")]
+    UnsupportedVersion(u16),
+    #[error("Event {index} refers to an unknown {what}")]
+    BadReference { index: usize, what: &'static str },
+    #[error("Event hashes don't match the digest, the archive is corrupted")]
+    DigestMismatch,
+    #[error("Archive continues after the digest")]
+    TrailingBytes,
+    #[error(transparent)]
+    Encoding(#[from] bincode::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum RestoreError<TPeerId> {
+    #[error(transparent)]
+    Archive(#[from] ArchiveError),
+    #[error(transparent)]
+    Push(#[from] PushError<TPeerId>),
+    #[error("Delta is taken from state {expected}, the graph is at {found}")]
+    BaseMismatch {
+        expected: event::Hash,
+        found: event::Hash,
+    },
+    #[error("Delta should lead to state {expected}, the graph is at {found}")]
+    StateMismatch {
+        expected: event::Hash,
+        found: event::Hash,
+    },
+}
+
+/// State of a graph when it was archived, to take
+/// [deltas](Graph::archive_delta) from later. Small compared to the
+/// archive, so it can be kept next to it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ArchivePoint {
+    /// Latest events of each author, fork branches included. What they
+    /// cover is worked out when the delta is taken: forks that arrive later
+    /// may fall below the sequence numbers covered by the tips.
+    pub tips: Vec<event::Hash>,
+    pub state_hash: event::Hash,
+}
+
+/// Encode `events`, which must be in topological order (parents first), as
+/// in [`Jobs`](super::sync::Jobs)
+pub fn encode_events<TPayload, TGenesisPayload, TPeerId>(
+    events: &[SignedEvent<TPayload, TGenesisPayload, TPeerId>],
+) -> Result<Vec<u8>, ArchiveError>
+where
+    TPayload: PayloadCodec,
+    TGenesisPayload: Serialize,
+    TPeerId: Serialize + Eq + std::hash::Hash,
+{
+    let mut members: HashMap<&TPeerId, usize> = HashMap::new();
+    let mut member_table = vec![];
+    let mut payloads: HashMap<Vec<u8>, usize> = HashMap::new();
+    let mut payload_table = vec![];
+    // Position of each event in its author's sequence
+    let mut sequence: HashMap<&event::Hash, (usize, u128)> = HashMap::new();
+    let mut sequence_lengths: Vec<u128> = vec![];
+    let mut previous_timestamps: Vec<Timestamp> = vec![];
+    let mut body = vec![];
+    let mut digest = Blake2b512::new();
+
+    write_varint(&mut body, events.len() as u128);
+    for event in events {
+        let fields = event.unsigned().fields();
+        let author = *members.entry(fields.author()).or_insert_with(|| {
+            member_table.push(fields.author());
+            sequence_lengths.push(0);
+            previous_timestamps.push(0);
+            member_table.len() - 1
+        });
+        write_varint(&mut body, author as u128);
+        match fields.kind() {
+            event::Kind::Genesis(payload) => {
+                body.push(0);
+                write_bytes(&mut body, &bincode::serialize(payload)?);
+            }
+            event::Kind::Regular(parents) => {
+                body.push(1);
+                for parent in [&parents.self_parent, &parents.other_parent] {
+                    match sequence.get(parent) {
+                        Some((creator, seq)) => {
+                            body.push(0);
+                            write_varint(&mut body, *creator as u128);
+                            write_varint(&mut body, *seq);
+                        }
+                        None => {
+                            body.push(1);
+                            body.extend_from_slice(parent.as_ref());
+                        }
+                    }
+                }
+            }
+        }
+        let delta = fields.timestamp().wrapping_sub(previous_timestamps[author]) as i128;
+        write_varint(&mut body, zigzag(delta));
+        previous_timestamps[author] = *fields.timestamp();
+        let encoded = fields
+            .user_payload()
+            .ok_or(codec::PayloadCodecError::Dropped)
+            .and_then(codec::encode_payload)
+            .map_err(bincode::Error::from)?;
+        let next_index = payloads.len();
+        let payload = *payloads.entry(encoded).or_insert_with_key(|encoded| {
+            payload_table.push(encoded.clone());
+            next_index
+        });
+        write_varint(&mut body, payload as u128);
+        body.extend_from_slice(event.signature().0.as_ref());
+
+        sequence.insert(event.hash(), (author, sequence_lengths[author]));
+        sequence_lengths[author] += 1;
+        digest.update(event.hash().as_ref());
+    }
+
+    let mut bytes = ARCHIVE_VERSION.to_le_bytes().to_vec();
+    write_varint(&mut bytes, member_table.len() as u128);
+    for member in member_table {
+        write_bytes(&mut bytes, &bincode::serialize(member)?);
+    }
+    write_varint(&mut bytes, payload_table.len() as u128);
+    for payload in payload_table {
+        write_bytes(&mut bytes, &payload);
+    }
+    bytes.extend(body);
+    bytes.extend_from_slice(&digest.finalize());
+    Ok(bytes)
+}
+
+/// Decode events written by [`encode_events`]. Hashes are recomputed and
+/// checked against the digest, signatures are not checked.
+pub fn decode_events<TPayload, TGenesisPayload, TPeerId>(
+    bytes: &[u8],
+) -> Result<Vec<SignedEvent<TPayload, TGenesisPayload, TPeerId>>, ArchiveError>
+where
+    TPayload: PayloadCodec,
+    TGenesisPayload: Serialize + DeserializeOwned,
+    TPeerId: Serialize + DeserializeOwned + Clone,
+{
+    let mut reader = Reader { bytes, position: 0 };
+    let version = u16::from_le_bytes(reader.take(2)?.try_into().unwrap());
+    if version != ARCHIVE_VERSION {
+        return Err(ArchiveError::UnsupportedVersion(version));
+    }
+    let members: Vec<TPeerId> = (0..reader.length()?)
+        .map(|_| Ok(bincode::deserialize(reader.prefixed()?)?))
+        .collect::<Result<_, ArchiveError>>()?;
+    let payloads: Vec<&[u8]> = (0..reader.length()?)
+        .map(|_| reader.prefixed())
+        .collect::<Result<_, _>>()?;
+    let mut sequences: Vec<Vec<event::Hash>> = vec![vec![]; members.len()];
+    let mut previous_timestamps: Vec<Timestamp> = vec![0; members.len()];
+    let mut digest = Blake2b512::new();
+
+    let count = reader.length()?;
+    let mut events = Vec::with_capacity(count.min(bytes.len()));
+    for index in 0..count {
+        let bad_reference = |what| ArchiveError::BadReference { index, what };
+        let author = reader.length()?;
+        let author_id = members.get(author).ok_or(bad_reference("member"))?;
+        let kind = match reader.byte()? {
+            0 => event::Kind::Genesis(bincode::deserialize(reader.prefixed()?)?),
+            1 => {
+                let mut parent = || -> Result<event::Hash, ArchiveError> {
+                    match reader.byte()? {
+                        0 => {
+                            let creator = reader.length()?;
+                            let seq = reader.length()?;
+                            sequences
+                                .get(creator)
+                                .and_then(|s| s.get(seq))
+                                .cloned()
+                                .ok_or(bad_reference("parent"))
+                        }
+                        1 => Ok(event::Hash::from_array(
+                            reader.take(64)?.try_into().unwrap(),
+                        )),
+                        _ => Err(bad_reference("parent kind")),
+                    }
+                };
+                let self_parent = parent()?;
+                let other_parent = parent()?;
+                event::Kind::Regular(event::Parents {
+                    self_parent,
+                    other_parent,
+                })
+            }
+            _ => return Err(bad_reference("event kind")),
+        };
+        let delta = unzigzag(reader.varint()?);
+        let timestamp = previous_timestamps[author].wrapping_add(delta as u128);
+        previous_timestamps[author] = timestamp;
+        let payload = payloads
+            .get(reader.length()?)
+            .ok_or(bad_reference("payload"))?;
+        let payload = codec::decode_payload(payload).map_err(bincode::Error::from)?;
+        let signature = Signature(event::Hash::from_array(
+            reader.take(64)?.try_into().unwrap(),
+        ));
+        let event = SignedEvent::new(payload, kind, author_id.clone(), timestamp, |_| signature)?;
+        digest.update(event.hash().as_ref());
+        sequences[author].push(event.hash().clone());
+        events.push(event);
+    }
+    if reader.take(64)? != &digest.finalize()[..] {
+        return Err(ArchiveError::DigestMismatch);
+    }
+    if reader.position != bytes.len() {
+        return Err(ArchiveError::TrailingBytes);
+    }
+    Ok(events)
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPayload: PayloadCodec + Eq + std::hash::Hash + Debug + Clone,
+    TGenesisPayload: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TPeerId: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+    TClock: Clock,
+{
+    /// All events of the graph in the [archive format](self). Redacted
+    /// events and their descendants are left out, as in sync responses.
+    pub fn archive(&self) -> Result<Vec<u8>, ArchiveError> {
+        let jobs = self
+            .jobs_for(&sync::Knowledge::new())
+            .expect("tips of the graph are known");
+        encode_events(jobs.as_linear())
+    }
+
+    /// Push the events of an archive, as with
+    /// [`apply_sync_jobs`](Self::apply_sync_jobs). Returns the number of new
+    /// events.
+    pub fn restore_archive(&mut self, bytes: &[u8]) -> Result<usize, RestoreError<TPeerId>> {
+        let events = decode_events(bytes)?;
+        Ok(self.apply_sync_jobs(super::sync::Jobs::from_linear(events))?)
+    }
+
+    /// Position to take [deltas](Self::archive_delta) from, e.g. right
+    /// after [`archive`](Self::archive)
+    pub fn archive_point(&self) -> ArchivePoint {
+        let mut tips: Vec<_> = self
+            .peer_index
+            .values()
+            .flat_map(|index| index.latest_events().iter().cloned())
+            .collect();
+        tips.sort();
+        ArchivePoint {
+            tips,
+            state_hash: self.state_hash(),
+        }
+    }
+
+    /// Events unknown at `since` in the [delta format](self), e.g. to store
+    /// an incremental backup on top of an earlier archive
+    pub fn archive_delta(&self, since: &ArchivePoint) -> Result<Vec<u8>, ArchiveError> {
+        let jobs = self
+            .jobs_for(&self.knowledge_of(since.tips.iter()))
+            .expect("tips of the graph are known");
+        let mut bytes = ARCHIVE_VERSION.to_le_bytes().to_vec();
+        bytes.extend_from_slice(since.state_hash.as_ref());
+        bytes.extend_from_slice(self.state_hash().as_ref());
+        bytes.extend(encode_events(jobs.as_linear())?);
+        Ok(bytes)
+    }
+
+    /// Push the events of a delta made by
+    /// [`archive_delta`](Self::archive_delta). The graph must be at the
+    /// state the delta was taken from, e.g. restored from the archive the
+    /// delta follows. Returns the number of new events.
+    ///
+    /// The events stay in the graph if they lead to another state than
+    /// recorded in the delta.
+    pub fn apply_archive_delta(&mut self, bytes: &[u8]) -> Result<usize, RestoreError<TPeerId>> {
+        let mut reader = Reader { bytes, position: 0 };
+        let version = u16::from_le_bytes(reader.take(2)?.try_into().unwrap());
+        if version != ARCHIVE_VERSION {
+            return Err(ArchiveError::UnsupportedVersion(version).into());
+        }
+        let base = event::Hash::from_array(reader.take(64)?.try_into().unwrap());
+        let expected = event::Hash::from_array(reader.take(64)?.try_into().unwrap());
+        if self.state_hash() != base {
+            return Err(RestoreError::BaseMismatch {
+                expected: base,
+                found: self.state_hash(),
+            });
+        }
+        let events = decode_events(&bytes[reader.position..])?;
+        let inserted = self.apply_sync_jobs(super::sync::Jobs::from_linear(events))?;
+        let found = self.state_hash();
+        if found != expected {
+            return Err(RestoreError::StateMismatch { expected, found });
+        }
+        Ok(inserted)
+    }
+}
+
+fn zigzag(value: i128) -> u128 {
+    ((value << 1) ^ (value >> 127)) as u128
+}
+
+fn unzigzag(value: u128) -> i128 {
+    (value >> 1) as i128 ^ -((value & 1) as i128)
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u128) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn write_bytes(bytes: &mut Vec<u8>, data: &[u8]) {
+    write_varint(bytes, data.len() as u128);
+    bytes.extend_from_slice(data);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ArchiveError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(ArchiveError::Truncated)?;
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, ArchiveError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u128, ArchiveError> {
+        let mut value = 0u128;
+        for shift in (0..128).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u128) << shift;
+            if byte < 0x80 {
+                return Ok(value);
+            }
+        }
+        Err(ArchiveError::Encoding(Box::new(
+            bincode::ErrorKind::Custom("varint is too long".to_owned()),
+        )))
+    }
+
+    /// Varint that must fit into `usize`, e.g. a length or an index
+    fn length(&mut self) -> Result<usize, ArchiveError> {
+        usize::try_from(self.varint()?).map_err(|_| ArchiveError::Truncated)
+    }
+
+    fn prefixed(&mut self) -> Result<&'a [u8], ArchiveError> {
+        let len = self.length()?;
+        self.take(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::datastructure::sync::Jobs;
+    use crate::testing::{fixture, GraphBuilder, TestGraph};
+
+    #[test]
+    fn roundtrip_and_size() {
+        let source = fixture::random_gossip().build().unwrap().graph;
+        let jobs = source.generate_sync_for(&u64::MAX).unwrap();
+        let bytes = source.archive().unwrap();
+        let naive = bincode::serialize(&jobs).unwrap();
+        assert!(
+            naive.len() >= 3 * bytes.len(),
+            "{} bytes vs {} bytes of bincode",
+            bytes.len(),
+            naive.len()
+        );
+        let decoded: Vec<SignedEvent<(), (), u64>> = decode_events(&bytes).unwrap();
+        assert_eq!(Jobs::from_linear(decoded), jobs);
+
+        let example = fixture::random_gossip();
+        let mut restored = GraphBuilder::new(&example.peers[0], 0u64, (), example.coin_frequency)
+            .build()
+            .unwrap()
+            .graph;
+        assert_eq!(
+            restored.restore_archive(&bytes).unwrap(),
+            jobs.as_linear().len() - 1
+        );
+        assert!(restored.ordering.ordered().eq(source.ordering.ordered()));
+    }
+
+    #[test]
+    fn corruption_detected() {
+        let source = fixture::detailed_example().build().unwrap().graph;
+        let bytes = source.archive().unwrap();
+        let decode = |bytes: &[u8]| decode_events::<(), (), u64>(bytes).map(|_| ());
+
+        let mut wrong_signature = bytes.clone();
+        let last_signature = wrong_signature.len() - 65;
+        wrong_signature[last_signature] ^= 1;
+        // Signatures are checked when pushed
+        assert!(decode(&wrong_signature).is_ok());
+        let mut graph = GraphBuilder::new("a", 0u64, (), 999).build().unwrap().graph;
+        assert!(matches!(
+            graph.restore_archive(&wrong_signature),
+            Err(RestoreError::Push(PushError::InvalidSignature { .. }))
+        ));
+
+        let mut wrong_digest = bytes.clone();
+        *wrong_digest.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            decode(&wrong_digest),
+            Err(ArchiveError::DigestMismatch)
+        ));
+        assert!(matches!(
+            decode(&bytes[..bytes.len() - 1]),
+            Err(ArchiveError::Truncated)
+        ));
+        let mut newer = bytes;
+        newer[0] = 2;
+        assert!(matches!(
+            decode(&newer),
+            Err(ArchiveError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn delta_on_top_of_archive() {
+        let example = fixture::random_gossip();
+        let reference = example.build().unwrap();
+        let empty = || example.build_geneses().unwrap().graph;
+        let mut source = empty();
+        let (before, after) = example.events.split_at(example.events.len() / 2);
+        fixture::replay(&reference, before, &mut source, |_, _| {});
+        let archive = source.archive().unwrap();
+        let point = source.archive_point();
+        fixture::replay(&reference, after, &mut source, |_, _| {});
+        let delta = source.archive_delta(&point).unwrap();
+        assert!(delta.len() < source.archive().unwrap().len());
+
+        let mut restored = empty();
+        restored.restore_archive(&archive).unwrap();
+        assert!(matches!(
+            empty().apply_archive_delta(&delta),
+            Err(RestoreError::BaseMismatch { .. })
+        ));
+        assert_eq!(restored.apply_archive_delta(&delta).unwrap(), after.len());
+        assert_eq!(restored.state_hash(), source.state_hash());
+        // Nothing new since the latest point
+        let empty_delta = source.archive_delta(&source.archive_point()).unwrap();
+        assert_eq!(restored.apply_archive_delta(&empty_delta).unwrap(), 0);
+    }
+
+    #[test]
+    fn delta_with_late_fork() {
+        let two_peers = || GraphBuilder::new("a", 0u64, (), 999).peer("b", 1);
+        // b2_fork shares its sequence number with b2, which the point covers
+        let reference = two_peers()
+            .event("b1", "b", "a")
+            .event("a1", "a", "b1")
+            .event("b2", "b", "a1")
+            .fork("b2_fork", "b2", "a")
+            .event("a2", "a", "b2_fork")
+            .build()
+            .unwrap();
+        let push = |graph: &mut TestGraph<(), u64>, names: &[&str]| {
+            for name in names {
+                let signed = reference.graph.event(reference.hash(name)).unwrap();
+                let (unsigned, signature) = signed.inner().clone().into_parts();
+                graph.push_event(unsigned, signature).unwrap();
+            }
+        };
+        let mut source = two_peers().build().unwrap().graph;
+        push(&mut source, &["b1", "a1", "b2"]);
+        let archive = source.archive().unwrap();
+        let point = source.archive_point();
+        push(&mut source, &["b2_fork", "a2"]);
+        let delta = source.archive_delta(&point).unwrap();
+
+        let mut restored = two_peers().build().unwrap().graph;
+        restored.restore_archive(&archive).unwrap();
+        assert_eq!(restored.apply_archive_delta(&delta).unwrap(), 2);
+        assert_eq!(restored.state_hash(), source.state_hash());
+        assert!(restored.event(reference.hash("b2_fork")).is_some());
+    }
+
+    #[test]
+    fn varints() {
+        for value in [0, 1, 127, 128, 300, u64::MAX as u128, u128::MAX] {
+            let mut bytes = vec![];
+            write_varint(&mut bytes, value);
+            let mut reader = Reader {
+                bytes: &bytes,
+                position: 0,
+            };
+            assert_eq!(reader.varint().unwrap(), value);
+        }
+        for value in [0, -1, 1, i128::MIN, i128::MAX] {
+            assert_eq!(unzigzag(zigzag(value)), value);
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! Finalized events in batches of a round, for external databases.
+//!
+//! Events with the same round received are finalized together, so a round
+//! is a natural unit for writing into a database or a message queue.
+//! [`Graph::emit_round_batches`] hands complete rounds to a [`Sink`] as
+//! [`RoundBatch`]es, in the consensus order.
+//!
+//! Delivery is at least once: the [`RoundBatcher`] moves past a batch only
+//! after the sink accepted it, and a batch the sink failed on is emitted
+//! again by the next call. If the batcher is saved (it is serializable)
+//! after the sink wrote to an external system, a crash in between emits the
+//! batch again after a restart as well. Sinks deduplicate by
+//! [`BatchId::idempotency_key`], which is the same on all replicas, e.g.
+//! as a primary key or a message key.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::app::OrderedTransaction;
+use super::Graph;
+use crate::algorithm::{event, RoundNum};
+use crate::light;
+
+/// Identifies a batch on all replicas
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BatchId {
+    /// Round received of the events
+    pub round: RoundNum,
+    /// Root of the events' hashes, as in a [`light::Checkpoint`]
+    pub root: event::Hash,
+}
+
+impl BatchId {
+    /// Round and root in hex, for deduplicating writes
+    pub fn idempotency_key(&self) -> String {
+        let root: String = self
+            .root
+            .as_ref()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        format!("{}-{}", self.round, root)
+    }
+}
+
+/// Finalized events of one round received, in the consensus order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoundBatch<'a, TPayload, TPeerId> {
+    pub id: BatchId,
+    pub transactions: Vec<OrderedTransaction<'a, TPayload, TPeerId>>,
+}
+
+impl<TPayload, TPeerId> RoundBatch<'_, TPayload, TPeerId> {
+    /// Position of the first event among all finalized events
+    pub fn first_position(&self) -> usize {
+        self.transactions[0].position
+    }
+}
+
+/// Destination of [`RoundBatch`]es, e.g. a database table or a topic
+pub trait Sink<TPayload, TPeerId> {
+    type Error;
+
+    /// May be called again with a batch written before, see the [module
+    /// docs](self)
+    fn write(&mut self, batch: &RoundBatch<'_, TPayload, TPeerId>) -> Result<(), Self::Error>;
+}
+
+#[derive(Error, Debug)]
+pub enum BatchError<E> {
+    #[error("Sink failed on the batch of round {}: {error}", batch.round)]
+    Sink { batch: BatchId, error: E },
+    #[error("Event #{0} was pruned before it was emitted")]
+    Pruned(usize),
+}
+
+/// Progress of emitting batches
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoundBatcher {
+    /// Finalized events in the accepted batches
+    pub emitted: usize,
+    pub last_batch: Option<BatchId>,
+}
+
+impl RoundBatcher {
+    /// Nothing emitted yet
+    pub fn start() -> Self {
+        Self::default()
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash,
+{
+    /// Write the rounds finalized since the batcher's last accepted batch
+    /// to the sink. Returns the number of accepted batches. Stops at the
+    /// first failure of the sink, which is retried by the next call.
+    ///
+    /// Events must be emitted before they are [pruned](Self::prune).
+    pub fn emit_round_batches<S>(
+        &self,
+        batcher: &mut RoundBatcher,
+        sink: &mut S,
+    ) -> Result<usize, BatchError<S::Error>>
+    where
+        S: Sink<TPayload, TPeerId>,
+    {
+        let mut batches = 0;
+        let mut events = self.ordering.ordered().skip(batcher.emitted).peekable();
+        while let Some(first) = events.peek() {
+            let (round, _, _) = self
+                .ordering_data(first)
+                .map_err(|_| BatchError::Pruned(batcher.emitted))?;
+            let mut transactions = vec![];
+            let mut hashes = vec![];
+            // Events of a round received are next to each other
+            while let Some(hash) = events.next_if(|hash| {
+                self.ordering_data(hash)
+                    .is_ok_and(|(received, _, _)| received == round)
+            }) {
+                let position = batcher.emitted + transactions.len();
+                let event = self
+                    .all_events
+                    .get(hash)
+                    .ok_or(BatchError::Pruned(position))?;
+                transactions.push(OrderedTransaction {
+                    position,
+                    event: hash,
+                    author: event.author(),
+                    payload: event.payload().ok_or(BatchError::Pruned(position))?,
+                });
+                hashes.push(hash);
+            }
+            let batch = RoundBatch {
+                id: BatchId {
+                    round,
+                    root: light::events_root(hashes),
+                },
+                transactions,
+            };
+            sink.write(&batch).map_err(|error| BatchError::Sink {
+                batch: batch.id.clone(),
+                error,
+            })?;
+            batcher.emitted += batch.transactions.len();
+            batcher.last_batch = Some(batch.id);
+            batches += 1;
+        }
+        Ok(batches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::testing::fixture;
+
+    /// Table keyed by the idempotency key
+    #[derive(Default)]
+    struct Table {
+        rows: HashMap<String, Vec<usize>>,
+        writes: usize,
+        fail_after: Option<usize>,
+    }
+
+    impl<TPayload, TPeerId> Sink<TPayload, TPeerId> for Table {
+        type Error = &'static str;
+
+        fn write(&mut self, batch: &RoundBatch<'_, TPayload, TPeerId>) -> Result<(), &'static str> {
+            if self.fail_after.is_some_and(|limit| self.writes >= limit) {
+                return Err("connection lost");
+            }
+            self.writes += 1;
+            let positions = batch.transactions.iter().map(|t| t.position).collect();
+            self.rows.insert(batch.id.idempotency_key(), positions);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn rounds_written_at_least_once() {
+        let graph = fixture::random_gossip().build().unwrap().graph;
+        let finalized = graph.ordering.len();
+        let mut table = Table {
+            fail_after: Some(2),
+            ..Default::default()
+        };
+        let mut batcher = RoundBatcher::start();
+        assert!(matches!(
+            graph.emit_round_batches(&mut batcher, &mut table),
+            Err(BatchError::Sink {
+                error: "connection lost",
+                ..
+            })
+        ));
+        let emitted = batcher.emitted;
+        assert_eq!(table.rows.values().map(Vec::len).sum::<usize>(), emitted);
+
+        // Restart from a batcher saved one batch too early
+        table.fail_after = None;
+        let second = table.rows.values().find(|p| p[0] > 0).unwrap()[0];
+        let mut restarted = RoundBatcher {
+            emitted: second,
+            last_batch: None,
+        };
+        let batches = graph
+            .emit_round_batches(&mut restarted, &mut table)
+            .unwrap();
+        assert_eq!(restarted.emitted, finalized);
+        assert_eq!(table.writes, 2 + batches);
+        // The repeated batch is the same row
+        let mut positions: Vec<_> = table.rows.values().flatten().copied().collect();
+        positions.sort();
+        assert_eq!(positions, (0..finalized).collect::<Vec<_>>());
+        assert_eq!(table.rows.len(), batches + 1);
+
+        // Same ids on another replica, one batch per round received
+        let mut other = Table::default();
+        let mut from_start = RoundBatcher::start();
+        let again = fixture::random_gossip().build().unwrap().graph;
+        again
+            .emit_round_batches(&mut from_start, &mut other)
+            .unwrap();
+        assert_eq!(other.rows, table.rows);
+        assert_eq!(from_start, restarted);
+        let last = graph.ordering.ordered().last().unwrap();
+        let (round, _, _) = graph.ordering_data(last).unwrap();
+        assert_eq!(from_start.last_batch.as_ref().unwrap().round, round);
+        assert_eq!(
+            graph
+                .emit_round_batches(&mut from_start, &mut other)
+                .unwrap(),
+            0
+        );
+    }
+}
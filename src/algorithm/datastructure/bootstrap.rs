@@ -0,0 +1,362 @@
+//! Joining a running network from a certified checkpoint (see
+//! [`crate::light`]).
+//!
+//! [`Graph::bootstrap`] checks the certificate of the checkpoint against the
+//! members known to the graph, then syncs with the source until the events
+//! of the checkpoint are finalized and verifies that they match it. After
+//! that it catches up with the rest (the tail after the checkpoint), until
+//! the source has nothing new.
+//!
+//! Rounds and fame are computed from the ancestry of events, so the history
+//! before the checkpoint is fetched as well. The checkpoint is what makes
+//! it trustworthy: a source can't feed a node a different past without
+//! forging signatures of a supermajority.
+//!
+//! A member that was offline for a long time catches up the same way with
+//! [`Graph::fast_forward`]. Rounds still come from the ancestry, so the
+//! missed events are fetched, but they are checked against the checkpoint
+//! in one pass instead of being gossiped round by round. Own events made
+//! while offline that nobody built on are reported, and, if the own latest
+//! event is rounds behind, a new own event is made on top of the frontier,
+//! so that the offline events are received by the next rounds instead of
+//! waiting for others to stumble upon them.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use serde::Serialize;
+use thiserror::Error;
+
+use super::sync::{Jobs, SyncRequest};
+use super::{EventCreateError, Graph};
+use crate::algorithm::codec::PayloadCodec;
+use crate::algorithm::event;
+use crate::algorithm::{Clock, PushError, Signer};
+use crate::light::{self, LightError, QuorumCertificate};
+
+/// Peer (or anything else) answering sync requests of a bootstrapping
+/// graph
+pub trait SyncSource<TPayload, TGenesisPayload, TPeerId> {
+    type Error;
+
+    /// Events missing in the summary of the request, nothing if the source
+    /// knows no more
+    fn sync(
+        &mut self,
+        request: SyncRequest<TPayload, TGenesisPayload, TPeerId>,
+    ) -> Result<Jobs<TPayload, TGenesisPayload, TPeerId>, Self::Error>;
+}
+
+/// Another graph in the same process
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    SyncSource<TPayload, TGenesisPayload, TPeerId>
+    for Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPayload: PayloadCodec + Eq + std::hash::Hash + Debug + Clone,
+    TGenesisPayload: Serialize + Eq + std::hash::Hash + Debug + Clone,
+    TPeerId: Serialize + Eq + std::hash::Hash + Debug + Clone,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+    TClock: Clock,
+{
+    type Error = super::sync::Error;
+
+    fn sync(
+        &mut self,
+        request: SyncRequest<TPayload, TGenesisPayload, TPeerId>,
+    ) -> Result<Jobs<TPayload, TGenesisPayload, TPeerId>, Self::Error> {
+        self.generate_sync_for_request(&request)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum BootstrapError<TPeerId, TSourceError> {
+    #[error("Certificate of the checkpoint is invalid: {0}")]
+    Certificate(#[from] LightError<TPeerId>),
+    #[error("Sync source failed: {0}")]
+    Source(TSourceError),
+    #[error("Event from the sync source was rejected: {0}")]
+    Push(#[from] PushError<TPeerId>),
+    #[error("Sync source ran out of events, {finalized} of {expected} events of the checkpoint are finalized")]
+    Incomplete { finalized: usize, expected: usize },
+    #[error("Finalized events differ from the checkpoint")]
+    Mismatch,
+    #[error("Checkpoint of {checkpoint} events is not ahead of {finalized} finalized ones")]
+    NotAhead { finalized: usize, checkpoint: usize },
+    #[error("Could not make an own event on top of the frontier: {0}")]
+    Graft(#[from] EventCreateError<TPeerId>),
+}
+
+/// See [`Graph::fast_forward`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FastForward {
+    /// Events received from the source
+    pub imported: usize,
+    /// Own events no other member has built on, the latest first
+    pub stale: Vec<event::Hash>,
+    /// Own event made on top of the frontier, if the own latest event was
+    /// behind it
+    pub grafted: Option<event::Hash>,
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPayload: PayloadCodec + Eq + std::hash::Hash + Debug + Clone,
+    TGenesisPayload: Serialize + Eq + std::hash::Hash + Debug + Clone,
+    TPeerId: Serialize + Eq + std::hash::Hash + Debug + Clone,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+    TClock: Clock,
+{
+    /// Catch up with the network through `sync_source`, trusting only what
+    /// agrees with `checkpoint`. See the [module docs](self).
+    ///
+    /// Members are the authors of geneses the graph knows, so they have to
+    /// be added before (e.g. from configuration). Returns the number of
+    /// imported events. Finalized events are reported by
+    /// [`next_finalized_event`](Self::next_finalized_event) as usual.
+    pub fn bootstrap<S>(
+        &mut self,
+        checkpoint: &QuorumCertificate<TPeerId>,
+        sync_source: &mut S,
+    ) -> Result<usize, BootstrapError<TPeerId, S::Error>>
+    where
+        S: SyncSource<TPayload, TGenesisPayload, TPeerId>,
+    {
+        checkpoint.verify(&self.members(), &self.signer)?;
+        let expected = checkpoint.checkpoint.events;
+        let mut imported = 0;
+        let mut verified = false;
+        loop {
+            let jobs = sync_source
+                .sync(self.sync_request())
+                .map_err(BootstrapError::Source)?;
+            let new = self.apply_sync_jobs(jobs)?;
+            imported += new;
+            let finalized = self.ordering.ordered().count();
+            if !verified && finalized >= expected {
+                let root = light::events_root(self.ordering.ordered().take(expected));
+                if root != checkpoint.checkpoint.events_root {
+                    return Err(BootstrapError::Mismatch);
+                }
+                verified = true;
+            }
+            if new == 0 {
+                return match verified {
+                    true => Ok(imported),
+                    false => Err(BootstrapError::Incomplete {
+                        finalized,
+                        expected,
+                    }),
+                };
+            }
+        }
+    }
+
+    /// Catch up after a long time offline, see the [module docs](self).
+    /// `payload` goes into the own event made on top of the frontier, if
+    /// one is needed.
+    ///
+    /// Fails with [`NotAhead`](BootstrapError::NotAhead) if the graph has
+    /// already finalized everything the checkpoint covers. Observers have no
+    /// own events to graft, they catch up with [`bootstrap`](Self::bootstrap).
+    pub fn fast_forward<S>(
+        &mut self,
+        checkpoint: &QuorumCertificate<TPeerId>,
+        sync_source: &mut S,
+        payload: TPayload,
+    ) -> Result<FastForward, BootstrapError<TPeerId, S::Error>>
+    where
+        S: SyncSource<TPayload, TGenesisPayload, TPeerId>,
+    {
+        self.check_authoring()?;
+        let finalized = self.ordering.len();
+        if checkpoint.checkpoint.events <= finalized {
+            return Err(BootstrapError::NotAhead {
+                finalized,
+                checkpoint: checkpoint.checkpoint.events,
+            });
+        }
+        let imported = self.bootstrap(checkpoint, sync_source)?;
+        let stale = self.stale_own_events();
+        let own_round = self.self_tip().map_or(0, |tip| self.round_of(tip));
+        let frontier = self
+            .peer_index
+            .iter()
+            .filter(|(peer, _)| Some(*peer) != self.self_id.as_ref())
+            .flat_map(|(_, entry)| entry.latest_events())
+            .max_by(|a, b| (self.round_of(a), b).cmp(&(self.round_of(b), a)))
+            .cloned();
+        let grafted = match frontier {
+            Some(tip) if own_round + 1 < self.round_of(&tip) => {
+                Some(self.create_event(payload, tip)?)
+            }
+            _ => None,
+        };
+        Ok(FastForward {
+            imported,
+            stale,
+            grafted,
+        })
+    }
+
+    /// Latest own events that are not ancestors of other members' latest
+    /// events. Geneses are known from the configuration, so never stale.
+    fn stale_own_events(&self) -> Vec<event::Hash> {
+        let others: Vec<_> = self
+            .peer_index
+            .iter()
+            .filter(|(peer, _)| Some(*peer) != self.self_id.as_ref())
+            .flat_map(|(_, entry)| entry.latest_events())
+            .collect();
+        let mut stale = vec![];
+        let mut current = self.self_tip().cloned();
+        while let Some(hash) = current {
+            let Some(parents) = self.headers.get(&hash).and_then(|h| h.parents.as_ref()) else {
+                break;
+            };
+            // Pruned tips of silent members are too old to build on own events
+            if others
+                .iter()
+                .any(|tip| matches!(self.is_ancestor(tip, &hash), Ok(true)))
+            {
+                break;
+            }
+            current = Some(parents.self_parent.clone());
+            stale.push(hash);
+        }
+        stale
+    }
+
+    /// Known members with their genesis payloads
+    fn members(&self) -> HashMap<TPeerId, TGenesisPayload> {
+        self.peer_index
+            .iter()
+            .filter_map(
+                |(peer, entry)| match self.all_events.get(entry.origin())?.kind() {
+                    event::Kind::Genesis(payload) => Some((peer.clone(), payload.clone())),
+                    event::Kind::Regular(_) => None,
+                },
+            )
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::MockSigner;
+    use crate::light::{Checkpoint, Ledger};
+    use crate::testing::fixture::{self, Fixture};
+    use crate::testing::TestGraph;
+
+    /// Source with the whole example, a graph knowing only the geneses and
+    /// a checkpoint of the first half of what the source has finalized
+    fn setup() -> (TestGraph<(), u64>, TestGraph<(), u64>, Checkpoint) {
+        let example = fixture::detailed_example();
+        let source = example.build().unwrap().graph;
+        let graph = example.build_geneses().unwrap().graph;
+        // Events after the checkpoint are caught up with as well
+        let finalized = source.ordering.ordered().count();
+        assert!(finalized > 1);
+        let mut ledger = Ledger::new();
+        for event in source.ordering.ordered().take(finalized / 2) {
+            ledger.push::<()>(event, []).unwrap();
+        }
+        let checkpoint = ledger.checkpoint();
+        (source, graph, checkpoint)
+    }
+
+    fn certify(checkpoint: Checkpoint, signers: &[u64]) -> QuorumCertificate<u64> {
+        let signer = MockSigner::<u64, ()>::new();
+        QuorumCertificate {
+            signatures: signers
+                .iter()
+                .map(|&m| (m, checkpoint.sign::<(), _>(&signer)))
+                .collect(),
+            checkpoint,
+        }
+    }
+
+    #[test]
+    fn bootstrap_from_checkpoint() {
+        let (mut source, mut graph, checkpoint) = setup();
+        let members: Vec<_> = (0..graph.peers().len() as u64).collect();
+        let imported = graph
+            .bootstrap(&certify(checkpoint, &members), &mut source)
+            .unwrap();
+        assert_eq!(imported, source.all_events.len() - members.len());
+        assert!(graph.ordering.ordered().eq(source.ordering.ordered()));
+    }
+
+    #[test]
+    fn fast_forward_after_offline() {
+        // A silent member goes offline early
+        let example = Fixture {
+            peers: [&["offline".to_owned()], &fixture::random_gossip().peers[..]].concat(),
+            ..fixture::random_gossip()
+        };
+        let source_graph = example.build().unwrap().graph;
+        let mut source = source_graph.fork();
+        let mut offline = Fixture {
+            events: example.events[..4].to_vec(),
+            ..example.clone()
+        }
+        .build()
+        .unwrap();
+        let other_parent = offline.hash(&example.events[3].name).clone();
+        let graph = &mut offline.graph;
+        let made_offline = graph.create_event((), other_parent).unwrap();
+
+        let finalized = source.ordering.len();
+        assert!(finalized > 0);
+        let mut ledger = Ledger::new();
+        for event in source.ordering.ordered() {
+            ledger.push::<()>(event, []).unwrap();
+        }
+        let members: Vec<_> = (0..example.peers.len() as u64).collect();
+        let certificate = certify(ledger.checkpoint(), &members);
+        let report = graph.fast_forward(&certificate, &mut source, ()).unwrap();
+        assert_eq!(report.imported, source.all_events.len() - members.len() - 4);
+        assert_eq!(report.stale, vec![made_offline.clone()]);
+        let grafted = report.grafted.unwrap();
+        assert_eq!(graph.self_tip(), Some(&grafted));
+        assert!(graph.is_ancestor(&grafted, &made_offline).unwrap());
+        assert!(graph
+            .ordering
+            .ordered()
+            .take(finalized)
+            .eq(source_graph.ordering.ordered().take(finalized)));
+
+        assert!(matches!(
+            graph.fast_forward(&certificate, &mut source, ()),
+            Err(BootstrapError::NotAhead { .. })
+        ));
+    }
+
+    #[test]
+    fn bootstrap_rejects_bad_checkpoints() {
+        let (mut source, mut graph, checkpoint) = setup();
+        let members = graph.peers().len() as u64;
+        assert!(matches!(
+            graph.bootstrap(&certify(checkpoint.clone(), &[0]), &mut source),
+            Err(BootstrapError::Certificate(LightError::NoQuorum { .. }))
+        ));
+        let other_history = Checkpoint {
+            events_root: event::Hash::from_array([1; 64]),
+            ..checkpoint.clone()
+        };
+        let all: Vec<_> = (0..members).collect();
+        assert!(matches!(
+            graph.bootstrap(&certify(other_history, &all), &mut source),
+            Err(BootstrapError::Mismatch)
+        ));
+        let future = Checkpoint {
+            events: checkpoint.events + 100,
+            ..checkpoint
+        };
+        assert!(matches!(
+            graph.bootstrap(&certify(future, &all), &mut source),
+            Err(BootstrapError::Incomplete { .. })
+        ));
+    }
+}
@@ -0,0 +1,198 @@
+//! Reclaiming memory of finalized rounds without forgetting their skeleton.
+//!
+//! [`Graph::prune`] forgets old events completely and needs epochs.
+//! [`Graph::compact`] works on the rounds whose events were all returned by
+//! [`next_finalized_event`](Graph::next_finalized_event): it drops their
+//! events except the witnesses, and drops the payloads of the witnesses.
+//! The witnesses keep their hashes, signatures, rounds and fame, so the
+//! famous witnesses of old rounds (what [epoch summaries](super::epoch) and
+//! checkpoints of the consensus order are signed by) stay known. The
+//! consensus order itself is kept as a list of hashes, like after pruning.
+//!
+//! Compacted witnesses don't match their hashes anymore, so they are not
+//! sent in sync responses, together with their descendants the peer doesn't
+//! know. Peers lagging behind the compacted rounds can't be synced from
+//! this graph.
+
+use std::hash::Hash;
+
+use super::Graph;
+use crate::algorithm::{event, RoundNum};
+
+/// What [`Graph::compact`] did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Compaction {
+    /// Non-witness events forgotten
+    pub dropped: usize,
+    /// Witnesses whose payloads were dropped
+    pub stripped: usize,
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + Hash + Clone,
+{
+    /// Compact the rounds before the earliest round with an event not yet
+    /// returned by [`next_finalized_event`](Self::next_finalized_event),
+    /// and before the latest decided round. Geneses and the latest events
+    /// of each member are kept with their payloads, and are compacted by a
+    /// later call once they aren't the latest anymore.
+    ///
+    /// Only the rounds after the previous call are looked at.
+    pub fn compact(&mut self) -> Compaction {
+        let Some(decided) = self.last_known_decided_round else {
+            return Compaction::default();
+        };
+        let delivered = |hash: &event::Hash| self.headers.get(hash).is_some_and(|h| h.delivered);
+        let mut bound = self.compacted_below;
+        while bound < decided
+            && self
+                .round_index
+                .get(bound)
+                .is_some_and(|events| events.iter().all(delivered))
+        {
+            bound += 1;
+        }
+        // Pruned rounds list no events, their kept ones are checked here
+        let bound = self
+            .compaction_kept
+            .iter()
+            .filter(|e| !delivered(e))
+            .filter_map(|e| self.round_of.get(e).copied())
+            .fold(bound, RoundNum::min);
+        if bound <= self.compacted_below {
+            return Compaction::default();
+        }
+        let kept = self.chain_ends();
+        let candidates: Vec<_> = (self.compacted_below..bound)
+            .flat_map(|round| self.round_index.get(round).into_iter().flatten())
+            .chain(
+                self.compaction_kept
+                    .iter()
+                    .filter(|e| self.round_of.get(*e).is_some_and(|&r| r < bound)),
+            )
+            .cloned()
+            .collect();
+        let mut compaction = Compaction::default();
+        for hash in candidates {
+            self.compaction_kept.remove(&hash);
+            if kept.contains(&hash) {
+                self.compaction_kept.insert(hash);
+            } else if self.headers.get(&hash).is_some_and(|h| h.witness) {
+                if let Some(event) = self.all_events.get_mut(&hash) {
+                    event.take_payload();
+                    self.compacted.insert(hash);
+                    compaction.stripped += 1;
+                }
+            } else {
+                self.forget_event(&hash);
+                compaction.dropped += 1;
+            }
+        }
+        self.compacted_below = bound;
+        compaction
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+{
+    /// Whether the event is a witness whose payload was dropped by
+    /// [`compact`](Self::compact)
+    pub fn is_compacted(&self, event: &event::Hash) -> bool {
+        self.compacted.contains(event)
+    }
+
+    /// Rounds before this one were compacted, 0 if none were
+    pub fn compacted_below(&self) -> RoundNum {
+        self.compacted_below
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::algorithm::datastructure::WitnessFamousness;
+    use crate::testing::fixture;
+
+    #[test]
+    fn skeleton_kept() {
+        let example = fixture::random_gossip();
+        let mut reference = example.build().unwrap();
+        let expected: Vec<_> = std::iter::from_fn(|| {
+            reference
+                .graph
+                .next_finalized_event()
+                .map(|e| e.hash().clone())
+        })
+        .collect();
+        let mut graph = example.build_geneses().unwrap().graph;
+        let mut finalized = vec![];
+        let mut total = Compaction::default();
+        fixture::replay(&reference, &example.events, &mut graph, |graph, _| {
+            while let Some(e) = graph.next_finalized_event() {
+                finalized.push(e.hash().clone());
+            }
+            let compaction = graph.compact();
+            total.dropped += compaction.dropped;
+            total.stripped += compaction.stripped;
+            assert_eq!(graph.check_consistency(), Ok(()));
+        });
+        assert_eq!(finalized, expected);
+        assert!(total.dropped > 0 && total.stripped > 0, "{total:?}");
+        assert!(graph.compacted_below() > 1);
+
+        // Witnesses and their fame are kept, the rest of the rounds is gone
+        let reference = &reference.graph;
+        for round in 0..graph.compacted_below() {
+            let witnesses: HashSet<_> = reference.witnesses_between(round, round).collect();
+            assert_eq!(
+                graph
+                    .witnesses_between(round, round)
+                    .collect::<HashSet<_>>(),
+                witnesses
+            );
+            for (_, witness) in witnesses {
+                assert_eq!(
+                    graph.is_famous_witness(witness).unwrap(),
+                    reference.is_famous_witness(witness).unwrap()
+                );
+                assert_ne!(
+                    graph.is_famous_witness(witness).unwrap(),
+                    WitnessFamousness::Undecided
+                );
+            }
+            assert_eq!(
+                graph.round_unique_famous_witnesses(round).unwrap(),
+                reference.round_unique_famous_witnesses(round).unwrap()
+            );
+            let kept = graph.round_index[round].len();
+            assert!(kept <= reference.round_index[round].len());
+        }
+        let stripped = graph.compacted.iter().next().unwrap().clone();
+        assert!(graph.is_compacted(&stripped));
+        assert!(graph.all_events[&stripped].payload().is_none());
+        // Whatever was kept is the latest of its author
+        assert!(graph.compaction_kept.is_subset(&graph.chain_ends()));
+        assert!(graph.all_events.len() < reference.all_events.len());
+
+        // Peers don't know the dropped events anymore
+        for (peer, index) in &graph.peer_index {
+            let known = index.known_events().len();
+            assert!(known <= graph.all_events.len());
+            assert!(known < reference.peer_index[peer].known_events().len());
+        }
+        assert_eq!(
+            graph.peer_candidates().len(),
+            reference.peer_candidates().len()
+        );
+
+        // Compacted witnesses can't be verified by others
+        let event = graph.all_events[&stripped].inner().clone();
+        assert!(graph.withhold_redacted(vec![event]).is_empty());
+        assert_eq!(graph.compact(), Compaction::default());
+    }
+}
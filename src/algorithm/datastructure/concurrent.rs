@@ -0,0 +1,231 @@
+//! Parallel ingestion of large batches (feature `concurrent`).
+//!
+//! Pushing an event is mostly checking it: the hash, the timestamp and,
+//! above all, the signature. None of these look at the graph structure, so
+//! [`Graph::push_concurrent`] runs them on several threads and only
+//! inserts the events into the graph (parents, rounds, fame, ordering) on
+//! the calling thread, in the order given. Events of one author are checked
+//! by the same thread, so that authors whose events are independent are
+//! spread over the threads.
+//!
+//! The result is the same as with
+//! [`apply_sync_jobs`](Graph::apply_sync_jobs), including the pending pool
+//! and the recently seen filter.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::time::Instant;
+
+use serde::Serialize;
+use tracing::{field, instrument, Span};
+
+use super::validation::VerifiedEvent;
+use super::{sync, Graph};
+use crate::algorithm::codec::PayloadCodec;
+use crate::algorithm::event;
+use crate::algorithm::{metrics, Clock, PushError, Signer};
+
+type Checked<TPayload, TGenesisPayload, TPeerId> =
+    Result<VerifiedEvent<TPayload, TGenesisPayload, TPeerId>, PushError<TPeerId>>;
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPayload: PayloadCodec + Eq + Hash + Debug + Clone + Send,
+    TGenesisPayload: Serialize + Eq + Hash + Debug + Clone + Send + Sync,
+    TPeerId: Serialize + Eq + Hash + Debug + Clone + Send + Sync,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId> + Sync,
+    TClock: Clock,
+{
+    /// [`apply_sync_jobs`](Self::apply_sync_jobs) with the events checked
+    /// on `threads` threads, see the [module docs](self). The clock is read
+    /// once for the whole batch.
+    ///
+    /// Returns number of new events.
+    #[instrument(
+        level = "debug",
+        skip_all,
+        fields(jobs = jobs.as_linear().len(), threads, applied = field::Empty)
+    )]
+    pub fn push_concurrent(
+        &mut self,
+        jobs: sync::Jobs<TPayload, TGenesisPayload, TPeerId>,
+        threads: usize,
+    ) -> Result<usize, PushError<TPeerId>> {
+        let events = jobs.into_linear();
+        let threads = threads.max(1);
+        // Authors introduced by the batch. Their geneses are checked like
+        // the rest, and come before their events.
+        let batch_geneses: HashMap<TPeerId, (event::Hash, TGenesisPayload)> = events
+            .iter()
+            .filter_map(|event| {
+                let fields = event.unsigned().fields();
+                match fields.kind() {
+                    event::Kind::Genesis(payload) => Some((
+                        fields.author().clone(),
+                        (event.hash().clone(), payload.clone()),
+                    )),
+                    event::Kind::Regular(_) => None,
+                }
+            })
+            .collect();
+
+        let mut lanes: Vec<Vec<_>> = (0..threads).map(|_| vec![]).collect();
+        let mut skipped = vec![];
+        for (index, event) in events.into_iter().enumerate() {
+            let known = self.all_events.contains_key(event.hash())
+                || self
+                    .recently_seen
+                    .as_ref()
+                    .is_some_and(|seen| seen.contains(event.hash()));
+            if known {
+                skipped.push(index);
+                continue;
+            }
+            let (unsigned, signature) = event.into_parts();
+            let author_genesis = self
+                .author_genesis(unsigned.fields().author())
+                .or_else(|| batch_geneses.get(unsigned.fields().author()).cloned());
+            let lane = lane_of(unsigned.fields().author(), threads);
+            lanes[lane].push((index, unsigned, signature, author_genesis));
+        }
+
+        let max_timestamp = self.max_timestamp();
+        let validator = self.validator(max_timestamp);
+        let mut checked: Vec<(usize, Checked<TPayload, TGenesisPayload, TPeerId>)> =
+            std::thread::scope(|scope| {
+                let validator = &validator;
+                let workers: Vec<_> = lanes
+                    .into_iter()
+                    .map(|lane| {
+                        scope.spawn(move || {
+                            lane.into_iter()
+                                .map(|(index, unsigned, signature, author_genesis)| {
+                                    (
+                                        index,
+                                        validator.validate(unsigned, signature, author_genesis),
+                                    )
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                    })
+                    .collect();
+                workers
+                    .into_iter()
+                    .flat_map(|worker| worker.join().expect("validation thread panicked"))
+                    .collect()
+            });
+        checked.sort_unstable_by_key(|(index, _)| *index);
+        for _ in skipped {
+            metrics::sync_event_received(metrics::SyncEventOutcome::RecentDuplicate);
+        }
+
+        let mut applied = 0;
+        for (_, result) in checked {
+            let started = Instant::now();
+            let result = result.and_then(|validated| {
+                let hash = validated.event.hash().clone();
+                // Duplicates within the batch
+                if self.all_events.contains_key(&hash) {
+                    return Err(PushError::EventAlreadyExists(hash));
+                }
+                self.commit_event(validated).map(|()| hash)
+            });
+            match result {
+                Ok(hash) => {
+                    metrics::event_pushed(started);
+                    metrics::sync_event_received(metrics::SyncEventOutcome::New);
+                    if let Some(seen) = &mut self.recently_seen {
+                        seen.insert(hash.clone());
+                    }
+                    if self.pending.is_some() {
+                        self.resolve_orphans(hash);
+                    }
+                    applied += 1;
+                }
+                Err(PushError::EventAlreadyExists(_)) => {
+                    metrics::sync_event_received(metrics::SyncEventOutcome::KnownDuplicate)
+                }
+                Err(e) => {
+                    metrics::push_rejected(e.category(), started);
+                    Span::current().record("applied", applied);
+                    self.report_state_metrics();
+                    return Err(e);
+                }
+            }
+        }
+        Span::current().record("applied", applied);
+        self.report_state_metrics();
+        Ok(applied)
+    }
+}
+
+fn lane_of<TPeerId: Hash>(author: &TPeerId, lanes: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    author.hash(&mut hasher);
+    (hasher.finish() % lanes as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::{MockSigner, Signer};
+    use crate::testing::{fixture, GraphBuilder, TestGraph};
+
+    fn empty(source_fixture: &fixture::Fixture) -> TestGraph<(), u64> {
+        GraphBuilder::new(
+            &source_fixture.peers[0],
+            0u64,
+            (),
+            source_fixture.coin_frequency,
+        )
+        .build()
+        .unwrap()
+        .graph
+    }
+
+    #[test]
+    fn same_as_sequential() {
+        let example = fixture::random_gossip();
+        let source = example.build().unwrap().graph;
+        let jobs = source.generate_sync_for(&u64::MAX).unwrap();
+        let mut sequential = empty(&example);
+        let applied = sequential.apply_sync_jobs(jobs.clone()).unwrap();
+        for threads in [1, 3, 8] {
+            let mut concurrent = empty(&example);
+            assert_eq!(
+                concurrent.push_concurrent(jobs.clone(), threads).unwrap(),
+                applied
+            );
+            assert!(concurrent
+                .ordering
+                .ordered()
+                .eq(sequential.ordering.ordered()));
+            assert_eq!(concurrent.round_index, sequential.round_index);
+            // Everything is known now
+            assert_eq!(
+                concurrent.push_concurrent(jobs.clone(), threads).unwrap(),
+                0
+            );
+        }
+    }
+
+    #[test]
+    fn stops_at_invalid_event() {
+        let example = fixture::detailed_example();
+        let source = example.build().unwrap().graph;
+        let mut events = source.generate_sync_for(&u64::MAX).unwrap().into_linear();
+        let forged = events.len() / 2;
+        let (unsigned, _) = events[forged].clone().into_parts();
+        let wrong_signature = MockSigner::<u64, ()>::new().sign(&event::Hash::from_array([7; 64]));
+        events[forged] =
+            event::SignedEvent::with_signature(unsigned, wrong_signature, |_, _, _| true).unwrap();
+        let mut graph = empty(&example);
+        let result = graph.push_concurrent(sync::Jobs::from_linear(events), 4);
+        assert!(matches!(result, Err(PushError::InvalidSignature { .. })));
+        // Events before the forged one are inserted
+        assert!(graph.all_events.len() > 1);
+    }
+}
@@ -0,0 +1,127 @@
+//! Events that have not reached a supermajority yet.
+//!
+//! An event is seen by a member once it is an ancestor of the member's
+//! latest event. Until more than `2n/3` members see an event, it can't be
+//! strongly seen and thus can't get to the consensus, so the gossip layer
+//! may want to push it to the members that miss it.
+
+use std::collections::HashSet;
+
+use super::Graph;
+use crate::algorithm::{core, event};
+
+/// Event seen by at most `2n/3` members, see
+/// [`Graph::unconfirmed_events`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnconfirmedEvent<TPeerId> {
+    pub hash: event::Hash,
+    pub author: TPeerId,
+    /// Members whose latest events don't descend from the event
+    pub missing: Vec<TPeerId>,
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash + Clone,
+{
+    /// Events not yet seen by a supermajority of members, with the members
+    /// that haven't seen them. Authors (and the members in `missing`) are
+    /// in the [canonical order](super::peer_order), events of an author
+    /// newest first.
+    ///
+    /// Ancestors of an event seen by a supermajority are seen as well, so
+    /// only the recent part of each author's chain is looked at.
+    pub fn unconfirmed_events(&self) -> Vec<UnconfirmedEvent<TPeerId>> {
+        let members = self.members_count();
+        let mut unconfirmed = vec![];
+        let order = self.peer_order();
+        let indices: Vec<_> = order
+            .peers()
+            .iter()
+            .map(|peer| (peer, &self.peer_index[peer]))
+            .collect();
+        for &(author, index) in &indices {
+            let mut to_visit: Vec<_> = index.latest_events().iter().collect();
+            let mut visited = HashSet::new();
+            while let Some(hash) = to_visit.pop() {
+                if !visited.insert(hash) {
+                    continue;
+                }
+                // The walk only reaches known events. A tip that is unknown
+                // was pruned, so it is too old to see them.
+                let missing: Vec<_> = indices
+                    .iter()
+                    .filter(|(_, index)| {
+                        !index
+                            .latest_events()
+                            .iter()
+                            .any(|tip| matches!(self.is_ancestor(tip, hash), Ok(true)))
+                    })
+                    .map(|(peer, _)| (*peer).clone())
+                    .collect();
+                if core::supermajority(members - missing.len(), members) {
+                    continue;
+                }
+                unconfirmed.push(UnconfirmedEvent {
+                    hash: hash.clone(),
+                    author: author.clone(),
+                    missing,
+                });
+                let parents = self.headers.get(hash).and_then(|h| h.parents.as_ref());
+                // Self parents may be pruned
+                if let Some(parents) = parents.filter(|p| self.headers.contains_key(&p.self_parent))
+                {
+                    to_visit.push(&parents.self_parent);
+                }
+            }
+        }
+        unconfirmed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::algorithm::core;
+    use crate::testing::fixture;
+
+    #[test]
+    fn unseen_by_supermajority_listed() {
+        let mut graph = fixture::random_gossip().build().unwrap().graph;
+        let own = graph.peer_latest_event(&0).unwrap().clone();
+        let created = graph.create_event((), own).unwrap();
+        let unconfirmed: HashMap<_, _> = graph
+            .unconfirmed_events()
+            .into_iter()
+            .map(|event| (event.hash.clone(), event))
+            .collect();
+        let mut others = graph.peers();
+        others.retain(|peer| *peer != 0);
+        others.sort();
+        let mut missing = unconfirmed[&created].missing.clone();
+        missing.sort();
+        assert_eq!(missing, others);
+
+        let members = graph.members_count();
+        for hash in graph.all_events.keys() {
+            let seen_by: Vec<_> = graph
+                .peers()
+                .into_iter()
+                .filter(|peer| {
+                    let tips = graph.peer_index[peer].latest_events();
+                    tips.iter().any(|tip| graph.is_ancestor(tip, hash).unwrap())
+                })
+                .collect();
+            match unconfirmed.get(hash) {
+                Some(event) => {
+                    assert!(!core::supermajority(seen_by.len(), members));
+                    assert_eq!(event.missing.len(), members - seen_by.len());
+                    assert!(event.missing.iter().all(|peer| !seen_by.contains(peer)));
+                }
+                None => assert!(core::supermajority(seen_by.len(), members)),
+            }
+        }
+    }
+}
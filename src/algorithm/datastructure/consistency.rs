@@ -0,0 +1,510 @@
+//! Self-check of the graph's indices, for catching corruption early (e.g.
+//! under injected faults in the simulator) instead of getting wrong
+//! consensus later.
+//!
+//! [`Graph::check_consistency`] stops at the first problem, while
+//! [`Graph::verify_integrity`] lists all of them, which helps to tell a
+//! single bad entry from a broken index after a recovery or an import.
+//!
+//! [`Graph::audit_peer_lane`] looks at the chain of a single author in more
+//! detail and reports everything it finds rather than the first problem,
+//! e.g. to see what an import or a recovery left behind.
+
+use std::collections::HashSet;
+
+use thiserror::Error;
+
+use super::Graph;
+use crate::algorithm::event::{self, Parents};
+use crate::Timestamp;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum InconsistencyError {
+    #[error("Event {0} is missing from the round index at its round")]
+    NotInRoundIndex(event::Hash),
+    #[error("Round index has event {0}, which is unknown or listed in another round")]
+    StrayInRoundIndex(event::Hash),
+    #[error("Parent {parent} of event {event} is unknown")]
+    UnknownParent {
+        event: event::Hash,
+        parent: event::Hash,
+    },
+    #[error("Event {child} is missing from children of its parent {parent}")]
+    MissingChild {
+        parent: event::Hash,
+        child: event::Hash,
+    },
+    #[error("Round of event {0} is less than of its parent")]
+    RoundDecreases(event::Hash),
+    #[error("Witness status of event {0} does not match its round")]
+    WitnessMismatch(event::Hash),
+    #[error("Event {0} is missing from the index of its author")]
+    NotInPeerIndex(event::Hash),
+    #[error("Peer index refers to unknown event {0}")]
+    StrayInPeerIndex(event::Hash),
+    #[error("Event {0} is unknown or ordered twice")]
+    BadOrder(event::Hash),
+    #[error("Header of event {0} is missing or does not match the event")]
+    HeaderMismatch(event::Hash),
+    #[error("Event {child} is listed as a child of {parent}, which is not its parent")]
+    StrayChild {
+        parent: event::Hash,
+        child: event::Hash,
+    },
+    #[error("Witness index has event {0}, which is unknown")]
+    StrayWitness(event::Hash),
+    #[error("Cached ordering data of event {0} is for an unknown event or an impossible round")]
+    StaleOrderingData(event::Hash),
+}
+
+/// Problem in the chain of an author, see [`Graph::audit_peer_lane`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LaneIssue {
+    /// Sequence number is not one more than the self parent's
+    SequenceGap {
+        event: event::Hash,
+        expected: u64,
+        found: u64,
+    },
+    /// Timestamp is earlier than the self parent's
+    TimestampDecreases {
+        event: event::Hash,
+        parent_timestamp: Timestamp,
+        timestamp: Timestamp,
+    },
+    /// Several self children, but the fork is not tracked
+    UntrackedFork {
+        event: event::Hash,
+        children: Vec<event::Hash>,
+    },
+    /// Self child that is unknown, by another author or has another self
+    /// parent
+    UnexpectedSelfChild {
+        event: event::Hash,
+        child: event::Hash,
+    },
+    /// Latest event of the author that has self children
+    TipHasChildren(event::Hash),
+}
+
+/// Result of [`Graph::audit_peer_lane`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LaneReport {
+    /// Number of the author's events looked at
+    pub events: usize,
+    /// Number of events with several self children
+    pub forks: usize,
+    pub issues: Vec<LaneIssue>,
+}
+
+impl LaneReport {
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash,
+{
+    /// Check the chain of `peer`'s events: sequence numbers go up by one,
+    /// timestamps don't go back and self children are what the index
+    /// expects. Forks are not issues as long as they are tracked. `None`
+    /// if the peer is unknown.
+    ///
+    /// Takes time linear in the number of the peer's events.
+    pub fn audit_peer_lane(&self, peer: &TPeerId) -> Option<LaneReport> {
+        let index = self.peer_index.get(peer)?;
+        let mut report = LaneReport::default();
+        let forks = index.fork_index().forks();
+        for hash in index.authored_events().keys() {
+            let Some(event) = self.all_events.get(hash) else {
+                // Reported by `check_consistency`
+                continue;
+            };
+            report.events += 1;
+            if let event::Kind::Regular(parents) = event.kind() {
+                // Pruned self parents can't be compared with
+                if let Some(parent) = self.all_events.get(&parents.self_parent) {
+                    let expected = self
+                        .headers
+                        .get(&parents.self_parent)
+                        .map(|h| h.sequence + 1);
+                    let found = self.headers.get(hash).map(|h| h.sequence);
+                    if let (Some(expected), Some(found)) = (expected, found) {
+                        if expected != found {
+                            report.issues.push(LaneIssue::SequenceGap {
+                                event: hash.clone(),
+                                expected,
+                                found,
+                            });
+                        }
+                    }
+                    if event.timestamp() < parent.timestamp() {
+                        report.issues.push(LaneIssue::TimestampDecreases {
+                            event: hash.clone(),
+                            parent_timestamp: *parent.timestamp(),
+                            timestamp: *event.timestamp(),
+                        });
+                    }
+                }
+            }
+
+            let children: Vec<event::Hash> = event.children.self_child.clone().into();
+            if children.len() > 1 {
+                report.forks += 1;
+                let tracked = forks
+                    .get(hash)
+                    .is_some_and(|tracked| children.iter().all(|c| tracked.contains(c)));
+                if !tracked {
+                    report.issues.push(LaneIssue::UntrackedFork {
+                        event: hash.clone(),
+                        children: children.clone(),
+                    });
+                }
+            }
+            for child in children {
+                let expected = self.all_events.get(&child).is_some_and(|child| {
+                    child.author() == peer
+                        && matches!(child.kind(), event::Kind::Regular(p) if &p.self_parent == hash)
+                });
+                if !expected {
+                    report.issues.push(LaneIssue::UnexpectedSelfChild {
+                        event: hash.clone(),
+                        child,
+                    });
+                }
+            }
+            if !event.children.self_child.is_empty() && index.latest_events().contains(hash) {
+                report.issues.push(LaneIssue::TipHasChildren(hash.clone()));
+            }
+        }
+        Some(report)
+    }
+
+    /// Check that the indices agree with each other and with the events.
+    /// Takes time linear in the size of the graph.
+    pub fn check_consistency(&self) -> Result<(), InconsistencyError> {
+        match self.verify_integrity().into_iter().next() {
+            Some(violation) => Err(violation),
+            None => Ok(()),
+        }
+    }
+
+    /// All violations of the invariants found, empty for a healthy graph.
+    /// Besides the checks of [`check_consistency`](Self::check_consistency),
+    /// children must point back to their parents and the witness and
+    /// ordering caches must not refer to unknown events. Each event is
+    /// reported at most once for its own entries.
+    ///
+    /// Takes time linear in the size of the graph.
+    pub fn verify_integrity(&self) -> Vec<InconsistencyError> {
+        let mut violations = vec![];
+        for (round, events) in self.round_index.iter() {
+            for hash in events {
+                if self.round_of.get(hash) != Some(&round) || !self.all_events.contains_key(hash) {
+                    violations.push(InconsistencyError::StrayInRoundIndex(hash.clone()));
+                }
+            }
+        }
+        for (hash, event) in &self.all_events {
+            if let Err(violation) = self.check_event(hash, event) {
+                violations.push(violation);
+            }
+            let self_children: Vec<_> = event.children.self_child.clone().into();
+            let self_children = self_children.into_iter().map(|child| (child, true));
+            let other_children = event.children.other_children.iter().cloned();
+            for (child, is_self_child) in self_children.chain(other_children.map(|c| (c, false))) {
+                let points_back = self.all_events.get(&child).is_some_and(|child| {
+                    matches!(child.kind(), event::Kind::Regular(parents)
+                    if match is_self_child {
+                        true => &parents.self_parent == hash,
+                        false => &parents.other_parent == hash,
+                    })
+                });
+                if !points_back {
+                    violations.push(InconsistencyError::StrayChild {
+                        parent: hash.clone(),
+                        child,
+                    });
+                }
+            }
+        }
+        if let Some(stray) = self
+            .headers
+            .keys()
+            .find(|hash| !self.all_events.contains_key(*hash))
+        {
+            violations.push(InconsistencyError::HeaderMismatch(stray.clone()));
+        }
+        for entry in self.peer_index.values() {
+            let referred = std::iter::once(entry.origin())
+                .chain(entry.latest_events())
+                .chain(entry.authored_events().keys());
+            for hash in referred {
+                if !self.all_events.contains_key(hash) {
+                    violations.push(InconsistencyError::StrayInPeerIndex(hash.clone()));
+                }
+            }
+        }
+        let mut ordered = HashSet::new();
+        for hash in self.ordering.ordered() {
+            // Pruned events stay in the order
+            let known = self.events_dropped() || self.all_events.contains_key(hash);
+            if !known || !ordered.insert(hash) {
+                violations.push(InconsistencyError::BadOrder(hash.clone()));
+            }
+        }
+        for hash in self.witnesses.lock().unwrap().keys() {
+            if !self.all_events.contains_key(hash) {
+                violations.push(InconsistencyError::StrayWitness(hash.clone()));
+            }
+        }
+        for (hash, (round_received, ..)) in self.ordering_data_cache.lock().unwrap().iter() {
+            let possible = self
+                .round_of
+                .get(hash)
+                .is_some_and(|round| round <= round_received);
+            if !possible {
+                violations.push(InconsistencyError::StaleOrderingData(hash.clone()));
+            }
+        }
+        violations
+    }
+
+    /// Entries of the event in the indices
+    fn check_event(
+        &self,
+        hash: &event::Hash,
+        event: &event::EventWrapper<TPayload, TGenesisPayload, TPeerId>,
+    ) -> Result<(), InconsistencyError> {
+        let round = match self.round_of.get(hash) {
+            // Pruned rounds are not indexed, their kept events only have rounds
+            Some(&r) if r < self.round_index.base() => r,
+            Some(&r) if self.round_index.get(r).is_some_and(|e| e.contains(hash)) => r,
+            _ => return Err(InconsistencyError::NotInRoundIndex(hash.clone())),
+        };
+        let authored = self
+            .peer_index
+            .get(event.author())
+            .is_some_and(|entry| entry.authored_events().contains_key(hash));
+        if !authored {
+            return Err(InconsistencyError::NotInPeerIndex(hash.clone()));
+        }
+        let is_witness = match event.kind() {
+            event::Kind::Genesis(_) => true,
+            // Nothing to compare with after pruning
+            event::Kind::Regular(_) if self.parents_pruned(hash) => {
+                self.witnesses.lock().unwrap().contains_key(hash)
+            }
+            event::Kind::Regular(Parents {
+                self_parent,
+                other_parent,
+            }) => {
+                let parent = |parent: &event::Hash| {
+                    let parent_event = self.all_events.get(parent).ok_or_else(|| {
+                        InconsistencyError::UnknownParent {
+                            event: hash.clone(),
+                            parent: parent.clone(),
+                        }
+                    })?;
+                    let parent_round = self.round_of.get(parent).copied().unwrap_or(0);
+                    Ok((parent_event, parent_round))
+                };
+                let (self_parent_event, self_parent_round) = parent(self_parent)?;
+                let (other_parent_event, other_parent_round) = parent(other_parent)?;
+                let self_children: Vec<_> = self_parent_event.children.self_child.clone().into();
+                if !self_children.contains(hash) {
+                    return Err(InconsistencyError::MissingChild {
+                        parent: self_parent.clone(),
+                        child: hash.clone(),
+                    });
+                }
+                if !other_parent_event.children.other_children.contains(hash) {
+                    return Err(InconsistencyError::MissingChild {
+                        parent: other_parent.clone(),
+                        child: hash.clone(),
+                    });
+                }
+                if round < self_parent_round.max(other_parent_round) {
+                    return Err(InconsistencyError::RoundDecreases(hash.clone()));
+                }
+                round > self_parent_round
+            }
+        };
+        if is_witness != self.witnesses.lock().unwrap().contains_key(hash) {
+            return Err(InconsistencyError::WitnessMismatch(hash.clone()));
+        }
+        let parents = match event.kind() {
+            event::Kind::Genesis(_) => None,
+            event::Kind::Regular(parents) => Some(parents),
+        };
+        // Pruned self parents can't be checked
+        let self_parent = parents.map(|parents| self.headers.get(&parents.self_parent));
+        let header_matches = self.headers.get(hash).is_some_and(|header| {
+            let chain_matches = match self_parent {
+                Some(parent) => parent.is_none_or(|parent| {
+                    header.sequence == parent.sequence + 1 && header.slot == parent.slot
+                }),
+                None => header.sequence == 0,
+            };
+            header.parents.as_ref() == parents
+                && chain_matches
+                && &header.author == event.author()
+                && header.round == Some(round)
+                && header.witness == is_witness
+        });
+        if !header_matches {
+            return Err(InconsistencyError::HeaderMismatch(hash.clone()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fixture, GraphBuilder};
+
+    #[test]
+    fn consistent_after_pushes() {
+        for fixture in [fixture::detailed_example(), fixture::fork()] {
+            let built = fixture.build().unwrap();
+            assert_eq!(built.graph.check_consistency(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn corruption_detected() {
+        let mut built = fixture::fork().build().unwrap();
+        let hash = built.hash("m2_1").clone();
+        let round = built.graph.round_of[&hash];
+        built.graph.round_index[round].remove(&hash);
+        assert_eq!(
+            built.graph.check_consistency(),
+            Err(InconsistencyError::NotInRoundIndex(hash.clone()))
+        );
+        built.graph.round_index[round].insert(hash.clone());
+
+        let m2 = built.hash("m2").clone();
+        built
+            .graph
+            .all_events
+            .get_mut(&m2)
+            .unwrap()
+            .children
+            .other_children
+            .clear();
+        assert_eq!(
+            built.graph.check_consistency(),
+            Err(InconsistencyError::MissingChild {
+                parent: m2,
+                child: hash
+            })
+        );
+    }
+
+    #[test]
+    fn all_violations_listed() {
+        let mut built = fixture::fork().build().unwrap();
+        let [m2, m2_1, a2] = ["m2", "m2_1", "a2"].map(|name| built.hash(name).clone());
+        let graph = &mut built.graph;
+        assert!(graph.verify_integrity().is_empty());
+
+        graph
+            .all_events
+            .get_mut(&a2)
+            .unwrap()
+            .children
+            .other_children
+            .push(m2.clone());
+        let unknown = event::Hash::from_array([3; 64]);
+        graph
+            .witnesses
+            .lock()
+            .unwrap()
+            .insert(unknown.clone(), super::super::WitnessFamousness::Undecided);
+        graph.ordering_data_cache.lock().unwrap().insert(
+            m2_1.clone(),
+            (0, 0, event::Signature(event::Hash::from_array([0; 64]))),
+        );
+        graph.header_mut(&m2_1).witness ^= true;
+
+        let mut violations = graph.verify_integrity();
+        violations.sort_by_key(|v| v.to_string());
+        let mut expected = vec![
+            InconsistencyError::StrayChild {
+                parent: a2,
+                child: m2,
+            },
+            InconsistencyError::StrayWitness(unknown),
+            InconsistencyError::StaleOrderingData(m2_1.clone()),
+            InconsistencyError::HeaderMismatch(m2_1),
+        ];
+        expected.sort_by_key(|v| v.to_string());
+        assert_eq!(violations, expected);
+        assert!(graph.check_consistency().is_err());
+    }
+
+    #[test]
+    fn header_mismatch_detected() {
+        let mut built = fixture::fork().build().unwrap();
+        let hash = built.hash("m2_1").clone();
+        built.graph.header_mut(&hash).witness ^= true;
+        assert_eq!(
+            built.graph.check_consistency(),
+            Err(InconsistencyError::HeaderMismatch(hash))
+        );
+    }
+
+    #[test]
+    fn lane_audit() {
+        let built = GraphBuilder::new("a", 0u64, (), 999)
+            .peer("b", 1)
+            .event("b1", "b", "a")
+            .timestamp(5)
+            .event("a1", "a", "b1")
+            .fork("b1_fork", "b1", "a1")
+            .timestamp(1)
+            .event("a2", "a", "b1_fork")
+            .build()
+            .unwrap();
+        let [a1, a2, b1] = ["a1", "a2", "b1"].map(|name| built.hash(name).clone());
+        let mut graph = built.graph;
+        assert_eq!(graph.audit_peer_lane(&2), None);
+        // Tracked forks are fine
+        let report = graph.audit_peer_lane(&1).unwrap();
+        assert_eq!((report.events, report.forks), (3, 1));
+        assert!(report.is_clean());
+        assert_eq!(
+            graph.audit_peer_lane(&0).unwrap().issues,
+            vec![LaneIssue::TimestampDecreases {
+                event: a2.clone(),
+                parent_timestamp: 5,
+                timestamp: 1,
+            }]
+        );
+
+        graph.header_mut(&a2).sequence = 5;
+        graph
+            .all_events
+            .get_mut(&b1)
+            .unwrap()
+            .children
+            .self_child
+            .add_child(a1.clone());
+        let report = graph.audit_peer_lane(&0).unwrap();
+        assert!(report.issues.contains(&LaneIssue::SequenceGap {
+            event: a2,
+            expected: 2,
+            found: 5
+        }));
+        let issues = graph.audit_peer_lane(&1).unwrap().issues;
+        assert!(issues.contains(&LaneIssue::UnexpectedSelfChild {
+            event: b1.clone(),
+            child: a1
+        }));
+        assert!(issues.contains(&LaneIssue::TipHasChildren(b1)));
+    }
+}
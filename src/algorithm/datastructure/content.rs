@@ -0,0 +1,242 @@
+//! Filtering of payloads at ingestion.
+//!
+//! A [`ContentPolicy`] set with [`Graph::set_content_policy`] sees payloads
+//! of events after their signatures are checked. A disallowed payload is
+//! replaced by [`Redacted::redacted`] of its digest before the event is
+//! stored. The event keeps its hash, signature and parents, so consensus
+//! goes on as if nothing happened: only the application sees the
+//! difference.
+//!
+//! The hash covers the original payload, so a redacted event can't be
+//! verified by others and is not sent in sync responses, together with its
+//! descendants the peer doesn't know. Peers get it from other members, after
+//! that syncs with us continue as usual. Replicas of an application should
+//! share the policy, otherwise they see different payloads.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use blake2::{Blake2b512, Digest};
+
+use super::Graph;
+use crate::algorithm::codec::{self, PayloadCodec};
+use crate::algorithm::event;
+
+/// Payload seen by a [`ContentPolicy`]
+pub struct Content<'a, TPayload> {
+    pub payload: &'a TPayload,
+    /// As encoded in the event
+    pub encoded: &'a [u8],
+    /// Blake2b of `encoded`
+    pub digest: &'a event::Hash,
+}
+
+pub trait ContentPolicy<TPayload>: Send + Sync {
+    /// Whether the payload may be stored and gossiped
+    fn allows(&self, content: &Content<'_, TPayload>) -> bool;
+}
+
+/// Payloads that can stand in for a disallowed one
+pub trait Redacted {
+    fn redacted(digest: &event::Hash) -> Self;
+}
+
+/// The digest itself
+impl Redacted for Vec<u8> {
+    fn redacted(digest: &event::Hash) -> Self {
+        digest.as_ref().to_vec()
+    }
+}
+
+/// Disallows payloads with listed digests
+#[derive(Debug, Clone, Default)]
+pub struct DigestDenylist {
+    pub denied: HashSet<event::Hash>,
+}
+
+impl<TPayload> ContentPolicy<TPayload> for DigestDenylist {
+    fn allows(&self, content: &Content<'_, TPayload>) -> bool {
+        !self.denied.contains(content.digest)
+    }
+}
+
+/// Disallows payloads longer than `max_bytes` when encoded
+#[derive(Debug, Clone, Copy)]
+pub struct SizeLimit {
+    pub max_bytes: usize,
+}
+
+impl<TPayload> ContentPolicy<TPayload> for SizeLimit {
+    fn allows(&self, content: &Content<'_, TPayload>) -> bool {
+        content.encoded.len() <= self.max_bytes
+    }
+}
+
+/// Blake2b of the encoded payload, what [`DigestDenylist`] lists
+pub fn payload_digest<TPayload: PayloadCodec>(payload: &TPayload) -> bincode::Result<event::Hash> {
+    Ok(digest(&codec::encode_payload(payload)?))
+}
+
+fn digest(encoded: &[u8]) -> event::Hash {
+    let mut hasher = Blake2b512::new();
+    hasher.update(encoded);
+    let hash_slice = &hasher.finalize()[..];
+    event::Hash::from_array(hash_slice.try_into().expect("blake2b512 gives 64 bytes"))
+}
+
+/// Policy of a graph with the way to redact payloads
+pub(super) struct ContentFilter<TPayload> {
+    policy: Arc<dyn ContentPolicy<TPayload>>,
+    redact: fn(&event::Hash) -> TPayload,
+}
+
+impl<TPayload> Clone for ContentFilter<TPayload> {
+    fn clone(&self) -> Self {
+        Self {
+            policy: self.policy.clone(),
+            redact: self.redact,
+        }
+    }
+}
+
+impl<TPayload: PayloadCodec> ContentFilter<TPayload> {
+    /// Replacement of the payload if the policy disallows it
+    pub(super) fn check(&self, payload: &TPayload) -> bincode::Result<Option<TPayload>> {
+        let encoded = codec::encode_payload(payload)?;
+        let digest = digest(&encoded);
+        let content = Content {
+            payload,
+            encoded: &encoded,
+            digest: &digest,
+        };
+        Ok((!self.policy.allows(&content)).then(|| (self.redact)(&digest)))
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+{
+    /// Consult `policy` for payloads of new events, `None` (the default)
+    /// accepts everything. Events already in the graph are not checked.
+    pub fn set_content_policy(&mut self, policy: Option<Arc<dyn ContentPolicy<TPayload>>>)
+    where
+        TPayload: Redacted,
+    {
+        self.content_filter = policy.map(|policy| ContentFilter {
+            policy,
+            redact: TPayload::redacted,
+        });
+    }
+
+    /// Whether the payload of the event was replaced by the content policy
+    pub fn is_redacted(&self, event: &event::Hash) -> bool {
+        self.redacted.contains(event)
+    }
+
+    /// Drop redacted and [compacted](Graph::compact) events and their
+    /// descendants from a sync response
+    pub(super) fn withhold_redacted(
+        &self,
+        events: Vec<event::SignedEvent<TPayload, TGenesisPayload, TPeerId>>,
+    ) -> Vec<event::SignedEvent<TPayload, TGenesisPayload, TPeerId>> {
+        if self.redacted.is_empty() && self.compacted.is_empty() {
+            return events;
+        }
+        let mut withheld = HashSet::new();
+        events
+            .into_iter()
+            .filter(|e| {
+                let parents_withheld = match e.unsigned().fields().kind() {
+                    event::Kind::Genesis(_) => false,
+                    event::Kind::Regular(parents) => {
+                        withheld.contains(&parents.self_parent)
+                            || withheld.contains(&parents.other_parent)
+                    }
+                };
+                let replaced =
+                    self.redacted.contains(e.hash()) || self.compacted.contains(e.hash());
+                if parents_withheld || replaced {
+                    withheld.insert(e.hash().clone());
+                    false
+                } else {
+                    true
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{BuiltGraph, GraphBuilder};
+
+    /// Members `a`, `b`, `c`; `b` publishes something objectionable in `b1`
+    fn build(with_events: bool) -> BuiltGraph<Vec<u8>, u64> {
+        let builder = GraphBuilder::new("a", 0u64, vec![], 999)
+            .peer("b", 1)
+            .peer("c", 2);
+        if !with_events {
+            return builder.build().unwrap();
+        }
+        builder
+            .event("a1", "GENESIS_a", "GENESIS_b")
+            .payload(b"objectionable".to_vec())
+            .event("b1", "GENESIS_b", "a1")
+            .payload(vec![])
+            .event("c1", "GENESIS_c", "b1")
+            .event("a2", "a1", "c1")
+            .event("c2", "c1", "a2")
+            .build()
+            .unwrap()
+    }
+
+    fn sync(to: &mut TestGraph, from: &TestGraph) -> usize {
+        let jobs = from.generate_sync_for_request(&to.sync_request()).unwrap();
+        to.apply_sync_jobs(jobs).unwrap()
+    }
+
+    type TestGraph = crate::testing::TestGraph<Vec<u8>, u64>;
+
+    #[test]
+    fn redacted_at_ingestion_and_withheld() {
+        let source = build(true);
+        let b1 = source.hash("b1").clone();
+        let mut filtering = build(false).graph;
+        let denied = payload_digest(&b"objectionable".to_vec()).unwrap();
+        filtering.set_content_policy(Some(Arc::new(DigestDenylist {
+            denied: HashSet::from([denied.clone()]),
+        })));
+        assert_eq!(sync(&mut filtering, &source.graph), 5);
+        assert!(filtering.is_redacted(&b1));
+        assert!(!filtering.is_redacted(source.hash("a1")));
+        assert_eq!(
+            filtering.event(&b1).unwrap().payload().unwrap(),
+            denied.as_ref()
+        );
+        assert_eq!(
+            filtering.event_info(&b1).unwrap().round,
+            source.graph.event_info(&b1).unwrap().round
+        );
+
+        // Only the events not built on `b1` are passed on
+        let mut late = build(false).graph;
+        assert_eq!(sync(&mut late, &filtering), 1);
+        assert!(late.event(&b1).is_none());
+        assert_eq!(sync(&mut late, &source.graph), 4);
+        assert_eq!(sync(&mut late, &filtering), 0);
+    }
+
+    #[test]
+    fn size_limit() {
+        let source = build(true);
+        let mut filtering = build(false).graph;
+        filtering.set_content_policy(Some(Arc::new(SizeLimit { max_bytes: 8 })));
+        sync(&mut filtering, &source.graph);
+        let redacted: Vec<_> = ["a1", "b1", "c1", "a2", "c2"]
+            .into_iter()
+            .filter(|name| filtering.is_redacted(source.hash(name)))
+            .collect();
+        assert_eq!(redacted, ["b1"]);
+    }
+}
@@ -0,0 +1,299 @@
+//! Resumable consumption of finalized events.
+//!
+//! [`Graph::next_finalized_event`] forgets what it returned when the process
+//! stops. A consumer that has to see each finalized event exactly once
+//! across restarts keeps a [`FinalizedCursor`] instead: the number of events
+//! it has acknowledged and the hash of the latest one. After a restart,
+//! [`Graph::finalized_stream_from`] continues right after the cursor, once
+//! the graph (e.g. restored from an [archive](super::archive)) has
+//! finalized that far again, and checks that the history is the same.
+//!
+//! [`DurableCursor`] saves the cursor to a [`CursorStore`] on every
+//! acknowledgement. Exactly-once holds if the effect of handling an event
+//! and saving the cursor are atomic, e.g. the store writes into the
+//! application's database in the same transaction. Otherwise an event
+//! handled right before a crash is delivered again (at least once).
+
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::app::OrderedTransaction;
+use super::Graph;
+use crate::algorithm::event;
+
+/// Position of a consumer in the consensus order
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FinalizedCursor {
+    /// Number of acknowledged events
+    pub position: usize,
+    /// Hash of the latest acknowledged event
+    pub last_event: Option<event::Hash>,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CursorError {
+    #[error("Event #{position} acknowledged, expected #{expected}")]
+    OutOfOrder { expected: usize, position: usize },
+    #[error("Event #{0} in the graph differs from the one acknowledged")]
+    Diverged(usize),
+    #[error("Event #{0} was pruned before it was acknowledged")]
+    Pruned(usize),
+}
+
+impl FinalizedCursor {
+    /// Nothing acknowledged yet
+    pub fn start() -> Self {
+        Self::default()
+    }
+
+    /// Move past the transaction, which must be the next one
+    pub fn acknowledge<TPayload, TPeerId>(
+        &mut self,
+        transaction: &OrderedTransaction<'_, TPayload, TPeerId>,
+    ) -> Result<(), CursorError> {
+        if transaction.position != self.position {
+            return Err(CursorError::OutOfOrder {
+                expected: self.position,
+                position: transaction.position,
+            });
+        }
+        self.position += 1;
+        self.last_event = Some(transaction.event.clone());
+        Ok(())
+    }
+}
+
+/// Where a [`DurableCursor`] keeps its cursor
+pub trait CursorStore {
+    type Error;
+
+    /// `None` if nothing was saved yet
+    fn load(&mut self) -> Result<Option<FinalizedCursor>, Self::Error>;
+    fn save(&mut self, cursor: &FinalizedCursor) -> Result<(), Self::Error>;
+}
+
+/// Cursor in a file. Saved to a temporary file that replaces the previous
+/// one, so a crash leaves either the old or the new cursor.
+pub struct FileCursorStore {
+    path: PathBuf,
+}
+
+impl FileCursorStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl CursorStore for FileCursorStore {
+    type Error = io::Error;
+
+    fn load(&mut self) -> io::Result<Option<FinalizedCursor>> {
+        match std::fs::read(&self.path) {
+            Ok(bytes) => bincode::deserialize(&bytes)
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn save(&mut self, cursor: &FinalizedCursor) -> io::Result<()> {
+        let bytes = bincode::serialize(cursor).map_err(io::Error::other)?;
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, bytes)?;
+        std::fs::rename(&temporary, &self.path)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum DurableCursorError<E> {
+    #[error(transparent)]
+    Cursor(#[from] CursorError),
+    #[error("Failed to save the cursor: {0}")]
+    Store(E),
+}
+
+/// Cursor saved on every acknowledgement, see the [module docs](self)
+pub struct DurableCursor<S> {
+    store: S,
+    cursor: FinalizedCursor,
+}
+
+impl<S: CursorStore> DurableCursor<S> {
+    /// Continue from the saved cursor, or from the start
+    pub fn open(mut store: S) -> Result<Self, S::Error> {
+        let cursor = store.load()?.unwrap_or_default();
+        Ok(Self { store, cursor })
+    }
+
+    pub fn cursor(&self) -> &FinalizedCursor {
+        &self.cursor
+    }
+
+    pub fn acknowledge<TPayload, TPeerId>(
+        &mut self,
+        transaction: &OrderedTransaction<'_, TPayload, TPeerId>,
+    ) -> Result<(), DurableCursorError<S::Error>> {
+        let mut cursor = self.cursor.clone();
+        cursor.acknowledge(transaction)?;
+        self.store
+            .save(&cursor)
+            .map_err(DurableCursorError::Store)?;
+        self.cursor = cursor;
+        Ok(())
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+{
+    /// Finalized events after the cursor, in consensus order. Empty if the
+    /// graph hasn't finalized up to the cursor yet. Independent of
+    /// [`next_finalized_event`](Self::next_finalized_event).
+    ///
+    /// Fails if the latest acknowledged event is not at its position in the
+    /// graph's order. Items fail if the event was [pruned](Self::prune).
+    pub fn finalized_stream_from<'a>(
+        &'a self,
+        cursor: &FinalizedCursor,
+    ) -> Result<
+        impl Iterator<Item = Result<OrderedTransaction<'a, TPayload, TPeerId>, CursorError>> + 'a,
+        CursorError,
+    > {
+        let caught_up = self.ordering.len() >= cursor.position;
+        if caught_up && cursor.position > 0 {
+            let acknowledged = self.ordering.ordered().nth(cursor.position - 1);
+            if acknowledged != cursor.last_event.as_ref() {
+                return Err(CursorError::Diverged(cursor.position - 1));
+            }
+        }
+        let start = match caught_up {
+            true => cursor.position,
+            false => self.ordering.len(),
+        };
+        Ok(self
+            .ordering
+            .ordered()
+            .enumerate()
+            .skip(start)
+            .map(|(position, hash)| {
+                let event = self
+                    .all_events
+                    .get(hash)
+                    .ok_or(CursorError::Pruned(position))?;
+                Ok(OrderedTransaction {
+                    position,
+                    event: hash,
+                    author: event.author(),
+                    payload: event.payload().ok_or(CursorError::Pruned(position))?,
+                })
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture;
+
+    #[derive(Default)]
+    struct MemoryStore {
+        saved: Option<FinalizedCursor>,
+        fail: bool,
+    }
+
+    impl CursorStore for MemoryStore {
+        type Error = &'static str;
+
+        fn load(&mut self) -> Result<Option<FinalizedCursor>, &'static str> {
+            Ok(self.saved.clone())
+        }
+
+        fn save(&mut self, cursor: &FinalizedCursor) -> Result<(), &'static str> {
+            if self.fail {
+                return Err("disk full");
+            }
+            self.saved = Some(cursor.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn resumed_without_gaps_or_duplicates() {
+        let graph = fixture::random_gossip().build().unwrap().graph;
+        let expected: Vec<_> = graph.ordering.ordered().cloned().collect();
+        assert!(expected.len() > 10);
+        let path = std::env::temp_dir().join(format!("cursor-{}", std::process::id()));
+        let mut delivered = vec![];
+
+        let mut consumer = DurableCursor::open(FileCursorStore::new(&path)).unwrap();
+        for transaction in graph
+            .finalized_stream_from(consumer.cursor())
+            .unwrap()
+            .take(5)
+        {
+            let transaction = transaction.unwrap();
+            delivered.push(transaction.event.clone());
+            consumer.acknowledge(&transaction).unwrap();
+        }
+        // Restart
+        drop(consumer);
+        let mut consumer = DurableCursor::open(FileCursorStore::new(&path)).unwrap();
+        assert_eq!(consumer.cursor().position, 5);
+        for transaction in graph.finalized_stream_from(consumer.cursor()).unwrap() {
+            let transaction = transaction.unwrap();
+            delivered.push(transaction.event.clone());
+            consumer.acknowledge(&transaction).unwrap();
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(delivered, expected);
+
+        // Graph that hasn't caught up yet
+        let empty = fixture::random_gossip().build_geneses().unwrap().graph;
+        assert_eq!(
+            empty
+                .finalized_stream_from(consumer.cursor())
+                .unwrap()
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn misuse_detected() {
+        let graph = fixture::random_gossip().build().unwrap().graph;
+        let mut stream = graph
+            .finalized_stream_from(&FinalizedCursor::start())
+            .unwrap();
+        let first = stream.next().unwrap().unwrap();
+        let second = stream.next().unwrap().unwrap();
+
+        let mut consumer = DurableCursor::open(MemoryStore::default()).unwrap();
+        assert!(matches!(
+            consumer.acknowledge(&second),
+            Err(DurableCursorError::Cursor(CursorError::OutOfOrder {
+                expected: 0,
+                position: 1
+            }))
+        ));
+        consumer.store.fail = true;
+        assert!(matches!(
+            consumer.acknowledge(&first),
+            Err(DurableCursorError::Store("disk full"))
+        ));
+        assert_eq!(consumer.cursor(), &FinalizedCursor::start());
+
+        let diverged = FinalizedCursor {
+            position: 2,
+            last_event: Some(first.event.clone()),
+        };
+        assert!(matches!(
+            graph.finalized_stream_from(&diverged),
+            Err(CursorError::Diverged(1))
+        ));
+    }
+}
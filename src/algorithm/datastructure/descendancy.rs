@@ -0,0 +1,126 @@
+//! Which events the unique famous witnesses descend from.
+//!
+//! An event is received in the first round whose unique famous witnesses
+//! all descend from it, so finding the round received of each event asks
+//! "is `x` an ancestor of witness `w`" for many `x` and few `w`. Each such
+//! witness gets the highest sequence number of every author among its
+//! ancestors (indexed by [slot](super::headers::EventHeader::slot)), once,
+//! when the question is first asked about it. Without forks the ancestors
+//! of a witness by an author are a prefix of the author's chain, so the
+//! answer is a comparison with the watermark. Events at or above the first
+//! fork of their author are checked with a walk as before.
+//!
+//! Witnesses of later rounds descend from the ones of earlier rounds, so
+//! the walk that fills the watermarks of a witness stops at witnesses with
+//! watermarks and takes theirs.
+
+use std::collections::HashMap;
+
+use super::{Graph, UnknownEvent};
+use crate::algorithm::{core, event};
+
+/// `unique famous witness -> highest sequence of each author among its
+/// ancestors`, `None` for authors it doesn't descend from
+pub(super) type Watermarks = HashMap<event::Hash, Vec<Option<u64>>>;
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash,
+{
+    /// Whether `event` is an ancestor of the unique famous witness (or the
+    /// witness itself). Errors if any of them is unknown.
+    pub(super) fn witness_descends_from(
+        &self,
+        witness: &event::Hash,
+        event: &event::Hash,
+    ) -> Result<bool, UnknownEvent> {
+        let header = self
+            .headers
+            .get(event)
+            .ok_or_else(|| UnknownEvent(event.clone()))?;
+        if header.sequence >= self.fork_floor(&header.author) {
+            return self.is_ancestor(witness, event);
+        }
+        let mut watermarks = self.descendancy.lock().unwrap();
+        if !watermarks.contains_key(witness) {
+            let marks = self.ancestor_watermarks(witness, &watermarks)?;
+            watermarks.insert(witness.clone(), marks);
+        }
+        Ok(watermarks[witness]
+            .get(header.slot)
+            .copied()
+            .flatten()
+            .is_some_and(|mark| header.sequence <= mark))
+    }
+
+    fn ancestor_watermarks(
+        &self,
+        witness: &event::Hash,
+        known: &Watermarks,
+    ) -> Result<Vec<Option<u64>>, UnknownEvent> {
+        let mut marks = vec![None; self.peer_index.len()];
+        let mut raise = |slot: usize, sequence: u64| {
+            let mark = &mut marks[slot];
+            *mark = (*mark).max(Some(sequence));
+        };
+        let walk = core::bounded_ancestors(self, witness, |_: &Self, e: &event::Hash| {
+            !known.contains_key(e)
+        })
+        .ok_or_else(|| UnknownEvent(witness.clone()))?;
+        for hash in walk {
+            let header = &self.headers[hash];
+            raise(header.slot, header.sequence);
+            for parent in header
+                .parents
+                .iter()
+                .flat_map(|p| [&p.self_parent, &p.other_parent])
+            {
+                for (slot, mark) in known.get(parent).into_iter().flatten().enumerate() {
+                    if let Some(sequence) = mark {
+                        raise(slot, *sequence);
+                    }
+                }
+            }
+        }
+        Ok(marks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::fixture;
+
+    #[test]
+    fn watermarks_match_walks() {
+        for example in [fixture::fork(), fixture::random_gossip()] {
+            let graph = example.build().unwrap().graph;
+            let rounds: Vec<_> = (graph.round_index.base()..graph.round_index.len()).collect();
+            // Later rounds first: nothing to reuse. Earlier first: the walks
+            // stop at the previous witnesses.
+            for order in [
+                rounds.iter().rev().collect::<Vec<_>>(),
+                rounds.iter().collect(),
+            ] {
+                graph.descendancy.lock().unwrap().clear();
+                let mut checked = 0;
+                for round in order {
+                    let Ok(witnesses) = graph.round_unique_famous_witnesses(*round) else {
+                        continue;
+                    };
+                    for witness in witnesses {
+                        for event in graph.all_events.keys() {
+                            assert_eq!(
+                                graph.witness_descends_from(witness, event),
+                                graph.is_ancestor(witness, event)
+                            );
+                        }
+                        checked += 1;
+                    }
+                }
+                assert!(checked > 0);
+                assert_eq!(graph.descendancy.lock().unwrap().len(), checked);
+            }
+        }
+    }
+}
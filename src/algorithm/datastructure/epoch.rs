@@ -0,0 +1,507 @@
+//! Epochs of a fixed number of rounds, for bounding the memory of
+//! long-running networks.
+//!
+//! Once all rounds of an epoch are decided, the graph derives an
+//! [`EpochSummary`]: the [state hash](Graph::state_hash) at the end of the
+//! epoch, the members with their stakes ([`Graph::set_stake`]) and the
+//! unique famous witnesses of
+//! the epoch's last round. The authors of these
+//! witnesses sign the summary ([`Graph::sign_epoch`]), which makes the
+//! transition verifiable without the graph ([`SignedEpoch::verify`]). The
+//! state hash is kept up to date as rounds are decided, so completing an
+//! epoch doesn't rehash the history.
+//!
+//! Round numbering restarts at each epoch. [`EventInfo`](super::EventInfo)
+//! gives the epoch of an event and its rounds counted from the epoch's
+//! first round, [`Graph::epoch_round`] numbers any round the same way.
+//! Epochs have a fixed length, so all members agree on the numbers.
+//!
+//! [`Graph::prune`] drops finalized events of the epochs before the latest
+//! completed one. Pruning also rebases the per-round index of the graph on
+//! the first round of the latest epoch, so its size depends on the rounds
+//! since then rather than on the age of the network.
+
+use std::collections::{HashMap, HashSet};
+
+use blake2::{Blake2b512, Digest};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::Graph;
+use crate::algorithm::arith::Stake;
+use crate::algorithm::event::{self, Parents, Signature};
+use crate::algorithm::{RoundNum, Signer};
+
+/// State of the consensus at the end of an epoch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpochSummary<TPeerId> {
+    pub epoch: usize,
+    /// Number of events finalized by the end of the epoch
+    pub finalized: usize,
+    /// [`Graph::state_hash`] once the epoch's last round is decided
+    pub state_hash: event::Hash,
+    /// Members with their stakes, in the order of their geneses' hashes
+    pub members: Vec<(TPeerId, Stake)>,
+    /// Unique famous witnesses of the last round with their authors, in the
+    /// order of hashes
+    pub witnesses: Vec<(TPeerId, event::Hash)>,
+}
+
+impl<TPeerId: Serialize> EpochSummary<TPeerId> {
+    /// What the witnesses' authors sign
+    pub fn hash(&self) -> bincode::Result<event::Hash> {
+        let mut hasher = Blake2b512::new();
+        hasher.update(bincode::serialize(self)?);
+        let hash_slice = &hasher.finalize()[..];
+        Ok(event::Hash::from_array(
+            hash_slice.try_into().expect("blake2b512 gives 64 bytes"),
+        ))
+    }
+}
+
+/// Summary with signatures of the authors of its witnesses
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedEpoch<TPeerId> {
+    pub summary: EpochSummary<TPeerId>,
+    pub signatures: Vec<(TPeerId, Signature)>,
+}
+
+#[derive(Error, Debug)]
+pub enum EpochError<TPeerId> {
+    #[error("Author of witness {0:?} did not sign the summary")]
+    MissingSignature(TPeerId),
+    #[error("Summary is signed by {0:?}, who authored none of its witnesses")]
+    UnexpectedSigner(TPeerId),
+    #[error("Signer {0:?} is not a member")]
+    UnknownSigner(TPeerId),
+    #[error("Signature of {0:?} is invalid")]
+    BadSignature(TPeerId),
+    #[error("Summary can't be serialized: {0}")]
+    Encoding(#[from] bincode::Error),
+}
+
+impl<TPeerId> SignedEpoch<TPeerId>
+where
+    TPeerId: Serialize + Eq + std::hash::Hash + Clone,
+{
+    /// Check that exactly the authors of the summary's witnesses signed it.
+    /// `members` are the members of the previous epoch with their genesis
+    /// payloads.
+    pub fn verify<TGenesisPayload, TSigner>(
+        &self,
+        members: &HashMap<TPeerId, TGenesisPayload>,
+        signer: &TSigner,
+    ) -> Result<(), EpochError<TPeerId>>
+    where
+        TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+    {
+        let hash = self.summary.hash()?;
+        let authors: HashSet<_> = self.summary.witnesses.iter().map(|(a, _)| a).collect();
+        let mut signed = HashSet::new();
+        for (member, signature) in &self.signatures {
+            if !authors.contains(member) {
+                return Err(EpochError::UnexpectedSigner(member.clone()));
+            }
+            let genesis_payload = members
+                .get(member)
+                .ok_or_else(|| EpochError::UnknownSigner(member.clone()))?;
+            if !signer.verify(&hash, signature, member, genesis_payload) {
+                return Err(EpochError::BadSignature(member.clone()));
+            }
+            signed.insert(member);
+        }
+        match authors.into_iter().find(|a| !signed.contains(a)) {
+            Some(missing) => Err(EpochError::MissingSignature(missing.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash + Clone,
+{
+    /// Complete an epoch every `rounds` decided rounds. `None` (the default)
+    /// disables epochs. Expected to be set before any round is decided.
+    ///
+    /// # Panics
+    /// If `rounds` is 0.
+    pub fn set_epoch_length(&mut self, rounds: Option<usize>) {
+        assert_ne!(rounds, Some(0), "Epochs need at least one round");
+        self.round_index.set_epoch_length(rounds);
+    }
+
+    /// Stake of the member in the following [epoch summaries](EpochSummary).
+    /// Members without a stake set have [`Stake::UNIT`].
+    pub fn set_stake(&mut self, member: TPeerId, stake: Stake) {
+        self.stakes.insert(member, stake);
+    }
+
+    /// Summaries of the completed epochs, oldest first
+    pub fn epochs(&self) -> &[EpochSummary<TPeerId>] {
+        &self.epochs
+    }
+
+    /// Epoch of the round and the round's number within it, `None` if
+    /// epochs are disabled
+    pub fn epoch_round(&self, round: RoundNum) -> Option<(usize, RoundNum)> {
+        self.round_index
+            .epoch_length()
+            .map(|_| self.round_index.number(round))
+    }
+
+    /// Derive the summary if `decided_round` completes an epoch. Called once
+    /// the events the round orders are added to the ordering.
+    pub(super) fn complete_epoch(&mut self, decided_round: RoundNum) {
+        let Some(length) = self.round_index.epoch_length() else {
+            return;
+        };
+        if !(decided_round + 1).is_multiple_of(length) {
+            return;
+        }
+        let witnesses = self
+            .round_unique_famous_witnesses(decided_round)
+            .expect("Ordering rounds are decided")
+            .into_iter()
+            .sorted()
+            .map(|w| (self.all_events[w].author().clone(), w.clone()))
+            .collect();
+        let members = self
+            .peer_index
+            .iter()
+            .sorted_by(|(_, a), (_, b)| a.origin().cmp(b.origin()))
+            .map(|(peer, _)| {
+                let stake = self.stakes.get(peer).copied().unwrap_or(Stake::UNIT);
+                (peer.clone(), stake)
+            })
+            .collect();
+        let summary = EpochSummary {
+            epoch: decided_round / length,
+            finalized: self.ordering.len(),
+            state_hash: self.state_hash(),
+            members,
+            witnesses,
+        };
+        self.epochs.push(summary);
+    }
+
+    /// Drop events finalized in the epochs before the latest completed one.
+    /// Only events already returned by
+    /// [`next_finalized_event`](Self::next_finalized_event) are dropped,
+    /// geneses and the latest events of each member are kept.
+    ///
+    /// Returns the number of dropped events. Pruned events are forgotten
+    /// completely, so peers that lag behind by more than an epoch can't be
+    /// synced from this graph anymore. The pruned rounds are dropped from
+    /// the round index: they list no events, including the kept ones.
+    pub fn prune(&mut self) -> usize {
+        let Some(bound) = self
+            .epochs
+            .last()
+            .map(|e| self.round_index.epoch_start(e.epoch))
+        else {
+            return 0;
+        };
+        if bound <= self.pruned_below {
+            return 0;
+        }
+        let kept = self.chain_ends();
+        let doomed: Vec<_> = self
+            .ordering
+            .delivered()
+            .filter(|e| !kept.contains(*e) && self.round_of.get(*e).is_some_and(|&r| r < bound))
+            .cloned()
+            .collect();
+        for hash in &doomed {
+            self.forget_event(hash);
+        }
+        // The index won't list them, compaction has to know them
+        let unindexed = kept
+            .into_iter()
+            .filter(|e| self.round_of.get(e).is_some_and(|&r| r < bound))
+            .filter(|e| !self.compacted.contains(e));
+        self.compaction_kept.extend(unindexed);
+        self.round_index.prune_below(bound);
+        self.pruned_below = bound;
+        doomed.len()
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash,
+{
+    /// Geneses and the latest events of each member, never dropped
+    pub(super) fn chain_ends(&self) -> HashSet<event::Hash> {
+        self.peer_index
+            .values()
+            .flat_map(|entry| std::iter::once(entry.origin()).chain(entry.latest_events()))
+            .cloned()
+            .collect()
+    }
+
+    /// Remove the event and everything known about it. Kept parents (e.g.
+    /// geneses) don't point to it anymore, its children are left as they
+    /// are.
+    pub(super) fn forget_event(&mut self, hash: &event::Hash) {
+        let Some(event) = self.all_events.remove(hash) else {
+            return;
+        };
+        self.headers.remove(hash);
+        if let Some(events) = self
+            .round_of
+            .remove(hash)
+            .and_then(|round| self.round_index.get_mut(round))
+        {
+            events.remove(hash);
+        }
+        self.witnesses.get_mut().unwrap().remove(hash);
+        self.ordering_data_cache.get_mut().unwrap().remove(hash);
+        self.descendancy.get_mut().unwrap().remove(hash);
+        self.redacted.remove(hash);
+        self.compacted.remove(hash);
+        self.compaction_kept.remove(hash);
+        self.annotations.remove(hash);
+        self.provenance.forget(hash);
+        if let Some(timings) = self.timings.as_mut() {
+            timings.remove(hash);
+        }
+        // Any peer may know the event, not only its author
+        for entry in self.peer_index.values_mut() {
+            entry.forget(hash);
+        }
+        if let event::Kind::Regular(Parents {
+            self_parent,
+            other_parent,
+        }) = event.kind()
+        {
+            if let Some(parent) = self.all_events.get_mut(self_parent) {
+                parent.children.self_child =
+                    parent.children.self_child.clone().with_child_removed(hash);
+            }
+            if let Some(parent) = self.all_events.get_mut(other_parent) {
+                parent.children.other_children.retain(|c| c != hash);
+            }
+        }
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+{
+    /// Whether events were dropped by [`prune`](Graph::prune) or
+    /// [`compact`](Graph::compact), so parents may be missing
+    pub(super) fn events_dropped(&self) -> bool {
+        self.pruned_below > 0 || self.compacted_below > 0
+    }
+
+    /// Whether the event is known, but some of its parents were pruned
+    pub(super) fn parents_pruned(&self, event: &event::Hash) -> bool {
+        match self.all_events.get(event).map(|e| e.kind()) {
+            Some(event::Kind::Regular(Parents {
+                self_parent,
+                other_parent,
+            })) => {
+                !self.all_events.contains_key(self_parent)
+                    || !self.all_events.contains_key(other_parent)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Serialize + Eq + std::hash::Hash + Clone,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+{
+    /// Our signature of the summary, `None` if we authored none of its
+    /// witnesses or are an observer
+    pub fn sign_epoch(
+        &self,
+        summary: &EpochSummary<TPeerId>,
+    ) -> bincode::Result<Option<(TPeerId, Signature)>> {
+        let Some(self_id) = &self.self_id else {
+            return Ok(None);
+        };
+        if !summary.witnesses.iter().any(|(a, _)| a == self_id) {
+            return Ok(None);
+        }
+        let signature = self.signer.sign(&summary.hash()?);
+        Ok(Some((self_id.clone(), signature)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::strategy::MostNewEvents;
+    use crate::algorithm::MockSigner;
+    use crate::testing::conformance::random_gossip;
+    use crate::testing::fixture::{self, Fixture};
+    use crate::testing::{seed_from_env, BuiltGraph};
+
+    const PEERS: usize = 4;
+
+    /// Replay the events of `reference` into a graph with epochs of
+    /// `length` rounds, pruning as it goes
+    fn replay_pruning(
+        reference: &BuiltGraph<(), u64>,
+        example: &Fixture,
+        length: usize,
+    ) -> Vec<event::Hash> {
+        let mut graph = example.build_geneses().unwrap().graph;
+        graph.set_epoch_length(Some(length));
+        let mut finalized = vec![];
+        let mut pruned = 0;
+        fixture::replay(reference, &example.events, &mut graph, |graph, _| {
+            while let Some(e) = graph.next_finalized_event() {
+                finalized.push(e.hash().clone());
+            }
+            pruned += graph.prune();
+            assert_eq!(graph.check_consistency(), Ok(()));
+        });
+        assert!(graph.epochs().len() >= 3, "{} epochs", graph.epochs().len());
+        assert!(pruned > 0);
+        assert!(graph.all_events.len() < example.events.len() / 2);
+        // Only the rounds since the latest epoch are indexed
+        let latest = graph.epochs().last().unwrap();
+        assert_eq!(
+            graph.round_index.number(graph.round_index.base()),
+            (latest.epoch, 0)
+        );
+        let indexed = graph.round_index.iter().count();
+        assert!(indexed <= 3 * length, "{indexed} rounds indexed");
+        assert_eq!(graph.verify_integrity(), vec![]);
+        // Nobody knows more events than are left
+        for index in graph.peer_index.values() {
+            assert!(index.known_events().len() <= graph.all_events.len());
+        }
+        assert_eq!(graph.peer_candidates().len(), PEERS - 1);
+        graph.create_event_with((), &mut MostNewEvents).unwrap();
+        for peer in 0..PEERS as u64 {
+            // Lanes go on after the pruned part
+            let lane = graph.peer_lane(&peer).unwrap();
+            assert_eq!(
+                lane.last().unwrap().hash(),
+                graph.peer_latest_event(&peer).unwrap()
+            );
+        }
+        finalized
+    }
+
+    #[test]
+    fn pruning_keeps_consensus() {
+        // Long enough for several epochs
+        let example = random_gossip(seed_from_env(5), PEERS, 400);
+        let mut reference = example.build().unwrap();
+        let expected: Vec<_> = std::iter::from_fn(|| {
+            reference
+                .graph
+                .next_finalized_event()
+                .map(|e| e.hash().clone())
+        })
+        .collect();
+        let finalized = replay_pruning(&reference, &example, 3);
+        assert_eq!(finalized, expected);
+    }
+
+    #[test]
+    fn late_event_of_pruned_round() {
+        let example = random_gossip(seed_from_env(5), PEERS, 200);
+        let reference = example.build().unwrap();
+        let mut graph = example.build_geneses().unwrap().graph;
+        graph.set_epoch_length(Some(2));
+        let (before, after) = example.events.split_at(example.events.len() / 2);
+        fixture::replay(&reference, before, &mut graph, |graph, _| {
+            while graph.next_finalized_event().is_some() {}
+            graph.prune();
+        });
+        assert!(graph.round_index.base() > 0);
+
+        // A fork on a genesis lands in round 0, long pruned
+        let late = event::SignedEvent::new(
+            (),
+            event::Kind::Regular(Parents {
+                self_parent: graph.peer_genesis(&1).unwrap().clone(),
+                other_parent: graph.peer_genesis(&2).unwrap().clone(),
+            }),
+            1,
+            1,
+            |h| MockSigner::<u64, ()>::new().sign(h),
+        )
+        .unwrap();
+        let hash = late.hash().clone();
+        let (unsigned, signature) = late.into_parts();
+        graph.push_event(unsigned, signature).unwrap();
+        assert_eq!(graph.event_info(&hash).unwrap().round, 0);
+        assert_eq!(graph.event_info(&hash).unwrap().round_received, None);
+
+        // Later rounds are still ordered
+        let ordered = graph.ordering.len();
+        fixture::replay(&reference, after, &mut graph, |graph, _| {
+            graph.prune();
+        });
+        assert!(graph.ordering.len() > ordered);
+        assert_eq!(graph.check_consistency(), Ok(()));
+    }
+
+    #[test]
+    fn epochs_summarized_and_signed() {
+        let example = random_gossip(seed_from_env(5), PEERS, 150);
+        let built = example.build().unwrap();
+        // Summaries are derived as rounds get decided, so replay
+        let mut graph = example.build_geneses().unwrap().graph;
+        graph.set_epoch_length(Some(2));
+        graph.set_stake(1, Stake(5));
+        fixture::replay(&built, &example.events, &mut graph, |_, _| {});
+        let summaries = graph.epochs();
+        assert!(summaries.len() >= 2);
+        for (i, summary) in summaries.iter().enumerate() {
+            assert_eq!(summary.epoch, i);
+            assert_eq!(summary.members.len(), PEERS);
+            for (member, stake) in &summary.members {
+                let expected = if *member == 1 { Stake(5) } else { Stake::UNIT };
+                assert_eq!(*stake, expected);
+            }
+            assert!(!summary.witnesses.is_empty());
+        }
+        assert!(summaries
+            .windows(2)
+            .all(|w| w[0].finalized <= w[1].finalized));
+        assert_eq!(graph.epoch_round(5), Some((2, 1)));
+        // Events report rounds within their epochs
+        let (hash, _) = graph.round_of.iter().find(|(_, &r)| r == 5).unwrap();
+        let info = graph.event_info(hash).unwrap();
+        assert_eq!((info.epoch, info.round), (2, 1));
+        assert!(info.round_received.is_none_or(|r| r > 1));
+
+        // Every author of a witness signs, as if each hosted the graph
+        let summary = summaries.last().unwrap().clone();
+        let signer = MockSigner::<u64, ()>::new();
+        let hash = summary.hash().unwrap();
+        let mut signed = SignedEpoch {
+            signatures: summary
+                .witnesses
+                .iter()
+                .map(|(author, _)| (*author, signer.sign(&hash)))
+                .collect(),
+            summary,
+        };
+        let members: HashMap<_, _> = (0..PEERS as u64).map(|p| (p, ())).collect();
+        signed.verify(&members, &signer).unwrap();
+        let own = graph.sign_epoch(&signed.summary).unwrap();
+        let owner_is_witness = signed.summary.witnesses.iter().any(|(a, _)| *a == 0);
+        assert_eq!(own.is_some(), owner_is_witness);
+
+        let (missing, _) = signed.signatures.pop().unwrap();
+        assert!(matches!(
+            signed.verify(&members, &signer),
+            Err(EpochError::MissingSignature(m)) if m == missing
+        ));
+    }
+}
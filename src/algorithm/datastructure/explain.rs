@@ -0,0 +1,284 @@
+//! Evidence for the place of an event in the consensus order.
+//!
+//! Finalized events are sorted by round received, then by consensus
+//! timestamp (computed from the events through which the unique famous
+//! witnesses of that round first saw it, see
+//! [`timestamping`](super::timestamping)), then by whitened signature
+//! (signature XOR signatures of the unique famous witnesses).
+//! [`Graph::ordering_explanation`] gives all of these inputs for an event and
+//! its neighbours in the order, so that anyone holding the same events can
+//! check the decision. The report is serializable, e.g. to JSON.
+
+use serde::Serialize;
+use thiserror::Error;
+
+use super::ordering::OrderedEvents;
+use super::{Graph, OrderingDataError, UnknownEvent};
+use crate::algorithm::{event, RoundNum};
+use crate::Timestamp;
+
+/// Event through which a unique famous witness first saw the explained one
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TimestampInput<TPeerId> {
+    /// The unique famous witness
+    pub witness: event::Hash,
+    /// Its earliest self ancestor that sees the explained event
+    pub receiver: event::Hash,
+    pub author: TPeerId,
+    pub timestamp: Timestamp,
+    pub round: RoundNum,
+}
+
+/// Which sort key told two events apart
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderCriterion {
+    RoundReceived,
+    ConsensusTimestamp,
+    WhitenedSignature,
+}
+
+/// Sort keys of a neighbour in the order
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Neighbour {
+    pub event: event::Hash,
+    pub round_received: RoundNum,
+    pub consensus_timestamp: Timestamp,
+    pub whitened_signature: event::Signature,
+    /// First key that differs from the explained event's
+    pub decided_by: OrderCriterion,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OrderingExplanation<TPeerId> {
+    pub event: event::Hash,
+    /// Among all finalized events
+    pub position: usize,
+    pub round_received: RoundNum,
+    /// Unique famous witnesses of `round_received`
+    pub unique_famous_witnesses: Vec<event::Hash>,
+    /// Sorted by timestamp
+    pub timestamp_inputs: Vec<TimestampInput<TPeerId>>,
+    /// Given by the timestamp strategy of the graph, by default the median
+    /// of the inputs' timestamps
+    pub consensus_timestamp: Timestamp,
+    pub signature: event::Signature,
+    pub whitened_signature: event::Signature,
+    /// `None` for the first event or if the neighbour was pruned
+    pub previous: Option<Neighbour>,
+    /// `None` for the latest finalized event or if the neighbour was pruned
+    pub next: Option<Neighbour>,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ExplainError {
+    #[error("Event {0} is unknown")]
+    UnknownEvent(event::Hash),
+    #[error("Event {0} is not finalized yet")]
+    NotFinalized(event::Hash),
+    #[error("Round {0} that received the event was pruned or compacted")]
+    Pruned(RoundNum),
+}
+
+impl From<UnknownEvent> for ExplainError {
+    fn from(UnknownEvent(hash): UnknownEvent) -> Self {
+        Self::UnknownEvent(hash)
+    }
+}
+
+struct SortKeys {
+    round_received: RoundNum,
+    consensus_timestamp: Timestamp,
+    signature: event::Signature,
+    whitened_signature: event::Signature,
+}
+
+impl SortKeys {
+    fn decided_by(&self, other: &SortKeys) -> OrderCriterion {
+        if self.round_received != other.round_received {
+            OrderCriterion::RoundReceived
+        } else if self.consensus_timestamp != other.consensus_timestamp {
+            OrderCriterion::ConsensusTimestamp
+        } else {
+            OrderCriterion::WhitenedSignature
+        }
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash + Clone,
+{
+    /// Why the finalized event is where it is in the consensus order, see
+    /// the [module docs](self)
+    pub fn ordering_explanation(
+        &self,
+        event_hash: &event::Hash,
+    ) -> Result<OrderingExplanation<TPeerId>, ExplainError> {
+        let keys = self.sort_keys(event_hash)?;
+        let position = self
+            .ordering
+            .ordered()
+            .position(|h| h == event_hash)
+            .ok_or_else(|| ExplainError::NotFinalized(event_hash.clone()))?;
+        let mut unique_famous_witnesses: Vec<_> = self
+            .round_unique_famous_witnesses(keys.round_received)
+            .expect("round received is decided")
+            .into_iter()
+            .cloned()
+            .collect();
+        unique_famous_witnesses.sort();
+        let mut timestamp_inputs: Vec<_> = unique_famous_witnesses
+            .iter()
+            .zip(self.receivers(&unique_famous_witnesses, event_hash)?)
+            .map(|(witness, receiver)| TimestampInput {
+                witness: witness.clone(),
+                receiver: receiver.hash().clone(),
+                author: receiver.author().clone(),
+                timestamp: *receiver.timestamp(),
+                round: self.round_of(receiver.hash()),
+            })
+            .collect();
+        timestamp_inputs.sort_by(|a, b| {
+            a.timestamp
+                .cmp(&b.timestamp)
+                .then_with(|| a.witness.cmp(&b.witness))
+        });
+        let neighbour = |hash: Option<&event::Hash>| {
+            let hash = hash?;
+            let other = self.sort_keys(hash).ok()?;
+            Some(Neighbour {
+                event: hash.clone(),
+                decided_by: keys.decided_by(&other),
+                round_received: other.round_received,
+                consensus_timestamp: other.consensus_timestamp,
+                whitened_signature: other.whitened_signature,
+            })
+        };
+        let previous = match position {
+            0 => None,
+            _ => neighbour(self.ordering.ordered().nth(position - 1)),
+        };
+        let next = neighbour(self.ordering.ordered().nth(position + 1));
+        Ok(OrderingExplanation {
+            event: event_hash.clone(),
+            position,
+            round_received: keys.round_received,
+            unique_famous_witnesses,
+            timestamp_inputs,
+            consensus_timestamp: keys.consensus_timestamp,
+            signature: keys.signature,
+            whitened_signature: keys.whitened_signature,
+            previous,
+            next,
+        })
+    }
+
+    fn sort_keys(&self, event_hash: &event::Hash) -> Result<SortKeys, ExplainError> {
+        let (round_received, consensus_timestamp, signature) = match self.ordering_data(event_hash)
+        {
+            Ok(data) => data,
+            Err(OrderingDataError::UnknownEvent(_)) => {
+                return Err(ExplainError::UnknownEvent(event_hash.clone()))
+            }
+            Err(OrderingDataError::Undecided) => {
+                return Err(ExplainError::NotFinalized(event_hash.clone()))
+            }
+            Err(OrderingDataError::Pruned) => {
+                return Err(ExplainError::Pruned(self.round_of(event_hash)))
+            }
+        };
+        if round_received < self.pruned_below.max(self.compacted_below) {
+            return Err(ExplainError::Pruned(round_received));
+        }
+        let ufw_signatures = self
+            .round_unique_famous_witnesses(round_received)
+            .expect("round received is decided")
+            .into_iter()
+            .map(|ufw| {
+                self.all_events
+                    .get(ufw)
+                    .expect("witnesses must be tracked")
+                    .signature()
+                    .clone()
+            });
+        let whitened_signature = &signature ^ &OrderedEvents::combine_sigs_xor(ufw_signatures);
+        Ok(SortKeys {
+            round_received,
+            consensus_timestamp,
+            signature,
+            whitened_signature,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture;
+
+    #[test]
+    fn explanations_match_the_order() {
+        let built = fixture::detailed_example().build().unwrap();
+        let graph = &built.graph;
+        let ordered: Vec<_> = graph.ordering.ordered().cloned().collect();
+        assert!(ordered.len() > 1);
+        for (position, hash) in ordered.iter().enumerate() {
+            let explanation = graph.ordering_explanation(hash).unwrap();
+            assert_eq!(explanation.position, position);
+            assert_eq!(
+                Some(explanation.round_received),
+                graph.event_info(hash).unwrap().round_received
+            );
+            let inputs = &explanation.timestamp_inputs;
+            assert_eq!(inputs.len(), explanation.unique_famous_witnesses.len());
+            assert_eq!(
+                inputs[inputs.len() / 2].timestamp,
+                explanation.consensus_timestamp
+            );
+            let key = (
+                explanation.round_received,
+                explanation.consensus_timestamp,
+                &explanation.whitened_signature,
+            );
+            if let Some(previous) = &explanation.previous {
+                assert_eq!(previous.event, ordered[position - 1]);
+                let previous_key = (
+                    previous.round_received,
+                    previous.consensus_timestamp,
+                    &previous.whitened_signature,
+                );
+                assert!(previous_key < key);
+            } else {
+                assert_eq!(position, 0);
+            }
+            if let Some(next) = &explanation.next {
+                let next_explanation = graph.ordering_explanation(&next.event).unwrap();
+                assert_eq!(
+                    next_explanation.previous.unwrap().decided_by,
+                    next.decided_by
+                );
+            } else {
+                assert_eq!(position, ordered.len() - 1);
+            }
+        }
+        let json = serde_json::to_value(graph.ordering_explanation(&ordered[0]).unwrap()).unwrap();
+        assert_eq!(json["position"], 0);
+    }
+
+    #[test]
+    fn unordered_events_rejected() {
+        let built = fixture::detailed_example().build().unwrap();
+        let graph = &built.graph;
+        let latest = graph.self_tip().unwrap();
+        assert_eq!(
+            graph.ordering_explanation(latest),
+            Err(ExplainError::NotFinalized(latest.clone()))
+        );
+        let unknown = event::Hash::from_array([1; 64]);
+        assert_eq!(
+            graph.ordering_explanation(&unknown),
+            Err(ExplainError::UnknownEvent(unknown))
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! CSV table of consensus timings, one row per event, for offline analysis
+//! (loads directly into pandas, polars, spreadsheets etc.).
+//!
+//! Columns:
+//! - `hash`: full hash in hex;
+//! - `author`: debug representation of the author;
+//! - `created_at`: timestamp set by the author;
+//! - `inserted_at`: local time of insertion into the graph;
+//! - `round`;
+//! - `round_received`;
+//! - `finalized_at`: local time the event got its place in the ordering;
+//! - `finalization_latency`: `finalized_at - inserted_at`.
+//!
+//! Unknown values are left empty. Local times are only known if timings
+//! were collected, see [`Graph::set_collect_timings`].
+
+use std::fmt::Debug;
+use std::io::{self, Write};
+
+use super::ExportFilter;
+use crate::algorithm::datastructure::Graph;
+
+const HEADER: &str =
+    "hash,author,created_at,inserted_at,round,round_received,finalized_at,finalization_latency";
+
+/// Quote the field if it has anything that breaks the row structure
+fn escape(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash + Clone + Debug,
+{
+    pub fn export_csv(&self, filter: &ExportFilter<TPeerId>) -> String {
+        let mut out = vec![];
+        self.write_csv(filter, &mut out)
+            .expect("writing to a vector doesn't fail");
+        String::from_utf8(out).expect("all fields are valid UTF-8")
+    }
+
+    pub fn write_csv<W: Write>(
+        &self,
+        filter: &ExportFilter<TPeerId>,
+        mut writer: W,
+    ) -> io::Result<()> {
+        writeln!(writer, "{}", HEADER)?;
+        for e in self.exported_events(filter) {
+            let info = &e.info;
+            let timings = self.event_timings(&info.hash);
+            let inserted_at = timings.map(|t| t.inserted_at);
+            let finalized_at = timings.and_then(|t| t.finalized_at);
+            let latency = inserted_at
+                .zip(finalized_at)
+                .map(|(inserted, finalized)| finalized.saturating_sub(inserted));
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{}",
+                info.hash.to_hex(),
+                escape(&format!("{:?}", info.author)),
+                info.timestamp,
+                optional(inserted_at),
+                info.round,
+                optional(info.round_received),
+                optional(finalized_at),
+                optional(latency),
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+//! [Graphviz](https://graphviz.org/) DOT output.
+//!
+//! Time flows upwards, like in the paper. Events are drawn as
+//! - ellipses if they are not witnesses,
+//! - diamonds for witnesses with undecided fame,
+//! - double circles for famous witnesses,
+//! - circles for witnesses that are not famous.
+//!
+//! Self parent edges are solid, other parent edges are dashed.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Write};
+
+use super::{exported_edges, ExportFilter};
+use crate::algorithm::datastructure::{Graph, WitnessFamousness};
+
+/// Fill colors, assigned to rounds in turns
+const ROUND_COLORS: [&str; 8] = [
+    "#8dd3c7", "#ffffb3", "#bebada", "#fb8072", "#80b1d3", "#fdb462", "#b3de69", "#fccde5",
+];
+const FORK_COLOR: &str = "red";
+
+#[derive(Debug, Clone)]
+pub struct DotOptions<TPeerId> {
+    /// Put events of each peer in a separate cluster (column)
+    pub cluster_by_peer: bool,
+    /// Fill events with color of their round
+    pub color_by_round: bool,
+    /// Draw events that are part of a fork with a thick red border
+    pub highlight_forks: bool,
+    /// Add author's timestamp to labels
+    pub show_timestamps: bool,
+    pub filter: ExportFilter<TPeerId>,
+}
+
+impl<TPeerId> Default for DotOptions<TPeerId> {
+    fn default() -> Self {
+        Self {
+            cluster_by_peer: true,
+            color_by_round: true,
+            highlight_forks: true,
+            show_timestamps: false,
+            filter: ExportFilter::default(),
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash + Clone + Debug,
+{
+    /// Render the graph in DOT format. Node ids are full event hashes in hex,
+    /// labels contain the compact hash and the round.
+    pub fn to_dot(&self, options: &DotOptions<TPeerId>) -> String {
+        let events = self.exported_events(&options.filter);
+        let mut out = String::new();
+        writeln!(out, "digraph hashgraph {{").unwrap();
+        writeln!(out, "    rankdir=BT;").unwrap();
+        writeln!(out, "    node [style=filled, fillcolor=white];").unwrap();
+
+        if options.cluster_by_peer {
+            let mut peers: Vec<&TPeerId> = vec![];
+            let mut peer_events: HashMap<&TPeerId, Vec<String>> = HashMap::new();
+            for e in &events {
+                let entry = peer_events.entry(&e.info.author).or_insert_with(|| {
+                    peers.push(&e.info.author);
+                    vec![]
+                });
+                entry.push(e.info.hash.to_hex());
+            }
+            for (i, peer) in peers.into_iter().enumerate() {
+                writeln!(out, "    subgraph cluster_{} {{", i).unwrap();
+                writeln!(out, "        label=\"{}\";", escape(&format!("{:?}", peer))).unwrap();
+                for hash in &peer_events[peer] {
+                    writeln!(out, "        \"{}\";", hash).unwrap();
+                }
+                writeln!(out, "    }}").unwrap();
+            }
+        }
+
+        for e in &events {
+            let info = &e.info;
+            let shape = match info.witness {
+                None => "ellipse",
+                Some(WitnessFamousness::Undecided) => "diamond",
+                Some(WitnessFamousness::Yes) => "doublecircle",
+                Some(WitnessFamousness::No) => "circle",
+            };
+            let mut label = format!("{}\\nr{}", info.hash.to_compact_hex(), info.round);
+            if options.show_timestamps {
+                write!(label, "\\nt{}", info.timestamp).unwrap();
+            }
+            let mut attributes = vec![format!("label=\"{}\"", label), format!("shape={}", shape)];
+            if options.color_by_round {
+                let color = ROUND_COLORS[info.round % ROUND_COLORS.len()];
+                attributes.push(format!("fillcolor=\"{}\"", color));
+            }
+            if options.highlight_forks && e.in_fork {
+                attributes.push(format!("color={}", FORK_COLOR));
+                attributes.push("penwidth=3".to_owned());
+            }
+            writeln!(
+                out,
+                "    \"{}\" [{}];",
+                info.hash.to_hex(),
+                attributes.join(", ")
+            )
+            .unwrap();
+        }
+
+        for (parent, child, is_self_parent) in exported_edges(&events) {
+            let style = if is_self_parent {
+                ""
+            } else {
+                " [style=dashed]"
+            };
+            writeln!(
+                out,
+                "    \"{}\" -> \"{}\"{};",
+                parent.to_hex(),
+                child.to_hex(),
+                style
+            )
+            .unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
@@ -0,0 +1,95 @@
+//! [GraphML](http://graphml.graphdrawing.org/) output, e.g. for analysis of
+//! large graphs in Gephi.
+//!
+//! Node ids are full event hashes in hex. Node attributes: `author` (debug
+//! representation), `round`, `timestamp` (string, as it doesn't fit into
+//! GraphML numeric types), `witness` (`undecided`/`famous`/`not_famous`,
+//! absent for non-witnesses), `round_received` (absent if undecided),
+//! `in_fork`. Edges go from parent to child and have `parent` attribute set
+//! to `self` or `other`.
+
+use std::fmt::{Debug, Write};
+
+use super::{exported_edges, ExportFilter};
+use crate::algorithm::datastructure::{Graph, WitnessFamousness};
+
+const KEYS: [(&str, &str, &str); 7] = [
+    ("author", "node", "string"),
+    ("round", "node", "long"),
+    ("timestamp", "node", "string"),
+    ("witness", "node", "string"),
+    ("round_received", "node", "long"),
+    ("in_fork", "node", "boolean"),
+    ("parent", "edge", "string"),
+];
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash + Clone + Debug,
+{
+    pub fn to_graphml(&self, filter: &ExportFilter<TPeerId>) -> String {
+        let events = self.exported_events(filter);
+        let mut out = String::new();
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+        writeln!(
+            out,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )
+        .unwrap();
+        for (name, domain, kind) in KEYS {
+            writeln!(
+                out,
+                r#"  <key id="{name}" for="{domain}" attr.name="{name}" attr.type="{kind}"/>"#
+            )
+            .unwrap();
+        }
+        writeln!(out, r#"  <graph id="hashgraph" edgedefault="directed">"#).unwrap();
+        for e in &events {
+            let info = &e.info;
+            writeln!(out, r#"    <node id="{}">"#, info.hash.to_hex()).unwrap();
+            let mut data = vec![
+                ("author", escape(&format!("{:?}", info.author))),
+                ("round", info.round.to_string()),
+                ("timestamp", info.timestamp.to_string()),
+            ];
+            if let Some(fame) = &info.witness {
+                let fame = match fame {
+                    WitnessFamousness::Undecided => "undecided",
+                    WitnessFamousness::Yes => "famous",
+                    WitnessFamousness::No => "not_famous",
+                };
+                data.push(("witness", fame.to_owned()));
+            }
+            if let Some(round_received) = info.round_received {
+                data.push(("round_received", round_received.to_string()));
+            }
+            data.push(("in_fork", e.in_fork.to_string()));
+            for (key, value) in data {
+                writeln!(out, r#"      <data key="{key}">{value}</data>"#).unwrap();
+            }
+            writeln!(out, "    </node>").unwrap();
+        }
+        for (parent, child, is_self_parent) in exported_edges(&events) {
+            let kind = if is_self_parent { "self" } else { "other" };
+            writeln!(
+                out,
+                r#"    <edge source="{}" target="{}"><data key="parent">{}</data></edge>"#,
+                parent.to_hex(),
+                child.to_hex(),
+                kind
+            )
+            .unwrap();
+        }
+        writeln!(out, "  </graph>").unwrap();
+        writeln!(out, "</graphml>").unwrap();
+        out
+    }
+}
@@ -0,0 +1,157 @@
+//! JSON description of the graph structure, for explorers and offline
+//! analysis. Payloads and signatures are not included.
+//!
+//! The schema is versioned by [`JSON_SCHEMA_VERSION`]; fields are only ever
+//! added within a version. A document looks like
+//! ```json
+//! {
+//!   "version": 1,
+//!   "self_id": 0,
+//!   "events": [
+//!     {
+//!       "hash": "<128 hex digits>",
+//!       "author": 0,
+//!       "timestamp": 0,
+//!       "self_parent": null,
+//!       "other_parent": null,
+//!       "round": 0,
+//!       "witness": "famous",
+//!       "round_received": 1,
+//!       "in_fork": false
+//!     }
+//!   ],
+//!   "ordering": ["<hash>", "<hash>"]
+//! }
+//! ```
+//! `self_id` is `null` for [observers](crate::algorithm::datastructure::observer).
+//! `witness` is one of `"undecided"`, `"famous"`, `"not_famous"` or `null`
+//! for non-witnesses. `ordering` lists finalized events in consensus order.
+//! Authors are serialized with their `Serialize` implementation.
+
+use std::fmt::Debug;
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+
+use super::{ExportFilter, ExportedEvent};
+use crate::algorithm::datastructure::{Graph, WitnessFamousness};
+use crate::algorithm::RoundNum;
+use crate::Timestamp;
+
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JsonFame {
+    Undecided,
+    Famous,
+    NotFamous,
+}
+
+impl From<&WitnessFamousness> for JsonFame {
+    fn from(value: &WitnessFamousness) -> Self {
+        match value {
+            WitnessFamousness::Undecided => JsonFame::Undecided,
+            WitnessFamousness::Yes => JsonFame::Famous,
+            WitnessFamousness::No => JsonFame::NotFamous,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JsonEvent<TPeerId> {
+    pub hash: String,
+    pub author: TPeerId,
+    pub timestamp: Timestamp,
+    /// `None` for geneses
+    pub self_parent: Option<String>,
+    /// `None` for geneses
+    pub other_parent: Option<String>,
+    pub round: RoundNum,
+    /// `None` if the event is not a witness
+    pub witness: Option<JsonFame>,
+    pub round_received: Option<RoundNum>,
+    pub in_fork: bool,
+}
+
+impl<TPeerId: Clone> From<&ExportedEvent<TPeerId>> for JsonEvent<TPeerId> {
+    fn from(value: &ExportedEvent<TPeerId>) -> Self {
+        let info = &value.info;
+        JsonEvent {
+            hash: info.hash.to_hex(),
+            author: info.author.clone(),
+            timestamp: info.timestamp,
+            self_parent: info.parents.as_ref().map(|p| p.self_parent.to_hex()),
+            other_parent: info.parents.as_ref().map(|p| p.other_parent.to_hex()),
+            round: info.round,
+            witness: info.witness.as_ref().map(Into::into),
+            round_received: info.round_received,
+            in_fork: value.in_fork,
+        }
+    }
+}
+
+/// Whole exported document, for reading the exports back.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JsonGraph<TPeerId> {
+    pub version: u32,
+    /// `None` for observers
+    pub self_id: Option<TPeerId>,
+    pub events: Vec<JsonEvent<TPeerId>>,
+    pub ordering: Vec<String>,
+}
+
+impl<TPeerId> JsonGraph<TPeerId>
+where
+    TPeerId: for<'de> Deserialize<'de>,
+{
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    pub fn from_reader<R: std::io::Read>(reader: R) -> serde_json::Result<Self> {
+        serde_json::from_reader(reader)
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash + Clone + Debug + Serialize,
+{
+    /// Graph structure as a JSON document, see the [module docs](self) for
+    /// the schema.
+    pub fn export_json(&self) -> String {
+        let mut out = vec![];
+        self.write_json(&mut out)
+            .expect("writing to a vector doesn't fail");
+        String::from_utf8(out).expect("serde_json produces valid UTF-8")
+    }
+
+    /// Same as [`export_json`](Self::export_json), but writes events one by
+    /// one without building the whole document in memory.
+    pub fn write_json<W: Write>(&self, mut writer: W) -> serde_json::Result<()> {
+        write!(writer, "{{\"version\":{},\"self_id\":", JSON_SCHEMA_VERSION)
+            .map_err(serde_json::Error::io)?;
+        serde_json::to_writer(&mut writer, &self.self_id)?;
+        writer
+            .write_all(b",\"events\":[")
+            .map_err(serde_json::Error::io)?;
+        for (i, event) in self
+            .exported_events(&ExportFilter::default())
+            .iter()
+            .enumerate()
+        {
+            if i > 0 {
+                writer.write_all(b",").map_err(serde_json::Error::io)?;
+            }
+            serde_json::to_writer(&mut writer, &JsonEvent::from(event))?;
+        }
+        writer
+            .write_all(b"],\"ordering\":")
+            .map_err(serde_json::Error::io)?;
+        let ordering: Vec<_> = self.ordering.ordered().map(|h| h.to_hex()).collect();
+        serde_json::to_writer(&mut writer, &ordering)?;
+        writer.write_all(b"}").map_err(serde_json::Error::io)
+    }
+}
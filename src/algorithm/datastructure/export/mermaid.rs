@@ -0,0 +1,58 @@
+//! [Mermaid](https://mermaid.js.org/) flowchart output, for embedding small
+//! graphs in docs and issues.
+//!
+//! Shapes follow the DOT output where Mermaid allows: rectangles for regular
+//! events, rhombi for witnesses with undecided fame, double circles for
+//! famous witnesses and circles for the rest. Self parent edges are solid,
+//! other parent edges are dotted. Forks are outlined in red.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Write};
+
+use super::{exported_edges, ExportFilter};
+use crate::algorithm::datastructure::{Graph, WitnessFamousness};
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash + Clone + Debug,
+{
+    /// Node ids are `e<N>` in the export order, labels contain the compact
+    /// hash, author and round.
+    pub fn to_mermaid(&self, filter: &ExportFilter<TPeerId>) -> String {
+        let events = self.exported_events(filter);
+        let ids: HashMap<_, _> = events
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (&e.info.hash, format!("e{}", i)))
+            .collect();
+        let mut out = String::new();
+        writeln!(out, "flowchart BT").unwrap();
+        for e in &events {
+            let info = &e.info;
+            let author = format!("{:?}", info.author).replace('"', "#quot;");
+            let label = format!("{} {} r{}", info.hash.to_compact_hex(), author, info.round);
+            let (open, close) = match info.witness {
+                None => ("[", "]"),
+                Some(WitnessFamousness::Undecided) => ("{", "}"),
+                Some(WitnessFamousness::Yes) => ("(((", ")))"),
+                Some(WitnessFamousness::No) => ("((", "))"),
+            };
+            writeln!(out, "    {}{}\"{}\"{}", ids[&info.hash], open, label, close).unwrap();
+        }
+        for (parent, child, is_self_parent) in exported_edges(&events) {
+            let arrow = if is_self_parent { "-->" } else { "-.->" };
+            writeln!(out, "    {} {} {}", ids[parent], arrow, ids[child]).unwrap();
+        }
+        let forks: Vec<_> = events
+            .iter()
+            .filter(|e| e.in_fork)
+            .map(|e| ids[&e.info.hash].as_str())
+            .collect();
+        if !forks.is_empty() {
+            writeln!(out, "    classDef fork stroke:#f00,stroke-width:3px").unwrap();
+            writeln!(out, "    class {} fork", forks.join(",")).unwrap();
+        }
+        out
+    }
+}
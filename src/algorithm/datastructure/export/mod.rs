@@ -0,0 +1,114 @@
+//! Rendering of the graph for external tools (visualization, analysis).
+
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::ops::RangeInclusive;
+
+use super::{EventInfo, Graph};
+use crate::algorithm::{event, RoundNum};
+
+pub mod csv;
+pub mod dot;
+pub mod graphml;
+pub mod json;
+pub mod mermaid;
+#[cfg(feature = "otel")]
+pub mod otel;
+
+/// Which events to include in diagrams. Everything by default.
+///
+/// Edges to parents that are filtered out are omitted.
+#[derive(Debug, Clone)]
+pub struct ExportFilter<TPeerId> {
+    pub rounds: Option<RangeInclusive<RoundNum>>,
+    pub peers: Option<HashSet<TPeerId>>,
+}
+
+impl<TPeerId> Default for ExportFilter<TPeerId> {
+    fn default() -> Self {
+        Self {
+            rounds: None,
+            peers: None,
+        }
+    }
+}
+
+impl<TPeerId: Eq + std::hash::Hash> ExportFilter<TPeerId> {
+    pub fn with_rounds(mut self, rounds: RangeInclusive<RoundNum>) -> Self {
+        self.rounds = Some(rounds);
+        self
+    }
+
+    pub fn with_peers(mut self, peers: impl IntoIterator<Item = TPeerId>) -> Self {
+        self.peers = Some(peers.into_iter().collect());
+        self
+    }
+
+    fn matches(&self, info: &EventInfo<TPeerId>) -> bool {
+        self.rounds.as_ref().is_none_or(|r| r.contains(&info.round))
+            && self.peers.as_ref().is_none_or(|p| p.contains(&info.author))
+    }
+}
+
+/// Event with everything the exporters need
+struct ExportedEvent<TPeerId> {
+    info: EventInfo<TPeerId>,
+    /// The event shares its self parent with another event of the same author
+    in_fork: bool,
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash + Clone + Debug,
+{
+    /// Events passing `filter` in a deterministic order: by round, then by
+    /// timestamp, then by hash.
+    fn exported_events(&self, filter: &ExportFilter<TPeerId>) -> Vec<ExportedEvent<TPeerId>> {
+        let mut events: Vec<_> = self
+            .all_events
+            .keys()
+            .map(|hash| self.event_info(hash).expect("iterating over known events"))
+            .filter(|info| filter.matches(info))
+            .map(|info| {
+                let in_fork = info.parents.as_ref().is_some_and(|p| {
+                    let self_parent = self
+                        .all_events
+                        .get(&p.self_parent)
+                        .expect("parents of tracked events are tracked");
+                    matches!(
+                        self_parent.children.self_child,
+                        event::SelfChild::ForkingParent(_)
+                    )
+                });
+                ExportedEvent { info, in_fork }
+            })
+            .collect();
+        events.sort_by(|a, b| {
+            (a.info.round, a.info.timestamp, &a.info.hash).cmp(&(
+                b.info.round,
+                b.info.timestamp,
+                &b.info.hash,
+            ))
+        });
+        events
+    }
+}
+
+/// `(parent, child, is_self_parent)` for edges with both ends among `events`
+fn exported_edges<TPeerId>(
+    events: &[ExportedEvent<TPeerId>],
+) -> Vec<(&event::Hash, &event::Hash, bool)> {
+    let included: HashSet<_> = events.iter().map(|e| &e.info.hash).collect();
+    events
+        .iter()
+        .filter_map(|e| e.info.parents.as_ref().map(|p| (&e.info.hash, p)))
+        .flat_map(|(child, p)| {
+            [
+                (&p.self_parent, child, true),
+                (&p.other_parent, child, false),
+            ]
+        })
+        .filter(|(parent, _, _)| included.contains(parent))
+        .collect()
+}
@@ -0,0 +1,195 @@
+//! Gossip causality as [OpenTelemetry](https://opentelemetry.io/) spans, for
+//! viewing in existing tracing UIs (Jaeger, Tempo, etc.).
+//!
+//! Each event becomes a span named `hashgraph.event` linked to the spans of
+//! its parents. Link attribute `hashgraph.parent` is `self` or `other`. Span
+//! ids are the first 8 bytes of event hashes and all spans share a single
+//! trace id (first 16 bytes of the smallest genesis hash), so exports of
+//! different nodes of the same network refer to the same spans.
+//!
+//! Event timestamps are interpreted as milliseconds since the Unix epoch.
+//! Span attributes: `hashgraph.event.hash`, `hashgraph.event.author` (debug
+//! representation), `hashgraph.event.round`, `hashgraph.event.witness`
+//! (`undecided`/`famous`/`not_famous`, absent for non-witnesses),
+//! `hashgraph.event.round_received` (absent if undecided).
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use opentelemetry::trace::{
+    Link, Span, SpanContext, SpanId, TraceFlags, TraceId, TraceState, Tracer,
+};
+use opentelemetry::{Context, KeyValue};
+
+use super::{exported_edges, ExportFilter};
+use crate::algorithm::datastructure::{Graph, WitnessFamousness};
+use crate::algorithm::event;
+use crate::Timestamp;
+
+pub const SPAN_NAME: &str = "hashgraph.event";
+
+fn span_id(hash: &event::Hash) -> SpanId {
+    SpanId::from_bytes(hash.as_ref()[..8].try_into().unwrap())
+}
+
+fn time(timestamp: Timestamp) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(timestamp.try_into().unwrap_or(u64::MAX))
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash + Clone + Debug,
+{
+    /// Trace id shared by all exported spans, see the [module docs](self).
+    /// `None` until a genesis is known (i.e. for observers that have
+    /// received nothing yet).
+    pub fn otel_trace_id(&self) -> Option<TraceId> {
+        let genesis = self.peer_index.values().map(|index| index.origin()).min()?;
+        Some(TraceId::from_bytes(
+            genesis.as_ref()[..16].try_into().unwrap(),
+        ))
+    }
+
+    /// Start and end a span for each event passing `filter`
+    pub fn export_otel<T: Tracer>(&self, tracer: &T, filter: &ExportFilter<TPeerId>) {
+        let Some(trace_id) = self.otel_trace_id() else {
+            return;
+        };
+        let events = self.exported_events(filter);
+        let mut links: HashMap<&event::Hash, Vec<Link>> = HashMap::new();
+        for (parent, child, is_self_parent) in exported_edges(&events) {
+            let context = SpanContext::new(
+                trace_id,
+                span_id(parent),
+                TraceFlags::SAMPLED,
+                false,
+                TraceState::NONE,
+            );
+            let kind = if is_self_parent { "self" } else { "other" };
+            links.entry(child).or_default().push(Link::new(
+                context,
+                vec![KeyValue::new("hashgraph.parent", kind)],
+                0,
+            ));
+        }
+        for e in &events {
+            let info = &e.info;
+            let mut attributes = vec![
+                KeyValue::new("hashgraph.event.hash", info.hash.to_hex()),
+                KeyValue::new("hashgraph.event.author", format!("{:?}", info.author)),
+                KeyValue::new("hashgraph.event.round", info.round as i64),
+            ];
+            if let Some(fame) = &info.witness {
+                let fame = match fame {
+                    WitnessFamousness::Undecided => "undecided",
+                    WitnessFamousness::Yes => "famous",
+                    WitnessFamousness::No => "not_famous",
+                };
+                attributes.push(KeyValue::new("hashgraph.event.witness", fame));
+            }
+            if let Some(round_received) = info.round_received {
+                attributes.push(KeyValue::new(
+                    "hashgraph.event.round_received",
+                    round_received as i64,
+                ));
+            }
+            let time = time(info.timestamp);
+            let builder = tracer
+                .span_builder(SPAN_NAME)
+                .with_trace_id(trace_id)
+                .with_span_id(span_id(&info.hash))
+                .with_start_time(time)
+                .with_end_time(time)
+                .with_attributes(attributes)
+                .with_links(links.remove(&info.hash).unwrap_or_default());
+            let mut span = tracer.build_with_context(builder, &Context::new());
+            span.end_with_timestamp(time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::sync::{Arc, Mutex};
+
+    use opentelemetry::trace::{SpanBuilder, Status};
+
+    use super::*;
+    use crate::algorithm::datastructure::tests::mocks::build_graph_detailed_example;
+
+    /// Remembers builders of all spans
+    #[derive(Default)]
+    struct RecordingTracer {
+        spans: Arc<Mutex<Vec<SpanBuilder>>>,
+    }
+
+    struct RecordedSpan;
+
+    impl Span for RecordedSpan {
+        fn add_event_with_timestamp<T>(&mut self, _: T, _: SystemTime, _: Vec<KeyValue>)
+        where
+            T: Into<Cow<'static, str>>,
+        {
+        }
+        fn span_context(&self) -> &SpanContext {
+            &SpanContext::NONE
+        }
+        fn is_recording(&self) -> bool {
+            false
+        }
+        fn set_attribute(&mut self, _: KeyValue) {}
+        fn set_status(&mut self, _: Status) {}
+        fn update_name<T>(&mut self, _: T)
+        where
+            T: Into<Cow<'static, str>>,
+        {
+        }
+        fn add_link(&mut self, _: SpanContext, _: Vec<KeyValue>) {}
+        fn end_with_timestamp(&mut self, _: SystemTime) {}
+    }
+
+    impl Tracer for RecordingTracer {
+        type Span = RecordedSpan;
+
+        fn build_with_context(&self, builder: SpanBuilder, _: &Context) -> Self::Span {
+            self.spans.lock().unwrap().push(builder);
+            RecordedSpan
+        }
+    }
+
+    #[test]
+    fn spans_linked_to_parents() {
+        let graph = build_graph_detailed_example((), 999).unwrap().graph;
+        let tracer = RecordingTracer::default();
+        graph.export_otel(&tracer, &ExportFilter::default());
+        let spans = tracer.spans.lock().unwrap();
+        assert_eq!(spans.len(), graph.all_events.len());
+        let trace_id = graph.otel_trace_id().unwrap();
+        for span in spans.iter() {
+            assert_eq!(span.trace_id, Some(trace_id));
+            let hash_attribute = span
+                .attributes
+                .as_ref()
+                .unwrap()
+                .iter()
+                .find(|kv| kv.key.as_str() == "hashgraph.event.hash")
+                .unwrap();
+            let hash = event::Hash::from_hex(&hash_attribute.value.as_str()).unwrap();
+            assert_eq!(span.span_id, Some(span_id(&hash)));
+            let linked: Vec<_> = span
+                .links
+                .iter()
+                .flatten()
+                .map(|l| l.span_context.span_id())
+                .collect();
+            let expected: Vec<_> = match graph.event(&hash).unwrap().kind() {
+                event::Kind::Genesis(_) => vec![],
+                event::Kind::Regular(p) => vec![span_id(&p.self_parent), span_id(&p.other_parent)],
+            };
+            assert_eq!(linked, expected);
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Measuring order fairness.
+//!
+//! The consensus timestamp of an event is the median of the times at which
+//! the unique famous witnesses first learned of it (see
+//! [`timestamping`](super::timestamping)), so that no minority of members
+//! can move it ahead of transactions the network saw earlier.
+//! [`Graph::fairness_report`] shows, for a finalized event, when each member
+//! first saw it, i.e. the timestamp of its earliest event having it as an
+//! ancestor, next to the time the author created it and the consensus
+//! timestamp.
+//!
+//! Reports of a range of finalized events are fed to a [`FairnessMetric`]
+//! with [`Graph::measure_fairness`]. Applications can implement their own
+//! metrics, [`Inversions`] and [`Latency`] are provided.
+
+use std::ops::Range;
+
+use serde::Serialize;
+
+use super::explain::ExplainError;
+use super::Graph;
+use crate::algorithm::{event, RoundNum};
+use crate::Timestamp;
+
+/// Earliest event of a member that has the reported event as an ancestor
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FirstSeen<TPeerId> {
+    pub peer: TPeerId,
+    pub event: event::Hash,
+    pub timestamp: Timestamp,
+    pub round: RoundNum,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FairnessReport<TPeerId> {
+    pub event: event::Hash,
+    /// Member that introduced the transaction
+    pub author: TPeerId,
+    /// Among all finalized events
+    pub position: usize,
+    /// Timestamp given by the author
+    pub created: Timestamp,
+    pub round_received: RoundNum,
+    pub consensus_timestamp: Timestamp,
+    /// Members that have seen the event, the author included, in the
+    /// [canonical order](super::peer_order)
+    pub first_seen: Vec<FirstSeen<TPeerId>>,
+}
+
+impl<TPeerId> FairnessReport<TPeerId> {
+    /// Median of the first-seen times of all members that have seen it
+    pub fn median_first_seen(&self) -> Timestamp {
+        let mut times: Vec<_> = self.first_seen.iter().map(|s| s.timestamp).collect();
+        times.sort();
+        times[times.len() / 2]
+    }
+
+    /// Time from the creation to the consensus timestamp
+    pub fn latency(&self) -> Timestamp {
+        self.consensus_timestamp.saturating_sub(self.created)
+    }
+}
+
+/// Statistic over reports of finalized events, given in the consensus order
+pub trait FairnessMetric<TPeerId> {
+    fn observe(&mut self, report: &FairnessReport<TPeerId>);
+}
+
+/// Neighbours in the consensus order where the later event was seen
+/// earlier, judging by the median of the first-seen times
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Inversions {
+    pub observed: usize,
+    pub inverted: usize,
+    previous: Option<Timestamp>,
+}
+
+impl<TPeerId> FairnessMetric<TPeerId> for Inversions {
+    fn observe(&mut self, report: &FairnessReport<TPeerId>) {
+        let seen = report.median_first_seen();
+        if self.previous.is_some_and(|previous| seen < previous) {
+            self.inverted += 1;
+        }
+        self.observed += 1;
+        self.previous = Some(seen);
+    }
+}
+
+/// Time from creation to consensus timestamp
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Latency {
+    pub observed: usize,
+    pub total: Timestamp,
+    pub max: Timestamp,
+}
+
+impl Latency {
+    pub fn mean(&self) -> Option<Timestamp> {
+        (self.observed > 0).then(|| self.total / self.observed as Timestamp)
+    }
+}
+
+impl<TPeerId> FairnessMetric<TPeerId> for Latency {
+    fn observe(&mut self, report: &FairnessReport<TPeerId>) {
+        let latency = report.latency();
+        self.observed += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash + Clone,
+{
+    /// How the finalized event was seen by the members, see the [module
+    /// docs](self)
+    pub fn fairness_report(
+        &self,
+        event_hash: &event::Hash,
+    ) -> Result<FairnessReport<TPeerId>, ExplainError> {
+        let event = self
+            .all_events
+            .get(event_hash)
+            .ok_or_else(|| ExplainError::UnknownEvent(event_hash.clone()))?;
+        let (round_received, consensus_timestamp, _) = self
+            .ordering_data(event_hash)
+            .map_err(|_| ExplainError::NotFinalized(event_hash.clone()))?;
+        let position = self
+            .ordering
+            .ordered()
+            .position(|h| h == event_hash)
+            .ok_or_else(|| ExplainError::NotFinalized(event_hash.clone()))?;
+        let mut first_seen = vec![];
+        for peer in self.peer_order().peers() {
+            let Some(tip) = self
+                .peer_index
+                .get(peer)
+                .and_then(|index| index.latest_events().iter().min())
+            else {
+                continue;
+            };
+            if !self.is_ancestor(tip, event_hash)? {
+                continue;
+            }
+            let receiver = self.receivers([tip], event_hash)?[0];
+            first_seen.push(FirstSeen {
+                peer: peer.clone(),
+                event: receiver.hash().clone(),
+                timestamp: *receiver.timestamp(),
+                round: self.round_of(receiver.hash()),
+            });
+        }
+        Ok(FairnessReport {
+            event: event_hash.clone(),
+            author: event.author().clone(),
+            position,
+            created: *event.timestamp(),
+            round_received,
+            consensus_timestamp,
+            first_seen,
+        })
+    }
+
+    /// Feed reports of the finalized events at `positions` to the metric,
+    /// in the consensus order. Pruned events are skipped. Returns the number
+    /// of reports.
+    pub fn measure_fairness<M>(&self, positions: Range<usize>, metric: &mut M) -> usize
+    where
+        M: FairnessMetric<TPeerId> + ?Sized,
+    {
+        let mut observed = 0;
+        let events = self
+            .ordering
+            .ordered()
+            .skip(positions.start)
+            .take(positions.len());
+        for hash in events {
+            if let Ok(report) = self.fairness_report(hash) {
+                metric.observe(&report);
+                observed += 1;
+            }
+        }
+        observed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture;
+
+    #[test]
+    fn reports_of_finalized_events() {
+        let built = fixture::detailed_example().build().unwrap();
+        let graph = &built.graph;
+        let finalized = graph.ordering.len();
+        let first = graph.ordering.ordered().next().unwrap();
+        let report = graph.fairness_report(first).unwrap();
+        assert_eq!(report.position, 0);
+        assert_eq!(report.event, *first);
+        // The author sees the event when creating it, everyone has seen a
+        // finalized event
+        let by_author = report
+            .first_seen
+            .iter()
+            .find(|s| s.peer == report.author)
+            .unwrap();
+        assert_eq!(
+            (&by_author.event, by_author.timestamp),
+            (first, report.created)
+        );
+        assert_eq!(report.first_seen.len(), graph.peers().len());
+        assert!(report
+            .first_seen
+            .iter()
+            .all(|s| s.timestamp >= report.created));
+        assert_eq!(
+            report.consensus_timestamp,
+            graph
+                .ordering_explanation(first)
+                .unwrap()
+                .consensus_timestamp
+        );
+
+        let mut inversions = Inversions::default();
+        let mut latency = Latency::default();
+        assert_eq!(
+            graph.measure_fairness(0..finalized, &mut inversions),
+            finalized
+        );
+        assert_eq!(
+            graph.measure_fairness(1..finalized + 10, &mut latency),
+            finalized - 1
+        );
+        assert_eq!(inversions.observed, finalized);
+        assert!(inversions.inverted < finalized);
+        assert!(latency.max >= latency.mean().unwrap());
+
+        let undecided = built.hash("b4");
+        assert_eq!(
+            graph.fairness_report(undecided),
+            Err(ExplainError::NotFinalized(undecided.clone()))
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! Reach of forks into the consensus.
+//!
+//! A fork (two or more self children of one event) is tolerated by the
+//! consensus, but an operator may want to know whether it could have
+//! influenced what was decided. [`Graph::fork_spans`] lists, for every known
+//! fork, the rounds of the events on its branches and the decided fame
+//! elections that depended on them.
+//!
+//! An election of a witness of round `r` decided at round `d` depends on a
+//! branch event if the event is the witness itself, one of the voters
+//! (witnesses of rounds `r + 1..=d`), or an event of rounds `r..=d` seen by
+//! some voter, i.e. it could have carried a vote or a path of strongly
+//! seeing. Elections are run again to find `d`, so the report takes time
+//! proportional to the decided elections, and is meant for occasional
+//! inspection.
+
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use super::{Graph, WitnessFamousness};
+use crate::algorithm::{core, event, RoundNum};
+
+/// Decided election that depended on events of a fork
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DependentDecision {
+    pub witness: event::Hash,
+    pub round: RoundNum,
+    pub famous: bool,
+    pub decided_at: RoundNum,
+    /// Events of the branches the election depended on, sorted
+    pub contributors: Vec<event::Hash>,
+}
+
+/// See [`Graph::fork_spans`]
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ForkSpan<TPeerId> {
+    pub author: TPeerId,
+    /// Event with several self children
+    pub forking_parent: event::Hash,
+    /// Self children of the forking parent, sorted
+    pub branches: Vec<event::Hash>,
+    /// Rounds of the events on the branches (the self children and their
+    /// self descendants)
+    pub first_round: RoundNum,
+    pub last_round: RoundNum,
+    /// Ordered by round of the witness, then by its hash
+    pub decisions: Vec<DependentDecision>,
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash + Clone,
+{
+    /// Rounds and decided elections affected by each known fork, ordered
+    /// by hash of the forking parent. See the [module docs](self).
+    pub fn fork_spans(&self) -> Vec<ForkSpan<TPeerId>> {
+        let mut spans = vec![];
+        for (author, index) in &self.peer_index {
+            for (forking_parent, children) in index.fork_index().forks() {
+                let mut branches: Vec<_> = children.iter().cloned().collect();
+                branches.sort();
+                let events = self.self_descendants(&branches);
+                let rounds = events.iter().filter_map(|e| self.headers.get(e)?.round);
+                let (Some(first_round), Some(last_round)) = (rounds.clone().min(), rounds.max())
+                else {
+                    // Branches were pruned
+                    continue;
+                };
+                spans.push(ForkSpan {
+                    author: author.clone(),
+                    forking_parent: forking_parent.clone(),
+                    branches,
+                    first_round,
+                    last_round,
+                    decisions: self.dependent_decisions(&events, first_round, last_round),
+                });
+            }
+        }
+        spans.sort_by(|a, b| a.forking_parent.cmp(&b.forking_parent));
+        spans
+    }
+
+    /// The events and all their self descendants
+    fn self_descendants(&self, roots: &[event::Hash]) -> HashSet<event::Hash> {
+        let mut found = HashSet::new();
+        let mut to_visit = roots.to_vec();
+        while let Some(hash) = to_visit.pop() {
+            let Some(event) = self.all_events.get(&hash) else {
+                continue;
+            };
+            if found.insert(hash) {
+                to_visit.extend(Vec::from(event.children.self_child.clone()));
+            }
+        }
+        found
+    }
+
+    /// Decided elections depending on `events`, which are in rounds
+    /// `first..=last`
+    fn dependent_decisions(
+        &self,
+        events: &HashSet<event::Hash>,
+        first: RoundNum,
+        last: RoundNum,
+    ) -> Vec<DependentDecision> {
+        let members = self.members_count();
+        let mut decisions = vec![];
+        // Elections of later witnesses are held by even later voters
+        for (round, witness) in self.witnesses_between(0, last) {
+            let famous = match self.witnesses.lock().unwrap().get(witness) {
+                Some(WitnessFamousness::Yes) => true,
+                Some(WitnessFamousness::No) => false,
+                _ => continue,
+            };
+            let Some(voting) = self.voting(round) else {
+                continue;
+            };
+            let election = core::fame(self, &members, voting, witness).expect("witness is known");
+            let Some(decided_at) = election.decided_at else {
+                continue;
+            };
+            if decided_at < first {
+                continue;
+            }
+            let voters: Vec<_> = self
+                .witnesses_between(round + 1, decided_at)
+                .map(|(_, voter)| voter)
+                .collect();
+            let mut contributors: Vec<_> = events
+                .iter()
+                .filter(|event| {
+                    *event == witness
+                        || voters.contains(event)
+                        || self
+                            .headers
+                            .get(*event)
+                            .and_then(|h| h.round)
+                            .is_some_and(|r| {
+                                (round..=decided_at).contains(&r)
+                                    && voters.iter().any(|voter| {
+                                        core::see(self, voter, event).expect("voters are known")
+                                    })
+                            })
+                })
+                .cloned()
+                .collect();
+            if contributors.is_empty() {
+                continue;
+            }
+            contributors.sort();
+            decisions.push(DependentDecision {
+                witness: witness.clone(),
+                round,
+                famous,
+                decided_at,
+                contributors,
+            });
+        }
+        decisions.sort_by(|a, b| (a.round, &a.witness).cmp(&(b.round, &b.witness)));
+        decisions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::fixture;
+
+    #[test]
+    fn fork_reaches_decisions() {
+        assert!(fixture::detailed_example()
+            .build()
+            .unwrap()
+            .graph
+            .fork_spans()
+            .is_empty());
+
+        let built = fixture::fork().build().unwrap();
+        let sorted = |names: &[&str]| {
+            let mut hashes: Vec<_> = names.iter().map(|n| built.hash(n).clone()).collect();
+            hashes.sort();
+            hashes
+        };
+        let spans = built.graph.fork_spans();
+        assert_eq!(spans.len(), 1);
+        let span = &spans[0];
+        assert_eq!(span.author, *built.peer_id("m").unwrap());
+        assert_eq!(&span.forking_parent, built.hash("GENESIS_m"));
+        assert_eq!(span.branches, sorted(&["m2", "m2_fork"]));
+        assert_eq!((span.first_round, span.last_round), (1, 3));
+
+        let decided: Vec<_> = span
+            .decisions
+            .iter()
+            .map(|d| (built.name(&d.witness).unwrap(), d.decided_at, d.famous))
+            .collect();
+        assert_eq!(decided.len(), 5);
+        for expected in [
+            ("GENESIS_a", 2, true),
+            ("GENESIS_m", 2, true),
+            ("a2", 3, true),
+            ("m2", 3, true),
+            ("m2_fork", 3, true),
+        ] {
+            assert!(decided.contains(&expected), "{expected:?}");
+        }
+        // Events of round 3 are not seen by the voters of round 2
+        assert_eq!(
+            span.decisions[0].contributors,
+            sorted(&["m2", "m2_fork", "m2_1", "m3"])
+        );
+        assert_eq!(
+            span.decisions[4].contributors,
+            sorted(&["m2", "m2_fork", "m2_1", "m3", "m4"])
+        );
+    }
+}
@@ -0,0 +1,53 @@
+//! Hot part of the stored events.
+//!
+//! Round and fame decisions walk ancestors again and again, but only look
+//! at the parents, the author (and its dense number, the slot), the round
+//! and whether an event is a witness.
+//! Sync looks at the position of the event in its author's chain.
+//! These live in [`EventHeader`]s, stored inline in a map of their own
+//! (an array of structs, one lookup per step of a walk):
+//!
+//! ```text
+//! headers:    hash -> EventHeader { parents, author, slot, sequence, round, witness, delivered }
+//! all_events: hash -> EventWrapper { children, hash, signature, timestamp, payload, .. }
+//! ```
+//!
+//! The cold part, [`EventWrapper`](crate::algorithm::event::EventWrapper)s
+//! in `all_events`, is what the rest of the graph and its API use. Parents
+//! and authors are copied into the headers once, on insertion; both parts
+//! are removed together when pruning.
+
+use crate::algorithm::event::Parents;
+use crate::algorithm::RoundNum;
+
+#[derive(Debug, Clone)]
+pub(super) struct EventHeader<TPeerId> {
+    /// `None` for geneses
+    pub parents: Option<Parents>,
+    pub author: TPeerId,
+    /// See [`core::Entry::slot`](crate::algorithm::core::Entry::slot),
+    /// the same for all events of the author
+    pub slot: usize,
+    /// 0 for geneses, one more than the self parent's for others
+    pub sequence: u64,
+    /// `None` until determined on insertion
+    pub round: Option<RoundNum>,
+    pub witness: bool,
+    /// Returned by [`Graph::next_finalized_event`](super::Graph::next_finalized_event),
+    /// compaction only touches rounds where all events are
+    pub delivered: bool,
+}
+
+impl<TPeerId> EventHeader<TPeerId> {
+    pub fn new(parents: Option<Parents>, author: TPeerId, slot: usize, sequence: u64) -> Self {
+        Self {
+            parents,
+            author,
+            slot,
+            sequence,
+            round: None,
+            witness: false,
+            delivered: false,
+        }
+    }
+}
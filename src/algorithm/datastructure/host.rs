@@ -0,0 +1,568 @@
+//! Several independent graphs (shards, topics) in one process.
+//!
+//! A [`GraphHost`] keeps a [`Graph`] per [`TopicId`]. Graphs don't share any
+//! consensus state, a message for one topic never touches the others. What
+//! is shared is the expensive and repetitive part of ingestion:
+//! - signatures of received events are checked on a [`VerifyPool`] before
+//!   the events are pushed. Hosted graphs use [`HostSigner`], which accepts
+//!   the signatures verified by the pool without checking them again;
+//! - author ids and their keys (genesis payloads) passed to the pool are
+//!   interned, so each of them is stored once for all graphs and batches.
+//!
+//! Sync messages between hosts are wrapped in [`Topical`] so that the
+//! receiver knows which graph they are for.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Debug;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::sync::wire::{MessageKind, WireMessage};
+use super::{sync, Graph};
+use crate::algorithm::codec::PayloadCodec;
+use crate::algorithm::event::{self, Signature};
+use crate::algorithm::{Clock, PushError, Signer};
+
+/// Identifier of a hosted graph, the same on all nodes
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TopicId(pub u64);
+
+/// Message for the graph of `topic`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Topical<M> {
+    pub topic: TopicId,
+    pub message: M,
+}
+
+const TOPIC_HEADER_LEN: usize = 9;
+
+/// Body is the topic (`u64`, little endian), kind of the wrapped message and
+/// its body
+impl<M: WireMessage> WireMessage for Topical<M> {
+    const KIND: MessageKind = MessageKind::Topic;
+
+    fn encode_body(&self, version: u16) -> bincode::Result<Vec<u8>> {
+        let body = self.message.encode_body(version)?;
+        let mut bytes = Vec::with_capacity(TOPIC_HEADER_LEN + body.len());
+        bytes.extend_from_slice(&self.topic.0.to_le_bytes());
+        bytes.push(M::KIND as u8);
+        bytes.extend(body);
+        Ok(bytes)
+    }
+
+    fn decode_body(version: u16, body: &[u8]) -> bincode::Result<Self> {
+        if body.len() < TOPIC_HEADER_LEN {
+            return Err(Box::new(bincode::ErrorKind::Custom(
+                "Topic header is truncated".to_owned(),
+            )));
+        }
+        let (header, body) = body.split_at(TOPIC_HEADER_LEN);
+        if header[8] != M::KIND as u8 {
+            return Err(Box::new(bincode::ErrorKind::Custom(format!(
+                "Expected {:?} message in the topic, got message kind {}",
+                M::KIND,
+                header[8]
+            ))));
+        }
+        let topic = TopicId(u64::from_le_bytes(header[..8].try_into().unwrap()));
+        Ok(Self {
+            topic,
+            message: M::decode_body(version, body)?,
+        })
+    }
+}
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// Fixed set of threads checking signatures
+pub struct VerifyPool {
+    sender: Option<mpsc::Sender<Task>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl VerifyPool {
+    /// At least one thread is started
+    pub fn new(threads: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Task>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..threads.max(1))
+            .map(|_| {
+                let receiver = receiver.clone();
+                thread::spawn(move || loop {
+                    let task = receiver.lock().expect("pool lock poisoned").recv();
+                    match task {
+                        Ok(task) => task(),
+                        Err(_) => break,
+                    }
+                })
+            })
+            .collect();
+        Self {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Run `check` on all items, spread over the threads. Returns the items
+    /// it accepted, in no particular order.
+    fn filter<T, F>(&self, items: Vec<T>, check: F) -> Vec<T>
+    where
+        T: Send + 'static,
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        let check = Arc::new(check);
+        let (results, received) = mpsc::channel();
+        let chunk_len = items.len().div_ceil(self.threads()).max(1);
+        let mut items = items.into_iter().peekable();
+        let mut tasks = 0;
+        while items.peek().is_some() {
+            let chunk: Vec<T> = items.by_ref().take(chunk_len).collect();
+            let check = check.clone();
+            let results = results.clone();
+            let task: Task = Box::new(move || {
+                let accepted: Vec<T> = chunk.into_iter().filter(|item| check(item)).collect();
+                // The receiver waits for all tasks
+                let _ = results.send(accepted);
+            });
+            self.sender
+                .as_ref()
+                .expect("sender is present until drop")
+                .send(task)
+                .expect("workers live until drop");
+            tasks += 1;
+        }
+        drop(results);
+        received.iter().take(tasks).flatten().collect::<Vec<_>>()
+    }
+}
+
+impl Drop for VerifyPool {
+    fn drop(&mut self) {
+        // Workers stop when the channel closes
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Stores each distinct value once
+#[derive(Debug)]
+pub struct Interner<T> {
+    values: HashSet<Arc<T>>,
+}
+
+impl<T> Default for Interner<T> {
+    fn default() -> Self {
+        Self {
+            values: HashSet::new(),
+        }
+    }
+}
+
+impl<T: Eq + std::hash::Hash + Clone> Interner<T> {
+    pub fn intern(&mut self, value: &T) -> Arc<T> {
+        if let Some(interned) = self.values.get(value) {
+            return interned.clone();
+        }
+        let interned = Arc::new(value.clone());
+        self.values.insert(interned.clone());
+        interned
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// Signatures already checked by the pool
+type Verified = Arc<Mutex<HashSet<(event::Hash, Signature)>>>;
+
+/// Signer of hosted graphs. Signs with the host's signer, verification is
+/// skipped for the signatures accepted by the [`VerifyPool`].
+pub struct HostSigner<TSigner> {
+    inner: Arc<TSigner>,
+    verified: Verified,
+}
+
+impl<TSigner> Clone for HostSigner<TSigner> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            verified: self.verified.clone(),
+        }
+    }
+}
+
+impl<TGenesisPayload, TSigner: Signer<TGenesisPayload>> Signer<TGenesisPayload>
+    for HostSigner<TSigner>
+{
+    type SignerIdentity = TSigner::SignerIdentity;
+
+    fn sign(&self, event_hash: &event::Hash) -> Signature {
+        self.inner.sign(event_hash)
+    }
+
+    fn verify(
+        &self,
+        event_hash: &event::Hash,
+        signature: &Signature,
+        identity: &Self::SignerIdentity,
+        genesis_payload: &TGenesisPayload,
+    ) -> bool {
+        let key = (event_hash.clone(), signature.clone());
+        self.verified
+            .lock()
+            .expect("verified signatures lock poisoned")
+            .contains(&key)
+            || self
+                .inner
+                .verify(event_hash, signature, identity, genesis_payload)
+    }
+}
+
+pub type HostedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock> =
+    Graph<TPayload, TGenesisPayload, TPeerId, HostSigner<TSigner>, TClock>;
+
+#[derive(Error, Debug)]
+pub enum HostError<TPeerId> {
+    #[error("No graph for topic {0:?}")]
+    UnknownTopic(TopicId),
+    #[error("Topic {0:?} already has a graph")]
+    TopicExists(TopicId),
+    #[error(transparent)]
+    Sync(#[from] sync::Error),
+    #[error(transparent)]
+    Push(#[from] PushError<TPeerId>),
+}
+
+/// Graphs of several topics with shared verification. See the
+/// [module docs](self).
+pub struct GraphHost<TPayload, TGenesisPayload, TPeerId, TSigner, TClock> {
+    graphs: BTreeMap<TopicId, HostedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>>,
+    signer: Arc<TSigner>,
+    verified: Verified,
+    pool: VerifyPool,
+    peer_ids: Interner<TPeerId>,
+    keys: Interner<TGenesisPayload>,
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    GraphHost<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPayload: PayloadCodec + Eq + std::hash::Hash + Debug + Clone,
+    TGenesisPayload: Serialize + Eq + std::hash::Hash + Debug + Clone + Send + Sync + 'static,
+    TPeerId: Serialize + Eq + std::hash::Hash + Debug + Clone + Send + Sync + 'static,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId> + Send + Sync + 'static,
+    TClock: Clock,
+{
+    /// `signer` signs own events of all graphs, `verify_threads` is the size
+    /// of the [`VerifyPool`]
+    pub fn new(signer: TSigner, verify_threads: usize) -> Self {
+        Self {
+            graphs: BTreeMap::new(),
+            signer: Arc::new(signer),
+            verified: Arc::new(Mutex::new(HashSet::new())),
+            pool: VerifyPool::new(verify_threads),
+            peer_ids: Interner::default(),
+            keys: Interner::default(),
+        }
+    }
+
+    /// Start a graph for `topic`, see [`Graph::new`]
+    pub fn add_graph(
+        &mut self,
+        topic: TopicId,
+        self_id: TPeerId,
+        genesis_ordinary_payload: TPayload,
+        genesis_specific_payload: TGenesisPayload,
+        coin_frequency: usize,
+        clock: TClock,
+    ) -> Result<
+        &mut HostedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+        HostError<TPeerId>,
+    > {
+        if self.graphs.contains_key(&topic) {
+            return Err(HostError::TopicExists(topic));
+        }
+        let signer = HostSigner {
+            inner: self.signer.clone(),
+            verified: self.verified.clone(),
+        };
+        let graph = Graph::new(
+            self_id,
+            genesis_ordinary_payload,
+            genesis_specific_payload,
+            coin_frequency,
+            signer,
+            clock,
+        );
+        Ok(self.graphs.entry(topic).or_insert(graph))
+    }
+
+    pub fn remove_graph(
+        &mut self,
+        topic: TopicId,
+    ) -> Option<HostedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>> {
+        self.graphs.remove(&topic)
+    }
+
+    pub fn graph(
+        &self,
+        topic: TopicId,
+    ) -> Option<&HostedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>> {
+        self.graphs.get(&topic)
+    }
+
+    pub fn graph_mut(
+        &mut self,
+        topic: TopicId,
+    ) -> Option<&mut HostedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>> {
+        self.graphs.get_mut(&topic)
+    }
+
+    /// In increasing order
+    pub fn topics(&self) -> impl Iterator<Item = TopicId> + '_ {
+        self.graphs.keys().copied()
+    }
+
+    /// Distinct author ids seen by the verification
+    pub fn interned_peers(&self) -> usize {
+        self.peer_ids.len()
+    }
+
+    /// Distinct keys (genesis payloads) seen by the verification
+    pub fn interned_keys(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn sync_request(
+        &self,
+        topic: TopicId,
+    ) -> Result<Topical<sync::SyncRequest<TPayload, TGenesisPayload, TPeerId>>, HostError<TPeerId>>
+    {
+        let graph = self.graph(topic).ok_or(HostError::UnknownTopic(topic))?;
+        Ok(Topical {
+            topic,
+            message: graph.sync_request(),
+        })
+    }
+
+    /// See [`Graph::generate_sync_for_request`]
+    pub fn generate_sync_for_request(
+        &self,
+        request: &Topical<sync::SyncRequest<TPayload, TGenesisPayload, TPeerId>>,
+    ) -> Result<Topical<sync::Jobs<TPayload, TGenesisPayload, TPeerId>>, HostError<TPeerId>> {
+        let graph = self
+            .graph(request.topic)
+            .ok_or(HostError::UnknownTopic(request.topic))?;
+        Ok(Topical {
+            topic: request.topic,
+            message: graph.generate_sync_for_request(&request.message)?,
+        })
+    }
+
+    /// Check the signatures on the pool, then apply the jobs to the graph of
+    /// their topic, see [`Graph::apply_sync_jobs`]
+    pub fn apply_sync_jobs(
+        &mut self,
+        jobs: Topical<sync::Jobs<TPayload, TGenesisPayload, TPeerId>>,
+    ) -> Result<usize, HostError<TPeerId>> {
+        let topic = jobs.topic;
+        let graph = self
+            .graphs
+            .get_mut(&topic)
+            .ok_or(HostError::UnknownTopic(topic))?;
+        let candidates =
+            Self::signature_checks(graph, &mut self.peer_ids, &mut self.keys, &jobs.message);
+        let signer = self.signer.clone();
+        let accepted = self
+            .pool
+            .filter(candidates, move |(hash, signature, author, key)| {
+                signer.verify(hash, signature, author, key)
+            });
+        self.verified
+            .lock()
+            .expect("verified signatures lock poisoned")
+            .extend(
+                accepted
+                    .into_iter()
+                    .map(|(hash, signature, _, _)| (hash, signature)),
+            );
+        let result = graph.apply_sync_jobs(jobs.message);
+        self.verified
+            .lock()
+            .expect("verified signatures lock poisoned")
+            .clear();
+        Ok(result?)
+    }
+
+    /// Signatures of new events with the authors' keys. Events whose author
+    /// is unknown are left to the graph to reject.
+    #[allow(clippy::type_complexity)]
+    fn signature_checks(
+        graph: &HostedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+        peer_ids: &mut Interner<TPeerId>,
+        keys: &mut Interner<TGenesisPayload>,
+        jobs: &sync::Jobs<TPayload, TGenesisPayload, TPeerId>,
+    ) -> Vec<(event::Hash, Signature, Arc<TPeerId>, Arc<TGenesisPayload>)> {
+        // Authors joining in this batch
+        let mut new_keys = HashMap::new();
+        let mut checks = vec![];
+        for event in jobs.as_linear() {
+            if graph.event(event.hash()).is_some() {
+                continue;
+            }
+            let author = event.unsigned().fields().author();
+            let key = match event.unsigned().fields().kind() {
+                event::Kind::Genesis(key) => {
+                    new_keys.insert(author, key);
+                    Some(key)
+                }
+                event::Kind::Regular(_) => graph
+                    .peer_genesis(author)
+                    .and_then(|genesis| graph.event(genesis))
+                    .and_then(|genesis| match genesis.kind() {
+                        event::Kind::Genesis(key) => Some(key),
+                        event::Kind::Regular(_) => None,
+                    })
+                    .or_else(|| new_keys.get(author).copied()),
+            };
+            if let Some(key) = key {
+                checks.push((
+                    event.hash().clone(),
+                    event.signature().clone(),
+                    peer_ids.intern(author),
+                    keys.intern(key),
+                ));
+            }
+        }
+        checks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::event::WithSignatureCreationError;
+    use crate::algorithm::{IncrementalClock, MockSigner};
+
+    type TestHost = GraphHost<u32, u64, u64, MockSigner<u64, u64>, IncrementalClock>;
+
+    const TOPICS: [TopicId; 2] = [TopicId(1), TopicId(2)];
+
+    fn host(id: u64) -> TestHost {
+        let mut host = GraphHost::new(MockSigner::new(), 2);
+        for topic in TOPICS {
+            host.add_graph(topic, id, 0, topic.0, 999, IncrementalClock::new())
+                .unwrap();
+        }
+        host
+    }
+
+    fn sync(to: &mut TestHost, from: &TestHost, topic: TopicId) -> usize {
+        let request = to.sync_request(topic).unwrap();
+        let jobs = from.generate_sync_for_request(&request).unwrap();
+        let bytes = jobs.to_wire().unwrap();
+        to.apply_sync_jobs(Topical::from_wire(&bytes).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn graphs_isolated_by_topic() {
+        let mut a = host(0);
+        let mut b = host(1);
+        assert!(matches!(
+            a.add_graph(TopicId(1), 0, 0, 0, 999, IncrementalClock::new()),
+            Err(HostError::TopicExists(TopicId(1)))
+        ));
+        for topic in TOPICS {
+            assert_eq!(sync(&mut a, &b, topic), 1);
+            assert_eq!(sync(&mut b, &a, topic), 1);
+        }
+        // One author in both topics, a key per topic
+        assert_eq!(a.interned_peers(), 1);
+        assert_eq!(a.interned_keys(), 2);
+
+        let graph = a.graph_mut(TopicId(1)).unwrap();
+        let other = graph.peer_latest_event(&1).unwrap().clone();
+        graph.create_event(5, other).unwrap();
+        let request = b.sync_request(TopicId(1)).unwrap();
+        let jobs = a.generate_sync_for_request(&request).unwrap();
+        assert_eq!(jobs.message.as_linear().len(), 1);
+
+        // The event means nothing in the other topic
+        let misrouted = Topical {
+            topic: TopicId(2),
+            message: jobs.message.clone(),
+        };
+        assert!(matches!(
+            b.apply_sync_jobs(misrouted),
+            Err(HostError::Push(PushError::NoParent { .. }))
+        ));
+        assert!(matches!(
+            b.apply_sync_jobs(Topical {
+                topic: TopicId(3),
+                message: jobs.message.clone(),
+            }),
+            Err(HostError::UnknownTopic(TopicId(3)))
+        ));
+        assert_eq!(b.apply_sync_jobs(jobs).unwrap(), 1);
+        assert_eq!(
+            b.graph(TopicId(2)).unwrap().peer_lane(&0).unwrap().count(),
+            1
+        );
+        assert_eq!(b.topics().collect::<Vec<_>>(), TOPICS);
+    }
+
+    #[test]
+    fn forged_signature_rejected() {
+        let a = host(0);
+        let mut b = host(1);
+        let request = b.sync_request(TopicId(1)).unwrap();
+        let jobs = a.generate_sync_for_request(&request).unwrap();
+        let forged = jobs
+            .message
+            .into_linear()
+            .into_iter()
+            .map(|event| {
+                let (unsigned, _) = event.into_parts();
+                let signature = Signature(event::Hash::from_array([3; 64]));
+                event::SignedEvent::with_signature(unsigned, signature, |_, _, _| true).unwrap()
+            })
+            .collect();
+        let result = b.apply_sync_jobs(Topical {
+            topic: TopicId(1),
+            message: sync::Jobs::from_linear(forged),
+        });
+        assert!(matches!(
+            result,
+            Err(HostError::Push(PushError::InvalidSignature {
+                source: WithSignatureCreationError::InvalidSignature,
+                ..
+            }))
+        ));
+        assert!(b.graph(TopicId(1)).unwrap().peer_genesis(&0).is_none());
+    }
+
+    #[test]
+    fn pool_checks_everything() {
+        let pool = VerifyPool::new(3);
+        let mut even = pool.filter((0..100).collect(), |i: &i32| i % 2 == 0);
+        even.sort();
+        assert_eq!(even, (0..100).step_by(2).collect::<Vec<_>>());
+        assert!(pool.filter(vec![], |_: &i32| true).is_empty());
+    }
+}
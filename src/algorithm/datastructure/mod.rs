@@ -1,27 +1,72 @@
-use itertools::{izip, Itertools};
+use itertools::Itertools;
 use serde::Serialize;
 use thiserror::Error;
-use tracing::{debug, error, instrument, trace, warn};
+use tracing::{debug, debug_span, error, field, instrument, trace, warn, Span};
 
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::Debug;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use self::ordering::OrderedEvents;
 use self::peer_index::{PeerIndex, PeerIndexEntry};
+use self::pending::PendingPool;
+use self::round_index::RoundIndex;
+use self::seen::RecentlySeen;
 use self::slice::SliceIterator;
+pub use self::validation::VerifiedEvent;
+use super::arith::Stake;
+use super::codec::PayloadCodec;
 use super::event::{self, EventWrapper, Parents, SignedEvent, UnsignedEvent};
-use super::{Clock, PushError, RoundNum, Signature};
+use super::strategy::{OtherParentStrategy, PeerCandidate, PeerSelectionStrategy};
+use super::{core, metrics, Clock, PushError, RoundNum, Signature};
 use crate::algorithm::Signer;
 use crate::Timestamp;
 
+pub mod adaptive_coin;
+pub mod ancestry;
+pub mod annotations;
+pub mod app;
+pub mod archive;
+pub mod batches;
+pub mod bootstrap;
+pub mod compaction;
+#[cfg(feature = "concurrent")]
+pub mod concurrent;
+pub mod confirmation;
+pub mod consistency;
+pub mod content;
+pub mod cursor;
+mod descendancy;
+pub mod epoch;
+pub mod explain;
+pub mod export;
+pub mod fairness;
+pub mod fork_span;
+mod headers;
+pub mod host;
+pub mod observer;
 mod ordering;
 mod peer_index;
+pub mod peer_order;
+mod pending;
+pub mod provenance;
+pub mod query;
+mod round_index;
+pub mod round_quota;
+mod seen;
+pub mod shared;
 mod slice;
+mod state;
+pub mod submit;
 pub mod sync;
+pub mod timestamping;
+mod validation;
+pub mod watchdog;
+pub mod withholding;
 
 #[derive(Debug, PartialEq, Clone)]
-enum WitnessFamousness {
+pub enum WitnessFamousness {
     Yes,
     No,
     Undecided,
@@ -75,7 +120,7 @@ impl From<WitnessUniqueFamousness> for WitnessFamousness {
 
 #[derive(Error, Debug, PartialEq)]
 #[error("Event with such hash is unknown to the graph (hash {0})")]
-pub struct UnknownEvent(event::Hash);
+pub struct UnknownEvent(pub event::Hash);
 
 #[derive(Error, Debug, PartialEq)]
 pub enum WitnessCheckError {
@@ -91,12 +136,16 @@ pub enum RoundUfwListError {
     UnknownRound,
     #[error("Fame of some witnesses in the round is undecided")]
     RoundUndecided,
+    #[error("Round with this number was pruned")]
+    Pruned,
 }
 
 #[derive(Error, Debug, PartialEq)]
 pub enum OrderingDataError {
     #[error("Ordering for the event is undecided")]
     Undecided,
+    #[error("The event was ordered in rounds that are pruned")]
+    Pruned,
     #[error(transparent)]
     UnknownEvent(#[from] UnknownEvent),
 }
@@ -113,15 +162,104 @@ pub enum EventCreateError<TPeerId> {
     SignatureError(#[from] bincode::Error),
     #[error("Could not push the new event")]
     PushError(#[from] PushError<TPeerId>),
+    #[error("Peer {0:?} is not hosted by this graph")]
+    NotLocalIdentity(TPeerId),
+    #[error("Peer {0:?} is already known to the graph")]
+    IdentityAlreadyExists(TPeerId),
+    #[error("Peer {0:?} is unknown")]
+    UnknownPeer(TPeerId),
+    #[error("Event would be over the quota of {quota} events in round {round}")]
+    RoundQuotaExceeded { round: RoundNum, quota: usize },
+    #[error("Observers don't author events")]
+    Observer,
+    #[error("No other parent was chosen and there is no own event to use instead")]
+    NoOtherParent,
 }
 
 pub type EventIndex<TValue> = HashMap<event::Hash, TValue>;
 
+/// Consensus-related metadata of an event, without its payload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventInfo<TPeerId> {
+    pub hash: event::Hash,
+    pub author: TPeerId,
+    pub timestamp: Timestamp,
+    /// `None` for geneses
+    pub parents: Option<Parents>,
+    pub children: event::Children,
+    /// Epoch of the event's round, 0 if epochs are disabled (see
+    /// [`Graph::set_epoch_length`])
+    pub epoch: usize,
+    /// Counted from the first round of `epoch`
+    pub round: RoundNum,
+    /// `None` if the event is not a witness
+    pub witness: Option<WitnessFamousness>,
+    /// Counted from the first round of `epoch` like `round`, so it may
+    /// exceed the epoch length. `None` if not decided yet.
+    pub round_received: Option<RoundNum>,
+    /// Local notes of the application, see [`Graph::annotate`]
+    pub annotations: annotations::Annotations,
+    /// Peer that delivered the event first, see [`provenance`]
+    pub relay: Option<TPeerId>,
+}
+
+/// Local times of the event's milestones, according to the graph's clock.
+/// Collected if enabled with [`Graph::set_collect_timings`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventTimings {
+    pub inserted_at: Timestamp,
+    /// `None` if the event is not finalized yet
+    pub finalized_at: Option<Timestamp>,
+}
+
+/// Result of [`Graph::push_or_buffer`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum PushOutcome {
+    /// The event is in the graph now
+    Inserted,
+    /// Some parent of the event is unknown, it will be inserted once
+    /// the parent arrives.
+    Buffered {
+        missing_parent: event::Hash,
+        /// Events dropped from the pending pool to make space
+        evicted: Vec<event::Hash>,
+    },
+}
+
+/// Bounds of the pending pool, see [`Graph::set_pending_limits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingLimits {
+    /// Events in the pool overall, and resolved orphans kept for
+    /// [`Graph::next_resolved_orphan`]
+    pub capacity: usize,
+    /// Drop events buffered for longer than this, by the graph's clock
+    pub max_age: Option<Timestamp>,
+    /// Events of a single author. Signatures aren't checked in the pool,
+    /// so this is what the events claim.
+    pub per_peer: Option<usize>,
+}
+
+impl PendingLimits {
+    /// Only the overall capacity
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            max_age: None,
+            per_peer: None,
+        }
+    }
+}
+
 pub struct Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock> {
     all_events: EventIndex<EventWrapper<TPayload, TGenesisPayload, TPeerId>>,
+    /// What the decisions read of `all_events`, see [`headers`]
+    headers: EventIndex<headers::EventHeader<TPeerId>>,
     peer_index: PeerIndex<TPeerId>,
-    /// Consistent and reliable index (should be)
-    round_index: Vec<HashSet<event::Hash>>,
+    /// Geneses of `peer_index`, sorted, for [canonical
+    /// indices](peer_order) without scanning all peers
+    sorted_geneses: Vec<event::Hash>,
+    /// Consistent and reliable index (should be), see [`round_index`]
+    round_index: RoundIndex,
     /// Some(false) means unfamous witness.
     ///
     /// All pushed events are checked for being a witness, thus
@@ -136,6 +274,8 @@ pub struct Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock> {
     round_of: HashMap<event::Hash, RoundNum>,
     /// The lock should always succeed because only we use this and don't hold it at all
     ordering_data_cache: Mutex<HashMap<event::Hash, (usize, Timestamp, event::Signature)>>,
+    /// See [`descendancy`], filled lazily like `ordering_data_cache`
+    descendancy: Mutex<descendancy::Watermarks>,
     /// The latest round known to have its fame decided. All previous rounds
     /// must be decided as well.
     ///
@@ -145,14 +285,73 @@ pub struct Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock> {
     /// Events that we've successfully pushed, in the order of push
     /// (e.g. ancestors before their descendants)
     recognized_events: VecDeque<event::Hash>,
+    /// Events waiting for their parents. `None` if buffering is disabled.
+    pending: Option<PendingPool<TPayload, TGenesisPayload, TPeerId>>,
+    /// `None` if collection is disabled
+    timings: Option<HashMap<event::Hash, EventTimings>>,
+    /// Events recently received in syncs. `None` if disabled.
+    recently_seen: Option<RecentlySeen>,
+    /// Stakes of members set with [`Graph::set_stake`], others have
+    /// [`Stake::UNIT`]
+    stakes: HashMap<TPeerId, Stake>,
+    /// Summaries of completed epochs
+    epochs: Vec<epoch::EpochSummary<TPeerId>>,
+    /// Finalized events of earlier rounds may have been pruned, see
+    /// [`Graph::prune`]
+    pruned_below: RoundNum,
+    /// Events of earlier rounds may have been dropped or stripped, see
+    /// [`Graph::compact`]
+    compacted_below: RoundNum,
+    /// Witnesses with payloads dropped by [`Graph::compact`]
+    compacted: HashSet<event::Hash>,
+    /// Events of pruned or compacted rounds that were kept as chain ends,
+    /// revisited by the next [`Graph::compact`]
+    compaction_kept: HashSet<event::Hash>,
+    /// Payloads submitted for own events, see [`Graph::submit_transaction`]
+    submissions: submit::Submissions<TPayload>,
+    /// See [`Graph::set_content_policy`]
+    content_filter: Option<content::ContentFilter<TPayload>>,
+    /// Events with payloads replaced by the content policy
+    redacted: HashSet<event::Hash>,
+    /// See [`Graph::annotate`]
+    annotations: annotations::AnnotationIndex,
+    provenance: provenance::Provenance<TPeerId>,
+    /// See [`Graph::set_round_quota`]
+    round_quota: round_quota::QuotaSchedule,
+    /// See [`Graph::set_timestamp_strategy`]
+    timestamp_strategy: Arc<dyn timestamping::TimestampStrategy>,
+    /// Buffers of round and fame decisions, released when a round is
+    /// decided so that they are sized for the rounds still in elections
+    scratch: core::Scratch,
+    /// See [`Graph::state_hash`]
+    state: state::StateAccumulator,
 
     // probably move to config later
-    self_id: TPeerId,
+    /// Member authoring our events, `None` for observers, see
+    /// [`Graph::new_observer`]
+    self_id: Option<TPeerId>,
+    /// Names the graph in its sync requests: `self_id` of members, the id
+    /// given to [`Graph::new_observer`] of observers
+    sync_id: TPeerId,
     /// Coin round frequency
     coin_frequency: usize,
+    /// See [`Graph::set_adaptive_coin`]
+    adaptive_coin: Option<adaptive_coin::AdaptiveCoin>,
+    /// Adaptive frequencies of the rounds from 0, filled lazily
+    coin_schedule: Mutex<Vec<usize>>,
+    /// Seed of coin flips instead of the voters' hashes, see
+    /// [`Graph::set_coin_seed`]
+    coin_seed: Option<u64>,
+    /// See [`Graph::set_coin_strategy`]
+    coin_strategy: core::CoinStrategy,
+    /// How far ahead of our clock event timestamps may be. `None` disables the check.
+    max_clock_skew: Option<Timestamp>,
 
     /// Sign events produced by us
     signer: TSigner,
+    /// Other peers hosted by this graph with their signers, see
+    /// [`Graph::add_local_identity`]
+    other_identities: HashMap<TPeerId, TSigner>,
 
     /// Make timestamps for new events
     clock: TClock,
@@ -161,7 +360,7 @@ pub struct Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock> {
 impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
     Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
 where
-    TPayload: Serialize + Eq + std::hash::Hash + Debug + Clone,
+    TPayload: PayloadCodec + Eq + std::hash::Hash + Debug + Clone,
     TGenesisPayload: Serialize + Eq + std::hash::Hash + Debug + Clone,
     TPeerId: Serialize + Eq + std::hash::Hash + Debug + Clone,
     TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
@@ -175,22 +374,7 @@ where
         signer: TSigner,
         clock: TClock,
     ) -> Self {
-        let mut graph = Self {
-            all_events: HashMap::new(),
-            peer_index: HashMap::new(),
-            self_id: self_id.clone(),
-            round_index: vec![HashSet::new()],
-            witnesses: Mutex::new(HashMap::new()),
-            round_of: HashMap::new(),
-            ordering_data_cache: Mutex::new(HashMap::new()),
-            last_known_decided_round: None,
-            ordering: OrderedEvents::new(),
-            recognized_events: VecDeque::new(),
-            coin_frequency,
-            signer,
-            clock,
-        };
-
+        let mut graph = Self::without_genesis(self_id.clone(), coin_frequency, signer, clock);
         let genesis_timestamp = graph.clock.current_timestamp();
         let (genesis_event, genesis_sig) = SignedEvent::new(
             genesis_ordinary_payload,
@@ -207,102 +391,714 @@ where
         graph
     }
 
+    /// Graph with no events yet, authoring as `self_id`
+    fn without_genesis(
+        self_id: TPeerId,
+        coin_frequency: usize,
+        signer: TSigner,
+        clock: TClock,
+    ) -> Self {
+        Self {
+            all_events: HashMap::new(),
+            headers: HashMap::new(),
+            peer_index: HashMap::new(),
+            sorted_geneses: vec![],
+            self_id: Some(self_id.clone()),
+            sync_id: self_id,
+            round_index: RoundIndex::new(),
+            witnesses: Mutex::new(HashMap::new()),
+            round_of: HashMap::new(),
+            ordering_data_cache: Mutex::new(HashMap::new()),
+            descendancy: Mutex::new(HashMap::new()),
+            last_known_decided_round: None,
+            ordering: OrderedEvents::new(),
+            recognized_events: VecDeque::new(),
+            pending: None,
+            timings: None,
+            recently_seen: None,
+            stakes: HashMap::new(),
+            epochs: vec![],
+            pruned_below: 0,
+            compacted_below: 0,
+            compacted: HashSet::new(),
+            compaction_kept: HashSet::new(),
+            submissions: Default::default(),
+            content_filter: None,
+            redacted: HashSet::new(),
+            annotations: HashMap::new(),
+            provenance: Default::default(),
+            round_quota: Default::default(),
+            timestamp_strategy: Arc::new(timestamping::MedianTimestamp),
+            scratch: core::Scratch::default(),
+            state: Default::default(),
+            coin_frequency,
+            adaptive_coin: None,
+            coin_schedule: Mutex::new(vec![]),
+            coin_seed: None,
+            coin_strategy: core::CoinStrategy::default(),
+            max_clock_skew: None,
+            signer,
+            other_identities: HashMap::new(),
+            clock,
+        }
+    }
+
+    /// Reject events with timestamps more than `max_skew` ahead of our clock.
+    /// Such events would otherwise let their authors pull consensus timestamps
+    /// forward. Disabled by default.
+    pub fn set_max_clock_skew(&mut self, max_skew: Option<Timestamp>) {
+        self.max_clock_skew = max_skew;
+    }
+
+    /// Allow [`push_or_buffer`](Self::push_or_buffer) to keep up to `capacity`
+    /// events with unknown parents. `None` disables buffering and drops the
+    /// buffered events. Disabled by default.
+    pub fn set_pending_pool(&mut self, capacity: Option<usize>) {
+        self.set_pending_limits(capacity.map(PendingLimits::new));
+    }
+
+    /// Same as [`set_pending_pool`](Self::set_pending_pool), with the age
+    /// of buffered events and their number per author bounded as well.
+    /// When a limit is hit, the oldest affected events are evicted.
+    ///
+    /// Note that the clock is queried once per buffered event if `max_age`
+    /// is set.
+    pub fn set_pending_limits(&mut self, limits: Option<PendingLimits>) {
+        self.pending = limits.map(PendingPool::new);
+    }
+
+    /// Derive coin flips from `seed` and the voter instead of the middle bit
+    /// of the voter's hash, to explore other outcomes of coin rounds on the
+    /// same graph. Replicas agree only if they use the same seed.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn set_coin_seed(&mut self, seed: Option<u64>) {
+        self.coin_seed = seed;
+    }
+
+    /// How votes are picked in coin rounds of fame elections, see
+    /// [`core::CoinStrategy`]. All members must use the same strategy,
+    /// otherwise they may decide fame differently. Elections already decided
+    /// are not affected.
+    pub fn set_coin_strategy(&mut self, strategy: core::CoinStrategy) {
+        self.coin_strategy = strategy;
+    }
+
+    /// Record local insertion and finalization times of events from now on,
+    /// see [`event_timings`](Self::event_timings). Disabling drops the
+    /// collected data. Disabled by default.
+    ///
+    /// Note that the clock is queried once per push when enabled.
+    pub fn set_collect_timings(&mut self, enabled: bool) {
+        match (enabled, &self.timings) {
+            (true, None) => self.timings = Some(HashMap::new()),
+            (false, Some(_)) => self.timings = None,
+            _ => (),
+        }
+    }
+
+    /// Drop events received in syncs if they are among the last `capacity`
+    /// received ones, before even looking them up in the graph. Cuts the cost
+    /// of the copies arriving from several neighbours. `None` disables the
+    /// window. Disabled by default.
+    ///
+    /// Events already in the graph are never verified again, with or without
+    /// the window.
+    pub fn set_dedup_window(&mut self, capacity: Option<usize>) {
+        self.recently_seen = capacity.map(RecentlySeen::new);
+    }
+
+    /// Number of events waiting for their parents.
+    pub fn pending_count(&self) -> usize {
+        self.pending.as_ref().map(|p| p.len()).unwrap_or(0)
+    }
+
+    pub fn is_pending(&self, hash: &event::Hash) -> bool {
+        self.pending
+            .as_ref()
+            .map(|p| p.contains(hash))
+            .unwrap_or(false)
+    }
+
+    /// Host one more peer in this graph, e.g. to run a whole test cluster in
+    /// a single process. Its genesis is created right away; after that, events
+    /// on its behalf are made with [`create_event_as`](Self::create_event_as).
+    ///
+    /// Signatures of all events are still verified with the signer passed in
+    /// [`Graph::new`], so it must accept signatures made by `signer`.
+    pub fn add_local_identity(
+        &mut self,
+        id: TPeerId,
+        signer: TSigner,
+        genesis_ordinary_payload: TPayload,
+        genesis_specific_payload: TGenesisPayload,
+    ) -> Result<event::Hash, EventCreateError<TPeerId>> {
+        self.check_authoring()?;
+        if self.peer_genesis(&id).is_some() || self.other_identities.contains_key(&id) {
+            return Err(EventCreateError::IdentityAlreadyExists(id));
+        }
+        let genesis = SignedEvent::new(
+            genesis_ordinary_payload,
+            event::Kind::Genesis(genesis_specific_payload),
+            id.clone(),
+            self.clock.current_timestamp(),
+            |h| signer.sign(h),
+        )?;
+        let identifier = genesis.hash().clone();
+        let (event, signature) = genesis.into_parts();
+        self.push_event(event, signature)?;
+        self.other_identities.insert(id, signer);
+        Ok(identifier)
+    }
+
+    /// Peers we can create events for, starting with [`self_id`](Self::self_id).
+    /// None for observers.
+    pub fn local_identities(&self) -> impl Iterator<Item = &TPeerId> {
+        self.self_id.iter().chain(self.other_identities.keys())
+    }
+
+    /// Latest event of this peer, `None` for observers, they have none
+    pub fn self_tip(&self) -> Option<&event::Hash> {
+        self.peer_latest_event(self.self_id.as_ref()?)
+    }
+
     /// Create an event authored by this peer and push it to the local graph.
     pub fn create_event(
         &mut self,
         payload: TPayload,
         other_parent: event::Hash,
     ) -> Result<event::Hash, EventCreateError<TPeerId>> {
+        let self_id = self.self_id.clone().ok_or(EventCreateError::Observer)?;
+        self.create_event_as(&self_id, payload, other_parent)
+    }
+
+    /// Create an event with the other parent picked by `strategy`. If no
+    /// other peers are known, our own latest event is used instead.
+    pub fn create_event_with<S>(
+        &mut self,
+        payload: TPayload,
+        strategy: &mut S,
+    ) -> Result<event::Hash, EventCreateError<TPeerId>>
+    where
+        S: OtherParentStrategy<TPeerId>,
+    {
+        self.check_authoring()?;
+        let candidates = self.peer_candidates();
+        let other_parent = strategy
+            .choose_other_parent(&candidates)
+            .map(|i| candidates[i].tip)
+            .or_else(|| self.self_tip())
+            .cloned()
+            .ok_or(EventCreateError::NoOtherParent)?;
+        self.create_event(payload, other_parent)
+    }
+
+    /// Create an event on behalf of a local identity (either `self_id` or
+    /// one added by [`add_local_identity`](Self::add_local_identity)).
+    pub fn create_event_as(
+        &mut self,
+        author: &TPeerId,
+        payload: TPayload,
+        other_parent: event::Hash,
+    ) -> Result<event::Hash, EventCreateError<TPeerId>> {
+        self.check_authoring()?;
+        let timestamp = self.clock.current_timestamp();
+        let signer = if Some(author) == self.self_id.as_ref() {
+            &self.signer
+        } else {
+            self.other_identities
+                .get(author)
+                .ok_or_else(|| EventCreateError::NotLocalIdentity(author.clone()))?
+        };
         let self_parent = self
-            .peer_latest_event(&self.self_id)
-            .expect("Peer must know itself")
+            .peer_latest_event(author)
+            .expect("Local identities have geneses")
             .clone();
+        let parents = Parents {
+            self_parent,
+            other_parent,
+        };
+        self.check_round_quota(author, parents.clone())?;
         let event = SignedEvent::new(
+            payload,
+            event::Kind::Regular(parents),
+            author.clone(),
+            timestamp,
+            |h| signer.sign(h),
+        )?;
+        let identifier = event.hash().clone();
+        let (event, signature) = event.into_parts();
+        self.push_event(event, signature)?;
+        Ok(identifier)
+    }
+
+    /// Event of `author` on top of its latest one, not signed yet. For
+    /// members whose events are signed outside of the graph, e.g. jointly
+    /// by a group (see [`threshold`](crate::algorithm::threshold)). Push it
+    /// with [`push_event`](Self::push_event) once signed.
+    pub fn draft_event(
+        &mut self,
+        author: &TPeerId,
+        payload: TPayload,
+        other_parent: event::Hash,
+    ) -> Result<UnsignedEvent<TPayload, TGenesisPayload, TPeerId>, EventCreateError<TPeerId>> {
+        self.check_authoring()?;
+        let self_parent = self
+            .peer_latest_event(author)
+            .ok_or_else(|| EventCreateError::UnknownPeer(author.clone()))?
+            .clone();
+        let event = UnsignedEvent::draft(
             payload,
             event::Kind::Regular(Parents {
                 self_parent,
                 other_parent,
             }),
-            self.self_id.clone(),
+            author.clone(),
             self.clock.current_timestamp(),
-            |h| self.signer.sign(h),
         )?;
-        let identifier = event.hash().clone();
-        let (event, signature) = event.into_parts();
-        self.push_event(event, signature)?;
-        Ok(identifier)
+        Ok(event)
+    }
+
+    /// Same as [`push_event`](Self::push_event), but events with unknown parents
+    /// are kept in the pending pool instead of being rejected (if the pool is
+    /// enabled with [`set_pending_pool`](Self::set_pending_pool)).
+    ///
+    /// Buffered events are inserted automatically once their parents arrive,
+    /// they can be observed via [`next_resolved_orphan`](Self::next_resolved_orphan).
+    pub fn push_or_buffer(
+        &mut self,
+        event: UnsignedEvent<TPayload, TGenesisPayload, TPeerId>,
+        signature: Signature,
+    ) -> Result<PushOutcome, PushError<TPeerId>> {
+        if self.pending.is_none() {
+            return self
+                .push_event(event, signature)
+                .map(|_| PushOutcome::Inserted);
+        }
+        let missing = self.missing_parents(&event);
+        let first_missing = event
+            .fields()
+            .kind()
+            .parents()
+            .find(|p| missing.contains(p));
+        let Some(missing_parent) = first_missing.cloned() else {
+            return self
+                .push_event(event, signature)
+                .map(|_| PushOutcome::Inserted);
+        };
+        debug!("Parent {} is unknown, buffering the event", missing_parent);
+        let evicted = self.buffer_orphan(event, signature, missing);
+        Ok(PushOutcome::Buffered {
+            missing_parent,
+            evicted,
+        })
+    }
+
+    /// Orphan events that were buffered and later inserted into the graph,
+    /// in the order of insertion.
+    ///
+    /// Only the latest [`capacity`](PendingLimits::capacity) of them are
+    /// kept, so drain this regularly if every one of them matters. Events
+    /// pruned since are skipped.
+    pub fn next_resolved_orphan(
+        &mut self,
+    ) -> Option<&EventWrapper<TPayload, TGenesisPayload, TPeerId>> {
+        let pending = self.pending.as_mut()?;
+        let hash = std::iter::from_fn(|| pending.next_resolved())
+            .find(|hash| self.all_events.contains_key(hash))?;
+        self.all_events.get(&hash)
+    }
+
+    fn missing_parents(
+        &self,
+        event: &UnsignedEvent<TPayload, TGenesisPayload, TPeerId>,
+    ) -> HashSet<event::Hash> {
+        event
+            .fields()
+            .kind()
+            .parents()
+            .filter(|p| !self.all_events.contains_key(p))
+            .cloned()
+            .collect()
+    }
+
+    fn buffer_orphan(
+        &mut self,
+        event: UnsignedEvent<TPayload, TGenesisPayload, TPeerId>,
+        signature: Signature,
+        missing: HashSet<event::Hash>,
+    ) -> Vec<event::Hash> {
+        let pending = self.pending.as_ref().expect("checked by callers");
+        // The clock is only needed for expiry
+        let now = match pending.limits().max_age {
+            Some(_) => self.clock.current_timestamp(),
+            None => 0,
+        };
+        let pending = self.pending.as_mut().expect("checked above");
+        let evicted = pending.insert(event, signature, missing, now);
+        metrics::pending_orphans(self.pending_count());
+        evicted
+    }
+
+    /// Insert buffered events that were waiting for `parent` (and, in turn,
+    /// the ones waiting for them).
+    fn resolve_orphans(&mut self, parent: event::Hash) {
+        let mut inserted = vec![parent];
+        while let Some(parent) = inserted.pop() {
+            let Some(pending) = self.pending.as_mut() else {
+                return;
+            };
+            for (event, signature) in pending.take_dependents(&parent) {
+                // Parents are tracked by the pool, this is only a safeguard
+                let missing = self.missing_parents(&event);
+                if !missing.is_empty() {
+                    trace!("Orphan still waits for {:?}", missing);
+                    self.buffer_orphan(event, signature, missing);
+                    continue;
+                }
+                let hash = event.hash().clone();
+                let started = Instant::now();
+                match self.insert_event(event, signature) {
+                    Ok(()) => {
+                        metrics::event_pushed(started);
+                        debug!("Orphan {} is inserted", hash);
+                        let pending = self.pending.as_mut().expect("checked above");
+                        pending.mark_resolved(hash.clone());
+                        inserted.push(hash);
+                    }
+                    Err(e) => debug!("Dropping invalid orphan {}: {:?}", hash, e),
+                }
+            }
+        }
     }
 
     /// Create and push event to the graph, adding it at the end of `author`'s lane
     /// (i.e. the event becomes the latest one of the peer).
     ///
     /// Errors are expected to leave the graph in consistent state
-    #[instrument(level = "error", skip_all)]
-    #[instrument(level = "trace", skip_all, fields(event=event.compact_fmt()))]
     pub fn push_event(
         &mut self,
         event: UnsignedEvent<TPayload, TGenesisPayload, TPeerId>,
         signature: Signature,
     ) -> Result<(), PushError<TPeerId>> {
-        // Verification first, no changing state
-        debug!("Validating the event");
-        trace!("Signature: {:?}", signature);
-        let genesis_payload = match event.fields().kind() {
-            event::Kind::Genesis(payload) => payload,
-            event::Kind::Regular(_) => {
-                let peer_author = event.fields().author();
-                let genesis_hash = self
-                    .peer_genesis(peer_author)
-                    .ok_or_else(|| PushError::PeerNotFound(peer_author.clone()))?;
-                let genesis = self.all_events.get(genesis_hash).expect(&format!(
-                    "Genesis of a peer is not tracked (peer: {:?}, genesis: {})",
-                    peer_author, genesis_hash
-                ));
-                let event::Kind::Genesis(gen_payload) = genesis.kind() else {
-                    panic!("Already verified genesis {} doesn't have `Genesis` kind", genesis_hash)
-                };
-                gen_payload
+        let hash = event.hash().clone();
+        let started = Instant::now();
+        let result = self.insert_event(event, signature);
+        self.finish_push(hash, started, result)
+    }
+
+    /// First half of [`push_event`](Self::push_event): the checks that don't
+    /// need the graph structure, i.e. the hash, the signature and the
+    /// content filter. It only reads the graph, so with a
+    /// [`SharedGraph`](shared::SharedGraph) network threads can verify
+    /// events under the read lock, leaving only [`commit`](Self::commit)
+    /// to the consensus thread.
+    ///
+    /// Fails with [`EventAlreadyExists`](PushError::EventAlreadyExists),
+    /// [`HashMismatch`](PushError::HashMismatch),
+    /// [`PeerNotFound`](PushError::PeerNotFound) (for regular events),
+    /// [`InvalidSignature`](PushError::InvalidSignature) or the errors of
+    /// the content filter.
+    pub fn preverify(
+        &self,
+        event: UnsignedEvent<TPayload, TGenesisPayload, TPeerId>,
+        signature: Signature,
+    ) -> Result<VerifiedEvent<TPayload, TGenesisPayload, TPeerId>, PushError<TPeerId>> {
+        if self.all_events.contains_key(event.hash()) {
+            return Err(PushError::EventAlreadyExists(event.hash().clone()));
+        }
+        let author_genesis = self.author_genesis(event.fields().author());
+        // The clock needs `&mut self`, the skew is checked on commit
+        self.validator(None)
+            .validate(event, signature, author_genesis)
+    }
+
+    /// Second half of [`push_event`](Self::push_event): insert an event
+    /// checked by [`preverify`](Self::preverify) of this graph, updating
+    /// the indices, rounds and fame. Buffered orphans are resolved the same
+    /// way.
+    ///
+    /// Fails with [`EventAlreadyExists`](PushError::EventAlreadyExists) (if
+    /// the event was inserted since it was verified),
+    /// [`TimestampFromFuture`](PushError::TimestampFromFuture),
+    /// [`GenesisAlreadyExists`](PushError::GenesisAlreadyExists),
+    /// [`NoParent`](PushError::NoParent),
+    /// [`IncorrectAuthor`](PushError::IncorrectAuthor),
+    /// [`PeerNotFound`](PushError::PeerNotFound) or
+    /// [`VerifiedWithOtherGenesis`](PushError::VerifiedWithOtherGenesis)
+    /// (if the event was verified by a graph where its author has another
+    /// genesis).
+    pub fn commit(
+        &mut self,
+        verified: VerifiedEvent<TPayload, TGenesisPayload, TPeerId>,
+    ) -> Result<(), PushError<TPeerId>> {
+        let hash = verified.hash().clone();
+        let started = Instant::now();
+        let result = self.commit_verified(verified);
+        self.finish_push(hash, started, result)
+    }
+
+    fn commit_verified(
+        &mut self,
+        verified: VerifiedEvent<TPayload, TGenesisPayload, TPeerId>,
+    ) -> Result<(), PushError<TPeerId>> {
+        if self.all_events.contains_key(verified.hash()) {
+            return Err(PushError::EventAlreadyExists(verified.hash().clone()));
+        }
+        // The signature holds only for the genesis it was checked with,
+        // e.g. not if the event was verified by another graph
+        if verified.author_genesis.is_some() {
+            let author = verified.event().unsigned().fields().author();
+            match self.peer_genesis(author) {
+                None => {
+                    return Err(PushError::PeerNotFound {
+                        event: verified.hash().clone(),
+                        peer: author.clone(),
+                    })
+                }
+                Some(genesis) if Some(genesis) != verified.author_genesis.as_ref() => {
+                    return Err(PushError::VerifiedWithOtherGenesis {
+                        event: verified.hash().clone(),
+                        author: author.clone(),
+                    })
+                }
+                Some(_) => (),
+            }
+        }
+        let max_timestamp = self.max_timestamp();
+        validation::check_timestamp(verified.event().unsigned(), max_timestamp)?;
+        self.commit_event(verified)
+    }
+
+    fn finish_push(
+        &mut self,
+        hash: event::Hash,
+        started: Instant,
+        result: Result<(), PushError<TPeerId>>,
+    ) -> Result<(), PushError<TPeerId>> {
+        match &result {
+            Ok(()) => metrics::event_pushed(started),
+            Err(e) => metrics::push_rejected(e.category(), started),
+        }
+        result?;
+        if self.pending.is_some() {
+            self.resolve_orphans(hash);
+        }
+        self.report_state_metrics();
+        Ok(())
+    }
+
+    /// Apply the events pushed with the request (if any), leaving the
+    /// request without them. The sender is recorded as their
+    /// [relay](provenance).
+    pub fn apply_sync_request(
+        &mut self,
+        request: &mut sync::SyncRequest<TPayload, TGenesisPayload, TPeerId>,
+    ) -> Result<usize, PushError<TPeerId>> {
+        match request.events.take() {
+            Some(jobs) => self.apply_jobs(jobs, Some(&request.from)),
+            None => Ok(0),
+        }
+    }
+
+    /// Push events received in a sync, in the order given. Events that are
+    /// already known are skipped, the first other error stops the process.
+    ///
+    /// Returns number of new events.
+    pub fn apply_sync_jobs(
+        &mut self,
+        jobs: sync::Jobs<TPayload, TGenesisPayload, TPeerId>,
+    ) -> Result<usize, PushError<TPeerId>> {
+        self.apply_jobs(jobs, None)
+    }
+
+    /// Same as [`apply_sync_jobs`](Self::apply_sync_jobs), recording
+    /// `relay` as the peer that delivered the events, see [`provenance`]
+    pub fn apply_sync_jobs_from(
+        &mut self,
+        relay: &TPeerId,
+        jobs: sync::Jobs<TPayload, TGenesisPayload, TPeerId>,
+    ) -> Result<usize, PushError<TPeerId>> {
+        self.apply_jobs(jobs, Some(relay))
+    }
+
+    #[instrument(
+        name = "apply_sync_jobs",
+        level = "debug",
+        skip_all,
+        fields(jobs = jobs.as_linear().len(), applied = field::Empty)
+    )]
+    fn apply_jobs(
+        &mut self,
+        jobs: sync::Jobs<TPayload, TGenesisPayload, TPeerId>,
+        relay: Option<&TPeerId>,
+    ) -> Result<usize, PushError<TPeerId>> {
+        use provenance::Delivery;
+        let record = |graph: &mut Self, hash: &event::Hash, delivery| {
+            if let Some(relay) = relay {
+                graph.provenance.record(relay, hash, delivery);
+            }
+        };
+        let mut applied = 0;
+        for event in jobs.into_linear() {
+            let hash = event.hash().clone();
+            if let Some(seen) = &self.recently_seen {
+                if seen.contains(&hash) {
+                    metrics::sync_event_received(metrics::SyncEventOutcome::RecentDuplicate);
+                    record(self, &hash, Delivery::Known);
+                    continue;
+                }
+            }
+            let (unsigned, signature) = event.into_parts();
+            let result = self.push_event(unsigned, signature);
+            if let (Some(seen), Ok(()) | Err(PushError::EventAlreadyExists(_))) =
+                (&mut self.recently_seen, &result)
+            {
+                seen.insert(hash.clone());
+            }
+            match result {
+                Ok(()) => {
+                    metrics::sync_event_received(metrics::SyncEventOutcome::New);
+                    record(self, &hash, Delivery::First);
+                    applied += 1
+                }
+                Err(PushError::EventAlreadyExists(_)) => {
+                    metrics::sync_event_received(metrics::SyncEventOutcome::KnownDuplicate);
+                    record(self, &hash, Delivery::Known);
+                }
+                Err(e) => {
+                    record(self, &hash, Delivery::Rejected);
+                    Span::current().record("applied", applied);
+                    return Err(e);
+                }
             }
+        }
+        Span::current().record("applied", applied);
+        Ok(applied)
+    }
+
+    fn report_state_metrics(&self) {
+        metrics::pending_orphans(self.pending_count());
+        let latest_round = self.round_index.len() - 1;
+        let behind = match self.last_known_decided_round {
+            Some(decided) => latest_round - decided,
+            None => latest_round + 1,
         };
-        let genesis_payload = genesis_payload.clone();
+        metrics::rounds_behind_finality(behind);
+    }
+
+    #[instrument(
+        name = "push_event",
+        level = "debug",
+        skip_all,
+        fields(
+            event = %event.hash().to_compact_hex(),
+            author = ?event.fields().author(),
+            round = field::Empty,
+            witness = field::Empty,
+        )
+    )]
+    fn insert_event(
+        &mut self,
+        event: UnsignedEvent<TPayload, TGenesisPayload, TPeerId>,
+        signature: Signature,
+    ) -> Result<(), PushError<TPeerId>> {
+        // Verification first, no changing state
+        trace!("Validating the event: {}", event.compact_fmt());
+        trace!("Signature: {:?}", signature);
+        // Before the signature check, which is the most expensive part. The
+        // hash doesn't cover the signature, so the known one is kept.
+        if self.all_events.contains_key(event.hash()) {
+            return Err(PushError::EventAlreadyExists(event.hash().clone()));
+        }
         trace!("Verify signature");
-        let event = SignedEvent::with_signature(event, signature, |hash, signature, author| {
-            self.signer
-                .verify(hash, signature, author, &genesis_payload)
-        })?;
-        trace!("Event hash: {}", event.hash());
+        let max_timestamp = self.max_timestamp();
+        let author_genesis = self.author_genesis(event.fields().author());
+        let validated = self
+            .validator(max_timestamp)
+            .validate(event, signature, author_genesis)?;
+        self.commit_event(validated)
+    }
 
-        let new_event = EventWrapper::new(event);
+    /// Local time and the allowed skew, if limited
+    fn max_timestamp(&mut self) -> Option<(Timestamp, Timestamp)> {
+        let max_skew = self.max_clock_skew?;
+        Some((self.clock.current_timestamp(), max_skew))
+    }
 
-        trace!("Testing if event is already known");
-        if self.all_events.contains_key(new_event.inner().hash()) {
-            return Err(PushError::EventAlreadyExists(
-                new_event.inner().hash().clone(),
-            ));
+    fn validator(
+        &self,
+        max_timestamp: Option<(Timestamp, Timestamp)>,
+    ) -> validation::Validator<'_, TPayload, TSigner> {
+        validation::Validator {
+            signer: &self.signer,
+            content_filter: self.content_filter.as_ref(),
+            max_timestamp,
         }
+    }
+
+    fn author_genesis(&self, author: &TPeerId) -> Option<(event::Hash, TGenesisPayload)> {
+        let genesis_hash = self.peer_genesis(author)?;
+        let genesis = self.all_events.get(genesis_hash).unwrap_or_else(|| {
+            panic!(
+                "Genesis of a peer is not tracked (peer: {:?}, genesis: {})",
+                author, genesis_hash
+            )
+        });
+        let event::Kind::Genesis(gen_payload) = genesis.kind() else {
+            panic!(
+                "Already verified genesis {} doesn't have `Genesis` kind",
+                genesis_hash
+            )
+        };
+        Some((genesis_hash.clone(), gen_payload.clone()))
+    }
+
+    /// Insert an event that passed [`validation`], updating the indices,
+    /// rounds and ordering. Checks that need the graph structure (parents,
+    /// authors) are done here.
+    fn commit_event(
+        &mut self,
+        validated: VerifiedEvent<TPayload, TGenesisPayload, TPeerId>,
+    ) -> Result<(), PushError<TPeerId>> {
+        let VerifiedEvent {
+            event, redacted, ..
+        } = validated;
+        let new_event = EventWrapper::new(event);
 
         trace!("Performing checks or updates specific to genesis or regular events");
         match new_event.kind() {
             event::Kind::Genesis(_) => {
                 trace!("It is a genesis event");
-                if self.peer_index.contains_key(&new_event.author()) {
-                    return Err(PushError::GenesisAlreadyExists);
+                if let Some(existing) = self.peer_genesis(new_event.author()) {
+                    return Err(PushError::GenesisAlreadyExists {
+                        peer: new_event.author().clone(),
+                        existing: existing.clone(),
+                    });
                 }
                 debug!("The event is valid, updating state to include it");
-                let new_peer_index = PeerIndexEntry::new(new_event.inner().hash().clone());
+                let genesis = new_event.inner().hash();
+                let position = self.sorted_geneses.partition_point(|g| g < genesis);
+                self.sorted_geneses.insert(position, genesis.clone());
+                let new_peer_index = PeerIndexEntry::new(genesis.clone());
                 self.peer_index
                     .insert(new_event.author().clone(), new_peer_index);
             }
             event::Kind::Regular(parents) => {
                 trace!("It is a regular event");
                 trace!("Checking presence of parents");
-                if !self.all_events.contains_key(&parents.self_parent) {
-                    return Err(PushError::NoParent(parents.self_parent.clone()));
-                }
-                if !self.all_events.contains_key(&parents.other_parent) {
-                    return Err(PushError::NoParent(parents.other_parent.clone()));
+                if let Some(missing_parent) = [&parents.self_parent, &parents.other_parent]
+                    .into_iter()
+                    .find(|p| !self.all_events.contains_key(p))
+                {
+                    return Err(PushError::NoParent {
+                        event: new_event.hash().clone(),
+                        author: new_event.author().clone(),
+                        missing_parent: missing_parent.clone(),
+                        local_tip: self.peer_latest_event(new_event.author()).cloned(),
+                    });
                 }
 
+                let events_dropped = self.events_dropped();
                 // taking mutable for update later
                 let self_parent_event = self
                     .all_events
@@ -317,17 +1113,21 @@ where
                         self_parent_event.author(),
                         new_event.author()
                     );
-                    return Err(PushError::IncorrectAuthor(
-                        self_parent_event.author().clone(),
-                        new_event.author().clone(),
-                    ));
+                    return Err(PushError::IncorrectAuthor {
+                        event: new_event.hash().clone(),
+                        expected: self_parent_event.author().clone(),
+                        provided: new_event.author().clone(),
+                    });
                 }
 
                 // taking mutable for update later
-                let author_index = self
-                    .peer_index
-                    .get_mut(&new_event.author())
-                    .ok_or(PushError::PeerNotFound(new_event.author().clone()))?;
+                let author_index =
+                    self.peer_index.get_mut(new_event.author()).ok_or_else(|| {
+                        PushError::PeerNotFound {
+                            event: new_event.hash().clone(),
+                            peer: new_event.author().clone(),
+                        }
+                    })?;
 
                 // Insertion, should be valid at this point so that we don't leave in inconsistent state on error.
                 debug!("The event is valid, updating state to include it");
@@ -350,7 +1150,11 @@ where
                             let kind = e.kind();
                             match kind {
                                 event::Kind::Genesis(_) => vec![],
-                                event::Kind::Regular(p) => vec![&p.self_parent, &p.other_parent],
+                                event::Kind::Regular(p) => [&p.self_parent, &p.other_parent]
+                                    .into_iter()
+                                    // Ancestors of the oldest events may be pruned
+                                    .filter(|p| !events_dropped || self.all_events.contains_key(p))
+                                    .collect(),
                             }
                         })
                     },
@@ -374,8 +1178,31 @@ where
         // Index the event and save
         trace!("Tracking the event");
         let hash = new_event.inner().hash().clone();
+        let parents = match new_event.kind() {
+            event::Kind::Genesis(_) => None,
+            event::Kind::Regular(parents) => Some(parents.clone()),
+        };
+        let (slot, sequence) = match &parents {
+            Some(parents) => {
+                let self_parent = self
+                    .headers
+                    .get(&parents.self_parent)
+                    .expect("Just checked self parent presence");
+                (self_parent.slot, self_parent.sequence + 1)
+            }
+            // Peers are never removed from the index, so the slots of
+            // earlier peers are never given out again
+            None => (self.peer_index.len() - 1, 0),
+        };
+        self.headers.insert(
+            hash.clone(),
+            headers::EventHeader::new(parents, new_event.author().clone(), slot, sequence),
+        );
         self.all_events.insert(hash.clone(), new_event);
         self.recognized_events.push_front(hash.clone());
+        if redacted {
+            self.redacted.insert(hash.clone());
+        }
 
         // Set round
         trace!("Calculating round");
@@ -383,61 +1210,87 @@ where
         let r = self
             .determine_round(&hash)
             .expect("The event was just added to tracking");
-        // Cache result
-        trace!("Caching the result");
+        Span::current().record("round", r);
         self.round_of.insert(hash.clone(), r);
+        self.header_mut(&hash).round = Some(r);
         if r > last_idx {
             // Create a new round
             trace!("Creating new round in index");
             let mut round_hs = HashSet::new();
             round_hs.insert(hash.clone());
             self.round_index.push(round_hs);
-        } else {
+        } else if let Some(round) = self.round_index.get_mut(r) {
             // Otherwise push onto appropriate round
             trace!("Inserting event into existing round index");
-            self.round_index[r].insert(hash.clone());
+            round.insert(hash.clone());
+        } else {
+            // Late event of a pruned round, whose fame is decided already
+            trace!("Round of the event is pruned, not indexing it");
         }
 
-        // Set witness status
-        trace!("Checking if the event is witness");
-        if self
-            .determine_witness(&hash)
-            .expect("Just inserted to `all_events`")
-        {
-            debug!("Event is a witness, performing additional checks");
-            trace!("Adding event to witness index");
-            self.witnesses
-                .lock()
-                .unwrap()
-                .insert(hash.clone(), WitnessFamousness::Undecided);
+        let ordered_before = self.ordering.len();
 
+        // Set witness status
+        trace!("Determining witness status");
+        let is_witness = self
+            .recompute_witness(&hash)
+            .expect("Just inserted to `all_events`");
+        Span::current().record("witness", is_witness);
+        if is_witness {
             // Update fame of previous rounds, if changed
             trace!("Updating fame and adding events to ordering");
             self.handle_ordering();
-        } else {
-            debug!("Event is not a witness");
         }
+        if self.timings.is_some() {
+            self.record_timings(hash, ordered_before);
+        }
+        debug!("Event is inserted");
         Ok(())
     }
 
+    fn record_timings(&mut self, inserted: event::Hash, ordered_before: usize) {
+        let now = self.clock.current_timestamp();
+        let Some(timings) = self.timings.as_mut() else {
+            return;
+        };
+        timings.insert(
+            inserted,
+            EventTimings {
+                inserted_at: now,
+                finalized_at: None,
+            },
+        );
+        for finalized in self.ordering.ordered().skip(ordered_before) {
+            if let Some(t) = timings.get_mut(finalized) {
+                t.finalized_at = Some(now);
+            }
+        }
+    }
+
     pub fn next_recognized_event(
         &mut self,
     ) -> Option<&EventWrapper<TPayload, TGenesisPayload, TPeerId>> {
-        self.recognized_events.pop_back().map(|hash| {
-            self.all_events
-                .get(&hash)
-                .expect("seen events must be tracked")
-        })
+        // Pruned events are skipped
+        while let Some(hash) = self.recognized_events.pop_back() {
+            if self.all_events.contains_key(&hash) {
+                return self.all_events.get(&hash);
+            }
+        }
+        None
     }
 
     pub fn next_finalized_event(
         &mut self,
     ) -> Option<&EventWrapper<TPayload, TGenesisPayload, TPeerId>> {
-        self.ordering.next_event().map(|hash| {
-            self.all_events
-                .get(hash)
-                .expect("ordered events must be tracked")
-        })
+        let hash = self.ordering.next_event()?;
+        if let Some(header) = self.headers.get_mut(hash) {
+            header.delivered = true;
+        }
+        let event = self
+            .all_events
+            .get(hash)
+            .expect("ordered events must be tracked");
+        Some(event)
     }
 }
 
@@ -449,36 +1302,235 @@ where
     TGenesisPayload: Clone,
     TPeerId: Eq + std::hash::Hash + Clone + Debug,
 {
-    #[instrument(level = "debug", skip(self))]
+    /// Known peers other than local identities, with some statistics for
+    /// [`strategy`](crate::algorithm::strategy) decisions.
+    ///
+    /// Candidates are ordered by genesis hash, so that strategies returning an
+    /// index into the list pick the same peer on every run.
+    pub fn peer_candidates(&self) -> Vec<PeerCandidate<'_, TPeerId>> {
+        let nothing = HashSet::new();
+        let our_known = self
+            .self_id
+            .as_ref()
+            .and_then(|id| self.peer_index.get(id))
+            .map_or(&nothing, |index| index.known_events());
+        let mut entries: Vec<_> = self
+            .peer_index
+            .iter()
+            .filter(|(peer, _)| {
+                Some(*peer) != self.self_id.as_ref() && !self.other_identities.contains_key(*peer)
+            })
+            .collect();
+        entries.sort_by(|(_, a), (_, b)| a.origin().as_ref().cmp(b.origin().as_ref()));
+        entries
+            .into_iter()
+            .map(|(peer, index)| PeerCandidate {
+                peer,
+                // several tips only when the peer forked, take the same one each time
+                tip: index
+                    .latest_events()
+                    .iter()
+                    .min_by(|a, b| a.as_ref().cmp(b.as_ref()))
+                    .expect("At least single latest event should be present"),
+                unknown_to_us: index.known_events().difference(our_known).count(),
+                unknown_to_peer: self
+                    .all_events
+                    .len()
+                    .saturating_sub(index.known_events().len()),
+            })
+            .collect()
+    }
+
+    /// Peer to sync with next, according to `strategy`.
+    pub fn choose_sync_peer<S>(&self, strategy: &mut S) -> Option<TPeerId>
+    where
+        S: PeerSelectionStrategy<TPeerId>,
+    {
+        let candidates = self.peer_candidates();
+        strategy
+            .choose_peer(&candidates)
+            .map(|i| candidates[i].peer.clone())
+    }
+
+    #[instrument(level = "debug", skip(self), fields(jobs = field::Empty))]
     pub fn generate_sync_for(
         &self,
         peer: &TPeerId,
     ) -> Result<sync::Jobs<TPayload, TGenesisPayload, TPeerId>, sync::Error> {
-        let empty_set = HashSet::new();
-        let peer_known_events = self
-            .peer_index
-            .get(peer)
-            .map(|index| index.known_events())
-            .unwrap_or(&empty_set);
-        trace!(
-            "Found {} events that `peer` definitely knows",
-            peer_known_events.len()
-        );
+        let peer_knowledge = self.peer_knowledge(peer).unwrap_or_default();
+        let jobs = self.jobs_for(&peer_knowledge)?;
+        Span::current().record("jobs", jobs.as_linear().len());
+        Ok(jobs)
+    }
+
+    /// Latest events of each author we know. Ordered by the tip hashes, so
+    /// equal states have equal summaries.
+    pub fn summary(&self) -> sync::Summary<TPeerId> {
+        sync::Summary {
+            tips: self
+                .peer_index
+                .iter()
+                .map(|(peer, index)| {
+                    let mut tips: Vec<_> = index.latest_events().iter().cloned().collect();
+                    tips.sort();
+                    (peer.clone(), tips)
+                })
+                .sorted_by(|(_, a), (_, b)| a.cmp(b))
+                .collect(),
+        }
+    }
+
+    pub fn sync_request(&self) -> sync::SyncRequest<TPayload, TGenesisPayload, TPeerId> {
+        sync::SyncRequest {
+            from: self.sync_id.clone(),
+            summary: self.summary(),
+            events: None,
+        }
+    }
+
+    /// Request carrying the events missing in `peer_summary`, for the
+    /// pull-only mode.
+    pub fn sync_request_pushing(
+        &self,
+        peer_summary: &sync::Summary<TPeerId>,
+    ) -> Result<sync::SyncRequest<TPayload, TGenesisPayload, TPeerId>, sync::Error> {
+        let jobs = self.jobs_for(&self.knowledge_from_summary(peer_summary))?;
+        Ok(sync::SyncRequest {
+            events: (!jobs.as_linear().is_empty()).then_some(jobs),
+            ..self.sync_request()
+        })
+    }
+
+    /// Same as [`generate_sync_for`](Self::generate_sync_for), but the peer's
+    /// knowledge is taken from its request instead of our observations.
+    /// Tips unknown to us are ignored.
+    #[instrument(level = "debug", skip_all, fields(from = ?request.from, jobs = field::Empty))]
+    pub fn generate_sync_for_request(
+        &self,
+        request: &sync::SyncRequest<TPayload, TGenesisPayload, TPeerId>,
+    ) -> Result<sync::Jobs<TPayload, TGenesisPayload, TPeerId>, sync::Error> {
+        let peer_knowledge = self.knowledge_from_summary(&request.summary);
+        let jobs = self.jobs_for(&peer_knowledge)?;
+        Span::current().record("jobs", jobs.as_linear().len());
+        Ok(jobs)
+    }
+
+    /// Same as [`generate_sync_for_request`](Self::generate_sync_for_request)
+    /// for a bare summary, when the transport doesn't tell who is asking
+    pub fn generate_sync_for_summary(
+        &self,
+        summary: &sync::Summary<TPeerId>,
+    ) -> Result<sync::Jobs<TPayload, TGenesisPayload, TPeerId>, sync::Error> {
+        self.jobs_for(&self.knowledge_from_summary(summary))
+    }
+
+    /// [`generate_sync_for_request`](Self::generate_sync_for_request) for
+    /// several requests, sharing a single traversal of the graph. Jobs are
+    /// in the order of the requests.
+    #[instrument(level = "debug", skip_all, fields(requests = requests.len()))]
+    pub fn generate_sync_for_requests(
+        &self,
+        requests: &[sync::SyncRequest<TPayload, TGenesisPayload, TPeerId>],
+    ) -> Result<Vec<sync::Jobs<TPayload, TGenesisPayload, TPeerId>>, sync::Error> {
+        let knowledge: Vec<_> = requests
+            .iter()
+            .map(|request| self.knowledge_from_summary(&request.summary))
+            .collect();
+        self.jobs_for_many(&knowledge.iter().collect::<Vec<_>>())
+    }
+
+    /// Events that `peer` knows according to our observations, `None` for
+    /// unknown peers.
+    pub fn peer_knowledge(&self, peer: &TPeerId) -> Option<sync::Knowledge<TPeerId>> {
+        let index = self.peer_index.get(peer)?;
+        Some(self.knowledge_of(index.latest_events().iter()))
+    }
+
+    /// Events that the sender of `summary` knows. Tips unknown to us are
+    /// ignored.
+    pub fn knowledge_from_summary(
+        &self,
+        summary: &sync::Summary<TPeerId>,
+    ) -> sync::Knowledge<TPeerId> {
+        self.knowledge_of(summary.tips())
+    }
+
+    /// Ancestry of `tips`. Chains of authors are walked down to what is
+    /// known already, so each event is looked at once.
+    fn knowledge_of<'a>(
+        &self,
+        tips: impl Iterator<Item = &'a event::Hash>,
+    ) -> sync::Knowledge<TPeerId> {
+        let mut knowledge = sync::Knowledge::new();
+        // Events of forking authors from the first fork on share sequence
+        // numbers, so they are tracked by hashes
+        let mut fork_floors: HashMap<&TPeerId, u64> = HashMap::new();
+        let mut to_visit: Vec<&event::Hash> = tips.collect();
+        while let Some(next) = to_visit.pop() {
+            let Some(header) = self.headers.get(next) else {
+                continue;
+            };
+            let author = &header.author;
+            if knowledge.knows(next, author, header.sequence) {
+                continue;
+            }
+            let floor = *fork_floors
+                .entry(author)
+                .or_insert_with(|| self.fork_floor(author));
+            let mark = knowledge.watermark(author);
+            let mut top = None;
+            let mut current = Some((next, header));
+            while let Some((hash, header)) = current {
+                if header.sequence < floor {
+                    if mark.is_some_and(|mark| header.sequence <= mark) {
+                        break;
+                    }
+                    top.get_or_insert(header.sequence);
+                } else {
+                    if knowledge.knows(hash, author, header.sequence) {
+                        break;
+                    }
+                    knowledge.insert(hash.clone(), author.clone(), header.sequence);
+                }
+                current = header.parents.as_ref().and_then(|parents| {
+                    to_visit.push(&parents.other_parent);
+                    // May be pruned
+                    self.headers
+                        .get(&parents.self_parent)
+                        .map(|header| (&parents.self_parent, header))
+                });
+            }
+            if let Some(top) = top {
+                knowledge.raise(author, top);
+            }
+        }
+        knowledge
+    }
+
+    fn jobs_for(
+        &self,
+        peer_knowledge: &sync::Knowledge<TPeerId>,
+    ) -> Result<sync::Jobs<TPayload, TGenesisPayload, TPeerId>, sync::Error> {
+        let mut jobs = self.jobs_for_many(&[peer_knowledge])?;
+        Ok(jobs.pop().expect("jobs are generated for each peer"))
+    }
+
+    fn jobs_for_many(
+        &self,
+        peer_knowledge: &[&sync::Knowledge<TPeerId>],
+    ) -> Result<Vec<sync::Jobs<TPayload, TGenesisPayload, TPeerId>>, sync::Error> {
         let tips = self
             .peer_index
             .values()
             .flat_map(|index| index.latest_events().iter())
             .cloned();
-        sync::Jobs::generate(
-            self,
-            |h| peer_known_events.contains(h),
-            tips,
-            |h| {
-                self.all_events
-                    .get(h)
-                    .map(|wrapper| (*wrapper.inner()).clone())
-            },
-        )
+        let jobs = sync::Jobs::generate_many(peer_knowledge, tips, |h| {
+            Some((self.all_events.get(h)?, self.headers.get(h)?.sequence))
+        })?;
+        Ok(jobs
+            .into_iter()
+            .map(|jobs| sync::Jobs::from_linear(self.withhold_redacted(jobs.into_linear())))
+            .collect())
     }
 }
 
@@ -487,7 +1539,7 @@ impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
 where
     TPayload: Eq + std::hash::Hash + Clone,
     TGenesisPayload: Eq + std::hash::Hash + Clone,
-    TPeerId: Eq + std::hash::Hash,
+    TPeerId: Eq + std::hash::Hash + Clone,
 {
     #[instrument(level = "debug", skip_all)]
     /// Process stuff related to event ordering.
@@ -536,10 +1588,7 @@ where
         );
         for checked_round in next_round_to_decide..self.round_index.len() {
             trace!("Checking round {}", checked_round);
-            let round_witnesses = self
-                .round_witnesses(checked_round)
-                .expect("Round number is bounded by `round_index` size");
-            for event_hash in round_witnesses {
+            for (_, event_hash) in self.witnesses_between(checked_round, checked_round) {
                 match self.is_famous_witness(event_hash) {
                     Ok(WitnessFamousness::Undecided) => {
                         debug!(
@@ -553,21 +1602,23 @@ where
                     }
                     Err(WitnessCheckError::NotWitness) => {
                         error!("Witnesses index or witness check is broken, inconsistent state");
-                        panic!("Events given by `round_witnesses` must be witnesses");
+                        panic!("Events given by `witnesses_between` must be witnesses");
                     }
                     Err(WitnessCheckError::Unknown(_)) => {
                         error!("Witnesses index or something else is broken, inconsistent state");
-                        panic!("Events given by `round_witnesses` must be known");
+                        panic!("Events given by `witnesses_between` must be known");
                     }
                 }
             }
             debug!("Round {} is decided", checked_round);
+            metrics::round_decided();
             // At this point we know that round `checked_round` is decided.
             // So we update the stored value.
             self.last_known_decided_round = Some(
                 self.last_known_decided_round
                     .map_or(checked_round, |d| d.max(checked_round)),
             );
+            self.scratch.release();
             progress_made = true;
         }
         progress_made
@@ -608,6 +1659,7 @@ where
                             .clone()
                     })
                     .collect();
+                let first_position = self.ordering.len();
                 self.ordering
                     .add_received_round(
                         decided_round,
@@ -615,6 +1667,9 @@ where
                         unique_famous_witness_sigs,
                     )
                     .expect("just got round # from ordering, must be correct");
+                self.update_state(decided_round, first_position);
+                self.track_finalized(decided_round, first_position);
+                self.complete_epoch(decided_round);
                 Ok(())
             }
             Err(e) => {
@@ -699,13 +1754,15 @@ where
             {
                 Ok((round_received, _, _)) => round_received >= target_round_received,
                 Err(OrderingDataError::Undecided) => true,
+                Err(OrderingDataError::Pruned) => false,
                 Err(OrderingDataError::UnknownEvent(UnknownEvent(e))) => {
                     panic!("events referenced in events must be tracked {e} is unknown.")
                 }
             },
             &self.all_events,
         )
-        .expect("witnesses must be tracked (2)");
+        .expect("witnesses must be tracked (2)")
+        .parents_pruned(self.events_dropped());
 
         let mut result = vec![];
 
@@ -726,6 +1783,7 @@ where
                 }
                 Err(OrderingDataError::Undecided) =>
                     trace!("Event does not have ordering data yet, its round_received must be higher than needed; skipping"),
+                Err(OrderingDataError::Pruned) => trace!("Event was ordered in a pruned round, skipping"),
             }
         }
         Ok(result)
@@ -735,10 +1793,64 @@ where
 impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
     Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
 where
+    TPayload: Clone,
+    TGenesisPayload: Clone,
     TPeerId: Clone,
+    TSigner: Clone,
+    TClock: Clone,
 {
-    pub fn peers(&self) -> Vec<TPeerId> {
-        self.peer_index.keys().cloned().collect_vec()
+    /// Independent copy of the graph, including caches and the state of
+    /// recognized/finalized event streams. Submitted payloads (see
+    /// [`submit_transaction`](Self::submit_transaction)) are not copied.
+    ///
+    /// Useful for "what-if" questions, e.g. whether some event would be a witness
+    /// if pushed now: push it to the fork and inspect the result, the original
+    /// graph stays intact.
+    pub fn fork(&self) -> Self {
+        Self {
+            all_events: self.all_events.clone(),
+            headers: self.headers.clone(),
+            peer_index: self.peer_index.clone(),
+            sorted_geneses: self.sorted_geneses.clone(),
+            round_index: self.round_index.clone(),
+            witnesses: Mutex::new(self.witnesses.lock().unwrap().clone()),
+            round_of: self.round_of.clone(),
+            ordering_data_cache: Mutex::new(self.ordering_data_cache.lock().unwrap().clone()),
+            descendancy: Mutex::new(self.descendancy.lock().unwrap().clone()),
+            last_known_decided_round: self.last_known_decided_round,
+            ordering: self.ordering.clone(),
+            recognized_events: self.recognized_events.clone(),
+            pending: self.pending.clone(),
+            timings: self.timings.clone(),
+            recently_seen: self.recently_seen.clone(),
+            stakes: self.stakes.clone(),
+            epochs: self.epochs.clone(),
+            pruned_below: self.pruned_below,
+            compacted_below: self.compacted_below,
+            compacted: self.compacted.clone(),
+            compaction_kept: self.compaction_kept.clone(),
+            // Handles follow the original graph
+            submissions: Default::default(),
+            content_filter: self.content_filter.clone(),
+            redacted: self.redacted.clone(),
+            annotations: self.annotations.clone(),
+            provenance: self.provenance.clone(),
+            round_quota: self.round_quota.clone(),
+            timestamp_strategy: self.timestamp_strategy.clone(),
+            scratch: core::Scratch::default(),
+            state: self.state.clone(),
+            self_id: self.self_id.clone(),
+            sync_id: self.sync_id.clone(),
+            coin_frequency: self.coin_frequency,
+            adaptive_coin: self.adaptive_coin,
+            coin_schedule: Mutex::new(self.coin_schedule.lock().unwrap().clone()),
+            coin_seed: self.coin_seed,
+            coin_strategy: self.coin_strategy,
+            max_clock_skew: self.max_clock_skew,
+            signer: self.signer.clone(),
+            other_identities: self.other_identities.clone(),
+            clock: self.clock.clone(),
+        }
     }
 }
 
@@ -766,6 +1878,36 @@ where
         self.peer_index.get(peer).map(|e| e.origin())
     }
 
+    /// Iterator over all events of the peer, from its genesis to the latest events.
+    /// See [`PeerLaneIter`] for details on forks. Pruned events (see
+    /// [`prune`](Self::prune)) are left out.
+    ///
+    /// `None` if the peer is unknown.
+    pub fn peer_lane(
+        &self,
+        peer: &TPeerId,
+    ) -> Option<PeerLaneIter<'_, TPayload, TGenesisPayload, TPeerId>> {
+        let entry = self.peer_index.get(peer)?;
+        let mut starts = vec![entry.origin().clone()];
+        if self.events_dropped() {
+            // The lane continues after the pruned part
+            starts.extend(
+                entry
+                    .authored_events()
+                    .keys()
+                    .filter(|e| match self.all_events.get(*e).map(|e| e.kind()) {
+                        Some(event::Kind::Regular(parents)) => {
+                            !self.all_events.contains_key(&parents.self_parent)
+                        }
+                        _ => false,
+                    })
+                    .sorted()
+                    .cloned(),
+            );
+        }
+        Some(PeerLaneIter::new(&self.all_events, starts))
+    }
+
     // for navigating the graph state externally
     pub fn event(
         &self,
@@ -774,30 +1916,105 @@ where
         self.all_events.get(id)
     }
 
-    pub fn self_id(&self) -> &TPeerId {
-        &self.self_id
+    /// Member this graph authors as, `None` for observers
+    pub fn self_id(&self) -> Option<&TPeerId> {
+        self.self_id.as_ref()
+    }
+
+    pub fn coin_frequency(&self) -> usize {
+        self.coin_frequency
+    }
+
+    /// Latest round with fame of all its witnesses decided. Events received
+    /// by it and earlier rounds are finalized.
+    pub fn last_decided_round(&self) -> Option<usize> {
+        self.last_known_decided_round
+    }
+
+    /// All events sharing the self parent with `id` (including itself) if
+    /// its author forked there. `None` if the event is unknown or not a fork.
+    pub fn fork_siblings(&self, id: &event::Hash) -> Option<Vec<event::Hash>> {
+        let event::Kind::Regular(parents) = self.all_events.get(id)?.kind() else {
+            return None;
+        };
+        match &self
+            .all_events
+            .get(&parents.self_parent)?
+            .children
+            .self_child
+        {
+            event::SelfChild::ForkingParent(children) => Some(children.clone()),
+            event::SelfChild::HonestParent(_) => None,
+        }
+    }
+
+    /// `None` if timings were not collected for the event (see
+    /// [`set_collect_timings`](Self::set_collect_timings)).
+    pub fn event_timings(&self, id: &event::Hash) -> Option<&EventTimings> {
+        self.timings.as_ref()?.get(id)
+    }
+
+    /// All known metadata of the event in one place. `None` if the event is unknown.
+    pub fn event_info(&self, id: &event::Hash) -> Option<EventInfo<TPeerId>>
+    where
+        TPeerId: Clone,
+    {
+        let event = self.all_events.get(id)?;
+        let parents = match event.kind() {
+            event::Kind::Genesis(_) => None,
+            event::Kind::Regular(parents) => Some(parents.clone()),
+        };
+        let witness = match self.is_famous_witness(id) {
+            Ok(fame) => Some(fame),
+            Err(WitnessCheckError::NotWitness) => None,
+            Err(WitnessCheckError::Unknown(_)) => {
+                panic!("Just checked presence of the event")
+            }
+        };
+        let (epoch, round) = self.round_index.number(self.round_of(id));
+        let epoch_start = self.round_index.epoch_start(epoch);
+        let round_received = match self.ordering_data(id) {
+            Ok((round_received, _, _)) => Some(round_received - epoch_start),
+            Err(OrderingDataError::Undecided | OrderingDataError::Pruned) => None,
+            Err(OrderingDataError::UnknownEvent(_)) => {
+                panic!("Just checked presence of the event")
+            }
+        };
+        Some(EventInfo {
+            hash: id.clone(),
+            author: event.author().clone(),
+            timestamp: *event.timestamp(),
+            parents,
+            children: event.children.clone(),
+            epoch,
+            round,
+            witness,
+            round_received,
+            annotations: self.annotations.get(id).cloned().unwrap_or_default(),
+            relay: self.provenance.relay_of(id).cloned(),
+        })
     }
 
     /// Iterator over ancestors of the event whose round number is `>= min_round`
+    #[cfg(test)]
     fn ancestor_iter<'a>(
         &'a self,
         event_hash: &'a event::Hash,
         min_round: usize,
-    ) -> Option<AncestorIter<TPayload, TGenesisPayload, TPeerId>> {
-        let event = self.all_events.get(event_hash)?;
-        let mut e_iter = AncestorIter::new(&self.all_events, &self.round_of, event_hash, min_round);
-
-        if let event::Kind::Regular(_) = event.kind() {
-            e_iter.push_self_ancestors(event_hash)
-        }
-        Some(e_iter)
+    ) -> Option<impl Iterator<Item = &'a EventWrapper<TPayload, TGenesisPayload, TPeerId>>> {
+        let ancestors = core::ancestors(self, event_hash, min_round)?;
+        Some(ancestors.map(|hash| {
+            self.all_events
+                .get(hash)
+                .expect("Ancestors of known events must be known")
+        }))
     }
 
     /// Iterator over self ancestors of the event
     fn self_ancestor_iter<'a>(
         &'a self,
         event_hash: &'a event::Hash,
-    ) -> Option<SelfAncestorIter<TPayload, TGenesisPayload, TPeerId>> {
+    ) -> Option<SelfAncestorIter<'a, TPayload, TGenesisPayload, TPeerId>> {
         let iter = SelfAncestorIter::new(&self.all_events, event_hash);
         iter
     }
@@ -806,86 +2023,47 @@ where
     /// is a witness.
     ///
     /// Actually calculates the number according to needed properties.
-    #[instrument(level = "trace", skip_all, fields(event=format!("{:?}", event_hash.as_compact())))]
+    #[instrument(level = "trace", skip_all, fields(event = %event_hash.to_compact_hex()))]
     fn determine_round(&self, event_hash: &event::Hash) -> Result<RoundNum, UnknownEvent> {
-        let event = self
-            .all_events
-            .get(event_hash)
-            .ok_or(UnknownEvent(event_hash.clone()))?;
-        match event.kind() {
-            event::Kind::Genesis(_) => Ok(0),
-            event::Kind::Regular(Parents {
-                self_parent,
-                other_parent,
-            }) => {
-                trace!("Finding round # of regular event");
-                // Check if it is cached
-                if let Some(r) = self.round_of.get(event_hash) {
-                    trace!("Result was cached, returning it");
-                    return Ok(*r);
-                }
-                let r = std::cmp::max(
-                    self.determine_round(self_parent)
-                        .expect("Parents of known events must be known"),
-                    self.determine_round(other_parent)
-                        .expect("Parents of known events must be known"),
-                );
-
-                // Get witnesses from round r
-                trace!("Fetching parents' round witnesses to check if it is a witness");
-                let round_witnesses = self
-                    .round_witnesses(r)
-                    .expect("Round of known events must be known")
-                    .into_iter()
-                    .filter(|eh| *eh != event_hash)
-                    .map(|e_hash| {
-                        self.all_events
-                            .get(e_hash)
-                            .expect("Witnesses must be known")
-                    })
-                    .collect::<Vec<_>>();
-
-                // Find out how many witnesses by unique members the event can strongly see
-                trace!("Find which of them are strongly seen by the event");
-                let round_witnesses_strongly_seen =
-                    round_witnesses
-                        .iter()
-                        .fold(HashSet::new(), |mut set, witness| {
-                            if self.strongly_see(event_hash, &witness.inner().hash()) {
-                                let author = witness.author();
-                                set.insert(author.clone());
-                            }
-                            set
-                        });
+        core::determine_round(self, &self.members_count(), event_hash)
+    }
 
-                // n is number of members in hashgraph
-                let n = self.members_count();
+    /// Events of the round, in no particular order. Empty if the round is
+    /// unknown.
+    pub fn events_in_round(&self, round: RoundNum) -> impl Iterator<Item = &event::Hash> + '_ {
+        self.round_index.get(round).into_iter().flatten()
+    }
 
-                let event_round = if round_witnesses_strongly_seen.len() > (2 * n / 3) {
-                    trace!("Supermajority achieved, it is a witness");
-                    r + 1
-                } else {
-                    trace!("No supermajority, it is not a witness");
-                    r
-                };
-                Ok(event_round)
-            }
-        }
+    /// Witnesses of rounds `first..=last` with their rounds, round by round
+    /// (in no particular order within a round). Rounds not known yet are
+    /// skipped.
+    pub fn witnesses_between(
+        &self,
+        first: RoundNum,
+        last: RoundNum,
+    ) -> impl Iterator<Item = (RoundNum, &event::Hash)> + '_ {
+        (first..=last)
+            .take_while(|&round| round < self.round_index.len())
+            .flat_map(move |round| {
+                self.events_in_round(round)
+                    .filter(|hash| self.headers.get(*hash).is_some_and(|h| h.witness))
+                    .map(move |hash| (round, hash))
+            })
     }
 
     /// None if this round is unknown
     fn round_witnesses(&self, r: usize) -> Option<HashSet<&event::Hash>> {
-        let all_round_events = self.round_index.get(r)?.iter();
-        let witnesses = all_round_events
-            .filter(|e| self.witnesses.lock().unwrap().contains_key(e))
-            .collect();
-        Some(witnesses)
+        self.round_index.get(r)?;
+        Some(self.witnesses_between(r, r).map(|(_, hash)| hash).collect())
     }
 
     fn round_unique_famous_witnesses(
         &self,
         r: usize,
     ) -> Result<HashSet<&event::Hash>, RoundUfwListError> {
+        if r < self.round_index.base() {
+            return Err(RoundUfwListError::Pruned);
+        }
         let round_index = self
             .round_index
             .get(r)
@@ -912,6 +2090,18 @@ impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
 where
     TPeerId: Eq + std::hash::Hash,
 {
+    /// Lowest sequence number shared by several events of `author`
+    fn fork_floor(&self, author: &TPeerId) -> u64 {
+        self.peer_index
+            .get(author)
+            .into_iter()
+            .flat_map(|index| index.fork_index().forks().values().flatten())
+            .filter_map(|child| self.headers.get(child))
+            .map(|header| header.sequence)
+            .min()
+            .unwrap_or(u64::MAX)
+    }
+
     // TODO: probably move to round field in event to avoid panics and stuff
     fn round_of(&self, event_hash: &event::Hash) -> RoundNum {
         match self.round_of.get(event_hash) {
@@ -919,31 +2109,64 @@ where
             None => {
                 self.round_index
                     .iter()
-                    .enumerate()
-                    .find(|(_, round)| round.contains(&event_hash))
+                    .find(|(_, round)| round.contains(event_hash))
                     .expect("Failed to find a round for event")
                     .0 // add to `round_of` in this case maybe??
             }
         }
     }
 
-    /// Determines if the event is a witness
-    fn determine_witness(&self, event_hash: &event::Hash) -> Result<bool, UnknownEvent> {
-        if self.witnesses.lock().unwrap().contains_key(event_hash) {
-            return Ok(true);
-        }
-        let r = match self
-            .all_events
-            .get(&event_hash)
-            .ok_or(UnknownEvent(event_hash.clone()))?
-            .kind()
-        {
-            event::Kind::Genesis(_) => true,
-            event::Kind::Regular(Parents { self_parent, .. }) => {
-                self.round_of(event_hash) > self.round_of(self_parent)
-            }
+    fn header_mut(&mut self, event_hash: &event::Hash) -> &mut headers::EventHeader<TPeerId> {
+        self.headers
+            .get_mut(event_hash)
+            .expect("headers are inserted with the events")
+    }
+
+    fn get_event(
+        &self,
+        event_hash: &event::Hash,
+    ) -> Result<&EventWrapper<TPayload, TGenesisPayload, TPeerId>, UnknownEvent> {
+        self.all_events
+            .get(event_hash)
+            .ok_or_else(|| UnknownEvent(event_hash.clone()))
+    }
+
+    /// Determines if the event is a witness, i.e. the first event of its author
+    /// in its round (geneses are always witnesses).
+    ///
+    /// The status is stored when the event is inserted, and determined again
+    /// when the rounds of stored events change (e.g. a fork is invalidated).
+    pub fn determine_witness(&self, event_hash: &event::Hash) -> Result<bool, UnknownEvent> {
+        self.headers
+            .get(event_hash)
+            .map(|header| header.witness)
+            .ok_or_else(|| UnknownEvent(event_hash.clone()))
+    }
+
+    /// Determine the witness status from the rounds of the event and its
+    /// self parent, and store it. Done on insertion; paths that change the
+    /// rounds of stored events (e.g. invalidating a fork) must call it again.
+    fn recompute_witness(&mut self, event_hash: &event::Hash) -> Result<bool, UnknownEvent> {
+        let stored = self.determine_witness(event_hash)?;
+        let is_witness = if self.parents_pruned(event_hash) {
+            // Can't be told from the parents anymore
+            stored
+        } else {
+            // `core::determine_witness` returns the stored flag if it is set,
+            // so clear it to have the status recomputed from the parents
+            self.header_mut(event_hash).witness = false;
+            core::determine_witness(self, event_hash)?
         };
-        Ok(r)
+        self.header_mut(event_hash).witness = is_witness;
+        let mut witnesses = self.witnesses.lock().unwrap();
+        if is_witness {
+            witnesses
+                .entry(event_hash.clone())
+                .or_insert(WitnessFamousness::Undecided);
+        } else {
+            witnesses.remove(event_hash);
+        }
+        Ok(is_witness)
     }
 
     /// Determine if the event is famous.
@@ -967,105 +2190,41 @@ where
             return Err(WitnessCheckError::NotWitness);
         }
 
-        let r = self.round_of(event_hash);
+        let span = debug_span!(
+            "fame_election",
+            witness = %event_hash.to_compact_hex(),
+            round = self.round_of(event_hash),
+            decided_at = field::Empty,
+        );
+        let _guard = span.enter();
+        metrics::fame_election_run();
 
-        // first round of the election
-        let this_round_index = match self.round_index.get(r + 1) {
-            Some(i) => i,
-            None => return Ok(WitnessFamousness::Undecided),
+        let Some(voting) = self.voting(self.round_of(event_hash)) else {
+            return Ok(WitnessFamousness::Undecided);
         };
-        let mut prev_round_votes = HashMap::new();
-        for y_hash in this_round_index {
-            if self.witnesses.lock().unwrap().contains_key(y_hash) {
-                prev_round_votes.insert(y_hash, self.see(y_hash, &event_hash));
-            }
+        let election = core::fame(self, &self.members_count(), voting, event_hash)?;
+        if let Some(decided_at) = election.decided_at {
+            // Should not change if decided
+            self.witnesses
+                .lock()
+                .unwrap()
+                .insert(event_hash.clone(), election.fame.clone());
+            span.record("decided_at", decided_at);
+            debug!("Fame decided: {:?}", election.fame);
+            metrics::fame_decided();
         }
+        Ok(election.fame)
+    }
 
-        // TODO: consider dynamic number of nodes
-        // (i.e. need to count members at particular round and not at the end)
-        let n = self.members_count();
-
-        let next_rounds_indices = match self.round_index.get(r + 2..) {
-            Some(i) => i,
-            None => return Ok(WitnessFamousness::Undecided),
-        };
-        for (d, this_round_index) in izip!((2..), next_rounds_indices) {
-            let mut this_round_votes = HashMap::new();
-            let voter_round = r + d;
-            let round_witnesses = this_round_index
-                .iter()
-                .filter(|e| self.witnesses.lock().unwrap().contains_key(e));
-            for y_hash in round_witnesses {
-                // The set of witness events in round (y.round-1) that y can strongly see
-                let s = self
-                    .round_witnesses(voter_round - 1)
-                    .unwrap() // TODO: handle/check if ok?
-                    .into_iter()
-                    .filter(|h| self.strongly_see(y_hash, h));
-                // count votes
-                let (votes_for, votes_against) = s.fold((0, 0), |(yes, no), prev_round_witness| {
-                    let vote = prev_round_votes.get(prev_round_witness);
-                    match vote {
-                        Some(true) => (yes + 1, no),
-                        Some(false) => (yes, no + 1),
-                        None => {
-                            // Should not happen but don't just panic, maybe return error later
-                            // TODO: warn on inconsistent state
-                            (yes, no)
-                        }
-                    }
-                });
-                // majority vote in s ( is TRUE for a tie )
-                let v = votes_for >= votes_against;
-                // number of events in s with a vote of v
-                let t = std::cmp::max(votes_for, votes_against);
-
-                if d % self.coin_frequency > 0 {
-                    // Normal round
-                    if t > (2 * n / 3) {
-                        // TODO: move supermajority cond to func
-                        // if supermajority, then decide
-                        let fame = match v {
-                            true => WitnessFamousness::Yes,
-                            false => WitnessFamousness::No,
-                        };
-                        // Should not change if decided
-                        self.witnesses
-                            .lock()
-                            .unwrap()
-                            .insert(event_hash.clone(), fame.clone());
-                        return Ok(fame);
-                    } else {
-                        this_round_votes.insert(y_hash, v);
-                    }
-                } else {
-                    // Coin round
-                    if t > (2 * n / 3) {
-                        // TODO: move supermajority cond to func
-                        // if supermajority, then vote
-                        this_round_votes.insert(y_hash, v);
-                    } else {
-                        let middle_bit = {
-                            // TODO: use actual signature, not sure if makes a diff tho
-                            let y_sig = self
-                                .all_events
-                                .get(y_hash)
-                                .expect("Inconsistent graph state") //TODO: turn to error
-                                .hash()
-                                .as_ref();
-                            let middle_bit_index = y_sig.len() * 8 / 2;
-                            let middle_byte_index = middle_bit_index / 8;
-                            let middle_byte = y_sig[middle_byte_index];
-                            let middle_bit_index = middle_bit_index % 8;
-                            (middle_byte >> middle_bit_index & 1) != 0
-                        };
-                        this_round_votes.insert(y_hash, middle_bit);
-                    }
-                }
-            }
-            prev_round_votes = this_round_votes;
-        }
-        Ok(WitnessFamousness::Undecided)
+    /// Parameters of the fame elections of the witnesses of the round,
+    /// `None` if its [coin frequency](Self::coin_frequency_at) is not known
+    /// yet
+    fn voting(&self, round: RoundNum) -> Option<core::Voting> {
+        Some(core::Voting {
+            coin_frequency: self.coin_frequency_at(round)?,
+            coin_seed: self.coin_seed,
+            coin: self.coin_strategy,
+        })
     }
 
     fn is_unique_famous_witness(
@@ -1165,55 +2324,43 @@ where
         // Since `x` is ancestor of round `r` witnesses and we search for the earliest
         // round that satisfies the condition, we start from round of `x` forward to
         // get `r`.
-        for checked_round in self.round_of(event_hash)..self.round_index.len() {
+        //
+        // Pruned rounds are ordered already. Events of these rounds that are
+        // not ordered yet are received after them, the rest were ordered
+        // (and maybe pruned and pushed again) with the witnesses now gone.
+        let first_round = self.round_of(event_hash);
+        if first_round < self.round_index.base() && self.ordering.contains(event_hash) {
+            return Err(OrderingDataError::Pruned);
+        }
+        for checked_round in first_round.max(self.round_index.base())..self.round_index.len() {
             trace!("Checking round {}", checked_round);
             let unique_famous_witnesses = match self.round_unique_famous_witnesses(checked_round) {
                 Ok(list) => list,
                 // round before this did not satisfy our condition and later ones
                 // are still undecided
                 Err(RoundUfwListError::RoundUndecided) => return Err(OrderingDataError::Undecided),
-                Err(RoundUfwListError::UnknownRound) => {
+                Err(RoundUfwListError::UnknownRound | RoundUfwListError::Pruned) => {
                     panic!("`checked_round` range boundary must not allow this")
                 }
             };
             trace!("Found {} ufw in the round", unique_famous_witnesses.len());
             // Is `x` an ancestor of every round `r` unique famous witness?
-            if unique_famous_witnesses
+            let descends = unique_famous_witnesses
                 .iter()
-                .all(|ufw| self.is_ancestor(ufw, event_hash))
-            {
+                .map(|ufw| self.witness_descends_from(ufw, event_hash));
+            if itertools::process_results(descends, |mut descends| descends.all(|d| d))? {
                 trace!("The event of interest is an ancestor of them all");
-                // set of each event z such that z is
-                // a self-ancestor of a round r unique famous
-                // witness, and x is an ancestor of z but not
-                // of the self-parent of z
-                let s = unique_famous_witnesses.iter().map(|ufw| {
-                    let mut self_ancestors = self
-                        .self_ancestor_iter(ufw)
-                        .expect("all self ancestors of unique famous witness must be known");
-                    // we want to keep track of possible z event
-                    let mut first_descendant_event_candidate = self_ancestors
-                        .next()
-                        .expect("at least 1 self-ancestor must be present - the event itself");
-                    for next_ufw_ancestor in self_ancestors {
-                        if !self.is_ancestor(next_ufw_ancestor.inner().hash(), event_hash) {
-                            break;
-                        }
-                        first_descendant_event_candidate = next_ufw_ancestor
-                    }
-                    first_descendant_event_candidate
-                });
-                let mut timestamps: Vec<_> = s.map(|event| event.timestamp()).collect();
-                timestamps.sort();
-                trace!("Found {} corresponding events-receivers", timestamps.len());
-                trace!("Their timestamps (sorted): {:?}", timestamps);
-                // Note that we assume a supermajority of honest members and
-                // the median is taken here. Thus this median value will always be
-                // in range of honest timestamps.
-                let consensus_timestamp = **timestamps
-                    .get(timestamps.len() / 2)
-                    .expect("there must be some unique famous witnesses in a round");
-                trace!("Median is {}", consensus_timestamp);
+                let receivers: Vec<_> = self
+                    .receivers(unique_famous_witnesses.iter().copied(), event_hash)?
+                    .into_iter()
+                    .map(|event| timestamping::Receiver {
+                        timestamp: *event.timestamp(),
+                        round: self.round_of(event.hash()),
+                    })
+                    .collect();
+                trace!("Found {} corresponding events-receivers", receivers.len());
+                let consensus_timestamp = self.timestamp_strategy.consensus_timestamp(&receivers);
+                trace!("Consensus timestamp is {}", consensus_timestamp);
                 trace!("Caching result");
                 let result = (checked_round, consensus_timestamp, event_signature.clone());
                 self.ordering_data_cache
@@ -1228,129 +2375,108 @@ where
         Err(OrderingDataError::Undecided)
     }
 
-    fn is_ancestor(&self, target: &event::Hash, potential_ancestor: &event::Hash) -> bool {
-        // TODO: check in other way and return error???
-        let _x = self.all_events.get(target).unwrap();
-        let _y = self.all_events.get(potential_ancestor).unwrap();
-
-        // Use round number as a heuristic to not check all events:
-        // Round number of any event is not less than round number of its parents.
-        // Transitively it's true for ancestors:
-        // Round number of any event is not less than round number of its ancestors.
-
-        // Therefore, we know that we don't need to iterate over events with round
-        // nubmer < `round_of(potential_ancestor)`. It's because the `potential_ancestor`
-        // is definitely not in any of their ancestors (due to the property)
-
-        let potential_ancestor_round = self.round_of(potential_ancestor);
+    /// Set of each event z such that z is a self-ancestor of a round r
+    /// unique famous witness, and x is an ancestor of z but not of the
+    /// self-parent of z. `x` (`event_hash`) must be an ancestor of all the
+    /// witnesses. Their timestamps give the consensus timestamp of `x`.
+    ///
+    /// In the order of the witnesses.
+    fn receivers<'a>(
+        &'a self,
+        unique_famous_witnesses: impl IntoIterator<Item = &'a event::Hash>,
+        event_hash: &event::Hash,
+    ) -> Result<Vec<&'a EventWrapper<TPayload, TGenesisPayload, TPeerId>>, UnknownEvent> {
+        unique_famous_witnesses
+            .into_iter()
+            .map(|ufw| {
+                let mut self_ancestors = self
+                    .self_ancestor_iter(ufw)
+                    .expect("all self ancestors of unique famous witness must be known");
+                // we want to keep track of possible z event
+                let mut first_descendant_event_candidate = self_ancestors
+                    .next()
+                    .expect("at least 1 self-ancestor must be present - the event itself");
+                for next_ufw_ancestor in self_ancestors {
+                    if !self.is_ancestor(next_ufw_ancestor.inner().hash(), event_hash)? {
+                        break;
+                    }
+                    first_descendant_event_candidate = next_ufw_ancestor
+                }
+                Ok(first_descendant_event_candidate)
+            })
+            .collect()
+    }
 
-        self.ancestor_iter(target, potential_ancestor_round)
-            .unwrap()
-            .any(|e| e.inner().hash() == potential_ancestor)
+    /// Errors if any of the events is unknown, e.g. was pruned.
+    fn is_ancestor(
+        &self,
+        target: &event::Hash,
+        potential_ancestor: &event::Hash,
+    ) -> Result<bool, UnknownEvent> {
+        core::see(self, target, potential_ancestor)
     }
 
-    /// True if target(y) is an ancestor of observer(x), but no fork of target is an
-    /// ancestor of observer.
-    fn see(&self, observer: &event::Hash, target: &event::Hash) -> bool {
+    /// True if target(y) is an ancestor of observer(x). This is plain
+    /// ancestry: forks of target's author are not considered, so observer
+    /// may see target even if it also has a fork of target as an ancestor.
+    ///
+    /// Errors if any of the events is unknown.
+    pub fn see(&self, observer: &event::Hash, target: &event::Hash) -> Result<bool, UnknownEvent> {
         // TODO: add fork check
-        return self.is_ancestor(observer, target);
+        self.get_event(observer)?;
+        core::see(self, observer, target)
     }
 
     /// Event `observer` strongly sees `target` through more than 2n/3 members.
+    /// In other words, `observer` sees events of the supermajority of members,
+    /// which in turn see `target`.
     ///
-    /// Target is ancestor of observer, for reference
-    fn strongly_see(&self, observer: &event::Hash, target: &event::Hash) -> bool {
+    /// Errors if any of the events is unknown.
+    pub fn strongly_see(
+        &self,
+        observer: &event::Hash,
+        target: &event::Hash,
+    ) -> Result<bool, UnknownEvent> {
         // TODO: Check fork conditions
-
-        // don't need to check events $z$ for $see(observer, z) && see(z, target)$
-        // if round of $z$ is less than round of target (it means $z$ definitely
-        // can't see `target`)
-        let target_round = self.round_of(target);
-        let authors_seen = self
-            .ancestor_iter(observer, target_round)
-            .unwrap()
-            .filter(|e| self.see(&e.inner().hash(), target))
-            .fold(HashSet::new(), |mut set, event| {
-                let author = event.author();
-                set.insert(author.clone());
-                set
-            });
-        let n = self.members_count();
-        authors_seen.len() > (2 * n / 3)
+        core::strongly_see(self, &self.members_count(), observer, target)
     }
 }
 
-struct AncestorIter<'a, T, G, P> {
-    event_list: Vec<&'a EventWrapper<T, G, P>>,
-    all_events: &'a HashMap<event::Hash, EventWrapper<T, G, P>>,
-    round_of: &'a HashMap<event::Hash, RoundNum>,
-    visited_events: HashSet<&'a event::Hash>,
-    min_round: RoundNum,
-}
-
-impl<'a, T, G, P> AncestorIter<'a, T, G, P> {
-    fn new(
-        all_events: &'a HashMap<event::Hash, EventWrapper<T, G, P>>,
-        round_of: &'a HashMap<event::Hash, RoundNum>,
-        ancestors_of: &'a event::Hash,
-        min_round: RoundNum,
-    ) -> Self {
-        let mut iter = Self {
-            event_list: vec![],
-            all_events,
-            round_of,
-            visited_events: HashSet::new(),
-            min_round,
-        };
-        iter.push_self_ancestors(ancestors_of);
-        iter
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock> core::EventTable
+    for Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash,
+{
+    type PeerId = TPeerId;
+
+    fn entry(&self, event: &event::Hash) -> Option<core::Entry<'_, TPeerId>> {
+        let (hash, header) = self.headers.get_key_value(event)?;
+        Some(core::Entry {
+            hash,
+            author: &header.author,
+            slot: Some(header.slot),
+            parents: header.parents.as_ref(),
+        })
     }
 
-    fn push_self_ancestors(&mut self, event_hash: &'a event::Hash) {
-        if self.visited_events.contains(event_hash) {
-            return;
-        }
-        if let Some(event_round) = self.round_of.get(event_hash) {
-            if event_round < &self.min_round {
-                return;
-            }
-        }
-
-        let mut event = self.all_events.get(event_hash).unwrap();
-
-        loop {
-            self.event_list.push(event);
-            self.visited_events.insert(event_hash);
+    fn round(&self, event: &event::Hash) -> Option<RoundNum> {
+        self.headers.get(event)?.round
+    }
 
-            if let event::Kind::Regular(Parents { self_parent, .. }) = event.kind() {
-                if self.visited_events.contains(self_parent) {
-                    // We've already visited all of its self ancestors
-                    break;
-                }
-                if let Some(self_parent_round) = self.round_of.get(self_parent) {
-                    if self_parent_round < &self.min_round {
-                        // All other self ancestors will have round less than `min_round`
-                        break;
-                    }
-                }
-                event = self.all_events.get(self_parent).unwrap();
-            } else {
-                break;
-            }
-        }
+    fn canonical_index(&self, peer: &TPeerId) -> Option<usize> {
+        Graph::canonical_index(self, peer)
     }
-}
 
-impl<'a, T, G, P> Iterator for AncestorIter<'a, T, G, P> {
-    type Item = &'a EventWrapper<T, G, P>;
+    fn round_events(&self, round: RoundNum) -> Option<&HashSet<event::Hash>> {
+        self.round_index.get(round)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let event = self.event_list.pop()?;
+    fn is_witness(&self, event: &event::Hash) -> bool {
+        self.headers.get(event).is_some_and(|header| header.witness)
+    }
 
-        if let event::Kind::Regular(Parents { other_parent, .. }) = event.kind() {
-            self.push_self_ancestors(other_parent);
-        }
-        Some(event)
+    fn scratch(&self) -> Option<&core::Scratch> {
+        Some(&self.scratch)
     }
 }
 
@@ -1364,14 +2490,18 @@ impl<'a, T, G, P> SelfAncestorIter<'a, T, G, P> {
         ancestors_of: &'a event::Hash,
     ) -> Option<Self> {
         let mut event_list = VecDeque::new();
-        let mut next_event_hash = ancestors_of;
+        let mut next_event = all_events.get(ancestors_of)?;
         loop {
-            let next_event = all_events.get(next_event_hash)?;
             event_list.push_back(next_event);
-            if let event::Kind::Regular(Parents { self_parent, .. }) = next_event.kind() {
-                next_event_hash = self_parent
-            } else {
-                break;
+            // Ends at the genesis or at pruned events
+            match next_event.kind() {
+                event::Kind::Regular(Parents { self_parent, .. }) => {
+                    match all_events.get(self_parent) {
+                        Some(parent) => next_event = parent,
+                        None => break,
+                    }
+                }
+                event::Kind::Genesis(_) => break,
             }
         }
         Some(Self { event_list })
@@ -1392,6 +2522,47 @@ impl<'a, T, G, P> DoubleEndedIterator for SelfAncestorIter<'a, T, G, P> {
     }
 }
 
+/// Iterator over events of a single peer, from genesis towards the latest events.
+///
+/// Goes depth-first over self children, so ancestors always come before
+/// descendants. If the peer forked, each branch is yielded completely before
+/// the next one starts (branches are visited in order of their hashes).
+pub struct PeerLaneIter<'a, T, G, P> {
+    all_events: &'a HashMap<event::Hash, EventWrapper<T, G, P>>,
+    to_visit: Vec<event::Hash>,
+}
+
+impl<'a, T, G, P> PeerLaneIter<'a, T, G, P> {
+    fn new(
+        all_events: &'a HashMap<event::Hash, EventWrapper<T, G, P>>,
+        mut from: Vec<event::Hash>,
+    ) -> Self {
+        // Stack, so `from` is visited in order
+        from.reverse();
+        Self {
+            all_events,
+            to_visit: from,
+        }
+    }
+}
+
+impl<'a, T, G, P> Iterator for PeerLaneIter<'a, T, G, P> {
+    type Item = &'a EventWrapper<T, G, P>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.to_visit.pop()?;
+        let event = self
+            .all_events
+            .get(&next)
+            .expect("self children of tracked events must be tracked");
+        let mut self_children: Vec<_> = event.children.self_child.clone().into();
+        // Stack, so the smallest hash is visited first
+        self_children.sort_by(|a, b| b.cmp(a));
+        self.to_visit.extend(self_children);
+        Some(event)
+    }
+}
+
 impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock> crate::common::Graph
     for Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
 where
@@ -0,0 +1,180 @@
+//! Graphs that follow the network without being a member of it.
+//!
+//! Indexers and auditors need the consensus order but must not take part in
+//! it. [`Graph::new_observer`] makes a graph without an own genesis: it
+//! ingests gossip, checks hashes and signatures, decides fame and
+//! finalizes events like any member, and serves queries, exports and sync
+//! requests. Authoring is refused at run time with
+//! [`EventCreateError::Observer`], so an observer never appears in the
+//! graphs of others.
+//!
+//! The id of an observer only names it in its own sync requests. It is
+//! never taken for a member, even if some member has the same id.
+
+use std::fmt::Debug;
+
+use serde::Serialize;
+
+use super::{EventCreateError, Graph};
+use crate::algorithm::codec::PayloadCodec;
+use crate::algorithm::{Clock, Signer};
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPayload: PayloadCodec + Eq + std::hash::Hash + Debug + Clone,
+    TGenesisPayload: Serialize + Eq + std::hash::Hash + Debug + Clone,
+    TPeerId: Serialize + Eq + std::hash::Hash + Debug + Clone,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+    TClock: Clock,
+{
+    /// Graph that verifies and finalizes the events of others but never
+    /// authors any. `signer` only verifies signatures. Geneses of the
+    /// members arrive with the gossip like any other event.
+    ///
+    /// `observer_id` names the graph in its sync requests only, it has no
+    /// [`self_id`](Self::self_id).
+    pub fn new_observer(
+        observer_id: TPeerId,
+        coin_frequency: usize,
+        signer: TSigner,
+        clock: TClock,
+    ) -> Self {
+        let mut graph = Self::without_genesis(observer_id, coin_frequency, signer, clock);
+        graph.self_id = None;
+        graph
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+{
+    /// Whether the graph was made by [`new_observer`](Self::new_observer)
+    pub fn is_observer(&self) -> bool {
+        self.self_id.is_none()
+    }
+
+    pub(super) fn check_authoring(&self) -> Result<(), EventCreateError<TPeerId>> {
+        match self.self_id {
+            None => Err(EventCreateError::Observer),
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::strategy::MostNewEvents;
+    use crate::algorithm::{IncrementalClock, MockSigner};
+    use crate::testing::{fixture, TestGraph};
+
+    #[test]
+    fn follows_without_authoring() {
+        let mut member = fixture::random_gossip().build().unwrap().graph;
+        let mut observer: TestGraph<(), u64> =
+            Graph::new_observer(999, 999, MockSigner::new(), IncrementalClock::new());
+        assert!(observer.is_observer() && !member.is_observer());
+        assert_eq!(observer.local_identities().count(), 0);
+
+        let jobs = member
+            .generate_sync_for_request(&observer.sync_request())
+            .unwrap();
+        let events = jobs.as_linear().len();
+        assert_eq!(observer.apply_sync_jobs(jobs).unwrap(), events);
+        assert_eq!(observer.peers(), member.peers());
+        assert_eq!(observer.state_hash(), member.state_hash());
+        let order = |graph: &mut TestGraph<(), u64>| {
+            std::iter::from_fn(|| graph.next_finalized_event().map(|e| e.hash().clone()))
+                .collect::<Vec<_>>()
+        };
+        let finalized = order(&mut observer);
+        assert!(!finalized.is_empty());
+        assert_eq!(finalized, order(&mut member));
+
+        // Nothing to author with, on its own behalf or of anyone else
+        let tip = member.self_tip().unwrap().clone();
+        let author = *member.self_id().unwrap();
+        assert!(matches!(
+            observer.create_event((), tip.clone()),
+            Err(EventCreateError::Observer)
+        ));
+        assert!(matches!(
+            observer.create_event_with((), &mut MostNewEvents),
+            Err(EventCreateError::Observer)
+        ));
+        assert!(matches!(
+            observer.create_event_as(&author, (), tip.clone()),
+            Err(EventCreateError::Observer)
+        ));
+        assert!(matches!(
+            observer.draft_event(&author, (), tip),
+            Err(EventCreateError::Observer)
+        ));
+        assert!(matches!(
+            observer.add_local_identity(1000, MockSigner::new(), (), ()),
+            Err(EventCreateError::Observer)
+        ));
+        assert_eq!(observer.peer_candidates().len(), member.peers().len());
+        assert!(observer.peer_genesis(&999).is_none());
+
+        // Serves the events it verified
+        assert!(observer.fork().is_observer());
+        let newcomer: TestGraph<(), u64> =
+            Graph::new_observer(1000, 999, MockSigner::new(), IncrementalClock::new());
+        let jobs = observer
+            .generate_sync_for_request(&newcomer.sync_request())
+            .unwrap();
+        assert_eq!(jobs.as_linear().len(), events);
+    }
+
+    #[test]
+    fn id_of_member_not_taken() {
+        let member = fixture::random_gossip().build().unwrap().graph;
+        let id = *member.self_id().unwrap();
+        let mut observer: TestGraph<(), u64> =
+            Graph::new_observer(id, 999, MockSigner::new(), IncrementalClock::new());
+        observer.set_epoch_length(Some(2));
+        let jobs = member
+            .generate_sync_for_request(&observer.sync_request())
+            .unwrap();
+        observer.apply_sync_jobs(jobs).unwrap();
+        assert_eq!(observer.self_id(), None);
+        assert_eq!(observer.peer_candidates().len(), member.peers().len());
+        assert!(!observer.epochs().is_empty());
+        for summary in observer.epochs() {
+            assert!(summary.witnesses.iter().any(|(author, _)| *author == id));
+            assert_eq!(observer.sign_epoch(summary).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn empty_observer_answers() {
+        let mut observer: TestGraph<(), u64> =
+            Graph::new_observer(999, 999, MockSigner::new(), IncrementalClock::new());
+        assert_eq!(observer.self_tip(), None);
+        assert!(observer.peer_candidates().is_empty());
+        assert_eq!(observer.choose_sync_peer(&mut MostNewEvents), None);
+        assert!(observer.summary().tips.is_empty());
+        assert_eq!(observer.query().iter().count(), 0);
+        assert_eq!(observer.events_in_round(0).count(), 0);
+        assert!(observer.unconfirmed_events().is_empty());
+        assert!(observer.fork_spans().is_empty());
+        assert_eq!(observer.verify_integrity(), vec![]);
+        assert!(observer.archive().is_ok());
+        assert!(observer.archive_point().tips.is_empty());
+        assert!(observer.next_finalized_event().is_none());
+        let filter = Default::default();
+        observer.export_json();
+        observer.export_csv(&filter);
+        observer.to_dot(&Default::default());
+        observer.to_graphml(&filter);
+        observer.to_mermaid(&filter);
+        #[cfg(feature = "otel")]
+        {
+            assert!(observer.otel_trace_id().is_none());
+            let tracer = opentelemetry::trace::noop::NoopTracer::new();
+            observer.export_otel(&tracer, &filter);
+        }
+    }
+}
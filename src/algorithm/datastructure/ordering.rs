@@ -6,6 +6,7 @@ use crate::{algorithm::event, Timestamp};
 /// decided round "sees" (not actually sees as definition says but has
 /// the round as ancestor, it's mostly a formality though). Such round
 /// is also called `round received`
+#[derive(Clone)]
 pub struct OrderedEvents {
     // None - no rounds were ordered (because first round is 0)
     latest_ordered_round: Option<usize>,
@@ -16,6 +17,7 @@ pub struct OrderedEvents {
 }
 
 // Ordering-related data about event
+#[derive(Clone)]
 struct OrderedEventsEntry {
     hash: event::Hash,
     // Do we need to store these fields??
@@ -92,13 +94,35 @@ impl OrderedEvents {
         Some(&event_data.hash)
     }
 
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// All ordered events so far, including the ones already returned
+    /// by [`next_event`](Self::next_event)
+    pub fn ordered(&self) -> impl Iterator<Item = &event::Hash> {
+        self.events.iter().map(|e| &e.hash)
+    }
+
+    /// Linear in the number of ordered events
+    pub fn contains(&self, hash: &event::Hash) -> bool {
+        self.events.iter().any(|e| &e.hash == hash)
+    }
+
+    /// Events already returned by [`next_event`](Self::next_event)
+    pub fn delivered(&self) -> impl Iterator<Item = &event::Hash> {
+        self.events[..self.next_element_to_access]
+            .iter()
+            .map(|e| &e.hash)
+    }
+
     fn verify_round_number(&self, r: usize) -> Result<(), RoundAddError> {
         (r == self.next_round_to_order())
             .then_some(())
             .ok_or(RoundAddError::IncorrectRoundNumber)
     }
 
-    fn combine_sigs_xor(sigs: impl Iterator<Item = event::Signature>) -> event::Signature {
+    pub fn combine_sigs_xor(sigs: impl Iterator<Item = event::Signature>) -> event::Signature {
         sigs.into_iter().fold(
             event::Signature(event::Hash::from_array([0u8; 64])),
             |acc, next| acc ^ &next,
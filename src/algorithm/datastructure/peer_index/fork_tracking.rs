@@ -4,7 +4,7 @@ use derive_getters::Getters;
 
 use crate::algorithm::event;
 
-#[derive(Getters)]
+#[derive(Getters, Clone)]
 pub struct ForkIndex {
     forks: HashMap<event::Hash, HashSet<event::Hash>>,
 }
@@ -30,7 +30,7 @@ impl ForkIndex {
                 let mut fork_children = fork_parent.children.self_child.clone();
                 fork_children.add_child(new_fork_child);
                 let fork_children: Vec<_> = fork_children.into();
-                place.insert(HashSet::from_iter(fork_children.into_iter()));
+                place.insert(HashSet::from_iter(fork_children));
             }
         }
     }
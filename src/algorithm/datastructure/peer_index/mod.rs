@@ -10,7 +10,7 @@ pub mod fork_tracking;
 
 pub type PeerIndex<TPeerId> = HashMap<TPeerId, PeerIndexEntry>;
 
-#[derive(Getters)]
+#[derive(Getters, Clone)]
 pub struct PeerIndexEntry {
     origin: event::Hash,
     known_events: HashSet<event::Hash>,
@@ -39,7 +39,7 @@ impl PeerIndexEntry {
             authored_events: HashMap::from([(genesis.clone(), ())]),
             known_events: HashSet::from([genesis.clone()]),
             fork_index: ForkIndex::new(),
-            latest_events: HashSet::from_iter([genesis.clone()].into_iter()),
+            latest_events: HashSet::from_iter([genesis.clone()]),
         }
     }
 
@@ -96,6 +96,13 @@ impl PeerIndexEntry {
         Ok(())
     }
 
+    /// Drop a pruned event from the index. The origin and the latest events
+    /// are expected to be kept.
+    pub fn forget(&mut self, event: &event::Hash) {
+        self.known_events.remove(event);
+        self.authored_events.remove(event);
+    }
+
     /// Update `known_events` index to include newly-seen events by the peer. It should
     /// help to always have the relevant list of events the peer sees and not recompute
     /// it on demand.
@@ -116,8 +123,12 @@ impl PeerIndexEntry {
         let mut to_visit = VecDeque::from(start);
         // Add them after traversal. To not to leave the index in potentially incorrect
         // state in case error happens.
-        let mut new_known_events = vec![];
+        let mut new_known_events = HashSet::new();
         while let Some(next) = to_visit.pop_front() {
+            // Reachable through several paths, visit once
+            if new_known_events.contains(&next) {
+                continue;
+            }
             let new_events =
                 events_in_direct_sight(&next).ok_or(Error::UnknownEvent(next.clone()))?;
             let new_events = new_events
@@ -125,7 +136,7 @@ impl PeerIndexEntry {
                 .filter(|h| !self.known_events.contains(h))
                 .cloned();
             to_visit.extend(new_events);
-            new_known_events.push(next.clone());
+            new_known_events.insert(next);
         }
         self.known_events.extend(new_known_events);
         Ok(())
@@ -149,7 +160,7 @@ mod tests {
         TGenesisPayload: Clone,
         TPeerId: Clone,
     {
-        let mut events = events.into_iter();
+        let mut events = events.iter();
         let mut all_events: HashMap<
             event::Hash,
             event::EventWrapper<TPayload, TGenesisPayload, TPeerId>,
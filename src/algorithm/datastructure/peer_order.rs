@@ -0,0 +1,158 @@
+//! Order of peers all members agree on.
+//!
+//! Hash maps iterate in a different order on each node, and peers are
+//! numbered locally in the order their geneses arrived. Whatever has to be
+//! the same on all members (picking a peer by number, e.g. the leader of
+//! [`RoundRobinLeaderBit`](crate::algorithm::core::CoinStrategy::RoundRobinLeaderBit)
+//! coins, indices of exported bitsets, breaking ties between peers) uses
+//! the canonical order instead: peers sorted by the hash of their genesis.
+//! Each peer has a single genesis, so members that know the same peers
+//! agree on their order and indices.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::Graph;
+use crate::algorithm::event;
+
+/// Peers in the canonical order, with their indices in it
+#[derive(Debug, Clone)]
+pub struct PeerOrder<TPeerId> {
+    peers: Vec<TPeerId>,
+    index: HashMap<TPeerId, usize>,
+}
+
+/// Indices follow from the order
+impl<TPeerId: PartialEq> PartialEq for PeerOrder<TPeerId> {
+    fn eq(&self, other: &Self) -> bool {
+        self.peers == other.peers
+    }
+}
+
+impl<TPeerId: Eq> Eq for PeerOrder<TPeerId> {}
+
+impl<TPeerId: Eq + Hash + Clone> PeerOrder<TPeerId> {
+    /// From the peers and the hashes of their geneses
+    pub fn from_geneses(geneses: impl IntoIterator<Item = (TPeerId, event::Hash)>) -> Self {
+        let mut geneses: Vec<_> = geneses.into_iter().collect();
+        geneses.sort_by(|(_, a), (_, b)| a.cmp(b));
+        let peers: Vec<_> = geneses.into_iter().map(|(peer, _)| peer).collect();
+        let index = peers
+            .iter()
+            .enumerate()
+            .map(|(i, peer)| (peer.clone(), i))
+            .collect();
+        Self { peers, index }
+    }
+
+    pub fn peers(&self) -> &[TPeerId] {
+        &self.peers
+    }
+
+    pub fn index_of(&self, peer: &TPeerId) -> Option<usize> {
+        self.index.get(peer).copied()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&TPeerId> {
+        self.peers.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + Hash + Clone,
+{
+    /// Known peers in the canonical order, see the [module docs](self)
+    pub fn peer_order(&self) -> PeerOrder<TPeerId> {
+        PeerOrder::from_geneses(
+            self.peer_index
+                .iter()
+                .map(|(peer, entry)| (peer.clone(), entry.origin().clone())),
+        )
+    }
+
+    /// Known peers in the canonical order
+    pub fn peers(&self) -> Vec<TPeerId> {
+        self.peer_order().peers
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + Hash,
+{
+    /// Index of the peer in the canonical order, without building the whole
+    /// [`PeerOrder`]
+    pub fn canonical_index(&self, peer: &TPeerId) -> Option<usize> {
+        let genesis = self.peer_index.get(peer)?.origin();
+        Some(self.sorted_geneses.partition_point(|g| g < genesis))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::{IncrementalClock, MockSigner};
+    use crate::testing::fixture;
+
+    #[test]
+    fn same_order_regardless_of_arrival() {
+        let example = fixture::random_gossip();
+        let graph = example.build().unwrap().graph;
+        let order = graph.peer_order();
+        assert_eq!(order.len(), example.peers.len());
+        for (i, peer) in order.peers().iter().enumerate() {
+            assert_eq!(order.index_of(peer), Some(i));
+            assert_eq!(graph.canonical_index(peer), Some(i));
+            assert_eq!(order.get(i), Some(peer));
+        }
+        let geneses: Vec<_> = order
+            .peers()
+            .iter()
+            .map(|peer| graph.peer_genesis(peer).unwrap())
+            .collect();
+        assert!(geneses.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(graph.peers(), order.peers());
+
+        // Geneses arriving in different orders
+        let geneses: Vec<_> = order
+            .peers()
+            .iter()
+            .map(|peer| {
+                let genesis = graph.peer_genesis(peer).unwrap();
+                graph.all_events.get(genesis).unwrap().inner().clone()
+            })
+            .collect();
+        let order_after = |geneses: Vec<event::SignedEvent<_, _, _>>| {
+            let mut other = Graph::new(
+                u64::MAX,
+                (),
+                (),
+                999,
+                MockSigner::new(),
+                IncrementalClock::new(),
+            );
+            for genesis in geneses {
+                let (unsigned, signature) = genesis.into_parts();
+                other.push_event(unsigned, signature).unwrap();
+            }
+            other.peer_order()
+        };
+        let forward = order_after(geneses.clone());
+        let backward = order_after(geneses.into_iter().rev().collect());
+        assert_eq!(forward, backward);
+        let mut without_new = forward.peers().to_vec();
+        without_new.retain(|peer| *peer != u64::MAX);
+        assert_eq!(without_new, order.peers());
+    }
+}
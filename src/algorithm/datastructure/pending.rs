@@ -0,0 +1,313 @@
+//! Buffer for events that arrived before their parents.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+
+use super::PendingLimits;
+use crate::algorithm::event::{self, Signature, UnsignedEvent};
+use crate::Timestamp;
+
+#[derive(Clone)]
+struct PendingEvent<TPayload, TGenesisPayload, TPeerId> {
+    event: UnsignedEvent<TPayload, TGenesisPayload, TPeerId>,
+    signature: Signature,
+    /// Parents the event still waits for
+    missing: HashSet<event::Hash>,
+    /// Position in arrival order, used for eviction
+    arrival: u64,
+    /// Time of arrival, by the graph's clock
+    received_at: Timestamp,
+}
+
+/// Bounded pool of orphan events (i.e. ones with unknown parents).
+///
+/// Events are indexed by every parent they wait for. Once a parent is
+/// inserted, exactly the events that waited only for it can be retried,
+/// without looking at the rest of the pool. Events are dropped, oldest
+/// first, when they are too old, when their author has too many of them
+/// buffered or when the pool is full (see [`PendingLimits`]).
+///
+/// Events in the pool are not validated in any way (their signatures can't
+/// be checked before the author's genesis is known), so the capacity should
+/// be kept reasonably small.
+#[derive(Clone)]
+pub(crate) struct PendingPool<TPayload, TGenesisPayload, TPeerId> {
+    events: HashMap<event::Hash, PendingEvent<TPayload, TGenesisPayload, TPeerId>>,
+    /// `missing parent -> events waiting for it`
+    waiting_for: HashMap<event::Hash, HashSet<event::Hash>>,
+    arrival_order: BTreeMap<u64, event::Hash>,
+    /// Arrivals of the buffered events of each author
+    by_author: HashMap<TPeerId, BTreeSet<u64>>,
+    next_arrival: u64,
+    limits: PendingLimits,
+    /// Orphans that were inserted into the graph, not yet consumed by the
+    /// user. Up to `limits.capacity`, the oldest are dropped first.
+    resolved: VecDeque<event::Hash>,
+}
+
+impl<TPayload, TGenesisPayload, TPeerId> PendingPool<TPayload, TGenesisPayload, TPeerId>
+where
+    TPeerId: Eq + Hash + Clone,
+{
+    pub fn new(limits: PendingLimits) -> Self {
+        Self {
+            events: HashMap::new(),
+            waiting_for: HashMap::new(),
+            arrival_order: BTreeMap::new(),
+            by_author: HashMap::new(),
+            next_arrival: 0,
+            limits,
+            resolved: VecDeque::new(),
+        }
+    }
+
+    pub fn limits(&self) -> &PendingLimits {
+        &self.limits
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn contains(&self, hash: &event::Hash) -> bool {
+        self.events.contains_key(hash)
+    }
+
+    /// Buffer the event until all of `missing` arrive. Returns the evicted
+    /// events, if any, in the order of their arrival.
+    ///
+    /// Events already in the pool keep their original place in the eviction
+    /// order, only the parents they wait for are updated.
+    pub fn insert(
+        &mut self,
+        event: UnsignedEvent<TPayload, TGenesisPayload, TPeerId>,
+        signature: Signature,
+        missing: HashSet<event::Hash>,
+        now: Timestamp,
+    ) -> Vec<event::Hash> {
+        let hash = event.hash().clone();
+        let (arrival, received_at) = match self.remove(&hash) {
+            Some(previous) => (previous.arrival, previous.received_at),
+            None => {
+                self.next_arrival += 1;
+                (self.next_arrival, now)
+            }
+        };
+        for parent in &missing {
+            self.waiting_for
+                .entry(parent.clone())
+                .or_default()
+                .insert(hash.clone());
+        }
+        let author = event.fields().author().clone();
+        self.by_author
+            .entry(author.clone())
+            .or_default()
+            .insert(arrival);
+        self.arrival_order.insert(arrival, hash.clone());
+        self.events.insert(
+            hash,
+            PendingEvent {
+                event,
+                signature,
+                missing,
+                arrival,
+                received_at,
+            },
+        );
+
+        let mut evicted = self.expire(now);
+        if let Some(quota) = self.limits.per_peer {
+            let arrivals = self.by_author.get(&author);
+            let over = arrivals.map_or(0, |a| a.len()).saturating_sub(quota);
+            let oldest: Vec<_> = arrivals.into_iter().flatten().take(over).copied().collect();
+            for arrival in oldest {
+                let hash = self.arrival_order[&arrival].clone();
+                self.remove(&hash);
+                evicted.push(hash);
+            }
+        }
+        while self.events.len() > self.limits.capacity {
+            let Some((_, oldest)) = self.arrival_order.first_key_value() else {
+                break;
+            };
+            let oldest = oldest.clone();
+            self.remove(&oldest);
+            evicted.push(oldest);
+        }
+        evicted
+    }
+
+    /// Drop events that are buffered for longer than allowed
+    pub fn expire(&mut self, now: Timestamp) -> Vec<event::Hash> {
+        let Some(max_age) = self.limits.max_age else {
+            return vec![];
+        };
+        let mut expired = vec![];
+        while let Some((_, oldest)) = self.arrival_order.first_key_value() {
+            if now.saturating_sub(self.events[oldest].received_at) <= max_age {
+                break;
+            }
+            let oldest = oldest.clone();
+            self.remove(&oldest);
+            expired.push(oldest);
+        }
+        expired
+    }
+
+    /// Note that `parent` is known now. Takes the events that waited for it
+    /// and for nothing else.
+    pub fn take_dependents(
+        &mut self,
+        parent: &event::Hash,
+    ) -> Vec<(UnsignedEvent<TPayload, TGenesisPayload, TPeerId>, Signature)> {
+        let Some(dependents) = self.waiting_for.remove(parent) else {
+            return vec![];
+        };
+        let mut ready = vec![];
+        for hash in dependents {
+            let Some(pending) = self.events.get_mut(&hash) else {
+                continue;
+            };
+            pending.missing.remove(parent);
+            if pending.missing.is_empty() {
+                ready.extend(self.remove(&hash));
+            }
+        }
+        // Keep the arrival order, `HashSet` iteration doesn't
+        ready.sort_unstable_by_key(|p| p.arrival);
+        ready.into_iter().map(|p| (p.event, p.signature)).collect()
+    }
+
+    pub fn mark_resolved(&mut self, hash: event::Hash) {
+        self.resolved.push_front(hash);
+        self.resolved.truncate(self.limits.capacity);
+    }
+
+    pub fn next_resolved(&mut self) -> Option<event::Hash> {
+        self.resolved.pop_back()
+    }
+
+    fn remove(
+        &mut self,
+        hash: &event::Hash,
+    ) -> Option<PendingEvent<TPayload, TGenesisPayload, TPeerId>> {
+        let pending = self.events.remove(hash)?;
+        self.arrival_order.remove(&pending.arrival);
+        let author = pending.event.fields().author();
+        if let Some(arrivals) = self.by_author.get_mut(author) {
+            arrivals.remove(&pending.arrival);
+            if arrivals.is_empty() {
+                self.by_author.remove(author);
+            }
+        }
+        for parent in &pending.missing {
+            if let Some(waiting) = self.waiting_for.get_mut(parent) {
+                waiting.remove(hash);
+                if waiting.is_empty() {
+                    self.waiting_for.remove(parent);
+                }
+            }
+        }
+        Some(pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::event::{Kind, Parents, SignedEvent};
+
+    type Pool = PendingPool<(), (), u64>;
+
+    fn hash(n: u8) -> event::Hash {
+        event::Hash::from_array([n; 64])
+    }
+
+    fn orphan(author: u64, self_parent: u8, other_parent: u8) -> SignedEvent<(), (), u64> {
+        let parents = Parents {
+            self_parent: hash(self_parent),
+            other_parent: hash(other_parent),
+        };
+        SignedEvent::new_fakely_signed((), Kind::Regular(parents), author, 0).unwrap()
+    }
+
+    fn buffer(
+        pool: &mut Pool,
+        event: &SignedEvent<(), (), u64>,
+        now: Timestamp,
+    ) -> Vec<event::Hash> {
+        let (unsigned, signature) = event.clone().into_parts();
+        let missing = unsigned.fields().kind().parents().cloned().collect();
+        pool.insert(unsigned, signature, missing, now)
+    }
+
+    fn woken(pool: &mut Pool, parent: u8) -> Vec<event::Hash> {
+        pool.take_dependents(&hash(parent))
+            .into_iter()
+            .map(|(e, _)| e.hash().clone())
+            .collect()
+    }
+
+    #[test]
+    fn woken_after_all_parents() {
+        let mut pool = Pool::new(PendingLimits::new(10));
+        let both = orphan(0, 1, 2);
+        let one = orphan(1, 1, 1);
+        buffer(&mut pool, &both, 0);
+        buffer(&mut pool, &one, 0);
+        assert_eq!(pool.waiting_for[&hash(1)].len(), 2);
+
+        assert_eq!(woken(&mut pool, 1), vec![one.hash().clone()]);
+        assert!(pool.contains(both.hash()));
+        assert!(!pool.waiting_for.contains_key(&hash(1)));
+
+        assert_eq!(woken(&mut pool, 2), vec![both.hash().clone()]);
+        assert_eq!(pool.len(), 0);
+        assert!(pool.waiting_for.is_empty());
+        assert!(pool.by_author.is_empty());
+    }
+
+    #[test]
+    fn evicted_by_age_and_quota() {
+        let mut limits = PendingLimits::new(10);
+        limits.max_age = Some(5);
+        limits.per_peer = Some(2);
+        let mut pool = Pool::new(limits);
+        let events: Vec<_> = (0..4).map(|i| orphan(i % 2, 10 + i as u8, 20)).collect();
+        // Authors 0, 1, 0, 1
+        for (time, event) in events.iter().enumerate() {
+            assert!(buffer(&mut pool, event, time as Timestamp).is_empty());
+        }
+        // Third event of author 0 pushes out its first one only
+        let extra = orphan(0, 30, 20);
+        assert_eq!(buffer(&mut pool, &extra, 4), vec![events[0].hash().clone()]);
+        assert_eq!(pool.len(), 4);
+
+        // Events received at 1 and 2 are older than 5 at 8
+        assert_eq!(
+            pool.expire(8),
+            vec![events[1].hash().clone(), events[2].hash().clone()]
+        );
+        assert_eq!(pool.by_author[&0].len(), 1);
+        assert_eq!(pool.by_author[&1].len(), 1);
+        // Buffering again doesn't renew the age
+        assert_eq!(
+            buffer(&mut pool, &events[3], 9),
+            vec![events[3].hash().clone()]
+        );
+        assert!(pool.contains(extra.hash()));
+        assert_eq!(pool.waiting_for[&hash(20)].len(), 1);
+    }
+
+    #[test]
+    fn resolved_bounded() {
+        let mut pool = Pool::new(PendingLimits::new(2));
+        for i in 0..5 {
+            pool.mark_resolved(hash(i));
+        }
+        assert_eq!(pool.next_resolved(), Some(hash(3)));
+        assert_eq!(pool.next_resolved(), Some(hash(4)));
+        assert_eq!(pool.next_resolved(), None);
+    }
+}
@@ -0,0 +1,175 @@
+//! Which peer delivered each event.
+//!
+//! Events arrive from relays, not only from their authors, so a relay can
+//! forward events that fail validation or that everybody has already.
+//! Events applied with [`Graph::apply_sync_jobs_from`] or pushed with a
+//! [`SyncRequest`](super::sync::SyncRequest) remember the peer that
+//! delivered them first ([`EventInfo::relay`](super::EventInfo::relay)),
+//! and each relay gets [`RelayStats`] of its deliveries. Together with the
+//! peer scores of [`strategy`](crate::algorithm::strategy) this tells which
+//! relays inject invalid or stale data.
+//!
+//! Provenance is local: it is not part of the events and doesn't affect the
+//! consensus. Relays of pruned events are forgotten, their stats are kept.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::Graph;
+use crate::algorithm::event;
+
+/// Events delivered by a relay
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RelayStats {
+    /// Events we didn't know before
+    pub first: u64,
+    /// Events we knew already, from another relay or from this one
+    pub known: u64,
+    /// Events that failed validation, e.g. bad signatures or unknown parents
+    pub rejected: u64,
+}
+
+impl RelayStats {
+    /// Share of the delivered events that were rejected
+    pub fn rejected_ratio(&self) -> f64 {
+        let total = self.first + self.known + self.rejected;
+        match total {
+            0 => 0.0,
+            _ => self.rejected as f64 / total as f64,
+        }
+    }
+}
+
+pub(super) enum Delivery {
+    First,
+    Known,
+    Rejected,
+}
+
+#[derive(Clone)]
+pub(super) struct Provenance<TPeerId> {
+    relays: HashMap<event::Hash, TPeerId>,
+    stats: HashMap<TPeerId, RelayStats>,
+}
+
+impl<TPeerId> Default for Provenance<TPeerId> {
+    fn default() -> Self {
+        Self {
+            relays: HashMap::new(),
+            stats: HashMap::new(),
+        }
+    }
+}
+
+impl<TPeerId: Eq + Hash + Clone> Provenance<TPeerId> {
+    pub(super) fn record(&mut self, relay: &TPeerId, event: &event::Hash, delivery: Delivery) {
+        let stats = self.stats.entry(relay.clone()).or_default();
+        match delivery {
+            Delivery::First => {
+                stats.first += 1;
+                self.relays.insert(event.clone(), relay.clone());
+            }
+            Delivery::Known => stats.known += 1,
+            Delivery::Rejected => stats.rejected += 1,
+        }
+    }
+}
+
+impl<TPeerId: Eq + Hash> Provenance<TPeerId> {
+    pub(super) fn relay_of(&self, event: &event::Hash) -> Option<&TPeerId> {
+        self.relays.get(event)
+    }
+
+    pub(super) fn forget(&mut self, event: &event::Hash) {
+        self.relays.remove(event);
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + Hash,
+{
+    /// Peer that delivered the event first, `None` for events created or
+    /// pushed locally and for unknown ones
+    pub fn relay_of(&self, event: &event::Hash) -> Option<&TPeerId> {
+        self.provenance.relay_of(event)
+    }
+
+    /// Deliveries of `relay` so far, zeros if it delivered nothing
+    pub fn relay_stats(&self, relay: &TPeerId) -> RelayStats {
+        self.provenance
+            .stats
+            .get(relay)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Stats of all relays, in no particular order
+    pub fn relays(&self) -> impl Iterator<Item = (&TPeerId, &RelayStats)> {
+        self.provenance.stats.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::datastructure::sync::{Jobs, SyncRequest};
+    use crate::algorithm::datastructure::PushError;
+    use crate::testing::{GraphBuilder, TestGraph};
+
+    fn node(id: u64) -> TestGraph<u32, u64> {
+        GraphBuilder::new("a", id, 0u32, 999).build().unwrap().graph
+    }
+
+    #[test]
+    fn first_relay_recorded() {
+        let (mut author, mut local) = (node(1), node(2));
+        let tip = author.self_tip().unwrap().clone();
+        author.create_event(5, tip).unwrap();
+        let jobs = author
+            .generate_sync_for_request(&local.sync_request())
+            .unwrap();
+        let events: Vec<_> = jobs.as_linear().iter().map(|e| e.hash().clone()).collect();
+
+        // Relay 3 forwards the author's events first, then the author pushes
+        // them with its request
+        assert_eq!(local.apply_sync_jobs_from(&3, jobs.clone()).unwrap(), 2);
+        let mut request = SyncRequest {
+            events: Some(jobs),
+            ..author.sync_request()
+        };
+        assert_eq!(local.apply_sync_request(&mut request).unwrap(), 0);
+        for event in &events {
+            assert_eq!(local.relay_of(event), Some(&3));
+            assert_eq!(local.event_info(event).unwrap().relay, Some(3));
+        }
+        assert_eq!(local.relay_of(local.self_tip().unwrap()), None);
+        let first = RelayStats {
+            first: 2,
+            ..Default::default()
+        };
+        assert_eq!(local.relay_stats(&3), first);
+        let known = RelayStats {
+            known: 2,
+            ..Default::default()
+        };
+        assert_eq!(local.relay_stats(&1), known);
+
+        // A relay of an event of a peer we don't know
+        let mut stranger = node(4);
+        let tip = stranger.self_tip().unwrap().clone();
+        stranger.create_event(6, tip).unwrap();
+        let orphan = stranger
+            .generate_sync_for_request(&local.sync_request())
+            .unwrap()
+            .into_linear()
+            .pop()
+            .unwrap();
+        let result = local.apply_sync_jobs_from(&5, Jobs::from_linear(vec![orphan]));
+        assert!(matches!(result, Err(PushError::PeerNotFound { .. })));
+        assert_eq!(local.relay_stats(&5).rejected, 1);
+        assert_eq!(local.relay_stats(&5).rejected_ratio(), 1.0);
+        assert_eq!(local.relays().count(), 3);
+    }
+}
@@ -0,0 +1,152 @@
+//! Search for events matching several conditions, so that debugging tools
+//! don't need to scan the graph by hand.
+//!
+//! ```ignore
+//! // Events of peer 1 from rounds 2-3 that see `x` but don't see `y`
+//! let found: Vec<_> = graph
+//!     .query()
+//!     .by_author(1)
+//!     .in_rounds(2..=3)
+//!     .sees(x)
+//!     .not_sees(y)
+//!     .iter()
+//!     .collect();
+//! ```
+
+use std::ops::RangeInclusive;
+
+use super::Graph;
+use crate::algorithm::{event, RoundNum};
+
+enum Condition<'a, TPayload, TPeerId> {
+    Author(TPeerId),
+    Payload(Box<dyn Fn(&TPayload) -> bool + 'a>),
+    /// The event has this one as an ancestor
+    Sees(event::Hash),
+    NotSees(event::Hash),
+    /// The event is an ancestor of this one
+    SeenBy(event::Hash),
+    NotSeenBy(event::Hash),
+}
+
+/// Conditions on events, all of which must hold. Created by [`Graph::query`].
+///
+/// Ancestry conditions referring to unknown events match nothing.
+pub struct Query<'a, TPayload, TGenesisPayload, TPeerId, TSigner, TClock> {
+    graph: &'a Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+    rounds: Option<RangeInclusive<RoundNum>>,
+    conditions: Vec<Condition<'a, TPayload, TPeerId>>,
+}
+
+impl<'a, TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Query<'a, TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash,
+{
+    pub fn by_author(mut self, author: TPeerId) -> Self {
+        self.conditions.push(Condition::Author(author));
+        self
+    }
+
+    /// Applying it several times intersects the ranges
+    pub fn in_rounds(mut self, rounds: RangeInclusive<RoundNum>) -> Self {
+        self.rounds = Some(match self.rounds {
+            Some(r) => *r.start().max(rounds.start())..=*r.end().min(rounds.end()),
+            None => rounds,
+        });
+        self
+    }
+
+    pub fn payload_matches<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&TPayload) -> bool + 'a,
+    {
+        self.conditions
+            .push(Condition::Payload(Box::new(predicate)));
+        self
+    }
+
+    /// The event has `target` as an ancestor (or is `target` itself)
+    pub fn sees(mut self, target: event::Hash) -> Self {
+        self.conditions.push(Condition::Sees(target));
+        self
+    }
+
+    pub fn not_sees(mut self, target: event::Hash) -> Self {
+        self.conditions.push(Condition::NotSees(target));
+        self
+    }
+
+    /// The event is an ancestor of `observer` (or is `observer` itself)
+    pub fn seen_by(mut self, observer: event::Hash) -> Self {
+        self.conditions.push(Condition::SeenBy(observer));
+        self
+    }
+
+    pub fn not_seen_by(mut self, observer: event::Hash) -> Self {
+        self.conditions.push(Condition::NotSeenBy(observer));
+        self
+    }
+
+    fn matches(&self, hash: &event::Hash) -> bool {
+        let graph = self.graph;
+        let event = match graph.event(hash) {
+            Some(e) => e,
+            None => return false,
+        };
+        self.conditions.iter().all(|c| match c {
+            Condition::Author(author) => event.author() == author,
+            Condition::Payload(predicate) => event.payload().is_some_and(predicate),
+            Condition::Sees(target) => graph.see(hash, target).unwrap_or(false),
+            Condition::NotSees(target) => graph.see(hash, target).is_ok_and(|s| !s),
+            Condition::SeenBy(observer) => graph.see(observer, hash).unwrap_or(false),
+            Condition::NotSeenBy(observer) => graph.see(observer, hash).is_ok_and(|s| !s),
+        })
+    }
+
+    /// Matching events in no particular order. Conditions are checked lazily,
+    /// as the iterator advances; the round range, if set, limits the events
+    /// that are checked at all.
+    pub fn iter(self) -> impl Iterator<Item = &'a event::Hash> + 'a
+    where
+        TPayload: 'a,
+        TGenesisPayload: 'a,
+        TPeerId: 'a,
+        TSigner: 'a,
+        TClock: 'a,
+    {
+        let graph = self.graph;
+        let candidates: Box<dyn Iterator<Item = &'a event::Hash> + 'a> = match &self.rounds {
+            Some(rounds) => Box::new(
+                graph
+                    .round_index
+                    .iter()
+                    .skip_while({
+                        let start = *rounds.start();
+                        move |(round, _)| *round < start
+                    })
+                    .take_while({
+                        let end = *rounds.end();
+                        move |(round, _)| *round <= end
+                    })
+                    .flat_map(|(_, events)| events.iter()),
+            ),
+            None => Box::new(graph.all_events.keys()),
+        };
+        candidates.filter(move |hash| self.matches(hash))
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+{
+    /// Start a search over the events, matching everything until conditions
+    /// are added.
+    pub fn query(&self) -> Query<'_, TPayload, TGenesisPayload, TPeerId, TSigner, TClock> {
+        Query {
+            graph: self,
+            rounds: None,
+            conditions: vec![],
+        }
+    }
+}
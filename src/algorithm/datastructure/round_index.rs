@@ -0,0 +1,156 @@
+//! Events of each round, stored from the first round that wasn't pruned.
+//!
+//! With epochs, round numbering restarts at each epoch: the index numbers a
+//! round by its epoch and its offset from the epoch's first round
+//! ([`RoundIndex::number`]). [`Graph::prune`](super::Graph::prune) rebases
+//! the index on the first round of the latest epoch, so the base offset is
+//! always the start of an epoch and the index holds entries for the rounds
+//! after that only. Internally rounds are counted from the first genesis,
+//! as consensus compares rounds of different epochs. Events kept in pruned
+//! rounds (geneses and the latest events of members) keep their rounds in
+//! `round_of`, the index lists no events for those rounds.
+
+use std::collections::{HashSet, VecDeque};
+use std::ops::{Index, IndexMut};
+
+use crate::algorithm::{event, RoundNum};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct RoundIndex {
+    /// Round of `rounds[0]`
+    base: RoundNum,
+    rounds: VecDeque<HashSet<event::Hash>>,
+    /// What pruned rounds list
+    empty: HashSet<event::Hash>,
+    /// Rounds per epoch, `None` if epochs are disabled
+    epoch_length: Option<usize>,
+}
+
+impl RoundIndex {
+    pub fn new() -> Self {
+        Self {
+            base: 0,
+            rounds: VecDeque::from([HashSet::new()]),
+            empty: HashSet::new(),
+            epoch_length: None,
+        }
+    }
+
+    pub fn epoch_length(&self) -> Option<usize> {
+        self.epoch_length
+    }
+
+    pub fn set_epoch_length(&mut self, rounds: Option<usize>) {
+        self.epoch_length = rounds;
+    }
+
+    /// Epoch of the round and the round's number within it. Without epochs
+    /// all rounds are in epoch 0.
+    pub fn number(&self, round: RoundNum) -> (usize, RoundNum) {
+        match self.epoch_length {
+            Some(length) => (round / length, round % length),
+            None => (0, round),
+        }
+    }
+
+    /// First round of the epoch
+    pub fn epoch_start(&self, epoch: usize) -> RoundNum {
+        epoch * self.epoch_length.unwrap_or(0)
+    }
+
+    /// Rounds before this one were pruned
+    pub fn base(&self) -> RoundNum {
+        self.base
+    }
+
+    /// Number of the round after the latest one
+    pub fn len(&self) -> usize {
+        self.base + self.rounds.len()
+    }
+
+    /// Empty for pruned rounds, `None` for rounds not reached yet
+    pub fn get(&self, round: RoundNum) -> Option<&HashSet<event::Hash>> {
+        match round.checked_sub(self.base) {
+            Some(offset) => self.rounds.get(offset),
+            None => Some(&self.empty),
+        }
+    }
+
+    /// `None` for pruned rounds and rounds not reached yet
+    pub fn get_mut(&mut self, round: RoundNum) -> Option<&mut HashSet<event::Hash>> {
+        self.rounds.get_mut(round.checked_sub(self.base)?)
+    }
+
+    /// Start the round after the latest one
+    pub fn push(&mut self, events: HashSet<event::Hash>) {
+        self.rounds.push_back(events);
+    }
+
+    /// Rounds that are not pruned, with their numbers
+    pub fn iter(&self) -> impl Iterator<Item = (RoundNum, &HashSet<event::Hash>)> + '_ {
+        (self.base..).zip(&self.rounds)
+    }
+
+    /// Drop the entries of the rounds before `bound`, keeping the latest
+    /// round. The graph passes [`epoch_start`](Self::epoch_start)s.
+    pub fn prune_below(&mut self, bound: RoundNum) {
+        while self.base < bound && self.rounds.len() > 1 {
+            self.rounds.pop_front();
+            self.base += 1;
+        }
+    }
+}
+
+impl Index<RoundNum> for RoundIndex {
+    type Output = HashSet<event::Hash>;
+
+    fn index(&self, round: RoundNum) -> &Self::Output {
+        self.get(round).expect("Round is not reached yet")
+    }
+}
+
+impl IndexMut<RoundNum> for RoundIndex {
+    fn index_mut(&mut self, round: RoundNum) -> &mut Self::Output {
+        self.get_mut(round)
+            .expect("Round is pruned or not reached yet")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebased_on_prune() {
+        let hash = |i: u8| event::Hash::from_array([i; 64]);
+        let mut index = RoundIndex::new();
+        index[0].insert(hash(0));
+        for i in 1..5 {
+            index.push(HashSet::from([hash(i)]));
+        }
+        assert_eq!(index.len(), 5);
+        index.prune_below(3);
+        assert_eq!((index.base(), index.len()), (3, 5));
+        assert!(index[1].is_empty());
+        assert!(index.get_mut(1).is_none());
+        assert!(index[3].contains(&hash(3)));
+        assert!(index.get(5).is_none());
+        assert_eq!(
+            index.iter().map(|(round, _)| round).collect::<Vec<_>>(),
+            [3, 4]
+        );
+        // The latest round stays, events go there next
+        index.prune_below(10);
+        assert_eq!((index.base(), index.len()), (4, 5));
+    }
+
+    #[test]
+    fn numbered_within_epochs() {
+        let mut index = RoundIndex::new();
+        assert_eq!(index.number(7), (0, 7));
+        index.set_epoch_length(Some(3));
+        assert_eq!(index.number(7), (2, 1));
+        assert_eq!(index.number(6), (2, 0));
+        assert_eq!(index.epoch_start(2), 6);
+    }
+}
@@ -0,0 +1,281 @@
+//! Fair share of events per member and round.
+//!
+//! Members agree on a quota: at most `Q` events of each member in a round.
+//! A graph with a quota ([`Graph::set_round_quota`]) refuses to author an
+//! own event that would exceed it, and [`Graph::quota_violations`] lists the
+//! members that exceeded it. Violations are not rejected, as the events of
+//! an honest member may land in one round when it is slow to see the
+//! others, so they are only flagged for the operator.
+//!
+//! The quota can be changed by the members themselves through finalized
+//! payloads: [`Graph::apply_quota_governance`] looks at the payloads
+//! finalized since its previous call and recognizes changes with the
+//! application's function. A change finalized in round `r` applies to rounds
+//! after `r`, so all members switch at the same round.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::{EventCreateError, Graph};
+use crate::algorithm::core::{self, Entry, EventTable, Scratch};
+use crate::algorithm::event::{self, Parents};
+use crate::algorithm::RoundNum;
+
+/// Quota in force at each round
+#[derive(Debug, Clone, Default)]
+pub(super) struct QuotaSchedule {
+    /// Sorted by the first round, the first one is from the configuration
+    changes: Vec<(RoundNum, Option<usize>)>,
+    /// Finalized events looked at by the governance
+    scanned: usize,
+}
+
+impl QuotaSchedule {
+    fn quota(&self, round: RoundNum) -> Option<usize> {
+        self.changes
+            .iter()
+            .rev()
+            .find(|(first, _)| *first <= round)
+            .and_then(|(_, quota)| *quota)
+    }
+
+    fn set(&mut self, first: RoundNum, quota: Option<usize>) {
+        self.changes.retain(|(r, _)| *r < first);
+        self.changes.push((first, quota));
+    }
+}
+
+/// Member that authored more events in a round than the quota allowed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaViolation<TPeerId> {
+    pub peer: TPeerId,
+    pub round: RoundNum,
+    pub events: usize,
+    pub quota: usize,
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + Hash + Clone,
+{
+    /// Quota of all rounds, until changed by the governance. `None` for
+    /// no limit. All members must use the same one.
+    pub fn set_round_quota(&mut self, quota: Option<usize>) {
+        self.round_quota.set(0, quota);
+    }
+
+    /// Quota in force in the round
+    pub fn round_quota(&self, round: RoundNum) -> Option<usize> {
+        self.round_quota.quota(round)
+    }
+
+    /// Look for quota changes in the payloads finalized since the previous
+    /// call. `governance` returns the new quota if the payload changes it.
+    /// Returns the number of changes.
+    pub fn apply_quota_governance<F>(&mut self, mut governance: F) -> usize
+    where
+        F: FnMut(&TPayload) -> Option<Option<usize>>,
+    {
+        let mut changes = vec![];
+        for hash in self.ordering.ordered().skip(self.round_quota.scanned) {
+            // Pruned events were scanned before
+            let Some(event) = self.all_events.get(hash) else {
+                continue;
+            };
+            if let Some(quota) = event.payload().and_then(&mut governance) {
+                let (round_received, _, _) = self
+                    .ordering_data(hash)
+                    .expect("Finalized events have ordering data");
+                changes.push((round_received + 1, quota));
+            }
+        }
+        self.round_quota.scanned = self.ordering.len();
+        for (first, quota) in &changes {
+            self.round_quota.set(*first, *quota);
+        }
+        changes.len()
+    }
+
+    /// Events of `peer` in the round
+    pub fn events_in_round_by(&self, peer: &TPeerId, round: RoundNum) -> usize {
+        self.round_index.get(round).map_or(0, |events| {
+            events
+                .iter()
+                .filter(|e| self.headers.get(*e).is_some_and(|h| &h.author == peer))
+                .count()
+        })
+    }
+
+    /// Members over the quota, by round and then in the [canonical
+    /// order](super::peer_order). Rounds pruned before are not counted.
+    pub fn quota_violations(&self) -> Vec<QuotaViolation<TPeerId>> {
+        let mut violations = vec![];
+        for (round, events) in self.round_index.iter() {
+            let Some(quota) = self.round_quota(round) else {
+                continue;
+            };
+            // One pass over the round instead of one per member
+            let mut by_author: HashMap<&TPeerId, usize> = HashMap::new();
+            for header in events.iter().filter_map(|e| self.headers.get(e)) {
+                *by_author.entry(&header.author).or_default() += 1;
+            }
+            let mut over: Vec<_> = by_author
+                .into_iter()
+                .filter(|(_, events)| *events > quota)
+                .map(|(peer, events)| (self.canonical_index(peer), peer, events))
+                .collect();
+            over.sort_by_key(|(index, _, _)| *index);
+            violations.extend(over.into_iter().map(|(_, peer, events)| QuotaViolation {
+                peer: peer.clone(),
+                round,
+                events,
+                quota,
+            }));
+        }
+        violations
+    }
+
+    /// Fails if an event of `author` with these parents would exceed the
+    /// quota of its round
+    pub(super) fn check_round_quota(
+        &self,
+        author: &TPeerId,
+        parents: Parents,
+    ) -> Result<(), EventCreateError<TPeerId>> {
+        // Unknown parents are reported when pushing
+        if self.round_quota.changes.is_empty()
+            || !self.headers.contains_key(&parents.self_parent)
+            || !self.headers.contains_key(&parents.other_parent)
+        {
+            return Ok(());
+        }
+        let candidate = Candidate {
+            table: self,
+            // Hashes of real events are not all zeros
+            hash: event::Hash::from_array([0; 64]),
+            author,
+            parents,
+        };
+        let round = core::determine_round(&candidate, &self.members_count(), &candidate.hash)
+            .expect("Parents are known");
+        match self.round_quota(round) {
+            Some(quota) if self.events_in_round_by(author, round) >= quota => {
+                Err(EventCreateError::RoundQuotaExceeded { round, quota })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Table with an event that is not created yet, to find its round
+struct Candidate<'a, T: EventTable> {
+    table: &'a T,
+    hash: event::Hash,
+    author: &'a T::PeerId,
+    parents: Parents,
+}
+
+impl<T: EventTable> EventTable for Candidate<'_, T> {
+    type PeerId = T::PeerId;
+
+    fn entry(&self, event: &event::Hash) -> Option<Entry<'_, T::PeerId>> {
+        if event != &self.hash {
+            return self.table.entry(event);
+        }
+        Some(Entry {
+            hash: &self.hash,
+            author: self.author,
+            // Same author, same slot
+            slot: self
+                .table
+                .entry(&self.parents.self_parent)
+                .and_then(|e| e.slot),
+            parents: Some(&self.parents),
+        })
+    }
+
+    fn round(&self, event: &event::Hash) -> Option<RoundNum> {
+        self.table.round(event)
+    }
+
+    fn round_events(&self, round: RoundNum) -> Option<&std::collections::HashSet<event::Hash>> {
+        self.table.round_events(round)
+    }
+
+    fn is_witness(&self, event: &event::Hash) -> bool {
+        self.table.is_witness(event)
+    }
+
+    fn canonical_index(&self, peer: &T::PeerId) -> Option<usize> {
+        self.table.canonical_index(peer)
+    }
+
+    fn scratch(&self) -> Option<&Scratch> {
+        self.table.scratch()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fixture, GraphBuilder};
+
+    #[test]
+    fn own_events_throttled() {
+        let mut graph = GraphBuilder::new("a", 0u64, 0u64, 999)
+            .peer("b", 1)
+            .peer("c", 2)
+            .build()
+            .unwrap()
+            .graph;
+        graph.set_round_quota(Some(3));
+        // The genesis counts, the others can't be strongly seen alone
+        for _ in 0..2 {
+            let tip = graph.self_tip().unwrap().clone();
+            graph.create_event(0, tip).unwrap();
+        }
+        let tip = graph.self_tip().unwrap().clone();
+        assert!(matches!(
+            graph.create_event(0, tip.clone()),
+            Err(EventCreateError::RoundQuotaExceeded { round: 0, quota: 3 })
+        ));
+        assert_eq!(graph.events_in_round_by(&0, 0), 3);
+        assert!(graph.quota_violations().is_empty());
+
+        graph.set_round_quota(Some(1));
+        assert_eq!(
+            graph.quota_violations(),
+            vec![QuotaViolation {
+                peer: 0,
+                round: 0,
+                events: 3,
+                quota: 1
+            }]
+        );
+        graph.set_round_quota(None);
+        graph.create_event(0, tip).unwrap();
+    }
+
+    #[test]
+    fn quota_changed_by_finalized_payloads() {
+        let mut graph = fixture::random_gossip().build().unwrap().graph;
+        graph.set_round_quota(Some(100));
+        let first = graph.ordering.ordered().next().unwrap().clone();
+        let (round_received, _, _) = graph.ordering_data(&first).unwrap();
+
+        // The first finalized payload lowers the quota
+        let mut governance = true;
+        let changes =
+            graph.apply_quota_governance(|_| std::mem::take(&mut governance).then_some(Some(1)));
+        assert_eq!(changes, 1);
+        assert_eq!(graph.round_quota(round_received), Some(100));
+        assert_eq!(graph.round_quota(round_received + 1), Some(1));
+        assert!(graph
+            .quota_violations()
+            .iter()
+            .all(|v| v.round > round_received && v.quota == 1));
+        // Already scanned
+        assert_eq!(graph.apply_quota_governance(|_| Some(None)), 0);
+    }
+}
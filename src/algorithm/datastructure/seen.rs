@@ -0,0 +1,63 @@
+//! Window of recently received events.
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::algorithm::event;
+
+/// Hashes of the last `capacity` events received in syncs. With gossip the
+/// same event usually arrives from several neighbours in a short time, the
+/// window lets such copies be dropped before any work is done on them.
+#[derive(Clone)]
+pub(crate) struct RecentlySeen {
+    hashes: HashSet<event::Hash>,
+    order: VecDeque<event::Hash>,
+    capacity: usize,
+}
+
+impl RecentlySeen {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            hashes: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn contains(&self, hash: &event::Hash) -> bool {
+        self.hashes.contains(hash)
+    }
+
+    /// Remember the hash, forgetting the oldest one if the window is full.
+    pub fn insert(&mut self, hash: event::Hash) {
+        if self.capacity == 0 || !self.hashes.insert(hash.clone()) {
+            return;
+        }
+        if self.order.len() == self.capacity {
+            let oldest = self.order.pop_front().expect("capacity is not zero");
+            self.hashes.remove(&oldest);
+        }
+        self.order.push_back(hash);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oldest_forgotten() {
+        let hashes: Vec<_> = (0u8..3).map(|i| event::Hash::from_array([i; 64])).collect();
+        let mut window = RecentlySeen::new(2);
+        window.insert(hashes[0].clone());
+        window.insert(hashes[1].clone());
+        window.insert(hashes[0].clone());
+        assert!(window.contains(&hashes[0]) && window.contains(&hashes[1]));
+        window.insert(hashes[2].clone());
+        assert!(!window.contains(&hashes[0]));
+        assert!(window.contains(&hashes[1]) && window.contains(&hashes[2]));
+
+        let mut window = RecentlySeen::new(0);
+        window.insert(hashes[0].clone());
+        assert!(!window.contains(&hashes[0]));
+    }
+}
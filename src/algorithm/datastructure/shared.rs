@@ -0,0 +1,140 @@
+//! Thread-safe access to the graph. Details are in [`SharedGraph`].
+
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use super::{sync, Graph};
+use crate::algorithm::event::{EventWrapper, SignedEvent};
+use crate::algorithm::{IncrementalClock, ManualClock, MockSigner};
+
+/// Handle to a graph shared between threads. Clones point to the same graph.
+///
+/// # Locking policy
+///
+/// The graph is behind a single `RwLock`, tuned for many readers and one writer:
+/// - queries (`event`, `event_info`, `see`, `generate_sync_for`, ...) take `&self`
+///   and run under [`read`](Self::read), concurrently with each other. Internal
+///   caches are guarded separately, so readers never block on each other for long;
+/// - anything that changes state (`push_event`, `create_event`, draining
+///   `next_finalized_event`, ...) needs [`write`](Self::write).
+///
+/// Keep write sections short (e.g. push a batch of received events and release),
+/// otherwise readers are starved. Poisoning is not recovered from: a panic while
+/// holding the lock means the graph may be inconsistent, so the following
+/// accesses panic too.
+pub struct SharedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock> {
+    inner: Arc<GraphLock<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>>,
+}
+
+type GraphLock<TPayload, TGenesisPayload, TPeerId, TSigner, TClock> =
+    RwLock<Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>>;
+type ReadGuard<'a, TPayload, TGenesisPayload, TPeerId, TSigner, TClock> =
+    RwLockReadGuard<'a, Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>>;
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    SharedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+{
+    pub fn new(graph: Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(graph)),
+        }
+    }
+
+    pub fn read(&self) -> ReadGuard<'_, TPayload, TGenesisPayload, TPeerId, TSigner, TClock> {
+        self.inner.read().expect("graph lock poisoned")
+    }
+
+    pub fn write(
+        &self,
+    ) -> RwLockWriteGuard<'_, Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>> {
+        self.inner.write().expect("graph lock poisoned")
+    }
+
+    /// `None` if the lock is held by a writer at the moment.
+    pub fn try_read(
+        &self,
+    ) -> Option<ReadGuard<'_, TPayload, TGenesisPayload, TPeerId, TSigner, TClock>> {
+        self.inner.try_read().ok()
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock> Clone
+    for SharedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    From<Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>>
+    for SharedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+{
+    fn from(value: Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>) -> Self {
+        Self::new(value)
+    }
+}
+
+// Compile-time audit: the types must stay `Send + Sync` as long as their
+// parameters are. Fails to build if e.g. `Rc` or raw pointers sneak in.
+#[allow(dead_code)]
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[allow(dead_code)]
+fn send_sync_audit() {
+    type TestGraph = Graph<Vec<u8>, Vec<u8>, u64, MockSigner<u64, Vec<u8>>, IncrementalClock>;
+    assert_send_sync::<TestGraph>();
+    assert_send_sync::<Graph<Vec<u8>, (), u64, MockSigner<u64, ()>, ManualClock>>();
+    assert_send_sync::<SharedGraph<Vec<u8>, (), u64, MockSigner<u64, ()>, ManualClock>>();
+    assert_send_sync::<EventWrapper<Vec<u8>, Vec<u8>, u64>>();
+    assert_send_sync::<SignedEvent<Vec<u8>, Vec<u8>, u64>>();
+    assert_send_sync::<sync::Jobs<Vec<u8>, Vec<u8>, u64>>();
+    assert_send_sync::<sync::Error>();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn readers_and_writer_work_concurrently() {
+        let graph = SharedGraph::new(Graph::new(
+            0u64,
+            0u64,
+            (),
+            999,
+            MockSigner::<u64, ()>::new(),
+            IncrementalClock::new(),
+        ));
+        let writer = {
+            let graph = graph.clone();
+            thread::spawn(move || {
+                for i in 1..=20 {
+                    let mut graph = graph.write();
+                    let tip = graph.peer_latest_event(&0).unwrap().clone();
+                    graph.create_event(i, tip).unwrap();
+                }
+            })
+        };
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let graph = graph.clone();
+                thread::spawn(move || {
+                    for _ in 0..20 {
+                        let graph = graph.read();
+                        let tip = graph.peer_latest_event(&0).unwrap();
+                        assert_eq!(graph.see(tip, graph.peer_genesis(&0).unwrap()), Ok(true));
+                    }
+                })
+            })
+            .collect();
+        writer.join().unwrap();
+        for r in readers {
+            r.join().unwrap();
+        }
+        assert_eq!(graph.read().peer_lane(&0).unwrap().count(), 21);
+    }
+}
@@ -17,8 +17,13 @@ use super::UnknownEvent;
 /// events).
 pub struct SliceIterator<'a, TPayload, TGenesisPayload, TPeerId, FContinue> {
     current_slice: HashSet<&'a EventWrapper<TPayload, TGenesisPayload, TPeerId>>,
+    /// Forking branches of a peer meet at their common self ancestor, it
+    /// must be returned once
+    visited: HashSet<&'a event::Hash>,
     continue_iterate_peer: FContinue,
     all_events: &'a HashMap<event::Hash, EventWrapper<TPayload, TGenesisPayload, TPeerId>>,
+    /// Unknown parents are skipped instead of being an error
+    parents_pruned: bool,
 }
 
 impl<'a, TPayload, TGenesisPayload, TPeerId, FContinue>
@@ -31,9 +36,9 @@ where
     /// Create iterator over graph slice.
     ///
     /// - `starting_slice`: initial slice, the iterator will go only to their same-peer
-    /// ancestors (created by the same peer).
+    ///   ancestors (created by the same peer).
     /// - `continue_condition`: predicate. When returns `false`, the event and its parents
-    /// are not considered.
+    ///   are not considered.
     /// - `all_events`: event lookup.
     pub fn new(
         starting_slice: &HashSet<&event::Hash>,
@@ -52,31 +57,37 @@ where
         let current_slice = HashSet::<_>::from_iter(current_slice);
         Ok(Self {
             current_slice,
+            visited: HashSet::new(),
             continue_iterate_peer: continue_condition,
             all_events,
+            parents_pruned: false,
         })
     }
 
+    /// Skip unknown parents, for graphs with pruned events (they are
+    /// ordered already, so the slice ends before them anyway).
+    pub fn parents_pruned(mut self, pruned: bool) -> Self {
+        self.parents_pruned = pruned;
+        self
+    }
+
     fn add_parents(
         &mut self,
         event: &EventWrapper<TPayload, TGenesisPayload, TPeerId>,
     ) -> Result<(), UnknownEvent> {
         if let event::Kind::Regular(parents) = event.kind() {
-            let self_parent = self
-                .all_events
-                .get(&parents.self_parent)
-                .ok_or(UnknownEvent(parents.self_parent.clone()))?;
-            self.current_slice.insert(self_parent);
-
+            let lookup = |parent: &event::Hash| match self.all_events.get(parent) {
+                Some(parent) => Ok(Some(parent)),
+                None if self.parents_pruned => Ok(None),
+                None => Err(UnknownEvent(parent.clone())),
+            };
+            let self_parent = lookup(&parents.self_parent)?;
             // We add only parents made by the same peer not to visit events multiple times
             let this_author = event.author();
-            let other_parent = self
-                .all_events
-                .get(&parents.other_parent)
-                .ok_or(UnknownEvent(parents.other_parent.clone()))?;
-            if other_parent.author() == this_author {
-                self.current_slice.insert(other_parent);
-            }
+            let other_parent = lookup(&parents.other_parent)?
+                .filter(|other_parent| other_parent.author() == this_author);
+            self.current_slice.extend(self_parent);
+            self.current_slice.extend(other_parent);
         }
         Ok(())
     }
@@ -95,7 +106,9 @@ where
     fn next(&mut self) -> Option<Self::Item> {
         while let Some(next_event) = self.current_slice.iter().next().cloned() {
             self.current_slice.remove(next_event);
-            if !(self.continue_iterate_peer)(next_event) {
+            if !self.visited.insert(next_event.inner().hash())
+                || !(self.continue_iterate_peer)(next_event)
+            {
                 continue;
             } else {
                 self.add_parents(next_event)
@@ -103,6 +116,6 @@ where
                 return Some(next_event);
             }
         }
-        return None;
+        None
     }
 }
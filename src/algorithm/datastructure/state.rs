@@ -0,0 +1,257 @@
+//! Hash of the consensus state, kept up to date as rounds are decided.
+//!
+//! Finalized events are the leaves of a Merkle mountain range, in their
+//! consensus order. The unique famous witnesses of each decided round are
+//! folded into a running hash round by round, sorted by hash. Both only
+//! depend on the consensus, so graphs that finalized the same rounds have
+//! the same [`Graph::state_hash`] regardless of the order they received
+//! events in. Other witnesses are left out: a late witness that nobody
+//! votes for may be known to some graphs when its round is decided and not
+//! to others.
+//!
+//! Appending a leaf merges at most one peak per level, and the state hash
+//! bags the peaks, so both take `O(log n)` hashes.
+
+use blake2::{Blake2b512, Digest};
+
+use super::Graph;
+use crate::algorithm::{event, RoundNum};
+
+const LEAF: u8 = 0;
+const NODE: u8 = 1;
+const FAME: u8 = 2;
+const STATE: u8 = 3;
+
+#[derive(Debug, Clone)]
+pub(super) struct StateAccumulator {
+    /// Roots of the perfect trees of the range, `peaks[h]` has `2^h` leaves
+    peaks: Vec<Option<event::Hash>>,
+    leaves: u64,
+    fame: event::Hash,
+}
+
+impl Default for StateAccumulator {
+    fn default() -> Self {
+        Self {
+            peaks: vec![],
+            leaves: 0,
+            fame: event::Hash::from_array([0; 64]),
+        }
+    }
+}
+
+fn bytes(hash: &event::Hash) -> &[u8] {
+    hash.as_ref()
+}
+
+fn digest<'a>(domain: u8, parts: impl IntoIterator<Item = &'a [u8]>) -> event::Hash {
+    let mut hasher = Blake2b512::new();
+    hasher.update([domain]);
+    for part in parts {
+        hasher.update(part);
+    }
+    event::Hash::from_array(hasher.finalize().into())
+}
+
+impl StateAccumulator {
+    /// Add the next finalized event
+    pub fn append(&mut self, event: &event::Hash) {
+        let mut carry = digest(LEAF, [bytes(event)]);
+        for peak in self.peaks.iter_mut() {
+            match peak.take() {
+                Some(left) => carry = digest(NODE, [bytes(&left), bytes(&carry)]),
+                None => {
+                    *peak = Some(carry);
+                    self.leaves += 1;
+                    return;
+                }
+            }
+        }
+        self.peaks.push(Some(carry));
+        self.leaves += 1;
+    }
+
+    /// Add the unique famous witnesses of the next decided round
+    pub fn decide_round(&mut self, round: RoundNum, mut witnesses: Vec<&event::Hash>) {
+        witnesses.sort_unstable();
+        let round = (round as u64).to_le_bytes();
+        let parts = [bytes(&self.fame), &round]
+            .into_iter()
+            .chain(witnesses.into_iter().map(bytes));
+        self.fame = digest(FAME, parts);
+    }
+
+    pub fn hash(&self) -> event::Hash {
+        let leaves = self.leaves.to_le_bytes();
+        let peaks = self.peaks.iter().rev().flatten().map(bytes);
+        digest(
+            STATE,
+            [&leaves[..]]
+                .into_iter()
+                .chain(peaks)
+                .chain([bytes(&self.fame)]),
+        )
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPeerId: Eq + std::hash::Hash,
+{
+    /// Add the round that was just ordered, starting at `first_position`
+    /// of the ordering
+    pub(super) fn update_state(&mut self, round: RoundNum, first_position: usize) {
+        let witnesses: Vec<_> = self
+            .round_unique_famous_witnesses(round)
+            .expect("ordered rounds are decided")
+            .into_iter()
+            .cloned()
+            .collect();
+        self.state.decide_round(round, witnesses.iter().collect());
+        for hash in self.ordering.ordered().skip(first_position) {
+            self.state.append(hash);
+        }
+    }
+
+    /// Hash of the finalized events in their order and of the unique
+    /// famous witnesses of decided rounds. Equal for graphs that agree on
+    /// the consensus so far, regardless of the order they received events
+    /// in.
+    ///
+    /// Finalized events are the leaves of a Merkle mountain range in their
+    /// consensus order, and the unique famous witnesses of each decided
+    /// round (sorted by hash) are folded into a running hash. Both are updated as
+    /// rounds are decided in `O(log n)` hashes, so the state hash is cheap
+    /// enough to compare on every sync.
+    pub fn state_hash(&self) -> event::Hash {
+        self.state.hash()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::{MockSigner, Signer};
+    use crate::testing::{fixture, TestGraph};
+
+    /// Root of the same range built over all leaves at once
+    fn mountain_range(leaves: &[event::Hash]) -> Vec<event::Hash> {
+        let mut peaks = vec![];
+        let mut rest = leaves;
+        while !rest.is_empty() {
+            let size = 1 << (usize::BITS - 1 - rest.len().leading_zeros());
+            let (tree, tail) = rest.split_at(size);
+            let mut level: Vec<_> = tree.iter().map(|l| digest(LEAF, [bytes(l)])).collect();
+            while level.len() > 1 {
+                level = level
+                    .chunks(2)
+                    .map(|pair| digest(NODE, [bytes(&pair[0]), bytes(&pair[1])]))
+                    .collect();
+            }
+            peaks.push(level.pop().unwrap());
+            rest = tail;
+        }
+        peaks
+    }
+
+    #[test]
+    fn incremental_range_matches_full() {
+        let leaves: Vec<_> = (0..37u8)
+            .map(|i| event::Hash::from_array([i; 64]))
+            .collect();
+        let mut state = StateAccumulator::default();
+        for (i, leaf) in leaves.iter().enumerate() {
+            state.append(leaf);
+            let incremental: Vec<_> = state.peaks.iter().rev().flatten().cloned().collect();
+            assert_eq!(incremental, mountain_range(&leaves[..=i]));
+        }
+        assert_eq!(state.leaves, 37);
+    }
+
+    #[test]
+    fn equal_for_same_consensus() {
+        let example = fixture::random_gossip();
+        let graph = example.build().unwrap().graph;
+        assert_ne!(graph.state_hash(), StateAccumulator::default().hash());
+
+        // Same events received in a different order
+        let mut other =
+            crate::testing::GraphBuilder::new(&example.peers[0], 0u64, (), example.coin_frequency)
+                .build()
+                .unwrap()
+                .graph;
+        let mut events = graph.generate_sync_for(&u64::MAX).unwrap().into_linear();
+        events.reverse();
+        while !events.is_empty() {
+            events.retain(|event| {
+                let (unsigned, signature) = event.clone().into_parts();
+                !matches!(
+                    other.push_event(unsigned, signature),
+                    Ok(()) | Err(crate::algorithm::PushError::EventAlreadyExists(_))
+                )
+            });
+        }
+        assert_eq!(other.state_hash(), graph.state_hash());
+
+        // Recomputed from scratch
+        let mut state = StateAccumulator::default();
+        for hash in graph.ordering.ordered() {
+            state.append(hash);
+        }
+        for round in 0..graph.ordering.next_round_to_order() {
+            let witnesses = graph.round_unique_famous_witnesses(round).unwrap();
+            state.decide_round(round, witnesses.into_iter().collect());
+        }
+        assert_eq!(state.hash(), graph.state_hash());
+    }
+
+    #[test]
+    fn late_witness_left_out() {
+        let example = fixture::random_gossip();
+        let reference = example.build().unwrap();
+        let (before, after) = example.events.split_at(example.events.len() / 2);
+        let author = |hash: &event::Hash| *reference.graph.event(hash).unwrap().author();
+        let round = |hash: &event::Hash| reference.graph.event_info(hash).unwrap().round;
+        // Fork of an earlier event on top of a later one: a witness of a
+        // later round that nobody builds on
+        let other_parent = reference.hash(&before.last().unwrap().name);
+        let self_parent = before
+            .iter()
+            .rev()
+            .map(|e| reference.hash(&e.name))
+            .find(|h| author(h) != author(other_parent) && round(h) < round(other_parent))
+            .unwrap();
+        let late = event::SignedEvent::new(
+            (),
+            event::Kind::Regular(event::Parents {
+                self_parent: self_parent.clone(),
+                other_parent: other_parent.clone(),
+            }),
+            author(self_parent),
+            *reference.graph.event(other_parent).unwrap().timestamp(),
+            |h| MockSigner::<u64, ()>::new().sign(h),
+        )
+        .unwrap();
+        let push_late = |graph: &mut TestGraph<(), u64>| {
+            let (unsigned, signature) = late.clone().into_parts();
+            graph.push_event(unsigned, signature).unwrap();
+        };
+
+        // One graph knows it before its round is decided, the other after
+        let mut early = example.build_geneses().unwrap().graph;
+        fixture::replay(&reference, before, &mut early, |_, _| {});
+        push_late(&mut early);
+        fixture::replay(&reference, after, &mut early, |_, _| {});
+        let mut late_graph = example.build_geneses().unwrap().graph;
+        fixture::replay(&reference, &example.events, &mut late_graph, |_, _| {});
+        let decided = late_graph.ordering.next_round_to_order();
+        push_late(&mut late_graph);
+
+        let witness = late.hash();
+        assert!(early.round_of(witness) < decided);
+        assert!(early.headers[witness].witness);
+        assert!(early.ordering.ordered().eq(late_graph.ordering.ordered()));
+        assert_eq!(early.state_hash(), late_graph.state_hash());
+    }
+}
@@ -0,0 +1,203 @@
+//! Tracking of payloads submitted by the application, from the queue to
+//! finalization.
+//!
+//! [`Graph::submit_transaction`] queues a payload and returns a
+//! [`TxHandle`]. The next event created by
+//! [`create_submitted_event`](Graph::create_submitted_event) carries it,
+//! and the handle follows the event until it is finalized. Handles can be
+//! kept apart from the graph (e.g. passed to the code waiting for a write),
+//! their status is updated by the graph.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+use super::{EventCreateError, Graph};
+use crate::algorithm::codec::PayloadCodec;
+use crate::algorithm::{event, Clock, RoundNum, Signer};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxStatus {
+    /// Waiting for the next own event
+    Pending,
+    /// Carried by an own event, not finalized yet
+    InEvent { hash: event::Hash },
+    /// The event is finalized: `round` received it, `position` is its place
+    /// among all finalized events
+    Finalized { round: RoundNum, position: usize },
+    /// Won't be finalized, e.g. the event could not be created
+    Dropped,
+}
+
+/// Status of a submitted payload, see the [module docs](self)
+#[derive(Debug, Clone)]
+pub struct TxHandle {
+    status: Arc<Mutex<TxStatus>>,
+}
+
+impl TxHandle {
+    pub fn status(&self) -> TxStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    fn set(&self, status: TxStatus) {
+        *self.status.lock().unwrap() = status;
+    }
+}
+
+/// Submitted payloads of a graph
+pub(super) struct Submissions<TPayload> {
+    queue: VecDeque<(TPayload, TxHandle)>,
+    in_flight: HashMap<event::Hash, TxHandle>,
+}
+
+impl<TPayload> Default for Submissions<TPayload> {
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            in_flight: HashMap::new(),
+        }
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+{
+    /// Queue the payload for the next own event created by
+    /// [`create_submitted_event`](Self::create_submitted_event)
+    pub fn submit_transaction(&mut self, payload: TPayload) -> TxHandle {
+        let handle = TxHandle {
+            status: Arc::new(Mutex::new(TxStatus::Pending)),
+        };
+        self.submissions.queue.push_back((payload, handle.clone()));
+        handle
+    }
+
+    /// Number of payloads waiting for an event
+    pub fn submitted_count(&self) -> usize {
+        self.submissions.queue.len()
+    }
+
+    /// Give up on the queued payloads, their handles report
+    /// [`TxStatus::Dropped`]
+    pub fn drop_submitted(&mut self) {
+        for (_, handle) in self.submissions.queue.drain(..) {
+            handle.set(TxStatus::Dropped);
+        }
+    }
+
+    /// Update handles of the events ordered from `first_position` on by
+    /// `round`
+    pub(super) fn track_finalized(&mut self, round: RoundNum, first_position: usize) {
+        if self.submissions.in_flight.is_empty() {
+            return;
+        }
+        for (position, hash) in self.ordering.ordered().enumerate().skip(first_position) {
+            if let Some(handle) = self.submissions.in_flight.remove(hash) {
+                handle.set(TxStatus::Finalized { round, position });
+            }
+        }
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPayload: PayloadCodec + Eq + std::hash::Hash + Debug + Clone,
+    TGenesisPayload: Serialize + Eq + std::hash::Hash + Debug + Clone,
+    TPeerId: Serialize + Eq + std::hash::Hash + Debug + Clone,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+    TClock: Clock,
+{
+    /// Create an own event carrying the oldest submitted payload, see
+    /// [`create_event`](Self::create_event). `None` if nothing is
+    /// submitted. If the event can't be created, the payload is dropped.
+    pub fn create_submitted_event(
+        &mut self,
+        other_parent: event::Hash,
+    ) -> Option<Result<event::Hash, EventCreateError<TPeerId>>> {
+        let (payload, handle) = self.submissions.queue.pop_front()?;
+        let result = self.create_event(payload, other_parent);
+        match &result {
+            Ok(hash) => match self.ordering.ordered().position(|h| h == hash) {
+                // Finalized right away, e.g. in a single-member network
+                Some(position) => {
+                    let round = self
+                        .event_info(hash)
+                        .and_then(|info| info.round_received)
+                        .expect("Ordered events have round received");
+                    handle.set(TxStatus::Finalized { round, position });
+                }
+                None => {
+                    handle.set(TxStatus::InEvent { hash: hash.clone() });
+                    self.submissions.in_flight.insert(hash.clone(), handle);
+                }
+            },
+            Err(_) => handle.set(TxStatus::Dropped),
+        }
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::{MockSigner, Signer};
+    use crate::testing::GraphBuilder;
+
+    #[test]
+    fn submission_followed_to_finality() {
+        let mut built = GraphBuilder::new("a", 0u64, 0u64, 999)
+            .peer("b", 1)
+            .peer("c", 2)
+            .build()
+            .unwrap();
+        let graph = &mut built.graph;
+        let first = graph.submit_transaction(10);
+        let second = graph.submit_transaction(20);
+        let dropped = graph.submit_transaction(30);
+        assert_eq!(first.status(), TxStatus::Pending);
+        assert_eq!(graph.submitted_count(), 3);
+
+        let genesis_b = graph.peer_genesis(&1).unwrap().clone();
+        let hash = graph.create_submitted_event(genesis_b).unwrap().unwrap();
+        assert_eq!(first.status(), TxStatus::InEvent { hash: hash.clone() });
+        assert_eq!(graph.event(&hash).unwrap().payload(), Some(&10));
+        let unknown = event::Hash::from_array([7; 64]);
+        assert!(graph.create_submitted_event(unknown).unwrap().is_err());
+        assert_eq!(second.status(), TxStatus::Dropped);
+        graph.drop_submitted();
+        assert_eq!(dropped.status(), TxStatus::Dropped);
+        assert!(graph.create_submitted_event(hash.clone()).is_none());
+
+        // Gossip between the members until the event is finalized
+        for step in 0..60 {
+            let author = [0u64, 1, 2][step % 3];
+            let other = graph
+                .peer_latest_event(&((author + 1) % 3))
+                .unwrap()
+                .clone();
+            let self_parent = graph.peer_latest_event(&author).unwrap().clone();
+            let event = event::SignedEvent::new(
+                0,
+                event::Kind::Regular(event::Parents {
+                    self_parent,
+                    other_parent: other,
+                }),
+                author,
+                step as u128 + 100,
+                |h| MockSigner::<u64, ()>::new().sign(h),
+            )
+            .unwrap();
+            let (event, signature) = event.into_parts();
+            graph.push_event(event, signature).unwrap();
+        }
+        let TxStatus::Finalized { round, position } = first.status() else {
+            panic!("Not finalized: {:?}", first.status());
+        };
+        assert_eq!(graph.ordering.ordered().nth(position), Some(&hash));
+        assert_eq!(graph.event_info(&hash).unwrap().round_received, Some(round));
+    }
+}
@@ -0,0 +1,447 @@
+//! Admission of received sync messages, so that a flooding peer can't take
+//! all the CPU time (consensus computations happen during ingestion, starving
+//! them delays fame elections for everyone). Details are in [`Ingress`].
+
+use std::collections::{HashMap, VecDeque};
+
+use super::Jobs;
+use crate::algorithm::metrics;
+use crate::Timestamp;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+/// Token bucket parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate {
+    /// Tokens added per second
+    pub per_second: u64,
+    /// Bucket capacity, i.e. the largest burst allowed
+    pub burst: u64,
+}
+
+impl Rate {
+    pub const UNLIMITED: Rate = Rate {
+        per_second: u64::MAX,
+        burst: u64::MAX,
+    };
+}
+
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    rate: Rate,
+    /// In billionths of a token, so that refills are exact
+    nano_tokens: u128,
+    last_refill: Timestamp,
+}
+
+impl TokenBucket {
+    fn new(rate: Rate, now: Timestamp) -> Self {
+        Self {
+            rate,
+            nano_tokens: Self::capacity(rate),
+            last_refill: now,
+        }
+    }
+
+    fn capacity(rate: Rate) -> u128 {
+        rate.burst as u128 * NANOS_PER_SECOND
+    }
+
+    fn refill(&mut self, now: Timestamp) {
+        let elapsed = now.saturating_sub(self.last_refill);
+        self.last_refill = self.last_refill.max(now);
+        self.nano_tokens = self
+            .nano_tokens
+            .saturating_add(elapsed.saturating_mul(self.rate.per_second as u128))
+            .min(Self::capacity(self.rate));
+    }
+
+    /// Costs above the burst are capped, otherwise such messages would never
+    /// pass.
+    fn has(&mut self, cost: u64, now: Timestamp) -> bool {
+        self.refill(now);
+        self.nano_tokens >= cost.min(self.rate.burst) as u128 * NANOS_PER_SECOND
+    }
+
+    fn take(&mut self, cost: u64) {
+        let cost = cost.min(self.rate.burst) as u128 * NANOS_PER_SECOND;
+        self.nano_tokens = self.nano_tokens.saturating_sub(cost);
+    }
+}
+
+/// What to do with a message that doesn't fit into a full queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Drop the incoming message
+    DropNewest,
+    /// Drop the oldest queued message of the same peer or, if the global
+    /// limit is hit, of the peer with the longest queue. Keeps the freshest
+    /// data, which is usually what's needed to catch up.
+    DropOldest,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IngressLimits {
+    /// Sync messages from a single peer
+    pub per_peer_messages: Rate,
+    /// Events in the messages from a single peer
+    pub per_peer_events: Rate,
+    pub global_messages: Rate,
+    pub global_events: Rate,
+    /// Messages queued for a single peer
+    pub per_peer_queue: usize,
+    /// Messages queued in total
+    pub global_queue: usize,
+    pub drop_policy: DropPolicy,
+}
+
+impl Default for IngressLimits {
+    fn default() -> Self {
+        Self {
+            per_peer_messages: Rate {
+                per_second: 20,
+                burst: 40,
+            },
+            per_peer_events: Rate {
+                per_second: 2_000,
+                burst: 4_000,
+            },
+            global_messages: Rate {
+                per_second: 200,
+                burst: 400,
+            },
+            global_events: Rate {
+                per_second: 20_000,
+                burst: 40_000,
+            },
+            per_peer_queue: 16,
+            global_queue: 256,
+            drop_policy: DropPolicy::DropOldest,
+        }
+    }
+}
+
+/// Result of [`Ingress::offer`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Admission<TPeerId> {
+    Queued,
+    /// Queued, a message of `peer` was dropped to make room
+    QueuedEvicting {
+        peer: TPeerId,
+    },
+    /// The queue is full, the message was dropped
+    Dropped,
+}
+
+struct PeerState<TPayload, TGenesisPayload, TPeerId, TTag> {
+    queue: VecDeque<(Jobs<TPayload, TGenesisPayload, TPeerId>, TTag)>,
+    messages: TokenBucket,
+    events: TokenBucket,
+}
+
+/// Bounded queue of received [`Jobs`] released at limited rates.
+///
+/// Received messages are [offered](Self::offer) along with the sender, and
+/// the processing loop takes them with [`next_ready`](Self::next_ready) to
+/// apply to the graph. Each peer has its own queue and token buckets, peers
+/// are served round-robin and global buckets cap the total rate, so a
+/// flooding peer only delays (and eventually loses) its own messages.
+///
+/// Time is passed explicitly in nanoseconds (as given by the `()`
+/// [`Clock`](crate::algorithm::Clock)), which keeps the behaviour
+/// deterministic in tests and simulations.
+///
+/// Senders are `TSource`, graph peer ids by default. Transports that know
+/// peers by other ids (e.g. libp2p ones) use those, as the ids in the
+/// messages are claimed by the senders themselves. Messages can carry a
+/// `TTag` with what the caller needs once they are released (e.g. a channel
+/// to the waiting connection), see [`offer_tagged`](Self::offer_tagged).
+pub struct Ingress<TPayload, TGenesisPayload, TPeerId, TSource = TPeerId, TTag = ()> {
+    limits: IngressLimits,
+    peers: HashMap<TSource, PeerState<TPayload, TGenesisPayload, TPeerId, TTag>>,
+    /// Peers with queued messages, in the order of serving
+    order: VecDeque<TSource>,
+    queued: usize,
+    messages: TokenBucket,
+    events: TokenBucket,
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSource>
+    Ingress<TPayload, TGenesisPayload, TPeerId, TSource>
+where
+    TSource: Eq + std::hash::Hash + Clone,
+{
+    pub fn offer(
+        &mut self,
+        peer: TSource,
+        jobs: Jobs<TPayload, TGenesisPayload, TPeerId>,
+        now: Timestamp,
+    ) -> Admission<TSource> {
+        self.offer_tagged(peer, jobs, (), now)
+    }
+
+    /// Next message allowed by the rate limits, `None` if there is nothing
+    /// to process at the moment.
+    pub fn next_ready(
+        &mut self,
+        now: Timestamp,
+    ) -> Option<(TSource, Jobs<TPayload, TGenesisPayload, TPeerId>)> {
+        self.next_ready_tagged(now)
+            .map(|(peer, jobs, ())| (peer, jobs))
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSource, TTag>
+    Ingress<TPayload, TGenesisPayload, TPeerId, TSource, TTag>
+where
+    TSource: Eq + std::hash::Hash + Clone,
+{
+    pub fn new(limits: IngressLimits, now: Timestamp) -> Self {
+        Self {
+            messages: TokenBucket::new(limits.global_messages, now),
+            events: TokenBucket::new(limits.global_events, now),
+            limits,
+            peers: HashMap::new(),
+            order: VecDeque::new(),
+            queued: 0,
+        }
+    }
+
+    /// Messages waiting in the queues
+    pub fn queued(&self) -> usize {
+        self.queued
+    }
+
+    /// Same as [`offer`](Self::offer), `tag` is released along with the
+    /// message. Tags of dropped messages are dropped.
+    pub fn offer_tagged(
+        &mut self,
+        peer: TSource,
+        jobs: Jobs<TPayload, TGenesisPayload, TPeerId>,
+        tag: TTag,
+        now: Timestamp,
+    ) -> Admission<TSource> {
+        let limits = &self.limits;
+        let state = self.peers.entry(peer.clone()).or_insert_with(|| PeerState {
+            queue: VecDeque::new(),
+            messages: TokenBucket::new(limits.per_peer_messages, now),
+            events: TokenBucket::new(limits.per_peer_events, now),
+        });
+        let mut admission = Admission::Queued;
+        if state.queue.len() >= self.limits.per_peer_queue {
+            match self.limits.drop_policy {
+                DropPolicy::DropNewest => {
+                    metrics::ingress_dropped();
+                    return Admission::Dropped;
+                }
+                DropPolicy::DropOldest => {
+                    state.queue.pop_front();
+                    self.queued -= 1;
+                    metrics::ingress_dropped();
+                    admission = Admission::QueuedEvicting { peer: peer.clone() };
+                }
+            }
+        } else if self.queued >= self.limits.global_queue {
+            match self.limits.drop_policy {
+                DropPolicy::DropNewest => {
+                    metrics::ingress_dropped();
+                    return Admission::Dropped;
+                }
+                DropPolicy::DropOldest => {
+                    let longest = self
+                        .peers
+                        .iter()
+                        .max_by_key(|(_, s)| s.queue.len())
+                        .map(|(p, _)| p.clone())
+                        .expect("queue is full, so someone has messages");
+                    self.evict_oldest(&longest);
+                    metrics::ingress_dropped();
+                    admission = Admission::QueuedEvicting { peer: longest };
+                }
+            }
+        }
+        let state = self.peers.get_mut(&peer).expect("inserted above");
+        if state.queue.is_empty() {
+            self.order.push_back(peer);
+        }
+        state.queue.push_back((jobs, tag));
+        self.queued += 1;
+        admission
+    }
+
+    /// Same as [`next_ready`](Self::next_ready), with the tag given to
+    /// [`offer_tagged`](Self::offer_tagged)
+    pub fn next_ready_tagged(
+        &mut self,
+        now: Timestamp,
+    ) -> Option<(TSource, Jobs<TPayload, TGenesisPayload, TPeerId>, TTag)> {
+        if !self.messages.has(1, now) {
+            return None;
+        }
+        for _ in 0..self.order.len() {
+            let peer = self.order.pop_front().expect("bounded by length");
+            let state = self
+                .peers
+                .get_mut(&peer)
+                .expect("peers in order are tracked");
+            let cost = state
+                .queue
+                .front()
+                .expect("peers in order have messages")
+                .0
+                .as_linear()
+                .len() as u64;
+            if !state.messages.has(1, now) || !state.events.has(cost, now) {
+                self.order.push_back(peer);
+                continue;
+            }
+            if !self.events.has(cost, now) {
+                // Global limit, no point in checking others
+                self.order.push_front(peer);
+                return None;
+            }
+            let (jobs, tag) = state.queue.pop_front().expect("checked above");
+            state.messages.take(1);
+            state.events.take(cost);
+            self.messages.take(1);
+            self.events.take(cost);
+            self.queued -= 1;
+            if !state.queue.is_empty() {
+                self.order.push_back(peer.clone());
+            }
+            return Some((peer, jobs, tag));
+        }
+        None
+    }
+
+    /// Drop the queue of the peer, e.g. on disconnect. Its rate limits are
+    /// kept, so reconnecting doesn't refill the buckets.
+    pub fn clear_peer(&mut self, peer: &TSource) {
+        if let Some(state) = self.peers.get_mut(peer) {
+            self.queued -= state.queue.len();
+            state.queue.clear();
+            self.order.retain(|p| p != peer);
+        }
+    }
+
+    fn evict_oldest(&mut self, peer: &TSource) {
+        let state = self.peers.get_mut(peer).expect("evicted peer is tracked");
+        state.queue.pop_front();
+        self.queued -= 1;
+        if state.queue.is_empty() {
+            self.order.retain(|p| p != peer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::datastructure::Graph;
+    use crate::algorithm::{IncrementalClock, MockSigner};
+
+    type TestJobs = Jobs<u64, (), u64>;
+
+    const SECOND: Timestamp = NANOS_PER_SECOND;
+
+    /// Jobs with `count` events
+    fn jobs(count: u64) -> TestJobs {
+        let mut g: Graph<u64, (), u64, MockSigner<u64, ()>, IncrementalClock> =
+            Graph::new(0, 0, (), 999, MockSigner::new(), IncrementalClock::new());
+        for i in 1..count {
+            let tip = g.self_tip().unwrap().clone();
+            g.create_event(i, tip).unwrap();
+        }
+        g.generate_sync_for(&1).unwrap()
+    }
+
+    fn limits(drop_policy: DropPolicy) -> IngressLimits {
+        IngressLimits {
+            per_peer_messages: Rate {
+                per_second: 1,
+                burst: 2,
+            },
+            per_peer_events: Rate::UNLIMITED,
+            global_messages: Rate::UNLIMITED,
+            global_events: Rate {
+                per_second: 10,
+                burst: 10,
+            },
+            per_peer_queue: 3,
+            global_queue: 5,
+            drop_policy,
+        }
+    }
+
+    #[test]
+    fn flooding_peer_does_not_starve_others() {
+        let mut ingress = Ingress::new(limits(DropPolicy::DropOldest), 0);
+        for _ in 0..3 {
+            assert_eq!(ingress.offer(1, jobs(1), 0), Admission::Queued);
+        }
+        assert_eq!(ingress.offer(2, jobs(1), 0), Admission::Queued);
+
+        let served: Vec<_> = std::iter::from_fn(|| ingress.next_ready(0))
+            .map(|(peer, _)| peer)
+            .collect();
+        // Peer 1 exhausted its burst of 2, peer 2 got its turn in between
+        assert_eq!(served, vec![1, 2, 1]);
+        assert_eq!(ingress.queued(), 1);
+        // One token per second
+        assert!(ingress.next_ready(SECOND / 2).is_none());
+        assert_eq!(ingress.next_ready(SECOND).map(|(p, _)| p), Some(1));
+    }
+
+    #[test]
+    fn global_event_rate_limited() {
+        let mut ingress = Ingress::new(limits(DropPolicy::DropOldest), 0);
+        ingress.offer(1, jobs(6), 0);
+        ingress.offer(2, jobs(6), 0);
+        assert!(ingress.next_ready(0).is_some());
+        assert!(ingress.next_ready(0).is_none());
+        // 0.5s gives 5 more tokens (4 left)
+        assert!(ingress.next_ready(SECOND / 2).is_some());
+        // Oversized message passes once the bucket is full
+        ingress.offer(3, jobs(20), SECOND / 2);
+        assert!(ingress.next_ready(SECOND).is_none());
+        assert!(ingress.next_ready(2 * SECOND).is_some());
+    }
+
+    #[test]
+    fn full_queues_drop_by_policy() {
+        let mut ingress = Ingress::new(limits(DropPolicy::DropNewest), 0);
+        for _ in 0..3 {
+            ingress.offer(1, jobs(1), 0);
+        }
+        assert_eq!(ingress.offer(1, jobs(2), 0), Admission::Dropped);
+        ingress.offer(2, jobs(1), 0);
+        ingress.offer(2, jobs(1), 0);
+        assert_eq!(ingress.offer(3, jobs(1), 0), Admission::Dropped);
+        assert_eq!(ingress.queued(), 5);
+
+        let mut ingress = Ingress::new(limits(DropPolicy::DropOldest), 0);
+        ingress.offer(1, jobs(1), 0);
+        for _ in 0..3 {
+            ingress.offer(2, jobs(1), 0);
+        }
+        // Newest message of the peer replaces its oldest one
+        assert_eq!(
+            ingress.offer(2, jobs(2), 0),
+            Admission::QueuedEvicting { peer: 2 }
+        );
+        ingress.offer(1, jobs(1), 0);
+        // Global limit hits the longest queue
+        assert_eq!(
+            ingress.offer(3, jobs(1), 0),
+            Admission::QueuedEvicting { peer: 2 }
+        );
+        assert_eq!(ingress.queued(), 5);
+
+        ingress.clear_peer(&2);
+        assert_eq!(ingress.queued(), 3);
+        let served: Vec<_> = std::iter::from_fn(|| ingress.next_ready(0))
+            .map(|(peer, _)| peer)
+            .collect();
+        assert_eq!(served, vec![1, 3, 1]);
+    }
+}
@@ -0,0 +1,156 @@
+//! Events known to a peer, as per-author watermarks.
+//!
+//! Events of an author are numbered by their position in the author's
+//! chain: the genesis is 0, each event is one more than its self parent.
+//! Whoever knows an event knows all its ancestors, so without forks
+//! "everything of `X` up to number `N`" describes the peer's knowledge of
+//! `X` completely. Branches of forks share numbers, they are listed by hash.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+use crate::algorithm::event;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(bound(
+    serialize = "TPeerId: Serialize + Eq + Hash",
+    deserialize = "TPeerId: Deserialize<'de> + Eq + Hash"
+))]
+pub struct Knowledge<TPeerId> {
+    /// Every event of the author up to this sequence number is known
+    watermarks: HashMap<TPeerId, u64>,
+    /// Known events above the watermarks, with their authors and sequence
+    /// numbers
+    extra: HashMap<event::Hash, (TPeerId, u64)>,
+}
+
+impl<TPeerId> Default for Knowledge<TPeerId> {
+    fn default() -> Self {
+        Self {
+            watermarks: HashMap::new(),
+            extra: HashMap::new(),
+        }
+    }
+}
+
+impl<TPeerId: Eq + Hash> PartialEq for Knowledge<TPeerId> {
+    fn eq(&self, other: &Self) -> bool {
+        self.watermarks == other.watermarks && self.extra == other.extra
+    }
+}
+
+impl<TPeerId: Eq + Hash> Eq for Knowledge<TPeerId> {}
+
+impl<TPeerId: Eq + Hash + Clone> Knowledge<TPeerId> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn watermark(&self, author: &TPeerId) -> Option<u64> {
+        self.watermarks.get(author).copied()
+    }
+
+    pub fn extra(&self) -> impl Iterator<Item = &event::Hash> {
+        self.extra.keys()
+    }
+
+    pub fn knows(&self, event: &event::Hash, author: &TPeerId, sequence: u64) -> bool {
+        self.watermark(author).is_some_and(|mark| sequence <= mark)
+            || self.extra.contains_key(event)
+    }
+
+    /// Mark all events of `author` up to `sequence` known
+    pub fn raise(&mut self, author: &TPeerId, sequence: u64) {
+        match self.watermarks.get_mut(author) {
+            Some(mark) => *mark = (*mark).max(sequence),
+            None => {
+                self.watermarks.insert(author.clone(), sequence);
+            }
+        }
+    }
+
+    /// Mark a single event known
+    pub fn insert(&mut self, event: event::Hash, author: TPeerId, sequence: u64) {
+        if !self.knows(&event, &author, sequence) {
+            self.extra.insert(event, (author, sequence));
+        }
+    }
+
+    /// Whether everything known here is known in `other`. Extra events of
+    /// `other` don't cover watermarks, so the answer may be `false` when
+    /// they happen to list everything.
+    pub fn is_subset(&self, other: &Self) -> bool {
+        self.watermarks
+            .iter()
+            .all(|(author, mark)| other.watermark(author).is_some_and(|m| *mark <= m))
+            && self
+                .extra
+                .iter()
+                .all(|(event, (author, sequence))| other.knows(event, author, *sequence))
+    }
+
+    /// Add everything known in `other`
+    pub fn merge(&mut self, other: &Self) {
+        for (author, mark) in &other.watermarks {
+            self.raise(author, *mark);
+        }
+        let watermarks = &self.watermarks;
+        self.extra.retain(|_, (author, sequence)| {
+            watermarks.get(author).is_none_or(|mark| *sequence > *mark)
+        });
+        for (event, (author, sequence)) in &other.extra {
+            self.insert(event.clone(), author.clone(), *sequence);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(n: u8) -> event::Hash {
+        event::Hash::from_array([n; 64])
+    }
+
+    #[test]
+    fn watermarks_cover_events_below() {
+        let mut knowledge = Knowledge::new();
+        knowledge.raise(&0, 5);
+        knowledge.raise(&0, 3);
+        assert_eq!(knowledge.watermark(&0), Some(5));
+        assert!(knowledge.knows(&hash(1), &0, 5));
+        assert!(!knowledge.knows(&hash(1), &0, 6));
+        assert!(!knowledge.knows(&hash(1), &1, 0));
+
+        knowledge.insert(hash(1), 0, 4);
+        assert_eq!(knowledge.extra().count(), 0);
+        knowledge.insert(hash(2), 0, 6);
+        assert!(knowledge.knows(&hash(2), &0, 6));
+        assert!(!knowledge.knows(&hash(3), &0, 6));
+    }
+
+    #[test]
+    fn merge_and_subset() {
+        let mut a = Knowledge::new();
+        a.raise(&0, 2);
+        a.insert(hash(1), 0, 4);
+        a.insert(hash(2), 1, 0);
+        let mut b = Knowledge::new();
+        b.raise(&0, 4);
+        b.raise(&2, 1);
+        assert!(!a.is_subset(&b));
+        assert!(!b.is_subset(&a));
+
+        let mut merged = a.clone();
+        merged.merge(&b);
+        assert!(a.is_subset(&merged));
+        assert!(b.is_subset(&merged));
+        // Covered by the watermark of `b` now
+        assert_eq!(merged.extra().collect::<Vec<_>>(), vec![&hash(2)]);
+        assert!(merged.is_subset(&merged));
+        assert!(!merged.is_subset(&Knowledge::new()));
+        assert!(Knowledge::<u64>::new().is_subset(&a));
+    }
+}
@@ -1,19 +1,56 @@
-use std::collections::{HashSet, VecDeque};
+use std::collections::{hash_map::Entry, HashMap, VecDeque};
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tracing::trace;
 
-use crate::{
-    algorithm::event,
-    common::{Directed, Reversable},
-};
+use crate::algorithm::{codec::PayloadCodec, event};
+
+pub mod ingress;
+mod knowledge;
+pub mod responder;
+pub mod wire;
+
+pub use knowledge::Knowledge;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(bound(
+    serialize = "TPayload: PayloadCodec, TGenesisPayload: Serialize, TPeerId: Serialize",
+    deserialize = "TPayload: PayloadCodec, TGenesisPayload: Deserialize<'de>, TPeerId: Deserialize<'de>"
+))]
 pub struct Jobs<TPayload, TGenesisPayload, TPeerId> {
     inner: Vec<event::SignedEvent<TPayload, TGenesisPayload, TPeerId>>,
 }
 
+/// Latest events of each author known to a node. Everything they are
+/// built upon is known as well, so it's enough to describe the node's state.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Summary<TPeerId> {
+    /// Author and its latest events (several if it forked)
+    pub tips: Vec<(TPeerId, Vec<event::Hash>)>,
+}
+
+impl<TPeerId> Summary<TPeerId> {
+    pub fn tips(&self) -> impl Iterator<Item = &event::Hash> {
+        self.tips.iter().flat_map(|(_, tips)| tips.iter())
+    }
+}
+
+/// Asks the receiver for events missing in `summary`, answered with [`Jobs`].
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(bound(
+    serialize = "TPayload: PayloadCodec, TGenesisPayload: Serialize, TPeerId: Serialize",
+    deserialize = "TPayload: PayloadCodec, TGenesisPayload: Deserialize<'de>, TPeerId: Deserialize<'de>"
+))]
+pub struct SyncRequest<TPayload, TGenesisPayload, TPeerId> {
+    pub from: TPeerId,
+    pub summary: Summary<TPeerId>,
+    /// Events pushed along with the request, so that nodes that can't accept
+    /// connections still spread their events. The receiver applies them
+    /// before answering.
+    pub events: Option<Jobs<TPayload, TGenesisPayload, TPeerId>>,
+}
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("The provided tip is unknown in this state. Hash: {:?}.", 0)]
@@ -27,122 +64,141 @@ impl<TPayload, TGenesisPayload, TPeerId> Jobs<TPayload, TGenesisPayload, TPeerId
         &self.inner
     }
 
+    pub(crate) fn from_linear(
+        events: Vec<event::SignedEvent<TPayload, TGenesisPayload, TPeerId>>,
+    ) -> Self {
+        Jobs { inner: events }
+    }
+
     pub fn into_linear(self) -> Vec<event::SignedEvent<TPayload, TGenesisPayload, TPeerId>> {
         self.inner
     }
 
-    /// Generate jobs for the peer to perform in order to achieve at least the same
-    /// state as ours.
-    pub(crate) fn generate<G, FKnows, FEvent>(
-        known_state: G,
-        peer_knows_event: FKnows,
+    /// Split into parts of at most `max_events` events (at least one). Order
+    /// is preserved, so each part can be applied as soon as it arrives.
+    pub fn into_chunks(self, max_events: usize) -> Vec<Self> {
+        let max_events = max_events.max(1);
+        let mut chunks = vec![];
+        let mut events = self.inner.into_iter().peekable();
+        while events.peek().is_some() {
+            chunks.push(Jobs {
+                inner: events.by_ref().take(max_events).collect(),
+            });
+        }
+        chunks
+    }
+
+    /// Generate jobs for each of the peers to perform in order to achieve at
+    /// least the same state as ours, in the order of `peer_knowledge`.
+    ///
+    /// The graph is traversed once for all peers, every event unknown to
+    /// some of them is visited and added to the jobs of those that don't
+    /// know it. The traversal follows the links stored in the events
+    /// (`get_event`, along with their sequence numbers) directly, without
+    /// collecting neighbors of each visited event.
+    pub(crate) fn generate_many<'a, FEvent>(
+        peer_knowledge: &[&Knowledge<TPeerId>],
         known_state_tips: impl Iterator<Item = event::Hash>,
         get_event: FEvent,
-    ) -> Result<Self, Error>
+    ) -> Result<Vec<Self>, Error>
     where
-        G: Directed<NodeIdentifier = event::Hash, NodeIdentifiers = Vec<event::Hash>>,
-        FKnows: Fn(&event::Hash) -> bool,
-        FEvent: Fn(&event::Hash) -> Option<event::SignedEvent<TPayload, TGenesisPayload, TPeerId>>,
+        TPayload: Clone + 'a,
+        TGenesisPayload: Clone + 'a,
+        TPeerId: Eq + std::hash::Hash + Clone + 'a,
+        FEvent: Fn(
+            &event::Hash,
+        ) -> Option<(
+            &'a event::EventWrapper<TPayload, TGenesisPayload, TPeerId>,
+            u64,
+        )>,
     {
+        type Visited<'a, TPayload, TGenesisPayload, TPeerId> = (
+            &'a event::EventWrapper<TPayload, TGenesisPayload, TPeerId>,
+            u64,
+        );
+        let peer_knows =
+            |knowledge: &Knowledge<TPeerId>,
+             (event, sequence): Visited<TPayload, TGenesisPayload, TPeerId>| {
+                knowledge.knows(event.hash(), event.author(), sequence)
+            };
+        let all_know = |event: Visited<TPayload, TGenesisPayload, TPeerId>| {
+            peer_knowledge
+                .iter()
+                .all(|knowledge| peer_knows(knowledge, event))
+        };
         // We need topologically sorted subgraph of known state, that is unknown
         // to the peer. The sorting must be from the oldest to the newest events.
         //
-        // To find it, we do a trick: we find the reverse topsort.
-        // 1. By definition of topological sorting,
-        //      "for every directed edge u -> v from vertex u to vertex v, u comes before v in the ordering"
-        //      We find topsort for such graph.
-        // 2. Then we reverse each edge in the graph, so for each edge
-        //      u <- v we will have u before v in the same ordering.
-        // 3. Then we reverse/flip the ordering itself, we will have that for each
-        //      u <- v, v is before u in the ordering.
-        // Thus, it is a topological sort by defenition. Let's find it!
-
-        // If we treat each parent -> child relationship as reverse (p <- c),
-        // we need to start from the nodes without any children (thus without any
-        // incoming edge p <- c). The nodes are tips.
+        // We sort from the newest events instead (Kahn's algorithm with
+        // child -> parent edges) and reverse the result: an event is visited
+        // once all of its children are, so after reversing each parent comes
+        // before its children. The same holds for the events unknown to a
+        // single peer, since all their descendants are unknown to it too.
         //
-        // We can already filter out tips known to the peer, since all of its ancestors
-        // are known to the peer.
-
-        // work with reversed events
-        let reversed_state = known_state.reversed();
-
-        // nodes without incoming edges
-        let sources: Vec<event::Hash> = known_state_tips
-            .filter_map(|h| match reversed_state.in_neighbors(&h) {
-                Some(in_neighbors) => {
-                    if in_neighbors.is_empty() {
-                        Some(Ok(h))
-                    } else {
-                        None
-                    }
-                }
-                None => Some(Err(Error::IncorrectTip(h))),
-            })
-            .collect::<Result<_, _>>()?;
-        trace!("Have {} sources", sources.len());
-        let unknown_sources = sources.into_iter().filter(|h| !peer_knows_event(h));
-
-        // Now do topsort with stop at known events
-
-        let mut to_visit = VecDeque::from_iter(unknown_sources);
-        let mut to_visit_set = HashSet::new();
+        // The traversal starts from the tips without children. Tips known to
+        // the peers are filtered out right away, since all of their ancestors
+        // are known to the peers as well.
+        let mut sources = vec![];
+        for tip in known_state_tips {
+            let event = get_event(&tip).ok_or_else(|| Error::IncorrectTip(tip.clone()))?;
+            if event.0.children.is_empty() && !all_know(event) {
+                sources.push(event);
+            }
+        }
         trace!(
-            "Starting to traverse from {} sources (filtered known sources)",
-            to_visit.len()
+            "Starting to traverse from {} unknown sources",
+            sources.len()
         );
-        // to check removed edges
-        let mut visited = HashSet::with_capacity(to_visit.len());
-        let mut sorted = Vec::with_capacity(to_visit.len());
+
+        let mut to_visit = VecDeque::from(sources);
+        // Children not visited yet, for events reached from some of them.
+        // Children of events unknown to a peer are unknown as well, so all
+        // of them are visited eventually.
+        let mut remaining_children = HashMap::new();
+        let mut sorted = vec![Vec::with_capacity(to_visit.len()); peer_knowledge.len()];
         while let Some(next) = to_visit.pop_front() {
-            if visited.contains(&next) {
-                continue;
-            }
+            let (wrapper, _) = next;
             trace!(
-                "Visiting {:?}; checking its out neighbors",
-                &next.as_compact()
+                "Visiting {:?}; checking its parents",
+                &wrapper.hash().as_compact()
             );
-            visited.insert(next.clone());
-            for affected_neighbor in reversed_state
-                .out_neighbors(&next)
-                .ok_or_else(|| Error::UnknownEvent(next.clone()))?
-            {
-                if to_visit_set.contains(&affected_neighbor) {
-                    trace!(
-                        "Neighbor {:?} is already scheduled, skipping it",
-                        &affected_neighbor.as_compact()
-                    );
-                    continue;
-                }
-                trace!("Checking neighbor {:?}", &affected_neighbor.as_compact());
-                if peer_knows_event(&affected_neighbor) {
-                    trace!("Neighbor is known to the peer, skipping");
-                    continue;
-                }
-                if reversed_state
-                    .in_neighbors(&affected_neighbor)
-                    .ok_or_else(|| Error::UnknownEvent(next.clone()))?
-                    .into_iter()
-                    .all(|in_neighbor| visited.contains(&in_neighbor))
-                {
-                    trace!("All in neighbors were visited before");
-                    if !visited.contains(&affected_neighbor) {
-                        to_visit_set.insert(affected_neighbor.clone());
-                        to_visit.push_back(affected_neighbor)
+            for parent in wrapper.kind().parents() {
+                let (remaining, parent_event) = match remaining_children.entry(parent) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        let parent_event =
+                            get_event(parent).ok_or_else(|| Error::UnknownEvent(parent.clone()))?;
+                        if all_know(parent_event) {
+                            trace!("Parent is known to the peers, skipping");
+                            continue;
+                        }
+                        entry.insert((parent_event.0.children.len(), parent_event))
                     }
+                };
+                *remaining -= 1;
+                if *remaining == 0 {
+                    trace!("All children of {:?} were visited", &parent.as_compact());
+                    to_visit.push_back(*parent_event);
+                }
+            }
+            for (knowledge, sorted) in peer_knowledge.iter().zip(&mut sorted) {
+                if !peer_knows(knowledge, next) {
+                    sorted.push(wrapper);
                 }
             }
-            sorted.push(next);
         }
         // note: no loop detection; we assume the graph already has no loops
 
-        // Prepare the jobs
-        trace!("Reversing the ordering to get the result");
-        sorted.reverse();
-        let jobs: Vec<event::SignedEvent<TPayload, TGenesisPayload, TPeerId>> = sorted
+        trace!("Reversing the orderings to get the result");
+        Ok(sorted
             .into_iter()
-            .map(|hash| get_event(&hash).ok_or_else(|| Error::UnknownEvent(hash)))
-            .collect::<Result<_, _>>()?;
-        Ok(Jobs { inner: jobs })
+            .map(|sorted| Jobs {
+                inner: sorted
+                    .into_iter()
+                    .rev()
+                    .map(|wrapper| wrapper.inner().clone())
+                    .collect(),
+            })
+            .collect())
     }
 }
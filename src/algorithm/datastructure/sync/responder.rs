@@ -0,0 +1,388 @@
+//! Answering sync requests of many peers at once without buffering
+//! unbounded amounts of data. Details are in [`SyncResponder`].
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+
+use serde::Serialize;
+use thiserror::Error;
+use tracing::{debug, trace};
+
+use super::{Jobs, SyncRequest};
+use crate::algorithm::codec::PayloadCodec;
+use crate::algorithm::datastructure::Graph;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponderLimits {
+    /// Total size of chunks sent to all peers and not acknowledged yet.
+    /// A single chunk larger than that is still sent when nothing else is
+    /// outstanding.
+    pub max_outstanding_bytes: usize,
+    /// Number of unacknowledged chunks per peer
+    pub max_chunks_per_peer: usize,
+    /// Number of events in a chunk
+    pub chunk_size: usize,
+}
+
+impl Default for ResponderLimits {
+    fn default() -> Self {
+        Self {
+            max_outstanding_bytes: 16 * 1024 * 1024,
+            max_chunks_per_peer: 2,
+            chunk_size: 256,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ResponderError<TPeerId> {
+    #[error("Could not generate jobs for {peer:?}: {source}")]
+    Generate { peer: TPeerId, source: super::Error },
+    #[error("Could not encode a chunk for {peer:?}: {source}")]
+    Encode {
+        peer: TPeerId,
+        source: bincode::Error,
+    },
+}
+
+/// Chunk to be sent to `peer`
+#[derive(Debug, PartialEq, Eq)]
+pub struct OutgoingChunk<TPayload, TGenesisPayload, TPeerId> {
+    pub peer: TPeerId,
+    pub chunk: Jobs<TPayload, TGenesisPayload, TPeerId>,
+    /// Encoded size, counted towards the outstanding bytes until acknowledged
+    pub bytes: usize,
+    /// The last chunk of the response to the request
+    pub last: bool,
+}
+
+struct PeerQueue<TPayload, TGenesisPayload, TPeerId> {
+    /// Not started yet, jobs are generated once the peer can take them
+    requests: VecDeque<SyncRequest<TPayload, TGenesisPayload, TPeerId>>,
+    /// Generated for the current request, not sent yet
+    chunks: VecDeque<Jobs<TPayload, TGenesisPayload, TPeerId>>,
+    /// Whether `chunks` belong to a request in progress
+    responding: bool,
+    /// Sizes of sent chunks, oldest first
+    in_flight: VecDeque<usize>,
+}
+
+impl<TPayload, TGenesisPayload, TPeerId> PeerQueue<TPayload, TGenesisPayload, TPeerId> {
+    fn new() -> Self {
+        Self {
+            requests: VecDeque::new(),
+            chunks: VecDeque::new(),
+            responding: false,
+            in_flight: VecDeque::new(),
+        }
+    }
+
+    fn has_work(&self) -> bool {
+        self.responding || !self.requests.is_empty()
+    }
+}
+
+/// Queue of sync requests from several peers answered in chunks.
+///
+/// Peers are served round-robin, one chunk at a time. A peer that has
+/// [`max_chunks_per_peer`](ResponderLimits::max_chunks_per_peer) chunks
+/// unacknowledged is skipped, and jobs for its next request are not even
+/// generated, so slow consumers cost nothing but their queued requests.
+/// Nothing is sent while the outstanding bytes are at the limit.
+///
+/// The application calls [`next_chunk`](Self::next_chunk) until it returns
+/// `None`, sends the chunks, and reports each received acknowledgement with
+/// [`acknowledge`](Self::acknowledge), after which it may be worth calling
+/// `next_chunk` again.
+pub struct SyncResponder<TPayload, TGenesisPayload, TPeerId> {
+    limits: ResponderLimits,
+    peers: HashMap<TPeerId, PeerQueue<TPayload, TGenesisPayload, TPeerId>>,
+    /// Peers with requests, in the order of serving
+    order: VecDeque<TPeerId>,
+    outstanding_bytes: usize,
+}
+
+impl<TPayload, TGenesisPayload, TPeerId> SyncResponder<TPayload, TGenesisPayload, TPeerId>
+where
+    TPayload: PayloadCodec + Clone,
+    TGenesisPayload: Serialize + Clone,
+    TPeerId: Serialize + Eq + std::hash::Hash + Debug + Clone,
+{
+    pub fn new(limits: ResponderLimits) -> Self {
+        Self {
+            limits,
+            peers: HashMap::new(),
+            order: VecDeque::new(),
+            outstanding_bytes: 0,
+        }
+    }
+
+    pub fn outstanding_bytes(&self) -> usize {
+        self.outstanding_bytes
+    }
+
+    /// Requests that are not fully sent
+    pub fn pending_requests(&self) -> usize {
+        self.peers
+            .values()
+            .map(|q| q.requests.len() + usize::from(q.responding))
+            .sum()
+    }
+
+    /// Queue the request. Requests of the same peer are answered in order.
+    ///
+    /// Events pushed with the request should be applied beforehand with
+    /// [`Graph::apply_sync_request`], they are not looked at here.
+    pub fn push_request(
+        &mut self,
+        peer: TPeerId,
+        request: SyncRequest<TPayload, TGenesisPayload, TPeerId>,
+    ) {
+        let queue = self
+            .peers
+            .entry(peer.clone())
+            .or_insert_with(PeerQueue::new);
+        if !queue.has_work() {
+            self.order.push_back(peer);
+        }
+        queue.requests.push_back(request);
+    }
+
+    /// The oldest unacknowledged chunk sent to `peer` was received. Returns
+    /// `false` if there was none.
+    pub fn acknowledge(&mut self, peer: &TPeerId) -> bool {
+        let Some(bytes) = self
+            .peers
+            .get_mut(peer)
+            .and_then(|queue| queue.in_flight.pop_front())
+        else {
+            return false;
+        };
+        self.outstanding_bytes -= bytes;
+        self.forget_if_done(peer);
+        true
+    }
+
+    /// Drop everything related to the peer, e.g. on disconnect
+    pub fn remove_peer(&mut self, peer: &TPeerId) {
+        if let Some(queue) = self.peers.remove(peer) {
+            self.outstanding_bytes -= queue.in_flight.iter().sum::<usize>();
+            self.order.retain(|p| p != peer);
+        }
+    }
+
+    /// Next chunk allowed by the limits, `None` if there is nothing to send
+    /// at the moment. On error the failed request is dropped, the rest are
+    /// kept.
+    pub fn next_chunk<TSigner, TClock>(
+        &mut self,
+        graph: &Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+    ) -> Result<Option<OutgoingChunk<TPayload, TGenesisPayload, TPeerId>>, ResponderError<TPeerId>>
+    {
+        if self.outstanding_bytes > 0 && self.outstanding_bytes >= self.limits.max_outstanding_bytes
+        {
+            return Ok(None);
+        }
+        for _ in 0..self.order.len() {
+            let peer = self.order.pop_front().expect("bounded by length");
+            let queue = self
+                .peers
+                .get_mut(&peer)
+                .expect("peers in order are tracked");
+            if queue.in_flight.len() >= self.limits.max_chunks_per_peer {
+                trace!(?peer, "Peer has too many chunks in flight, skipping");
+                self.order.push_back(peer);
+                continue;
+            }
+            if !queue.responding {
+                let request = queue
+                    .requests
+                    .pop_front()
+                    .expect("peers in order have requests");
+                match graph.generate_sync_for_request(&request) {
+                    Ok(jobs) => {
+                        debug!(?peer, jobs = jobs.as_linear().len(), "Generated response");
+                        queue.chunks = jobs.into_chunks(self.limits.chunk_size).into();
+                        queue.responding = true;
+                    }
+                    Err(source) => {
+                        if queue.has_work() {
+                            self.order.push_back(peer.clone());
+                        }
+                        self.forget_if_done(&peer);
+                        return Err(ResponderError::Generate { peer, source });
+                    }
+                }
+            }
+            // Empty response still gets a chunk, so the requester isn't left waiting
+            let chunk = queue
+                .chunks
+                .front()
+                .cloned()
+                .unwrap_or_else(|| Jobs::from_linear(vec![]));
+            let bytes = match bincode::serialized_size(&chunk) {
+                Ok(bytes) => bytes as usize,
+                Err(source) => {
+                    queue.chunks.clear();
+                    queue.responding = false;
+                    if queue.has_work() {
+                        self.order.push_back(peer.clone());
+                    }
+                    self.forget_if_done(&peer);
+                    return Err(ResponderError::Encode { peer, source });
+                }
+            };
+            if self.outstanding_bytes > 0
+                && self.outstanding_bytes + bytes > self.limits.max_outstanding_bytes
+            {
+                trace!(
+                    outstanding = self.outstanding_bytes,
+                    bytes,
+                    "Outstanding bytes limit reached"
+                );
+                self.order.push_front(peer);
+                return Ok(None);
+            }
+            queue.chunks.pop_front();
+            let last = queue.chunks.is_empty();
+            if last {
+                queue.responding = false;
+            }
+            queue.in_flight.push_back(bytes);
+            self.outstanding_bytes += bytes;
+            if queue.has_work() {
+                self.order.push_back(peer.clone());
+            }
+            return Ok(Some(OutgoingChunk {
+                peer,
+                chunk,
+                bytes,
+                last,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// Stop tracking the peer if nothing is queued or in flight
+    fn forget_if_done(&mut self, peer: &TPeerId) {
+        if self
+            .peers
+            .get(peer)
+            .is_some_and(|q| !q.has_work() && q.in_flight.is_empty())
+        {
+            self.peers.remove(peer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{GraphBuilder, TestGraph};
+
+    type Request = SyncRequest<u64, (), u64>;
+
+    fn setup() -> (TestGraph<u64, u64>, Request, Request) {
+        let mut g = GraphBuilder::new("a", 0u64, 0u64, 999)
+            .build()
+            .unwrap()
+            .graph;
+        for i in 0..9 {
+            let tip = g.self_tip().unwrap().clone();
+            g.create_event(i, tip).unwrap();
+        }
+        let request = |name, id| {
+            let built = GraphBuilder::new(name, id, 0u64, 999).build().unwrap();
+            built.graph.sync_request()
+        };
+        (g, request("b", 1), request("c", 2))
+    }
+
+    fn drain(
+        responder: &mut SyncResponder<u64, (), u64>,
+        g: &TestGraph<u64, u64>,
+    ) -> Vec<OutgoingChunk<u64, (), u64>> {
+        std::iter::from_fn(|| responder.next_chunk(g).unwrap()).collect()
+    }
+
+    #[test]
+    fn peers_served_round_robin_within_limits() {
+        let (g, request_1, request_2) = setup();
+        let mut responder = SyncResponder::new(ResponderLimits {
+            max_outstanding_bytes: usize::MAX,
+            max_chunks_per_peer: 2,
+            chunk_size: 4,
+        });
+        responder.push_request(1, request_1);
+        responder.push_request(2, request_2);
+
+        let sent = drain(&mut responder, &g);
+        let peers: Vec<_> = sent.iter().map(|c| c.peer).collect();
+        assert_eq!(peers, vec![1, 2, 1, 2]);
+        assert_eq!(
+            responder.outstanding_bytes(),
+            sent.iter().map(|c| c.bytes).sum::<usize>()
+        );
+
+        // Slow peer 2 doesn't hold back peer 1
+        assert!(responder.acknowledge(&1));
+        let sent = drain(&mut responder, &g);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].peer, 1);
+        assert!(sent[0].last);
+        assert_eq!(sent[0].chunk.as_linear().len(), 2);
+        assert_eq!(responder.pending_requests(), 1);
+
+        // Everything is acknowledged eventually
+        let mut acknowledged = 0;
+        loop {
+            let mut progress = false;
+            for peer in [1, 2] {
+                if responder.acknowledge(&peer) {
+                    progress = true;
+                    acknowledged += 1;
+                }
+            }
+            drain(&mut responder, &g);
+            if !progress {
+                break;
+            }
+        }
+        assert_eq!(acknowledged, 5);
+        assert_eq!(responder.outstanding_bytes(), 0);
+        assert_eq!(responder.pending_requests(), 0);
+        assert!(!responder.acknowledge(&1));
+    }
+
+    #[test]
+    fn outstanding_bytes_bounded() {
+        let (g, request_1, request_2) = setup();
+        let mut responder = SyncResponder::new(ResponderLimits {
+            max_outstanding_bytes: 1,
+            max_chunks_per_peer: 10,
+            chunk_size: 4,
+        });
+        responder.push_request(1, request_1);
+        responder.push_request(2, request_2.clone());
+        // Oversized chunk is sent alone
+        let sent = drain(&mut responder, &g);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(responder.outstanding_bytes(), sent[0].bytes);
+        // Peer 2 goes next
+        responder.acknowledge(&1);
+        assert_eq!(drain(&mut responder, &g)[0].peer, 2);
+
+        // Disconnect frees the budget
+        responder.remove_peer(&2);
+        assert_eq!(responder.outstanding_bytes(), 0);
+        assert_eq!(drain(&mut responder, &g)[0].peer, 1);
+
+        // Request of a peer that is up to date is answered anyway
+        responder.remove_peer(&1);
+        responder.push_request(0, g.sync_request());
+        let sent = drain(&mut responder, &g);
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].last);
+        assert!(sent[0].chunk.as_linear().is_empty());
+    }
+}
@@ -0,0 +1,337 @@
+//! Versioned binary encoding of sync messages.
+//!
+//! Every message starts with a 3 byte header: wire format version (`u16`,
+//! little endian) and [`MessageKind`]. The rest is bincode of the message in
+//! the format of that version.
+//!
+//! Nodes read and write any version from [`MIN_WIRE_VERSION`] to
+//! [`WIRE_VERSION`], so peers running adjacent crate versions can still
+//! sync (using the version negotiated in the
+//! [handshake](crate::net::handshake)). Changing the serialized form of any
+//! message requires bumping [`WIRE_VERSION`] and converting bodies of older
+//! versions in [`WireMessage::encode_body`] and
+//! [`WireMessage::decode_body`]. The golden bytes in the tests catch
+//! accidental changes.
+//!
+//! History:
+//! - 1: initial;
+//! - 2: [`SyncRequest`] can carry pushed events.
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{Jobs, Summary, SyncRequest};
+use crate::algorithm::codec::PayloadCodec;
+use crate::algorithm::compression::{self, MAX_DECOMPRESSED_PER_MESSAGE};
+
+/// Version written by this crate
+pub const WIRE_VERSION: u16 = 2;
+/// Oldest version this crate can read
+pub const MIN_WIRE_VERSION: u16 = 1;
+
+const HEADER_LEN: usize = 3;
+
+/// Messages in the formats of older versions, converted to the current ones
+/// after decoding
+pub(crate) mod v1 {
+    use super::*;
+
+    #[derive(Deserialize)]
+    pub(crate) struct SyncRequest<TPeerId> {
+        from: TPeerId,
+        summary: Summary<TPeerId>,
+    }
+
+    /// Encoding of [`SyncRequest`], pushed events can't be sent
+    #[derive(Serialize)]
+    pub(crate) struct SyncRequestRef<'a, TPeerId> {
+        from: &'a TPeerId,
+        summary: &'a Summary<TPeerId>,
+    }
+
+    impl<'a, TPayload, TGenesisPayload, TPeerId>
+        From<&'a super::SyncRequest<TPayload, TGenesisPayload, TPeerId>>
+        for SyncRequestRef<'a, TPeerId>
+    {
+        fn from(value: &'a super::SyncRequest<TPayload, TGenesisPayload, TPeerId>) -> Self {
+            Self {
+                from: &value.from,
+                summary: &value.summary,
+            }
+        }
+    }
+
+    impl<TPayload, TGenesisPayload, TPeerId> From<SyncRequest<TPeerId>>
+        for super::SyncRequest<TPayload, TGenesisPayload, TPeerId>
+    {
+        fn from(value: SyncRequest<TPeerId>) -> Self {
+            Self {
+                from: value.from,
+                summary: value.summary,
+                events: None,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MessageKind {
+    Jobs = 0,
+    SyncRequest = 1,
+    Summary = 2,
+    /// [`net::protocol::Message`](crate::net::protocol::Message)
+    Protocol = 3,
+    /// [`Topical`](crate::algorithm::datastructure::host::Topical) message
+    /// of a graph host
+    Topic = 4,
+}
+
+#[derive(Error, Debug)]
+pub enum WireError {
+    #[error("Message is shorter than the header")]
+    Truncated,
+    #[error("Wire format version {version} is not supported (supported {min}..={max})")]
+    UnsupportedVersion { version: u16, min: u16, max: u16 },
+    #[error("Expected {expected:?} message, got message kind {found}")]
+    UnexpectedKind { expected: MessageKind, found: u8 },
+    #[error("Malformed message body: {0}")]
+    Body(#[from] bincode::Error),
+}
+
+pub trait WireMessage: Sized {
+    const KIND: MessageKind;
+
+    /// Body in the format of `version`, which is guaranteed to be
+    /// supported. Compatibility shims for older versions go here, parts
+    /// they can't carry are left out.
+    fn encode_body(&self, version: u16) -> bincode::Result<Vec<u8>>;
+
+    /// `version` is guaranteed to be supported. Compatibility shims for
+    /// older versions go here.
+    fn decode_body(version: u16, body: &[u8]) -> bincode::Result<Self>;
+
+    /// Message in the format of [`WIRE_VERSION`]
+    fn to_wire(&self) -> Result<Vec<u8>, WireError> {
+        self.to_wire_version(WIRE_VERSION)
+    }
+
+    /// Message in the format of `version`, e.g. the one negotiated with
+    /// the peer
+    fn to_wire_version(&self, version: u16) -> Result<Vec<u8>, WireError> {
+        check_version(version)?;
+        let body = self.encode_body(version)?;
+        let mut bytes = Vec::with_capacity(HEADER_LEN + body.len());
+        bytes.extend_from_slice(&version.to_le_bytes());
+        bytes.push(Self::KIND as u8);
+        bytes.extend(body);
+        Ok(bytes)
+    }
+
+    fn from_wire(bytes: &[u8]) -> Result<Self, WireError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(WireError::Truncated);
+        }
+        let version = u16::from_le_bytes([bytes[0], bytes[1]]);
+        check_version(version)?;
+        if bytes[2] != Self::KIND as u8 {
+            return Err(WireError::UnexpectedKind {
+                expected: Self::KIND,
+                found: bytes[2],
+            });
+        }
+        let body = &bytes[HEADER_LEN..];
+        let message = compression::with_decompression_budget(MAX_DECOMPRESSED_PER_MESSAGE, || {
+            Self::decode_body(version, body)
+        })?;
+        Ok(message)
+    }
+}
+
+fn check_version(version: u16) -> Result<(), WireError> {
+    if !(MIN_WIRE_VERSION..=WIRE_VERSION).contains(&version) {
+        return Err(WireError::UnsupportedVersion {
+            version,
+            min: MIN_WIRE_VERSION,
+            max: WIRE_VERSION,
+        });
+    }
+    Ok(())
+}
+
+impl<TPayload, TGenesisPayload, TPeerId> WireMessage for Jobs<TPayload, TGenesisPayload, TPeerId>
+where
+    TPayload: PayloadCodec,
+    TGenesisPayload: Serialize + DeserializeOwned,
+    TPeerId: Serialize + DeserializeOwned,
+{
+    const KIND: MessageKind = MessageKind::Jobs;
+
+    fn encode_body(&self, _version: u16) -> bincode::Result<Vec<u8>> {
+        bincode::serialize(self)
+    }
+
+    fn decode_body(version: u16, body: &[u8]) -> bincode::Result<Self> {
+        match version {
+            1 | 2 => bincode::deserialize(body),
+            _ => unreachable!("version is checked before decoding"),
+        }
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId> WireMessage
+    for SyncRequest<TPayload, TGenesisPayload, TPeerId>
+where
+    TPayload: PayloadCodec,
+    TGenesisPayload: Serialize + DeserializeOwned,
+    TPeerId: Serialize + DeserializeOwned,
+{
+    const KIND: MessageKind = MessageKind::SyncRequest;
+
+    fn encode_body(&self, version: u16) -> bincode::Result<Vec<u8>> {
+        match version {
+            1 => bincode::serialize(&v1::SyncRequestRef::from(self)),
+            2 => bincode::serialize(self),
+            _ => unreachable!("version is checked before encoding"),
+        }
+    }
+
+    fn decode_body(version: u16, body: &[u8]) -> bincode::Result<Self> {
+        match version {
+            1 => bincode::deserialize::<v1::SyncRequest<TPeerId>>(body).map(Into::into),
+            2 => bincode::deserialize(body),
+            _ => unreachable!("version is checked before decoding"),
+        }
+    }
+}
+
+impl<TPeerId> WireMessage for Summary<TPeerId>
+where
+    TPeerId: Serialize + DeserializeOwned,
+{
+    const KIND: MessageKind = MessageKind::Summary;
+
+    fn encode_body(&self, _version: u16) -> bincode::Result<Vec<u8>> {
+        bincode::serialize(self)
+    }
+
+    fn decode_body(version: u16, body: &[u8]) -> bincode::Result<Self> {
+        match version {
+            1 | 2 => bincode::deserialize(body),
+            _ => unreachable!("version is checked before decoding"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+
+    use super::*;
+    use crate::testing::{GraphBuilder, TestGraph};
+
+    // Encodings of messages of `graph()`. If the current ones change, the
+    // wire format changed: bump `WIRE_VERSION` and add the shims instead of
+    // updating the bytes. Older ones must stay decodable.
+    const JOBS_V1: [u8; 179] = hex!(
+        "010000010000000000000004000000000000002a000000000000000700000000"
+        "00000000000000000000000000000000000000e943da8c437f5421eeb20faa97"
+        "f40ed2b35f68e8dd79910dc34f6cc10dd79f835403a69d4dead0e13bf9ad1c56"
+        "ac39d34cc1c74dd6a70ca0736edf7559c3ca5a1b11e1366f993a0843366567f7"
+        "394110009ebd9dc4a247f7edec5810f61dbd9957bc79f78c6f37f85277a4ee4a"
+        "80bf25261e93dbb97784468f4920e08ab79693"
+    );
+    const SYNC_REQUEST_V1: [u8; 99] = hex!(
+        "0100010700000000000000010000000000000007000000000000000100000000"
+        "000000e943da8c437f5421eeb20faa97f40ed2b35f68e8dd79910dc34f6cc10d"
+        "d79f835403a69d4dead0e13bf9ad1c56ac39d34cc1c74dd6a70ca0736edf7559"
+        "c3ca5a"
+    );
+    const JOBS_V2: [u8; 179] = hex!(
+        "020000010000000000000004000000000000002a000000000000000700000000"
+        "00000000000000000000000000000000000000e943da8c437f5421eeb20faa97"
+        "f40ed2b35f68e8dd79910dc34f6cc10dd79f835403a69d4dead0e13bf9ad1c56"
+        "ac39d34cc1c74dd6a70ca0736edf7559c3ca5a1b11e1366f993a0843366567f7"
+        "394110009ebd9dc4a247f7edec5810f61dbd9957bc79f78c6f37f85277a4ee4a"
+        "80bf25261e93dbb97784468f4920e08ab79693"
+    );
+    const SYNC_REQUEST_V2: [u8; 100] = hex!(
+        "0200010700000000000000010000000000000007000000000000000100000000"
+        "000000e943da8c437f5421eeb20faa97f40ed2b35f68e8dd79910dc34f6cc10d"
+        "d79f835403a69d4dead0e13bf9ad1c56ac39d34cc1c74dd6a70ca0736edf7559"
+        "c3ca5a00"
+    );
+
+    fn graph() -> TestGraph<u32, u64> {
+        GraphBuilder::new("a", 7u64, 42u32, 999)
+            .build()
+            .unwrap()
+            .graph
+    }
+
+    #[test]
+    fn golden_bytes_match() {
+        let g = graph();
+        let jobs = g.generate_sync_for(&8).unwrap();
+        assert_eq!(jobs.to_wire().unwrap(), JOBS_V2);
+        assert_eq!(Jobs::from_wire(&JOBS_V2).unwrap(), jobs);
+
+        let request = g.sync_request();
+        assert_eq!(request.to_wire().unwrap(), SYNC_REQUEST_V2);
+        assert_eq!(SyncRequest::from_wire(&SYNC_REQUEST_V2).unwrap(), request);
+
+        let summary = g.summary();
+        let bytes = summary.to_wire().unwrap();
+        assert_eq!(bytes[..HEADER_LEN], [2, 0, MessageKind::Summary as u8]);
+        // Summary follows the sender id in the request
+        assert_eq!(
+            bytes[HEADER_LEN..],
+            SYNC_REQUEST_V2[HEADER_LEN + 8..SYNC_REQUEST_V2.len() - 1]
+        );
+        assert_eq!(Summary::from_wire(&bytes).unwrap(), summary);
+    }
+
+    #[test]
+    fn older_versions_decoded_and_encoded() {
+        let g = graph();
+        let jobs = g.generate_sync_for(&8).unwrap();
+        assert_eq!(Jobs::from_wire(&JOBS_V1).unwrap(), jobs);
+        assert_eq!(jobs.to_wire_version(1).unwrap(), JOBS_V1);
+        let request = g.sync_request();
+        assert_eq!(SyncRequest::from_wire(&SYNC_REQUEST_V1).unwrap(), request);
+        assert_eq!(request.to_wire_version(1).unwrap(), SYNC_REQUEST_V1);
+        assert!(matches!(
+            request.to_wire_version(WIRE_VERSION + 1),
+            Err(WireError::UnsupportedVersion { .. })
+        ));
+        let mut summary = SYNC_REQUEST_V1[..HEADER_LEN].to_vec();
+        summary[2] = MessageKind::Summary as u8;
+        summary.extend(&SYNC_REQUEST_V1[HEADER_LEN + 8..]);
+        assert_eq!(Summary::from_wire(&summary).unwrap(), g.summary());
+    }
+
+    #[test]
+    fn bad_headers_rejected() {
+        let mut future = SYNC_REQUEST_V1;
+        future[..2].copy_from_slice(&(WIRE_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            SyncRequest::<u32, (), u64>::from_wire(&future),
+            Err(WireError::UnsupportedVersion { version, .. }) if version == WIRE_VERSION + 1
+        ));
+        assert!(matches!(
+            Summary::<u64>::from_wire(&SYNC_REQUEST_V1),
+            Err(WireError::UnexpectedKind {
+                expected: MessageKind::Summary,
+                found: 1
+            })
+        ));
+        assert!(matches!(
+            SyncRequest::<u32, (), u64>::from_wire(&SYNC_REQUEST_V1[..2]),
+            Err(WireError::Truncated)
+        ));
+        assert!(matches!(
+            SyncRequest::<u32, (), u64>::from_wire(&SYNC_REQUEST_V1[..20]),
+            Err(WireError::Body(_))
+        ));
+    }
+}
@@ -1,6 +1,7 @@
 use std::{hash::Hash, iter::repeat};
 
 use crate::algorithm::MockSigner;
+use crate::testing::GraphBuilder;
 
 use super::*;
 
@@ -29,241 +30,47 @@ pub struct TestSetup<TPayload, TGenesisPayload, TPeerId> {
     pub setup_name: String,
 }
 
-/// See [`add_events_with_timestamps`]
-fn add_events<TPayload, TGenesisPayload, TPeerId, TIter, TSigner>(
-    graph: &mut Graph<
-        TPayload,
-        TGenesisPayload,
-        TPeerId,
-        MockSigner<TPeerId, TGenesisPayload>,
-        IncrementalClock,
-    >,
-    events: &[(&'static str, &'static str, &'static str)],
-    author_ids: HashMap<&'static str, TPeerId>,
-    payload: &mut TIter,
-    universal_signer: TSigner,
-) -> Result<
-    (
-        HashMap<String, PeerEvents<TPeerId>>,
-        HashMap<event::Hash, String>, // hash -> event_name
-    ),
-    String,
->
-where
-    TPayload: Serialize + Copy + Default + Eq + Hash + Debug,
-    TGenesisPayload: Serialize + Copy + Default + Eq + Hash + Debug,
-    TPeerId: Serialize + Eq + std::hash::Hash + Debug + Clone,
-    TIter: Iterator<Item = TPayload>,
-    TSigner: Signer<(), SignerIdentity = TPeerId>,
-{
-    let timestamps = events.iter().map(|&(name, _, _)| (name, 0)).collect();
-    add_events_with_timestamps(
-        graph,
-        events,
-        author_ids,
-        payload,
-        timestamps,
-        universal_signer,
-    )
-}
-
-/// ## Description
-/// Add multiple events in the graph (for easier test case creation and
-/// concise and more intuitive writing).
-///
-/// ## Arguments
-/// ### `events`
-/// is list of tuples (`event_name`, `creator`, `other_parent_name`)
-///
-/// ### `creator`
-/// is either
-/// * `"GENESIS_<peer_name>"` for genesis event of the peer (intended for fork testing)
-/// * `event_name` of a previous event (intended for fork testing)
-/// * name of peer for **its latest event** (in `author_ids`, to insert at the end of the graph)
-///
-/// first match is chosen
-///
-/// ### `other_parent_name`
-/// is either
-/// * `event_name` of a previous event
-/// * name of peer for **its genesis**
-///
-/// first match is chosen
-fn add_events_with_timestamps<T, G, TPeerId, TIter, TSigner>(
-    graph: &mut Graph<T, G, TPeerId, MockSigner<TPeerId, G>, IncrementalClock>,
-    events: &[(&'static str, &'static str, &'static str)],
-    author_ids: HashMap<&'static str, TPeerId>,
-    payload: &mut TIter,
-    timestamps: HashMap<&'static str, Timestamp>,
-    universal_signer: TSigner,
-) -> Result<
-    (
-        HashMap<String, PeerEvents<TPeerId>>,
-        HashMap<event::Hash, String>, // hash -> event_name
-    ),
-    String,
->
+/// Add `events` (see [`GraphBuilder::event`]) with payloads and timestamps
+/// taken from the iterators
+fn build_setup<T, TPayloadIter, TTimestampIter>(
+    mut builder: GraphBuilder<T, MockPeerId>,
+    events: &[(&str, &str, &str)],
+    payload: &mut TPayloadIter,
+    timestamps: &mut TTimestampIter,
+    setup_name: &str,
+) -> Result<TestSetup<T, (), MockPeerId>, String>
 where
-    T: Serialize + Copy + Default + Eq + Hash + Debug,
-    G: Serialize + Copy + Default + Eq + Hash + Debug,
-    TPeerId: Serialize + Eq + std::hash::Hash + Debug + Clone,
-    TIter: Iterator<Item = T>,
-    TSigner: Signer<(), SignerIdentity = TPeerId>,
+    T: PayloadCodec + Copy + Default + Eq + Hash + Debug,
+    TPayloadIter: Iterator<Item = T>,
+    TTimestampIter: Iterator<Item = Timestamp>,
 {
-    let mut inserted_events = HashMap::with_capacity(events.len());
-    for (peer_name, peer_id) in &author_ids {
-        let key = format!("GENESIS_{}", peer_name).to_owned();
-        inserted_events.insert(
-            key,
-            graph
-                .peer_genesis(&peer_id)
-                .expect(&format!("Incorrect peer id: {:?}", peer_id))
-                .clone(),
-        );
+    for &(name, creator, other_parent) in events {
+        builder = builder
+            .payload(payload.next().expect("Iterator finished"))
+            .timestamp(timestamps.next().expect("No timestamps left"))
+            .event(name, creator, other_parent);
     }
-    let mut peers_events: HashMap<String, PeerEvents<TPeerId>> = author_ids
-        .keys()
-        .map(|&name| {
-            let id = author_ids
-                .get(name)
-                .expect(&format!("Unknown author name '{}'", name))
-                .clone();
-            let genesis = graph
-                .peer_genesis(&id)
-                .expect(&format!("Unknown author id '{:?}' (name {})", id, name));
-            (
-                name.to_owned(),
-                PeerEvents {
-                    id,
-                    events: vec![genesis.clone()],
-                },
-            )
+    let built = builder.build().map_err(|e| e.to_string())?;
+    let peers_events = built
+        .peer_names()
+        .map(|name| {
+            let events = PeerEvents {
+                id: *built.peer_id(name).expect("Just listed"),
+                events: built.peer_events(name).expect("Just listed").to_vec(),
+            };
+            (name.to_owned(), events)
         })
         .collect();
-
-    for &(event_name, self_parent_str, other_parent_event) in events {
-        let other_parent_event_hash = match author_ids.get(other_parent_event) {
-            Some(h) => graph.peer_genesis(h).expect(&format!(
-                "Unknown peer id {:?} to graph (name '{}')",
-                h, self_parent_str
-            )),
-            None => inserted_events
-                .get(other_parent_event)
-                .expect(&format!("Unknown `other_parent` '{}'", other_parent_event)),
-        };
-        let genesis_prefix = "GENESIS_";
-        let (self_parent_event_hash, author_id, author) = match (
-            self_parent_str.starts_with(genesis_prefix),
-            inserted_events.get(self_parent_str),
-            author_ids.get(self_parent_str),
-        ) {
-            // `self_parent_str` has form `GENESIS_{author_name}`; genesis is self_parent.
-            (true, _, _) => {
-                let author_name = self_parent_str.trim_start_matches(genesis_prefix);
-                let author_id = author_ids
-                    .get(author_name)
-                    .expect(&format!("Unknown author name '{}'", author_name));
-                let self_parent = graph.peer_genesis(author_id).expect(&format!(
-                    "Unknown author id of '{}': {:?}",
-                    author_name, author_id
-                ));
-                let author_name = self_parent_str
-                    .trim_start_matches(genesis_prefix)
-                    .to_owned();
-                (self_parent, author_id, author_name)
-            }
-            // `self_parent_str` is a name of previously inserted event; it is the self parent.
-            (false, Some(self_parent_hash), _) => {
-                let self_parent_event = graph
-                    .event(self_parent_hash)
-                    .expect("Just inserted the event");
-                let author_name = author_ids
-                    .iter()
-                    .find(|(_name, id)| id == &self_parent_event.author())
-                    .expect("Just inserted the event, should be tracked")
-                    .0
-                    .deref()
-                    .to_owned();
-                (self_parent_hash, self_parent_event.author(), author_name)
-            }
-            // `self_parent_str` is a name of peer; latest event of the peer is self parent.
-            (false, None, Some(self_parent_author_id)) => (
-                graph
-                    .peer_latest_event(self_parent_author_id)
-                    .expect(&format!("Unknown event author {}", self_parent_str)),
-                self_parent_author_id,
-                self_parent_str.to_owned(),
-            ),
-            (false, None, None) => panic!("Could not recognize creator '{}'", self_parent_str),
-        };
-        let parents = Parents {
-            self_parent: self_parent_event_hash.clone(),
-            other_parent: other_parent_event_hash.clone(),
-        };
-        let new_event = SignedEvent::new(
-            payload.next().expect("Iterator finished"),
-            event::Kind::Regular(parents),
-            author_id.clone(),
-            *timestamps
-                .get(event_name)
-                .expect(&format!("No timestamp for event {}", event_name)),
-            |h| universal_signer.sign(h),
-        )
-        .expect("Failed to create event");
-        let new_event_hash = new_event.hash().clone();
-        let (unsigned, signature) = new_event.into_parts();
-        graph
-            .push_event(unsigned, signature)
-            .map_err(|e| format!("Failer to push event {}: {:?}", event_name, e))?;
-        peers_events
-            .get_mut(&author)
-            .expect(&format!("Author '{}' should be in the index", author))
-            .events
-            .push(new_event_hash.clone());
-        let clashed_event = inserted_events.insert(event_name.to_owned(), new_event_hash);
-        if clashed_event.is_some() {
-            panic!("Event name clash '{}'", event_name)
-        }
-    }
-    let names = inserted_events.into_iter().map(|(a, b)| (b, a)).collect();
-    Ok((peers_events, names))
-}
-
-fn add_geneses<TPayload, TPeerId, TSigner>(
-    graph: &mut Graph<TPayload, (), TPeerId, TSigner, IncrementalClock>,
-    this_author: &str,
-    author_ids: &HashMap<&'static str, TPeerId>,
-    payload: TPayload,
-    universal_signer: TSigner,
-) -> Result<HashMap<event::Hash, String>, PushError<TPeerId>>
-where
-    TPayload: Serialize + Copy + Eq + Hash + Debug,
-    TPeerId: Serialize + Eq + std::hash::Hash + Debug + Clone,
-    TSigner: Signer<(), SignerIdentity = TPeerId>,
-{
-    let mut names = HashMap::with_capacity(author_ids.len());
-
-    for (&name, id) in author_ids {
-        let hash = if name == this_author {
-            graph
-                .peer_genesis(id)
-                .expect("Mush have own genesis")
-                .clone()
-        } else {
-            // Geneses should not have timestamp 0 (?), but why not do it for testing other components
-            let next_genesis =
-                SignedEvent::new(payload, event::Kind::Genesis(()), id.clone(), 0, |h| {
-                    universal_signer.sign(h)
-                })
-                .expect("Failed to create event");
-            let gen_id = next_genesis.hash().clone();
-            let (unsigned, signature) = next_genesis.into_parts();
-            graph.push_event(unsigned, signature)?;
-            gen_id
-        };
-        names.insert(hash, name.to_owned());
-    }
-    Ok(names)
+    let names = built
+        .events()
+        .map(|(name, hash)| (hash.clone(), name.to_owned()))
+        .collect();
+    Ok(TestSetup {
+        graph: built.graph,
+        peers_events,
+        names,
+        setup_name: setup_name.to_owned(),
+    })
 }
 
 pub fn build_graph_from_paper<T>(
@@ -271,19 +78,13 @@ pub fn build_graph_from_paper<T>(
     coin_frequency: usize,
 ) -> Result<TestSetup<T, (), MockPeerId>, String>
 where
-    T: Serialize + Copy + Default + Eq + Hash + Debug,
+    T: PayloadCodec + Copy + Default + Eq + Hash + Debug,
 {
-    let author_ids = HashMap::from([("a", 0), ("b", 1), ("c", 2), ("d", 3), ("e", 4)]);
-    let mut graph = Graph::new(
-        *author_ids.get("a").unwrap(),
-        payload,
-        (),
-        coin_frequency,
-        MockSigner::new(),
-        IncrementalClock::new(),
-    );
-    let mut names = add_geneses(&mut graph, "a", &author_ids, payload, MockSigner::new())
-        .map_err(|e| format!("{}", e))?;
+    let builder = GraphBuilder::new("a", 0, payload, coin_frequency)
+        .peer("b", 1)
+        .peer("c", 2)
+        .peer("d", 3)
+        .peer("e", 4);
     let events = [
         //  (name, peer, other_parent)
         ("c2", "c", "d"),
@@ -299,20 +100,13 @@ where
         ("c6", "c", "a3"),
     ];
     // let timestamps = events.iter().zip(0..).map(|(a, b)| (a.0, b)).collect();
-    let (peers_events, new_names) = add_events(
-        &mut graph,
+    build_setup(
+        builder,
         &events,
-        author_ids,
         &mut repeat(payload),
-        MockSigner::new(),
-    )?;
-    names.extend(new_names);
-    Ok(TestSetup {
-        graph,
-        peers_events,
-        names,
-        setup_name: "Whitepaper example".to_owned(),
-    })
+        &mut repeat(0),
+        "Whitepaper example",
+    )
 }
 
 pub fn build_graph_some_chain<T>(
@@ -320,7 +114,7 @@ pub fn build_graph_some_chain<T>(
     coin_frequency: usize,
 ) -> Result<TestSetup<T, (), MockPeerId>, String>
 where
-    T: Serialize + Copy + Default + Eq + Hash + Debug,
+    T: PayloadCodec + Copy + Default + Eq + Hash + Debug,
 {
     /* Generates the following graph for each member (c1,c2,c3)
      *
@@ -333,17 +127,9 @@ where
         o__|  |  -- e1
         o  o  o  -- (g1,g2,g3)
     */
-    let author_ids = HashMap::from([("g1", 0), ("g2", 1), ("g3", 2)]);
-    let mut graph = Graph::new(
-        *author_ids.get("g1").unwrap(),
-        payload,
-        (),
-        coin_frequency,
-        MockSigner::new(),
-        IncrementalClock::new(),
-    );
-    let mut names = add_geneses(&mut graph, "g1", &author_ids, payload, MockSigner::new())
-        .map_err(|e| format!("{}", e))?;
+    let builder = GraphBuilder::new("g1", 0, payload, coin_frequency)
+        .peer("g2", 1)
+        .peer("g3", 2);
     let events = [
         //  (name, peer, other_parent)
         ("e1", "g1", "g2"),
@@ -354,20 +140,13 @@ where
         ("e6", "g3", "e5"),
         ("e7", "g2", "e6"),
     ];
-    let (peers_events, new_names) = add_events(
-        &mut graph,
+    build_setup(
+        builder,
         &events,
-        author_ids,
         &mut repeat(payload),
-        MockSigner::new(),
-    )?;
-    names.extend(new_names);
-    Ok(TestSetup {
-        graph,
-        peers_events,
-        names,
-        setup_name: "Chain events".to_owned(),
-    })
+        &mut repeat(0),
+        "Chain events",
+    )
 }
 
 pub fn build_graph_detailed_example<T>(
@@ -375,7 +154,7 @@ pub fn build_graph_detailed_example<T>(
     coin_frequency: usize,
 ) -> Result<TestSetup<T, (), MockPeerId>, String>
 where
-    T: Serialize + Copy + Default + Eq + Hash + Debug,
+    T: PayloadCodec + Copy + Default + Eq + Hash + Debug,
 {
     build_graph_detailed_example_with_timestamps(payload, coin_frequency, repeat(0))
 }
@@ -386,24 +165,17 @@ pub fn build_graph_detailed_example_with_timestamps<T, TIter>(
     mut timestamp_generator: TIter,
 ) -> Result<TestSetup<T, (), MockPeerId>, String>
 where
-    T: Serialize + Copy + Default + Eq + Hash + Debug,
+    T: PayloadCodec + Copy + Default + Eq + Hash + Debug,
     TIter: Iterator<Item = Timestamp>,
 {
     // Defines graph from paper HASHGRAPH CONSENSUS: DETAILED EXAMPLES
     // https://www.swirlds.com/downloads/SWIRLDS-TR-2016-02.pdf
     // also in resources/graph_example.png
 
-    let author_ids = HashMap::from([("a", 0), ("b", 1), ("c", 2), ("d", 3)]);
-    let mut graph = Graph::new(
-        *author_ids.get("a").unwrap(),
-        payload,
-        (),
-        coin_frequency,
-        MockSigner::new(),
-        IncrementalClock::new(),
-    );
-    let mut names = add_geneses(&mut graph, "a", &author_ids, payload, MockSigner::new())
-        .map_err(|e| format!("{}", e))?;
+    let builder = GraphBuilder::new("a", 0, payload, coin_frequency)
+        .peer("b", 1)
+        .peer("c", 2)
+        .peer("d", 3);
     // resources/graph_example.png for reference
     let events = [
         //  (name,  peer, other_parent)
@@ -443,30 +215,13 @@ where
         ("d4", "d", "c3"),
         ("b4", "b", "d4"),
     ];
-    let timestamps = events
-        .iter()
-        .map(|&(event, _, _)| {
-            (
-                event,
-                timestamp_generator.next().expect("No timestamps left"),
-            )
-        })
-        .collect();
-    let (peers_events, new_names) = add_events_with_timestamps(
-        &mut graph,
+    build_setup(
+        builder,
         &events,
-        author_ids,
         &mut repeat(payload),
-        timestamps,
-        MockSigner::new(),
-    )?;
-    names.extend(new_names);
-    Ok(TestSetup {
-        graph,
-        peers_events,
-        names,
-        setup_name: "Detailed examples tech report".to_owned(),
-    })
+        &mut timestamp_generator,
+        "Detailed examples tech report",
+    )
 }
 
 pub fn build_graph_fork<T, TIter>(
@@ -474,29 +229,21 @@ pub fn build_graph_fork<T, TIter>(
     coin_frequency: usize,
 ) -> Result<TestSetup<T, (), MockPeerId>, String>
 where
-    T: Serialize + Copy + Default + Eq + Hash + Debug,
+    T: PayloadCodec + Copy + Default + Eq + Hash + Debug,
     TIter: Iterator<Item = T>,
 {
     // Graph to test fork handling
     // In peers_events the "_forked" event goes before non-fork (they're simmetric, so we refer
     // to the names)
-    let author_ids = HashMap::from([("a", 0), ("m", 1)]);
-    let mut graph = Graph::new(
-        *author_ids.get("a").unwrap(),
+    let builder = GraphBuilder::new(
+        "a",
+        0,
         payload.next().expect("Iterator finished"),
-        (),
         coin_frequency,
-        MockSigner::new(),
-        IncrementalClock::new(),
     );
-    let mut names = add_geneses(
-        &mut graph,
-        "a",
-        &author_ids,
-        payload.next().expect("Iterator finished"),
-        MockSigner::new(),
-    )
-    .map_err(|e| format!("{}", e))?;
+    let builder = builder
+        .payload(payload.next().expect("Iterator finished"))
+        .peer("m", 1);
     let events = [
         //  (name,  peer, other_parent)
         // round 1
@@ -513,20 +260,7 @@ where
         ("m4", "m", "a3"),
         ("a4", "a", "m4"),
     ];
-    let (peers_events, new_names) = add_events(
-        &mut graph,
-        &events,
-        author_ids,
-        &mut payload,
-        MockSigner::new(),
-    )?;
-    names.extend(new_names);
-    Ok(TestSetup {
-        graph,
-        peers_events,
-        names,
-        setup_name: "Fork graph".to_owned(),
-    })
+    build_setup(builder, &events, &mut payload, &mut repeat(0), "Fork graph")
 }
 
 pub fn build_graph_index_test<T>(
@@ -534,21 +268,14 @@ pub fn build_graph_index_test<T>(
     coin_frequency: usize,
 ) -> Result<TestSetup<T, (), MockPeerId>, String>
 where
-    T: Serialize + Copy + Default + Eq + Hash + Debug,
+    T: PayloadCodec + Copy + Default + Eq + Hash + Debug,
 {
     // Graph to test round_index assignment. It seems that the logic is broken slightly,
     // this should fail with existing impl.
-    let author_ids = HashMap::from([("a", 0), ("b", 1), ("c", 2), ("d", 3)]);
-    let mut graph = Graph::new(
-        *author_ids.get("b").unwrap(),
-        payload,
-        (),
-        coin_frequency,
-        MockSigner::new(),
-        IncrementalClock::new(),
-    );
-    let mut names = add_geneses(&mut graph, "b", &author_ids, payload, MockSigner::new())
-        .map_err(|e| format!("{}", e))?;
+    let builder = GraphBuilder::new("b", 1, payload, coin_frequency)
+        .peer("a", 0)
+        .peer("c", 2)
+        .peer("d", 3);
     // resources/graph_example.png for reference
     let events = [
         //  (name,  peer, other_parent)
@@ -565,18 +292,11 @@ where
         ("a1", "a", "b"),
         ("a2", "a", "b1"),
     ];
-    let (peers_events, new_names) = add_events(
-        &mut graph,
+    build_setup(
+        builder,
         &events,
-        author_ids,
         &mut repeat(payload),
-        MockSigner::new(),
-    )?;
-    names.extend(new_names);
-    Ok(TestSetup {
-        graph,
-        peers_events,
-        names,
-        setup_name: "`round_index` test".to_owned(),
-    })
+        &mut repeat(0),
+        "`round_index` test",
+    )
 }
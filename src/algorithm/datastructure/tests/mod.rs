@@ -1,4 +1,4 @@
-use std::{iter::successors, ops::Deref};
+use std::iter::successors;
 
 use itertools::Itertools;
 use mocks::{
@@ -7,11 +7,15 @@ use mocks::{
 };
 use test_utils::{run_tests, test_cases, Test};
 
-use crate::algorithm::{datastructure::tests::mocks::MockPeerId, IncrementalClock, MockSigner};
+use crate::algorithm::{
+    datastructure::tests::mocks::MockPeerId, IncrementalClock, ManualClock, MockSigner,
+    PushErrorCategory,
+};
 
 use super::*;
 
-mod mocks;
+pub(super) mod mocks;
+mod properties;
 mod test_utils;
 
 // Test simple work + errors
@@ -24,10 +28,10 @@ fn push_works() {
     let new_event = SignedEvent::new(
         (),
         event::Kind::Regular(Parents {
-            self_parent: graph.peer_latest_event(&graph.self_id).unwrap().clone(),
-            other_parent: graph.peer_latest_event(&graph.self_id).unwrap().clone(),
+            self_parent: graph.self_tip().unwrap().clone(),
+            other_parent: graph.self_tip().unwrap().clone(),
         }),
-        graph.self_id,
+        graph.self_id.unwrap(),
         1,
         |h| MockSigner::<i32, ()>::new().sign(h),
     )
@@ -48,7 +52,7 @@ fn push_works() {
         (),
         event::Kind::Regular(Parents {
             self_parent: graph.peer_latest_event(&1).unwrap().clone(),
-            other_parent: graph.peer_latest_event(&graph.self_id).unwrap().clone(),
+            other_parent: graph.self_tip().unwrap().clone(),
         }),
         1,
         3,
@@ -72,7 +76,7 @@ fn create_works() {
 
     // new event by the same author
     graph
-        .create_event((), graph.peer_latest_event(&graph.self_id).unwrap().clone())
+        .create_event((), graph.self_tip().unwrap().clone())
         .unwrap();
 
     // new peer
@@ -114,7 +118,8 @@ fn duplicate_push_fails() {
     let (unsigned, signature) = new_event.into_parts();
     assert!(matches!(
         graph.push_event(unsigned, signature),
-        Err(PushError::EventAlreadyExists(hash)) if &hash == graph.peer_genesis(&a_id).unwrap()
+        Err(e @ PushError::EventAlreadyExists(_))
+            if e.event() == graph.peer_genesis(&a_id) && e.category() == PushErrorCategory::Duplicate
     ));
 }
 
@@ -137,10 +142,43 @@ fn double_genesis_fails() {
     let (unsigned, signature) = new_event.into_parts();
     assert!(matches!(
         graph.push_event(unsigned, signature),
-        Err(PushError::GenesisAlreadyExists)
+        Err(e @ PushError::GenesisAlreadyExists { peer: 0, .. })
+            if e.category() == PushErrorCategory::PeerMisbehavior
     ))
 }
 
+#[test]
+fn tampered_hash_fails() {
+    let mut graph = Graph::new(0, 0, (), 999, MockSigner::new(), IncrementalClock::new());
+    let genesis = graph.self_tip().unwrap().clone();
+    let event = |payload: u64| {
+        SignedEvent::<_, (), MockPeerId>::new(
+            payload,
+            event::Kind::Regular(Parents {
+                self_parent: genesis.clone(),
+                other_parent: genesis.clone(),
+            }),
+            0,
+            1,
+            |h| MockSigner::<MockPeerId, ()>::new().sign(h),
+        )
+        .unwrap()
+    };
+    // Contents of one event with the hash and signature of another
+    let honest = bincode::serialize(&event(1)).unwrap();
+    let other = bincode::serialize(&event(2)).unwrap();
+    let suffix = 2 * bincode::serialize(&genesis).unwrap().len();
+    let mut tampered = other[..other.len() - suffix].to_vec();
+    tampered.extend_from_slice(&honest[honest.len() - suffix..]);
+    let tampered: SignedEvent<u64, (), MockPeerId> = bincode::deserialize(&tampered).unwrap();
+    assert_eq!(tampered.unsigned().fields().user_payload(), Some(&2));
+
+    let (unsigned, signature) = tampered.into_parts();
+    let err = graph.push_event(unsigned, signature).unwrap_err();
+    assert!(matches!(err, PushError::HashMismatch { author: 0, .. }));
+    assert_eq!(err.category(), PushErrorCategory::PeerMisbehavior);
+}
+
 #[test]
 fn missing_parent_fails() {
     let TestSetup {
@@ -155,6 +193,7 @@ fn missing_parent_fails() {
     })
     .unwrap();
     let legit_event_hash = graph.peer_latest_event(&0).unwrap().clone();
+    let a_id = peers.get("a").unwrap().id;
 
     let fake_parents_1 = Parents {
         self_parent: fake_event.hash().clone(),
@@ -169,10 +208,18 @@ fn missing_parent_fails() {
     )
     .expect("Failed to create event");
     let (unsigned, signature) = new_event.into_parts();
-    assert!(matches!(
-        graph.push_event(unsigned, signature),
-        Err(PushError::NoParent(fake_hash)) if &fake_hash == fake_event.hash()
-    ));
+    let err = graph.push_event(unsigned, signature).unwrap_err();
+    assert_eq!(err.category(), PushErrorCategory::Transient);
+    let PushError::NoParent {
+        missing_parent,
+        local_tip,
+        ..
+    } = &err
+    else {
+        panic!("unexpected error: {}", err);
+    };
+    assert_eq!(missing_parent, fake_event.hash());
+    assert_eq!(local_tip.as_ref(), graph.peer_latest_event(&a_id));
 
     let fake_parents_2 = Parents {
         self_parent: legit_event_hash.clone(),
@@ -187,10 +234,940 @@ fn missing_parent_fails() {
     )
     .expect("Failed to create event");
     let (unsigned, signature) = new_event.into_parts();
+    let err = graph.push_event(unsigned, signature).unwrap_err();
+    assert_eq!(err.category(), PushErrorCategory::Transient);
+    let PushError::NoParent {
+        missing_parent,
+        local_tip,
+        ..
+    } = &err
+    else {
+        panic!("unexpected error: {}", err);
+    };
+    assert_eq!(missing_parent, fake_event.hash());
+    assert_eq!(local_tip.as_ref(), graph.peer_latest_event(&a_id));
+}
+
+#[test]
+fn fork_is_independent() {
+    let TestSetup {
+        graph,
+        peers_events: _,
+        names: _,
+        setup_name: _,
+    } = build_graph_detailed_example((), 999).unwrap();
+    let mut original = graph;
+    let mut fork = original.fork();
+
+    // Both produce the same results
+    let mut original_finalized = vec![];
+    while let Some(event) = original.next_finalized_event() {
+        original_finalized.push(event.hash().clone());
+    }
+    let mut fork_finalized = vec![];
+    while let Some(event) = fork.next_finalized_event() {
+        fork_finalized.push(event.hash().clone());
+    }
+    assert!(!original_finalized.is_empty());
+    assert_eq!(original_finalized, fork_finalized);
+
+    // But the changes are not shared
+    let self_id = *fork.self_id().unwrap();
+    let other_parent = fork.peer_latest_event(&self_id).unwrap().clone();
+    let new_event = fork.create_event((), other_parent).unwrap();
+    assert!(fork.event(&new_event).is_some());
+    assert!(original.event(&new_event).is_none());
+    assert_ne!(
+        fork.peer_latest_event(&self_id),
+        original.peer_latest_event(&self_id)
+    );
+}
+
+#[test]
+fn peer_lane_correct() {
+    fn check_lane(setup: &TestSetup<i32, (), u64>, peer_name: &str, expected_len: usize) {
+        let peer_events = setup.peers_events.get(peer_name).unwrap();
+        let lane: Vec<_> = setup
+            .graph
+            .peer_lane(&peer_events.id)
+            .unwrap()
+            .map(|e| e.hash().clone())
+            .collect();
+        assert_eq!(lane.len(), expected_len);
+        assert_eq!(
+            lane.iter().collect::<HashSet<_>>(),
+            peer_events.events.iter().collect::<HashSet<_>>()
+        );
+        // self parents always come before
+        for (i, hash) in lane.iter().enumerate() {
+            if let event::Kind::Regular(p) = setup.graph.event(hash).unwrap().kind() {
+                assert!(lane[..i].contains(&p.self_parent));
+            }
+        }
+    }
+
+    let setup = build_graph_fork([42, 1337, 80085].into_iter().cycle(), 999).unwrap();
+    check_lane(&setup, "a", 5);
+    check_lane(&setup, "m", 6);
+    // Branches are not interleaved
+    let m = setup.peers_events.get("m").unwrap().id;
+    let lane: Vec<_> = setup
+        .graph
+        .peer_lane(&m)
+        .unwrap()
+        .map(|e| setup.names.get(e.hash()).unwrap().as_str())
+        .collect();
+    let m2_position = lane.iter().position(|n| *n == "m2").unwrap();
+    let m2_fork_position = lane.iter().position(|n| *n == "m2_fork").unwrap();
+    let m2_1_position = lane.iter().position(|n| *n == "m2_1").unwrap();
+    if m2_fork_position < m2_position {
+        assert_eq!(m2_1_position, m2_fork_position + 1);
+    } else {
+        assert_eq!(m2_position + 1, m2_fork_position);
+    }
+
+    let setup = build_graph_detailed_example(0, 999).unwrap();
+    check_lane(
+        &setup,
+        "b",
+        setup.peers_events.get("b").unwrap().events.len(),
+    );
+    assert!(setup.graph.peer_lane(&12345).is_none());
+}
+
+#[test]
+fn relations_on_unknown_events_fail() {
+    let TestSetup {
+        graph,
+        peers_events: _,
+        names: _,
+        setup_name: _,
+    } = build_graph_from_paper((), 999).unwrap();
+    let known = graph.peer_latest_event(&0).unwrap();
+    let unknown = event::Hash::from_hex(&"00".repeat(64)).unwrap();
+    let expected_err = Err(UnknownEvent(unknown.clone()));
+    assert_eq!(graph.determine_witness(&unknown), expected_err);
+    assert_eq!(graph.see(known, &unknown), expected_err);
+    assert_eq!(graph.see(&unknown, known), expected_err);
+    assert_eq!(graph.strongly_see(known, &unknown), expected_err);
+    assert_eq!(graph.strongly_see(&unknown, known), expected_err);
+    assert_eq!(graph.is_ancestor(known, &unknown), expected_err);
+    assert_eq!(graph.is_ancestor(&unknown, known), expected_err);
+    assert_eq!(graph.see(known, known), Ok(true));
+}
+
+#[test]
+fn future_events_rejected() {
+    let clock = ManualClock::new(100);
+    let mut graph = Graph::new(0, (), (), 999, MockSigner::new(), clock.clone());
+    graph.set_max_clock_skew(Some(10));
+    let signer = MockSigner::<MockPeerId, ()>::new();
+    let push_genesis = |graph: &mut Graph<_, _, _, _, _>, author, timestamp| {
+        let (unsigned, signature) =
+            SignedEvent::new((), event::Kind::Genesis(()), author, timestamp, |h| {
+                signer.sign(h)
+            })
+            .unwrap()
+            .into_parts();
+        graph.push_event(unsigned, signature)
+    };
     assert!(matches!(
-        graph.push_event(unsigned, signature),
-        Err(PushError::NoParent(fake_hash)) if &fake_hash == fake_event.hash()
+        push_genesis(&mut graph, 1, 111),
+        Err(PushError::TimestampFromFuture {
+            author: 1,
+            timestamp: 111,
+            local_time: 100,
+            ..
+        })
+    ));
+    push_genesis(&mut graph, 1, 110).unwrap();
+
+    // Our own events use the clock
+    clock.advance(5);
+    let created = graph
+        .create_event((), graph.peer_latest_event(&1).unwrap().clone())
+        .unwrap();
+    assert_eq!(graph.event(&created).unwrap().timestamp(), &105);
+}
+
+#[test]
+fn pushed_in_two_stages() {
+    let source = build_graph_some_chain((), 999).unwrap().graph;
+    let clock = ManualClock::new(0);
+    let mut graph = Graph::new(0, (), (), 999, MockSigner::new(), clock.clone());
+    let own_genesis = graph.peer_genesis(&0).unwrap().clone();
+    let (own, events): (Vec<_>, Vec<_>) = source
+        .generate_sync_for(&u64::MAX)
+        .unwrap()
+        .into_linear()
+        .into_iter()
+        .partition(|event| event.hash() == &own_genesis);
+    let (unsigned, signature) = own[0].clone().into_parts();
+    assert!(matches!(
+        graph.preverify(unsigned, signature),
+        Err(PushError::EventAlreadyExists(_))
+    ));
+
+    // Verified before anything is inserted, up to the first event of a peer
+    // whose genesis isn't known yet
+    let verified: Vec<_> = events
+        .iter()
+        .map_while(|event| {
+            let (unsigned, signature) = event.clone().into_parts();
+            graph.preverify(unsigned, signature).ok()
+        })
+        .collect();
+    assert!(!verified.is_empty());
+    assert!(verified.iter().all(|v| graph.event(v.hash()).is_none()));
+    for verified in verified {
+        graph.commit(verified).unwrap();
+    }
+    for event in &events {
+        let (unsigned, signature) = event.clone().into_parts();
+        match graph.preverify(unsigned, signature) {
+            Ok(verified) => graph.commit(verified).unwrap(),
+            Err(PushError::EventAlreadyExists(_)) => (),
+            Err(e) => panic!("{e}"),
+        }
+    }
+    assert_eq!(graph.state_hash(), source.state_hash());
+
+    // The clock is only read on commit
+    graph.set_max_clock_skew(Some(10));
+    let genesis = SignedEvent::new((), event::Kind::Genesis(()), 999, 11, |h| {
+        MockSigner::<MockPeerId, ()>::new().sign(h)
+    })
+    .unwrap();
+    let (unsigned, signature) = genesis.into_parts();
+    let verified = graph.preverify(unsigned, signature).unwrap();
+    let early = verified.clone();
+    assert!(matches!(
+        graph.commit(early),
+        Err(PushError::TimestampFromFuture { timestamp: 11, .. })
+    ));
+    clock.advance(1);
+    graph.commit(verified.clone()).unwrap();
+    assert!(matches!(
+        graph.commit(verified),
+        Err(PushError::EventAlreadyExists(_))
+    ));
+}
+
+#[test]
+fn verified_only_with_the_same_genesis() {
+    let other = Graph::new(0, (), (), 999, MockSigner::new(), ManualClock::new(5));
+    let genesis = other.peer_genesis(&0).unwrap().clone();
+    let event = SignedEvent::new(
+        (),
+        event::Kind::Regular(Parents {
+            self_parent: genesis.clone(),
+            other_parent: genesis,
+        }),
+        0,
+        6,
+        |h| MockSigner::<MockPeerId, ()>::new().sign(h),
+    )
+    .unwrap();
+    let (unsigned, signature) = event.into_parts();
+    let verified = other.preverify(unsigned, signature).unwrap();
+
+    // Same author, but its genesis (and so its key) is another one
+    let mut graph = Graph::new(0, (), (), 999, MockSigner::new(), ManualClock::new(0));
+    assert!(matches!(
+        graph.commit(verified.clone()),
+        Err(PushError::VerifiedWithOtherGenesis { author: 0, .. })
+    ));
+    let mut stranger = Graph::new(1, (), (), 999, MockSigner::new(), ManualClock::new(0));
+    assert!(matches!(
+        stranger.commit(verified.clone()),
+        Err(PushError::PeerNotFound { peer: 0, .. })
+    ));
+    // Forks share the geneses
+    other.fork().commit(verified).unwrap();
+}
+
+#[test]
+fn orphans_resolved_from_pending_pool() {
+    let mut source = build_graph_some_chain((), 999).unwrap().graph;
+    let mut events = vec![];
+    while let Some(event) = source.next_recognized_event() {
+        events.push(event.inner().clone());
+    }
+    let mut graph = Graph::new(0, (), (), 999, MockSigner::new(), IncrementalClock::new());
+    assert_eq!(graph.peer_genesis(&0), Some(events[0].hash()));
+    let to_push = events[1..].iter().rev().cloned().collect_vec();
+
+    // Disabled by default (the author's genesis is unknown as well)
+    let (unsigned, signature) = to_push[0].clone().into_parts();
+    assert!(matches!(
+        graph.push_or_buffer(unsigned, signature),
+        Err(PushError::PeerNotFound { peer: 1, .. })
+    ));
+
+    graph.set_pending_pool(Some(100));
+    let mut inserted_directly = 0;
+    for event in to_push.clone() {
+        let (unsigned, signature) = event.into_parts();
+        match graph.push_or_buffer(unsigned, signature).unwrap() {
+            PushOutcome::Inserted => inserted_directly += 1,
+            PushOutcome::Buffered { evicted, .. } => assert!(evicted.is_empty()),
+        }
+    }
+    assert_eq!(graph.pending_count(), 0);
+    let mut resolved = vec![];
+    while let Some(event) = graph.next_resolved_orphan() {
+        resolved.push(event.hash().clone());
+    }
+    assert_eq!(resolved.len() + inserted_directly, to_push.len());
+    for event in &events {
+        assert!(graph.event(event.hash()).is_some());
+    }
+    // Ancestors are always resolved before descendants
+    for (i, hash) in resolved.iter().enumerate() {
+        for later in &resolved[i + 1..] {
+            assert!(!graph.is_ancestor(hash, later).unwrap());
+        }
+    }
+
+    // The pool is bounded
+    let mut graph = Graph::new(0, (), (), 999, MockSigner::new(), IncrementalClock::new());
+    graph.set_pending_pool(Some(2));
+    let mut evicted_all = vec![];
+    for event in to_push.iter().take(3).cloned() {
+        let (unsigned, signature) = event.into_parts();
+        let PushOutcome::Buffered { evicted, .. } =
+            graph.push_or_buffer(unsigned, signature).unwrap()
+        else {
+            panic!("the last events can't have parents yet");
+        };
+        evicted_all.extend(evicted);
+    }
+    assert_eq!(graph.pending_count(), 2);
+    assert_eq!(evicted_all, vec![to_push[0].hash().clone()]);
+    assert!(!graph.is_pending(to_push[0].hash()));
+    assert!(graph.is_pending(to_push[2].hash()));
+}
+
+#[test]
+fn orphans_wait_for_all_parents() {
+    let clock = ManualClock::new(0);
+    let mut graph = Graph::new(0, (), (), 999, MockSigner::new(), clock.clone());
+    graph.set_pending_limits(Some(PendingLimits {
+        max_age: Some(10),
+        ..PendingLimits::new(100)
+    }));
+    let signer = MockSigner::<MockPeerId, ()>::new();
+    let sign = |kind, author| {
+        SignedEvent::new((), kind, author, 0, |h| signer.sign(h))
+            .unwrap()
+            .into_parts()
+    };
+    let regular = |self_parent: &event::Hash, other_parent: &event::Hash| {
+        event::Kind::Regular(Parents {
+            self_parent: self_parent.clone(),
+            other_parent: other_parent.clone(),
+        })
+    };
+    let genesis_0 = graph.peer_genesis(&0).unwrap().clone();
+    let genesis_1 = sign(event::Kind::Genesis(()), 1);
+    let genesis_2 = sign(event::Kind::Genesis(()), 2);
+    let orphan = sign(regular(genesis_1.0.hash(), genesis_2.0.hash()), 1);
+    let orphan_hash = orphan.0.hash().clone();
+
+    assert_eq!(
+        graph.push_or_buffer(orphan.0, orphan.1).unwrap(),
+        PushOutcome::Buffered {
+            missing_parent: genesis_1.0.hash().clone(),
+            evicted: vec![]
+        }
+    );
+    // Still waits for the self parent
+    graph.push_or_buffer(genesis_2.0, genesis_2.1).unwrap();
+    assert!(graph.is_pending(&orphan_hash));
+    assert!(graph.next_resolved_orphan().is_none());
+    graph.push_or_buffer(genesis_1.0, genesis_1.1).unwrap();
+    assert_eq!(graph.pending_count(), 0);
+    assert_eq!(graph.next_resolved_orphan().unwrap().hash(), &orphan_hash);
+
+    // Expired by the graph's clock
+    let old = sign(regular(&event::Hash::from_array([3; 64]), &genesis_0), 3);
+    let old_hash = old.0.hash().clone();
+    graph.push_or_buffer(old.0, old.1).unwrap();
+    clock.advance(11);
+    let new = sign(regular(&event::Hash::from_array([4; 64]), &genesis_0), 4);
+    let PushOutcome::Buffered { evicted, .. } = graph.push_or_buffer(new.0, new.1).unwrap() else {
+        panic!("the self parent is unknown");
+    };
+    assert_eq!(evicted, vec![old_hash]);
+    assert_eq!(graph.pending_count(), 1);
+}
+
+#[test]
+fn local_identities_work() {
+    let mut graph = Graph::new(0, (), (), 999, MockSigner::new(), IncrementalClock::new());
+    assert_eq!(graph.self_tip(), graph.peer_genesis(&0));
+    let genesis_1 = graph
+        .add_local_identity(1, MockSigner::new(), (), ())
+        .unwrap();
+    assert!(matches!(
+        graph.add_local_identity(1, MockSigner::new(), (), ()),
+        Err(EventCreateError::IdentityAlreadyExists(1))
+    ));
+    assert!(matches!(
+        graph.add_local_identity(0, MockSigner::new(), (), ()),
+        Err(EventCreateError::IdentityAlreadyExists(0))
+    ));
+    assert_eq!(
+        graph.local_identities().copied().sorted().collect_vec(),
+        vec![0, 1]
+    );
+
+    let event_0 = graph.create_event((), genesis_1.clone()).unwrap();
+    assert_eq!(graph.self_tip(), Some(&event_0));
+    let event_1 = graph.create_event_as(&1, (), event_0.clone()).unwrap();
+    assert_eq!(graph.peer_latest_event(&1), Some(&event_1));
+    assert_eq!(graph.event(&event_1).unwrap().author(), &1);
+    // Others' tips don't affect ours
+    assert_eq!(graph.self_tip(), Some(&event_0));
+
+    assert!(matches!(
+        graph.create_event_as(&2, (), event_1),
+        Err(EventCreateError::NotLocalIdentity(2))
+    ));
+}
+
+#[test]
+fn strategies_used_for_choices() {
+    use crate::algorithm::strategy::{MostNewEvents, RoundRobin};
+
+    let mut graph = Graph::new(0, (), (), 999, MockSigner::new(), IncrementalClock::new());
+    assert!(graph.peer_candidates().is_empty());
+    assert_eq!(graph.choose_sync_peer(&mut RoundRobin::new()), None);
+    let created = graph.create_event_with((), &mut MostNewEvents).unwrap();
+    assert_eq!(
+        graph.event(&created).unwrap().kind(),
+        &event::Kind::Regular(Parents {
+            self_parent: graph.peer_genesis(&0).unwrap().clone(),
+            other_parent: graph.peer_genesis(&0).unwrap().clone(),
+        })
+    );
+
+    let TestSetup {
+        mut graph,
+        peers_events: peers,
+        names: _,
+        setup_name: _,
+    } = build_graph_from_paper((), 999).unwrap();
+    let candidates = graph.peer_candidates();
+    assert_eq!(candidates.len(), peers.len() - 1);
+    assert!(candidates.iter().all(|c| Some(c.peer) != graph.self_id()));
+    let best_other_parent = candidates
+        .iter()
+        .max_by_key(|c| c.unknown_to_us)
+        .unwrap()
+        .clone();
+    let best_sync_peer = candidates
+        .iter()
+        .max_by_key(|c| c.unknown_to_peer)
+        .unwrap()
+        .clone();
+    assert_eq!(
+        graph.choose_sync_peer(&mut MostNewEvents).as_ref(),
+        Some(best_sync_peer.peer)
+    );
+    let other_parent = best_other_parent.tip.clone();
+    let unknown_to_us = best_other_parent.unknown_to_us;
+    let self_known_before = graph.peer_index.get(&0).unwrap().known_events().len();
+    let created = graph.create_event_with((), &mut MostNewEvents).unwrap();
+    let event::Kind::Regular(parents) = graph.event(&created).unwrap().kind() else {
+        panic!("created event must be regular");
+    };
+    assert_eq!(parents.other_parent, other_parent);
+    assert_eq!(
+        graph.peer_index.get(&0).unwrap().known_events().len(),
+        self_known_before + unknown_to_us + 1
+    );
+}
+
+#[test]
+fn peer_candidates_ordered_deterministically() {
+    use crate::algorithm::strategy::RoundRobin;
+
+    let first = build_graph_from_paper((), 999).unwrap().graph;
+    let second = build_graph_from_paper((), 999).unwrap().graph;
+    let geneses: Vec<_> = first
+        .peer_candidates()
+        .iter()
+        .map(|c| first.peer_genesis(c.peer).unwrap().as_ref().to_owned())
+        .collect();
+    assert!(geneses.windows(2).all(|w| w[0] < w[1]));
+    let peers = |graph: &Graph<_, _, _, _, _>| -> Vec<MockPeerId> {
+        graph.peer_candidates().iter().map(|c| *c.peer).collect()
+    };
+    assert_eq!(peers(&first), peers(&second));
+
+    let (mut first_rr, mut second_rr) = (RoundRobin::new(), RoundRobin::new());
+    for _ in 0..8 {
+        assert_eq!(
+            first.choose_sync_peer(&mut first_rr),
+            second.choose_sync_peer(&mut second_rr)
+        );
+    }
+}
+
+#[test]
+fn dot_export_correct() {
+    use export::dot::DotOptions;
+
+    let setup = build_graph_detailed_example((), 999).unwrap();
+    let graph = &setup.graph;
+    let dot = graph.to_dot(&DotOptions::default());
+    assert!(dot.starts_with("digraph hashgraph {"));
+    assert!(dot.trim_end().ends_with('}'));
+    let regular_events = graph
+        .all_events
+        .values()
+        .filter(|e| matches!(e.kind(), event::Kind::Regular(_)))
+        .count();
+    assert_eq!(dot.matches(" -> ").count(), regular_events * 2);
+    assert_eq!(dot.matches("[style=dashed]").count(), regular_events);
+    assert_eq!(
+        dot.matches("subgraph cluster_").count(),
+        setup.peers_events.len()
+    );
+    for (hash, fame) in graph.witnesses.lock().unwrap().iter() {
+        let shape = match fame {
+            WitnessFamousness::Yes => "doublecircle",
+            WitnessFamousness::No => "circle",
+            WitnessFamousness::Undecided => "diamond",
+        };
+        let node_line = dot
+            .lines()
+            .find(|l| l.contains(&format!("\"{}\" [label", hash.to_hex())))
+            .unwrap();
+        assert!(node_line.contains(&format!("shape={},", shape)));
+    }
+    assert!(!dot.contains("color=red"));
+    // Output is deterministic
+    assert_eq!(dot, graph.fork().to_dot(&DotOptions::default()));
+
+    let setup = build_graph_fork([42, 1337, 80085].into_iter().cycle(), 999).unwrap();
+    let plain = setup.graph.to_dot(&DotOptions {
+        cluster_by_peer: false,
+        color_by_round: false,
+        highlight_forks: false,
+        show_timestamps: true,
+        filter: Default::default(),
+    });
+    assert!(!plain.contains("subgraph"));
+    assert!(!plain.contains("fillcolor=\"#"));
+    assert!(!plain.contains("color=red"));
+    let highlighted = setup.graph.to_dot(&DotOptions::default());
+    let forking_parents = setup
+        .graph
+        .all_events
+        .values()
+        .filter_map(|e| match &e.children.self_child {
+            event::SelfChild::ForkingParent(children) => Some(children.len()),
+            event::SelfChild::HonestParent(_) => None,
+        })
+        .sum::<usize>();
+    assert!(forking_parents > 0);
+    assert_eq!(highlighted.matches("color=red").count(), forking_parents);
+}
+
+#[test]
+fn json_export_roundtrips() {
+    use export::json::{JsonFame, JsonGraph, JSON_SCHEMA_VERSION};
+
+    let setup = build_graph_detailed_example((), 999).unwrap();
+    let graph = &setup.graph;
+    let json = graph.export_json();
+    let parsed: JsonGraph<MockPeerId> = JsonGraph::from_json(&json).unwrap();
+    assert_eq!(parsed.version, JSON_SCHEMA_VERSION);
+    assert_eq!(parsed.self_id.as_ref(), graph.self_id());
+    assert_eq!(parsed.events.len(), graph.all_events.len());
+    for exported in &parsed.events {
+        let hash = event::Hash::from_hex(&exported.hash).unwrap();
+        let info = graph.event_info(&hash).unwrap();
+        assert_eq!(exported.author, info.author);
+        assert_eq!(exported.round, info.round);
+        assert_eq!(exported.round_received, info.round_received);
+        assert_eq!(
+            exported.self_parent,
+            info.parents.as_ref().map(|p| p.self_parent.to_hex())
+        );
+        assert_eq!(exported.witness, info.witness.as_ref().map(JsonFame::from));
+    }
+    let mut finalized = vec![];
+    let mut graph = setup.graph;
+    while let Some(event) = graph.next_finalized_event() {
+        finalized.push(event.hash().to_hex());
+    }
+    assert!(!finalized.is_empty());
+    assert_eq!(parsed.ordering, finalized);
+    // Ordering doesn't depend on consumption of finalized events
+    assert_eq!(graph.export_json(), json);
+
+    // Streaming gives the same document
+    let mut streamed = vec![];
+    graph.write_json(&mut streamed).unwrap();
+    assert_eq!(
+        JsonGraph::<MockPeerId>::from_reader(&streamed[..]).unwrap(),
+        parsed
+    );
+}
+
+#[test]
+fn sync_jobs_applied() {
+    let source = build_graph_from_paper((), 999).unwrap().graph;
+    let mut target = Graph::new(0, (), (), 999, MockSigner::new(), IncrementalClock::new());
+    assert_eq!(target.peer_genesis(&0), source.peer_genesis(&0));
+    // Unknown peer gets everything
+    let jobs = source.generate_sync_for(&999).unwrap();
+    assert_eq!(jobs.as_linear().len(), source.all_events.len());
+    // Except for the known genesis
+    assert_eq!(
+        target.apply_sync_jobs(jobs).unwrap(),
+        source.all_events.len() - 1
+    );
+    assert_eq!(target.all_events.len(), source.all_events.len());
+    // Repeated sync doesn't change anything
+    let jobs = source.generate_sync_for(&999).unwrap();
+    assert_eq!(target.apply_sync_jobs(jobs).unwrap(), 0);
+}
+
+#[test]
+fn duplicates_dropped_before_verification() {
+    let source = build_graph_from_paper((), 999).unwrap().graph;
+    let mut target = Graph::new(0, (), (), 999, MockSigner::new(), IncrementalClock::new());
+    target.set_dedup_window(Some(4));
+    let jobs = source.generate_sync_for(&999).unwrap();
+    let known = jobs.as_linear().last().unwrap().clone();
+    target.apply_sync_jobs(jobs).unwrap();
+    // Signature of a known event is not looked at
+    let (unsigned, _) = known.into_parts();
+    let bad_signature = event::Signature(event::Hash::from_array([0u8; 64]));
+    assert!(matches!(
+        target.push_event(unsigned, bad_signature),
+        Err(PushError::EventAlreadyExists(_))
     ));
+    // Both the window and the graph lookups skip the copies
+    let jobs = source.generate_sync_for(&999).unwrap();
+    assert_eq!(target.apply_sync_jobs(jobs).unwrap(), 0);
+    assert_eq!(target.all_events.len(), source.all_events.len());
+}
+
+#[test]
+fn sync_requests_answered() {
+    let source = build_graph_detailed_example((), 999).unwrap().graph;
+    let mut target = Graph::new(0, (), (), 999, MockSigner::new(), IncrementalClock::new());
+    let jobs = source
+        .generate_sync_for_request(&target.sync_request())
+        .unwrap();
+    // Only the genesis is known from the summary
+    assert_eq!(jobs.as_linear().len(), source.all_events.len() - 1);
+    assert_eq!(
+        target.apply_sync_jobs(jobs).unwrap(),
+        source.all_events.len() - 1
+    );
+    let jobs = source
+        .generate_sync_for_request(&target.sync_request())
+        .unwrap();
+    assert!(jobs.as_linear().is_empty());
+    // Nothing to send back
+    let jobs = target
+        .generate_sync_for_request(&source.sync_request())
+        .unwrap();
+    assert!(jobs.as_linear().is_empty());
+}
+
+#[test]
+fn sync_requests_answered_together() {
+    let source = build_graph_detailed_example((), 999).unwrap().graph;
+    let empty = || Graph::new(0, (), (), 999, MockSigner::new(), IncrementalClock::new());
+    let mut halfway = empty();
+    let all_jobs = source
+        .generate_sync_for_request(&halfway.sync_request())
+        .unwrap();
+    let half = all_jobs.as_linear().len() / 2;
+    halfway
+        .apply_sync_jobs(all_jobs.into_chunks(half).remove(0))
+        .unwrap();
+    let requests = [
+        empty().sync_request(),
+        halfway.sync_request(),
+        source.sync_request(),
+    ];
+    let together = source.generate_sync_for_requests(&requests).unwrap();
+    assert_eq!(together.len(), requests.len());
+    for ((request, jobs), target) in
+        requests
+            .iter()
+            .zip(together)
+            .zip([empty(), halfway, source.fork()])
+    {
+        let alone = source.generate_sync_for_request(request).unwrap();
+        let as_set = |jobs: &sync::Jobs<_, _, _>| {
+            jobs.as_linear()
+                .iter()
+                .map(|e| e.hash().clone())
+                .collect::<HashSet<_>>()
+        };
+        assert_eq!(as_set(&jobs), as_set(&alone));
+        // Ordered so that they can be applied
+        let mut target = target;
+        assert_eq!(
+            target.apply_sync_jobs(jobs).unwrap(),
+            alone.as_linear().len()
+        );
+        assert_eq!(target.all_events.len(), source.all_events.len());
+    }
+}
+
+#[test]
+fn knowledge_matches_ancestry() {
+    use crate::testing::fixture;
+
+    let mut fork_branches_listed = false;
+    for example in [fixture::fork(), fixture::random_gossip()] {
+        let graph = example.build().unwrap().graph;
+        for tip in graph.all_events.keys() {
+            let knowledge = graph.knowledge_of(std::iter::once(tip));
+            fork_branches_listed |= knowledge.extra().next().is_some();
+            let ancestors: HashSet<_> = graph
+                .ancestor_iter(tip, 0)
+                .unwrap()
+                .map(|e| e.hash())
+                .collect();
+            for (hash, header) in &graph.headers {
+                assert_eq!(
+                    knowledge.knows(hash, &header.author, header.sequence),
+                    ancestors.contains(hash)
+                );
+            }
+        }
+    }
+    assert!(fork_branches_listed);
+}
+
+#[test]
+fn coin_strategies_decide() {
+    use crate::algorithm::core::CoinStrategy;
+    use crate::testing::{fixture, replay, GraphBuilder};
+
+    let example = fixture::random_gossip();
+    let reference = example.build().unwrap().graph;
+    let strategies = [
+        CoinStrategy::MiddleBit,
+        CoinStrategy::InjectedCoin { seed: 7 },
+        CoinStrategy::RoundRobinLeaderBit,
+    ];
+    for strategy in strategies {
+        // The first voting round of each election is a coin round
+        let mut graph = GraphBuilder::new(&example.peers[0], 0u64, (), 2)
+            .build()
+            .unwrap()
+            .graph;
+        graph.set_coin_strategy(strategy);
+        replay(&reference, &mut graph);
+        let latest = graph.round_index.len() - 1;
+        let decided = graph.last_decided_round().unwrap();
+        assert!(decided + 3 >= latest, "{strategy:?}: {decided} of {latest}");
+    }
+}
+
+#[test]
+fn round_iterators_match_index() {
+    use crate::testing::fixture;
+
+    let graph = fixture::random_gossip().build().unwrap().graph;
+    let last = graph.round_index.len() - 1;
+    let mut in_rounds: Vec<_> = (0..=last + 1)
+        .flat_map(|round| graph.events_in_round(round))
+        .collect();
+    in_rounds.sort();
+    let mut all: Vec<_> = graph.all_events.keys().collect();
+    all.sort();
+    assert_eq!(in_rounds, all);
+
+    let witnesses: Vec<_> = graph.witnesses_between(1, usize::MAX).collect();
+    assert!(witnesses.windows(2).all(|w| w[0].0 <= w[1].0));
+    assert_eq!(witnesses.last().unwrap().0, last);
+    for (round, hash) in &witnesses {
+        assert_eq!(graph.round_of(hash), *round);
+        assert!(graph.witnesses.lock().unwrap().contains_key(*hash));
+    }
+    let expected = graph
+        .all_events
+        .keys()
+        .filter(|h| graph.round_of(h) >= 1 && graph.witnesses.lock().unwrap().contains_key(*h));
+    assert_eq!(witnesses.len(), expected.count());
+    assert_eq!(graph.witnesses_between(2, 1).count(), 0);
+    assert_eq!(graph.witnesses_between(last + 1, last + 5).count(), 0);
+}
+
+#[test]
+fn diagram_exports_filtered() {
+    use export::{dot::DotOptions, ExportFilter};
+
+    let setup = build_graph_detailed_example((), 999).unwrap();
+    let graph = &setup.graph;
+    let regular_events = graph
+        .all_events
+        .values()
+        .filter(|e| matches!(e.kind(), event::Kind::Regular(_)))
+        .count();
+
+    let everything = ExportFilter::default();
+    let graphml = graph.to_graphml(&everything);
+    assert!(graphml.starts_with("<?xml"));
+    assert_eq!(graphml.matches("<node ").count(), graph.all_events.len());
+    assert_eq!(graphml.matches("<edge ").count(), regular_events * 2);
+    assert_eq!(
+        graphml.matches(r#"<data key="witness">"#).count(),
+        graph.witnesses.lock().unwrap().len()
+    );
+    let mermaid = graph.to_mermaid(&everything);
+    assert!(mermaid.starts_with("flowchart BT"));
+    assert_eq!(mermaid.matches(" --> ").count(), regular_events);
+    assert_eq!(mermaid.matches(" -.-> ").count(), regular_events);
+
+    // Filtering keeps only edges between included events
+    let peer_a = setup.peers_events.get("a").unwrap();
+    let filter = ExportFilter::default()
+        .with_rounds(0..=1)
+        .with_peers([peer_a.id]);
+    let included = graph
+        .all_events
+        .keys()
+        .filter(|h| graph.round_of(h) <= 1 && graph.event(h).unwrap().author() == &peer_a.id)
+        .count();
+    assert!(included > 1);
+    assert!(included < peer_a.events.len() + 1);
+    let graphml = graph.to_graphml(&filter);
+    assert_eq!(graphml.matches("<node ").count(), included);
+    // Only the self parent chain remains
+    assert_eq!(graphml.matches("<edge ").count(), included - 1);
+    assert!(!graphml.contains(">other</data>"));
+    let mermaid = graph.to_mermaid(&filter);
+    assert_eq!(mermaid.matches(" --> ").count(), included - 1);
+    assert_eq!(mermaid.matches(" -.-> ").count(), 0);
+    let dot = graph.to_dot(&DotOptions {
+        filter,
+        ..Default::default()
+    });
+    assert_eq!(dot.matches(" -> ").count(), included - 1);
+
+    let setup = build_graph_fork([42, 1337, 80085].into_iter().cycle(), 999).unwrap();
+    let mermaid = setup.graph.to_mermaid(&ExportFilter::default());
+    assert!(mermaid.contains("class ") && mermaid.contains(" fork"));
+}
+
+#[test]
+fn timings_exported_to_csv() {
+    use export::ExportFilter;
+
+    let source = build_graph_detailed_example((), 999).unwrap().graph;
+    let clock = ManualClock::new(0);
+    let mut target = Graph::new(0, (), (), 999, MockSigner::new(), clock.clone());
+    target.set_collect_timings(true);
+    for event in source.generate_sync_for(&999).unwrap().into_linear() {
+        clock.advance(10);
+        let (unsigned, signature) = event.into_parts();
+        match target.push_event(unsigned, signature) {
+            Ok(()) | Err(PushError::EventAlreadyExists(_)) => (),
+            Err(e) => panic!("{}", e),
+        }
+    }
+    let own_genesis = target.peer_genesis(&0).unwrap().clone();
+    // Inserted before collection was enabled
+    assert!(target.event_timings(&own_genesis).is_none());
+    let ordered: Vec<_> = target.ordering.ordered().cloned().collect();
+    assert!(!ordered.is_empty());
+    for hash in &ordered {
+        if hash == &own_genesis {
+            continue;
+        }
+        let timings = target.event_timings(hash).unwrap();
+        assert!(timings.finalized_at.unwrap() >= timings.inserted_at);
+    }
+
+    let csv = target.export_csv(&ExportFilter::default());
+    let mut lines = csv.lines();
+    assert!(lines.next().unwrap().starts_with("hash,author,"));
+    let rows: Vec<Vec<&str>> = lines.map(|l| l.split(',').collect()).collect();
+    assert_eq!(rows.len(), target.all_events.len());
+    assert!(rows.iter().all(|r| r.len() == 8));
+    let finalized = rows.iter().filter(|r| !r[7].is_empty()).count();
+    assert_eq!(finalized, ordered.len() - 1);
+}
+
+#[test]
+fn query_finds_events() {
+    let setup = build_graph_detailed_example(0, 999).unwrap();
+    let graph = &setup.graph;
+    let peers = &setup.peers_events;
+    let sorted = |q: Vec<&event::Hash>| {
+        let mut q: Vec<_> = q.into_iter().cloned().collect();
+        q.sort();
+        q
+    };
+
+    assert_eq!(graph.query().iter().count(), graph.all_events.len());
+    let a = peers.get("a").unwrap();
+    let mut a_events = a.events.clone();
+    a_events.sort();
+    assert_eq!(
+        sorted(graph.query().by_author(a.id).iter().collect()),
+        a_events
+    );
+    assert_eq!(
+        graph.query().in_rounds(1..=2).iter().count(),
+        graph.round_index[1].len() + graph.round_index[2].len()
+    );
+    assert_eq!(
+        graph
+            .query()
+            .in_rounds(1..=2)
+            .in_rounds(0..=0)
+            .iter()
+            .count(),
+        0
+    );
+    assert_eq!(graph.query().payload_matches(|p| *p != 0).iter().count(), 0);
+
+    // Ancestry relations
+    let b = peers.get("b").unwrap();
+    let d = peers.get("d").unwrap();
+    let (x, y) = (&d.events[1], &b.events[1]);
+    let found = sorted(
+        graph
+            .query()
+            .sees(x.clone())
+            .not_sees(y.clone())
+            .iter()
+            .collect(),
+    );
+    assert!(found.contains(x));
+    assert!(!found.contains(y));
+    for hash in &found {
+        assert!(graph.see(hash, x).unwrap());
+        assert!(!graph.see(hash, y).unwrap());
+    }
+    let ancestors = sorted(graph.query().seen_by(y.clone()).iter().collect());
+    assert!(ancestors.contains(x) && ancestors.contains(y));
+    let others = graph.query().not_seen_by(y.clone()).iter().count();
+    assert_eq!(ancestors.len() + others, graph.all_events.len());
+    // Unknown events match nothing
+    assert_eq!(
+        graph
+            .query()
+            .not_sees(event::Hash::from_hex(&"00".repeat(64)).unwrap())
+            .iter()
+            .count(),
+        0
+    );
 }
 
 // Test graph properties
@@ -199,7 +1176,7 @@ fn missing_parent_fails() {
 fn test_ancestor() {
     run_tests!(
         tested_function_name => "ancestor",
-        tested_function => |g, (e1, e2)| g.is_ancestor(&e1, &e2),
+        tested_function => |g, (e1, e2)| g.is_ancestor(e1, e2).unwrap(),
         name_lookup => |names, (e1, e2)| format!("({}, {})", names.get(e1).unwrap(), names.get(e2).unwrap()),
         peers_literal => peers,
         tests => [
@@ -408,7 +1385,7 @@ fn test_ancestor_iter() {
 fn test_strongly_see() {
     run_tests!(
         tested_function_name => "strongly_see",
-        tested_function => |graph, (e1, e2)| graph.strongly_see(e1, e2),
+        tested_function => |graph, (e1, e2)| graph.strongly_see(e1, e2).unwrap(),
         name_lookup => |names, (e1, e2)| format!("({}, {})", names.get(e1).unwrap(), names.get(e2).unwrap()),
         peers_literal => peers,
         tests => [
@@ -520,7 +1497,7 @@ fn test_strongly_see() {
 fn test_determine_round() {
     run_tests!(
         tested_function_name => "round",
-        tested_function => |g, args| g.round_of(&args),
+        tested_function => |g, args| g.round_of(args),
         name_lookup => |names, event| names.get(event).unwrap().to_owned(),
         peers_literal => peers,
         tests => [
@@ -660,7 +1637,7 @@ fn test_round_indices_consistent() {
     }
     run_tests!(
         tested_function_name => "round_index_consistency",
-        tested_function => |g, args| round_index_consistent(g, &args),
+        tested_function => |g, args| round_index_consistent(g, args),
         name_lookup => |names, event| names.get(event).unwrap().to_owned(),
         peers_literal => peers,
         tests => [
@@ -771,11 +1748,32 @@ fn test_round_indices_consistent() {
     );
 }
 
+#[test]
+fn witness_status_stored_on_insertion() {
+    let TestSetup {
+        mut graph, names, ..
+    } = build_graph_detailed_example((), 999).unwrap();
+    let stored: Vec<_> = names
+        .keys()
+        .map(|hash| (hash, graph.determine_witness(hash).unwrap()))
+        .collect();
+    let witnesses_before = graph.witnesses.lock().unwrap().clone();
+    for (hash, is_witness) in stored {
+        assert_eq!(
+            graph.recompute_witness(hash),
+            Ok(is_witness),
+            "{}",
+            names[hash]
+        );
+    }
+    assert_eq!(*graph.witnesses.lock().unwrap(), witnesses_before);
+}
+
 #[test]
 fn test_determine_witness() {
     run_tests!(
         tested_function_name => "determine_witness",
-        tested_function => |graph, event| graph.determine_witness(&event).expect(&format!("Can't find event {:?}", event)),
+        tested_function => |graph, event| graph.determine_witness(event).unwrap_or_else(|_| panic!("Can't find event {:?}", event)),
         name_lookup => |names, event| names.get(event).unwrap().to_owned(),
         peers_literal => peers,
         tests => [
@@ -783,11 +1781,9 @@ fn test_determine_witness() {
                 setup => build_graph_some_chain((), 999).unwrap(),
                 test_case => (
                     expect: false,
-                    arguments: vec![
-                        &peers.get("g1").unwrap().events[1..2],
+                    arguments: [&peers.get("g1").unwrap().events[1..2],
                         &peers.get("g2").unwrap().events[1..3],
-                        &peers.get("g3").unwrap().events[1..2]
-                    ].iter()
+                        &peers.get("g3").unwrap().events[1..2]].iter()
                         .flat_map(|s| s.iter().collect::<Vec<&_>>())
                         .collect()
                 ),
@@ -807,13 +1803,11 @@ fn test_determine_witness() {
                 setup => build_graph_from_paper((), 999).unwrap(),
                 test_case => (
                     expect: false,
-                    arguments: vec![
-                        &peers.get("a").unwrap().events[1..],
+                    arguments: [&peers.get("a").unwrap().events[1..],
                         &peers.get("b").unwrap().events[1..],
                         &peers.get("c").unwrap().events[1..5],
                         &peers.get("d").unwrap().events[1..],
-                        &peers.get("e").unwrap().events[1..]
-                    ].iter()
+                        &peers.get("e").unwrap().events[1..]].iter()
                         .flat_map(|s| s.iter().collect::<Vec<&_>>())
                         .collect()
                 ),
@@ -876,7 +1870,7 @@ fn test_determine_witness() {
 fn test_is_famous_witness() {
     run_tests!(
         tested_function_name => "fame",
-        tested_function => |g, event| g.is_famous_witness(&event),
+        tested_function => |g, event| g.is_famous_witness(event),
         name_lookup => |names, event| names.get(event).unwrap().to_owned(),
         peers_literal => peers,
         tests => [
@@ -919,13 +1913,11 @@ fn test_is_famous_witness() {
                 ),
                 test_case => (
                     expect: Err(WitnessCheckError::NotWitness),
-                    arguments: vec![
-                        &peers.get("a").unwrap().events[1..],
+                    arguments: [&peers.get("a").unwrap().events[1..],
                         &peers.get("b").unwrap().events[1..],
                         &peers.get("c").unwrap().events[1..5],
                         &peers.get("d").unwrap().events[1..],
-                        &peers.get("e").unwrap().events[1..]
-                    ].iter()
+                        &peers.get("e").unwrap().events[1..]].iter()
                         .flat_map(|s| s.iter().collect::<Vec<&_>>())
                         .collect(),
                 ),
@@ -987,7 +1979,7 @@ fn test_is_famous_witness() {
 fn test_is_unique_famous_witness() {
     run_tests!(
         tested_function_name => "uniqueness + fame",
-        tested_function => |g, event| g.is_unique_famous_witness(&event),
+        tested_function => |g, event| g.is_unique_famous_witness(event),
         name_lookup => |names, event| names.get(event).unwrap().to_owned(),
         peers_literal => peers,
         tests => [
@@ -1030,13 +2022,11 @@ fn test_is_unique_famous_witness() {
                 ),
                 test_case => (
                     expect: Err(WitnessCheckError::NotWitness),
-                    arguments: vec![
-                        &peers.get("a").unwrap().events[1..],
+                    arguments: [&peers.get("a").unwrap().events[1..],
                         &peers.get("b").unwrap().events[1..],
                         &peers.get("c").unwrap().events[1..5],
                         &peers.get("d").unwrap().events[1..],
-                        &peers.get("e").unwrap().events[1..]
-                    ].iter()
+                        &peers.get("e").unwrap().events[1..]].iter()
                         .flat_map(|s| s.iter().collect::<Vec<&_>>())
                         .collect(),
                 ),
@@ -1172,7 +2162,7 @@ fn test_is_round_decided() {
 fn test_ordering_decided() {
     run_tests!(
         tested_function_name => "ordering_data decided",
-        tested_function => |g, event| matches!(g.ordering_data(*event), Ok(_)),
+        tested_function => |g, event| g.ordering_data(event).is_ok(),
         name_lookup => |names, event| names.get(event).unwrap().to_owned(),
         peers_literal => peers,
         tests => [
@@ -1217,7 +2207,7 @@ fn test_ordering_data_correct() {
     let signer = MockSigner::<u64, ()>::new();
     run_tests!(
         tested_function_name => "ordering_data correct values",
-        tested_function => |g, event| g.ordering_data(*event),
+        tested_function => |g, event| g.ordering_data(event),
         name_lookup => |names, event| names.get(event).unwrap().to_owned(),
         peers_literal => peers,
         tests => [
@@ -1347,6 +2337,48 @@ fn test_ordering_data_correct() {
     );
 }
 
+#[test]
+fn event_info_matches_internals() {
+    let TestSetup {
+        graph,
+        peers_events: _,
+        names,
+        setup_name: _,
+    } = build_graph_detailed_example((), 999).unwrap();
+    for (hash, name) in &names {
+        let info = graph.event_info(hash).unwrap();
+        let event = graph.event(hash).unwrap();
+        assert_eq!(&info.hash, hash);
+        assert_eq!(&info.author, event.author());
+        assert_eq!(&info.timestamp, event.timestamp());
+        assert_eq!(&info.children, &event.children);
+        assert_eq!(info.round, graph.round_of(hash), "round of {}", name);
+        assert_eq!(
+            info.witness.is_some(),
+            graph.determine_witness(hash).unwrap(),
+            "witness status of {}",
+            name
+        );
+        assert_eq!(
+            info.round_received,
+            graph.ordering_data(hash).ok().map(|(r, _, _)| r),
+            "round received of {}",
+            name
+        );
+        match event.kind() {
+            event::Kind::Genesis(_) => assert_eq!(info.parents, None),
+            event::Kind::Regular(p) => assert_eq!(info.parents.as_ref(), Some(p)),
+        }
+    }
+    assert!(names
+        .keys()
+        .any(|h| graph.event_info(h).unwrap().round_received.is_some()));
+    assert_eq!(
+        graph.event_info(&event::Hash::from_hex(&"00".repeat(64)).unwrap()),
+        None
+    );
+}
+
 fn check_recognized_events(setup: TestSetup<(), (), u64>) {
     let TestSetup {
         mut graph,
@@ -1372,7 +2404,7 @@ fn check_recognized_events(setup: TestSetup<(), (), u64>) {
             .map(move |h_prev| (h, h_prev))
     }) {
         // this event must not be an ancestor of all previous
-        assert!(!graph.is_ancestor(h_prev, h), "recognized events must be partially ordered by ancestry relation; {} is an ancestor of {} but placed before in the order", names.get(h).unwrap(), names.get(h_prev).unwrap());
+        assert!(!graph.is_ancestor(h_prev, h).unwrap(), "recognized events must be partially ordered by ancestry relation; {} is an ancestor of {} but placed before in the order", names.get(h).unwrap(), names.get(h_prev).unwrap());
     }
 }
 
@@ -1482,7 +2514,7 @@ fn test_sync_data_correct() {
     ];
     for (peer_name, expected_events) in tests {
         test_topsort(&setup, peer_name, expected_events)
-            .expect(&format!("sync for peer {} failed", peer_name));
+            .unwrap_or_else(|_| panic!("sync for peer {} failed", peer_name));
     }
 
     let setup = build_graph_some_chain((), 999).unwrap();
@@ -1496,7 +2528,7 @@ fn test_sync_data_correct() {
     ];
     for (peer_name, expected_events) in tests {
         test_topsort(&setup, peer_name, expected_events)
-            .expect(&format!("sync for peer {} failed", peer_name));
+            .unwrap_or_else(|_| panic!("sync for peer {} failed", peer_name));
     }
 
     let setup = build_graph_detailed_example((), 999).unwrap();
@@ -1525,13 +2557,13 @@ fn test_sync_data_correct() {
     ];
     for (peer_name, expected_events) in tests {
         test_topsort(&setup, peer_name, expected_events)
-            .expect(&format!("sync for peer {} failed", peer_name));
+            .unwrap_or_else(|_| panic!("sync for peer {} failed", peer_name));
     }
 
     let setup = build_graph_fork([42, 1337, 80085].into_iter().cycle(), 999).unwrap();
     let tests = vec![("a", vec![]), ("m", vec![PeerEventsSince::new("a", 4)])];
     for (peer_name, expected_events) in tests {
         test_topsort(&setup, peer_name, expected_events)
-            .expect(&format!("sync for peer {} failed", peer_name));
+            .unwrap_or_else(|_| panic!("sync for peer {} failed", peer_name));
     }
 }
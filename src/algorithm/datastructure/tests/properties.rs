@@ -0,0 +1,231 @@
+//! Properties that must hold for any valid hashgraph. Graphs are generated
+//! from random gossip schedules: each step some peer authors an event on top
+//! of its own latest event and the latest event of another peer.
+
+use proptest::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::*;
+use crate::algorithm::IncrementalClock;
+use crate::testing::fixture::Fixture;
+use crate::testing::shrink::{builder_snippet, replicas_disagree, shrink};
+
+type PropGraph = Graph<u64, (), u64, MockSigner<u64, ()>, IncrementalClock>;
+
+const MAX_PEERS: usize = 5;
+const MAX_STEPS: usize = 120;
+
+#[derive(Debug, Clone)]
+struct Schedule {
+    peers: usize,
+    /// Author and the peer whose latest event is the other parent
+    steps: Vec<(usize, usize)>,
+}
+
+impl Schedule {
+    /// Events of the schedule in the order of authoring, geneses first
+    fn events(&self) -> Vec<SignedEvent<u64, (), u64>> {
+        let sign = |h: &event::Hash| MockSigner::<u64, ()>::new().sign(h);
+        let mut events = vec![];
+        let mut tips = vec![];
+        for peer in 0..self.peers {
+            // Same as the genesis created by `Graph::new`
+            let genesis =
+                SignedEvent::new(0, event::Kind::Genesis(()), peer as u64, 0, sign).unwrap();
+            tips.push(genesis.hash().clone());
+            events.push(genesis);
+        }
+        for (step, &(author, other)) in self.steps.iter().enumerate() {
+            let event = SignedEvent::new(
+                step as u64 + 1,
+                event::Kind::Regular(Parents {
+                    self_parent: tips[author].clone(),
+                    other_parent: tips[other].clone(),
+                }),
+                author as u64,
+                step as u128 + 1,
+                sign,
+            )
+            .unwrap();
+            tips[author] = event.hash().clone();
+            events.push(event);
+        }
+        events
+    }
+}
+
+fn schedule() -> impl Strategy<Value = Schedule> {
+    (2..=MAX_PEERS).prop_flat_map(|peers| {
+        let step = (0..peers, 1..peers)
+            .prop_map(move |(author, offset)| (author, (author + offset) % peers));
+        proptest::collection::vec(step, 0..MAX_STEPS)
+            .prop_map(move |steps| Schedule { peers, steps })
+    })
+}
+
+fn replica(self_id: u64) -> PropGraph {
+    Graph::new(
+        self_id,
+        0,
+        (),
+        999,
+        MockSigner::new(),
+        IncrementalClock::new(),
+    )
+}
+
+/// Replica with frequent coin rounds flipping coins by `coin_seed`
+fn replica_with_coin(self_id: u64, coin_seed: u64) -> PropGraph {
+    let mut graph = Graph::new(
+        self_id,
+        0,
+        (),
+        3,
+        MockSigner::new(),
+        IncrementalClock::new(),
+    );
+    graph.set_coin_seed(Some(coin_seed));
+    graph
+}
+
+/// Push `events` in the given order, skipping the own genesis
+fn push_all<'a>(
+    graph: &mut PropGraph,
+    events: impl IntoIterator<Item = &'a SignedEvent<u64, (), u64>>,
+) {
+    for event in events {
+        let (unsigned, signature) = event.clone().into_parts();
+        match graph.push_event(unsigned, signature) {
+            Ok(()) | Err(PushError::EventAlreadyExists(_)) => (),
+            Err(e) => panic!("valid event is rejected: {}", e),
+        }
+    }
+}
+
+/// Random order of `events` in which parents still come before children.
+/// Geneses stay first, since membership is expected to be known from the
+/// start.
+fn shuffled_topologically(
+    events: &[SignedEvent<u64, (), u64>],
+    peers: usize,
+    seed: u64,
+) -> Vec<&SignedEvent<u64, (), u64>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut result: Vec<_> = events[..peers].iter().collect();
+    let mut pushed: HashSet<_> = result.iter().map(|e| e.hash().clone()).collect();
+    let mut remaining: Vec<_> = events[peers..].iter().collect();
+    while !remaining.is_empty() {
+        let ready: Vec<_> = remaining
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| match e.unsigned().fields().kind() {
+                event::Kind::Genesis(_) => unreachable!("geneses are pushed first"),
+                event::Kind::Regular(parents) => {
+                    pushed.contains(&parents.self_parent) && pushed.contains(&parents.other_parent)
+                }
+            })
+            .map(|(i, _)| i)
+            .collect();
+        let next = remaining.swap_remove(ready[rng.gen_range(0..ready.len())]);
+        pushed.insert(next.hash().clone());
+        result.push(next);
+    }
+    result
+}
+
+fn decided_fame(graph: &PropGraph) -> HashMap<event::Hash, WitnessFamousness> {
+    graph
+        .witnesses
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, fame)| **fame != WitnessFamousness::Undecided)
+        .map(|(hash, fame)| (hash.clone(), fame.clone()))
+        .collect()
+}
+
+/// Decided fame and finalized events of the replicas don't contradict
+fn check_agreement(in_order: &PropGraph, shuffled: &PropGraph) -> Result<(), TestCaseError> {
+    let fame_in_order = decided_fame(in_order);
+    let fame_shuffled = decided_fame(shuffled);
+    for (witness, fame) in fame_in_order.iter() {
+        if let Some(other) = fame_shuffled.get(witness) {
+            prop_assert_eq!(fame, other, "reproducer:\n{}", reproducer(in_order));
+        }
+    }
+    let order_in_order: Vec<_> = in_order.ordering.ordered().collect();
+    let order_shuffled: Vec<_> = shuffled.ordering.ordered().collect();
+    let common = order_in_order.len().min(order_shuffled.len());
+    prop_assert_eq!(
+        &order_in_order[..common],
+        &order_shuffled[..common],
+        "reproducer:\n{}",
+        reproducer(in_order)
+    );
+    Ok(())
+}
+
+/// Builder code of the smallest found part of the graph on which replicas
+/// disagree
+fn reproducer(graph: &PropGraph) -> String {
+    let scenario = Fixture::from_graph("disagreeing replicas", graph);
+    if !replicas_disagree(&scenario) {
+        return "(does not reproduce without payloads)".to_owned();
+    }
+    builder_snippet(&shrink(&scenario, replicas_disagree))
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn rounds_monotone_along_self_parents(schedule in schedule()) {
+        let events = schedule.events();
+        let mut graph = replica(0);
+        push_all(&mut graph, &events);
+        for event in &events[schedule.peers..] {
+            let event::Kind::Regular(parents) = event.unsigned().fields().kind() else {
+                unreachable!("geneses come first");
+            };
+            prop_assert!(graph.round_of(event.hash()) >= graph.round_of(&parents.self_parent));
+        }
+    }
+
+    #[test]
+    fn fame_unanimous_across_replicas(schedule in schedule(), seed in any::<u64>()) {
+        let events = schedule.events();
+        let mut in_order = replica(0);
+        push_all(&mut in_order, &events);
+        let mut shuffled = replica(1);
+        push_all(&mut shuffled, shuffled_topologically(&events, schedule.peers, seed));
+        check_agreement(&in_order, &shuffled)?;
+    }
+
+    #[test]
+    fn seeded_coin_unanimous_across_replicas(
+        schedule in schedule(),
+        seed in any::<u64>(),
+        coin_seed in any::<u64>(),
+    ) {
+        let events = schedule.events();
+        let mut in_order = replica_with_coin(0, coin_seed);
+        push_all(&mut in_order, &events);
+        let mut shuffled = replica_with_coin(1, coin_seed);
+        push_all(&mut shuffled, shuffled_topologically(&events, schedule.peers, seed));
+        check_agreement(&in_order, &shuffled)?;
+    }
+
+    #[test]
+    fn ordering_prefix_stable(schedule in schedule()) {
+        let events = schedule.events();
+        let mut graph = replica(0);
+        let mut previous: Vec<event::Hash> = vec![];
+        for event in &events {
+            push_all(&mut graph, [event]);
+            let current: Vec<_> = graph.ordering.ordered().cloned().collect();
+            prop_assert!(current.starts_with(&previous));
+            previous = current;
+        }
+    }
+}
@@ -204,17 +204,17 @@ pub(crate) struct Test<TPayload, TGenesisPayload, TPeerId, TResult, TArg> {
 ///
 /// # Arguments
 /// * `cases`: List of test cases. Each list entry consists of graph (with
-/// helper data structures, see [`TestGraph`](TestGraph<T, ()>)) and test cases
-/// for the graph. The graph cases are grouped by result expected. For each
-/// result there is a list of arguments to be supplied to `tested_function`.
+///   helper data structures, see [`TestGraph`](TestGraph<T, ()>)) and test cases
+///   for the graph. The graph cases are grouped by result expected. For each
+///   result there is a list of arguments to be supplied to `tested_function`.
 ///
 /// * `tested_function_name`: name of the function, used for assert messages.
 ///
 /// * `tested_function`: function to test, takes 2 arguments: graph itself and
-/// argument specified in each test case.
+///   argument specified in each test case.
 ///
 /// * `name_lookup`: function for obtaining event name based on corresponding
-/// `HashMap` and argument of the test case, used for better readable assert messages.
+///   `HashMap` and argument of the test case, used for better readable assert messages.
 ///
 /// # Example
 /// Suppose we want to check correctness of round calculation:
@@ -336,7 +336,7 @@ pub mod topsort {
 
         // First we need to verify that the set of events is the same,
         // then we can check the ordering itself
-        let tested_set = HashSet::<_>::from_iter(tested_topsort.clone().into_iter());
+        let tested_set = HashSet::<_>::from_iter(tested_topsort.clone());
         if tested_set != expected_events {
             return Err(format!(
                 "Events, missing in result: {}; unexpected events: {}",
@@ -373,7 +373,7 @@ pub mod topsort {
             }
             events_before.insert(next);
         }
-        return Ok(());
+        Ok(())
     }
 
     pub struct PeerEventsSince {
@@ -412,17 +412,17 @@ pub mod topsort {
         verify_topsort(
             sync_for
                 .as_linear()
-                .into_iter()
+                .iter()
                 .map(|e| e.hash().clone())
                 .collect(),
             HashSet::<_>::from_iter(
                 expected_events
                     .into_iter()
                     .map(|e| &peers.get(e.peer_name).unwrap().events[e.event_since_number..])
-                    .flat_map(|s| s.iter().cloned().collect::<Vec<event::Hash>>()),
+                    .flat_map(|s| s.to_vec()),
             ),
-            &graph,
-            Some(&names),
+            graph,
+            Some(names),
         )
     }
 }
@@ -0,0 +1,172 @@
+//! Where consensus timestamps come from.
+//!
+//! Events with the same round received are sorted by consensus timestamp
+//! (and whitened signature on ties). The timestamp is computed from the
+//! [`Receiver`]s of the event by a [`TimestampStrategy`]:
+//! - [`MedianTimestamp`] (the default) takes the median of the authors'
+//!   timestamps, as in the paper. It is correct while more than 2/3 of the
+//!   members are honest, but honest clocks still have to be roughly in sync;
+//! - [`StructuralTimestamp`] ignores the authors' clocks and takes the
+//!   median round of the receivers, i.e. how early the network saw the
+//!   event. Coarser, ties are broken by whitened signatures.
+//!
+//! All members must use the same strategy, otherwise their orders differ.
+
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use super::Graph;
+use crate::algorithm::RoundNum;
+use crate::Timestamp;
+
+/// Self ancestor of a unique famous witness of the round received through
+/// which the witness first saw the event being ordered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Receiver {
+    pub timestamp: Timestamp,
+    pub round: RoundNum,
+}
+
+pub trait TimestampStrategy: Send + Sync {
+    /// `receivers` is not empty, one for each unique famous witness in no
+    /// particular order
+    fn consensus_timestamp(&self, receivers: &[Receiver]) -> Timestamp;
+}
+
+/// Median of the receivers' timestamps
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MedianTimestamp;
+
+impl TimestampStrategy for MedianTimestamp {
+    fn consensus_timestamp(&self, receivers: &[Receiver]) -> Timestamp {
+        // Note that we assume a supermajority of honest members and
+        // the median is taken here. Thus this median value will always be
+        // in range of honest timestamps.
+        median(receivers.iter().map(|r| r.timestamp))
+    }
+}
+
+/// Median of the receivers' rounds, see the [module docs](self)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StructuralTimestamp;
+
+impl TimestampStrategy for StructuralTimestamp {
+    fn consensus_timestamp(&self, receivers: &[Receiver]) -> Timestamp {
+        median(receivers.iter().map(|r| r.round as Timestamp))
+    }
+}
+
+fn median(values: impl Iterator<Item = Timestamp>) -> Timestamp {
+    let mut values: Vec<_> = values.collect();
+    values.sort();
+    *values
+        .get(values.len() / 2)
+        .expect("there must be some unique famous witnesses in a round")
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("Events are already ordered with the previous timestamp strategy")]
+pub struct AlreadyOrdered;
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+{
+    /// Compute consensus timestamps with `strategy`. Can only be changed
+    /// before any event is finalized.
+    pub fn set_timestamp_strategy(
+        &mut self,
+        strategy: Arc<dyn TimestampStrategy>,
+    ) -> Result<(), AlreadyOrdered> {
+        if self.ordering.len() > 0 {
+            return Err(AlreadyOrdered);
+        }
+        self.ordering_data_cache.lock().unwrap().clear();
+        self.timestamp_strategy = strategy;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fixture, GraphBuilder, TestGraph};
+
+    /// Graph of the fixture's first member with the events pushed after
+    /// setting the strategy
+    fn with_strategy(
+        strategy: Arc<dyn TimestampStrategy>,
+    ) -> (TestGraph<(), u64>, TestGraph<(), u64>) {
+        let example = fixture::detailed_example();
+        let source = example.build().unwrap().graph;
+        let mut graph = GraphBuilder::new(&example.peers[0], 0u64, (), example.coin_frequency)
+            .build()
+            .unwrap()
+            .graph;
+        graph.set_timestamp_strategy(strategy).unwrap();
+        let jobs = source
+            .generate_sync_for_request(&graph.sync_request())
+            .unwrap();
+        graph.apply_sync_jobs(jobs).unwrap();
+        (graph, source)
+    }
+
+    #[test]
+    fn structural_orders_by_rounds() {
+        let (graph, source) = with_strategy(Arc::new(StructuralTimestamp));
+        let ordered: Vec<_> = graph.ordering.ordered().cloned().collect();
+        assert_eq!(ordered.len(), source.ordering.len());
+        let mut previous = (0, 0);
+        for hash in &ordered {
+            // Same rounds received, only the order within them may differ
+            let round_received = graph.event_info(hash).unwrap().round_received;
+            assert_eq!(
+                round_received,
+                source.event_info(hash).unwrap().round_received
+            );
+            let explanation = graph.ordering_explanation(hash).unwrap();
+            let mut rounds: Vec<_> = explanation
+                .timestamp_inputs
+                .iter()
+                .map(|input| input.round as Timestamp)
+                .collect();
+            rounds.sort();
+            assert_eq!(explanation.consensus_timestamp, rounds[rounds.len() / 2]);
+            let key = (explanation.round_received, explanation.consensus_timestamp);
+            assert!(previous <= key);
+            previous = key;
+        }
+    }
+
+    #[test]
+    fn structural_ignores_clocks() {
+        let receivers = |skewed: Timestamp| {
+            [(skewed, 3), (20, 4), (30, 4)].map(|(timestamp, round)| Receiver { timestamp, round })
+        };
+        for skewed in [0, 10, u128::MAX] {
+            assert_eq!(
+                StructuralTimestamp.consensus_timestamp(&receivers(skewed)),
+                4
+            );
+        }
+        assert_eq!(
+            MedianTimestamp.consensus_timestamp(&receivers(u128::MAX)),
+            30
+        );
+    }
+
+    #[test]
+    fn median_is_default() {
+        let (graph, source) = with_strategy(Arc::new(MedianTimestamp));
+        assert!(graph.ordering.ordered().eq(source.ordering.ordered()));
+    }
+
+    #[test]
+    fn locked_after_ordering() {
+        let (mut graph, _) = with_strategy(Arc::new(MedianTimestamp));
+        assert_eq!(
+            graph.set_timestamp_strategy(Arc::new(StructuralTimestamp)),
+            Err(AlreadyOrdered)
+        );
+    }
+}
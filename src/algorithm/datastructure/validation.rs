@@ -0,0 +1,125 @@
+//! Checks of an event that don't need the graph structure: hash, timestamp,
+//! signature and content. They only borrow a few parts of the graph, so
+//! they can run away from it (see `concurrent` and [`Graph::preverify`]).
+//!
+//! [`Graph::preverify`]: super::Graph::preverify
+
+use super::content::ContentFilter;
+use crate::algorithm::event::{self, SignedEvent, UnsignedEvent};
+use crate::algorithm::{PushError, Signature, Signer};
+use crate::Timestamp;
+
+pub(super) struct Validator<'a, TPayload, TSigner> {
+    pub signer: &'a TSigner,
+    pub content_filter: Option<&'a ContentFilter<TPayload>>,
+    /// Latest timestamp accepted, if the clock skew is limited
+    pub max_timestamp: Option<(Timestamp, Timestamp)>,
+}
+
+/// Event that passed the checks, to be inserted with
+/// [`Graph::commit`](super::Graph::commit)
+#[derive(Debug, Clone)]
+pub struct VerifiedEvent<TPayload, TGenesisPayload, TPeerId> {
+    pub(super) event: SignedEvent<TPayload, TGenesisPayload, TPeerId>,
+    /// The payload was replaced by the content filter
+    pub(super) redacted: bool,
+    /// Genesis of the author the signature was checked with, `None` for
+    /// geneses
+    pub(super) author_genesis: Option<event::Hash>,
+}
+
+impl<TPayload, TGenesisPayload, TPeerId> VerifiedEvent<TPayload, TGenesisPayload, TPeerId> {
+    pub fn hash(&self) -> &event::Hash {
+        self.event.hash()
+    }
+
+    pub fn event(&self) -> &SignedEvent<TPayload, TGenesisPayload, TPeerId> {
+        &self.event
+    }
+}
+
+/// Reject events from too far in the future, `max_timestamp` is the local
+/// time and the allowed skew
+pub(super) fn check_timestamp<TPayload, TGenesisPayload, TPeerId: Clone>(
+    event: &UnsignedEvent<TPayload, TGenesisPayload, TPeerId>,
+    max_timestamp: Option<(Timestamp, Timestamp)>,
+) -> Result<(), PushError<TPeerId>> {
+    let Some((local_time, max_skew)) = max_timestamp else {
+        return Ok(());
+    };
+    let timestamp = *event.fields().timestamp();
+    if timestamp > local_time.saturating_add(max_skew) {
+        return Err(PushError::TimestampFromFuture {
+            event: event.hash().clone(),
+            author: event.fields().author().clone(),
+            timestamp,
+            local_time,
+        });
+    }
+    Ok(())
+}
+
+impl<'a, TPayload, TSigner> Validator<'a, TPayload, TSigner> {
+    /// `author_genesis` is the genesis of the author with its payload
+    /// (ignored for geneses), `None` if the author is unknown.
+    pub fn validate<TGenesisPayload, TPeerId>(
+        &self,
+        event: UnsignedEvent<TPayload, TGenesisPayload, TPeerId>,
+        signature: Signature,
+        author_genesis: Option<(event::Hash, TGenesisPayload)>,
+    ) -> Result<VerifiedEvent<TPayload, TGenesisPayload, TPeerId>, PushError<TPeerId>>
+    where
+        TPayload: crate::algorithm::codec::PayloadCodec,
+        TGenesisPayload: serde::Serialize + Clone,
+        TPeerId: serde::Serialize + Clone,
+        TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+    {
+        // Decoded events carry the hash as sent, it's what the signature covers
+        if !event.hash_matches()? {
+            return Err(PushError::HashMismatch {
+                event: event.hash().clone(),
+                author: event.fields().author().clone(),
+            });
+        }
+        check_timestamp(&event, self.max_timestamp)?;
+        let (author_genesis, genesis_payload) = match event.fields().kind() {
+            event::Kind::Genesis(payload) => (None, payload.clone()),
+            event::Kind::Regular(_) => {
+                let (genesis, payload) = author_genesis.ok_or_else(|| PushError::PeerNotFound {
+                    event: event.hash().clone(),
+                    peer: event.fields().author().clone(),
+                })?;
+                (Some(genesis), payload)
+            }
+        };
+        let hash = event.hash().clone();
+        let author = event.fields().author().clone();
+        let event = SignedEvent::with_signature(event, signature, |hash, signature, author| {
+            self.signer
+                .verify(hash, signature, author, &genesis_payload)
+        })
+        .map_err(|source| PushError::InvalidSignature {
+            event: hash,
+            author,
+            source,
+        })?;
+
+        let replacement = match self.content_filter {
+            Some(filter) => match event.unsigned().fields().user_payload() {
+                Some(payload) => filter.check(payload)?,
+                None => None,
+            },
+            None => None,
+        };
+        let redacted = replacement.is_some();
+        let event = match replacement {
+            Some(payload) => event.with_payload_replaced(payload),
+            None => event,
+        };
+        Ok(VerifiedEvent {
+            event,
+            redacted,
+            author_genesis,
+        })
+    }
+}
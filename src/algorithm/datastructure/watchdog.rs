@@ -0,0 +1,297 @@
+//! Monitoring of finality latency.
+//!
+//! A [`Watchdog`] is [checked](Watchdog::check) against the graph
+//! periodically (e.g. after each sync) and queues an [`Alert`] when
+//! - the newest round is too far ahead of the last decided one, or
+//! - nothing was finalized for too long.
+//!
+//! An alert is raised once per breach, [`Alert::Recovered`] follows when the
+//! value is back within the threshold. Alerts name the members without
+//! witnesses in the undecided rounds: fame of a round is decided by votes of
+//! later witnesses, so members that don't produce them (offline, partitioned
+//! or not gossiped with) are the usual reason of stalls.
+//!
+//! Time is passed explicitly in nanoseconds, as in
+//! [`Ingress`](super::sync::ingress::Ingress).
+
+use std::collections::VecDeque;
+
+use super::Graph;
+use crate::algorithm::RoundNum;
+use crate::Timestamp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Thresholds {
+    /// Newest round minus the last decided one
+    pub max_rounds_behind: usize,
+    /// Nanoseconds since an event was finalized last time
+    pub max_time_since_finalization: Timestamp,
+}
+
+/// Member without witnesses in some undecided rounds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingWitnesses<TPeerId> {
+    pub peer: TPeerId,
+    pub rounds: Vec<RoundNum>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertKind {
+    RoundsBehind,
+    FinalizationStalled,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Alert<TPeerId> {
+    RoundsBehind {
+        latest_round: RoundNum,
+        last_decided_round: Option<RoundNum>,
+        /// Members missing the most witnesses first
+        suspects: Vec<MissingWitnesses<TPeerId>>,
+    },
+    FinalizationStalled {
+        /// Time of the last finalization (or of the watchdog creation)
+        last_finalization: Timestamp,
+        now: Timestamp,
+        /// Members missing the most witnesses first
+        suspects: Vec<MissingWitnesses<TPeerId>>,
+    },
+    /// The condition of an earlier alert no longer holds
+    Recovered(AlertKind),
+}
+
+/// See the [module docs](self)
+pub struct Watchdog<TPeerId> {
+    thresholds: Thresholds,
+    /// Finalized events seen by the previous check
+    finalized: usize,
+    last_finalization: Timestamp,
+    rounds_behind_raised: bool,
+    stall_raised: bool,
+    alerts: VecDeque<Alert<TPeerId>>,
+}
+
+impl<TPeerId> Watchdog<TPeerId> {
+    pub fn new(thresholds: Thresholds, now: Timestamp) -> Self {
+        Self {
+            thresholds,
+            finalized: 0,
+            last_finalization: now,
+            rounds_behind_raised: false,
+            stall_raised: false,
+            alerts: VecDeque::new(),
+        }
+    }
+
+    pub fn thresholds(&self) -> &Thresholds {
+        &self.thresholds
+    }
+
+    pub fn next_alert(&mut self) -> Option<Alert<TPeerId>> {
+        self.alerts.pop_front()
+    }
+
+    /// Compare the state of the graph with the thresholds, queueing alerts
+    pub fn check<TPayload, TGenesisPayload, TSigner, TClock>(
+        &mut self,
+        graph: &Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+        now: Timestamp,
+    ) where
+        TPeerId: Eq + std::hash::Hash + Clone,
+    {
+        let finalized = graph.ordering.len();
+        if finalized > self.finalized {
+            self.finalized = finalized;
+            self.last_finalization = now;
+        }
+
+        let latest_round = graph.round_index.len() - 1;
+        let last_decided_round = graph.last_decided_round();
+        let behind = match last_decided_round {
+            Some(decided) => latest_round - decided,
+            None => latest_round + 1,
+        };
+        let rounds_behind = behind > self.thresholds.max_rounds_behind;
+        let stalled = now.saturating_sub(self.last_finalization)
+            > self.thresholds.max_time_since_finalization;
+
+        if rounds_behind && !self.rounds_behind_raised {
+            self.alerts.push_back(Alert::RoundsBehind {
+                latest_round,
+                last_decided_round,
+                suspects: missing_witnesses(graph),
+            });
+        } else if !rounds_behind && self.rounds_behind_raised {
+            self.alerts
+                .push_back(Alert::Recovered(AlertKind::RoundsBehind));
+        }
+        self.rounds_behind_raised = rounds_behind;
+
+        if stalled && !self.stall_raised {
+            self.alerts.push_back(Alert::FinalizationStalled {
+                last_finalization: self.last_finalization,
+                now,
+                suspects: missing_witnesses(graph),
+            });
+        } else if !stalled && self.stall_raised {
+            self.alerts
+                .push_back(Alert::Recovered(AlertKind::FinalizationStalled));
+        }
+        self.stall_raised = stalled;
+    }
+}
+
+/// Members without witnesses in the rounds from the first undecided one up
+/// to the newest, which is still being filled and is not counted
+fn missing_witnesses<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>(
+    graph: &Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+) -> Vec<MissingWitnesses<TPeerId>>
+where
+    TPeerId: Eq + std::hash::Hash + Clone,
+{
+    let first_undecided = graph.last_decided_round().map_or(0, |r| r + 1);
+    let latest_round = graph.round_index.len() - 1;
+    let mut suspects: Vec<_> = graph
+        .peer_index
+        .keys()
+        .filter_map(|peer| {
+            let rounds: Vec<_> = (first_undecided..latest_round)
+                .filter(|&round| {
+                    !graph
+                        .witnesses_between(round, round)
+                        .any(|(_, w)| graph.all_events.get(w).map(|e| e.author()) == Some(peer))
+                })
+                .collect();
+            let genesis = graph.peer_genesis(peer).expect("members have geneses");
+            (!rounds.is_empty()).then(|| {
+                (
+                    genesis,
+                    MissingWitnesses {
+                        peer: peer.clone(),
+                        rounds,
+                    },
+                )
+            })
+        })
+        .collect();
+    // Genesis hashes make the order deterministic
+    suspects.sort_by(|(genesis_a, a), (genesis_b, b)| {
+        b.rounds
+            .len()
+            .cmp(&a.rounds.len())
+            .then_with(|| genesis_a.cmp(genesis_b))
+    });
+    suspects.into_iter().map(|(_, suspect)| suspect).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::{event, MockSigner, Signer};
+    use crate::testing::{GraphBuilder, TestGraph};
+
+    /// `a`, `b` and `c` gossip in turns, `d` is silent
+    fn gossip(graph: &mut TestGraph<u64, u64>, steps: std::ops::Range<usize>) {
+        for step in steps {
+            let author = (step % 3) as u64;
+            let other = graph
+                .peer_latest_event(&((author + 1) % 3))
+                .unwrap()
+                .clone();
+            let self_parent = graph.peer_latest_event(&author).unwrap().clone();
+            let event = event::SignedEvent::new(
+                0,
+                event::Kind::Regular(event::Parents {
+                    self_parent,
+                    other_parent: other,
+                }),
+                author,
+                step as u128,
+                |h| MockSigner::<u64, ()>::new().sign(h),
+            )
+            .unwrap();
+            let (event, signature) = event.into_parts();
+            graph.push_event(event, signature).unwrap();
+        }
+    }
+
+    fn graph() -> TestGraph<u64, u64> {
+        GraphBuilder::new("a", 0u64, 0u64, 999)
+            .peer("b", 1)
+            .peer("c", 2)
+            .peer("d", 3)
+            .build()
+            .unwrap()
+            .graph
+    }
+
+    #[test]
+    fn stall_raised_once_and_recovered() {
+        let mut graph = graph();
+        let mut watchdog = Watchdog::new(
+            Thresholds {
+                max_rounds_behind: usize::MAX,
+                max_time_since_finalization: 100,
+            },
+            0,
+        );
+        watchdog.check(&graph, 50);
+        assert_eq!(watchdog.next_alert(), None);
+        gossip(&mut graph, 0..15);
+        watchdog.check(&graph, 150);
+        let Some(Alert::FinalizationStalled {
+            last_finalization: 0,
+            now: 150,
+            suspects,
+        }) = watchdog.next_alert()
+        else {
+            panic!("Expected a stall alert");
+        };
+        assert_eq!(
+            suspects,
+            [MissingWitnesses {
+                peer: 3,
+                rounds: vec![1]
+            }]
+        );
+        watchdog.check(&graph, 200);
+        assert_eq!(watchdog.next_alert(), None);
+
+        gossip(&mut graph, 15..30);
+        assert!(graph.ordering.len() > 0);
+        watchdog.check(&graph, 210);
+        assert_eq!(
+            watchdog.next_alert(),
+            Some(Alert::Recovered(AlertKind::FinalizationStalled))
+        );
+    }
+
+    #[test]
+    fn rounds_behind_raised() {
+        let mut graph = graph();
+        let mut watchdog = Watchdog::new(
+            Thresholds {
+                max_rounds_behind: 1,
+                max_time_since_finalization: Timestamp::MAX,
+            },
+            0,
+        );
+        watchdog.check(&graph, 0);
+        assert_eq!(watchdog.next_alert(), None);
+        gossip(&mut graph, 0..15);
+        watchdog.check(&graph, 0);
+        assert_eq!(
+            watchdog.next_alert(),
+            Some(Alert::RoundsBehind {
+                latest_round: 2,
+                last_decided_round: Some(0),
+                suspects: vec![MissingWitnesses {
+                    peer: 3,
+                    rounds: vec![1]
+                }],
+            })
+        );
+        watchdog.check(&graph, 0);
+        assert_eq!(watchdog.next_alert(), None);
+    }
+}
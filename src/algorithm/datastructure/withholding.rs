@@ -0,0 +1,393 @@
+//! Heuristics for spotting members that withhold events.
+//!
+//! A [`Monitor`] is [checked](Monitor::check) against the graph
+//! periodically (e.g. after each sync), the same way as a
+//! [`Watchdog`](super::watchdog::Watchdog). It looks at the events of each
+//! member that arrived since the previous check and queues an [`Advisory`]
+//! when a member
+//! - keeps choosing stale other parents: the other parent is several rounds
+//!   behind the latest event of its author known at the check, as if the
+//!   member only pretended to know old events of others, or
+//! - shows its events in bursts: many of them at once after checks with
+//!   none, as if it held them back and released them together.
+//!
+//! Both can have honest reasons (slow links, partitions), so advisories are
+//! only hints for the operator or the gossip scheduler. An advisory is raised
+//! once per breach and followed by [`Advisory::Cleared`] when the member
+//! behaves again.
+//!
+//! Events already in the graph when a member is first seen are not counted,
+//! so the initial sync doesn't look like a burst.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+
+use super::Graph;
+use crate::algorithm::{event, RoundNum};
+use crate::Timestamp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Heuristics {
+    /// Number of latest events, and of latest checks, looked at for each
+    /// member
+    pub window: usize,
+    /// Other parents at least this many rounds behind the latest events
+    /// of their authors are stale. A member is suspected when all events in the window have one.
+    pub stale_rounds: RoundNum,
+    /// At least this many events of a member arriving at once, when the
+    /// previous check saw none, are a burst
+    pub burst_size: usize,
+    /// A member is suspected when this many of the checks in the window saw
+    /// bursts
+    pub max_bursts: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AdvisoryKind {
+    StaleReferences,
+    Bursts,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Advisory<TPeerId> {
+    StaleReferences {
+        peer: TPeerId,
+        /// Mean distance in rounds between the other parents of the events
+        /// in the window and the latest events of their authors
+        mean_lag: RoundNum,
+    },
+    Bursts {
+        peer: TPeerId,
+        /// Number of bursts in the window
+        bursts: usize,
+        /// Size of the latest burst
+        latest: usize,
+        now: Timestamp,
+    },
+    /// The condition of an earlier advisory no longer holds
+    Cleared { peer: TPeerId, kind: AdvisoryKind },
+}
+
+/// What is known about a member from the previous checks
+#[derive(Debug, Default)]
+struct PeerTrack {
+    /// Highest sequence number seen
+    sequence: u64,
+    /// Lags of the latest events, newest last
+    lags: VecDeque<RoundNum>,
+    /// Number of events that arrived in each of the latest checks, newest
+    /// last
+    arrivals: VecDeque<usize>,
+    raised: HashSet<AdvisoryKind>,
+}
+
+/// See the [module docs](self)
+pub struct Monitor<TPeerId> {
+    heuristics: Heuristics,
+    peers: HashMap<TPeerId, PeerTrack>,
+    advisories: VecDeque<Advisory<TPeerId>>,
+}
+
+impl<TPeerId> Monitor<TPeerId>
+where
+    TPeerId: Eq + Hash + Clone,
+{
+    pub fn new(heuristics: Heuristics) -> Self {
+        Self {
+            heuristics,
+            peers: HashMap::new(),
+            advisories: VecDeque::new(),
+        }
+    }
+
+    pub fn heuristics(&self) -> &Heuristics {
+        &self.heuristics
+    }
+
+    pub fn next_advisory(&mut self) -> Option<Advisory<TPeerId>> {
+        self.advisories.pop_front()
+    }
+
+    /// Look at the events that arrived since the previous check, queueing
+    /// advisories
+    pub fn check<TPayload, TGenesisPayload, TSigner, TClock>(
+        &mut self,
+        graph: &Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+        now: Timestamp,
+    ) {
+        let window = self.heuristics.window.max(1);
+        for (peer, index) in &graph.peer_index {
+            let latest = index.latest_events();
+            let sequence = latest
+                .iter()
+                .filter_map(|hash| graph.headers.get(hash))
+                .map(|header| header.sequence)
+                .max()
+                .unwrap_or(0);
+            let Some(track) = self.peers.get_mut(peer) else {
+                self.peers.insert(
+                    peer.clone(),
+                    PeerTrack {
+                        sequence,
+                        ..Default::default()
+                    },
+                );
+                continue;
+            };
+
+            let arrived = new_events(graph, latest, track.sequence);
+            track.sequence = track.sequence.max(sequence);
+            for hash in &arrived {
+                if let Some(lag) = other_parent_lag(graph, hash) {
+                    track.lags.push_back(lag);
+                }
+            }
+            while track.lags.len() > window {
+                track.lags.pop_front();
+            }
+            track.arrivals.push_back(arrived.len());
+            while track.arrivals.len() > window {
+                track.arrivals.pop_front();
+            }
+            let bursts = bursts(&track.arrivals, self.heuristics.burst_size.max(1));
+
+            let stale = track.lags.len() == window
+                && track
+                    .lags
+                    .iter()
+                    .all(|&lag| lag >= self.heuristics.stale_rounds);
+            let mean_lag = track.lags.iter().sum::<RoundNum>() / track.lags.len().max(1);
+            update(
+                &mut self.advisories,
+                track,
+                peer,
+                AdvisoryKind::StaleReferences,
+                stale,
+                || Advisory::StaleReferences {
+                    peer: peer.clone(),
+                    mean_lag,
+                },
+            );
+            update(
+                &mut self.advisories,
+                track,
+                peer,
+                AdvisoryKind::Bursts,
+                bursts >= self.heuristics.max_bursts.max(1),
+                || Advisory::Bursts {
+                    peer: peer.clone(),
+                    bursts,
+                    latest: arrived.len(),
+                    now,
+                },
+            );
+        }
+    }
+}
+
+/// Raise or clear the advisory of `kind` according to `suspected`
+fn update<TPeerId: Clone>(
+    advisories: &mut VecDeque<Advisory<TPeerId>>,
+    track: &mut PeerTrack,
+    peer: &TPeerId,
+    kind: AdvisoryKind,
+    suspected: bool,
+    advisory: impl FnOnce() -> Advisory<TPeerId>,
+) {
+    let raised = track.raised.contains(&kind);
+    if suspected && !raised {
+        track.raised.insert(kind);
+        advisories.push_back(advisory());
+    } else if !suspected && raised {
+        track.raised.remove(&kind);
+        advisories.push_back(Advisory::Cleared {
+            peer: peer.clone(),
+            kind,
+        });
+    }
+}
+
+/// Checks with at least `size` arrivals right after a check with none
+fn bursts(arrivals: &VecDeque<usize>, size: usize) -> usize {
+    arrivals
+        .iter()
+        .zip(arrivals.iter().skip(1))
+        .filter(|(&before, &after)| before == 0 && after >= size)
+        .count()
+}
+
+/// Events of the chains ending at `latest` with sequence numbers above
+/// `seen`
+fn new_events<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>(
+    graph: &Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+    latest: &HashSet<event::Hash>,
+    seen: u64,
+) -> Vec<event::Hash> {
+    let mut found = HashSet::new();
+    for tip in latest {
+        let mut next = Some(tip);
+        while let Some(hash) = next {
+            let Some(header) = graph.headers.get(hash) else {
+                break;
+            };
+            if header.sequence <= seen || !found.insert(hash.clone()) {
+                break;
+            }
+            next = header.parents.as_ref().map(|p| &p.self_parent);
+        }
+    }
+    found.into_iter().collect()
+}
+
+/// Rounds between the other parent of the event and the latest known
+/// event of its author. `None` for geneses or if the other parent was
+/// pruned
+fn other_parent_lag<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>(
+    graph: &Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+    hash: &event::Hash,
+) -> Option<RoundNum>
+where
+    TPeerId: Eq + Hash,
+{
+    let header = graph.headers.get(hash)?;
+    let other = graph.headers.get(&header.parents.as_ref()?.other_parent)?;
+    let latest_round = graph
+        .peer_index
+        .get(&other.author)?
+        .latest_events()
+        .iter()
+        .filter_map(|latest| graph.headers.get(latest)?.round)
+        .max()?;
+    Some(latest_round.saturating_sub(other.round?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::{MockSigner, Signer};
+    use crate::testing::{GraphBuilder, TestGraph};
+
+    fn graph() -> TestGraph<(), u64> {
+        GraphBuilder::new("a", 0u64, (), 999)
+            .peer("b", 1)
+            .peer("c", 2)
+            .peer("d", 3)
+            .build()
+            .unwrap()
+            .graph
+    }
+
+    /// Add an event of `author` on top of its latest one
+    fn add(graph: &mut TestGraph<(), u64>, author: u64, other_parent: event::Hash) {
+        let self_parent = graph.peer_latest_event(&author).unwrap().clone();
+        let event = event::SignedEvent::new(
+            (),
+            event::Kind::Regular(event::Parents {
+                self_parent,
+                other_parent,
+            }),
+            author,
+            graph.all_events.len() as u128,
+            |h| MockSigner::<u64, ()>::new().sign(h),
+        )
+        .unwrap();
+        let (event, signature) = event.into_parts();
+        graph.push_event(event, signature).unwrap();
+    }
+
+    /// `a`, `b` and `c` gossip in turns
+    fn gossip(graph: &mut TestGraph<(), u64>, step: usize) {
+        let author = (step % 3) as u64;
+        let other = graph
+            .peer_latest_event(&((author + 1) % 3))
+            .unwrap()
+            .clone();
+        add(graph, author, other);
+    }
+
+    fn advisories(monitor: &mut Monitor<u64>) -> Vec<Advisory<u64>> {
+        std::iter::from_fn(|| monitor.next_advisory()).collect()
+    }
+
+    #[test]
+    fn stale_references_raised_and_cleared() {
+        let mut graph = graph();
+        let mut monitor = Monitor::new(Heuristics {
+            window: 3,
+            stale_rounds: 2,
+            burst_size: usize::MAX,
+            max_bursts: usize::MAX,
+        });
+        monitor.check(&graph, 0);
+        let genesis = graph.peer_genesis(&0).unwrap().clone();
+        let mut raised = vec![];
+        for step in 0..60 {
+            gossip(&mut graph, step);
+            if step % 3 == 0 {
+                // `d` keeps pointing at the genesis of `a` at first
+                let other = match step < 30 {
+                    true => genesis.clone(),
+                    false => graph.peer_latest_event(&0).unwrap().clone(),
+                };
+                add(&mut graph, 3, other);
+            }
+            monitor.check(&graph, step as Timestamp);
+            raised.extend(advisories(&mut monitor).into_iter().map(|a| (step, a)));
+        }
+        assert_eq!(raised.len(), 2, "{raised:?}");
+        let (raised_at, Advisory::StaleReferences { peer: 3, mean_lag }) = &raised[0] else {
+            panic!("Expected stale references of d: {raised:?}");
+        };
+        assert!(*raised_at < 30 && *mean_lag >= 2);
+        assert!(matches!(
+            raised[1],
+            (
+                30..,
+                Advisory::Cleared {
+                    peer: 3,
+                    kind: AdvisoryKind::StaleReferences
+                }
+            )
+        ));
+    }
+
+    #[test]
+    fn bursts_raised_and_cleared() {
+        let mut graph = graph();
+        let mut monitor = Monitor::new(Heuristics {
+            window: 10,
+            stale_rounds: RoundNum::MAX,
+            burst_size: 3,
+            max_bursts: 2,
+        });
+        monitor.check(&graph, 0);
+        let mut all = vec![];
+        for step in 0..24 {
+            gossip(&mut graph, step);
+            // `d` releases 4 events at once every 4th check
+            if step % 4 == 3 && step < 12 {
+                for _ in 0..4 {
+                    let other = graph.peer_latest_event(&0).unwrap().clone();
+                    add(&mut graph, 3, other);
+                }
+            }
+            monitor.check(&graph, step as Timestamp);
+            all.extend(advisories(&mut monitor));
+        }
+        assert_eq!(
+            all,
+            vec![
+                Advisory::Bursts {
+                    peer: 3,
+                    bursts: 2,
+                    latest: 4,
+                    now: 7
+                },
+                Advisory::Cleared {
+                    peer: 3,
+                    kind: AdvisoryKind::Bursts
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,402 @@
+//! Transactions finalized before, across restarts.
+//!
+//! The [mempool](super::mempool) only rejects duplicates of transactions
+//! still waiting, and a client can resubmit a transaction that was already
+//! finalized. A [`DedupIndex`] remembers the hashes of finalized
+//! transactions for a number of finalized rounds, so that the application
+//! can skip replays when applying finalized payloads (all members record
+//! the same hashes in the same rounds, so they skip the same ones) and
+//! [`Mempool::submit_unless_finalized`](super::mempool::Mempool::submit_unless_finalized)
+//! can refuse them right away.
+//!
+//! Hashes are kept in segments of [`DedupConfig::rounds_per_segment`]
+//! rounds, like the runs of an LSM tree. The segment of the latest rounds
+//! is open and takes new hashes, older ones are sealed: their hashes are
+//! sorted and have a bloom filter in front, so most lookups of new
+//! transactions don't search them. Segments are saved to a [`DedupStore`]
+//! and dropped from it once all of their rounds are more than
+//! [`DedupConfig::retention_rounds`] behind the latest one, so the index
+//! doesn't grow with the history. The open segment is saved on
+//! [`DedupIndex::flush`], e.g. after each finalized round.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::RoundNum;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DedupConfig {
+    /// Finalized rounds a hash is kept for, at least
+    pub retention_rounds: RoundNum,
+    pub rounds_per_segment: RoundNum,
+    /// Size of the bloom filters, about 1% false positives at 10
+    pub bloom_bits_per_hash: usize,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self {
+            retention_rounds: 1000,
+            rounds_per_segment: 100,
+            bloom_bits_per_hash: 10,
+        }
+    }
+}
+
+/// Segment as saved, the open one included
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StoredSegment<K> {
+    pub first_round: RoundNum,
+    /// Sorted
+    pub hashes: Vec<K>,
+}
+
+/// Where a [`DedupIndex`] keeps its segments
+pub trait DedupStore<K> {
+    type Error;
+
+    fn load(&mut self) -> Result<Vec<StoredSegment<K>>, Self::Error>;
+    /// Replaces the segment with the same first round
+    fn save(&mut self, segment: &StoredSegment<K>) -> Result<(), Self::Error>;
+    fn remove(&mut self, first_round: RoundNum) -> Result<(), Self::Error>;
+}
+
+/// Segments in a directory, one file each. Saved to a temporary file that
+/// replaces the previous one, so a crash leaves either the old or the new
+/// segment.
+pub struct FileDedupStore {
+    directory: PathBuf,
+}
+
+impl FileDedupStore {
+    /// The directory must exist
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    fn path(&self, first_round: RoundNum) -> PathBuf {
+        self.directory.join(format!("segment-{first_round}"))
+    }
+}
+
+impl<K: Serialize + DeserializeOwned> DedupStore<K> for FileDedupStore {
+    type Error = io::Error;
+
+    fn load(&mut self) -> io::Result<Vec<StoredSegment<K>>> {
+        let mut segments = vec![];
+        for entry in std::fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            let is_segment = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("segment-"))
+                .is_some_and(|round| round.parse::<RoundNum>().is_ok());
+            if !is_segment {
+                continue;
+            }
+            let segment = bincode::deserialize(&std::fs::read(&path)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            segments.push(segment);
+        }
+        Ok(segments)
+    }
+
+    fn save(&mut self, segment: &StoredSegment<K>) -> io::Result<()> {
+        let bytes = bincode::serialize(segment).map_err(io::Error::other)?;
+        let path = self.path(segment.first_round);
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, bytes)?;
+        std::fs::rename(&temporary, &path)
+    }
+
+    fn remove(&mut self, first_round: RoundNum) -> io::Result<()> {
+        match std::fs::remove_file(self.path(first_round)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DedupError<E> {
+    #[error("Round {round} is before the open segment, starting at {open}")]
+    RoundBehind { round: RoundNum, open: RoundNum },
+    #[error("Failed to access the store: {0}")]
+    Store(E),
+}
+
+struct Bloom {
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+impl Bloom {
+    fn new<K: Hash>(keys: &[K], bits_per_key: usize) -> Self {
+        let words = (keys.len() * bits_per_key).div_ceil(64).max(1);
+        // ln 2 * bits per key is optimal
+        let hashes = ((bits_per_key as f64 * 0.69).round() as u32).max(1);
+        let mut bloom = Self {
+            bits: vec![0; words],
+            hashes,
+        };
+        for key in keys {
+            for bit in bloom.bits_of(key) {
+                bloom.bits[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        bloom
+    }
+
+    fn bits_of<K: Hash>(&self, key: &K) -> impl Iterator<Item = usize> {
+        let hash = |seed: u64| {
+            let mut hasher = DefaultHasher::new();
+            seed.hash(&mut hasher);
+            key.hash(&mut hasher);
+            hasher.finish()
+        };
+        let (first, second) = (hash(0), hash(1));
+        let len = (self.bits.len() * 64) as u64;
+        (0..self.hashes as u64)
+            .map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % len) as usize)
+    }
+
+    fn may_contain<K: Hash>(&self, key: &K) -> bool {
+        self.bits_of(key)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+}
+
+struct Sealed<K> {
+    first_round: RoundNum,
+    hashes: Vec<K>,
+    bloom: Bloom,
+}
+
+/// See the [module docs](self)
+pub struct DedupIndex<K, S> {
+    config: DedupConfig,
+    store: S,
+    /// Oldest first
+    sealed: Vec<Sealed<K>>,
+    open_round: RoundNum,
+    open: BTreeSet<K>,
+}
+
+impl<K, S> DedupIndex<K, S>
+where
+    K: Ord + Hash + Clone,
+    S: DedupStore<K>,
+{
+    /// Continue with the saved segments, or start empty
+    pub fn open(mut store: S, config: DedupConfig) -> Result<Self, S::Error> {
+        let mut segments = store.load()?;
+        segments.sort_by_key(|s| s.first_round);
+        let (open_round, open) = match segments.pop() {
+            Some(latest) => (latest.first_round, latest.hashes.into_iter().collect()),
+            None => (0, BTreeSet::new()),
+        };
+        let sealed = segments
+            .into_iter()
+            .map(|s| Sealed {
+                bloom: Bloom::new(&s.hashes, config.bloom_bits_per_hash),
+                first_round: s.first_round,
+                hashes: s.hashes,
+            })
+            .collect();
+        Ok(Self {
+            config,
+            store,
+            sealed,
+            open_round,
+            open,
+        })
+    }
+
+    pub fn config(&self) -> &DedupConfig {
+        &self.config
+    }
+
+    /// Hashes kept, in all segments
+    pub fn len(&self) -> usize {
+        self.open.len() + self.sealed.iter().map(|s| s.hashes.len()).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Segments, the open one included
+    pub fn segments(&self) -> usize {
+        self.sealed.len() + 1
+    }
+
+    /// Whether the transaction was finalized within the retention
+    pub fn contains(&self, hash: &K) -> bool {
+        self.open.contains(hash)
+            || self
+                .sealed
+                .iter()
+                .any(|s| s.bloom.may_contain(hash) && s.hashes.binary_search(hash).is_ok())
+    }
+
+    /// Record a transaction finalized in `round` (its round received).
+    /// Returns `false` if it was recorded before, i.e. it is a replay.
+    /// Rounds must not go back by a segment.
+    pub fn insert(&mut self, round: RoundNum, hash: K) -> Result<bool, DedupError<S::Error>> {
+        if round < self.open_round {
+            return Err(DedupError::RoundBehind {
+                round,
+                open: self.open_round,
+            });
+        }
+        let segment_round = round - round % self.config.rounds_per_segment.max(1);
+        if segment_round > self.open_round {
+            self.rotate(segment_round)?;
+        }
+        if self.contains(&hash) {
+            return Ok(false);
+        }
+        self.open.insert(hash);
+        Ok(true)
+    }
+
+    /// Save the open segment
+    pub fn flush(&mut self) -> Result<(), S::Error> {
+        self.store.save(&StoredSegment {
+            first_round: self.open_round,
+            hashes: self.open.iter().cloned().collect(),
+        })
+    }
+
+    /// Seal the open segment, start a new one and drop the expired ones
+    fn rotate(&mut self, segment_round: RoundNum) -> Result<(), DedupError<S::Error>> {
+        if self.open.is_empty() {
+            self.store
+                .remove(self.open_round)
+                .map_err(DedupError::Store)?;
+        } else {
+            self.flush().map_err(DedupError::Store)?;
+            let hashes: Vec<_> = std::mem::take(&mut self.open).into_iter().collect();
+            self.sealed.push(Sealed {
+                bloom: Bloom::new(&hashes, self.config.bloom_bits_per_hash),
+                first_round: self.open_round,
+                hashes,
+            });
+        }
+        self.open_round = segment_round;
+        // Segments ending before this are all out of the retention
+        let kept_from = segment_round.saturating_sub(self.config.retention_rounds);
+        let per_segment = self.config.rounds_per_segment.max(1);
+        while let Some(oldest) = self.sealed.first() {
+            if oldest.first_round + per_segment > kept_from {
+                break;
+            }
+            self.store
+                .remove(oldest.first_round)
+                .map_err(DedupError::Store)?;
+            self.sealed.remove(0);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MemoryStore {
+        segments: BTreeMap<RoundNum, StoredSegment<u64>>,
+    }
+
+    impl DedupStore<u64> for &mut MemoryStore {
+        type Error = std::convert::Infallible;
+
+        fn load(&mut self) -> Result<Vec<StoredSegment<u64>>, Self::Error> {
+            Ok(self.segments.values().cloned().collect())
+        }
+
+        fn save(&mut self, segment: &StoredSegment<u64>) -> Result<(), Self::Error> {
+            self.segments.insert(segment.first_round, segment.clone());
+            Ok(())
+        }
+
+        fn remove(&mut self, first_round: RoundNum) -> Result<(), Self::Error> {
+            self.segments.remove(&first_round);
+            Ok(())
+        }
+    }
+
+    const CONFIG: DedupConfig = DedupConfig {
+        retention_rounds: 20,
+        rounds_per_segment: 10,
+        bloom_bits_per_hash: 10,
+    };
+
+    #[test]
+    fn replays_rejected_within_retention() {
+        let mut store = MemoryStore::default();
+        let mut index = DedupIndex::open(&mut store, CONFIG).unwrap();
+        for round in 0..30 {
+            for i in 0..10 {
+                assert_eq!(index.insert(round, round as u64 * 100 + i), Ok(true));
+            }
+            assert_eq!(index.insert(round, round as u64 * 100), Ok(false));
+        }
+        assert_eq!(index.segments(), 3);
+        assert!(index.contains(&5));
+        assert_eq!(index.insert(25, 105), Ok(false));
+        assert_eq!(
+            index.insert(15, 1),
+            Err(DedupError::RoundBehind {
+                round: 15,
+                open: 20
+            })
+        );
+
+        // Rounds up to 19 are out of the retention once round 40 is reached
+        index.insert(40, 1).unwrap();
+        assert!(!index.contains(&5));
+        assert!(!index.contains(&1905));
+        assert!(index.contains(&2005));
+        assert_eq!(index.len(), 101);
+        index.insert(75, 2).unwrap();
+        assert_eq!(index.len(), 1);
+        index.flush().unwrap();
+        drop(index);
+        assert_eq!(store.segments.keys().collect::<Vec<_>>(), vec![&70]);
+    }
+
+    #[test]
+    fn survives_restarts() {
+        let directory = std::env::temp_dir().join(format!("dedup-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mut index = DedupIndex::open(FileDedupStore::new(&directory), CONFIG).unwrap();
+        for round in 0..15 {
+            index.insert(round, round as u64).unwrap();
+        }
+        index.flush().unwrap();
+        // Restart
+        drop(index);
+        let mut index: DedupIndex<u64, _> =
+            DedupIndex::open(FileDedupStore::new(&directory), CONFIG).unwrap();
+        assert_eq!(index.len(), 15);
+        assert_eq!(index.segments(), 2);
+        assert!((0..15).all(|hash| index.contains(&hash)));
+        assert!(!index.contains(&15));
+        assert!(!index.insert(16, 3).unwrap());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
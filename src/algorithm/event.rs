@@ -5,15 +5,14 @@ use serde_big_array::BigArray;
 use std::fmt::Debug;
 use thiserror::Error;
 
+use super::codec::{self, PayloadCodec};
 use crate::Timestamp;
 
 // smth like H256 ??? (some hash type)
-#[derive(Serialize, Hash, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Hash {
     #[serde(with = "BigArray")]
     inner: [u8; 64],
-    #[serde(skip)]
-    compact: [u8; 4],
 }
 
 impl std::fmt::Display for Hash {
@@ -53,37 +52,52 @@ impl std::ops::BitXor<&Hash> for Hash {
     }
 }
 
-impl PartialOrd for Hash {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+impl AsRef<[u8; 64]> for Hash {
+    fn as_ref(&self) -> &[u8; 64] {
+        &self.inner
     }
 }
 
-impl Ord for Hash {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.inner.cmp(&other.inner)
+impl Hash {
+    pub fn into_array(self) -> [u8; 64] {
+        self.inner
     }
-}
 
-impl PartialEq for Hash {
-    fn eq(&self, other: &Self) -> bool {
-        self.inner == other.inner
+    /// Short form for logs, computed on each call (it's only 64 XORs, and
+    /// most hashes, e.g. decoded ones, are never printed)
+    pub fn as_compact(&self) -> [u8; 4] {
+        let (a, c) = self.inner.split_at(32);
+        let (a, b) = a.split_at(16);
+        let (c, d) = c.split_at(16);
+        [a, b, c, d].map(Self::xor_bytes)
     }
-}
-
-impl Eq for Hash {}
 
-impl Hash {
-    pub fn into_array(self) -> [u8; 64] {
-        return self.inner;
+    /// Lowercase hex of the full hash
+    pub fn to_hex(&self) -> String {
+        self.inner.iter().map(|b| format!("{b:02x}")).collect()
     }
 
-    pub fn as_ref(&self) -> &[u8; 64] {
-        return &self.inner;
+    /// Inverse of [`to_hex`](Self::to_hex), accepts both cases. `None` if
+    /// the string is not 128 hex digits.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        // `from_str_radix` alone would also take a sign, e.g. "+f"
+        if hex.len() != 128 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let mut inner = [0u8; 64];
+        for (byte, pair) in inner.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            let pair = std::str::from_utf8(pair).ok()?;
+            *byte = u8::from_str_radix(pair, 16).ok()?;
+        }
+        Some(Self::from_array(inner))
     }
 
-    pub fn as_compact(&self) -> &[u8; 4] {
-        return &self.compact;
+    /// Lowercase hex of the compact form, for labels and logs
+    pub fn to_compact_hex(&self) -> String {
+        self.as_compact()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
     }
 
     fn xor_bytes(slice: &[u8]) -> u8 {
@@ -94,27 +108,8 @@ impl Hash {
         result
     }
 
-    fn calc_compact(inner: &[u8; 64]) -> [u8; 4] {
-        let (a, c) = inner.split_at(32);
-        let (a, b) = a.split_at(16);
-        let (c, d) = c.split_at(16);
-        let [a, b, c, d] = [a, b, c, d].map(Self::xor_bytes);
-        return [a, b, c, d];
-    }
-
     pub fn from_array(inner: [u8; 64]) -> Self {
-        let compact = Self::calc_compact(&inner);
-        return Hash { inner, compact };
-    }
-}
-
-impl<'de> Deserialize<'de> for Hash {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let s = <[u8; 64]>::deserialize(deserializer)?;
-        Ok(Self::from_array(s))
+        Hash { inner }
     }
 }
 
@@ -160,6 +155,13 @@ impl<TPayload, TGenesisPayload, TPeerId> EventWrapper<TPayload, TGenesisPayload,
         &self.inner
     }
 
+    /// Drop the payload, keeping the hash and the signature. Like
+    /// [`SignedEvent::with_payload_replaced`], the event can't be sent to
+    /// other peers anymore.
+    pub(crate) fn take_payload(&mut self) -> Option<TPayload> {
+        self.inner.unsigned.fields.user_payload.take()
+    }
+
     /// Event with signature that is just its hash. Does not involve any actual
     /// signing process.
     ///
@@ -172,7 +174,7 @@ impl<TPayload, TGenesisPayload, TPeerId> EventWrapper<TPayload, TGenesisPayload,
         timestamp: Timestamp,
     ) -> Result<Self, bincode::Error>
     where
-        TPayload: Serialize,
+        TPayload: PayloadCodec,
         TGenesisPayload: Serialize,
         TPeerId: Serialize,
     {
@@ -195,8 +197,10 @@ impl<TPayload, TGenesisPayload, TPeerId> EventWrapper<TPayload, TGenesisPayload,
         &self.inner.unsigned.fields.kind
     }
 
-    pub fn payload(&self) -> &TPayload {
-        &self.inner.unsigned.fields.user_payload
+    /// `None` if the payload was dropped by
+    /// [`Graph::compact`](super::datastructure::Graph::compact)
+    pub fn payload(&self) -> Option<&TPayload> {
+        self.inner.unsigned.fields.user_payload()
     }
 
     pub fn author(&self) -> &TPeerId {
@@ -209,6 +213,10 @@ impl<TPayload, TGenesisPayload, TPeerId> EventWrapper<TPayload, TGenesisPayload,
 }
 
 #[derive(Eq, PartialEq, Hash, Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "TPayload: PayloadCodec, TGenesisPayload: Serialize, TPeerId: Serialize",
+    deserialize = "TPayload: PayloadCodec, TGenesisPayload: Deserialize<'de>, TPeerId: Deserialize<'de>"
+))]
 pub struct SignedEvent<TPayload, TGenesisPayload, TPeerId> {
     unsigned: UnsignedEvent<TPayload, TGenesisPayload, TPeerId>,
     /// Hash of the fields of the event, signed by author's private key
@@ -239,11 +247,18 @@ impl<TPayload, TGenesisPayload, TPeerId> SignedEvent<TPayload, TGenesisPayload,
     pub fn into_parts(self) -> (UnsignedEvent<TPayload, TGenesisPayload, TPeerId>, Signature) {
         (self.unsigned, self.signature)
     }
+
+    /// Replace the payload, keeping the hash and the signature. They don't
+    /// match the event anymore, so it can't be sent to other peers.
+    pub(crate) fn with_payload_replaced(mut self, payload: TPayload) -> Self {
+        self.unsigned.fields.user_payload = Some(payload);
+        self
+    }
 }
 
 impl<TPayload, TGenesisPayload, TPeerId> SignedEvent<TPayload, TGenesisPayload, TPeerId>
 where
-    TPayload: Serialize,
+    TPayload: PayloadCodec,
     TGenesisPayload: Serialize,
     TPeerId: Serialize,
 {
@@ -258,7 +273,7 @@ where
         F: FnOnce(&Hash) -> Signature,
     {
         let fields = EventFields {
-            user_payload: payload,
+            user_payload: Some(payload),
             kind: event_kind,
             author,
             timestamp,
@@ -296,11 +311,7 @@ where
         event_kind: Kind<TGenesisPayload>,
         author: TPeerId,
         timestamp: Timestamp,
-    ) -> Result<Self, bincode::Error>
-    where
-        TPayload: Serialize,
-        TGenesisPayload: Serialize,
-    {
+    ) -> Result<Self, bincode::Error> {
         Self::new(payload, event_kind, author, timestamp, |h| {
             Signature(h.clone())
         })
@@ -308,6 +319,10 @@ where
 }
 
 #[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Debug, Getters)]
+#[serde(bound(
+    serialize = "TPayload: PayloadCodec, TGenesisPayload: Serialize, TPeerId: Serialize",
+    deserialize = "TPayload: PayloadCodec, TGenesisPayload: Deserialize<'de>, TPeerId: Deserialize<'de>"
+))]
 pub struct UnsignedEvent<TPayload, TGenesisPayload, TPeerId> {
     fields: EventFields<TPayload, TGenesisPayload, TPeerId>,
     hash: Hash,
@@ -315,20 +330,37 @@ pub struct UnsignedEvent<TPayload, TGenesisPayload, TPeerId> {
 
 impl<TPayload, TGenesisPayload, TPeerId> UnsignedEvent<TPayload, TGenesisPayload, TPeerId>
 where
-    TPayload: Serialize,
+    TPayload: PayloadCodec,
     TGenesisPayload: Serialize,
     TPeerId: Serialize,
 {
     pub fn new(fields: EventFields<TPayload, TGenesisPayload, TPeerId>) -> bincode::Result<Self> {
-        let mut hasher = Blake2b512::new();
-        hasher.update(fields.digest()?);
-        let hash_slice = &hasher.finalize()[..];
-        let hash_arr: [u8; 64] = hash_slice.try_into().expect("event hashing failure");
-        Ok(Self {
-            fields,
-            hash: Hash::from_array(hash_arr),
+        let hash = fields.hash()?;
+        Ok(Self { fields, hash })
+    }
+
+    /// Event to be signed separately, e.g. by a group (see
+    /// [`threshold`](crate::algorithm::threshold))
+    pub fn draft(
+        payload: TPayload,
+        event_kind: Kind<TGenesisPayload>,
+        author: TPeerId,
+        timestamp: Timestamp,
+    ) -> bincode::Result<Self> {
+        Self::new(EventFields {
+            user_payload: Some(payload),
+            kind: event_kind,
+            author,
+            timestamp,
         })
     }
+
+    /// Whether the hash corresponds to the fields. Always true for events
+    /// created with [`new`](Self::new), but decoded events carry the hash
+    /// as it was sent.
+    pub fn hash_matches(&self) -> bincode::Result<bool> {
+        Ok(self.fields.hash()? == self.hash)
+    }
 }
 
 impl<TPayload, TGenesisPayload, TPeerId> UnsignedEvent<TPayload, TGenesisPayload, TPeerId>
@@ -346,31 +378,63 @@ where
                 parents.other_parent.as_compact()
             ),
         };
+        let payload_string = match &self.fields.user_payload {
+            Some(p) => format!("{:?}", p),
+            None => "<dropped>".to_owned(),
+        };
         format!(
-            "UnsignedEvent {{ user_payload: {:?}, kind: {}, author: {:?}, timestamp; {:?}, hash: {:?} }}",
-            self.fields.user_payload, kind_string, self.fields.author, self.fields.timestamp, self.hash.as_compact()
+            "UnsignedEvent {{ user_payload: {}, kind: {}, author: {:?}, timestamp; {:?}, hash: {:?} }}",
+            payload_string, kind_string, self.fields.author, self.fields.timestamp, self.hash.as_compact()
         ).to_string()
     }
 }
 
 #[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Debug, Getters)]
+#[serde(bound(
+    serialize = "TPayload: PayloadCodec, TGenesisPayload: Serialize, TPeerId: Serialize",
+    deserialize = "TPayload: PayloadCodec, TGenesisPayload: Deserialize<'de>, TPeerId: Deserialize<'de>"
+))]
 pub struct EventFields<TPayload, TGenesisPayload, TPeerId> {
-    user_payload: TPayload,
+    /// Always present in events that are sent or received, `None` only
+    /// after a graph dropped it
+    #[serde(with = "codec::serde_kept_payload")]
+    #[getter(skip)]
+    user_payload: Option<TPayload>,
     kind: Kind<TGenesisPayload>,
     author: TPeerId,
     /// Timestamp set by author
     timestamp: Timestamp,
 }
 
+impl<TPayload, TGenesisPayload, TPeerId> EventFields<TPayload, TGenesisPayload, TPeerId> {
+    /// `None` if the payload was dropped by
+    /// [`Graph::compact`](super::datastructure::Graph::compact)
+    pub fn user_payload(&self) -> Option<&TPayload> {
+        self.user_payload.as_ref()
+    }
+}
+
 impl<TPayload, TGenesisPayload, TPeerId> EventFields<TPayload, TGenesisPayload, TPeerId>
 where
-    TPayload: Serialize,
+    TPayload: PayloadCodec,
     TGenesisPayload: Serialize,
     TPeerId: Serialize,
 {
+    fn hash(&self) -> bincode::Result<Hash> {
+        let mut hasher = Blake2b512::new();
+        hasher.update(self.digest()?);
+        let hash_slice = &hasher.finalize()[..];
+        let hash_arr: [u8; 64] = hash_slice.try_into().expect("event hashing failure");
+        Ok(Hash::from_array(hash_arr))
+    }
+
     fn digest(&self) -> bincode::Result<Vec<u8>> {
         let mut v = vec![];
-        let payload_bytes = bincode::serialize(&self.user_payload)?;
+        let payload = self
+            .user_payload
+            .as_ref()
+            .ok_or(codec::PayloadCodecError::Dropped)?;
+        let payload_bytes = codec::encode_payload(payload)?;
         v.extend(payload_bytes);
         let kind_bytes = bincode::serialize(&self.kind)?;
         v.extend(kind_bytes);
@@ -390,10 +454,20 @@ pub struct Children {
     pub other_children: Vec<Hash>,
 }
 
-impl Into<Vec<Hash>> for Children {
-    fn into(self) -> Vec<Hash> {
-        let mut result: Vec<_> = self.self_child.into();
-        result.extend(self.other_children);
+impl Children {
+    pub fn len(&self) -> usize {
+        self.self_child.len() + self.other_children.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl From<Children> for Vec<Hash> {
+    fn from(val: Children) -> Self {
+        let mut result: Vec<_> = val.self_child.into();
+        result.extend(val.other_children);
         result
     }
 }
@@ -425,6 +499,17 @@ impl SelfChild {
         dishonesty
     }
 
+    pub fn len(&self) -> usize {
+        match self {
+            SelfChild::HonestParent(child_opt) => child_opt.iter().len(),
+            SelfChild::ForkingParent(children_list) => children_list.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     /// Returns `true` if the child was removed (thus was present before removal)
     pub fn with_child_removed(self, child: &Hash) -> Self {
         let self_children_vec: Vec<_> = self.into();
@@ -436,9 +521,9 @@ impl SelfChild {
     }
 }
 
-impl Into<Vec<Hash>> for SelfChild {
-    fn into(self) -> Vec<Hash> {
-        match self {
+impl From<SelfChild> for Vec<Hash> {
+    fn from(val: SelfChild) -> Self {
+        match val {
             SelfChild::HonestParent(child_opt) => child_opt.into_iter().collect(),
             SelfChild::ForkingParent(children_list) => children_list,
         }
@@ -467,9 +552,20 @@ pub enum Kind<TGenesisPayload> {
     Regular(Parents),
 }
 
-impl<G> Into<Vec<Hash>> for Kind<G> {
-    fn into(self) -> Vec<Hash> {
-        match self {
+impl<G> Kind<G> {
+    /// Self parent and other parent, nothing for geneses
+    pub fn parents(&self) -> impl Iterator<Item = &Hash> {
+        let parents = match self {
+            Kind::Genesis(_) => None,
+            Kind::Regular(parents) => Some([&parents.self_parent, &parents.other_parent]),
+        };
+        parents.into_iter().flatten()
+    }
+}
+
+impl<G> From<Kind<G>> for Vec<Hash> {
+    fn from(val: Kind<G>) -> Self {
+        match val {
             Kind::Genesis(_) => vec![],
             Kind::Regular(Parents {
                 self_parent,
@@ -544,7 +640,9 @@ mod tests {
         EventWrapper::new_fakely_signed((), Kind::Genesis(()), 0, 0).unwrap();
         EventWrapper::new_fakely_signed((0,), Kind::Genesis(()), 0, 0).unwrap();
         EventWrapper::new_fakely_signed(vec![()], Kind::Genesis(()), 0, 0).unwrap();
-        EventWrapper::new_fakely_signed("asdassa", Kind::Genesis(()), 0, 0).unwrap();
+        // payloads must be decodable, so borrowed ones like `&str` are not allowed
+        EventWrapper::new_fakely_signed(Box::<str>::from("asdassa"), Kind::Genesis(()), 0, 0)
+            .unwrap();
         EventWrapper::new_fakely_signed("asdassa".to_owned(), Kind::Genesis(()), 0, 0).unwrap();
     }
 
@@ -579,6 +677,15 @@ mod tests {
         assert_eq!(expected_xor, xor);
         let xor = hash2 ^ &hash1;
         assert_eq!(expected_xor, xor);
+
+        let hex = "8a64b55fcfa60235edf16cebbfb36364d6481c3c5ec4de987114ed86c8f252c22\
+                   3fadfa820edd589d9c723f032fdf6c9ca95f2fd95c4ffc01808812d8c1bafea";
+        let hex: String = hex.split_whitespace().collect();
+        assert_eq!(hash1.to_hex(), hex);
+        assert_eq!(Hash::from_hex(&hex.to_uppercase()), Some(hash1.clone()));
+        assert_eq!(Hash::from_hex(&hex[2..]), None);
+        assert_eq!(Hash::from_hex(&hex.replace('8', "g")), None);
+        assert_eq!(Hash::from_hex(&format!("+{}", &hex[1..])), None);
     }
 
     #[test]
@@ -604,4 +711,26 @@ mod tests {
         assert_eq!(hash1.as_compact(), hash1_deserialized.as_compact());
         assert_eq!(hash2.as_compact(), hash2_deserialized.as_compact());
     }
+
+    #[test]
+    fn links_counted_without_collecting() {
+        let events = create_events().unwrap();
+        let mut children = Children {
+            self_child: SelfChild::HonestParent(None),
+            other_children: vec![],
+        };
+        assert!(children.is_empty());
+        for (i, event) in events.iter().enumerate() {
+            children.self_child.add_child(event.hash().clone());
+            assert_eq!(children.len(), i + 1);
+        }
+        children.other_children.push(events[0].hash().clone());
+        assert_eq!(children.len(), events.len() + 1);
+        assert_eq!(Vec::<Hash>::from(children.clone()).len(), children.len());
+
+        for event in events {
+            let expected: Vec<Hash> = event.kind().clone().into();
+            assert!(event.kind().parents().eq(expected.iter()));
+        }
+    }
 }
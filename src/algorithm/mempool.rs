@@ -0,0 +1,276 @@
+//! Queue of transactions waiting for own events.
+//!
+//! A [`Mempool`] takes transactions from several submitters (e.g. clients
+//! of the application) and hands them out in batches, one batch per own
+//! event, like the `Vec<T>` payloads of the `net::node`:
+//! - it is bounded by [`MempoolLimits::max_transactions`]. When it is full,
+//!   a transaction pushes out the newest one of a lower priority, or is
+//!   rejected if there is none;
+//! - transactions are kept in priority lanes, `0` being the most urgent.
+//!   Batches take lane `0` first, and each lane in submission order;
+//! - each submitter has at most [`MempoolLimits::per_submitter`]
+//!   transactions waiting, so a single one can't fill the pool;
+//! - a transaction equal to one already waiting is rejected. Transactions
+//!   that left in a batch are forgotten, so resubmitting them is possible.
+//!   [`Mempool::submit_unless_finalized`] also rejects transactions that a
+//!   [`DedupIndex`] recorded as finalized.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+
+use thiserror::Error;
+
+use super::dedup::{DedupIndex, DedupStore};
+
+/// Lane of a transaction, `0` is drained first
+pub type Priority = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MempoolLimits {
+    pub max_transactions: usize,
+    pub per_submitter: usize,
+    /// Number of priority lanes
+    pub lanes: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    Duplicate,
+    /// Recorded in the [`DedupIndex`]
+    Finalized,
+    QuotaExceeded,
+    /// No transactions of lower priority to push out
+    Full,
+    UnknownLane,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Transaction rejected: {reason:?}")]
+pub struct Rejected<T> {
+    pub transaction: T,
+    pub reason: RejectReason,
+}
+
+/// Transaction pushed out of a full pool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Evicted<T, TSubmitter> {
+    pub submitter: TSubmitter,
+    pub priority: Priority,
+    pub transaction: T,
+}
+
+/// See the [module docs](self)
+pub struct Mempool<T, TSubmitter> {
+    limits: MempoolLimits,
+    lanes: Vec<VecDeque<(TSubmitter, T)>>,
+    pending: HashSet<T>,
+    per_submitter: HashMap<TSubmitter, usize>,
+}
+
+impl<T, TSubmitter> Mempool<T, TSubmitter>
+where
+    T: Eq + Hash + Clone,
+    TSubmitter: Eq + Hash + Clone,
+{
+    pub fn new(limits: MempoolLimits) -> Self {
+        Self {
+            limits,
+            lanes: (0..limits.lanes.max(1)).map(|_| VecDeque::new()).collect(),
+            pending: HashSet::new(),
+            per_submitter: HashMap::new(),
+        }
+    }
+
+    pub fn limits(&self) -> &MempoolLimits {
+        &self.limits
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Transactions of the submitter waiting for a batch
+    pub fn submitted_by(&self, submitter: &TSubmitter) -> usize {
+        self.per_submitter.get(submitter).copied().unwrap_or(0)
+    }
+
+    /// Add the transaction. Returns the transaction pushed out to make
+    /// room for it, if any.
+    pub fn submit(
+        &mut self,
+        submitter: TSubmitter,
+        priority: Priority,
+        transaction: T,
+    ) -> Result<Option<Evicted<T, TSubmitter>>, Rejected<T>> {
+        let reject = |transaction, reason| {
+            Err(Rejected {
+                transaction,
+                reason,
+            })
+        };
+        if priority >= self.lanes.len() {
+            return reject(transaction, RejectReason::UnknownLane);
+        }
+        if self.pending.contains(&transaction) {
+            return reject(transaction, RejectReason::Duplicate);
+        }
+        if self.submitted_by(&submitter) >= self.limits.per_submitter {
+            return reject(transaction, RejectReason::QuotaExceeded);
+        }
+        let mut evicted = None;
+        if self.len() >= self.limits.max_transactions {
+            let Some(lowest) = (priority + 1..self.lanes.len())
+                .rev()
+                .find(|&lane| !self.lanes[lane].is_empty())
+            else {
+                return reject(transaction, RejectReason::Full);
+            };
+            let (submitter, transaction) =
+                self.lanes[lowest].pop_back().expect("lane is not empty");
+            self.forget(&submitter, &transaction);
+            evicted = Some(Evicted {
+                submitter,
+                priority: lowest,
+                transaction,
+            });
+        }
+        self.pending.insert(transaction.clone());
+        *self.per_submitter.entry(submitter.clone()).or_default() += 1;
+        self.lanes[priority].push_back((submitter, transaction));
+        Ok(evicted)
+    }
+
+    /// Like [`submit`](Self::submit), but rejects the transaction if its
+    /// `hash` is in the index of finalized transactions
+    pub fn submit_unless_finalized<K, S>(
+        &mut self,
+        finalized: &DedupIndex<K, S>,
+        hash: &K,
+        submitter: TSubmitter,
+        priority: Priority,
+        transaction: T,
+    ) -> Result<Option<Evicted<T, TSubmitter>>, Rejected<T>>
+    where
+        K: Ord + Hash + Clone,
+        S: DedupStore<K>,
+    {
+        if finalized.contains(hash) {
+            return Err(Rejected {
+                transaction,
+                reason: RejectReason::Finalized,
+            });
+        }
+        self.submit(submitter, priority, transaction)
+    }
+
+    /// Up to `max` transactions for an own event, the most urgent first
+    pub fn next_batch(&mut self, max: usize) -> Vec<T> {
+        let mut batch = vec![];
+        for lane in 0..self.lanes.len() {
+            while batch.len() < max {
+                let Some((submitter, transaction)) = self.lanes[lane].pop_front() else {
+                    break;
+                };
+                self.forget(&submitter, &transaction);
+                batch.push(transaction);
+            }
+        }
+        batch
+    }
+
+    fn forget(&mut self, submitter: &TSubmitter, transaction: &T) {
+        self.pending.remove(transaction);
+        if let Some(count) = self.per_submitter.get_mut(submitter) {
+            *count -= 1;
+            if *count == 0 {
+                self.per_submitter.remove(submitter);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+
+    use super::*;
+
+    fn mempool() -> Mempool<u64, &'static str> {
+        Mempool::new(MempoolLimits {
+            max_transactions: 4,
+            per_submitter: 3,
+            lanes: 2,
+        })
+    }
+
+    fn reason<E: Debug>(result: Result<E, Rejected<u64>>) -> RejectReason {
+        result.unwrap_err().reason
+    }
+
+    #[test]
+    fn admission() {
+        let mut pool = mempool();
+        assert_eq!(pool.submit("a", 1, 10), Ok(None));
+        assert_eq!(reason(pool.submit("b", 0, 10)), RejectReason::Duplicate);
+        assert_eq!(reason(pool.submit("b", 2, 11)), RejectReason::UnknownLane);
+        pool.submit("a", 1, 11).unwrap();
+        pool.submit("a", 0, 12).unwrap();
+        assert_eq!(reason(pool.submit("a", 0, 13)), RejectReason::QuotaExceeded);
+        assert_eq!(pool.submitted_by(&"a"), 3);
+        pool.submit("b", 1, 20).unwrap();
+        assert_eq!(pool.len(), 4);
+
+        // Full: low priority is rejected, high priority pushes out the
+        // newest low priority transaction
+        assert_eq!(reason(pool.submit("c", 1, 30)), RejectReason::Full);
+        assert_eq!(
+            pool.submit("c", 0, 31),
+            Ok(Some(Evicted {
+                submitter: "b",
+                priority: 1,
+                transaction: 20
+            }))
+        );
+        assert_eq!(pool.submitted_by(&"b"), 0);
+        assert_eq!(pool.len(), 4);
+    }
+
+    #[test]
+    fn batches_by_priority() {
+        let mut pool = mempool();
+        pool.submit("a", 1, 1).unwrap();
+        pool.submit("b", 0, 2).unwrap();
+        pool.submit("a", 1, 3).unwrap();
+        pool.submit("b", 0, 4).unwrap();
+        assert_eq!(pool.next_batch(3), vec![2, 4, 1]);
+        assert_eq!(pool.submitted_by(&"b"), 0);
+        assert_eq!(pool.submitted_by(&"a"), 1);
+        // Batched transactions can be submitted again
+        pool.submit("b", 0, 2).unwrap();
+        assert_eq!(pool.next_batch(10), vec![2, 3]);
+        assert!(pool.is_empty());
+        assert!(pool.next_batch(10).is_empty());
+    }
+
+    #[test]
+    fn finalized_rejected() {
+        let directory = std::env::temp_dir().join(format!("mempool-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let store = crate::algorithm::dedup::FileDedupStore::new(&directory);
+        let mut finalized = DedupIndex::open(store, Default::default()).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        finalized.insert(0, 1u64).unwrap();
+        let mut pool = mempool();
+        assert_eq!(
+            reason(pool.submit_unless_finalized(&finalized, &1, "a", 0, 1)),
+            RejectReason::Finalized
+        );
+        pool.submit_unless_finalized(&finalized, &2, "a", 0, 2)
+            .unwrap();
+        assert_eq!(pool.next_batch(10), vec![2]);
+    }
+}
@@ -0,0 +1,168 @@
+//! Operational metrics, reported through the [`metrics`](https://docs.rs/metrics)
+//! facade when the `metrics` feature is enabled. Without the feature all the
+//! functions here are no-ops.
+//!
+//! Exported metrics:
+//! - `hashgraph_events_ingested_total` (counter): events added to the graph;
+//! - `hashgraph_push_rejected_total` (counter, label `category`): rejected
+//!   events by [`PushErrorCategory`];
+//! - `hashgraph_push_duration_seconds` (histogram): time spent in `push_event`,
+//!   including consensus computations triggered by it;
+//! - `hashgraph_fame_elections_total` (counter): fame elections that were run
+//!   (including ones that did not reach a decision);
+//! - `hashgraph_fame_decided_total` (counter): witnesses with decided fame;
+//! - `hashgraph_rounds_decided_total` (counter);
+//! - `hashgraph_pending_orphans` (gauge): events in the pending pool;
+//! - `hashgraph_rounds_behind_finality` (gauge): distance between the latest
+//!   known round and the latest decided one. Growth means consensus stalls;
+//! - `hashgraph_sync_bytes_total` (counter, label `direction`): reported by the
+//!   networking layer with [`record_sync_bytes`];
+//! - `hashgraph_ingress_dropped_total` (counter): received sync messages
+//!   dropped by the [ingress](crate::algorithm::datastructure::sync::ingress)
+//!   queues;
+//! - `hashgraph_sync_events_received_total` (counter, label `outcome`): events
+//!   received in syncs, `new`, `recent_duplicate` (dropped by the dedup window,
+//!   see [`Graph::set_dedup_window`](super::datastructure::Graph::set_dedup_window))
+//!   or `known_duplicate` (found in the graph). Events rejected for other
+//!   reasons are not counted.
+//!
+//! Installing a recorder/exporter (e.g. `metrics-exporter-prometheus`) is up
+//! to the application.
+
+use std::time::Instant;
+
+use super::PushErrorCategory;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncDirection {
+    Sent,
+    Received,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SyncEventOutcome {
+    New,
+    RecentDuplicate,
+    KnownDuplicate,
+}
+
+/// Account for sync traffic, to be called by the transport.
+pub fn record_sync_bytes(direction: SyncDirection, bytes: usize) {
+    imp::sync_bytes(direction, bytes)
+}
+
+pub(crate) fn event_pushed(started: Instant) {
+    imp::event_pushed(started)
+}
+
+pub(crate) fn push_rejected(category: PushErrorCategory, started: Instant) {
+    imp::push_rejected(category, started)
+}
+
+pub(crate) fn fame_election_run() {
+    imp::fame_election_run()
+}
+
+pub(crate) fn fame_decided() {
+    imp::fame_decided()
+}
+
+pub(crate) fn round_decided() {
+    imp::round_decided()
+}
+
+pub(crate) fn pending_orphans(count: usize) {
+    imp::pending_orphans(count)
+}
+
+pub(crate) fn rounds_behind_finality(rounds: usize) {
+    imp::rounds_behind_finality(rounds)
+}
+
+pub(crate) fn ingress_dropped() {
+    imp::ingress_dropped()
+}
+
+pub(crate) fn sync_event_received(outcome: SyncEventOutcome) {
+    imp::sync_event_received(outcome)
+}
+
+#[cfg(feature = "metrics")]
+mod imp {
+    use metrics::{counter, gauge, histogram};
+
+    use super::*;
+
+    pub fn sync_bytes(direction: SyncDirection, bytes: usize) {
+        let direction = match direction {
+            SyncDirection::Sent => "sent",
+            SyncDirection::Received => "received",
+        };
+        counter!("hashgraph_sync_bytes_total", "direction" => direction).increment(bytes as u64);
+    }
+
+    pub fn event_pushed(started: Instant) {
+        counter!("hashgraph_events_ingested_total").increment(1);
+        histogram!("hashgraph_push_duration_seconds").record(started.elapsed().as_secs_f64());
+    }
+
+    pub fn push_rejected(category: PushErrorCategory, started: Instant) {
+        let category = match category {
+            PushErrorCategory::Transient => "transient",
+            PushErrorCategory::Duplicate => "duplicate",
+            PushErrorCategory::PeerMisbehavior => "peer_misbehavior",
+            PushErrorCategory::CallerBug => "caller_bug",
+        };
+        counter!("hashgraph_push_rejected_total", "category" => category).increment(1);
+        histogram!("hashgraph_push_duration_seconds").record(started.elapsed().as_secs_f64());
+    }
+
+    pub fn fame_election_run() {
+        counter!("hashgraph_fame_elections_total").increment(1);
+    }
+
+    pub fn fame_decided() {
+        counter!("hashgraph_fame_decided_total").increment(1);
+    }
+
+    pub fn round_decided() {
+        counter!("hashgraph_rounds_decided_total").increment(1);
+    }
+
+    pub fn pending_orphans(count: usize) {
+        gauge!("hashgraph_pending_orphans").set(count as f64);
+    }
+
+    pub fn rounds_behind_finality(rounds: usize) {
+        gauge!("hashgraph_rounds_behind_finality").set(rounds as f64);
+    }
+
+    pub fn ingress_dropped() {
+        counter!("hashgraph_ingress_dropped_total").increment(1);
+    }
+
+    pub fn sync_event_received(outcome: SyncEventOutcome) {
+        let outcome = match outcome {
+            SyncEventOutcome::New => "new",
+            SyncEventOutcome::RecentDuplicate => "recent_duplicate",
+            SyncEventOutcome::KnownDuplicate => "known_duplicate",
+        };
+        counter!("hashgraph_sync_events_received_total", "outcome" => outcome).increment(1);
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+mod imp {
+    use super::*;
+
+    pub fn sync_bytes(_: SyncDirection, _: usize) {}
+    pub fn event_pushed(_: Instant) {}
+    pub fn push_rejected(_: PushErrorCategory, _: Instant) {}
+    pub fn fame_election_run() {}
+    pub fn fame_decided() {}
+    pub fn round_decided() {}
+    pub fn pending_orphans(_: usize) {}
+    pub fn rounds_behind_finality(_: usize) {}
+    pub fn ingress_dropped() {}
+    pub fn sync_event_received(_: SyncEventOutcome) {}
+}
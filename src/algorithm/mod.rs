@@ -1,4 +1,5 @@
 use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
 
 use blake2::{Blake2b512, Digest};
 use thiserror::Error;
@@ -7,15 +8,25 @@ use crate::Timestamp;
 
 use self::event::{Hash, Signature, WithSignatureCreationError};
 
+pub mod arith;
+pub mod cadence;
+pub mod codec;
+pub mod compression;
+pub mod core;
 pub mod datastructure;
+pub mod dedup;
 pub mod event;
+pub mod mempool;
+pub mod metrics;
+pub mod strategy;
+pub mod threshold;
 
 // u64 must be enough, if new round each 0.1 second
 // then we'll be supplied for >5*10^10 years lol
 //
 // For 10 round/sec and u32 it's 27 years, so
 // TODO put a warning for such case or drop program.
-type RoundNum = usize;
+pub type RoundNum = usize;
 
 pub trait Signer<TGenesisPayload> {
     type SignerIdentity;
@@ -34,6 +45,12 @@ pub trait Signer<TGenesisPayload> {
 #[derive(Clone)]
 pub struct MockSigner<I, G>(PhantomData<(I, G)>);
 
+impl<I, G> Default for MockSigner<I, G> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<I, G> MockSigner<I, G> {
     pub fn new() -> Self {
         Self(PhantomData)
@@ -62,10 +79,14 @@ impl<I, G> Signer<G> for MockSigner<I, G> {
     }
 }
 
+/// Source of time for the graph. It's used for timestamps of events authored
+/// by us and for rejecting events from the future (see
+/// [`Graph::set_max_clock_skew`](datastructure::Graph::set_max_clock_skew)).
 pub trait Clock {
     fn current_timestamp(&mut self) -> Timestamp;
 }
 
+/// System time in nanoseconds since unix epoch.
 impl Clock for () {
     fn current_timestamp(&mut self) -> Timestamp {
         let start = std::time::SystemTime::now();
@@ -76,10 +97,17 @@ impl Clock for () {
     }
 }
 
+#[derive(Clone)]
 pub struct IncrementalClock {
     next_time: u128,
 }
 
+impl Default for IncrementalClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl IncrementalClock {
     pub fn new() -> Self {
         Self { next_time: 0 }
@@ -94,23 +122,138 @@ impl Clock for IncrementalClock {
     }
 }
 
+/// Clock that only moves when told to. Clones share the same time, so a
+/// single handle can drive the clocks of many graphs (e.g. in simulations).
+#[derive(Clone, Default)]
+pub struct ManualClock {
+    time: Arc<Mutex<Timestamp>>,
+}
+
+impl ManualClock {
+    pub fn new(start: Timestamp) -> Self {
+        Self {
+            time: Arc::new(Mutex::new(start)),
+        }
+    }
+
+    pub fn set(&self, time: Timestamp) {
+        *self.time.lock().unwrap() = time;
+    }
+
+    pub fn advance(&self, by: Timestamp) {
+        *self.time.lock().unwrap() += by;
+    }
+
+    pub fn now(&self) -> Timestamp {
+        *self.time.lock().unwrap()
+    }
+}
+
+impl Clock for ManualClock {
+    fn current_timestamp(&mut self) -> Timestamp {
+        self.now()
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum PushError<TPeerId> {
-    #[error("Each peer can have only one genesis")]
-    GenesisAlreadyExists,
-    #[error("Could not find specified parent in the graph. Parent hash: `{0}`")]
-    NoParent(event::Hash),
+    #[error("Each peer can have only one genesis (peer {peer:?} already has `{existing}`)")]
+    GenesisAlreadyExists {
+        peer: TPeerId,
+        existing: event::Hash,
+    },
+    #[error("Could not find parent `{missing_parent}` of event `{event}` by {author:?}")]
+    NoParent {
+        event: event::Hash,
+        author: TPeerId,
+        missing_parent: event::Hash,
+        /// Latest known event of the author at the moment of push
+        local_tip: Option<event::Hash>,
+    },
     #[error("Pushed event is already present in the graph. Hash: `{0}`. Can be triggered if hashes collide for actually different events (although extremely unlikely for 512 bit hash)")]
     EventAlreadyExists(event::Hash),
-    #[error("The author of a regular event is unkown")]
-    PeerNotFound(TPeerId),
-    /// `(expected, provided)`
-    #[error("Provided author is different from author of self parent (expected {0}, provided {1}")]
-    IncorrectAuthor(TPeerId, TPeerId),
+    #[error("The author {peer:?} of regular event `{event}` is unkown")]
+    PeerNotFound { event: event::Hash, peer: TPeerId },
+    #[error("Event `{event}` timestamp {timestamp} is too far ahead of local time {local_time}")]
+    TimestampFromFuture {
+        event: event::Hash,
+        author: TPeerId,
+        timestamp: Timestamp,
+        local_time: Timestamp,
+    },
+    #[error("Author of event `{event}` is different from author of self parent (expected {expected:?}, provided {provided:?})")]
+    IncorrectAuthor {
+        event: event::Hash,
+        expected: TPeerId,
+        provided: TPeerId,
+    },
+    #[error("Hash of event `{event}` by {author:?} doesn't match its contents")]
+    HashMismatch { event: event::Hash, author: TPeerId },
     #[error("Serialization failed")]
     SerializationFailure(#[from] bincode::Error),
-    #[error(transparent)]
-    InvalidSignature(#[from] WithSignatureCreationError),
+    #[error("Could not verify signature of event `{event}` by {author:?}: {source}")]
+    InvalidSignature {
+        event: event::Hash,
+        author: TPeerId,
+        source: WithSignatureCreationError,
+    },
+    #[error("Event `{event}` by {author:?} was verified with another genesis of its author")]
+    VerifiedWithOtherGenesis { event: event::Hash, author: TPeerId },
+}
+
+/// Who is responsible for a [`PushError`], i.e. what to do about it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushErrorCategory {
+    /// The event itself is fine, but can't be accepted yet (e.g. its parents
+    /// are not known). Retry after the next sync.
+    Transient,
+    /// The event is already known, nothing to do.
+    Duplicate,
+    /// The event is invalid regardless of local state. Drop it, its sender
+    /// may be penalized.
+    PeerMisbehavior,
+    /// Incorrect use of the library (e.g. payload that can't be encoded).
+    /// Retrying won't help.
+    CallerBug,
+}
+
+impl<TPeerId> PushError<TPeerId> {
+    pub fn category(&self) -> PushErrorCategory {
+        match self {
+            PushError::NoParent { .. }
+            | PushError::PeerNotFound { .. }
+            | PushError::TimestampFromFuture { .. } => PushErrorCategory::Transient,
+            PushError::EventAlreadyExists(_) => PushErrorCategory::Duplicate,
+            PushError::GenesisAlreadyExists { .. }
+            | PushError::IncorrectAuthor { .. }
+            | PushError::HashMismatch { .. }
+            | PushError::InvalidSignature {
+                source: WithSignatureCreationError::InvalidSignature,
+                ..
+            } => PushErrorCategory::PeerMisbehavior,
+            PushError::SerializationFailure(_)
+            | PushError::VerifiedWithOtherGenesis { .. }
+            | PushError::InvalidSignature {
+                source: WithSignatureCreationError::DigestError(_),
+                ..
+            } => PushErrorCategory::CallerBug,
+        }
+    }
+
+    /// Hash of the rejected event, if known.
+    pub fn event(&self) -> Option<&event::Hash> {
+        match self {
+            PushError::NoParent { event, .. }
+            | PushError::PeerNotFound { event, .. }
+            | PushError::TimestampFromFuture { event, .. }
+            | PushError::IncorrectAuthor { event, .. }
+            | PushError::HashMismatch { event, .. }
+            | PushError::InvalidSignature { event, .. }
+            | PushError::VerifiedWithOtherGenesis { event, .. }
+            | PushError::EventAlreadyExists(event) => Some(event),
+            PushError::GenesisAlreadyExists { .. } | PushError::SerializationFailure(_) => None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,445 @@
+//! Choice of the other parent for new events and of the peer to sync with.
+//!
+//! Both are made from a list of [`PeerCandidate`]s, see
+//! [`Graph::peer_candidates`](super::datastructure::Graph::peer_candidates).
+//! Strategies return an index in this list.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::Duration;
+
+use rand::{Rng, RngCore};
+
+use super::event;
+use crate::Timestamp;
+
+/// Another peer as seen from the local graph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerCandidate<'a, TPeerId> {
+    pub peer: &'a TPeerId,
+    /// Latest known event of the peer
+    pub tip: &'a event::Hash,
+    /// Events known to the peer that are not ancestors of our latest event,
+    /// i.e. what we learn by using its tip as the other parent.
+    pub unknown_to_us: usize,
+    /// Events we have that the peer (as far as we know) doesn't, i.e. what
+    /// it would get from syncing with us.
+    pub unknown_to_peer: usize,
+}
+
+pub trait OtherParentStrategy<TPeerId> {
+    /// `None` if no candidate is suitable (or the list is empty).
+    fn choose_other_parent(&mut self, candidates: &[PeerCandidate<TPeerId>]) -> Option<usize>;
+}
+
+pub trait PeerSelectionStrategy<TPeerId> {
+    /// `None` if no candidate is suitable (or the list is empty).
+    fn choose_peer(&mut self, candidates: &[PeerCandidate<TPeerId>]) -> Option<usize>;
+}
+
+/// Takes the peers in turns: the one chosen the longest time ago (or never)
+/// goes first.
+pub struct RoundRobin<TPeerId> {
+    last_chosen: HashMap<TPeerId, u64>,
+    picks: u64,
+}
+
+impl<TPeerId> Default for RoundRobin<TPeerId> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<TPeerId> RoundRobin<TPeerId> {
+    pub fn new() -> Self {
+        Self {
+            last_chosen: HashMap::new(),
+            picks: 0,
+        }
+    }
+}
+
+impl<TPeerId: Eq + Hash + Clone> RoundRobin<TPeerId> {
+    fn choose(&mut self, candidates: &[PeerCandidate<TPeerId>]) -> Option<usize> {
+        let (index, candidate) = candidates
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, c)| self.last_chosen.get(c.peer).map(|p| p + 1).unwrap_or(0))?;
+        self.picks += 1;
+        self.last_chosen.insert(candidate.peer.clone(), self.picks);
+        Some(index)
+    }
+}
+
+impl<TPeerId: Eq + Hash + Clone> OtherParentStrategy<TPeerId> for RoundRobin<TPeerId> {
+    fn choose_other_parent(&mut self, candidates: &[PeerCandidate<TPeerId>]) -> Option<usize> {
+        self.choose(candidates)
+    }
+}
+
+impl<TPeerId: Eq + Hash + Clone> PeerSelectionStrategy<TPeerId> for RoundRobin<TPeerId> {
+    fn choose_peer(&mut self, candidates: &[PeerCandidate<TPeerId>]) -> Option<usize> {
+        self.choose(candidates)
+    }
+}
+
+/// Uniformly random choice, as in the original gossip protocol.
+pub struct Random<R> {
+    rng: R,
+}
+
+impl<R: RngCore> Random<R> {
+    pub fn new(rng: R) -> Self {
+        Self { rng }
+    }
+
+    fn choose<T>(&mut self, candidates: &[T]) -> Option<usize> {
+        if candidates.is_empty() {
+            None
+        } else {
+            Some(self.rng.gen_range(0..candidates.len()))
+        }
+    }
+}
+
+impl<R: RngCore, TPeerId> OtherParentStrategy<TPeerId> for Random<R> {
+    fn choose_other_parent(&mut self, candidates: &[PeerCandidate<TPeerId>]) -> Option<usize> {
+        self.choose(candidates)
+    }
+}
+
+impl<R: RngCore, TPeerId> PeerSelectionStrategy<TPeerId> for Random<R> {
+    fn choose_peer(&mut self, candidates: &[PeerCandidate<TPeerId>]) -> Option<usize> {
+        self.choose(candidates)
+    }
+}
+
+/// Syncs with the peer we haven't synced with for the longest time. Peers
+/// we never synced with go first.
+///
+/// Unlike [`RoundRobin`], it relies on actual syncs reported with
+/// [`record_sync`](Self::record_sync), not on the previous choices.
+#[derive(Default)]
+pub struct LeastRecentlySynced<TPeerId> {
+    last_synced: HashMap<TPeerId, Timestamp>,
+}
+
+impl<TPeerId: Eq + Hash> LeastRecentlySynced<TPeerId> {
+    pub fn new() -> Self {
+        Self {
+            last_synced: HashMap::new(),
+        }
+    }
+
+    pub fn record_sync(&mut self, peer: TPeerId, time: Timestamp) {
+        self.last_synced.insert(peer, time);
+    }
+
+    pub fn last_synced(&self, peer: &TPeerId) -> Option<Timestamp> {
+        self.last_synced.get(peer).copied()
+    }
+}
+
+impl<TPeerId: Eq + Hash> PeerSelectionStrategy<TPeerId> for LeastRecentlySynced<TPeerId> {
+    fn choose_peer(&mut self, candidates: &[PeerCandidate<TPeerId>]) -> Option<usize> {
+        candidates
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, c)| self.last_synced.get(c.peer))
+            .map(|(i, _)| i)
+    }
+}
+
+/// Measured usefulness of a peer, see [`Scored`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeerScore {
+    /// Smoothed round-trip time of a sync
+    pub rtt: Duration,
+    /// Smoothed number of new events received per sync
+    pub new_events: f64,
+    pub syncs: u64,
+    /// Time of the latest sync
+    pub updated_at: Timestamp,
+}
+
+impl PeerScore {
+    /// New events per second spent syncing
+    pub fn events_per_second(&self) -> f64 {
+        // Avoid infinities for local peers
+        self.new_events / self.rtt.as_secs_f64().max(1e-3)
+    }
+}
+
+/// Picks peers at random, biased towards the ones that delivered the most
+/// new events per second of sync recently. Syncs are reported with
+/// [`record_sync`](Self::record_sync).
+///
+/// Influence of a measurement halves every `half_life`: new measurements
+/// weigh more, and scores of peers not synced with for a while drift to the
+/// average. Peers without measurements are treated as the best ones, so
+/// that they are tried, and every peer keeps a small chance to be picked to
+/// notice improvements.
+pub struct Scored<TPeerId, R> {
+    rng: R,
+    scores: HashMap<TPeerId, PeerScore>,
+    half_life: Duration,
+    /// Latest time reported
+    now: Timestamp,
+}
+
+/// Weight of the previous value when a new measurement arrives right away
+const SMOOTHING: f64 = 0.8;
+/// Chance of the worst peers relative to the best one
+const EXPLORATION: f64 = 0.05;
+
+impl<TPeerId: Eq + Hash, R: RngCore> Scored<TPeerId, R> {
+    pub fn new(rng: R, half_life: Duration) -> Self {
+        Self {
+            rng,
+            scores: HashMap::new(),
+            half_life,
+            now: 0,
+        }
+    }
+
+    /// Account for a sync with `peer` that took `rtt` and brought
+    /// `new_events`. Failed syncs should be reported as well, e.g. with the
+    /// timeout as `rtt` and no events.
+    pub fn record_sync(&mut self, peer: TPeerId, rtt: Duration, new_events: usize, now: Timestamp) {
+        self.now = self.now.max(now);
+        let new_events = new_events as f64;
+        let half_life = self.half_life;
+        self.scores
+            .entry(peer)
+            .and_modify(|score| {
+                let old = SMOOTHING * decay(half_life, now.saturating_sub(score.updated_at));
+                score.rtt = rtt.mul_f64(1.0 - old) + score.rtt.mul_f64(old);
+                score.new_events = new_events * (1.0 - old) + score.new_events * old;
+                score.syncs += 1;
+                score.updated_at = score.updated_at.max(now);
+            })
+            .or_insert(PeerScore {
+                rtt,
+                new_events,
+                syncs: 1,
+                updated_at: now,
+            });
+    }
+
+    pub fn score(&self, peer: &TPeerId) -> Option<&PeerScore> {
+        self.scores.get(peer)
+    }
+
+    /// All peers synced with so far, e.g. for monitoring
+    pub fn scores(&self) -> impl Iterator<Item = (&TPeerId, &PeerScore)> {
+        self.scores.iter()
+    }
+
+    /// Forget the peer, e.g. once it's disconnected
+    pub fn remove(&mut self, peer: &TPeerId) {
+        self.scores.remove(peer);
+    }
+
+    /// Choose from the peers, giving the index
+    pub fn choose<'a>(&mut self, peers: impl IntoIterator<Item = &'a TPeerId>) -> Option<usize>
+    where
+        TPeerId: 'a,
+    {
+        let weights = self.weights(peers);
+        let total: f64 = weights.iter().sum();
+        if weights.is_empty() {
+            return None;
+        }
+        if total <= 0.0 || !total.is_finite() {
+            return Some(self.rng.gen_range(0..weights.len()));
+        }
+        let mut point = self.rng.gen_range(0.0..total);
+        for (i, weight) in weights.iter().enumerate() {
+            if point < *weight {
+                return Some(i);
+            }
+            point -= weight;
+        }
+        Some(weights.len() - 1)
+    }
+
+    fn weights<'a>(&self, peers: impl IntoIterator<Item = &'a TPeerId>) -> Vec<f64>
+    where
+        TPeerId: 'a,
+    {
+        let measured: Vec<_> = self
+            .scores
+            .values()
+            .map(|s| (s.events_per_second(), s.updated_at))
+            .collect();
+        let average = if measured.is_empty() {
+            1.0
+        } else {
+            measured.iter().map(|(v, _)| v).sum::<f64>() / measured.len() as f64
+        };
+        let current = |(value, updated_at): (f64, Timestamp)| {
+            average + (value - average) * decay(self.half_life, self.now.saturating_sub(updated_at))
+        };
+        let best = measured
+            .iter()
+            .copied()
+            .map(current)
+            .fold(average, f64::max);
+        let floor = best * EXPLORATION;
+        peers
+            .into_iter()
+            .map(|peer| match self.scores.get(peer) {
+                Some(score) => current((score.events_per_second(), score.updated_at)),
+                None => best,
+            })
+            .map(|value| value.max(floor))
+            .collect()
+    }
+}
+
+/// Part of a value remaining after `elapsed` nanoseconds
+fn decay(half_life: Duration, elapsed: Timestamp) -> f64 {
+    let half_life = half_life.as_nanos();
+    if half_life == 0 {
+        return 0.0;
+    }
+    0.5f64.powf(elapsed as f64 / half_life as f64)
+}
+
+impl<TPeerId: Eq + Hash, R: RngCore> PeerSelectionStrategy<TPeerId> for Scored<TPeerId, R> {
+    fn choose_peer(&mut self, candidates: &[PeerCandidate<TPeerId>]) -> Option<usize> {
+        self.choose(candidates.iter().map(|c| c.peer))
+    }
+}
+
+/// Greedy choice of the candidate that transfers the most events: the other
+/// parent that brings the most new events to us, or the peer that gets the
+/// most events from a sync.
+///
+/// Ties are resolved in favor of the first candidate.
+#[derive(Default, Clone, Copy)]
+pub struct MostNewEvents;
+
+fn first_max_by_key<T>(candidates: &[T], key: impl Fn(&T) -> usize) -> Option<usize> {
+    candidates
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|(_, c)| key(c))
+        .map(|(i, _)| i)
+}
+
+impl<TPeerId> OtherParentStrategy<TPeerId> for MostNewEvents {
+    fn choose_other_parent(&mut self, candidates: &[PeerCandidate<TPeerId>]) -> Option<usize> {
+        first_max_by_key(candidates, |c| c.unknown_to_us)
+    }
+}
+
+impl<TPeerId> PeerSelectionStrategy<TPeerId> for MostNewEvents {
+    fn choose_peer(&mut self, candidates: &[PeerCandidate<TPeerId>]) -> Option<usize> {
+        first_max_by_key(candidates, |c| c.unknown_to_peer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn candidates<'a>(
+        peers: &'a [u64],
+        tip: &'a event::Hash,
+        unknown: &[(usize, usize)],
+    ) -> Vec<PeerCandidate<'a, u64>> {
+        peers
+            .iter()
+            .zip(unknown)
+            .map(|(peer, &(unknown_to_us, unknown_to_peer))| PeerCandidate {
+                peer,
+                tip,
+                unknown_to_us,
+                unknown_to_peer,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn strategies_choose_correctly() {
+        let tip = event::Hash::from_array([0; 64]);
+        let peers = [10, 11, 12];
+        let list = candidates(&peers, &tip, &[(1, 5), (3, 0), (3, 7)]);
+
+        let mut round_robin = RoundRobin::new();
+        let picks = (0..6)
+            .map(|_| round_robin.choose_peer(&list).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(picks, vec![0, 1, 2, 0, 1, 2]);
+        // New peers are preferred
+        let more_peers = [10, 11, 12, 13];
+        let longer_list = candidates(&more_peers, &tip, &[(0, 0); 4]);
+        assert_eq!(round_robin.choose_other_parent(&longer_list), Some(3));
+
+        assert_eq!(MostNewEvents.choose_other_parent(&list), Some(1));
+        assert_eq!(MostNewEvents.choose_peer(&list), Some(2));
+
+        let mut least_recent = LeastRecentlySynced::new();
+        least_recent.record_sync(10, 5);
+        least_recent.record_sync(12, 3);
+        assert_eq!(least_recent.choose_peer(&list), Some(1));
+        least_recent.record_sync(11, 7);
+        assert_eq!(least_recent.choose_peer(&list), Some(2));
+
+        let mut random = Random::new(rand::rngs::StdRng::seed_from_u64(0));
+        for _ in 0..10 {
+            assert!(random.choose_peer(&list).unwrap() < list.len());
+        }
+
+        let mut scored = Scored::new(rand::rngs::StdRng::seed_from_u64(0), Duration::ZERO);
+        for _ in 0..10 {
+            assert!(scored.choose_peer(&list).unwrap() < list.len());
+        }
+
+        let empty: Vec<PeerCandidate<u64>> = vec![];
+        assert_eq!(RoundRobin::new().choose_peer(&empty), None);
+        assert_eq!(MostNewEvents.choose_other_parent(&empty), None);
+        assert_eq!(LeastRecentlySynced::new().choose_peer(&empty), None);
+        assert_eq!(random.choose_other_parent(&empty), None);
+        assert_eq!(scored.choose_peer(&empty), None);
+    }
+
+    #[test]
+    fn scores_bias_choice() {
+        const SECOND: Timestamp = 1_000_000_000;
+        let mut scored = Scored::new(
+            rand::rngs::StdRng::seed_from_u64(0),
+            Duration::from_secs(10),
+        );
+        let ms = Duration::from_millis;
+        scored.record_sync(10, ms(10), 20, 0);
+        scored.record_sync(11, ms(500), 1, 0);
+        // Newer measurements weigh more
+        scored.record_sync(11, ms(500), 11, 0);
+        let score = scored.score(&11).unwrap();
+        assert_eq!(score.syncs, 2);
+        assert!((score.new_events - 3.0).abs() < 1e-9);
+        assert_eq!(scored.scores().count(), 2);
+
+        let peers = [10, 11, 12];
+        let mut picks = [0; 3];
+        for _ in 0..1000 {
+            picks[scored.choose(&peers).unwrap()] += 1;
+        }
+        // Unknown peer is as good as the best one, the slow one is rarely
+        // chosen but still is
+        assert!(picks[0] > 300 && picks[2] > 300, "{:?}", picks);
+        assert!(picks[1] > 0 && picks[1] < 100, "{:?}", picks);
+
+        // Old measurements fade
+        let weights = scored.weights(&peers[..2]);
+        assert!(weights[0] > 10.0 * weights[1]);
+        scored.record_sync(12, ms(100), 0, 100 * SECOND);
+        let weights = scored.weights(&peers[..2]);
+        assert!((weights[0] - weights[1]).abs() < 0.01 * weights[0]);
+    }
+}
@@ -0,0 +1,371 @@
+//! Members run jointly by a group.
+//!
+//! An organization can run one validator on several machines, so that no
+//! single one of them holds its key. The member is registered with a
+//! [`MemberKey::Group`] record in its genesis payload: the group key and the
+//! keys of the parties, at least `threshold` of which must take part in
+//! signing an event. Each of them signs the event hash with its share of the
+//! key, and a [`SigningSession`] combines the shares into one signature
+//! that fits into the event's signature field. Other members check it
+//! against the group key with [`ThresholdSigner`], without knowing which
+//! parties signed.
+//!
+//! The cryptography comes from a [`ThresholdScheme`] (e.g. threshold BLS or
+//! FROST). Events of a group are authored in two steps:
+//! [`Graph::draft_event`](crate::algorithm::datastructure::Graph::draft_event)
+//! makes the unsigned event, and
+//! [`push_event`](crate::algorithm::datastructure::Graph::push_event) adds
+//! it once the session produced the signature.
+
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+use blake2::{Blake2b512, Digest};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::event::{Hash, Signature};
+use super::Signer;
+
+/// Key of a member run by a group
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GroupKey<K> {
+    /// Verifies the combined signatures
+    pub key: K,
+    /// Verify the shares of the parties
+    pub parties: Vec<K>,
+    /// Shares needed for a signature
+    pub threshold: usize,
+}
+
+impl<K> GroupKey<K> {
+    pub fn is_valid(&self) -> bool {
+        (1..=self.parties.len()).contains(&self.threshold)
+    }
+}
+
+/// How signatures of a member's events are checked
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MemberKey<K> {
+    Single(K),
+    Group(GroupKey<K>),
+}
+
+/// Genesis payloads that carry a [`MemberKey`]
+pub trait MembershipRecord<K> {
+    fn member_key(&self) -> &MemberKey<K>;
+}
+
+impl<K> MembershipRecord<K> for MemberKey<K> {
+    fn member_key(&self) -> &MemberKey<K> {
+        self
+    }
+}
+
+/// Signature scheme where shares of a group key combine into a signature
+/// verifiable with the group key itself
+pub trait ThresholdScheme {
+    type PublicKey;
+    type SecretKey;
+    type Share;
+
+    fn sign(&self, secret: &Self::SecretKey, hash: &Hash) -> Signature;
+    fn verify(&self, key: &Self::PublicKey, hash: &Hash, signature: &Signature) -> bool;
+    /// Share of a party holding `secret`
+    fn sign_share(&self, secret: &Self::SecretKey, hash: &Hash) -> Self::Share;
+    fn verify_share(
+        &self,
+        group: &GroupKey<Self::PublicKey>,
+        party: usize,
+        hash: &Hash,
+        share: &Self::Share,
+    ) -> bool;
+    /// Combine `threshold` verified shares, keyed by party
+    fn combine(
+        &self,
+        group: &GroupKey<Self::PublicKey>,
+        hash: &Hash,
+        shares: &BTreeMap<usize, Self::Share>,
+    ) -> Signature;
+}
+
+/// Signer for graphs whose genesis payloads are [membership
+/// records](MembershipRecord). Own events are signed with an ordinary key,
+/// events of any member are verified against its record.
+#[derive(Clone)]
+pub struct ThresholdSigner<S: ThresholdScheme, I> {
+    scheme: S,
+    secret: S::SecretKey,
+    _identity: PhantomData<I>,
+}
+
+impl<S: ThresholdScheme, I> ThresholdSigner<S, I> {
+    pub fn new(scheme: S, secret: S::SecretKey) -> Self {
+        Self {
+            scheme,
+            secret,
+            _identity: PhantomData,
+        }
+    }
+}
+
+impl<S, I, G> Signer<G> for ThresholdSigner<S, I>
+where
+    S: ThresholdScheme,
+    G: MembershipRecord<S::PublicKey>,
+{
+    type SignerIdentity = I;
+
+    fn sign(&self, event_hash: &Hash) -> Signature {
+        self.scheme.sign(&self.secret, event_hash)
+    }
+
+    fn verify(
+        &self,
+        event_hash: &Hash,
+        signature: &Signature,
+        _identity: &I,
+        genesis_payload: &G,
+    ) -> bool {
+        match genesis_payload.member_key() {
+            MemberKey::Single(key) => self.scheme.verify(key, event_hash, signature),
+            MemberKey::Group(group) => {
+                group.is_valid() && self.scheme.verify(&group.key, event_hash, signature)
+            }
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ShareError {
+    #[error("The group has no party #{0}")]
+    UnknownParty(usize),
+    #[error("Party #{0} already provided a share")]
+    Duplicate(usize),
+    #[error("Share of party #{0} is invalid")]
+    InvalidShare(usize),
+    #[error("Combined signature does not match the group key")]
+    InvalidSignature,
+    #[error("Group needs {threshold} of {parties} shares, which is impossible")]
+    InvalidGroup { threshold: usize, parties: usize },
+}
+
+/// Shares of the parties for one event hash
+pub struct SigningSession<'a, S: ThresholdScheme> {
+    scheme: &'a S,
+    group: &'a GroupKey<S::PublicKey>,
+    hash: Hash,
+    shares: BTreeMap<usize, S::Share>,
+}
+
+impl<'a, S: ThresholdScheme> SigningSession<'a, S> {
+    pub fn new(
+        scheme: &'a S,
+        group: &'a GroupKey<S::PublicKey>,
+        hash: Hash,
+    ) -> Result<Self, ShareError> {
+        if !group.is_valid() {
+            return Err(ShareError::InvalidGroup {
+                threshold: group.threshold,
+                parties: group.parties.len(),
+            });
+        }
+        Ok(Self {
+            scheme,
+            group,
+            hash,
+            shares: BTreeMap::new(),
+        })
+    }
+
+    pub fn hash(&self) -> &Hash {
+        &self.hash
+    }
+
+    /// Parties that provided a share
+    pub fn signed_by(&self) -> impl Iterator<Item = usize> + '_ {
+        self.shares.keys().copied()
+    }
+
+    /// Returns the signature once `threshold` shares are collected
+    pub fn add_share(
+        &mut self,
+        party: usize,
+        share: S::Share,
+    ) -> Result<Option<Signature>, ShareError> {
+        if party >= self.group.parties.len() {
+            return Err(ShareError::UnknownParty(party));
+        }
+        if self.shares.contains_key(&party) {
+            return Err(ShareError::Duplicate(party));
+        }
+        if !self
+            .scheme
+            .verify_share(self.group, party, &self.hash, &share)
+        {
+            return Err(ShareError::InvalidShare(party));
+        }
+        self.shares.insert(party, share);
+        if self.shares.len() < self.group.threshold {
+            return Ok(None);
+        }
+        let signature = self.scheme.combine(self.group, &self.hash, &self.shares);
+        match self.scheme.verify(&self.group.key, &self.hash, &signature) {
+            true => Ok(Some(signature)),
+            false => Err(ShareError::InvalidSignature),
+        }
+    }
+}
+
+/// Scheme with keys that are their own public keys. Insecure, for tests
+/// and simulations only.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MockThreshold;
+
+impl ThresholdScheme for MockThreshold {
+    type PublicKey = u64;
+    type SecretKey = u64;
+    type Share = Signature;
+
+    fn sign(&self, secret: &u64, hash: &Hash) -> Signature {
+        let mut hasher = Blake2b512::new();
+        hasher.update(secret.to_le_bytes());
+        hasher.update(hash.as_ref());
+        let hash_slice = &hasher.finalize()[..];
+        Signature(Hash::from_array(hash_slice.try_into().unwrap()))
+    }
+
+    fn verify(&self, key: &u64, hash: &Hash, signature: &Signature) -> bool {
+        &self.sign(key, hash) == signature
+    }
+
+    fn sign_share(&self, secret: &u64, hash: &Hash) -> Signature {
+        self.sign(secret, hash)
+    }
+
+    fn verify_share(
+        &self,
+        group: &GroupKey<u64>,
+        party: usize,
+        hash: &Hash,
+        share: &Signature,
+    ) -> bool {
+        group
+            .parties
+            .get(party)
+            .is_some_and(|key| self.verify(key, hash, share))
+    }
+
+    fn combine(
+        &self,
+        group: &GroupKey<u64>,
+        hash: &Hash,
+        _shares: &BTreeMap<usize, Signature>,
+    ) -> Signature {
+        self.sign(&group.key, hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::datastructure::{EventCreateError, Graph};
+    use crate::algorithm::event::{Kind, UnsignedEvent};
+    use crate::algorithm::{IncrementalClock, PushError};
+
+    type GroupGraph =
+        Graph<(), MemberKey<u64>, u64, ThresholdSigner<MockThreshold, u64>, IncrementalClock>;
+
+    fn group() -> GroupKey<u64> {
+        GroupKey {
+            key: 100,
+            parties: vec![101, 102, 103],
+            threshold: 2,
+        }
+    }
+
+    fn group_signature(hash: &Hash, parties: &[usize]) -> Result<Option<Signature>, ShareError> {
+        let group = group();
+        let mut session = SigningSession::new(&MockThreshold, &group, hash.clone())?;
+        let mut signature = None;
+        for &party in parties {
+            signature =
+                session.add_share(party, MockThreshold.sign_share(&(101 + party as u64), hash))?;
+        }
+        Ok(signature)
+    }
+
+    #[test]
+    fn session_combines_shares() {
+        let hash = Hash::from_array([1; 64]);
+        let group = group();
+        let mut session = SigningSession::new(&MockThreshold, &group, hash.clone()).unwrap();
+        let share = |secret| MockThreshold.sign_share(&secret, &hash);
+        assert_eq!(
+            session.add_share(3, share(104)),
+            Err(ShareError::UnknownParty(3))
+        );
+        assert_eq!(
+            session.add_share(0, share(102)),
+            Err(ShareError::InvalidShare(0))
+        );
+        assert_eq!(session.add_share(1, share(102)), Ok(None));
+        assert_eq!(
+            session.add_share(1, share(102)),
+            Err(ShareError::Duplicate(1))
+        );
+        let signature = session.add_share(2, share(103)).unwrap().unwrap();
+        assert!(MockThreshold.verify(&100, &hash, &signature));
+        assert_eq!(session.signed_by().collect::<Vec<_>>(), vec![1, 2]);
+
+        let impossible = GroupKey {
+            threshold: 4,
+            ..group
+        };
+        assert!(matches!(
+            SigningSession::new(&MockThreshold, &impossible, hash),
+            Err(ShareError::InvalidGroup {
+                threshold: 4,
+                parties: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn group_member_authors_events() {
+        let mut graph = GroupGraph::new(
+            1,
+            (),
+            MemberKey::Single(1),
+            999,
+            ThresholdSigner::new(MockThreshold, 1),
+            IncrementalClock::new(),
+        );
+        let genesis =
+            UnsignedEvent::draft((), Kind::Genesis(MemberKey::Group(group())), 2, 0).unwrap();
+        let signature = group_signature(genesis.hash(), &[0, 2]).unwrap().unwrap();
+        graph.push_event(genesis.clone(), signature).unwrap();
+
+        let self_tip = graph.self_tip().unwrap().clone();
+        let event = graph.draft_event(&2, (), self_tip.clone()).unwrap();
+        assert_eq!(graph.peer_latest_event(&2), Some(genesis.hash()));
+        // One share is not enough, and the key of a single party is not the
+        // group's
+        assert_eq!(group_signature(event.hash(), &[1]), Ok(None));
+        let party_signature = MockThreshold.sign(&102, event.hash());
+        assert!(matches!(
+            graph.push_event(event.clone(), party_signature),
+            Err(PushError::InvalidSignature { .. })
+        ));
+        let signature = group_signature(event.hash(), &[1, 0]).unwrap().unwrap();
+        graph.push_event(event.clone(), signature).unwrap();
+        assert_eq!(graph.peer_latest_event(&2), Some(event.hash()));
+
+        // Own events are signed as usual
+        graph.create_event((), event.hash().clone()).unwrap();
+        assert!(matches!(
+            graph.draft_event(&3, (), self_tip),
+            Err(EventCreateError::UnknownPeer(3))
+        ));
+    }
+}
@@ -0,0 +1,162 @@
+//! Terminal browser for graphs exported with
+//! [`Graph::write_json`](rust_hashgraph::algorithm::datastructure::Graph::write_json).
+//!
+//! Usage: `hashgraph-inspect <export.json>`
+//!
+//! Keys:
+//! - `←`/`→`: previous/next round, `↑`/`↓`: select event;
+//! - `w`: show only witnesses;
+//! - `s`/`o`: go to self/other parent, `c`: go to the first child;
+//! - `b`: go back to the event selected before the last jump;
+//! - `q`/`Esc`: quit.
+//!
+//! For witnesses, votes of the next round witnesses are shown, which helps
+//! to compare fame decisions between exports of different nodes.
+
+mod model;
+
+use std::fs::File;
+use std::io::{self, BufReader};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use rust_hashgraph::algorithm::datastructure::export::json::JsonGraph;
+
+use model::{fame_label, Inspector};
+
+fn main() -> io::Result<()> {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("Usage: hashgraph-inspect <export.json>");
+        std::process::exit(2);
+    };
+    let graph = JsonGraph::from_reader(BufReader::new(File::open(&path)?))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut inspector = Inspector::new(graph);
+
+    let terminal = ratatui::init();
+    let result = run(terminal, &mut inspector);
+    ratatui::restore();
+    result
+}
+
+fn run(mut terminal: DefaultTerminal, inspector: &mut Inspector) -> io::Result<()> {
+    loop {
+        terminal.draw(|frame| draw(frame, inspector))?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Left => inspector.previous_round(),
+            KeyCode::Right => inspector.next_round(),
+            KeyCode::Up => inspector.previous_event(),
+            KeyCode::Down => inspector.next_event(),
+            KeyCode::Char('w') => inspector.toggle_witnesses(),
+            KeyCode::Char('s') => inspector.jump_to_self_parent(),
+            KeyCode::Char('o') => inspector.jump_to_other_parent(),
+            KeyCode::Char('c') => inspector.jump_to_child(),
+            KeyCode::Char('b') => inspector.back(),
+            _ => (),
+        }
+    }
+}
+
+fn short(hash: &str) -> &str {
+    &hash[..hash.len().min(8)]
+}
+
+fn draw(frame: &mut Frame, inspector: &Inspector) {
+    let [list_area, details_area] =
+        Layout::horizontal([Constraint::Length(48), Constraint::Min(0)]).areas(frame.area());
+
+    let items: Vec<_> = inspector
+        .round_events()
+        .into_iter()
+        .map(|e| {
+            let fame = fame_label(e.witness);
+            Line::from(format!("{} {} {}", short(&e.hash), e.author, fame))
+        })
+        .collect();
+    let title = format!(
+        " Round {}/{}{} ",
+        inspector.round(),
+        inspector.rounds_count() - 1,
+        if inspector.only_witnesses() {
+            ", witnesses"
+        } else {
+            ""
+        }
+    );
+    let list = List::new(items)
+        .block(Block::bordered().title(title))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(Some(inspector.selected_index()));
+    frame.render_stateful_widget(list, list_area, &mut state);
+
+    let details = match inspector.selected() {
+        Some(e) => details(inspector, e),
+        None => vec![Line::from("No events")],
+    };
+    frame.render_widget(
+        Paragraph::new(details)
+            .block(Block::bordered().title(" Event "))
+            .wrap(Wrap { trim: false }),
+        details_area,
+    );
+}
+
+fn details<'a>(inspector: &'a Inspector, e: &'a model::Event) -> Vec<Line<'a>> {
+    let describe = |hash: &Option<String>| match hash {
+        Some(h) => match inspector.event(h) {
+            Some(p) => format!("{} by {} (round {})", short(h), p.author, p.round),
+            None => format!("{} (not exported)", short(h)),
+        },
+        None => "-".to_owned(),
+    };
+    let optional = |value: Option<usize>| value.map(|v| v.to_string()).unwrap_or("-".to_owned());
+    let mut lines = vec![
+        Line::from(format!("hash:           {}", e.hash)),
+        Line::from(format!("author:         {}", e.author)),
+        Line::from(format!("timestamp:      {}", e.timestamp)),
+        Line::from(format!("round:          {}", e.round)),
+        Line::from(format!("witness:        {}", fame_label(e.witness))),
+        Line::from(format!("round received: {}", optional(e.round_received))),
+        Line::from(format!(
+            "order position: {}",
+            optional(inspector.position(&e.hash))
+        )),
+        Line::from(format!("in fork:        {}", e.in_fork)),
+        Line::from(format!("self parent:    {}", describe(&e.self_parent))),
+        Line::from(format!("other parent:   {}", describe(&e.other_parent))),
+        Line::from("children:"),
+    ];
+    for child in inspector.children(&e.hash) {
+        lines.push(Line::from(format!(
+            "  {} by {} (round {})",
+            short(&child.hash),
+            child.author,
+            child.round
+        )));
+    }
+    if let Some(votes) = inspector.votes(&e.hash) {
+        lines.push(Line::from(format!(
+            "votes of round {} witnesses (first voting round):",
+            e.round + 1
+        )));
+        for (vote, voters) in [("yes", &votes.yes), ("no", &votes.no)] {
+            let voters: Vec<_> = voters
+                .iter()
+                .map(|v| format!("{} by {}", short(&v.hash), v.author))
+                .collect();
+            lines.push(Line::from(format!("  {}: {}", vote, voters.join(", "))));
+        }
+    }
+    lines
+}
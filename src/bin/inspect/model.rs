@@ -0,0 +1,328 @@
+//! Navigation state of the inspector, independent of the terminal.
+
+use std::collections::{HashMap, HashSet};
+
+use rust_hashgraph::algorithm::datastructure::export::json::{JsonEvent, JsonFame, JsonGraph};
+use serde_json::Value;
+
+pub type Event = JsonEvent<Value>;
+
+pub struct Inspector {
+    graph: JsonGraph<Value>,
+    by_hash: HashMap<String, usize>,
+    children: Vec<Vec<usize>>,
+    /// Indices of events of each round, witnesses first
+    rounds: Vec<Vec<usize>>,
+    round: usize,
+    selected: usize,
+    only_witnesses: bool,
+    /// Previously selected events, for going back after jumps
+    history: Vec<usize>,
+}
+
+/// Fame votes of the next round witnesses for a witness: in the first
+/// voting round a witness votes "yes" iff it sees the candidate.
+pub struct Votes<'a> {
+    pub yes: Vec<&'a Event>,
+    pub no: Vec<&'a Event>,
+}
+
+impl Inspector {
+    pub fn new(graph: JsonGraph<Value>) -> Self {
+        let by_hash: HashMap<_, _> = graph
+            .events
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.hash.clone(), i))
+            .collect();
+        let mut children = vec![vec![]; graph.events.len()];
+        let max_round = graph.events.iter().map(|e| e.round).max().unwrap_or(0);
+        let mut rounds = vec![vec![]; max_round + 1];
+        for (i, e) in graph.events.iter().enumerate() {
+            for parent in e.self_parent.iter().chain(e.other_parent.iter()) {
+                if let Some(&p) = by_hash.get(parent) {
+                    children[p].push(i);
+                }
+            }
+            rounds[e.round].push(i);
+        }
+        for events in &mut rounds {
+            events.sort_by_key(|&i| {
+                let e = &graph.events[i];
+                (e.witness.is_none(), e.timestamp, e.hash.clone())
+            });
+        }
+        Self {
+            graph,
+            by_hash,
+            children,
+            rounds,
+            round: 0,
+            selected: 0,
+            only_witnesses: false,
+            history: vec![],
+        }
+    }
+
+    pub fn rounds_count(&self) -> usize {
+        self.rounds.len()
+    }
+
+    pub fn round(&self) -> usize {
+        self.round
+    }
+
+    pub fn only_witnesses(&self) -> bool {
+        self.only_witnesses
+    }
+
+    /// Events of the current round shown in the list
+    pub fn round_events(&self) -> Vec<&Event> {
+        self.visible(self.round)
+            .into_iter()
+            .map(|i| &self.graph.events[i])
+            .collect()
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected(&self) -> Option<&Event> {
+        self.selected_id().map(|i| &self.graph.events[i])
+    }
+
+    pub fn event(&self, hash: &str) -> Option<&Event> {
+        self.by_hash.get(hash).map(|&i| &self.graph.events[i])
+    }
+
+    pub fn children(&self, hash: &str) -> Vec<&Event> {
+        self.by_hash
+            .get(hash)
+            .map(|&i| {
+                self.children[i]
+                    .iter()
+                    .map(|&c| &self.graph.events[c])
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Position of the event in the consensus order
+    pub fn position(&self, hash: &str) -> Option<usize> {
+        self.graph.ordering.iter().position(|h| h == hash)
+    }
+
+    /// `None` if the event is not a witness or there are no witnesses in the
+    /// next round yet
+    pub fn votes(&self, hash: &str) -> Option<Votes<'_>> {
+        let &candidate = self.by_hash.get(hash)?;
+        let event = &self.graph.events[candidate];
+        event.witness?;
+        let voters = self.rounds.get(event.round + 1)?;
+        let mut votes = Votes {
+            yes: vec![],
+            no: vec![],
+        };
+        for &voter in voters {
+            let voter_event = &self.graph.events[voter];
+            if voter_event.witness.is_none() {
+                continue;
+            }
+            if self.sees(voter, candidate) {
+                votes.yes.push(voter_event);
+            } else {
+                votes.no.push(voter_event);
+            }
+        }
+        Some(votes)
+    }
+
+    pub fn next_round(&mut self) {
+        if self.round + 1 < self.rounds.len() {
+            self.round += 1;
+            self.selected = 0;
+        }
+    }
+
+    pub fn previous_round(&mut self) {
+        if self.round > 0 {
+            self.round -= 1;
+            self.selected = 0;
+        }
+    }
+
+    pub fn next_event(&mut self) {
+        if self.selected + 1 < self.visible(self.round).len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn previous_event(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn toggle_witnesses(&mut self) {
+        let current = self.selected_id();
+        self.only_witnesses = !self.only_witnesses;
+        self.selected = current
+            .and_then(|c| self.visible(self.round).iter().position(|&i| i == c))
+            .unwrap_or(0);
+    }
+
+    pub fn jump_to_self_parent(&mut self) {
+        let parent = self.selected().and_then(|e| e.self_parent.clone());
+        if let Some(parent) = parent {
+            self.jump(&parent);
+        }
+    }
+
+    pub fn jump_to_other_parent(&mut self) {
+        let parent = self.selected().and_then(|e| e.other_parent.clone());
+        if let Some(parent) = parent {
+            self.jump(&parent);
+        }
+    }
+
+    pub fn jump_to_child(&mut self) {
+        let child = self
+            .selected()
+            .and_then(|e| self.children(&e.hash).first().map(|c| c.hash.clone()));
+        if let Some(child) = child {
+            self.jump(&child);
+        }
+    }
+
+    /// Return to the event selected before the last jump
+    pub fn back(&mut self) {
+        if let Some(previous) = self.history.pop() {
+            self.select(previous);
+        }
+    }
+
+    fn jump(&mut self, hash: &str) {
+        let (Some(&target), Some(current)) = (self.by_hash.get(hash), self.selected_id()) else {
+            return;
+        };
+        self.history.push(current);
+        self.select(target);
+    }
+
+    fn select(&mut self, id: usize) {
+        let event = &self.graph.events[id];
+        if event.witness.is_none() {
+            self.only_witnesses = false;
+        }
+        self.round = event.round;
+        self.selected = self
+            .visible(self.round)
+            .iter()
+            .position(|&i| i == id)
+            .expect("the event is in its round");
+    }
+
+    fn visible(&self, round: usize) -> Vec<usize> {
+        self.rounds[round]
+            .iter()
+            .copied()
+            .filter(|&i| !self.only_witnesses || self.graph.events[i].witness.is_some())
+            .collect()
+    }
+
+    fn selected_id(&self) -> Option<usize> {
+        self.visible(self.round).get(self.selected).copied()
+    }
+
+    /// `target` is an ancestor of `observer` (or the same event)
+    fn sees(&self, observer: usize, target: usize) -> bool {
+        let target_round = self.graph.events[target].round;
+        let mut visited = HashSet::new();
+        let mut stack = vec![observer];
+        while let Some(current) = stack.pop() {
+            if current == target {
+                return true;
+            }
+            if !visited.insert(current) {
+                continue;
+            }
+            let event = &self.graph.events[current];
+            for parent in event.self_parent.iter().chain(event.other_parent.iter()) {
+                match self.by_hash.get(parent) {
+                    // Rounds never decrease towards descendants
+                    Some(&p) if self.graph.events[p].round >= target_round => stack.push(p),
+                    _ => (),
+                }
+            }
+        }
+        false
+    }
+}
+
+pub fn fame_label(fame: Option<JsonFame>) -> &'static str {
+    match fame {
+        None => "",
+        Some(JsonFame::Undecided) => "witness (undecided)",
+        Some(JsonFame::Famous) => "witness (famous)",
+        Some(JsonFame::NotFamous) => "witness (not famous)",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(name: &str, round: usize, parents: Option<(&str, &str)>, witness: bool) -> Event {
+        JsonEvent {
+            hash: name.to_owned(),
+            author: Value::from(name.chars().next().unwrap().to_string()),
+            timestamp: 0,
+            self_parent: parents.map(|p| p.0.to_owned()),
+            other_parent: parents.map(|p| p.1.to_owned()),
+            round,
+            witness: witness.then_some(JsonFame::Undecided),
+            round_received: None,
+            in_fork: false,
+        }
+    }
+
+    #[test]
+    fn navigation_and_votes_work() {
+        let graph = JsonGraph {
+            version: 1,
+            self_id: Some(Value::from("a")),
+            events: vec![
+                event("a0", 0, None, true),
+                event("b0", 0, None, true),
+                event("a1", 0, Some(("a0", "b0")), false),
+                event("b1", 1, Some(("b0", "a1")), true),
+                event("a2", 1, Some(("a1", "b1")), true),
+            ],
+            ordering: vec![],
+        };
+        let mut inspector = Inspector::new(graph);
+        assert_eq!(inspector.rounds_count(), 2);
+        assert_eq!(inspector.round_events().len(), 3);
+        inspector.toggle_witnesses();
+        assert_eq!(inspector.round_events().len(), 2);
+
+        let votes = inspector.votes("b0").unwrap();
+        assert_eq!(votes.yes.len(), 2);
+        let votes = inspector.votes("a1");
+        assert!(votes.is_none());
+
+        inspector.next_round();
+        while inspector.selected().unwrap().hash != "a2" {
+            inspector.next_event();
+        }
+        inspector.jump_to_self_parent();
+        assert_eq!(inspector.selected().unwrap().hash, "a1");
+        // Non-witness is shown
+        assert!(!inspector.only_witnesses());
+        inspector.jump_to_other_parent();
+        assert_eq!(inspector.selected().unwrap().hash, "b0");
+        inspector.back();
+        inspector.back();
+        assert_eq!(inspector.selected().unwrap().hash, "a2");
+        assert_eq!(inspector.children("b0").len(), 2);
+    }
+}
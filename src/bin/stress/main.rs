@@ -0,0 +1,213 @@
+//! Sustained ingestion of generated gossip by a single graph.
+//!
+//! Usage: `hashgraph-stress [--peers N] [--rate EVENTS_PER_SEC]
+//! [--payload BYTES] [--duration SECS] [--report SECS]`
+//!
+//! Events of random syncs between `peers` members are pushed at `rate`
+//! (`0` for as fast as possible) for `duration` seconds. Every `report`
+//! seconds a line with ingestion throughput, percentiles of the time from
+//! push to finalization and resident memory is printed, followed by a
+//! summary for the whole run. The workload is seeded from
+//! `HASHGRAPH_SEED` (`1` by default).
+
+mod workload;
+
+use std::collections::HashMap;
+use std::process::exit;
+use std::time::{Duration, Instant};
+
+use rust_hashgraph::algorithm::datastructure::Graph;
+use rust_hashgraph::algorithm::{IncrementalClock, MockSigner};
+use rust_hashgraph::testing::seed_from_env;
+
+use workload::{resident_bytes, Latencies, Payload, Workload};
+
+type StressGraph = Graph<Payload, (), usize, MockSigner<usize, ()>, IncrementalClock>;
+
+const USAGE: &str = "Usage: hashgraph-stress [--peers N] [--rate EVENTS_PER_SEC] \
+                     [--payload BYTES] [--duration SECS] [--report SECS]";
+
+struct Config {
+    peers: usize,
+    /// Events per second, `0` for no limit
+    rate: u64,
+    payload: usize,
+    duration: Duration,
+    report: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            peers: 5,
+            rate: 1000,
+            payload: 256,
+            duration: Duration::from_secs(30),
+            report: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Config {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut config = Self::default();
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Missing value of {}", flag))?;
+            let number = value
+                .parse::<u64>()
+                .map_err(|_| format!("Value of {} must be a number, got '{}'", flag, value))?;
+            match flag.as_str() {
+                "--peers" if number > 0 => config.peers = number as usize,
+                "--peers" => return Err("At least one peer is needed".to_owned()),
+                "--rate" => config.rate = number,
+                "--payload" => config.payload = number as usize,
+                "--duration" => config.duration = Duration::from_secs(number),
+                "--report" if number > 0 => config.report = Duration::from_secs(number),
+                "--report" => return Err("Report interval must be positive".to_owned()),
+                _ => return Err(format!("Unknown option {}", flag)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Measurements since the last report
+#[derive(Default)]
+struct Period {
+    pushed: usize,
+    latencies: Latencies,
+}
+
+fn main() {
+    let config = Config::from_args(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        exit(2);
+    });
+    let seed = seed_from_env(1);
+    println!(
+        "peers {}, rate {}/s, payload {} B, duration {}s, seed {}",
+        config.peers,
+        config.rate,
+        config.payload,
+        config.duration.as_secs(),
+        seed
+    );
+
+    let (mut workload, geneses) = Workload::new(config.peers, config.payload, seed);
+    let mut graph: StressGraph = Graph::new(
+        0,
+        vec![],
+        (),
+        999,
+        MockSigner::new(),
+        IncrementalClock::new(),
+    );
+    // Own genesis is created by the graph
+    for genesis in geneses.into_iter().skip(1) {
+        let (unsigned, signature) = genesis.into_parts();
+        graph
+            .push_event(unsigned, signature)
+            .expect("Geneses are accepted");
+    }
+
+    let start = Instant::now();
+    let initial_memory = resident_bytes();
+    let mut pushed_at: HashMap<_, Instant> = HashMap::new();
+    let mut period = Period::default();
+    let mut total = Period::default();
+    let mut next_report = start + config.report;
+    let mut generated: u64 = 0;
+    while start.elapsed() < config.duration {
+        if config.rate > 0 {
+            // Keep the rate on average, catching up if behind
+            let due = start + Duration::from_secs_f64(generated as f64 / config.rate as f64);
+            let now = Instant::now();
+            if due > now {
+                std::thread::sleep((due - now).min(next_report.saturating_duration_since(now)));
+                if Instant::now() < due {
+                    report_if_due(&mut period, &mut next_report, &config, start, &pushed_at);
+                    continue;
+                }
+            }
+        }
+        let event = workload.next_event();
+        generated += 1;
+        pushed_at.insert(event.hash().clone(), Instant::now());
+        let (unsigned, signature) = event.into_parts();
+        graph
+            .push_event(unsigned, signature)
+            .expect("Generated events are valid");
+        period.pushed += 1;
+        while let Some(finalized) = graph.next_finalized_event() {
+            if let Some(pushed) = pushed_at.remove(finalized.hash()) {
+                let latency = pushed.elapsed();
+                period.latencies.record(latency);
+                total.latencies.record(latency);
+            }
+        }
+        total.pushed += 1;
+        report_if_due(&mut period, &mut next_report, &config, start, &pushed_at);
+    }
+
+    let elapsed = start.elapsed();
+    println!("---");
+    println!(
+        "pushed {} events in {:.1}s ({:.0}/s), finalized {}, pending {}",
+        total.pushed,
+        elapsed.as_secs_f64(),
+        total.pushed as f64 / elapsed.as_secs_f64(),
+        total.latencies.len(),
+        pushed_at.len()
+    );
+    println!("latency {}", latency_summary(&mut total.latencies));
+    match (initial_memory, resident_bytes()) {
+        (Some(initial), Some(last)) => println!(
+            "memory {} -> {} ({:+.1} KiB/event)",
+            mebibytes(initial),
+            mebibytes(last),
+            (last as f64 - initial as f64) / 1024.0 / total.pushed.max(1) as f64
+        ),
+        _ => println!("memory n/a"),
+    }
+}
+
+fn report_if_due<K>(
+    period: &mut Period,
+    next_report: &mut Instant,
+    config: &Config,
+    start: Instant,
+    pending: &HashMap<K, Instant>,
+) {
+    let now = Instant::now();
+    if now < *next_report {
+        return;
+    }
+    println!(
+        "{:>6.1}s  in {:>7.0}/s  finalized {:>6}  pending {:>6}  latency {}  memory {}",
+        (now - start).as_secs_f64(),
+        period.pushed as f64 / config.report.as_secs_f64(),
+        period.latencies.len(),
+        pending.len(),
+        latency_summary(&mut period.latencies),
+        resident_bytes().map_or_else(|| "n/a".to_owned(), mebibytes)
+    );
+    period.pushed = 0;
+    period.latencies.clear();
+    *next_report += config.report;
+}
+
+fn latency_summary(latencies: &mut Latencies) -> String {
+    let [p50, p90, p99, max] = [50.0, 90.0, 99.0, 100.0].map(|p| {
+        latencies.percentile(p).map_or_else(
+            || "-".to_owned(),
+            |d| format!("{:.1}", d.as_secs_f64() * 1e3),
+        )
+    });
+    format!("p50 {} p90 {} p99 {} max {} ms", p50, p90, p99, max)
+}
+
+fn mebibytes(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
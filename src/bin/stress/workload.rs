@@ -0,0 +1,151 @@
+//! Generated gossip and measurements of the graph under it, independent of
+//! the command line.
+
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rust_hashgraph::algorithm::event::{self, SignedEvent};
+use rust_hashgraph::algorithm::{MockSigner, Signer};
+use rust_hashgraph::Timestamp;
+
+pub type Payload = Vec<u8>;
+pub type StressEvent = SignedEvent<Payload, (), usize>;
+
+/// Events of `peers` members gossiping at random: each event is created by
+/// a random member after a sync with another random member, so its other
+/// parent is the latest event of that member.
+pub struct Workload {
+    signer: MockSigner<usize, ()>,
+    rng: StdRng,
+    /// Latest event of each peer
+    tips: Vec<event::Hash>,
+    payload_size: usize,
+    timestamp: Timestamp,
+}
+
+impl Workload {
+    /// Workload and geneses of all peers, the first one is of peer `0`
+    /// (the graph's owner) and is the same as created by `Graph::new`.
+    ///
+    /// # Panics
+    /// If `peers` is 0.
+    pub fn new(peers: usize, payload_size: usize, seed: u64) -> (Self, Vec<StressEvent>) {
+        assert!(peers > 0, "Workload needs at least one peer");
+        let signer = MockSigner::new();
+        let geneses: Vec<_> = (0..peers)
+            .map(|id| {
+                SignedEvent::new(vec![], event::Kind::Genesis(()), id, 0, |h| signer.sign(h))
+                    .expect("Failed to create event")
+            })
+            .collect();
+        let workload = Self {
+            signer,
+            rng: StdRng::seed_from_u64(seed),
+            tips: geneses.iter().map(|g| g.hash().clone()).collect(),
+            payload_size,
+            timestamp: 0,
+        };
+        (workload, geneses)
+    }
+
+    pub fn next_event(&mut self) -> StressEvent {
+        let peers = self.tips.len();
+        let author = self.rng.gen_range(0..peers);
+        let partner = if peers > 1 {
+            // Anyone but the author
+            (author + self.rng.gen_range(1..peers)) % peers
+        } else {
+            author
+        };
+        let mut payload = vec![0; self.payload_size];
+        self.rng.fill(&mut payload[..]);
+        self.timestamp += 1;
+        let parents = event::Parents {
+            self_parent: self.tips[author].clone(),
+            other_parent: self.tips[partner].clone(),
+        };
+        let new_event = SignedEvent::new(
+            payload,
+            event::Kind::Regular(parents),
+            author,
+            self.timestamp,
+            |h| self.signer.sign(h),
+        )
+        .expect("Failed to create event");
+        self.tips[author] = new_event.hash().clone();
+        new_event
+    }
+}
+
+/// Finalization latencies collected over a period
+#[derive(Default)]
+pub struct Latencies(Vec<Duration>);
+
+impl Latencies {
+    pub fn record(&mut self, latency: Duration) {
+        self.0.push(latency);
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Nearest-rank percentile, `None` if nothing was recorded
+    pub fn percentile(&mut self, percent: f64) -> Option<Duration> {
+        if self.0.is_empty() {
+            return None;
+        }
+        self.0.sort_unstable();
+        let rank = (percent / 100.0 * self.0.len() as f64).ceil() as usize;
+        Some(self.0[rank.clamp(1, self.0.len()) - 1])
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// Resident set size of the process, only known on Linux
+pub fn resident_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gossip_between_different_peers() {
+        let (mut workload, geneses) = Workload::new(3, 16, 1);
+        assert_eq!(geneses.len(), 3);
+        let mut known: Vec<_> = geneses.iter().map(|g| g.hash().clone()).collect();
+        for _ in 0..50 {
+            let event = workload.next_event();
+            let fields = event.unsigned().fields();
+            assert_eq!(fields.user_payload().unwrap().len(), 16);
+            let event::Kind::Regular(parents) = fields.kind() else {
+                panic!("Workload creates regular events");
+            };
+            assert_ne!(parents.self_parent, parents.other_parent);
+            assert!(known.contains(&parents.self_parent));
+            assert!(known.contains(&parents.other_parent));
+            known.push(event.hash().clone());
+        }
+    }
+
+    #[test]
+    fn percentiles_by_nearest_rank() {
+        let mut latencies = Latencies::default();
+        assert_eq!(latencies.percentile(50.0), None);
+        for ms in (1..=10).rev() {
+            latencies.record(Duration::from_millis(ms));
+        }
+        assert_eq!(latencies.percentile(50.0), Some(Duration::from_millis(5)));
+        assert_eq!(latencies.percentile(99.0), Some(Duration::from_millis(10)));
+        assert_eq!(latencies.percentile(0.0), Some(Duration::from_millis(1)));
+    }
+}
@@ -2,6 +2,7 @@ pub trait Graph {
     type NodeIdentifier;
     type NodeIdentifiers: IntoIterator<Item = Self::NodeIdentifier>;
 
+    #[allow(dead_code)]
     fn neighbors(&self, node: &Self::NodeIdentifier) -> Option<Self::NodeIdentifiers>;
 }
 
@@ -14,6 +15,8 @@ impl<G: Graph> Graph for &G {
     }
 }
 
+// Only for checking orderings in tests now
+#[allow(dead_code)]
 pub trait Directed: Graph {
     fn in_neighbors(&self, node: &Self::NodeIdentifier) -> Option<Self::NodeIdentifiers>;
     fn out_neighbors(&self, node: &Self::NodeIdentifier) -> Option<Self::NodeIdentifiers>;
@@ -28,63 +31,3 @@ impl<G: Directed> Directed for &G {
         (*self).out_neighbors(node)
     }
 }
-
-pub trait Reversable<'a, TReverse = ReversedGraph<'a, Self>>: Directed {
-    fn reversed(&'a self) -> TReverse;
-}
-
-impl<'a, G> Reversable<'a, ReversedGraph<'a, G>> for G
-where
-    G: Directed,
-{
-    fn reversed(&'a self) -> ReversedGraph<'a, G> {
-        self.into()
-    }
-}
-
-pub struct ReversedGraph<'a, G> {
-    inner: &'a G,
-}
-
-impl<'a, G> From<&'a G> for ReversedGraph<'a, G> {
-    fn from(value: &'a G) -> Self {
-        ReversedGraph { inner: value }
-    }
-}
-
-impl<'a, G> Graph for ReversedGraph<'a, G>
-where
-    G: Graph,
-{
-    type NodeIdentifier = G::NodeIdentifier;
-    type NodeIdentifiers = G::NodeIdentifiers;
-
-    fn neighbors(&self, node: &Self::NodeIdentifier) -> Option<Self::NodeIdentifiers> {
-        self.inner.neighbors(node)
-    }
-}
-
-impl<'a, G> Directed for ReversedGraph<'a, G>
-where
-    G: Directed,
-{
-    fn in_neighbors(&self, node: &Self::NodeIdentifier) -> Option<Self::NodeIdentifiers> {
-        self.inner.out_neighbors(node)
-    }
-
-    fn out_neighbors(&self, node: &Self::NodeIdentifier) -> Option<Self::NodeIdentifiers> {
-        self.inner.in_neighbors(node)
-    }
-}
-
-impl<'a, G> Reversable<'a, &'a G> for ReversedGraph<'a, G>
-where
-    G: Directed,
-{
-    fn reversed(&'a self) -> &G
-    where
-        Self: Sized,
-    {
-        self.inner
-    }
-}
@@ -1,5 +1,15 @@
+// Errors carry 64-byte event hashes for context, they are not on hot paths
+#![allow(clippy::result_large_err)]
+
 pub mod algorithm;
 mod common;
+pub mod latency;
+pub mod light;
+pub mod net;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 // In milliseconds, I guess. Should work for 500+
 // million years.
@@ -0,0 +1,574 @@
+//! Verification of finality without the graph, e.g. for mobile or browser
+//! clients.
+//!
+//! A full node feeds its finalized events to a [`Ledger`], which commits to
+//! the consensus order with Merkle trees over the event hashes and over the
+//! transactions of the events. Members sign [`Checkpoint`]s of their
+//! ledgers; a checkpoint signed by a supermajority of them is a
+//! [`QuorumCertificate`]. A [`LightClient`] knowing only the members accepts
+//! such certificates and then checks that an event
+//! ([`OrderingCertificate`]) or a transaction ([`TransactionCertificate`])
+//! was finalized at a given position.
+//!
+//! Honest members finalize the same order, so their checkpoints of the same
+//! length are equal. A quorum certificate thus can only be formed for the
+//! actual order unless more than a third of the members are faulty, which
+//! the consensus doesn't tolerate anyway.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+
+use blake2::{Blake2b512, Digest};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::algorithm::event::{Hash, Signature};
+use crate::algorithm::Signer;
+
+const LEAF_TAG: u8 = 0;
+const NODE_TAG: u8 = 1;
+const CHECKPOINT_TAG: &[u8] = b"hashgraph-checkpoint";
+
+#[derive(Error, Debug)]
+pub enum LightError<TPeerId> {
+    #[error("Certificate is signed by {0:?}, which is not a member")]
+    UnknownSigner(TPeerId),
+    #[error("Signature of member {0:?} is invalid")]
+    BadSignature(TPeerId),
+    #[error("Only {signed} of {members} members signed the checkpoint")]
+    NoQuorum { signed: usize, members: usize },
+    #[error("Checkpoint of {events} events conflicts with an accepted one")]
+    ConflictingCheckpoint { events: usize },
+    #[error("No accepted checkpoint covers exactly {leaves} leaves")]
+    UnknownCheckpoint { leaves: usize },
+    #[error("Proof does not lead to the root of the checkpoint")]
+    ProofMismatch,
+    #[error("Could not encode the transaction: {0}")]
+    Encoding(#[from] bincode::Error),
+}
+
+fn hash_parts(parts: &[&[u8]]) -> Hash {
+    let mut hasher = Blake2b512::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    let hash_slice = &hasher.finalize()[..];
+    Hash::from_array(hash_slice.try_into().expect("blake2b512 gives 64 bytes"))
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    hash_parts(&[&[NODE_TAG], left.as_ref(), right.as_ref()])
+}
+
+fn event_leaf(event: &Hash) -> Hash {
+    hash_parts(&[&[LEAF_TAG], event.as_ref()])
+}
+
+fn transaction_leaf<T: Serialize>(
+    event: &Hash,
+    index: usize,
+    transaction: &T,
+) -> bincode::Result<Hash> {
+    let encoded = bincode::serialize(transaction)?;
+    Ok(hash_parts(&[
+        &[LEAF_TAG],
+        event.as_ref(),
+        &(index as u64).to_le_bytes(),
+        &encoded,
+    ]))
+}
+
+/// Root of the tree over `leaves`. Pairs are hashed level by level, the
+/// last node of an odd level is carried up as is.
+pub fn merkle_root(leaves: &[Hash]) -> Hash {
+    if leaves.is_empty() {
+        return hash_parts(&[]);
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = parent_level(&level);
+    }
+    level.pop().expect("non-empty level")
+}
+
+/// Root of [`Checkpoint::events_root`] for the events in consensus order
+pub(crate) fn events_root<'a>(events: impl IntoIterator<Item = &'a Hash>) -> Hash {
+    let leaves: Vec<_> = events.into_iter().map(event_leaf).collect();
+    merkle_root(&leaves)
+}
+
+fn parent_level(level: &[Hash]) -> Vec<Hash> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => single.clone(),
+            _ => unreachable!("chunks of two"),
+        })
+        .collect()
+}
+
+/// Path from a leaf to the root of a [`merkle_root`] tree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub index: usize,
+    /// Number of leaves in the tree
+    pub leaves: usize,
+    /// From the bottom, levels where the node is carried up are skipped
+    pub siblings: Vec<Hash>,
+}
+
+impl MerkleProof {
+    /// `None` if `index` is out of range
+    pub fn new(leaves: &[Hash], index: usize) -> Option<Self> {
+        if index >= leaves.len() {
+            return None;
+        }
+        let mut siblings = vec![];
+        let mut level = leaves.to_vec();
+        let mut position = index;
+        while level.len() > 1 {
+            if let Some(sibling) = level.get(position ^ 1) {
+                siblings.push(sibling.clone());
+            }
+            level = parent_level(&level);
+            position /= 2;
+        }
+        Some(Self {
+            index,
+            leaves: leaves.len(),
+            siblings,
+        })
+    }
+
+    /// Root of the tree if `leaf` is at `index`, `None` if the proof is
+    /// malformed
+    pub fn root(&self, leaf: &Hash) -> Option<Hash> {
+        if self.index >= self.leaves {
+            return None;
+        }
+        let mut siblings = self.siblings.iter();
+        let mut node = leaf.clone();
+        let mut position = self.index;
+        let mut width = self.leaves;
+        while width > 1 {
+            if position % 2 == 1 {
+                node = node_hash(siblings.next()?, &node);
+            } else if position + 1 < width {
+                node = node_hash(&node, siblings.next()?);
+            }
+            position /= 2;
+            width = width.div_ceil(2);
+        }
+        match siblings.next() {
+            Some(_) => None,
+            None => Some(node),
+        }
+    }
+}
+
+/// Commitment to the first `events` finalized events and their
+/// `transactions`, in consensus order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub events: usize,
+    pub events_root: Hash,
+    pub transactions: usize,
+    pub transactions_root: Hash,
+}
+
+impl Checkpoint {
+    /// What members sign
+    pub fn hash(&self) -> Hash {
+        hash_parts(&[
+            CHECKPOINT_TAG,
+            &(self.events as u64).to_le_bytes(),
+            self.events_root.as_ref(),
+            &(self.transactions as u64).to_le_bytes(),
+            self.transactions_root.as_ref(),
+        ])
+    }
+
+    pub fn sign<TGenesisPayload, TSigner>(&self, signer: &TSigner) -> Signature
+    where
+        TSigner: Signer<TGenesisPayload>,
+    {
+        signer.sign(&self.hash())
+    }
+
+    /// Whether both could be taken of the same order
+    fn compatible(&self, other: &Checkpoint) -> bool {
+        match self.events.cmp(&other.events) {
+            std::cmp::Ordering::Equal => self == other,
+            std::cmp::Ordering::Less => self.transactions <= other.transactions,
+            std::cmp::Ordering::Greater => self.transactions >= other.transactions,
+        }
+    }
+}
+
+/// Checkpoint with signatures of members
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuorumCertificate<TPeerId> {
+    pub checkpoint: Checkpoint,
+    pub signatures: Vec<(TPeerId, Signature)>,
+}
+
+impl<TPeerId> QuorumCertificate<TPeerId>
+where
+    TPeerId: Eq + std::hash::Hash + Clone,
+{
+    /// Check that a supermajority of `members` (with their genesis
+    /// payloads) signed the checkpoint and nobody else did
+    pub fn verify<TGenesisPayload, TSigner>(
+        &self,
+        members: &HashMap<TPeerId, TGenesisPayload>,
+        signer: &TSigner,
+    ) -> Result<(), LightError<TPeerId>>
+    where
+        TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+    {
+        let hash = self.checkpoint.hash();
+        let mut signed = HashSet::new();
+        for (member, signature) in &self.signatures {
+            let genesis_payload = members
+                .get(member)
+                .ok_or_else(|| LightError::UnknownSigner(member.clone()))?;
+            if !signer.verify(&hash, signature, member, genesis_payload) {
+                return Err(LightError::BadSignature(member.clone()));
+            }
+            signed.insert(member);
+        }
+        if !crate::algorithm::core::supermajority(signed.len(), members.len()) {
+            return Err(LightError::NoQuorum {
+                signed: signed.len(),
+                members: members.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Proof that `event` is at position `proof.index` of the consensus order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderingCertificate {
+    pub event: Hash,
+    pub proof: MerkleProof,
+}
+
+/// Proof that the transaction number `index` of `event` is at position
+/// `proof.index` among all finalized transactions
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionCertificate {
+    pub event: Hash,
+    pub index: usize,
+    pub proof: MerkleProof,
+}
+
+/// Finalized order as seen by a full node, produces checkpoints and
+/// certificates. Keeps only hashes.
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+    event_leaves: Vec<Hash>,
+    transaction_leaves: Vec<Hash>,
+    /// Position of each event and positions of its transactions
+    positions: HashMap<Hash, (usize, Range<usize>)>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append the next finalized event with its transactions
+    pub fn push<'a, T>(
+        &mut self,
+        event: &Hash,
+        transactions: impl IntoIterator<Item = &'a T>,
+    ) -> bincode::Result<()>
+    where
+        T: Serialize + 'a,
+    {
+        let leaves = transactions
+            .into_iter()
+            .enumerate()
+            .map(|(index, transaction)| transaction_leaf(event, index, transaction))
+            .collect::<bincode::Result<Vec<_>>>()?;
+        let first = self.transaction_leaves.len();
+        self.positions.insert(
+            event.clone(),
+            (self.event_leaves.len(), first..first + leaves.len()),
+        );
+        self.event_leaves.push(event_leaf(event));
+        self.transaction_leaves.extend(leaves);
+        Ok(())
+    }
+
+    /// Number of events
+    pub fn len(&self) -> usize {
+        self.event_leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.event_leaves.is_empty()
+    }
+
+    /// Checkpoint of everything pushed so far
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            events: self.event_leaves.len(),
+            events_root: merkle_root(&self.event_leaves),
+            transactions: self.transaction_leaves.len(),
+            transactions_root: merkle_root(&self.transaction_leaves),
+        }
+    }
+
+    /// `None` if the event is not covered by the checkpoint
+    pub fn ordering_certificate(
+        &self,
+        event: &Hash,
+        checkpoint: &Checkpoint,
+    ) -> Option<OrderingCertificate> {
+        let (position, _) = self.positions.get(event)?;
+        let leaves = self.event_leaves.get(..checkpoint.events)?;
+        Some(OrderingCertificate {
+            event: event.clone(),
+            proof: MerkleProof::new(leaves, *position)?,
+        })
+    }
+
+    /// `None` if the transaction is not covered by the checkpoint
+    pub fn transaction_certificate(
+        &self,
+        event: &Hash,
+        index: usize,
+        checkpoint: &Checkpoint,
+    ) -> Option<TransactionCertificate> {
+        let (_, transactions) = self.positions.get(event)?;
+        let position = transactions.clone().nth(index)?;
+        let leaves = self.transaction_leaves.get(..checkpoint.transactions)?;
+        Some(TransactionCertificate {
+            event: event.clone(),
+            index,
+            proof: MerkleProof::new(leaves, position)?,
+        })
+    }
+}
+
+/// Verifier holding only the members and accepted checkpoints
+pub struct LightClient<TPeerId, TGenesisPayload, TSigner> {
+    /// Genesis payloads are needed to verify signatures
+    members: HashMap<TPeerId, TGenesisPayload>,
+    signer: TSigner,
+    /// By number of events
+    checkpoints: BTreeMap<usize, Checkpoint>,
+}
+
+impl<TPeerId, TGenesisPayload, TSigner> LightClient<TPeerId, TGenesisPayload, TSigner>
+where
+    TPeerId: Eq + std::hash::Hash + Clone + std::fmt::Debug,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+{
+    pub fn new(
+        members: impl IntoIterator<Item = (TPeerId, TGenesisPayload)>,
+        signer: TSigner,
+    ) -> Self {
+        Self {
+            members: members.into_iter().collect(),
+            signer,
+            checkpoints: BTreeMap::new(),
+        }
+    }
+
+    pub fn checkpoints(&self) -> impl Iterator<Item = &Checkpoint> {
+        self.checkpoints.values()
+    }
+
+    /// Accept the checkpoint if a supermajority of members signed it and it
+    /// doesn't contradict the checkpoints accepted before.
+    pub fn accept(
+        &mut self,
+        certificate: &QuorumCertificate<TPeerId>,
+    ) -> Result<(), LightError<TPeerId>> {
+        certificate.verify(&self.members, &self.signer)?;
+        let checkpoint = &certificate.checkpoint;
+        if self.checkpoints.values().any(|c| !c.compatible(checkpoint)) {
+            return Err(LightError::ConflictingCheckpoint {
+                events: checkpoint.events,
+            });
+        }
+        self.checkpoints
+            .insert(checkpoint.events, checkpoint.clone());
+        Ok(())
+    }
+
+    /// Position of the event in the consensus order
+    pub fn verify_event(
+        &self,
+        certificate: &OrderingCertificate,
+    ) -> Result<usize, LightError<TPeerId>> {
+        let proof = &certificate.proof;
+        let checkpoint =
+            self.checkpoints
+                .get(&proof.leaves)
+                .ok_or(LightError::UnknownCheckpoint {
+                    leaves: proof.leaves,
+                })?;
+        match proof.root(&event_leaf(&certificate.event)) {
+            Some(root) if root == checkpoint.events_root => Ok(proof.index),
+            _ => Err(LightError::ProofMismatch),
+        }
+    }
+
+    /// Position of the transaction among all finalized transactions
+    pub fn verify_transaction<T: Serialize>(
+        &self,
+        certificate: &TransactionCertificate,
+        transaction: &T,
+    ) -> Result<usize, LightError<TPeerId>> {
+        let proof = &certificate.proof;
+        let checkpoint = self
+            .checkpoints
+            .values()
+            .find(|c| c.transactions == proof.leaves)
+            .ok_or(LightError::UnknownCheckpoint {
+                leaves: proof.leaves,
+            })?;
+        let leaf = transaction_leaf(&certificate.event, certificate.index, transaction)?;
+        match proof.root(&leaf) {
+            Some(root) if root == checkpoint.transactions_root => Ok(proof.index),
+            _ => Err(LightError::ProofMismatch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::MockSigner;
+
+    type Client = LightClient<usize, (), MockSigner<usize, ()>>;
+
+    fn hash(n: u8) -> Hash {
+        hash_parts(&[&[n]])
+    }
+
+    /// Ledger of 5 events with `i` transactions in event `i`
+    fn ledger() -> Ledger {
+        let mut ledger = Ledger::new();
+        for i in 0..5u8 {
+            let transactions: Vec<u64> = (0..i as u64).map(|t| 10 * i as u64 + t).collect();
+            ledger.push(&hash(i), &transactions).unwrap();
+        }
+        ledger
+    }
+
+    fn certify(checkpoint: &Checkpoint, signers: &[usize]) -> QuorumCertificate<usize> {
+        let signer = MockSigner::<usize, ()>::new();
+        QuorumCertificate {
+            checkpoint: checkpoint.clone(),
+            signatures: signers
+                .iter()
+                .map(|&m| (m, checkpoint.sign::<(), _>(&signer)))
+                .collect(),
+        }
+    }
+
+    fn client() -> Client {
+        LightClient::new((0..4).map(|m| (m, ())), MockSigner::new())
+    }
+
+    #[test]
+    fn merkle_proofs_of_all_leaves() {
+        for size in 1..12u8 {
+            let leaves: Vec<_> = (0..size).map(hash).collect();
+            let root = merkle_root(&leaves);
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = MerkleProof::new(&leaves, index).unwrap();
+                assert_eq!(proof.root(leaf), Some(root.clone()));
+                assert_ne!(proof.root(&hash(100)), Some(root.clone()));
+            }
+            assert!(MerkleProof::new(&leaves, size as usize).is_none());
+        }
+    }
+
+    #[test]
+    fn finalized_event_and_transaction_verified() {
+        let ledger = ledger();
+        let checkpoint = ledger.checkpoint();
+        let mut client = client();
+        client.accept(&certify(&checkpoint, &[0, 1, 2])).unwrap();
+
+        let certificate = ledger.ordering_certificate(&hash(3), &checkpoint).unwrap();
+        assert_eq!(client.verify_event(&certificate).unwrap(), 3);
+        let forged = OrderingCertificate {
+            event: hash(7),
+            ..certificate
+        };
+        assert!(matches!(
+            client.verify_event(&forged),
+            Err(LightError::ProofMismatch)
+        ));
+
+        // Transactions 0, 1, 2 of event 3 follow 0 + 1 + 2 earlier ones
+        let certificate = ledger
+            .transaction_certificate(&hash(3), 1, &checkpoint)
+            .unwrap();
+        assert_eq!(client.verify_transaction(&certificate, &31u64).unwrap(), 4);
+        assert!(matches!(
+            client.verify_transaction(&certificate, &32u64),
+            Err(LightError::ProofMismatch)
+        ));
+        assert!(ledger
+            .transaction_certificate(&hash(3), 3, &checkpoint)
+            .is_none());
+    }
+
+    #[test]
+    fn certificates_of_older_checkpoint() {
+        let mut ledger = ledger();
+        let old = ledger.checkpoint();
+        ledger.push(&hash(5), &[50u64]).unwrap();
+        let mut client = client();
+        client.accept(&certify(&old, &[0, 1, 2])).unwrap();
+        client
+            .accept(&certify(&ledger.checkpoint(), &[1, 2, 3]))
+            .unwrap();
+        let certificate = ledger.ordering_certificate(&hash(2), &old).unwrap();
+        assert_eq!(client.verify_event(&certificate).unwrap(), 2);
+        assert!(ledger.ordering_certificate(&hash(5), &old).is_none());
+    }
+
+    #[test]
+    fn bad_quorum_certificates_rejected() {
+        let checkpoint = ledger().checkpoint();
+        let mut client = client();
+        assert!(matches!(
+            client.accept(&certify(&checkpoint, &[0, 1, 1])),
+            Err(LightError::NoQuorum {
+                signed: 2,
+                members: 4
+            })
+        ));
+        assert!(matches!(
+            client.accept(&certify(&checkpoint, &[0, 1, 9])),
+            Err(LightError::UnknownSigner(9))
+        ));
+        let mut certificate = certify(&checkpoint, &[0, 1, 2]);
+        certificate.signatures[2].1 = Signature(hash(0));
+        assert!(matches!(
+            client.accept(&certificate),
+            Err(LightError::BadSignature(2))
+        ));
+
+        client.accept(&certify(&checkpoint, &[0, 1, 2])).unwrap();
+        let conflicting = Checkpoint {
+            events_root: hash(0),
+            ..checkpoint
+        };
+        assert!(matches!(
+            client.accept(&certify(&conflicting, &[0, 1, 2])),
+            Err(LightError::ConflictingCheckpoint { events: 5 })
+        ));
+        assert_eq!(client.checkpoints().count(), 1);
+    }
+}
@@ -0,0 +1,83 @@
+//! [`Ingress`] shared by the connections of a tokio transport. A connection
+//! that received events waits in [`IngressGate::admit`] until the limits
+//! let them through, and only then applies them, so a flooding peer only
+//! slows down its own connections.
+
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use tokio::sync::oneshot;
+
+use crate::algorithm::datastructure::sync::ingress::{Admission, Ingress, IngressLimits};
+use crate::algorithm::datastructure::sync::Jobs;
+use crate::Timestamp;
+
+/// How often waiting connections check the limits
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Messages tagged with the connections waiting for them
+type WaitingIngress<TPayload, TGenesisPayload, TPeerId, TSource> = Ingress<
+    TPayload,
+    TGenesisPayload,
+    TPeerId,
+    TSource,
+    oneshot::Sender<Jobs<TPayload, TGenesisPayload, TPeerId>>,
+>;
+
+pub(crate) struct IngressGate<TPayload, TGenesisPayload, TPeerId, TSource> {
+    ingress: Mutex<WaitingIngress<TPayload, TGenesisPayload, TPeerId, TSource>>,
+    started: Instant,
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSource>
+    IngressGate<TPayload, TGenesisPayload, TPeerId, TSource>
+where
+    TSource: Eq + std::hash::Hash + Clone,
+{
+    pub fn new(limits: IngressLimits) -> Self {
+        Self {
+            ingress: Mutex::new(Ingress::new(limits, 0)),
+            started: Instant::now(),
+        }
+    }
+
+    /// Wait until the limits let `jobs` of `source` through. `None` if they
+    /// were dropped from the full queue.
+    pub async fn admit(
+        &self,
+        source: TSource,
+        jobs: Jobs<TPayload, TGenesisPayload, TPeerId>,
+    ) -> Option<Jobs<TPayload, TGenesisPayload, TPeerId>> {
+        let (waiter, mut released) = oneshot::channel();
+        let admission = self.lock().offer_tagged(source, jobs, waiter, self.now());
+        if matches!(admission, Admission::Dropped) {
+            return None;
+        }
+        loop {
+            self.release();
+            match released.try_recv() {
+                Ok(jobs) => return Some(jobs),
+                // Evicted by a newer message
+                Err(oneshot::error::TryRecvError::Closed) => return None,
+                Err(oneshot::error::TryRecvError::Empty) => tokio::time::sleep(POLL_INTERVAL).await,
+            }
+        }
+    }
+
+    /// Hand the messages allowed by now to their connections
+    fn release(&self) {
+        let mut ingress = self.lock();
+        while let Some((_, jobs, waiter)) = ingress.next_ready_tagged(self.now()) {
+            // The connection may be gone already
+            let _ = waiter.send(jobs);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, WaitingIngress<TPayload, TGenesisPayload, TPeerId, TSource>> {
+        self.ingress.lock().expect("ingress lock poisoned")
+    }
+
+    fn now(&self) -> Timestamp {
+        self.started.elapsed().as_nanos()
+    }
+}
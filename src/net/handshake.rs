@@ -0,0 +1,352 @@
+//! First message of every connection, checking that the peers can talk to
+//! each other at all before any [protocol](super::protocol) message is sent.
+//!
+//! Each side sends its [`Handshake`] and calls [`Handshake::negotiate`] with
+//! the received one. Both sides come to the same result, so no further round
+//! trip is needed. On error the connection should be closed.
+//!
+//! The encoding of the handshake is independent of the
+//! [wire format version](crate::algorithm::datastructure::sync::wire) and
+//! never changes: 4 magic bytes `HGHS` followed by bincode of [`Handshake`].
+//! This way a peer running an incompatible version is reported as such
+//! instead of failing on a garbled message. New fields are only appended:
+//! older peers ignore them, and handshakes of older peers get their
+//! defaults.
+
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::algorithm::compression::PayloadCompression;
+use crate::algorithm::datastructure::sync::wire::{MIN_WIRE_VERSION, WIRE_VERSION};
+use crate::algorithm::datastructure::Graph;
+
+const MAGIC: [u8; 4] = *b"HGHS";
+
+/// Event hashes are BLAKE2b with 512 bit output
+pub const HASH_BLAKE2B_512: &str = "blake2b-512";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Handshake {
+    /// Peers of different networks never talk to each other
+    pub network_id: String,
+    pub min_version: u16,
+    pub max_version: u16,
+    /// In the order of preference
+    pub hash_algorithms: Vec<String>,
+    /// In the order of preference. Names are up to the application, as
+    /// signatures are made by its [`Signer`](crate::algorithm::Signer).
+    pub signature_algorithms: Vec<String>,
+    /// Informational, e.g. for choosing whom to sync with first
+    pub last_finalized_round: Option<u64>,
+    /// [Compressions](PayloadCompression::name) of payloads used in the
+    /// network, in the order of preference. Like `network_id`, it is a
+    /// parameter of the whole network: events are gossiped further than
+    /// the peer, so every member must decode payloads of every other. Peers
+    /// announcing other sets are rejected. `none` for peers older than the
+    /// field.
+    pub payload_compressions: Vec<String>,
+}
+
+/// [`Handshake`] before `payload_compressions`
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct HandshakeV1 {
+    network_id: String,
+    min_version: u16,
+    max_version: u16,
+    hash_algorithms: Vec<String>,
+    signature_algorithms: Vec<String>,
+    last_finalized_round: Option<u64>,
+}
+
+impl From<HandshakeV1> for Handshake {
+    fn from(value: HandshakeV1) -> Self {
+        Self {
+            network_id: value.network_id,
+            min_version: value.min_version,
+            max_version: value.max_version,
+            hash_algorithms: value.hash_algorithms,
+            signature_algorithms: value.signature_algorithms,
+            last_finalized_round: value.last_finalized_round,
+            payload_compressions: vec![PayloadCompression::None.name().to_owned()],
+        }
+    }
+}
+
+/// Parameters of the connection agreed on by both sides
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Negotiated {
+    /// Wire format version to use for the rest of the connection
+    pub version: u16,
+    pub hash_algorithm: String,
+    pub signature_algorithm: String,
+    pub peer_last_finalized_round: Option<u64>,
+    /// Preferred compression of the network, for
+    /// [`Compressed`](crate::algorithm::compression::Compressed) payloads
+    /// of own events. Events of others keep the compression their authors
+    /// chose.
+    pub payload_compression: PayloadCompression,
+}
+
+#[derive(Error, Debug)]
+pub enum HandshakeError {
+    #[error("Not a handshake message")]
+    BadMagic,
+    #[error("Malformed handshake: {0}")]
+    Malformed(#[from] bincode::Error),
+    #[error("Peer is in network {theirs:?}, we are in {ours:?}")]
+    NetworkMismatch { ours: String, theirs: String },
+    #[error("Peer supports wire versions {theirs:?}, we support {ours:?}")]
+    IncompatibleVersion {
+        ours: RangeInclusive<u16>,
+        theirs: RangeInclusive<u16>,
+    },
+    #[error("No common hash algorithm: we support {ours:?}, peer supports {theirs:?}")]
+    NoCommonHashAlgorithm {
+        ours: Vec<String>,
+        theirs: Vec<String>,
+    },
+    #[error("No common signature algorithm: we support {ours:?}, peer supports {theirs:?}")]
+    NoCommonSignatureAlgorithm {
+        ours: Vec<String>,
+        theirs: Vec<String>,
+    },
+    #[error("Peer's network uses payload compressions {theirs:?}, ours uses {ours:?}")]
+    PayloadCompressionMismatch {
+        ours: Vec<String>,
+        theirs: Vec<String>,
+    },
+}
+
+impl Handshake {
+    /// Handshake of this crate version for `graph`, in a network with
+    /// uncompressed payloads
+    pub fn new<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>(
+        network_id: impl Into<String>,
+        signature_algorithms: Vec<String>,
+        graph: &Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+    ) -> Self
+    where
+        TPeerId: Eq + std::hash::Hash,
+    {
+        Self {
+            network_id: network_id.into(),
+            min_version: MIN_WIRE_VERSION,
+            max_version: WIRE_VERSION,
+            hash_algorithms: vec![HASH_BLAKE2B_512.to_owned()],
+            signature_algorithms,
+            last_finalized_round: graph.last_decided_round().map(|r| r as u64),
+            payload_compressions: vec![PayloadCompression::None.name().to_owned()],
+        }
+    }
+
+    /// Same handshake in a network using `compressions`, the preferred
+    /// first
+    pub fn with_payload_compressions(mut self, compressions: &[PayloadCompression]) -> Self {
+        self.payload_compressions = compressions.iter().map(|c| c.name().to_owned()).collect();
+        self
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bincode::serialize_into(&mut bytes, self).expect("handshake is always serializable");
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, HandshakeError> {
+        let body = bytes.strip_prefix(&MAGIC).ok_or(HandshakeError::BadMagic)?;
+        match bincode::deserialize(body) {
+            Ok(handshake) => Ok(handshake),
+            Err(_) => Ok(bincode::deserialize::<HandshakeV1>(body)?.into()),
+        }
+    }
+
+    /// Agree on the connection parameters with the peer that sent `theirs`:
+    /// - network ids and sets of payload compressions must be equal;
+    /// - the highest version supported by both is used;
+    /// - out of the algorithms supported by both, the one with the smallest
+    ///   sum of positions in the preference lists is used (ties are broken by
+    ///   name), so both sides choose the same.
+    pub fn negotiate(&self, theirs: &Handshake) -> Result<Negotiated, HandshakeError> {
+        if self.network_id != theirs.network_id {
+            return Err(HandshakeError::NetworkMismatch {
+                ours: self.network_id.clone(),
+                theirs: theirs.network_id.clone(),
+            });
+        }
+        let compression_mismatch = || HandshakeError::PayloadCompressionMismatch {
+            ours: self.payload_compressions.clone(),
+            theirs: theirs.payload_compressions.clone(),
+        };
+        if BTreeSet::from_iter(&self.payload_compressions)
+            != BTreeSet::from_iter(&theirs.payload_compressions)
+        {
+            return Err(compression_mismatch());
+        }
+        let version = self.max_version.min(theirs.max_version);
+        if version < self.min_version.max(theirs.min_version) {
+            return Err(HandshakeError::IncompatibleVersion {
+                ours: self.min_version..=self.max_version,
+                theirs: theirs.min_version..=theirs.max_version,
+            });
+        }
+        let hash_algorithm =
+            choose(&self.hash_algorithms, &theirs.hash_algorithms).ok_or_else(|| {
+                HandshakeError::NoCommonHashAlgorithm {
+                    ours: self.hash_algorithms.clone(),
+                    theirs: theirs.hash_algorithms.clone(),
+                }
+            })?;
+        let signature_algorithm = choose(&self.signature_algorithms, &theirs.signature_algorithms)
+            .ok_or_else(|| HandshakeError::NoCommonSignatureAlgorithm {
+                ours: self.signature_algorithms.clone(),
+                theirs: theirs.signature_algorithms.clone(),
+            })?;
+        let payload_compression = choose(&self.payload_compressions, &theirs.payload_compressions)
+            .and_then(|name| PayloadCompression::from_name(&name))
+            .ok_or_else(compression_mismatch)?;
+        Ok(Negotiated {
+            version,
+            hash_algorithm,
+            signature_algorithm,
+            peer_last_finalized_round: theirs.last_finalized_round,
+            payload_compression,
+        })
+    }
+}
+
+fn choose(ours: &[String], theirs: &[String]) -> Option<String> {
+    ours.iter()
+        .enumerate()
+        .filter_map(|(i, name)| {
+            let j = theirs.iter().position(|n| n == name)?;
+            Some((i + j, name))
+        })
+        .min()
+        .map(|(_, name)| name.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::{IncrementalClock, MockSigner};
+
+    fn handshake(network_id: &str, signatures: &[&str]) -> Handshake {
+        let graph: Graph<u64, (), u64, MockSigner<u64, ()>, IncrementalClock> =
+            Graph::new(0, 0, (), 999, MockSigner::new(), IncrementalClock::new());
+        let signatures = signatures.iter().map(|s| s.to_string()).collect();
+        Handshake::new(network_id, signatures, &graph)
+    }
+
+    #[test]
+    fn negotiation_symmetric() {
+        let a = handshake("main", &["ed25519", "secp256k1", "mock"]);
+        let mut b = handshake("main", &["mock", "secp256k1"]);
+        b.max_version = WIRE_VERSION + 3;
+        b.last_finalized_round = Some(7);
+
+        let from_a = a.negotiate(&b).unwrap();
+        let from_b = b.negotiate(&a).unwrap();
+        assert_eq!(from_a.version, WIRE_VERSION);
+        assert_eq!(from_a.hash_algorithm, HASH_BLAKE2B_512);
+        // secp256k1: 1 + 1, mock: 2 + 0, tie broken by name
+        assert_eq!(from_a.signature_algorithm, "mock");
+        assert_eq!(from_a.peer_last_finalized_round, Some(7));
+        assert_eq!(
+            (from_b.version, from_b.signature_algorithm),
+            (from_a.version, from_a.signature_algorithm)
+        );
+        assert_eq!(from_b.peer_last_finalized_round, None);
+
+        let decoded = Handshake::decode(&b.encode()).unwrap();
+        assert_eq!(decoded, b);
+    }
+
+    #[test]
+    fn payload_compression_negotiated() {
+        let (lz4, none) = (PayloadCompression::Lz4, PayloadCompression::None);
+        let a = handshake("main", &["mock"]).with_payload_compressions(&[lz4, none]);
+        let b = handshake("main", &["mock"]).with_payload_compressions(&[none, lz4]);
+        // lz4: 0 + 1, none: 1 + 0, tie broken by name
+        assert_eq!(a.negotiate(&b).unwrap().payload_compression, lz4);
+        assert_eq!(b.negotiate(&a).unwrap().payload_compression, lz4);
+        // Common compressions are not enough, the sets must match
+        for other in [vec![lz4], vec![none]] {
+            let b = handshake("main", &["mock"]).with_payload_compressions(&other);
+            assert!(matches!(
+                a.negotiate(&b),
+                Err(HandshakeError::PayloadCompressionMismatch { .. })
+            ));
+        }
+        let mut b = b;
+        b.payload_compressions.push("zstd".to_owned());
+        assert!(matches!(
+            a.negotiate(&b),
+            Err(HandshakeError::PayloadCompressionMismatch { .. })
+        ));
+
+        // Peers of both ages read each other's handshakes
+        let old = HandshakeV1 {
+            network_id: a.network_id.clone(),
+            min_version: a.min_version,
+            max_version: a.max_version,
+            hash_algorithms: a.hash_algorithms.clone(),
+            signature_algorithms: a.signature_algorithms.clone(),
+            last_finalized_round: Some(3),
+        };
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(bincode::serialize(&old).unwrap());
+        let decoded = Handshake::decode(&bytes).unwrap();
+        assert_eq!(decoded.last_finalized_round, Some(3));
+        assert!(a.negotiate(&decoded).is_err());
+        let uncompressed = handshake("main", &["mock"]);
+        assert_eq!(
+            uncompressed
+                .negotiate(&decoded)
+                .unwrap()
+                .payload_compression,
+            none
+        );
+        let new = a.encode();
+        let read_by_old: HandshakeV1 = bincode::deserialize(&new[MAGIC.len()..]).unwrap();
+        assert_eq!(read_by_old.signature_algorithms, a.signature_algorithms);
+    }
+
+    #[test]
+    fn incompatible_peers_rejected() {
+        let a = handshake("main", &["ed25519"]);
+        assert!(matches!(
+            a.negotiate(&handshake("test", &["ed25519"])),
+            Err(HandshakeError::NetworkMismatch { .. })
+        ));
+        assert!(matches!(
+            a.negotiate(&handshake("main", &["mock"])),
+            Err(HandshakeError::NoCommonSignatureAlgorithm { .. })
+        ));
+        let mut future = handshake("main", &["ed25519"]);
+        future.min_version = WIRE_VERSION + 1;
+        future.max_version = WIRE_VERSION + 2;
+        assert!(matches!(
+            a.negotiate(&future),
+            Err(HandshakeError::IncompatibleVersion { theirs, .. })
+                if theirs == (WIRE_VERSION + 1..=WIRE_VERSION + 2)
+        ));
+        let mut other_hash = handshake("main", &["ed25519"]);
+        other_hash.hash_algorithms = vec!["sha3-256".to_owned()];
+        assert!(matches!(
+            a.negotiate(&other_hash),
+            Err(HandshakeError::NoCommonHashAlgorithm { .. })
+        ));
+        assert!(matches!(
+            Handshake::decode(b"HGSY"),
+            Err(HandshakeError::BadMagic)
+        ));
+        assert!(matches!(
+            Handshake::decode(b"HGHS\x01"),
+            Err(HandshakeError::Malformed(_))
+        ));
+    }
+}
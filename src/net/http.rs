@@ -0,0 +1,666 @@
+//! Sync over plain HTTP (`net-http` feature), for environments where raw
+//! sockets or libp2p are not allowed but HTTP requests get through.
+//!
+//! [`HttpServer`] serves a [shared graph](SharedGraph) on three endpoints,
+//! bodies are in the [wire format](crate::algorithm::datastructure::sync::wire):
+//! - `GET /summary` answers with the [`Summary`] of the graph;
+//! - `POST /sync` takes the client's [`Summary`] and answers with the events
+//!   it misses, as [`Jobs`] chunks of at most [`HttpConfig::chunk_size`]
+//!   events. Each chunk is framed as in the [node](super::node): `u32`
+//!   little endian length followed by the message. When the client knows
+//!   everything, the request is held until new events arrive or
+//!   [`HttpConfig::long_poll`] passes (then the body is empty), so clients
+//!   can poll in a loop without flooding the server;
+//! - `POST /submit` takes [`Jobs`] with events for the server (e.g. the
+//!   client's own) and answers with the number of new ones in decimal.
+//!   Submissions wait for the [ingress limits](HttpConfig::ingress) of the
+//!   client's IP address, ones that don't fit are answered with 429.
+//!
+//! [`HttpClient`] makes these requests. Each connection carries a single
+//! request. There is no [handshake](super::handshake), the wire version is
+//! checked for each message, and as in the node, events are checked only by
+//! their signatures. Finalized events are left in the graph for the
+//! application to drain.
+
+use std::fmt::Debug;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tracing::{debug, warn};
+
+use super::gate::IngressGate;
+use super::protocol::DEFAULT_CHUNK_SIZE;
+use crate::algorithm::codec::PayloadCodec;
+use crate::algorithm::datastructure::shared::SharedGraph;
+use crate::algorithm::datastructure::sync::ingress::IngressLimits;
+use crate::algorithm::datastructure::sync::wire::{WireError, WireMessage};
+use crate::algorithm::datastructure::sync::{Jobs, Summary};
+use crate::algorithm::{Clock, Signer};
+
+/// Bodies longer than this are rejected without reading
+pub const MAX_BODY_LEN: usize = 64 * 1024 * 1024;
+/// Limit on the request line and headers together
+const MAX_HEAD_LEN: usize = 16 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    pub listen: SocketAddr,
+    pub chunk_size: usize,
+    /// How long `/sync` waits for new events when the client knows all of
+    /// ours
+    pub long_poll: Duration,
+    /// How often the graph is checked for new events while waiting
+    pub poll_interval: Duration,
+    /// Limit on reading a request and writing the response, not counting
+    /// the long poll
+    pub request_timeout: Duration,
+    /// Limit on the connections served at once, further ones wait to be
+    /// accepted
+    pub max_inbound: usize,
+    /// Limits on the events submitted by clients, per IP address. Requests
+    /// wait for their turn, ones dropped from the full queues are answered
+    /// with 429.
+    pub ingress: IngressLimits,
+}
+
+impl HttpConfig {
+    pub fn new(listen: SocketAddr) -> Self {
+        Self {
+            listen,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            long_poll: Duration::from_secs(25),
+            poll_interval: Duration::from_millis(50),
+            request_timeout: Duration::from_secs(10),
+            max_inbound: 64,
+            ingress: IngressLimits::default(),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum HttpError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Body of {0} bytes exceeds the limit")]
+    BodyTooLarge(usize),
+    #[error("Malformed HTTP message: {0}")]
+    Malformed(&'static str),
+    #[error("Server answered with status {0}")]
+    Status(u16),
+    #[error(transparent)]
+    Wire(#[from] WireError),
+}
+
+/// Request line (or status line) and body of an HTTP message
+struct HttpMessage {
+    start: String,
+    body: Vec<u8>,
+}
+
+/// Read a message that has `Content-Length` (or no body)
+async fn read_message<R>(reader: &mut R) -> Result<HttpMessage, HttpError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut buffer = vec![];
+    let head_end = loop {
+        if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end;
+        }
+        if buffer.len() > MAX_HEAD_LEN {
+            return Err(HttpError::Malformed("headers are too long"));
+        }
+        let mut chunk = [0; 1024];
+        let read = reader.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(HttpError::Malformed("connection closed in headers"));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+    let head =
+        std::str::from_utf8(&buffer[..head_end]).map_err(|_| HttpError::Malformed("not UTF-8"))?;
+    let mut lines = head.split("\r\n");
+    let start = lines.next().unwrap_or_default().to_owned();
+    let mut length = 0;
+    for line in lines {
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(HttpError::Malformed("header without a colon"))?;
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            length = value
+                .trim()
+                .parse()
+                .map_err(|_| HttpError::Malformed("bad content length"))?;
+        }
+    }
+    if length > MAX_BODY_LEN {
+        return Err(HttpError::BodyTooLarge(length));
+    }
+    let mut body = buffer.split_off(head_end + 4);
+    if body.len() > length {
+        return Err(HttpError::Malformed("body is longer than its length"));
+    }
+    // Grows with the received bytes, announcing a large body and sending
+    // nothing costs no memory
+    let rest = length - body.len();
+    reader.take(rest as u64).read_to_end(&mut body).await?;
+    if body.len() < length {
+        return Err(HttpError::Malformed("connection closed in the body"));
+    }
+    Ok(HttpMessage { start, body })
+}
+
+async fn write_message<W>(writer: &mut W, start: &str, body: &[u8]) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let head = format!(
+        "{start}\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    writer.write_all(head.as_bytes()).await?;
+    writer.write_all(body).await?;
+    writer.flush().await
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        429 => "Too Many Requests",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Length-prefixed messages, as in the body of `/sync` responses
+fn frame(messages: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = vec![];
+    for message in messages {
+        bytes.extend_from_slice(&(message.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(message);
+    }
+    bytes
+}
+
+fn unframe(mut bytes: &[u8]) -> Result<Vec<&[u8]>, HttpError> {
+    let mut messages = vec![];
+    while !bytes.is_empty() {
+        let (len, rest) = bytes
+            .split_first_chunk::<4>()
+            .ok_or(HttpError::Malformed("truncated frame length"))?;
+        let len = u32::from_le_bytes(*len) as usize;
+        if rest.len() < len {
+            return Err(HttpError::Malformed("truncated frame"));
+        }
+        let (message, rest) = rest.split_at(len);
+        messages.push(message);
+        bytes = rest;
+    }
+    Ok(messages)
+}
+
+/// Running server, see the [module docs](self). Stops when dropped.
+pub struct HttpServer {
+    local_addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl HttpServer {
+    /// Bind the listener and spawn the serving task. Has to be called
+    /// within a tokio runtime.
+    pub async fn start<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>(
+        graph: SharedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+        config: HttpConfig,
+    ) -> io::Result<Self>
+    where
+        TPayload: PayloadCodec + Eq + std::hash::Hash + Debug + Clone + Send + Sync + 'static,
+        TGenesisPayload: Serialize
+            + DeserializeOwned
+            + Eq
+            + std::hash::Hash
+            + Debug
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        TPeerId: Serialize
+            + DeserializeOwned
+            + Eq
+            + std::hash::Hash
+            + Debug
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId> + Send + Sync + 'static,
+        TClock: Clock + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind(config.listen).await?;
+        let local_addr = listener.local_addr()?;
+        let task = tokio::spawn(accept_loop(listener, graph, config));
+        Ok(Self { local_addr, task })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for HttpServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn accept_loop<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>(
+    listener: TcpListener,
+    graph: SharedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+    config: HttpConfig,
+) where
+    TPayload: PayloadCodec + Eq + std::hash::Hash + Debug + Clone + Send + Sync + 'static,
+    TGenesisPayload:
+        Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone + Send + Sync + 'static,
+    TPeerId:
+        Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone + Send + Sync + 'static,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId> + Send + Sync + 'static,
+    TClock: Clock + Send + Sync + 'static,
+{
+    let slots = Arc::new(Semaphore::new(config.max_inbound));
+    let ingress = Arc::new(IngressGate::new(config.ingress.clone()));
+    loop {
+        let slot = slots
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("The semaphore is never closed");
+        let (mut stream, address) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!("Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        let graph = graph.clone();
+        let config = config.clone();
+        let ingress = ingress.clone();
+        tokio::spawn(async move {
+            let _slot = slot;
+            let (status, body) = match timeout(config.request_timeout, read_message(&mut stream))
+                .await
+            {
+                Ok(Ok(request)) => handle(&request, address.ip(), &graph, &config, &ingress).await,
+                Ok(Err(HttpError::BodyTooLarge(_))) => (413, vec![]),
+                Ok(Err(e)) => {
+                    warn!(%address, "Failed to read a request: {}", e);
+                    (400, vec![])
+                }
+                Err(_) => {
+                    warn!(%address, "Reading a request timed out");
+                    return;
+                }
+            };
+            let start = format!("HTTP/1.1 {status} {}", reason(status));
+            match timeout(
+                config.request_timeout,
+                write_message(&mut stream, &start, &body),
+            )
+            .await
+            {
+                Ok(Ok(())) => (),
+                Ok(Err(e)) => warn!(%address, "Failed to answer: {}", e),
+                Err(_) => warn!(%address, "Answering timed out"),
+            }
+        });
+    }
+}
+
+/// Status and body of the response to the client at `ip`
+async fn handle<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>(
+    request: &HttpMessage,
+    ip: IpAddr,
+    graph: &SharedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+    config: &HttpConfig,
+    ingress: &IngressGate<TPayload, TGenesisPayload, TPeerId, IpAddr>,
+) -> (u16, Vec<u8>)
+where
+    TPayload: PayloadCodec + Eq + std::hash::Hash + Debug + Clone,
+    TGenesisPayload: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TPeerId: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+    TClock: Clock,
+{
+    let mut parts = request.start.split(' ');
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+    let result = match (method, path) {
+        ("GET", "/summary") => graph.read().summary().to_wire().map(|body| (200, body)),
+        ("POST", "/sync") => match Summary::from_wire(&request.body) {
+            Ok(summary) => sync(&summary, graph, config).await,
+            Err(e) => {
+                debug!("Bad summary: {}", e);
+                return (400, vec![]);
+            }
+        },
+        ("POST", "/submit") => match Jobs::from_wire(&request.body) {
+            Ok(jobs) => match ingress.admit(ip, jobs).await {
+                Some(jobs) => Ok(submit(jobs, graph)),
+                None => return (429, vec![]),
+            },
+            Err(e) => {
+                debug!("Bad submitted events: {}", e);
+                return (400, vec![]);
+            }
+        },
+        (_, "/summary" | "/sync" | "/submit") => return (405, vec![]),
+        _ => return (404, vec![]),
+    };
+    result.unwrap_or_else(|e| {
+        warn!("Failed to encode the response: {}", e);
+        (500, vec![])
+    })
+}
+
+/// Apply submitted events
+fn submit<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>(
+    jobs: Jobs<TPayload, TGenesisPayload, TPeerId>,
+    graph: &SharedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+) -> (u16, Vec<u8>)
+where
+    TPayload: PayloadCodec + Eq + std::hash::Hash + Debug + Clone,
+    TGenesisPayload: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TPeerId: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+    TClock: Clock,
+{
+    match graph.write().apply_sync_jobs(jobs) {
+        Ok(applied) => (200, applied.to_string().into_bytes()),
+        Err(e) => {
+            warn!("Submitted events rejected: {}", e);
+            (422, e.to_string().into_bytes())
+        }
+    }
+}
+
+/// Events missing in `summary`, waiting for them if there are none yet
+async fn sync<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>(
+    summary: &Summary<TPeerId>,
+    graph: &SharedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+    config: &HttpConfig,
+) -> Result<(u16, Vec<u8>), WireError>
+where
+    TPayload: PayloadCodec + Eq + std::hash::Hash + Debug + Clone,
+    TGenesisPayload: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TPeerId: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+    TClock: Clock,
+{
+    let deadline = Instant::now() + config.long_poll;
+    loop {
+        let jobs = match graph.read().generate_sync_for_summary(summary) {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                warn!("Failed to generate sync: {}", e);
+                return Ok((500, vec![]));
+            }
+        };
+        if !jobs.as_linear().is_empty() || Instant::now() >= deadline {
+            let chunks = jobs
+                .into_chunks(config.chunk_size.max(1))
+                .iter()
+                .map(WireMessage::to_wire)
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok((200, frame(&chunks)));
+        }
+        tokio::time::sleep(config.poll_interval).await;
+    }
+}
+
+/// Client of an [`HttpServer`]
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    server: SocketAddr,
+}
+
+impl HttpClient {
+    pub fn new(server: SocketAddr) -> Self {
+        Self { server }
+    }
+
+    async fn request(&self, start: &str, body: &[u8]) -> Result<Vec<u8>, HttpError> {
+        let mut stream = TcpStream::connect(self.server).await?;
+        write_message(&mut stream, start, body).await?;
+        let response = read_message(&mut stream).await?;
+        let status = response
+            .start
+            .split(' ')
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or(HttpError::Malformed("bad status line"))?;
+        if status != 200 {
+            return Err(HttpError::Status(status));
+        }
+        Ok(response.body)
+    }
+
+    pub async fn summary<TPeerId>(&self) -> Result<Summary<TPeerId>, HttpError>
+    where
+        TPeerId: Serialize + DeserializeOwned,
+    {
+        let body = self.request("GET /summary HTTP/1.1", &[]).await?;
+        Ok(Summary::from_wire(&body)?)
+    }
+
+    /// Events missing in `summary`, in chunks. Empty if none arrived at the
+    /// server within its long poll.
+    pub async fn sync<TPayload, TGenesisPayload, TPeerId>(
+        &self,
+        summary: &Summary<TPeerId>,
+    ) -> Result<Vec<Jobs<TPayload, TGenesisPayload, TPeerId>>, HttpError>
+    where
+        TPayload: PayloadCodec,
+        TGenesisPayload: Serialize + DeserializeOwned,
+        TPeerId: Serialize + DeserializeOwned,
+    {
+        let body = self
+            .request("POST /sync HTTP/1.1", &summary.to_wire()?)
+            .await?;
+        unframe(&body)?
+            .into_iter()
+            .map(|chunk| Ok(Jobs::from_wire(chunk)?))
+            .collect()
+    }
+
+    /// Returns the number of events new to the server
+    pub async fn submit<TPayload, TGenesisPayload, TPeerId>(
+        &self,
+        jobs: &Jobs<TPayload, TGenesisPayload, TPeerId>,
+    ) -> Result<usize, HttpError>
+    where
+        TPayload: PayloadCodec,
+        TGenesisPayload: Serialize + DeserializeOwned,
+        TPeerId: Serialize + DeserializeOwned,
+    {
+        let body = self
+            .request("POST /submit HTTP/1.1", &jobs.to_wire()?)
+            .await?;
+        std::str::from_utf8(&body)
+            .ok()
+            .and_then(|applied| applied.parse().ok())
+            .ok_or(HttpError::Malformed("bad number of applied events"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::datastructure::sync::ingress::{DropPolicy, Rate};
+    use crate::testing::{GraphBuilder, TestGraph};
+
+    /// Graph of the server (0) or of a client with only its genesis
+    fn graph(id: u64) -> TestGraph<u64, u64> {
+        GraphBuilder::new("a", id, 0u64, 999).build().unwrap().graph
+    }
+
+    fn config() -> HttpConfig {
+        HttpConfig {
+            long_poll: Duration::from_millis(300),
+            poll_interval: Duration::from_millis(5),
+            ..HttpConfig::new("127.0.0.1:0".parse().unwrap())
+        }
+    }
+
+    #[tokio::test]
+    async fn sync_and_submit() {
+        let shared: SharedGraph<_, _, _, _, _> = graph(0).into();
+        let server = HttpServer::start(shared.clone(), config()).await.unwrap();
+        let client = HttpClient::new(server.local_addr());
+        let mut local = graph(1);
+
+        let summary: Summary<u64> = client.summary().await.unwrap();
+        assert_eq!(summary, shared.read().summary());
+        let chunks = client.sync(&local.summary()).await.unwrap();
+        assert_eq!(chunks.len(), 1);
+        for chunk in chunks {
+            local.apply_sync_jobs(chunk).unwrap();
+        }
+        let tip = local.peer_latest_event(&0).unwrap().clone();
+        local.create_event(7, tip).unwrap();
+        let jobs = local
+            .generate_sync_for_summary(&client.summary().await.unwrap())
+            .unwrap();
+        assert_eq!(client.submit(&jobs).await.unwrap(), 2);
+        assert_eq!(shared.read().summary(), local.summary());
+
+        // Nothing new within the long poll
+        let started = Instant::now();
+        let chunks: Vec<Jobs<u64, (), u64>> = client.sync(&local.summary()).await.unwrap();
+        assert!(chunks.is_empty());
+        assert!(started.elapsed() >= config().long_poll);
+    }
+
+    #[tokio::test]
+    async fn sync_waits_for_events() {
+        let shared: SharedGraph<_, _, _, _, _> = graph(0).into();
+        let server = HttpServer::start(
+            shared.clone(),
+            HttpConfig {
+                long_poll: Duration::from_secs(30),
+                ..config()
+            },
+        )
+        .await
+        .unwrap();
+        let client = HttpClient::new(server.local_addr());
+        let summary = shared.read().summary();
+        let author = tokio::spawn({
+            let shared = shared.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let mut graph = shared.write();
+                let tip = graph.self_tip().unwrap().clone();
+                graph.create_event(1, tip).unwrap();
+            }
+        });
+        let chunks: Vec<Jobs<u64, (), u64>> =
+            timeout(Duration::from_secs(10), client.sync(&summary))
+                .await
+                .expect("Long poll didn't return after new events")
+                .unwrap();
+        author.await.unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(
+            Some(chunks[0].as_linear()[0].hash()),
+            shared.read().self_tip()
+        );
+    }
+
+    #[tokio::test]
+    async fn clients_bounded() {
+        let mut truncated = b"POST /submit HTTP/1.1\r\nContent-Length: 67108864\r\n\r\n".to_vec();
+        truncated.extend_from_slice(&[1, 2, 3]);
+        assert!(matches!(
+            read_message(&mut &truncated[..]).await,
+            Err(HttpError::Malformed("connection closed in the body"))
+        ));
+
+        let shared: SharedGraph<_, _, _, _, _> = graph(0).into();
+        let server = HttpServer::start(
+            shared.clone(),
+            HttpConfig {
+                max_inbound: 2,
+                ingress: IngressLimits {
+                    per_peer_messages: Rate {
+                        per_second: 4,
+                        burst: 1,
+                    },
+                    per_peer_queue: 1,
+                    drop_policy: DropPolicy::DropNewest,
+                    ..IngressLimits::default()
+                },
+                ..config()
+            },
+        )
+        .await
+        .unwrap();
+        let client = HttpClient::new(server.local_addr());
+
+        // Silent connections take the slots until they go away
+        let mut silent = vec![];
+        for _ in 0..2 {
+            silent.push(TcpStream::connect(server.local_addr()).await.unwrap());
+        }
+        let waiting = timeout(Duration::from_millis(200), client.summary::<u64>()).await;
+        assert!(waiting.is_err());
+        drop(silent);
+        let summary = client.summary().await.unwrap();
+
+        // Submissions beyond the rate wait, ones beyond the queue are refused
+        let jobs = graph(1).generate_sync_for_summary(&summary).unwrap();
+        assert_eq!(client.submit(&jobs).await.unwrap(), 1);
+        let (waiting, dropped) = tokio::join!(client.submit(&jobs), async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            client.submit(&jobs).await
+        });
+        assert!(matches!(dropped, Err(HttpError::Status(429))));
+        assert_eq!(waiting.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn bad_requests() {
+        let server = HttpServer::start(graph(0).into(), config()).await.unwrap();
+        let client = HttpClient::new(server.local_addr());
+        for (start, body, status) in [
+            ("GET /nothing HTTP/1.1", vec![], 404),
+            ("GET /sync HTTP/1.1", vec![], 405),
+            ("POST /sync HTTP/1.1", vec![1, 2, 3], 400),
+            ("POST /submit HTTP/1.1", vec![], 400),
+        ] {
+            assert!(
+                matches!(
+                    client.request(start, &body).await,
+                    Err(HttpError::Status(s)) if s == status
+                ),
+                "{start}"
+            );
+        }
+        assert_eq!(
+            unframe(&frame(&[vec![1], vec![]])).unwrap(),
+            [&[1][..], &[]]
+        );
+        assert!(unframe(&[1, 0, 0, 0]).is_err());
+    }
+}
@@ -0,0 +1,13 @@
+//! Networking for running the graph on real nodes. The protocol is
+//! transport-agnostic, transports are optional and enabled by features.
+
+#[cfg(any(feature = "node", feature = "net-http"))]
+mod gate;
+pub mod handshake;
+#[cfg(feature = "net-http")]
+pub mod http;
+#[cfg(feature = "node")]
+pub mod node;
+#[cfg(feature = "net-libp2p")]
+pub mod p2p;
+pub mod protocol;
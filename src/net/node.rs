@@ -0,0 +1,1307 @@
+//! Minimal runnable node over plain TCP (`node` feature). Serves as a
+//! reference for integrating the [protocol](super::protocol) with a transport.
+//!
+//! The node:
+//! - accepts connections on [`NodeConfig::listen`] and answers sync requests
+//!   (unless [`NodeConfig::pull_only`] is set);
+//! - every [`NodeConfig::gossip_interval`] connects to a configured peer
+//!   (chosen at random, favouring the ones that recently delivered more new
+//!   events per second, see [`Node::peer_scores`]), pulls the events it doesn't know (pushing its own in the pull-only
+//!   mode) and, when its [cadence policy](crate::algorithm::cadence) says
+//!   so (by default after every sync), authors an event with the latest
+//!   synced peer's latest event as the other parent. The event carries transactions
+//!   [submitted](Node::submit) since the previous one, so the graph payload
+//!   is a batch `Vec<T>`. Receiving pushed events also results in an (empty)
+//!   event, otherwise events of pull-only nodes would never get descendants;
+//! - emits transactions of finalized events in consensus order, see
+//!   [`Node::next_finalized`].
+//!
+//! Each message is sent as a frame: `u32` little endian length followed by
+//! the message in the [wire format](crate::algorithm::datastructure::sync::wire).
+//! The first frame in both directions is the [handshake](super::handshake),
+//! then the accepting side sends its hello.
+//!
+//! Members are the peers whose geneses are known, so all of them should
+//! be known to every node before authoring starts (e.g. distributed along
+//! with the configuration), otherwise early rounds are decided by a subset.
+//!
+//! The node drains [`Graph::next_finalized_event`], so the application
+//! shouldn't do it through [`Node::graph`]. No encryption or authentication
+//! of connections is done, events are checked by their signatures only.
+//! Unauthenticated peers are bounded instead: handshakes larger than
+//! [`MAX_HANDSHAKE_LEN`] are refused, frames are read as their bytes
+//! arrive rather than allocated up front from the announced length, at
+//! most [`NodeConfig::max_inbound`] connections are served at once, and
+//! frames being received take at most [`NodeConfig::max_buffered`] bytes in
+//! total. Served peers silent for [`NodeConfig::read_timeout`] are
+//! disconnected.
+
+use std::fmt::Debug;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tracing::{debug, warn};
+
+use super::gate::IngressGate;
+use super::handshake::{Handshake, HandshakeError, Negotiated};
+use super::protocol::{Message, Protocol, ProtocolError, ProtocolEvent, DEFAULT_CHUNK_SIZE};
+use crate::algorithm::cadence::{Cadence, CadencePolicy, EverySync, QueueFull};
+use crate::algorithm::compression::PayloadCompression;
+use crate::algorithm::datastructure::shared::SharedGraph;
+use crate::algorithm::datastructure::sync::ingress::IngressLimits;
+use crate::algorithm::datastructure::sync::wire::{WireError, WireMessage};
+use crate::algorithm::datastructure::sync::Jobs;
+use crate::algorithm::datastructure::Graph;
+use crate::algorithm::strategy::{PeerScore, Scored};
+use crate::algorithm::{event, Clock, Signer};
+
+/// Frames longer than this are rejected without reading
+pub const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+/// Limit on the handshake frame, the only one read before the peer is known
+/// to speak the protocol
+pub const MAX_HANDSHAKE_LEN: usize = 4 * 1024;
+
+type Batch<T> = Vec<T>;
+type NodeMessage<T, TGenesisPayload, TPeerId> = Message<Batch<T>, TGenesisPayload, TPeerId>;
+type NodeGraph<T, TGenesisPayload, TPeerId, TSigner, TClock> =
+    SharedGraph<Batch<T>, TGenesisPayload, TPeerId, TSigner, TClock>;
+type NodeCadence<T> = Arc<Mutex<Cadence<T, Box<dyn CadencePolicy + Send>>>>;
+/// Peers that haven't named themselves in a sync request share the `None`
+/// queue
+type NodeIngress<T, TGenesisPayload, TPeerId> =
+    IngressGate<Batch<T>, TGenesisPayload, TPeerId, Option<TPeerId>>;
+
+/// Limits shared by all connections of a node
+struct Inbound<T, TGenesisPayload, TPeerId> {
+    /// A permit per byte of the frames being received
+    buffered: Semaphore,
+    max_buffered: usize,
+    ingress: NodeIngress<T, TGenesisPayload, TPeerId>,
+}
+
+impl<T, TGenesisPayload, TPeerId> Inbound<T, TGenesisPayload, TPeerId>
+where
+    TPeerId: Eq + std::hash::Hash + Clone,
+{
+    fn new(config: &NodeConfig<TPeerId>) -> Self {
+        let max_buffered = config.max_buffered.clamp(1, Semaphore::MAX_PERMITS);
+        Self {
+            buffered: Semaphore::new(max_buffered),
+            max_buffered,
+            ingress: IngressGate::new(config.ingress.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NodeConfig<TPeerId> {
+    pub listen: SocketAddr,
+    /// Don't listen, only connect to peers. For nodes behind NAT.
+    pub pull_only: bool,
+    pub network_id: String,
+    /// See [`Handshake::signature_algorithms`]
+    pub signature_algorithms: Vec<String>,
+    /// Compressions of payloads in the network, the same on every node.
+    /// See [`Handshake::payload_compressions`], all of them must be
+    /// [supported](PayloadCompression::supported) by the build.
+    pub payload_compressions: Vec<PayloadCompression>,
+    /// Graph ids and addresses of peers to gossip with. More can be added
+    /// with [`Node::add_peer`].
+    pub peers: Vec<(TPeerId, SocketAddr)>,
+    pub gossip_interval: Duration,
+    /// Limit on the time of a whole exchange with a peer
+    pub session_timeout: Duration,
+    /// Limit on waiting for the handshake and, when serving, for each next
+    /// frame. Much shorter than `session_timeout`, so silent connections
+    /// don't hold their slots for long.
+    pub read_timeout: Duration,
+    pub chunk_size: usize,
+    /// Limit on the number of transactions in an authored event
+    pub max_batch: usize,
+    /// Limit on the number of transactions waiting for an event, further
+    /// ones are [refused](Node::submit)
+    pub max_queued: usize,
+    /// How fast peer scores forget old syncs, see [`Scored`]
+    pub score_half_life: Duration,
+    /// Seed of the choice of gossip partners. Random if `None`, set it to
+    /// make tests reproducible.
+    pub seed: Option<u64>,
+    /// Limit on the connections served at once, further ones wait to be
+    /// accepted
+    pub max_inbound: usize,
+    /// Limit on the bytes of frames being received at once over all
+    /// connections, further frames wait. Frames larger than the limit take
+    /// all of it.
+    pub max_buffered: usize,
+    /// Limits on the events received from peers, pulled and pushed alike.
+    /// Connections wait for their turn, ones with events dropped from the
+    /// full queues are closed.
+    pub ingress: IngressLimits,
+}
+
+impl<TPeerId> NodeConfig<TPeerId> {
+    pub fn new(
+        listen: SocketAddr,
+        network_id: impl Into<String>,
+        signature_algorithm: impl Into<String>,
+    ) -> Self {
+        Self {
+            listen,
+            pull_only: false,
+            network_id: network_id.into(),
+            signature_algorithms: vec![signature_algorithm.into()],
+            payload_compressions: vec![PayloadCompression::None],
+            peers: vec![],
+            gossip_interval: Duration::from_millis(100),
+            session_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(2),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            max_batch: 1024,
+            max_queued: 64 * 1024,
+            score_half_life: Duration::from_secs(30),
+            seed: None,
+            max_inbound: 64,
+            max_buffered: 256 * 1024 * 1024,
+            ingress: IngressLimits::default(),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum NodeError<TPeerId> {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Frame of {0} bytes exceeds the limit")]
+    FrameTooLarge(usize),
+    #[error("Connection closed in the middle of an exchange")]
+    Closed,
+    #[error("Received events were dropped by the ingress limits")]
+    Throttled,
+    #[error("Peer was silent for too long")]
+    TimedOut,
+    #[error(transparent)]
+    Handshake(#[from] HandshakeError),
+    #[error(transparent)]
+    Wire(#[from] WireError),
+    #[error(transparent)]
+    Protocol(#[from] ProtocolError<TPeerId>),
+}
+
+/// Transaction of a finalized event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinalizedTransaction<T, TPeerId> {
+    pub event: event::Hash,
+    pub author: TPeerId,
+    pub transaction: T,
+}
+
+/// Running node, see the [module docs](self). Background tasks are stopped
+/// when it is dropped.
+pub struct Node<T, TGenesisPayload, TPeerId, TSigner, TClock> {
+    graph: NodeGraph<T, TGenesisPayload, TPeerId, TSigner, TClock>,
+    local_addr: Option<SocketAddr>,
+    peers: Arc<Mutex<Vec<(TPeerId, SocketAddr)>>>,
+    scores: Arc<Mutex<Scored<TPeerId, StdRng>>>,
+    cadence: NodeCadence<T>,
+    finalized: mpsc::UnboundedReceiver<FinalizedTransaction<T, TPeerId>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl<T, TGenesisPayload, TPeerId, TSigner, TClock>
+    Node<T, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    T: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone + Send + Sync + 'static,
+    TGenesisPayload:
+        Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone + Send + Sync + 'static,
+    TPeerId:
+        Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone + Send + Sync + 'static,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId> + Send + Sync + 'static,
+    TClock: Clock + Send + Sync + 'static,
+{
+    /// Bind the listener (if any) and spawn the background tasks. Has to be
+    /// called within a tokio runtime. Fails if the build doesn't support a
+    /// payload compression of the network.
+    pub async fn start(
+        graph: NodeGraph<T, TGenesisPayload, TPeerId, TSigner, TClock>,
+        config: NodeConfig<TPeerId>,
+    ) -> io::Result<Self> {
+        Self::start_with_cadence(graph, config, EverySync).await
+    }
+
+    /// Same as [`start`](Self::start), authoring events according to
+    /// `policy`. It is polled every [`NodeConfig::gossip_interval`], times
+    /// are in nanoseconds since the start.
+    pub async fn start_with_cadence(
+        graph: NodeGraph<T, TGenesisPayload, TPeerId, TSigner, TClock>,
+        config: NodeConfig<TPeerId>,
+        policy: impl CadencePolicy + Send + 'static,
+    ) -> io::Result<Self> {
+        let supported = PayloadCompression::supported();
+        if let Some(unsupported) =
+            (config.payload_compressions.iter()).find(|c| !supported.contains(c))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Payload compression {} is not supported",
+                    unsupported.name()
+                ),
+            ));
+        }
+        let (finalized_sender, finalized) = mpsc::unbounded_channel();
+        let mut tasks = vec![];
+        let mut local_addr = None;
+        let inbound = Arc::new(Inbound::new(&config));
+        if !config.pull_only {
+            let listener = TcpListener::bind(config.listen).await?;
+            local_addr = Some(listener.local_addr()?);
+            tasks.push(tokio::spawn(accept_loop(
+                listener,
+                graph.clone(),
+                config.clone(),
+                inbound.clone(),
+                finalized_sender.clone(),
+            )));
+        }
+        let peers = Arc::new(Mutex::new(config.peers.clone()));
+        let rng = config
+            .seed
+            .map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+        let scores = Arc::new(Mutex::new(Scored::new(rng, config.score_half_life)));
+        let policy: Box<dyn CadencePolicy + Send> = Box::new(policy);
+        let cadence = Arc::new(Mutex::new(Cadence::new(
+            policy,
+            config.max_queued,
+            config.max_batch,
+            0,
+        )));
+        tasks.push(tokio::spawn(gossip_loop(
+            graph.clone(),
+            config,
+            peers.clone(),
+            scores.clone(),
+            cadence.clone(),
+            inbound,
+            finalized_sender,
+        )));
+        Ok(Self {
+            graph,
+            local_addr,
+            peers,
+            scores,
+            cadence,
+            finalized,
+            tasks,
+        })
+    }
+
+    /// `None` in the pull-only mode
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    pub fn graph(&self) -> &NodeGraph<T, TGenesisPayload, TPeerId, TSigner, TClock> {
+        &self.graph
+    }
+
+    pub fn add_peer(&self, id: TPeerId, address: SocketAddr) {
+        self.peers
+            .lock()
+            .expect("peers lock poisoned")
+            .push((id, address));
+    }
+
+    /// Scores of the peers synced with so far. Timestamps are nanoseconds
+    /// since the node start.
+    pub fn peer_scores(&self) -> Vec<(TPeerId, PeerScore)> {
+        self.scores
+            .lock()
+            .expect("scores lock poisoned")
+            .scores()
+            .map(|(peer, score)| (peer.clone(), *score))
+            .collect()
+    }
+
+    /// Queue the transaction for the next authored event. Handed back if
+    /// [`NodeConfig::max_queued`] transactions are waiting already.
+    pub fn submit(&self, transaction: T) -> Result<(), QueueFull<T>> {
+        self.cadence
+            .lock()
+            .expect("cadence lock poisoned")
+            .submit(transaction)
+    }
+
+    /// Number of transactions waiting for an event
+    pub fn queued(&self) -> usize {
+        self.cadence.lock().expect("cadence lock poisoned").queued()
+    }
+
+    /// Transactions of finalized events in consensus order
+    pub async fn next_finalized(&mut self) -> Option<FinalizedTransaction<T, TPeerId>> {
+        self.finalized.recv().await
+    }
+}
+
+impl<T, TGenesisPayload, TPeerId, TSigner, TClock> Drop
+    for Node<T, TGenesisPayload, TPeerId, TSigner, TClock>
+{
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+async fn write_bytes<W, TPeerId>(writer: &mut W, bytes: &[u8]) -> Result<(), NodeError<TPeerId>>
+where
+    W: AsyncWrite + Unpin,
+{
+    if bytes.len() > MAX_FRAME_LEN {
+        return Err(NodeError::FrameTooLarge(bytes.len()));
+    }
+    writer
+        .write_all(&(bytes.len() as u32).to_le_bytes())
+        .await?;
+    writer.write_all(bytes).await?;
+    Ok(())
+}
+
+/// `None` if the connection was closed between frames. Frames longer than
+/// `limit` are rejected without reading them.
+async fn read_bytes<R, TPeerId>(
+    reader: &mut R,
+    limit: usize,
+) -> Result<Option<Vec<u8>>, NodeError<TPeerId>>
+where
+    R: AsyncRead + Unpin,
+{
+    match read_len(reader, limit).await? {
+        Some(len) => Ok(Some(read_body(reader, len).await?)),
+        None => Ok(None),
+    }
+}
+
+/// Length of the next frame, see [`read_bytes`]
+async fn read_len<R, TPeerId>(
+    reader: &mut R,
+    limit: usize,
+) -> Result<Option<usize>, NodeError<TPeerId>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len).await {
+        Ok(_) => (),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > limit {
+        return Err(NodeError::FrameTooLarge(len));
+    }
+    Ok(Some(len))
+}
+
+async fn read_body<R, TPeerId>(reader: &mut R, len: usize) -> Result<Vec<u8>, NodeError<TPeerId>>
+where
+    R: AsyncRead + Unpin,
+{
+    // Grows with the received bytes, a peer announcing a large frame and
+    // sending nothing costs no memory
+    let mut bytes = vec![];
+    (&mut *reader)
+        .take(len as u64)
+        .read_to_end(&mut bytes)
+        .await?;
+    if bytes.len() < len {
+        return Err(NodeError::Closed);
+    }
+    Ok(bytes)
+}
+
+/// Frame in the wire `version` negotiated with the peer
+async fn write_frame<W, T, TGenesisPayload, TPeerId>(
+    writer: &mut W,
+    version: u16,
+    message: &NodeMessage<T, TGenesisPayload, TPeerId>,
+) -> Result<(), NodeError<TPeerId>>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize + DeserializeOwned,
+    TGenesisPayload: Serialize + DeserializeOwned,
+    TPeerId: Serialize + DeserializeOwned,
+{
+    write_bytes(writer, &message.to_wire_version(version)?).await
+}
+
+/// Bytes of the frame count towards [`NodeConfig::max_buffered`] until it
+/// is decoded
+async fn read_frame<R, T, TGenesisPayload, TPeerId>(
+    reader: &mut R,
+    inbound: &Inbound<T, TGenesisPayload, TPeerId>,
+) -> Result<Option<NodeMessage<T, TGenesisPayload, TPeerId>>, NodeError<TPeerId>>
+where
+    R: AsyncRead + Unpin,
+    T: Serialize + DeserializeOwned,
+    TGenesisPayload: Serialize + DeserializeOwned,
+    TPeerId: Serialize + DeserializeOwned,
+{
+    let Some(len) = read_len(reader, MAX_FRAME_LEN).await? else {
+        return Ok(None);
+    };
+    // Frames are at most `MAX_FRAME_LEN` long, so the count fits
+    let _buffered = inbound
+        .buffered
+        .acquire_many(len.min(inbound.max_buffered) as u32)
+        .await
+        .expect("The semaphore is never closed");
+    let bytes = read_body(reader, len).await?;
+    Ok(Some(Message::from_wire(&bytes)?))
+}
+
+/// Exchange handshakes, fails if the peer is incompatible
+async fn handshake<T, TGenesisPayload, TPeerId, TSigner, TClock>(
+    stream: &mut TcpStream,
+    graph: &NodeGraph<T, TGenesisPayload, TPeerId, TSigner, TClock>,
+    config: &NodeConfig<TPeerId>,
+) -> Result<Negotiated, NodeError<TPeerId>>
+where
+    TPeerId: Eq + std::hash::Hash,
+{
+    let ours = Handshake::new(
+        config.network_id.clone(),
+        config.signature_algorithms.clone(),
+        &graph.read(),
+    )
+    .with_payload_compressions(&config.payload_compressions);
+    write_bytes(stream, &ours.encode()).await?;
+    let theirs = timeout(config.read_timeout, read_bytes(stream, MAX_HANDSHAKE_LEN))
+        .await
+        .map_err(|_| NodeError::TimedOut)??
+        .ok_or(NodeError::Closed)?;
+    let negotiated = ours.negotiate(&Handshake::decode(&theirs)?)?;
+    debug!(
+        version = negotiated.version,
+        payload_compression = negotiated.payload_compression.name(),
+        "Handshake succeeded"
+    );
+    Ok(negotiated)
+}
+
+/// Wait until the limits let the events in `message` of `peer` through
+async fn admit<T, TGenesisPayload, TPeerId>(
+    inbound: &Inbound<T, TGenesisPayload, TPeerId>,
+    peer: &Option<TPeerId>,
+    message: &mut NodeMessage<T, TGenesisPayload, TPeerId>,
+) -> Result<(), NodeError<TPeerId>>
+where
+    TPeerId: Eq + std::hash::Hash + Clone,
+{
+    let jobs = match message {
+        Message::SyncRequest(request) => match &mut request.events {
+            Some(pushed) => pushed,
+            None => return Ok(()),
+        },
+        Message::SyncResponse { chunk, .. } => chunk,
+        _ => return Ok(()),
+    };
+    let received = std::mem::replace(jobs, Jobs::from_linear(vec![]));
+    *jobs = inbound
+        .ingress
+        .admit(peer.clone(), received)
+        .await
+        .ok_or(NodeError::Throttled)?;
+    Ok(())
+}
+
+fn drain_finalized<T, TGenesisPayload, TPeerId, TSigner, TClock>(
+    graph: &mut Graph<Batch<T>, TGenesisPayload, TPeerId, TSigner, TClock>,
+    sender: &mpsc::UnboundedSender<FinalizedTransaction<T, TPeerId>>,
+) where
+    T: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TGenesisPayload: Serialize + Eq + std::hash::Hash + Debug + Clone,
+    TPeerId: Serialize + Eq + std::hash::Hash + Debug + Clone,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+    TClock: Clock,
+{
+    while let Some(event) = graph.next_finalized_event() {
+        for transaction in event.payload().into_iter().flatten() {
+            // The receiver is gone only when the node is dropped
+            let _ = sender.send(FinalizedTransaction {
+                event: event.hash().clone(),
+                author: event.author().clone(),
+                transaction: transaction.clone(),
+            });
+        }
+    }
+}
+
+async fn accept_loop<T, TGenesisPayload, TPeerId, TSigner, TClock>(
+    listener: TcpListener,
+    graph: NodeGraph<T, TGenesisPayload, TPeerId, TSigner, TClock>,
+    config: NodeConfig<TPeerId>,
+    inbound: Arc<Inbound<T, TGenesisPayload, TPeerId>>,
+    finalized: mpsc::UnboundedSender<FinalizedTransaction<T, TPeerId>>,
+) where
+    T: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone + Send + Sync + 'static,
+    TGenesisPayload:
+        Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone + Send + Sync + 'static,
+    TPeerId:
+        Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone + Send + Sync + 'static,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId> + Send + Sync + 'static,
+    TClock: Clock + Send + Sync + 'static,
+{
+    let slots = Arc::new(Semaphore::new(config.max_inbound));
+    loop {
+        let slot = slots
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("The semaphore is never closed");
+        let (stream, address) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!("Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        let graph = graph.clone();
+        let config = config.clone();
+        let inbound = inbound.clone();
+        let finalized = finalized.clone();
+        tokio::spawn(async move {
+            let _slot = slot;
+            let result = timeout(
+                config.session_timeout,
+                serve(stream, &graph, &config, &inbound, &finalized),
+            )
+            .await;
+            match result {
+                Ok(Ok(())) => (),
+                Ok(Err(e)) => warn!(%address, "Serving the peer failed: {}", e),
+                Err(_) => warn!(%address, "Serving the peer timed out"),
+            }
+        });
+    }
+}
+
+/// Answer requests until the peer disconnects
+async fn serve<T, TGenesisPayload, TPeerId, TSigner, TClock>(
+    mut stream: TcpStream,
+    graph: &NodeGraph<T, TGenesisPayload, TPeerId, TSigner, TClock>,
+    config: &NodeConfig<TPeerId>,
+    inbound: &Inbound<T, TGenesisPayload, TPeerId>,
+    finalized: &mpsc::UnboundedSender<FinalizedTransaction<T, TPeerId>>,
+) -> Result<(), NodeError<TPeerId>>
+where
+    T: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TGenesisPayload: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TPeerId: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+    TClock: Clock,
+{
+    let version = handshake(&mut stream, graph, config).await?.version;
+    let mut protocol = Protocol::new(config.chunk_size);
+    let hello = protocol.hello(&graph.read());
+    write_frame(&mut stream, version, &hello).await?;
+    let mut remote = None;
+    while let Some(mut message) = timeout(config.read_timeout, read_frame(&mut stream, inbound))
+        .await
+        .map_err(|_| NodeError::TimedOut)??
+    {
+        if let Message::SyncRequest(request) = &message {
+            remote = Some(request.from.clone());
+        }
+        admit(inbound, &remote, &mut message).await?;
+        let output = protocol.handle(&mut graph.write(), message)?;
+        for event in output.events {
+            match event {
+                ProtocolEvent::Pushed { from, applied } if applied > 0 => {
+                    author_event(&mut graph.write(), vec![], Some(&from), finalized)
+                }
+                ProtocolEvent::ForkDetected(evidence) => {
+                    warn!("Pushed events contain a fork by {:?}", evidence.author)
+                }
+                ProtocolEvent::ForkReported {
+                    evidence,
+                    confirmed: true,
+                } => warn!("Peer reported a fork by {:?}", evidence.author),
+                _ => (),
+            }
+        }
+        for reply in &output.replies {
+            write_frame(&mut stream, version, reply).await?;
+        }
+    }
+    if !protocol.is_idle() {
+        return Err(NodeError::Closed);
+    }
+    Ok(())
+}
+
+/// Request the events we don't know from `peer` and apply them, pushing
+/// ours in the pull-only mode. Returns the number of new events.
+async fn pull<T, TGenesisPayload, TPeerId, TSigner, TClock>(
+    (peer, address): &(TPeerId, SocketAddr),
+    graph: &NodeGraph<T, TGenesisPayload, TPeerId, TSigner, TClock>,
+    config: &NodeConfig<TPeerId>,
+    inbound: &Inbound<T, TGenesisPayload, TPeerId>,
+    finalized: &mpsc::UnboundedSender<FinalizedTransaction<T, TPeerId>>,
+) -> Result<usize, NodeError<TPeerId>>
+where
+    T: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TGenesisPayload: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TPeerId: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+    TClock: Clock,
+{
+    let mut stream = TcpStream::connect(address).await?;
+    let peer = Some(peer.clone());
+    let version = handshake(&mut stream, graph, config).await?.version;
+    let mut protocol = if config.pull_only {
+        Protocol::pull_only(config.chunk_size)
+    } else {
+        Protocol::new(config.chunk_size)
+    };
+    let mut total = 0;
+    // Starts with the hello of the peer, which makes us request if needed
+    loop {
+        let mut message = read_frame(&mut stream, inbound)
+            .await?
+            .ok_or(NodeError::Closed)?;
+        admit(inbound, &peer, &mut message).await?;
+        let output = {
+            let mut graph = graph.write();
+            let output = protocol.handle(&mut graph, message)?;
+            drain_finalized(&mut graph, finalized);
+            output
+        };
+        for event in output.events {
+            match event {
+                ProtocolEvent::Synced { applied, .. } => total += applied,
+                ProtocolEvent::ForkDetected(evidence) => {
+                    warn!("Received events contain a fork by {:?}", evidence.author)
+                }
+                ProtocolEvent::ForkReported { .. } | ProtocolEvent::Pushed { .. } => (),
+            }
+        }
+        for reply in &output.replies {
+            write_frame(&mut stream, version, reply).await?;
+        }
+        if protocol.is_idle() {
+            break;
+        }
+    }
+    stream.shutdown().await?;
+    Ok(total)
+}
+
+async fn gossip_loop<T, TGenesisPayload, TPeerId, TSigner, TClock>(
+    graph: NodeGraph<T, TGenesisPayload, TPeerId, TSigner, TClock>,
+    config: NodeConfig<TPeerId>,
+    peers: Arc<Mutex<Vec<(TPeerId, SocketAddr)>>>,
+    scores: Arc<Mutex<Scored<TPeerId, StdRng>>>,
+    cadence: NodeCadence<T>,
+    inbound: Arc<Inbound<T, TGenesisPayload, TPeerId>>,
+    finalized: mpsc::UnboundedSender<FinalizedTransaction<T, TPeerId>>,
+) where
+    T: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TGenesisPayload: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TPeerId: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+    TClock: Clock,
+{
+    // Times of the cadence and the scores are counted from here
+    let started = Instant::now();
+    let mut synced_peer = None;
+    let mut interval = tokio::time::interval(config.gossip_interval);
+    loop {
+        interval.tick().await;
+        let peer = {
+            let peers = peers.lock().expect("peers lock poisoned");
+            let chosen = scores
+                .lock()
+                .expect("scores lock poisoned")
+                .choose(peers.iter().map(|(id, _)| id));
+            chosen.map(|i| peers[i].clone())
+        };
+        if let Some(peer) = peer {
+            let sync_started = Instant::now();
+            let result = timeout(
+                config.session_timeout,
+                pull(&peer, &graph, &config, &inbound, &finalized),
+            )
+            .await;
+            let (peer, _) = peer;
+            let applied = match &result {
+                Ok(Ok(applied)) => *applied,
+                _ => 0,
+            };
+            // Failures count as useless syncs taking the whole timeout
+            let rtt = match &result {
+                Ok(Ok(_)) => sync_started.elapsed(),
+                _ => config.session_timeout,
+            };
+            scores.lock().expect("scores lock poisoned").record_sync(
+                peer.clone(),
+                rtt,
+                applied,
+                started.elapsed().as_nanos(),
+            );
+            match result {
+                Ok(Ok(applied)) => {
+                    debug!(?peer, applied, "Pulled events");
+                    cadence
+                        .lock()
+                        .expect("cadence lock poisoned")
+                        .record_sync(applied);
+                    synced_peer = Some(peer);
+                }
+                Ok(Err(e)) => warn!(?peer, "Sync failed: {}", e),
+                Err(_) => warn!(?peer, "Sync timed out"),
+            }
+        }
+
+        if graph.read().is_observer() {
+            continue;
+        }
+        let batch = cadence
+            .lock()
+            .expect("cadence lock poisoned")
+            .poll(started.elapsed().as_nanos());
+        if let Some(batch) = batch {
+            author_event(&mut graph.write(), batch, synced_peer.as_ref(), &finalized);
+        }
+    }
+}
+
+/// Author an event on top of the latest event of `peer` we know (or of our
+/// own latest event)
+fn author_event<T, TGenesisPayload, TPeerId, TSigner, TClock>(
+    graph: &mut Graph<Batch<T>, TGenesisPayload, TPeerId, TSigner, TClock>,
+    batch: Batch<T>,
+    peer: Option<&TPeerId>,
+    finalized: &mpsc::UnboundedSender<FinalizedTransaction<T, TPeerId>>,
+) where
+    T: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone,
+    TGenesisPayload: Serialize + Eq + std::hash::Hash + Debug + Clone,
+    TPeerId: Serialize + Eq + std::hash::Hash + Debug + Clone,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+    TClock: Clock,
+{
+    let other_parent = peer
+        .and_then(|peer| graph.peer_latest_event(peer))
+        .or_else(|| graph.self_tip())
+        .cloned();
+    let Some(other_parent) = other_parent else {
+        warn!("Observers don't author events");
+        return;
+    };
+    if let Err(e) = graph.create_event(batch, other_parent) {
+        warn!("Failed to author an event: {}", e);
+    }
+    drain_finalized(graph, finalized);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::cadence::{Interval, QueuedTransactions};
+    use crate::algorithm::datastructure::sync::ingress::{DropPolicy, Rate};
+    use crate::algorithm::datastructure::sync::wire::WIRE_VERSION;
+    use crate::algorithm::{IncrementalClock, MockSigner};
+    use crate::testing::{seed_from_env, SEED_VARIABLE};
+
+    type TestNode = Node<u64, (), u64, MockSigner<u64, ()>, IncrementalClock>;
+    type TestGraph = Graph<Vec<u64>, (), u64, MockSigner<u64, ()>, IncrementalClock>;
+
+    /// Seed of the test, printed so that a failure with a random one can
+    /// be reproduced (the output is shown for failed tests only)
+    fn seed() -> u64 {
+        let seed = seed_from_env(0);
+        eprintln!("{}={}", SEED_VARIABLE, seed);
+        seed
+    }
+
+    fn config(network_id: &str) -> NodeConfig<u64> {
+        NodeConfig {
+            seed: Some(seed()),
+            ..NodeConfig::new("127.0.0.1:0".parse().unwrap(), network_id, "mock")
+        }
+    }
+
+    fn inbound() -> Inbound<u64, (), u64> {
+        Inbound::new(&config("main"))
+    }
+
+    async fn start(graph: TestGraph, mut config: NodeConfig<u64>) -> TestNode {
+        config.gossip_interval = Duration::from_millis(5);
+        Node::start(graph.into(), config).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn nodes_agree_on_transactions() {
+        let mut graphs: Vec<TestGraph> = (0..3)
+            .map(|id| {
+                Graph::new(
+                    id,
+                    vec![],
+                    (),
+                    999,
+                    MockSigner::new(),
+                    IncrementalClock::new(),
+                )
+            })
+            .collect();
+        // Membership must be known before consensus starts
+        for i in 0..3 {
+            for j in 0..3 {
+                if i != j {
+                    let jobs = graphs[j].generate_sync_for(&(i as u64)).unwrap();
+                    graphs[i].apply_sync_jobs(jobs).unwrap();
+                }
+            }
+        }
+        let mut nodes = vec![];
+        for graph in graphs {
+            nodes.push(start(graph, config("test")).await);
+        }
+        let addresses: Vec<_> = nodes.iter().map(|n| n.local_addr().unwrap()).collect();
+        for (i, node) in nodes.iter().enumerate() {
+            for (j, address) in addresses.iter().enumerate() {
+                if i != j {
+                    node.add_peer(j as u64, *address);
+                }
+            }
+        }
+        for (i, node) in nodes.iter().enumerate() {
+            for t in 0..3 {
+                node.submit(i as u64 * 10 + t).unwrap();
+            }
+        }
+
+        let result = timeout(Duration::from_secs(60), async {
+            let mut outputs = vec![];
+            for node in &mut nodes {
+                let mut transactions = vec![];
+                while transactions.len() < 9 {
+                    let finalized = node.next_finalized().await.unwrap();
+                    transactions.push(finalized.transaction);
+                }
+                outputs.push(transactions);
+            }
+            outputs
+        })
+        .await;
+        let outputs = result.expect("Transactions weren't finalized in time");
+        let mut sorted = outputs[0].clone();
+        sorted.sort();
+        assert_eq!(sorted, vec![0, 1, 2, 10, 11, 12, 20, 21, 22]);
+        assert!(outputs.iter().all(|o| o == &outputs[0]), "{:?}", outputs);
+        for node in &nodes {
+            let scores = node.peer_scores();
+            assert!(!scores.is_empty() && scores.len() <= 2, "{:?}", scores);
+            assert!(scores.iter().all(|(_, s)| s.syncs > 0));
+        }
+    }
+
+    #[tokio::test]
+    async fn pull_only_node_spreads_transactions() {
+        let mut graphs: Vec<TestGraph> = (0..2)
+            .map(|id| {
+                Graph::new(
+                    id,
+                    vec![],
+                    (),
+                    999,
+                    MockSigner::new(),
+                    IncrementalClock::new(),
+                )
+            })
+            .collect();
+        for i in 0..2 {
+            let jobs = graphs[1 - i].generate_sync_for(&(i as u64)).unwrap();
+            graphs[i].apply_sync_jobs(jobs).unwrap();
+        }
+        let listening = start(graphs.remove(0), config("test")).await;
+        let mut pull_only_config = config("test");
+        pull_only_config.pull_only = true;
+        pull_only_config.peers = vec![(0, listening.local_addr().unwrap())];
+        let mut pull_only = start(graphs.remove(0), pull_only_config).await;
+        assert_eq!(pull_only.local_addr(), None);
+        pull_only.submit(7).unwrap();
+
+        let result = timeout(Duration::from_secs(60), pull_only.next_finalized()).await;
+        let finalized = result
+            .expect("Transaction wasn't finalized in time")
+            .unwrap();
+        assert_eq!(finalized.transaction, 7);
+        assert_eq!(finalized.author, 1);
+        // Got there only by being pushed
+        assert!(listening.graph().read().event(&finalized.event).is_some());
+    }
+
+    #[tokio::test]
+    async fn cadence_controls_authoring() {
+        let graph = || {
+            Graph::new(
+                0,
+                vec![],
+                (),
+                999,
+                MockSigner::new(),
+                IncrementalClock::new(),
+            )
+        };
+        let mut config = config("test");
+        config.pull_only = true;
+        config.gossip_interval = Duration::from_millis(5);
+        config.max_queued = 2;
+        let never = Node::start_with_cadence(graph().into(), config.clone(), Interval(u128::MAX))
+            .await
+            .unwrap();
+        never.submit(1).unwrap();
+        never.submit(2).unwrap();
+        assert_eq!(never.submit(3), Err(QueueFull(3)));
+        assert_eq!(never.queued(), 2);
+
+        let batched: TestNode =
+            Node::start_with_cadence(graph().into(), config, QueuedTransactions(2))
+                .await
+                .unwrap();
+        let own_tip = || {
+            let graph = batched.graph().read();
+            graph
+                .event(graph.self_tip().unwrap())
+                .unwrap()
+                .payload()
+                .unwrap()
+                .clone()
+        };
+        batched.submit(1).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(batched.queued(), 1);
+        assert!(own_tip().is_empty());
+        batched.submit(2).unwrap();
+        let result = timeout(Duration::from_secs(10), async {
+            while batched.queued() > 0 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await;
+        result.expect("Batch wasn't authored in time");
+        assert_eq!(own_tip(), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn other_network_rejected() {
+        let graph = |id| {
+            Graph::new(
+                id,
+                vec![],
+                (),
+                999,
+                MockSigner::new(),
+                IncrementalClock::new(),
+            )
+        };
+        let node = start(graph(0), config("main")).await;
+        let other = graph(1).into();
+        let result = pull(
+            &(0, node.local_addr().unwrap()),
+            &other,
+            &config("test"),
+            &inbound(),
+            &mpsc::unbounded_channel().0,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(NodeError::Handshake(HandshakeError::NetworkMismatch { .. }))
+        ));
+        assert_eq!(other.read().peers().len(), 1);
+
+        // Compressions are a parameter of the network too
+        let compressed = NodeConfig {
+            payload_compressions: vec![PayloadCompression::Lz4, PayloadCompression::None],
+            ..config("main")
+        };
+        let result = pull(
+            &(0, node.local_addr().unwrap()),
+            &other,
+            &compressed,
+            &inbound(),
+            &mpsc::unbounded_channel().0,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(NodeError::Handshake(
+                HandshakeError::PayloadCompressionMismatch { .. }
+            ))
+        ));
+        assert_eq!(other.read().peers().len(), 1);
+        let started = Node::start(graph(2).into(), compressed).await;
+        assert_eq!(started.is_ok(), cfg!(feature = "compression"));
+    }
+
+    #[tokio::test]
+    async fn older_peer_served_in_its_version() {
+        let graph = |id| {
+            Graph::new(
+                id,
+                vec![],
+                (),
+                999,
+                MockSigner::new(),
+                IncrementalClock::new(),
+            )
+        };
+        let node = start(graph(0), config("main")).await;
+        let mut stream = TcpStream::connect(node.local_addr().unwrap())
+            .await
+            .unwrap();
+        let mut other: TestGraph = graph(1);
+        let ours = Handshake {
+            max_version: 1,
+            ..Handshake::new("main", vec!["mock".to_owned()], &other)
+        };
+        write_bytes::<_, u64>(&mut stream, &ours.encode())
+            .await
+            .unwrap();
+        let theirs = read_bytes::<_, u64>(&mut stream, MAX_HANDSHAKE_LEN)
+            .await
+            .unwrap()
+            .unwrap();
+        let version = ours
+            .negotiate(&Handshake::decode(&theirs).unwrap())
+            .unwrap()
+            .version;
+        assert_eq!(version, 1);
+
+        // Every frame of the node is in the negotiated version
+        let mut protocol = Protocol::new(16);
+        let mut synced = 0;
+        while !protocol.is_idle() || synced == 0 {
+            let bytes = read_bytes::<_, u64>(&mut stream, MAX_FRAME_LEN)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(bytes[..2], 1u16.to_le_bytes());
+            let message: NodeMessage<u64, (), u64> = Message::from_wire(&bytes).unwrap();
+            let output = protocol.handle(&mut other, message).unwrap();
+            for event in output.events {
+                if let ProtocolEvent::Synced { applied, .. } = event {
+                    synced += applied;
+                }
+            }
+            for reply in &output.replies {
+                write_frame::<_, u64, (), u64>(&mut stream, version, reply)
+                    .await
+                    .unwrap();
+            }
+        }
+        assert_eq!(synced, 1);
+        assert_eq!(other.peers().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn flooding_peer_throttled() {
+        let graph = |id| {
+            Graph::new(
+                id,
+                vec![],
+                (),
+                999,
+                MockSigner::new(),
+                IncrementalClock::new(),
+            )
+        };
+        let node = start(
+            graph(0),
+            NodeConfig {
+                ingress: IngressLimits {
+                    per_peer_messages: Rate {
+                        per_second: 2,
+                        burst: 1,
+                    },
+                    per_peer_events: Rate::UNLIMITED,
+                    global_messages: Rate::UNLIMITED,
+                    global_events: Rate::UNLIMITED,
+                    per_peer_queue: 1,
+                    global_queue: 16,
+                    drop_policy: DropPolicy::DropNewest,
+                },
+                ..config("main")
+            },
+        )
+        .await;
+        let target = (0, node.local_addr().unwrap());
+        let pushing = NodeConfig {
+            pull_only: true,
+            ..config("main")
+        };
+        let finalized = mpsc::unbounded_channel().0;
+        let (flooder, other): (NodeGraph<_, _, _, _, _>, _) = (graph(1).into(), graph(2).into());
+        let (target, pushing, finalized) = (&target, &pushing, &finalized);
+        let push = |graph| async move {
+            let result = pull(target, graph, pushing, &inbound(), finalized).await;
+            (result, Instant::now())
+        };
+
+        // Takes the only token of the flooder
+        push(&flooder).await.0.unwrap();
+        {
+            let mut flooder = flooder.write();
+            let tip = flooder.self_tip().unwrap().clone();
+            flooder.create_event(vec![1], tip).unwrap();
+        }
+        let (waiting, dropped, served) = tokio::join!(
+            push(&flooder),
+            async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                push(&flooder).await
+            },
+            async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                push(&other).await
+            }
+        );
+        assert!(dropped.0.is_err());
+        assert!(served.0.is_ok() && waiting.0.is_ok());
+        assert!(served.1 < waiting.1);
+        let node = node.graph().read();
+        assert!(node.peer_latest_event(&2).is_some());
+        assert_eq!(node.peer_latest_event(&1), flooder.read().self_tip());
+    }
+
+    #[tokio::test]
+    async fn unauthenticated_peers_bounded() {
+        let frame = |len: usize, body: &[u8]| {
+            let mut bytes = (len as u32).to_le_bytes().to_vec();
+            bytes.extend_from_slice(body);
+            bytes
+        };
+        let oversized = frame(MAX_HANDSHAKE_LEN + 1, &[0; 16]);
+        assert!(matches!(
+            read_bytes::<_, u64>(&mut &oversized[..], MAX_HANDSHAKE_LEN).await,
+            Err(NodeError::FrameTooLarge(len)) if len == MAX_HANDSHAKE_LEN + 1
+        ));
+        let truncated = frame(MAX_FRAME_LEN, &[1, 2, 3]);
+        assert!(matches!(
+            read_bytes::<_, u64>(&mut &truncated[..], MAX_FRAME_LEN).await,
+            Err(NodeError::Closed)
+        ));
+
+        // A silent connection takes the only slot until it goes away
+        let graph = |id| {
+            Graph::new(
+                id,
+                vec![],
+                (),
+                999,
+                MockSigner::new(),
+                IncrementalClock::new(),
+            )
+        };
+        let node = start(
+            graph(0),
+            NodeConfig {
+                max_inbound: 1,
+                ..config("main")
+            },
+        )
+        .await;
+        let address = node.local_addr().unwrap();
+        let silent = TcpStream::connect(address).await.unwrap();
+        let other = graph(1).into();
+        let finalized = mpsc::unbounded_channel().0;
+        let waiting = timeout(
+            Duration::from_millis(200),
+            pull(
+                &(0, address),
+                &other,
+                &config("main"),
+                &inbound(),
+                &finalized,
+            ),
+        )
+        .await;
+        assert!(waiting.is_err());
+        drop(silent);
+        let pulled = pull(
+            &(0, address),
+            &other,
+            &config("main"),
+            &inbound(),
+            &finalized,
+        )
+        .await;
+        assert_eq!(pulled.unwrap(), 1);
+
+        // Or until it times out
+        let node = start(
+            graph(2),
+            NodeConfig {
+                max_inbound: 1,
+                read_timeout: Duration::from_millis(100),
+                ..config("main")
+            },
+        )
+        .await;
+        let address = node.local_addr().unwrap();
+        let _silent = TcpStream::connect(address).await.unwrap();
+        let pulled = timeout(
+            Duration::from_secs(1),
+            pull(
+                &(2, address),
+                &other,
+                &config("main"),
+                &inbound(),
+                &finalized,
+            ),
+        )
+        .await;
+        assert_eq!(pulled.unwrap().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn buffered_frames_bounded() {
+        let inbound: Inbound<u64, (), u64> = Inbound::new(&NodeConfig {
+            max_buffered: 4,
+            ..config("main")
+        });
+        let mut bytes = Vec::new();
+        write_frame(
+            &mut bytes,
+            WIRE_VERSION,
+            &NodeMessage::<u64, (), u64>::Ack { applied: 1 },
+        )
+        .await
+        .unwrap();
+        // Frames larger than the budget wait for all of it
+        let taken = inbound.buffered.acquire().await.unwrap();
+        let waiting = timeout(
+            Duration::from_millis(50),
+            read_frame(&mut &bytes[..], &inbound),
+        )
+        .await;
+        assert!(waiting.is_err());
+        drop(taken);
+        let frame = read_frame(&mut &bytes[..], &inbound).await.unwrap();
+        assert!(matches!(frame, Some(Message::Ack { applied: 1 })));
+        assert_eq!(inbound.buffered.available_permits(), 4);
+    }
+}
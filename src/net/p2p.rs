@@ -0,0 +1,575 @@
+//! [libp2p](https://libp2p.io/) transport for syncing graphs (`net-libp2p`
+//! feature).
+//!
+//! Two protocols are used:
+//! - request/response on [`SYNC_PROTOCOL`]: [`SyncRequest`] is answered with
+//!   [`Jobs`], both in the [versioned wire format](crate::algorithm::datastructure::sync::wire);
+//! - gossipsub on [`TIPS_TOPIC`]: nodes announce their [`Summary`], peers that
+//!   see unknown tips in it request a sync from the announcer.
+//!
+//! [`SyncNode`] wires it all to a [`SharedGraph`]. The application drives it
+//! by polling [`SyncNode::next_event`] and decides when to announce tips and
+//! whom to sync with. Received events (responses and pushes alike) go
+//! through an [`Ingress`] keyed by libp2p peer ids and are applied when its
+//! limits allow. Mapping between libp2p peer ids and graph peer ids is
+//! up to the application as well.
+
+use std::fmt::Debug;
+use std::io;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use libp2p::futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, StreamExt};
+use libp2p::request_response::{self, OutboundRequestId, ProtocolSupport};
+use libp2p::swarm::{NetworkBehaviour, SwarmEvent};
+use libp2p::{gossipsub, identity, noise, tcp, yamux, Multiaddr, PeerId, StreamProtocol, Swarm};
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::algorithm::codec::PayloadCodec;
+use crate::algorithm::datastructure::shared::SharedGraph;
+use crate::algorithm::datastructure::sync::ingress::{Admission, Ingress, IngressLimits};
+use crate::algorithm::datastructure::sync::wire::{WireError, WireMessage};
+use crate::algorithm::datastructure::sync::{Jobs, Summary, SyncRequest};
+use crate::algorithm::{Clock, PushError, Signer};
+use crate::Timestamp;
+
+pub const SYNC_PROTOCOL: StreamProtocol = StreamProtocol::new("/hashgraph/sync/1");
+pub const TIPS_TOPIC: &str = "/hashgraph/tips/1";
+
+const MAX_REQUEST_SIZE: u64 = 1024 * 1024;
+const MAX_RESPONSE_SIZE: u64 = 64 * 1024 * 1024;
+/// How often queued events are checked against the ingress limits when
+/// nothing else happens
+const INGRESS_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// `fn` keeps the codec `Send` and `Sync` regardless of the parameters
+type Marker<TPayload, TGenesisPayload, TPeerId> =
+    PhantomData<fn() -> Jobs<TPayload, TGenesisPayload, TPeerId>>;
+
+/// Encodes sync messages in the wire format
+pub struct SyncCodec<TPayload, TGenesisPayload, TPeerId> {
+    _types: Marker<TPayload, TGenesisPayload, TPeerId>,
+}
+
+impl<TPayload, TGenesisPayload, TPeerId> Default for SyncCodec<TPayload, TGenesisPayload, TPeerId> {
+    fn default() -> Self {
+        Self {
+            _types: PhantomData,
+        }
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId> Clone for SyncCodec<TPayload, TGenesisPayload, TPeerId> {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+fn invalid_data(error: WireError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+async fn read_message<M, T>(io: &mut T, limit: u64) -> io::Result<M>
+where
+    M: WireMessage,
+    T: AsyncRead + Unpin + Send,
+{
+    let mut bytes = vec![];
+    io.take(limit).read_to_end(&mut bytes).await?;
+    M::from_wire(&bytes).map_err(invalid_data)
+}
+
+async fn write_message<M, T>(io: &mut T, message: M) -> io::Result<()>
+where
+    M: WireMessage,
+    T: AsyncWrite + Unpin + Send,
+{
+    let bytes = message.to_wire().map_err(invalid_data)?;
+    io.write_all(&bytes).await?;
+    io.close().await
+}
+
+#[async_trait]
+impl<TPayload, TGenesisPayload, TPeerId> request_response::Codec
+    for SyncCodec<TPayload, TGenesisPayload, TPeerId>
+where
+    TPayload: PayloadCodec + Send,
+    TGenesisPayload: Serialize + DeserializeOwned + Send,
+    TPeerId: Serialize + DeserializeOwned + Send,
+{
+    type Protocol = StreamProtocol;
+    type Request = SyncRequest<TPayload, TGenesisPayload, TPeerId>;
+    type Response = Jobs<TPayload, TGenesisPayload, TPeerId>;
+
+    async fn read_request<T>(&mut self, _: &StreamProtocol, io: &mut T) -> io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_message(io, MAX_REQUEST_SIZE).await
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+    ) -> io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_message(io, MAX_RESPONSE_SIZE).await
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+        request: Self::Request,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_message(io, request).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+        response: Self::Response,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_message(io, response).await
+    }
+}
+
+#[derive(NetworkBehaviour)]
+pub struct Behaviour<TPayload, TGenesisPayload, TPeerId>
+where
+    TPayload: PayloadCodec + Send + 'static,
+    TGenesisPayload: Serialize + DeserializeOwned + Send + 'static,
+    TPeerId: Serialize + DeserializeOwned + Send + 'static,
+{
+    pub sync: request_response::Behaviour<SyncCodec<TPayload, TGenesisPayload, TPeerId>>,
+    pub gossipsub: gossipsub::Behaviour,
+}
+
+#[derive(Error, Debug)]
+pub enum NetError {
+    #[error("Failed to set up the transport: {0}")]
+    Setup(String),
+    #[error(transparent)]
+    Listen(#[from] libp2p::TransportError<io::Error>),
+    #[error(transparent)]
+    Dial(#[from] libp2p::swarm::DialError),
+    #[error(transparent)]
+    Subscription(#[from] gossipsub::SubscriptionError),
+    #[error(transparent)]
+    Publish(#[from] gossipsub::PublishError),
+    #[error(transparent)]
+    Wire(#[from] WireError),
+}
+
+/// Notable things that happened while driving the node
+#[derive(Debug)]
+pub enum NetEvent<TPeerId> {
+    Listening(Multiaddr),
+    Connected(PeerId),
+    Disconnected(PeerId),
+    /// Jobs received from the peer were applied
+    Synced {
+        peer: PeerId,
+        applied: usize,
+    },
+    /// Jobs received from the peer were dropped from the full ingress queue
+    Throttled {
+        peer: PeerId,
+    },
+    /// Jobs from the peer contained an event that was rejected. Events
+    /// before it were applied.
+    SyncRejected {
+        peer: PeerId,
+        error: PushError<TPeerId>,
+    },
+    /// Our request was not answered
+    RequestFailed {
+        peer: PeerId,
+        error: request_response::OutboundFailure,
+    },
+    /// The peer sent a malformed or unanswerable message
+    Misbehaved {
+        peer: PeerId,
+        reason: String,
+    },
+}
+
+/// Node taking part in gossip through libp2p. See the [module docs](self).
+pub struct SyncNode<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPayload: PayloadCodec + Send + 'static,
+    TGenesisPayload: Serialize + DeserializeOwned + Send + 'static,
+    TPeerId: Serialize + DeserializeOwned + Send + 'static,
+{
+    swarm: Swarm<Behaviour<TPayload, TGenesisPayload, TPeerId>>,
+    graph: SharedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+    topic: gossipsub::IdentTopic,
+    /// Received jobs with the graph ids of the peers that pushed them
+    ingress: Ingress<TPayload, TGenesisPayload, TPeerId, PeerId, Option<TPeerId>>,
+    started: Instant,
+}
+
+impl<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+    SyncNode<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>
+where
+    TPayload: PayloadCodec + Eq + std::hash::Hash + Debug + Clone + Send + 'static,
+    TGenesisPayload:
+        Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone + Send + 'static,
+    TPeerId: Serialize + DeserializeOwned + Eq + std::hash::Hash + Debug + Clone + Send + 'static,
+    TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+    TClock: Clock,
+{
+    /// Node communicating over TCP with noise encryption and yamux
+    /// multiplexing. Has to be created within a tokio runtime.
+    pub fn new(
+        keypair: identity::Keypair,
+        graph: SharedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+    ) -> Result<Self, NetError> {
+        let swarm = libp2p::SwarmBuilder::with_existing_identity(keypair)
+            .with_tokio()
+            .with_tcp(
+                tcp::Config::default(),
+                noise::Config::new,
+                yamux::Config::default,
+            )
+            .map_err(|e| NetError::Setup(e.to_string()))?
+            .with_behaviour(|key| {
+                Ok(Behaviour {
+                    sync: request_response::Behaviour::new(
+                        [(SYNC_PROTOCOL, ProtocolSupport::Full)],
+                        request_response::Config::default(),
+                    ),
+                    gossipsub: gossipsub::Behaviour::new(
+                        gossipsub::MessageAuthenticity::Signed(key.clone()),
+                        gossipsub::Config::default(),
+                    )?,
+                })
+            })
+            .map_err(|e| NetError::Setup(e.to_string()))?
+            .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+            .build();
+        Self::with_swarm(swarm, graph)
+    }
+
+    /// For custom transports
+    pub fn with_swarm(
+        mut swarm: Swarm<Behaviour<TPayload, TGenesisPayload, TPeerId>>,
+        graph: SharedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+    ) -> Result<Self, NetError> {
+        let topic = gossipsub::IdentTopic::new(TIPS_TOPIC);
+        swarm.behaviour_mut().gossipsub.subscribe(&topic)?;
+        Ok(Self {
+            swarm,
+            graph,
+            topic,
+            ingress: Ingress::new(IngressLimits::default(), 0),
+            started: Instant::now(),
+        })
+    }
+
+    /// Same node with other limits on the received events
+    pub fn with_ingress_limits(mut self, limits: IngressLimits) -> Self {
+        self.ingress = Ingress::new(limits, self.now());
+        self
+    }
+
+    pub fn local_peer_id(&self) -> &PeerId {
+        self.swarm.local_peer_id()
+    }
+
+    pub fn graph(&self) -> &SharedGraph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock> {
+        &self.graph
+    }
+
+    pub fn swarm_mut(&mut self) -> &mut Swarm<Behaviour<TPayload, TGenesisPayload, TPeerId>> {
+        &mut self.swarm
+    }
+
+    pub fn listen_on(&mut self, address: Multiaddr) -> Result<(), NetError> {
+        self.swarm.listen_on(address)?;
+        Ok(())
+    }
+
+    pub fn dial(&mut self, address: Multiaddr) -> Result<(), NetError> {
+        self.swarm.dial(address)?;
+        Ok(())
+    }
+
+    /// Ask `peer` for events we don't know
+    pub fn request_sync(&mut self, peer: &PeerId) -> OutboundRequestId {
+        let request = self.graph.read().sync_request();
+        self.swarm.behaviour_mut().sync.send_request(peer, request)
+    }
+
+    /// Publish our summary, so that peers behind us request a sync
+    pub fn announce_tips(&mut self) -> Result<(), NetError> {
+        let summary = self.graph.read().summary().to_wire()?;
+        self.swarm
+            .behaviour_mut()
+            .gossipsub
+            .publish(self.topic.clone(), summary)?;
+        Ok(())
+    }
+
+    /// Drive the network until something notable happens. Requests of
+    /// peers are answered and announcements are acted upon on the way.
+    pub async fn next_event(&mut self) -> NetEvent<TPeerId> {
+        loop {
+            if let Some(event) = self.apply_ready() {
+                return event;
+            }
+            let event = if self.ingress.queued() > 0 {
+                tokio::select! {
+                    event = self.swarm.select_next_some() => event,
+                    _ = tokio::time::sleep(INGRESS_POLL_INTERVAL) => continue,
+                }
+            } else {
+                self.swarm.select_next_some().await
+            };
+            if let Some(event) = self.handle_swarm_event(event) {
+                return event;
+            }
+        }
+    }
+
+    /// Apply the next received jobs the ingress limits allow
+    fn apply_ready(&mut self) -> Option<NetEvent<TPeerId>> {
+        let (peer, jobs, relay) = self.ingress.next_ready_tagged(self.now())?;
+        let mut graph = self.graph.write();
+        let result = match &relay {
+            Some(relay) => graph.apply_sync_jobs_from(relay, jobs),
+            None => graph.apply_sync_jobs(jobs),
+        };
+        Some(match result {
+            Ok(applied) => NetEvent::Synced { peer, applied },
+            Err(error) => NetEvent::SyncRejected { peer, error },
+        })
+    }
+
+    /// Queue received jobs, `Some` if they were dropped
+    fn offer(
+        &mut self,
+        peer: PeerId,
+        jobs: Jobs<TPayload, TGenesisPayload, TPeerId>,
+        relay: Option<TPeerId>,
+    ) -> Option<NetEvent<TPeerId>> {
+        match self.ingress.offer_tagged(peer, jobs, relay, self.now()) {
+            Admission::Dropped => Some(NetEvent::Throttled { peer }),
+            Admission::Queued | Admission::QueuedEvicting { .. } => None,
+        }
+    }
+
+    fn now(&self) -> Timestamp {
+        self.started.elapsed().as_nanos()
+    }
+
+    fn handle_swarm_event(
+        &mut self,
+        event: SwarmEvent<BehaviourEvent<TPayload, TGenesisPayload, TPeerId>>,
+    ) -> Option<NetEvent<TPeerId>> {
+        match event {
+            SwarmEvent::NewListenAddr { address, .. } => Some(NetEvent::Listening(address)),
+            SwarmEvent::ConnectionEstablished {
+                peer_id,
+                num_established,
+                ..
+            } if num_established.get() == 1 => Some(NetEvent::Connected(peer_id)),
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                num_established: 0,
+                ..
+            } => Some(NetEvent::Disconnected(peer_id)),
+            SwarmEvent::Behaviour(BehaviourEvent::Sync(event)) => self.handle_sync_event(event),
+            SwarmEvent::Behaviour(BehaviourEvent::Gossipsub(event)) => {
+                self.handle_gossip_event(event)
+            }
+            _ => None,
+        }
+    }
+
+    fn handle_sync_event(
+        &mut self,
+        event: request_response::Event<
+            SyncRequest<TPayload, TGenesisPayload, TPeerId>,
+            Jobs<TPayload, TGenesisPayload, TPeerId>,
+        >,
+    ) -> Option<NetEvent<TPeerId>> {
+        match event {
+            request_response::Event::Message {
+                peer,
+                message:
+                    request_response::Message::Request {
+                        mut request,
+                        channel,
+                        ..
+                    },
+            } => {
+                let throttled = match request.events.take() {
+                    Some(pushed) => self.offer(peer, pushed, Some(request.from.clone())),
+                    None => None,
+                };
+                let jobs = match self.graph.read().generate_sync_for_request(&request) {
+                    Ok(jobs) => jobs,
+                    Err(e) => {
+                        return Some(NetEvent::Misbehaved {
+                            peer,
+                            reason: e.to_string(),
+                        })
+                    }
+                };
+                debug!(%peer, jobs = jobs.as_linear().len(), "Answering sync request");
+                if self
+                    .swarm
+                    .behaviour_mut()
+                    .sync
+                    .send_response(channel, jobs)
+                    .is_err()
+                {
+                    warn!(%peer, "Connection closed before the response was sent");
+                }
+                throttled
+            }
+            request_response::Event::Message {
+                peer,
+                message: request_response::Message::Response { response, .. },
+            } => self.offer(peer, response, None),
+            request_response::Event::OutboundFailure { peer, error, .. } => {
+                Some(NetEvent::RequestFailed { peer, error })
+            }
+            request_response::Event::InboundFailure { peer, error, .. } => {
+                Some(NetEvent::Misbehaved {
+                    peer,
+                    reason: error.to_string(),
+                })
+            }
+            request_response::Event::ResponseSent { .. } => None,
+        }
+    }
+
+    fn handle_gossip_event(&mut self, event: gossipsub::Event) -> Option<NetEvent<TPeerId>> {
+        let gossipsub::Event::Message {
+            propagation_source: peer,
+            message,
+            ..
+        } = event
+        else {
+            return None;
+        };
+        let summary = match Summary::<TPeerId>::from_wire(&message.data) {
+            Ok(summary) => summary,
+            Err(e) => {
+                return Some(NetEvent::Misbehaved {
+                    peer,
+                    reason: e.to_string(),
+                })
+            }
+        };
+        let behind = {
+            let graph = self.graph.read();
+            summary.tips().any(|tip| graph.event(tip).is_none())
+        };
+        if behind {
+            debug!(%peer, "Announced tips are unknown, requesting sync");
+            self.request_sync(&peer);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::{sleep, timeout};
+
+    use super::*;
+    use crate::algorithm::datastructure::Graph;
+    use crate::algorithm::{IncrementalClock, MockSigner};
+
+    type TestNode = SyncNode<u64, (), u64, MockSigner<u64, ()>, IncrementalClock>;
+
+    fn node(id: u64) -> TestNode {
+        let graph = Graph::new(id, 0, (), 999, MockSigner::new(), IncrementalClock::new());
+        SyncNode::new(identity::Keypair::generate_ed25519(), graph.into()).unwrap()
+    }
+
+    fn add_events(node: &TestNode, count: u64) {
+        let mut graph = node.graph().write();
+        for i in 0..count {
+            let tip = graph.self_tip().unwrap().clone();
+            graph.create_event(i, tip).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn nodes_sync_over_tcp() {
+        let mut a = node(0);
+        let mut b = node(1);
+        add_events(&a, 3);
+        a.listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+        let address = loop {
+            if let NetEvent::Listening(address) = a.next_event().await {
+                break address;
+            }
+        };
+        b.dial(address).unwrap();
+        let a_id = *a.local_peer_id();
+
+        let result = timeout(Duration::from_secs(30), async {
+            // Explicit request
+            let mut requested = false;
+            loop {
+                tokio::select! {
+                    _ = a.next_event() => (),
+                    event = b.next_event() => match event {
+                        NetEvent::Connected(peer) if peer == a_id && !requested => {
+                            b.request_sync(&a_id);
+                            requested = true;
+                        }
+                        NetEvent::Synced { applied, .. } => {
+                            assert_eq!(applied, 4);
+                            break;
+                        }
+                        NetEvent::Connected(_) | NetEvent::Listening(_) => (),
+                        other => panic!("Unexpected event {:?}", other),
+                    },
+                }
+            }
+            // Announcement of new tips
+            add_events(&a, 1);
+            loop {
+                tokio::select! {
+                    _ = a.next_event() => (),
+                    _ = sleep(Duration::from_millis(100)) => {
+                        // Fails until `a` learns about the subscription of `b`
+                        let _ = a.announce_tips();
+                    }
+                    event = b.next_event() => match event {
+                        NetEvent::Synced { applied, .. } => {
+                            assert_eq!(applied, 1);
+                            break;
+                        }
+                        other => panic!("Unexpected event {:?}", other),
+                    },
+                }
+            }
+        })
+        .await;
+        assert!(result.is_ok(), "Nodes didn't sync in time");
+        let a_events = a.graph().read().summary();
+        let b_graph = b.graph().read();
+        assert!(a_events.tips().all(|tip| b_graph.event(tip).is_some()));
+    }
+}
@@ -0,0 +1,667 @@
+//! Gossip protocol independent of the transport. Whatever carries the bytes
+//! (TCP, QUIC, in-process channels) only has to deliver [`Message`]s between
+//! the two [`Protocol`] instances of a connection, in order.
+//!
+//! A session goes like this (after a [handshake](super::handshake)):
+//! 1. both sides send [`Message::Hello`] with their summary;
+//! 2. a side that sees unknown tips in the peer's summary sends
+//!    [`Message::SyncRequest`] (also can be sent at any time later);
+//! 3. the answer is a sequence of [`Message::SyncResponse`] chunks. Each
+//!    chunk is acknowledged with [`Message::Ack`] and the next chunk is only
+//!    sent after that, which bounds the amount of data in flight;
+//! 4. forks found in received events are reported with
+//!    [`ProtocolEvent::ForkDetected`], the application may relay them to
+//!    other peers with [`Message::ForkAlert`].
+//!
+//! Nodes that can't accept connections (e.g. behind NAT) should use
+//! [`Protocol::pull_only`]: they always connect themselves, so their requests
+//! carry the events the peer is missing according to its hello, and the
+//! peer applies them before answering.
+//!
+//! Messages are encoded with the [wire format](crate::algorithm::datastructure::sync::wire),
+//! so equal messages always have equal bytes.
+
+use std::collections::VecDeque;
+use std::fmt::Debug;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::algorithm::codec::PayloadCodec;
+use crate::algorithm::datastructure::sync::wire::{self, MessageKind, WireMessage};
+use crate::algorithm::datastructure::sync::{self, Jobs, Summary, SyncRequest};
+use crate::algorithm::datastructure::Graph;
+use crate::algorithm::{event, Clock, PushError, Signer};
+
+/// Default limit on the number of events in a single response chunk
+pub const DEFAULT_CHUNK_SIZE: usize = 256;
+
+/// Proof that `author` created several events with the same self parent.
+/// Can be checked by anyone who has the events.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ForkEvidence<TPeerId> {
+    pub author: TPeerId,
+    pub self_parent: event::Hash,
+    pub events: Vec<event::Hash>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(bound(
+    serialize = "TPayload: PayloadCodec, TGenesisPayload: Serialize, TPeerId: Serialize",
+    deserialize = "TPayload: PayloadCodec, TGenesisPayload: Deserialize<'de>, TPeerId: Deserialize<'de>"
+))]
+pub enum Message<TPayload, TGenesisPayload, TPeerId> {
+    Hello {
+        summary: Summary<TPeerId>,
+    },
+    SyncRequest(SyncRequest<TPayload, TGenesisPayload, TPeerId>),
+    SyncResponse {
+        chunk: Jobs<TPayload, TGenesisPayload, TPeerId>,
+        /// No more chunks follow for this request
+        last: bool,
+    },
+    /// Chunk received, `applied` events were new
+    Ack {
+        applied: usize,
+    },
+    ForkAlert(ForkEvidence<TPeerId>),
+}
+
+impl<TPayload, TGenesisPayload, TPeerId> Message<TPayload, TGenesisPayload, TPeerId> {
+    fn name(&self) -> &'static str {
+        match self {
+            Message::Hello { .. } => "Hello",
+            Message::SyncRequest(_) => "SyncRequest",
+            Message::SyncResponse { .. } => "SyncResponse",
+            Message::Ack { .. } => "Ack",
+            Message::ForkAlert(_) => "ForkAlert",
+        }
+    }
+}
+
+/// [`Message`] in the wire format version 1
+#[derive(Deserialize)]
+#[serde(bound(
+    deserialize = "TPayload: PayloadCodec, TGenesisPayload: Deserialize<'de>, TPeerId: Deserialize<'de>"
+))]
+enum MessageV1<TPayload, TGenesisPayload, TPeerId> {
+    Hello {
+        summary: Summary<TPeerId>,
+    },
+    SyncRequest(wire::v1::SyncRequest<TPeerId>),
+    SyncResponse {
+        chunk: Jobs<TPayload, TGenesisPayload, TPeerId>,
+        last: bool,
+    },
+    Ack {
+        applied: usize,
+    },
+    ForkAlert(ForkEvidence<TPeerId>),
+}
+
+/// Encoding of [`MessageV1`], without copying the message
+#[derive(Serialize)]
+#[serde(bound(
+    serialize = "TPayload: PayloadCodec, TGenesisPayload: Serialize, TPeerId: Serialize"
+))]
+enum MessageV1Ref<'a, TPayload, TGenesisPayload, TPeerId> {
+    Hello {
+        summary: &'a Summary<TPeerId>,
+    },
+    SyncRequest(wire::v1::SyncRequestRef<'a, TPeerId>),
+    SyncResponse {
+        chunk: &'a Jobs<TPayload, TGenesisPayload, TPeerId>,
+        last: bool,
+    },
+    Ack {
+        applied: usize,
+    },
+    ForkAlert(&'a ForkEvidence<TPeerId>),
+}
+
+impl<'a, TPayload, TGenesisPayload, TPeerId> From<&'a Message<TPayload, TGenesisPayload, TPeerId>>
+    for MessageV1Ref<'a, TPayload, TGenesisPayload, TPeerId>
+{
+    fn from(value: &'a Message<TPayload, TGenesisPayload, TPeerId>) -> Self {
+        match value {
+            Message::Hello { summary } => MessageV1Ref::Hello { summary },
+            Message::SyncRequest(request) => MessageV1Ref::SyncRequest(request.into()),
+            Message::SyncResponse { chunk, last } => {
+                MessageV1Ref::SyncResponse { chunk, last: *last }
+            }
+            Message::Ack { applied } => MessageV1Ref::Ack { applied: *applied },
+            Message::ForkAlert(evidence) => MessageV1Ref::ForkAlert(evidence),
+        }
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId> From<MessageV1<TPayload, TGenesisPayload, TPeerId>>
+    for Message<TPayload, TGenesisPayload, TPeerId>
+{
+    fn from(value: MessageV1<TPayload, TGenesisPayload, TPeerId>) -> Self {
+        match value {
+            MessageV1::Hello { summary } => Message::Hello { summary },
+            MessageV1::SyncRequest(request) => Message::SyncRequest(request.into()),
+            MessageV1::SyncResponse { chunk, last } => Message::SyncResponse { chunk, last },
+            MessageV1::Ack { applied } => Message::Ack { applied },
+            MessageV1::ForkAlert(evidence) => Message::ForkAlert(evidence),
+        }
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId> WireMessage for Message<TPayload, TGenesisPayload, TPeerId>
+where
+    TPayload: PayloadCodec,
+    TGenesisPayload: Serialize + DeserializeOwned,
+    TPeerId: Serialize + DeserializeOwned,
+{
+    const KIND: MessageKind = MessageKind::Protocol;
+
+    fn encode_body(&self, version: u16) -> bincode::Result<Vec<u8>> {
+        match version {
+            1 => bincode::serialize(&MessageV1Ref::from(self)),
+            2 => bincode::serialize(self),
+            _ => unreachable!("version is checked before encoding"),
+        }
+    }
+
+    fn decode_body(version: u16, body: &[u8]) -> bincode::Result<Self> {
+        match version {
+            1 => bincode::deserialize::<MessageV1<_, _, _>>(body).map(Into::into),
+            2 => bincode::deserialize(body),
+            _ => unreachable!("version is checked before decoding"),
+        }
+    }
+}
+
+/// Things the application may want to react to
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ProtocolEvent<TPeerId> {
+    /// A chunk of events from the peer was applied
+    Synced {
+        applied: usize,
+        /// Response to our request is fully received
+        complete: bool,
+    },
+    /// Events pushed by the peer with its request were applied. `from` is
+    /// the sender according to the request.
+    Pushed { from: TPeerId, applied: usize },
+    /// Received events contain a fork
+    ForkDetected(ForkEvidence<TPeerId>),
+    /// The peer alerted about a fork. `confirmed` if we know the events and
+    /// they are indeed a fork.
+    ForkReported {
+        evidence: ForkEvidence<TPeerId>,
+        confirmed: bool,
+    },
+}
+
+/// Result of handling a message
+#[derive(Debug)]
+pub struct Output<TPayload, TGenesisPayload, TPeerId> {
+    /// To be sent to the peer, in order
+    pub replies: Vec<Message<TPayload, TGenesisPayload, TPeerId>>,
+    pub events: Vec<ProtocolEvent<TPeerId>>,
+}
+
+impl<TPayload, TGenesisPayload, TPeerId> Default for Output<TPayload, TGenesisPayload, TPeerId> {
+    fn default() -> Self {
+        Self {
+            replies: vec![],
+            events: vec![],
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ProtocolError<TPeerId> {
+    #[error("Unexpected {message} message: {reason}")]
+    Unexpected {
+        message: &'static str,
+        reason: &'static str,
+    },
+    #[error("Could not answer sync request: {0}")]
+    Sync(#[from] sync::Error),
+    #[error("Received event was rejected: {0}")]
+    Push(#[from] PushError<TPeerId>),
+}
+
+/// State of one side of a connection. See the [module docs](self).
+pub struct Protocol<TPayload, TGenesisPayload, TPeerId> {
+    chunk_size: usize,
+    /// Push our events with requests
+    pull_only: bool,
+    /// We requested a sync and wait for (more) response chunks
+    awaiting_response: bool,
+    /// We sent a chunk and wait for the peer to acknowledge it
+    awaiting_ack: bool,
+    /// Chunks to send after the acknowledgement
+    outgoing: VecDeque<Jobs<TPayload, TGenesisPayload, TPeerId>>,
+    /// Id of the peer, once it sent a request. Recorded as the relay of the
+    /// events it sends.
+    remote: Option<TPeerId>,
+}
+
+impl<TPayload, TGenesisPayload, TPeerId> Default for Protocol<TPayload, TGenesisPayload, TPeerId> {
+    fn default() -> Self {
+        Self::new(DEFAULT_CHUNK_SIZE)
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId> Protocol<TPayload, TGenesisPayload, TPeerId> {
+    pub fn new(chunk_size: usize) -> Self {
+        Self {
+            chunk_size: chunk_size.max(1),
+            pull_only: false,
+            awaiting_response: false,
+            awaiting_ack: false,
+            outgoing: VecDeque::new(),
+            remote: None,
+        }
+    }
+
+    /// For nodes that never accept connections, see the [module docs](self)
+    pub fn pull_only(chunk_size: usize) -> Self {
+        Self {
+            pull_only: true,
+            ..Self::new(chunk_size)
+        }
+    }
+
+    /// No exchange is in progress in either direction
+    pub fn is_idle(&self) -> bool {
+        !self.awaiting_response && !self.awaiting_ack
+    }
+}
+
+impl<TPayload, TGenesisPayload, TPeerId> Protocol<TPayload, TGenesisPayload, TPeerId>
+where
+    TPayload: PayloadCodec + Eq + std::hash::Hash + Debug + Clone,
+    TGenesisPayload: Serialize + Eq + std::hash::Hash + Debug + Clone,
+    TPeerId: Serialize + Eq + std::hash::Hash + Debug + Clone,
+{
+    /// First message to send after connecting
+    pub fn hello<TSigner, TClock>(
+        &self,
+        graph: &Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+    ) -> Message<TPayload, TGenesisPayload, TPeerId> {
+        Message::Hello {
+            summary: graph.summary(),
+        }
+    }
+
+    /// `None` if a request is already in progress
+    pub fn request_sync<TSigner, TClock>(
+        &mut self,
+        graph: &Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+    ) -> Option<Message<TPayload, TGenesisPayload, TPeerId>> {
+        if self.awaiting_response {
+            return None;
+        }
+        self.awaiting_response = true;
+        Some(Message::SyncRequest(graph.sync_request()))
+    }
+
+    pub fn handle<TSigner, TClock>(
+        &mut self,
+        graph: &mut Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+        message: Message<TPayload, TGenesisPayload, TPeerId>,
+    ) -> Result<Output<TPayload, TGenesisPayload, TPeerId>, ProtocolError<TPeerId>>
+    where
+        TSigner: Signer<TGenesisPayload, SignerIdentity = TPeerId>,
+        TClock: Clock,
+    {
+        let mut output = Output::default();
+        let name = message.name();
+        match message {
+            Message::Hello { summary } => {
+                let behind = summary.tips().any(|tip| graph.event(tip).is_none());
+                if self.pull_only && !self.awaiting_response {
+                    let request = graph.sync_request_pushing(&summary)?;
+                    if behind || request.events.is_some() {
+                        debug!("Requesting sync, pushing our events");
+                        self.awaiting_response = true;
+                        output.replies.push(Message::SyncRequest(request));
+                    }
+                } else if behind {
+                    debug!("Peer knows events we don't, requesting sync");
+                    output.replies.extend(self.request_sync(graph));
+                }
+            }
+            Message::SyncRequest(mut request) => {
+                if self.awaiting_ack {
+                    return Err(ProtocolError::Unexpected {
+                        message: name,
+                        reason: "previous response is still being sent",
+                    });
+                }
+                self.remote = Some(request.from.clone());
+                if let Some(pushed) = &request.events {
+                    let hashes: Vec<_> = pushed
+                        .as_linear()
+                        .iter()
+                        .map(|e| e.hash().clone())
+                        .collect();
+                    let applied = graph.apply_sync_request(&mut request)?;
+                    output.events.push(ProtocolEvent::Pushed {
+                        from: request.from.clone(),
+                        applied,
+                    });
+                    output
+                        .events
+                        .extend(find_forks(graph, &hashes).map(ProtocolEvent::ForkDetected));
+                }
+                let jobs = graph.generate_sync_for_request(&request)?;
+                self.outgoing = jobs.into_chunks(self.chunk_size).into();
+                output.replies.push(self.next_chunk());
+            }
+            Message::SyncResponse { chunk, last } => {
+                if !self.awaiting_response {
+                    return Err(ProtocolError::Unexpected {
+                        message: name,
+                        reason: "no sync was requested",
+                    });
+                }
+                let hashes: Vec<_> = chunk.as_linear().iter().map(|e| e.hash().clone()).collect();
+                let applied = match &self.remote {
+                    Some(remote) => graph.apply_sync_jobs_from(remote, chunk)?,
+                    None => graph.apply_sync_jobs(chunk)?,
+                };
+                if last {
+                    self.awaiting_response = false;
+                }
+                output.replies.push(Message::Ack { applied });
+                output.events.push(ProtocolEvent::Synced {
+                    applied,
+                    complete: last,
+                });
+                output
+                    .events
+                    .extend(find_forks(graph, &hashes).map(ProtocolEvent::ForkDetected));
+            }
+            Message::Ack { .. } => {
+                if !self.awaiting_ack {
+                    return Err(ProtocolError::Unexpected {
+                        message: name,
+                        reason: "nothing was sent",
+                    });
+                }
+                self.awaiting_ack = false;
+                if !self.outgoing.is_empty() {
+                    output.replies.push(self.next_chunk());
+                }
+            }
+            Message::ForkAlert(evidence) => {
+                let confirmed = evidence.events.len() > 1
+                    && evidence.events.iter().all(|e| {
+                        graph
+                            .event(e)
+                            .is_some_and(|e| e.author() == &evidence.author)
+                            && graph.fork_siblings(e).is_some_and(|siblings| {
+                                evidence.events.iter().all(|s| siblings.contains(s))
+                            })
+                    });
+                if !confirmed {
+                    warn!("Could not confirm reported fork");
+                }
+                output.events.push(ProtocolEvent::ForkReported {
+                    evidence,
+                    confirmed,
+                });
+            }
+        }
+        Ok(output)
+    }
+
+    /// Also used for empty responses, so the requester always gets an answer
+    fn next_chunk(&mut self) -> Message<TPayload, TGenesisPayload, TPeerId> {
+        let chunk = self
+            .outgoing
+            .pop_front()
+            .unwrap_or_else(|| Jobs::from_linear(vec![]));
+        self.awaiting_ack = true;
+        Message::SyncResponse {
+            chunk,
+            last: self.outgoing.is_empty(),
+        }
+    }
+}
+
+/// Evidence of forks among `received` events, one per forked self parent
+pub(crate) fn find_forks<'a, TPayload, TGenesisPayload, TPeerId, TSigner, TClock>(
+    graph: &'a Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+    received: &'a [event::Hash],
+) -> impl Iterator<Item = ForkEvidence<TPeerId>> + 'a
+where
+    TPeerId: Eq + std::hash::Hash + Clone,
+{
+    let mut reported = std::collections::HashSet::new();
+    received.iter().filter_map(move |hash| {
+        let mut events = graph.fork_siblings(hash)?;
+        let event = graph.event(hash).expect("applied events are known");
+        let event::Kind::Regular(parents) = event.kind() else {
+            unreachable!("geneses have no siblings")
+        };
+        if !reported.insert(parents.self_parent.clone()) {
+            return None;
+        }
+        events.sort();
+        Some(ForkEvidence {
+            author: event.author().clone(),
+            self_parent: parents.self_parent.clone(),
+            events,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::event::{Parents, SignedEvent};
+    use crate::algorithm::MockSigner;
+    use crate::testing::{GraphBuilder, TestGraph};
+
+    type TestMessage = Message<u64, (), u64>;
+
+    /// Graphs of peers 0 and 1 with only their geneses
+    fn pair() -> [TestGraph<u64, u64>; 2] {
+        [("a", 0u64), ("b", 1)].map(|(name, id)| {
+            GraphBuilder::new(name, id, 0u64, 999)
+                .build()
+                .unwrap()
+                .graph
+        })
+    }
+
+    /// Run the session until both sides are silent, passing messages through
+    /// the wire encoding. Returns events of each side.
+    fn run(
+        graphs: &mut [TestGraph<u64, u64>; 2],
+        protocols: &mut [Protocol<u64, (), u64>; 2],
+        initial: Vec<(usize, TestMessage)>,
+    ) -> [Vec<ProtocolEvent<u64>>; 2] {
+        let mut in_flight = VecDeque::from(initial);
+        let mut events = [vec![], vec![]];
+        while let Some((to, message)) = in_flight.pop_front() {
+            let bytes = message.to_wire().unwrap();
+            let message = TestMessage::from_wire(&bytes).unwrap();
+            let output = protocols[to].handle(&mut graphs[to], message).unwrap();
+            in_flight.extend(output.replies.into_iter().map(|m| (1 - to, m)));
+            events[to].extend(output.events);
+        }
+        events
+    }
+
+    #[test]
+    fn session_syncs_in_chunks() {
+        let mut graphs = pair();
+        for i in 0..10 {
+            let tip = graphs[0].self_tip().unwrap().clone();
+            graphs[0].create_event(i, tip).unwrap();
+        }
+        let mut protocols = [Protocol::new(3), Protocol::new(3)];
+        let hellos = vec![
+            (1, protocols[0].hello(&graphs[0])),
+            (0, protocols[1].hello(&graphs[1])),
+        ];
+        let [events_0, events_1] = run(&mut graphs, &mut protocols, hellos);
+
+        // Only genesis of 1 is unknown to 0
+        assert_eq!(
+            events_0,
+            vec![ProtocolEvent::Synced {
+                applied: 1,
+                complete: true
+            }]
+        );
+        // 11 events in chunks of 3
+        let applied: Vec<_> = events_1
+            .iter()
+            .map(|e| match e {
+                ProtocolEvent::Synced { applied, .. } => *applied,
+                other => panic!("Unexpected event {:?}", other),
+            })
+            .collect();
+        assert_eq!(applied, vec![3, 3, 3, 2]);
+        assert!(protocols.iter().all(|p| p.is_idle()));
+        assert_eq!(graphs[0].summary(), graphs[1].summary());
+
+        // Nothing new, but the request is still answered
+        let request = protocols[1].request_sync(&graphs[1]).unwrap();
+        assert!(protocols[1].request_sync(&graphs[1]).is_none());
+        let [_, events_1] = run(&mut graphs, &mut protocols, vec![(0, request)]);
+        assert_eq!(
+            events_1,
+            vec![ProtocolEvent::Synced {
+                applied: 0,
+                complete: true
+            }]
+        );
+    }
+
+    #[test]
+    fn pull_only_pushes_events() {
+        let mut graphs = pair();
+        for i in 0..5 {
+            let tip = graphs[0].self_tip().unwrap().clone();
+            graphs[0].create_event(i, tip).unwrap();
+        }
+        let mut protocols = [Protocol::pull_only(4), Protocol::new(4)];
+        // 1 accepted the connection of 0 and greets it
+        let hello = protocols[1].hello(&graphs[1]);
+        let [events_0, events_1] = run(&mut graphs, &mut protocols, vec![(0, hello)]);
+        assert_eq!(
+            events_1,
+            vec![ProtocolEvent::Pushed {
+                from: 0,
+                applied: 6
+            }]
+        );
+        assert_eq!(
+            events_0,
+            vec![ProtocolEvent::Synced {
+                applied: 1,
+                complete: true
+            }]
+        );
+        assert_eq!(graphs[0].summary(), graphs[1].summary());
+
+        // Nothing to exchange
+        let hello = protocols[1].hello(&graphs[1]);
+        let [events_0, _] = run(&mut graphs, &mut protocols, vec![(0, hello)]);
+        assert!(events_0.is_empty());
+        assert!(protocols.iter().all(|p| p.is_idle()));
+    }
+
+    #[test]
+    fn unexpected_messages_rejected() {
+        let mut g = GraphBuilder::new("a", 0u64, 0u64, 999)
+            .build()
+            .unwrap()
+            .graph;
+        let mut protocol = Protocol::default();
+        assert!(matches!(
+            protocol.handle(&mut g, Message::Ack { applied: 0 }),
+            Err(ProtocolError::Unexpected { message: "Ack", .. })
+        ));
+        let chunk = Jobs::from_linear(vec![]);
+        assert!(matches!(
+            protocol.handle(&mut g, Message::SyncResponse { chunk, last: true }),
+            Err(ProtocolError::Unexpected {
+                message: "SyncResponse",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn forks_detected_and_confirmed() {
+        let mut graphs = pair();
+        // Peer 2 forks in the graph of 0
+        let signer = MockSigner::<u64, ()>::new();
+        let genesis =
+            SignedEvent::new(0, event::Kind::Genesis(()), 2, 0, |h| signer.sign(h)).unwrap();
+        let genesis_hash = genesis.hash().clone();
+        let (unsigned, signature) = genesis.into_parts();
+        graphs[0].push_event(unsigned, signature).unwrap();
+        let own_genesis = graphs[0].self_tip().unwrap().clone();
+        for payload in [1, 2] {
+            let fork = SignedEvent::new(
+                payload,
+                event::Kind::Regular(Parents {
+                    self_parent: genesis_hash.clone(),
+                    other_parent: own_genesis.clone(),
+                }),
+                2,
+                1,
+                |h| signer.sign(h),
+            )
+            .unwrap();
+            let (unsigned, signature) = fork.into_parts();
+            graphs[0].push_event(unsigned, signature).unwrap();
+        }
+
+        let mut protocols = [Protocol::default(), Protocol::default()];
+        let request = protocols[1].request_sync(&graphs[1]).unwrap();
+        let [_, events_1] = run(&mut graphs, &mut protocols, vec![(0, request)]);
+        let evidence = events_1
+            .iter()
+            .find_map(|e| match e {
+                ProtocolEvent::ForkDetected(evidence) => Some(evidence.clone()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(evidence.author, 2);
+        assert_eq!(evidence.self_parent, genesis_hash);
+        assert_eq!(evidence.events.len(), 2);
+
+        // Relayed alert is confirmed by those who have the events
+        let [events_0, _] = run(
+            &mut graphs,
+            &mut protocols,
+            vec![(0, Message::ForkAlert(evidence.clone()))],
+        );
+        assert_eq!(
+            events_0,
+            vec![ProtocolEvent::ForkReported {
+                evidence: evidence.clone(),
+                confirmed: true
+            }]
+        );
+        let mut forged = evidence;
+        forged.events[1] = own_genesis;
+        let [events_0, _] = run(
+            &mut graphs,
+            &mut protocols,
+            vec![(0, Message::ForkAlert(forged.clone()))],
+        );
+        assert_eq!(
+            events_0,
+            vec![ProtocolEvent::ForkReported {
+                evidence: forged,
+                confirmed: false
+            }]
+        );
+    }
+}
@@ -0,0 +1,978 @@
+//! In-process network of graphs for testing consensus end to end (`sim`
+//! feature).
+//!
+//! [`Simulation`] runs several [`Graph`]s gossiping through a simulated
+//! network with random latency, message loss and partitions. Time is
+//! virtual (driven through a shared [`ManualClock`]) and all random choices
+//! come from a single seeded generator, so a run is fully reproducible from
+//! [`SimConfig::seed`], which is printed if a test panics.
+//!
+//! Gossip follows the usual scheme: every [`SimConfig::gossip_interval`]
+//! each node sends a sync request to a random peer; once the answer arrives,
+//! the node applies it and authors an event with the peer's latest event as
+//! the other parent. Each authored event carries a unique `u64`
+//! transaction.
+//!
+//! Geneses are exchanged before the start, so all nodes are members from the
+//! beginning.
+//!
+//! [`SimConfig::faults`] makes the network duplicate, hold back (so that
+//! later messages overtake) and truncate messages. While any of them are
+//! enabled, each node checks its indices after every applied response (see
+//! [`Graph::check_consistency`]) and the run fails with
+//! [`SimError::Inconsistent`] on the first problem.
+//!
+//! Some nodes may be made adversarial with [`SimConfig::adversaries`] (see
+//! [`Behavior`]). Checks of agreement and liveness then only consider honest
+//! nodes, and [`Simulation::check_fork_evidence`] verifies that honest nodes
+//! detect exactly the forks that were injected.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::algorithm::core::CoinStrategy;
+use crate::algorithm::datastructure::adaptive_coin::AdaptiveCoin;
+use crate::algorithm::datastructure::sync::{Jobs, SyncRequest};
+use crate::algorithm::datastructure::Graph;
+use crate::algorithm::event::{Parents, Signature, SignedEvent};
+use crate::algorithm::{event, ManualClock, MockSigner, Signer};
+use crate::net::protocol::{find_forks, ForkEvidence};
+use crate::testing::fixture::Fixture;
+use crate::testing::seed_from_env;
+use crate::testing::shrink::{replicas_disagree, shrink};
+use crate::Timestamp;
+
+pub type SimGraph = Graph<u64, (), usize, MockSigner<usize, ()>, ManualClock>;
+
+const MILLISECOND: Timestamp = 1_000_000;
+
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub nodes: usize,
+    /// Seed of all random choices: latencies, losses, gossip partners,
+    /// adversaries and coin flips (see [`Graph::set_coin_seed`]). By
+    /// default taken from [`seed_from_env`].
+    pub seed: u64,
+    /// Delay of each message is uniform in `min_latency..=max_latency`
+    /// (nanoseconds)
+    pub min_latency: Timestamp,
+    pub max_latency: Timestamp,
+    /// Probability of losing each message
+    pub loss: f64,
+    pub gossip_interval: Timestamp,
+    pub coin_frequency: usize,
+    /// See [`Graph::set_coin_strategy`]
+    pub coin_strategy: CoinStrategy,
+    /// See [`Graph::set_adaptive_coin`]
+    pub adaptive_coin: Option<AdaptiveCoin>,
+    /// Nodes deviating from the protocol, others are honest
+    pub adversaries: Vec<(usize, Behavior)>,
+    pub faults: Faults,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            nodes: 4,
+            seed: seed_from_env(0),
+            min_latency: MILLISECOND,
+            max_latency: 10 * MILLISECOND,
+            loss: 0.0,
+            gossip_interval: 10 * MILLISECOND,
+            coin_frequency: 10,
+            coin_strategy: CoinStrategy::default(),
+            adaptive_coin: None,
+            adversaries: vec![],
+            faults: Faults::default(),
+        }
+    }
+}
+
+/// Network faults besides losses. Probabilities are per message.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Faults {
+    /// Probability of delivering a message twice, with independent latencies
+    pub duplicate: f64,
+    /// Probability of holding a message back for up to `max_hold` on top of
+    /// its latency
+    pub hold: f64,
+    pub max_hold: Timestamp,
+    /// Probability of delivering only a random prefix of a sync response,
+    /// as if the connection broke midway
+    pub truncate: f64,
+}
+
+impl Faults {
+    fn any(&self) -> bool {
+        self.duplicate > 0.0 || self.hold > 0.0 || self.truncate > 0.0
+    }
+}
+
+/// How a node takes part in gossip
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Behavior {
+    #[default]
+    Honest,
+    /// With the given probability authors a second event on the same self
+    /// parent along with each regular one
+    Forker { probability: f64 },
+    /// Appends an event impersonating another member to each sync response.
+    /// The signature is garbage, so the whole rest of the response is
+    /// rejected.
+    Forger,
+    /// Never answers sync requests of `victims`
+    Withholder { victims: Vec<usize> },
+    /// Timestamps of its events are off by a random amount of up to
+    /// `max_shift` in either direction
+    TimestampLiar { max_shift: Timestamp },
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SimError {
+    /// See [`Simulation::shrink_divergence`] for a minimal scenario
+    #[error("Nodes {a} and {b} finalized different events at position {position}")]
+    Diverged { a: usize, b: usize, position: usize },
+    #[error("Consensus stalled, finalized events per node: {finalized:?}")]
+    Stalled { finalized: Vec<usize> },
+    #[error("Node {node} reports a fork of {author} that wasn't injected")]
+    FalseEvidence { node: usize, author: usize },
+    #[error("Node {node} knows events of a fork of {author} but doesn't report it")]
+    MissedFork { node: usize, author: usize },
+    #[error("Indices of node {node} are inconsistent: {reason}")]
+    Inconsistent { node: usize, reason: String },
+}
+
+/// When an event was authored and when a node finalized it, in virtual
+/// time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinalityTime {
+    pub event: event::Hash,
+    pub created: Timestamp,
+    pub finalized: Timestamp,
+}
+
+impl FinalityTime {
+    pub fn latency(&self) -> Timestamp {
+        self.finalized - self.created
+    }
+}
+
+/// Message counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SimStats {
+    pub sent: usize,
+    /// Lost randomly or dropped by a partition
+    pub lost: usize,
+    pub delivered: usize,
+    /// Responses that failed to apply (at least partially)
+    pub rejected: usize,
+    /// Extra copies sent, see [`Faults::duplicate`]
+    pub duplicated: usize,
+    /// See [`Faults::truncate`]
+    pub truncated: usize,
+}
+
+#[derive(Clone)]
+enum Packet {
+    Request(SyncRequest<u64, (), usize>),
+    Response(Jobs<u64, (), usize>),
+}
+
+struct InFlight {
+    at: Timestamp,
+    /// Order of sending, breaks ties of `at` deterministically
+    seq: u64,
+    from: usize,
+    to: usize,
+    packet: Packet,
+}
+
+impl PartialEq for InFlight {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for InFlight {}
+
+impl PartialOrd for InFlight {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for InFlight {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.at, self.seq).cmp(&(other.at, other.seq))
+    }
+}
+
+pub struct Simulation {
+    config: SimConfig,
+    rng: StdRng,
+    clock: ManualClock,
+    nodes: Vec<SimGraph>,
+    behaviors: Vec<Behavior>,
+    /// Finalized events of each node, in order
+    finalized: Vec<Vec<event::Hash>>,
+    /// Time of finalization of each event in `finalized`
+    finalized_at: Vec<Vec<Timestamp>>,
+    /// Time of authoring of each regular event, regardless of the
+    /// timestamp it carries
+    created: HashMap<event::Hash, Timestamp>,
+    in_flight: BinaryHeap<Reverse<InFlight>>,
+    next_seq: u64,
+    next_gossip: Timestamp,
+    /// Group of each node, messages between groups are dropped. `None` if
+    /// the network is whole.
+    partition: Option<Vec<usize>>,
+    next_transaction: u64,
+    /// Forks authored by forkers so far
+    forks: Vec<ForkEvidence<usize>>,
+    stats: SimStats,
+    /// First inconsistency found while checking under faults
+    inconsistency: Option<SimError>,
+}
+
+impl Simulation {
+    pub fn new(config: SimConfig) -> Self {
+        let clock = ManualClock::new(0);
+        let mut nodes: Vec<SimGraph> = (0..config.nodes)
+            .map(|id| {
+                let mut graph = Graph::new(
+                    id,
+                    0,
+                    (),
+                    config.coin_frequency,
+                    MockSigner::new(),
+                    clock.clone(),
+                );
+                graph.set_coin_seed(Some(config.seed));
+                graph.set_coin_strategy(config.coin_strategy);
+                graph.set_adaptive_coin(config.adaptive_coin);
+                graph
+            })
+            .collect();
+        for from in 0..nodes.len() {
+            for to in 0..nodes.len() {
+                if from != to {
+                    let jobs = nodes[from]
+                        .generate_sync_for(&to)
+                        .expect("fresh graph syncs");
+                    nodes[to].apply_sync_jobs(jobs).expect("geneses are valid");
+                }
+            }
+        }
+        let mut behaviors = vec![Behavior::Honest; config.nodes];
+        for (node, behavior) in config.adversaries.iter() {
+            behaviors[*node] = behavior.clone();
+        }
+        Self {
+            rng: StdRng::seed_from_u64(config.seed),
+            finalized: vec![vec![]; config.nodes],
+            finalized_at: vec![vec![]; config.nodes],
+            created: HashMap::new(),
+            clock,
+            nodes,
+            behaviors,
+            in_flight: BinaryHeap::new(),
+            next_seq: 0,
+            next_gossip: config.gossip_interval,
+            partition: None,
+            next_transaction: 0,
+            forks: vec![],
+            stats: SimStats::default(),
+            inconsistency: None,
+            config,
+        }
+    }
+
+    pub fn node(&self, index: usize) -> &SimGraph {
+        &self.nodes[index]
+    }
+
+    /// Events finalized by the node so far, in consensus order
+    pub fn finalized(&self, index: usize) -> &[event::Hash] {
+        &self.finalized[index]
+    }
+
+    /// Authoring and finalization times of regular events finalized by
+    /// the node, in consensus order
+    pub fn finality_times(&self, index: usize) -> Vec<FinalityTime> {
+        self.finalized[index]
+            .iter()
+            .zip(&self.finalized_at[index])
+            .filter_map(|(event, &finalized)| {
+                Some(FinalityTime {
+                    event: event.clone(),
+                    created: *self.created.get(event)?,
+                    finalized,
+                })
+            })
+            .collect()
+    }
+
+    pub fn now(&self) -> Timestamp {
+        self.clock.now()
+    }
+
+    pub fn stats(&self) -> SimStats {
+        self.stats
+    }
+
+    pub fn is_honest(&self, index: usize) -> bool {
+        self.behaviors[index] == Behavior::Honest
+    }
+
+    fn honest(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.nodes.len()).filter(|&i| self.is_honest(i))
+    }
+
+    /// Forks authored by forkers so far
+    pub fn injected_forks(&self) -> &[ForkEvidence<usize>] {
+        &self.forks
+    }
+
+    /// Forks the node can prove with the events it knows
+    pub fn fork_evidence(&self, index: usize) -> Vec<ForkEvidence<usize>> {
+        let graph = &self.nodes[index];
+        (0..self.nodes.len())
+            .flat_map(|author| {
+                let lane: Vec<_> = graph
+                    .peer_lane(&author)
+                    .expect("geneses are exchanged")
+                    .map(|e| e.hash().clone())
+                    .collect();
+                find_forks(graph, &lane).collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Split the network into `groups`, nodes not listed are isolated.
+    /// Messages already in flight between the groups are lost as well.
+    pub fn partition(&mut self, groups: &[&[usize]]) {
+        let mut assignment: Vec<_> = (0..self.nodes.len()).map(|i| groups.len() + i).collect();
+        for (group, members) in groups.iter().enumerate() {
+            for &node in members.iter() {
+                assignment[node] = group;
+            }
+        }
+        self.partition = Some(assignment);
+    }
+
+    pub fn heal(&mut self) {
+        self.partition = None;
+    }
+
+    /// Change how the node takes part from now on, e.g. to end an attack
+    pub fn set_behavior(&mut self, node: usize, behavior: Behavior) {
+        self.behaviors[node] = behavior;
+    }
+
+    /// Advance to the next gossip round or message delivery, whichever
+    /// comes first.
+    pub fn step(&mut self) {
+        let next_delivery = self.in_flight.peek().map(|Reverse(m)| m.at);
+        let now = match next_delivery {
+            Some(at) if at < self.next_gossip => at,
+            _ => self.next_gossip,
+        };
+        self.clock.set(now);
+        if now == self.next_gossip {
+            self.next_gossip += self.config.gossip_interval;
+            for from in 0..self.nodes.len() {
+                let to = self.random_peer(from);
+                let request = self.nodes[from].sync_request();
+                self.send(from, to, Packet::Request(request));
+            }
+        }
+        while self.in_flight.peek().map(|Reverse(m)| m.at <= now) == Some(true) {
+            let Reverse(message) = self.in_flight.pop().expect("just peeked");
+            self.deliver(message);
+        }
+    }
+
+    /// Run for `duration` of virtual time
+    pub fn run_for(&mut self, duration: Timestamp) {
+        let end = self.now() + duration;
+        while self.now() < end {
+            self.step();
+        }
+    }
+
+    /// Run until every honest node has finalized at least `events` events, checking
+    /// that the nodes agree along the way. Fails if it takes longer than
+    /// `timeout` of virtual time.
+    pub fn run_until_finalized(
+        &mut self,
+        events: usize,
+        timeout: Timestamp,
+    ) -> Result<(), SimError> {
+        let deadline = self.now() + timeout;
+        loop {
+            self.check_agreement()?;
+            if let Some(inconsistency) = &self.inconsistency {
+                return Err(inconsistency.clone());
+            }
+            if self.honest().all(|i| self.finalized[i].len() >= events) {
+                return Ok(());
+            }
+            if self.now() >= deadline {
+                return Err(SimError::Stalled {
+                    finalized: self.finalized.iter().map(|f| f.len()).collect(),
+                });
+            }
+            self.step();
+        }
+    }
+
+    /// Check that finalized sequences of all honest nodes are prefixes of
+    /// the longest one.
+    pub fn check_agreement(&self) -> Result<(), SimError> {
+        let Some(longest) = self.honest().max_by_key(|&i| self.finalized[i].len()) else {
+            return Ok(());
+        };
+        let reference = &self.finalized[longest];
+        for node in self.honest() {
+            if let Some(position) = self.finalized[node]
+                .iter()
+                .zip(reference)
+                .position(|(ours, theirs)| ours != theirs)
+            {
+                return Err(SimError::Diverged {
+                    a: node,
+                    b: longest,
+                    position,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check indices of all nodes, see [`Graph::check_consistency`]
+    pub fn check_consistency(&self) -> Result<(), SimError> {
+        if let Some(inconsistency) = &self.inconsistency {
+            return Err(inconsistency.clone());
+        }
+        for (node, graph) in self.nodes.iter().enumerate() {
+            graph
+                .check_consistency()
+                .map_err(|e| SimError::Inconsistent {
+                    node,
+                    reason: e.to_string(),
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Graph of `node` as a scenario shrunk to the events on which replicas
+    /// receiving them in different orders disagree, to investigate a
+    /// [`SimError::Diverged`]. `None` if the disagreement does not show up
+    /// this way, e.g. because it depends on payloads.
+    pub fn shrink_divergence(&self, node: usize) -> Option<Fixture> {
+        let scenario = Fixture::from_graph(
+            &format!("node {} with seed {}", node, self.config.seed),
+            &self.nodes[node],
+        );
+        if !replicas_disagree(&scenario) {
+            return None;
+        }
+        Some(shrink(&scenario, replicas_disagree))
+    }
+
+    /// Check that honest nodes report only injected forks and don't miss
+    /// any of them once they know the forked events.
+    pub fn check_fork_evidence(&self) -> Result<(), SimError> {
+        for node in self.honest() {
+            let evidence = self.fork_evidence(node);
+            if let Some(false_evidence) = evidence.iter().find(|e| !self.forks.contains(e)) {
+                return Err(SimError::FalseEvidence {
+                    node,
+                    author: false_evidence.author,
+                });
+            }
+            let graph = &self.nodes[node];
+            if let Some(missed) = self.forks.iter().find(|fork| {
+                fork.events.iter().all(|e| graph.event(e).is_some()) && !evidence.contains(fork)
+            }) {
+                return Err(SimError::MissedFork {
+                    node,
+                    author: missed.author,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn random_peer(&mut self, node: usize) -> usize {
+        let other = self.rng.gen_range(0..self.nodes.len() - 1);
+        if other >= node {
+            other + 1
+        } else {
+            other
+        }
+    }
+
+    fn connected(&self, a: usize, b: usize) -> bool {
+        match &self.partition {
+            Some(groups) => groups[a] == groups[b],
+            None => true,
+        }
+    }
+
+    fn send(&mut self, from: usize, to: usize, packet: Packet) {
+        self.stats.sent += 1;
+        // Always drawn, so that partitions don't shift the random sequence
+        let lost = self.rng.gen_bool(self.config.loss);
+        let latency = self.latency();
+        if lost || !self.connected(from, to) {
+            self.stats.lost += 1;
+            return;
+        }
+        let packet = match packet {
+            Packet::Response(jobs) if self.chance(self.config.faults.truncate) => {
+                let mut events = jobs.into_linear();
+                events.truncate(self.rng.gen_range(0..=events.len()));
+                self.stats.truncated += 1;
+                Packet::Response(Jobs::from_linear(events))
+            }
+            packet => packet,
+        };
+        if self.chance(self.config.faults.duplicate) {
+            let latency = self.latency();
+            self.stats.duplicated += 1;
+            self.enqueue(from, to, packet.clone(), latency);
+        }
+        self.enqueue(from, to, packet, latency);
+    }
+
+    /// Random latency, plus the hold if the message is held back
+    fn latency(&mut self) -> Timestamp {
+        let latency = self
+            .rng
+            .gen_range(self.config.min_latency..=self.config.max_latency);
+        if self.chance(self.config.faults.hold) {
+            latency + self.rng.gen_range(0..=self.config.faults.max_hold)
+        } else {
+            latency
+        }
+    }
+
+    /// Draws only for enabled faults, so that runs without them stay the same
+    fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.rng.gen_bool(probability)
+    }
+
+    fn enqueue(&mut self, from: usize, to: usize, packet: Packet, latency: Timestamp) {
+        self.in_flight.push(Reverse(InFlight {
+            at: self.now() + latency,
+            seq: self.next_seq,
+            from,
+            to,
+            packet,
+        }));
+        self.next_seq += 1;
+    }
+
+    fn deliver(&mut self, message: InFlight) {
+        let InFlight {
+            from, to, packet, ..
+        } = message;
+        if !self.connected(from, to) {
+            self.stats.lost += 1;
+            return;
+        }
+        self.stats.delivered += 1;
+        match packet {
+            Packet::Request(request) => {
+                if let Behavior::Withholder { victims } = &self.behaviors[to] {
+                    if victims.contains(&from) {
+                        return;
+                    }
+                }
+                let jobs = self.nodes[to]
+                    .generate_sync_for_request(&request)
+                    .expect("requests of honest nodes are valid");
+                let jobs = match self.behaviors[to] {
+                    Behavior::Forger => {
+                        let mut events = jobs.into_linear();
+                        events.extend(self.forge(to, from));
+                        Jobs::from_linear(events)
+                    }
+                    _ => jobs,
+                };
+                self.send(to, from, Packet::Response(jobs));
+            }
+            Packet::Response(jobs) => {
+                if let Err(e) = self.nodes[to].apply_sync_jobs(jobs) {
+                    debug!("Node {} failed to apply sync from {}: {}", to, from, e);
+                    self.stats.rejected += 1;
+                }
+                if self.config.faults.any() && self.inconsistency.is_none() {
+                    if let Err(e) = self.nodes[to].check_consistency() {
+                        self.inconsistency = Some(SimError::Inconsistent {
+                            node: to,
+                            reason: e.to_string(),
+                        });
+                    }
+                }
+                let other_parent = latest_event(&self.nodes[to], from);
+                self.author_event(to, other_parent);
+                let now = self.now();
+                let node = &mut self.nodes[to];
+                while let Some(event) = node.next_finalized_event() {
+                    self.finalized[to].push(event.hash().clone());
+                    self.finalized_at[to].push(now);
+                }
+            }
+        }
+    }
+
+    fn next_transaction(&mut self) -> u64 {
+        self.next_transaction += 1;
+        self.next_transaction - 1
+    }
+
+    fn author_event(&mut self, author: usize, other_parent: event::Hash) {
+        let timestamp = match self.behaviors[author] {
+            Behavior::TimestampLiar { max_shift } => {
+                let shift = self.rng.gen_range(0..=2 * max_shift);
+                (self.now() + shift).saturating_sub(max_shift)
+            }
+            _ => self.now(),
+        };
+        let self_parent = latest_event(&self.nodes[author], author);
+        let regular = self.push_own(author, &self_parent, &other_parent, timestamp);
+        if let Behavior::Forker { probability } = self.behaviors[author] {
+            if self.rng.gen_bool(probability) {
+                let fork = self.push_own(author, &self_parent, &other_parent, timestamp);
+                let mut events = vec![regular, fork];
+                events.sort();
+                self.forks.push(ForkEvidence {
+                    author,
+                    self_parent,
+                    events,
+                });
+            }
+        }
+    }
+
+    /// Author an event with the given parents and push it to the own graph
+    fn push_own(
+        &mut self,
+        author: usize,
+        self_parent: &event::Hash,
+        other_parent: &event::Hash,
+        timestamp: Timestamp,
+    ) -> event::Hash {
+        let transaction = self.next_transaction();
+        let event = SignedEvent::new(
+            transaction,
+            event::Kind::Regular(Parents {
+                self_parent: self_parent.clone(),
+                other_parent: other_parent.clone(),
+            }),
+            author,
+            timestamp,
+            |h| MockSigner::<usize, ()>::new().sign(h),
+        )
+        .expect("transactions are serializable");
+        let hash = event.hash().clone();
+        self.created.insert(hash.clone(), self.now());
+        let (unsigned, signature) = event.into_parts();
+        self.nodes[author]
+            .push_event(unsigned, signature)
+            .expect("authoring on known events succeeds");
+        hash
+    }
+
+    /// Event on behalf of a random member other than `forger` and
+    /// `recipient`, with a garbage signature
+    fn forge(&mut self, forger: usize, recipient: usize) -> Option<SignedEvent<u64, (), usize>> {
+        let victims: Vec<_> = (0..self.nodes.len())
+            .filter(|&i| i != forger && i != recipient)
+            .collect();
+        if victims.is_empty() {
+            return None;
+        }
+        let victim = victims[self.rng.gen_range(0..victims.len())];
+        let transaction = self.next_transaction();
+        let graph = &self.nodes[forger];
+        let event = SignedEvent::new(
+            transaction,
+            event::Kind::Regular(Parents {
+                self_parent: latest_event(graph, victim),
+                other_parent: latest_event(graph, forger),
+            }),
+            victim,
+            self.now(),
+            |_| Signature(event::Hash::from_array([0; 64])),
+        )
+        .expect("transactions are serializable");
+        Some(event)
+    }
+}
+
+/// Latest event of `peer` known to the graph. Smallest of the tips if the
+/// peer forked, so that the choice is reproducible.
+fn latest_event(graph: &SimGraph, peer: usize) -> event::Hash {
+    graph
+        .summary()
+        .tips
+        .into_iter()
+        .find(|(author, _)| *author == peer)
+        .and_then(|(_, tips)| tips.into_iter().min())
+        .expect("geneses are exchanged")
+}
+
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            eprintln!("Simulation failed with seed {}", self.config.seed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::latency::estimate_finality_latency;
+
+    const SECOND: Timestamp = 1000 * MILLISECOND;
+
+    #[test]
+    fn nodes_converge() {
+        let mut sim = Simulation::new(SimConfig::default());
+        sim.run_until_finalized(50, 10 * SECOND).unwrap();
+        assert_eq!(sim.stats().lost, 0);
+    }
+
+    fn with_adversary(behavior: Behavior) -> Simulation {
+        Simulation::new(SimConfig {
+            adversaries: vec![(3, behavior)],
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn forks_detected() {
+        let mut sim = with_adversary(Behavior::Forker { probability: 0.3 });
+        sim.run_until_finalized(30, 10 * SECOND).unwrap();
+        assert!(!sim.injected_forks().is_empty());
+        sim.check_fork_evidence().unwrap();
+        for node in 0..3 {
+            assert!(!sim.fork_evidence(node).is_empty());
+        }
+    }
+
+    #[test]
+    fn forgeries_rejected() {
+        let mut sim = with_adversary(Behavior::Forger);
+        sim.run_until_finalized(30, 10 * SECOND).unwrap();
+        assert!(sim.stats().rejected > 0);
+        // Accepted forgeries would look like forks of honest nodes
+        sim.check_fork_evidence().unwrap();
+    }
+
+    #[test]
+    fn withholder_tolerated() {
+        let mut sim = with_adversary(Behavior::Withholder {
+            victims: vec![0, 1],
+        });
+        sim.run_until_finalized(30, 10 * SECOND).unwrap();
+    }
+
+    #[test]
+    fn coin_strategies_survive_split_votes() {
+        let strategies = [
+            CoinStrategy::MiddleBit,
+            CoinStrategy::InjectedCoin { seed: 3 },
+            CoinStrategy::RoundRobinLeaderBit,
+        ];
+        for coin_strategy in strategies {
+            // Witnesses of the withholder are seen late by node 0, so the
+            // votes on them split, and with coin rounds right after the
+            // first round of voting the coins decide
+            let mut sim = Simulation::new(SimConfig {
+                coin_frequency: 2,
+                coin_strategy,
+                adversaries: vec![(3, Behavior::Withholder { victims: vec![0] })],
+                ..Default::default()
+            });
+            sim.run_until_finalized(30, 10 * SECOND)
+                .unwrap_or_else(|e| panic!("{coin_strategy:?}: {e}"));
+            sim.check_agreement().unwrap();
+        }
+    }
+
+    #[test]
+    fn adaptive_coin_agreed() {
+        let mut sim = Simulation::new(SimConfig {
+            adaptive_coin: Some(AdaptiveCoin::default()),
+            adversaries: vec![(3, Behavior::Withholder { victims: vec![0] })],
+            ..Default::default()
+        });
+        sim.run_until_finalized(30, 10 * SECOND).unwrap();
+        sim.check_agreement().unwrap();
+        let decided = (0..4)
+            .filter_map(|node| sim.node(node).last_decided_round())
+            .min()
+            .unwrap();
+        for round in 0..=decided {
+            let frequency = sim.node(0).coin_frequency_at(round);
+            assert!(frequency.is_some());
+            for node in 1..4 {
+                assert_eq!(sim.node(node).coin_frequency_at(round), frequency);
+            }
+        }
+    }
+
+    #[test]
+    fn adaptive_coin_follows_withholding() {
+        let adaptive = AdaptiveCoin {
+            min: 3,
+            max: 6,
+            window: 2,
+            lag: 3,
+        };
+        let mut sim = Simulation::new(SimConfig {
+            adaptive_coin: Some(adaptive),
+            adversaries: vec![(3, Behavior::Withholder { victims: vec![0] })],
+            ..Default::default()
+        });
+        let schedule = |sim: &Simulation| {
+            let decided = sim.node(0).last_decided_round().unwrap();
+            (0..=decided)
+                .map(|round| sim.node(0).coin_frequency_at(round).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // Votes on the witnesses of the withholder split, elections get
+        // slow and the coin comes sooner, but they still end
+        sim.run_for(SECOND / 2);
+        sim.check_agreement().unwrap();
+        let attacked = schedule(&sim);
+        assert!(attacked.contains(&adaptive.min), "{attacked:?}");
+        let finalized = sim.finalized(0).len();
+        assert!(finalized > 0);
+
+        // Elections are fast again once it stops
+        sim.set_behavior(3, Behavior::Honest);
+        sim.run_for(SECOND / 2);
+        sim.check_agreement().unwrap();
+        assert!(sim.finalized(0).len() > finalized);
+        let recovered = schedule(&sim);
+        let lowest = recovered.iter().rposition(|&f| f == adaptive.min).unwrap();
+        let growing = &recovered[lowest..];
+        assert!(growing
+            .windows(2)
+            .all(|w| w[1] == (w[0] + 1).min(adaptive.max)));
+        assert_eq!(recovered.last(), Some(&adaptive.max), "{recovered:?}");
+    }
+
+    #[test]
+    fn timestamp_liar_tolerated() {
+        let mut sim = with_adversary(Behavior::TimestampLiar { max_shift: SECOND });
+        sim.run_until_finalized(30, 10 * SECOND).unwrap();
+    }
+
+    #[test]
+    fn runs_reproducible() {
+        let config = SimConfig {
+            loss: 0.2,
+            seed: 7,
+            ..Default::default()
+        };
+        let mut runs = vec![];
+        for _ in 0..2 {
+            let mut sim = Simulation::new(config.clone());
+            sim.run_for(SECOND);
+            sim.check_agreement().unwrap();
+            runs.push((sim.finalized(0).to_vec(), sim.stats()));
+        }
+        assert!(!runs[0].0.is_empty());
+        assert_eq!(runs[0], runs[1]);
+    }
+
+    #[test]
+    fn agreeing_run_has_no_divergence() {
+        let mut sim = Simulation::new(SimConfig::default());
+        sim.run_for(SECOND / 4);
+        assert!(sim.node(0).last_decided_round().is_some());
+        assert!(sim.shrink_divergence(0).is_none());
+    }
+
+    #[test]
+    fn network_faults_tolerated() {
+        let mut sim = Simulation::new(SimConfig {
+            loss: 0.1,
+            faults: Faults {
+                duplicate: 0.2,
+                hold: 0.2,
+                max_hold: 50 * MILLISECOND,
+                truncate: 0.2,
+            },
+            ..Default::default()
+        });
+        sim.run_until_finalized(20, 20 * SECOND).unwrap();
+        sim.check_consistency().unwrap();
+        let stats = sim.stats();
+        assert!(stats.duplicated > 0 && stats.truncated > 0);
+    }
+
+    #[test]
+    fn partition_halts_and_heals() {
+        let mut sim = Simulation::new(SimConfig {
+            loss: 0.1,
+            ..Default::default()
+        });
+        sim.run_until_finalized(10, 10 * SECOND).unwrap();
+        // Neither half has a supermajority
+        sim.partition(&[&[0, 1], &[2, 3]]);
+        // Let the events authored before the split settle
+        sim.run_for(SECOND / 2);
+        let before: Vec<_> = (0..4).map(|i| sim.finalized(i).len()).collect();
+        sim.run_for(SECOND);
+        let after: Vec<_> = (0..4).map(|i| sim.finalized(i).len()).collect();
+        assert_eq!(before, after);
+
+        sim.heal();
+        let target = after.iter().max().unwrap() + 10;
+        sim.run_until_finalized(target, 20 * SECOND).unwrap();
+    }
+
+    #[test]
+    fn finality_latency_matches_estimate() {
+        for (nodes, loss) in [(4, 0.0), (4, 0.2), (5, 0.0)] {
+            let mut sim = Simulation::new(SimConfig {
+                nodes,
+                loss,
+                ..Default::default()
+            });
+            sim.run_for(SECOND * 3 / 4);
+            // Later events may be not finalized yet, skewing the median
+            let mut latencies: Vec<_> = (0..nodes)
+                .flat_map(|node| sim.finality_times(node))
+                .filter(|t| t.created < SECOND / 3)
+                .map(|t| t.latency())
+                .collect();
+            latencies.sort_unstable();
+            let median = latencies[latencies.len() / 2] as f64;
+            let estimate =
+                estimate_finality_latency(nodes, sim.config.gossip_interval, loss) as f64;
+            assert!(
+                (0.8..1.25).contains(&(estimate / median)),
+                "{} nodes, loss {}: estimated {}ms, simulated {}ms",
+                nodes,
+                loss,
+                estimate / 1e6,
+                median / 1e6
+            );
+        }
+    }
+}
@@ -0,0 +1,431 @@
+//! Checks for other implementations of the consensus (`conformance`
+//! feature).
+//!
+//! An implementation under test is wrapped into a [`Reference`] and given
+//! to [`run`]: it must reproduce the outcome of the
+//! [canonical fixtures](super::fixture::canonical) and keep the properties
+//! of any hashgraph on random gossip. [`wire_vectors`] are encoded sync
+//! messages for checking decoders of the wire format.
+//!
+//! ```
+//! use rust_hashgraph::testing::conformance;
+//! use rust_hashgraph::testing::differential::GraphReference;
+//!
+//! let failures = conformance::run(GraphReference::new);
+//! assert!(failures.is_empty(), "{:?}", failures);
+//! ```
+
+use std::collections::HashSet;
+use std::fmt::Display;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use super::differential::{Reference, ScenarioEvent, Stream};
+use super::fixture::{self, Fixture, FixtureEvent, Outcome};
+use super::{seed_from_env, GENESIS_PREFIX};
+use crate::algorithm::datastructure::WitnessFamousness;
+use crate::algorithm::event;
+
+/// Random scenarios checked by [`run`]
+pub const PROPERTY_CASES: u64 = 16;
+const MAX_PEERS: usize = 5;
+const MAX_STEPS: usize = 60;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    /// E.g. `fixture 'fork'`
+    pub check: String,
+    pub details: Vec<String>,
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} failed:\n{}", self.check, self.details.join("\n"))
+    }
+}
+
+/// Failures of implementations made by `make_engine` (from the coin
+/// frequency) on all checks. Random scenarios are seeded from
+/// [`SEED_VARIABLE`](super::SEED_VARIABLE), `0` by default.
+pub fn run<R, F>(make_engine: F) -> Vec<Failure>
+where
+    R: Reference,
+    F: Fn(usize) -> R,
+{
+    let fixtures = fixture::canonical()
+        .into_iter()
+        .map(|f| check_fixture(&f, &make_engine));
+    let base_seed = seed_from_env(0);
+    let properties = (0..PROPERTY_CASES).map(|i| {
+        let scenario = random_scenario(base_seed.wrapping_add(i));
+        check_properties(&scenario, &make_engine)
+    });
+    fixtures.chain(properties).filter_map(Result::err).collect()
+}
+
+/// The engine reaches the outcome expected by the fixture
+pub fn check_fixture<R, F>(fixture: &Fixture, make_engine: F) -> Result<(), Failure>
+where
+    R: Reference,
+    F: Fn(usize) -> R,
+{
+    let failure = |details| Failure {
+        check: format!("fixture '{}'", fixture.name),
+        details,
+    };
+    let stream = Stream::new(fixture);
+    let mut engine = make_engine(fixture.coin_frequency);
+    push_all(&mut engine, &stream, stream.events.iter()).map_err(|e| failure(vec![e]))?;
+    let mismatches = fixture::differences(&fixture.expected, &outcome(&engine, &stream));
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(failure(mismatches))
+    }
+}
+
+/// Properties of any hashgraph hold on the scenario (its `expected` is
+/// ignored):
+/// - geneses are round 0 witnesses, rounds don't decrease from parents,
+///   events of a later round than their self parent are witnesses;
+/// - the order only grows;
+/// - decided fame and the order don't depend on the order of pushing.
+pub fn check_properties<R, F>(scenario: &Fixture, make_engine: F) -> Result<(), Failure>
+where
+    R: Reference,
+    F: Fn(usize) -> R,
+{
+    let failure = |property: &str, details| Failure {
+        check: format!("property '{}' on '{}'", property, scenario.name),
+        details,
+    };
+    let stream = Stream::new(scenario);
+    let mut in_order = make_engine(scenario.coin_frequency);
+    let mut previous_order = vec![];
+    for event in &stream.events {
+        push_all(&mut in_order, &stream, [event]).map_err(|e| failure("accepted", vec![e]))?;
+        let order = in_order.order();
+        if !order.starts_with(&previous_order) {
+            return Err(failure(
+                "order only grows",
+                vec![format!(
+                    "order changed after '{}'",
+                    stream.name(event.hash())
+                )],
+            ));
+        }
+        previous_order = order;
+    }
+    let mismatches = round_violations(&in_order, &stream);
+    if !mismatches.is_empty() {
+        return Err(failure("rounds", mismatches));
+    }
+
+    let mut shuffled = make_engine(scenario.coin_frequency);
+    push_all(
+        &mut shuffled,
+        &stream,
+        shuffled_topologically(&stream, scenario.peers.len()),
+    )
+    .map_err(|e| failure("accepted", vec![e]))?;
+    let mismatches = disagreements(&in_order, &shuffled, &stream);
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(failure("replicas agree", mismatches))
+    }
+}
+
+/// Gossip between 2 to 5 peers: each step a random peer creates an event
+/// on top of its latest event and the latest event of another peer
+pub fn random_scenario(seed: u64) -> Fixture {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let peers = rng.gen_range(2..=MAX_PEERS);
+    let steps = rng.gen_range(0..=MAX_STEPS);
+    gossip(&mut rng, seed, peers, steps)
+}
+
+/// Gossip like [`random_scenario`], with the given numbers of peers and
+/// events
+pub fn random_gossip(seed: u64, peers: usize, steps: usize) -> Fixture {
+    gossip(&mut StdRng::seed_from_u64(seed), seed, peers, steps)
+}
+
+fn gossip(rng: &mut StdRng, seed: u64, peers: usize, steps: usize) -> Fixture {
+    let peers: Vec<_> = (0..peers).map(|i| format!("p{}", i)).collect();
+    let mut tips: Vec<_> = peers
+        .iter()
+        .map(|p| format!("{}{}", GENESIS_PREFIX, p))
+        .collect();
+    let mut counts = vec![0; peers.len()];
+    let mut events = vec![];
+    for step in 0..steps as u128 {
+        let author = rng.gen_range(0..peers.len());
+        let other = (author + rng.gen_range(1..peers.len())) % peers.len();
+        counts[author] += 1;
+        let name = format!("{}_{}", peers[author], counts[author]);
+        events.push(FixtureEvent {
+            name: name.clone(),
+            creator: tips[author].clone(),
+            other_parent: tips[other].clone(),
+            timestamp: step + 1,
+        });
+        tips[author] = name;
+    }
+    Fixture {
+        name: format!("random gossip (seed {})", seed),
+        source: "Generated by `conformance::random_scenario`".to_owned(),
+        peers,
+        coin_frequency: 999,
+        events,
+        expected: Outcome::default(),
+    }
+}
+
+fn push_all<'a, R: Reference>(
+    engine: &mut R,
+    stream: &Stream,
+    events: impl IntoIterator<Item = &'a ScenarioEvent>,
+) -> Result<(), String> {
+    for event in events {
+        engine
+            .push(event)
+            .map_err(|e| format!("'{}' rejected: {}", stream.name(event.hash()), e))?;
+    }
+    Ok(())
+}
+
+fn outcome<R: Reference>(engine: &R, stream: &Stream) -> Outcome {
+    let mut outcome = Outcome::default();
+    for (hash, name) in &stream.names {
+        if let Some(round) = engine.round(hash) {
+            outcome.rounds.insert(name.clone(), round);
+        }
+        if let Some(fame) = engine.fame(hash) {
+            outcome.witnesses.insert(name.clone(), (&fame).into());
+        }
+    }
+    outcome.order = engine.order().iter().map(|h| stream.name(h)).collect();
+    outcome
+}
+
+fn round_violations<R: Reference>(engine: &R, stream: &Stream) -> Vec<String> {
+    let mut violations = vec![];
+    for event in &stream.events {
+        let hash = event.hash();
+        let name = stream.name(hash);
+        let Some(round) = engine.round(hash) else {
+            violations.push(format!("round of '{}' unknown", name));
+            continue;
+        };
+        let witness = engine.fame(hash).is_some();
+        match event.unsigned().fields().kind() {
+            event::Kind::Genesis(_) => {
+                if round != 0 || !witness {
+                    violations.push(format!(
+                        "genesis '{}': round {}, witness {}",
+                        name, round, witness
+                    ));
+                }
+            }
+            event::Kind::Regular(parents) => {
+                let self_parent_round = engine.round(&parents.self_parent).unwrap_or(0);
+                let other_parent_round = engine.round(&parents.other_parent).unwrap_or(0);
+                if round < self_parent_round.max(other_parent_round) {
+                    violations.push(format!(
+                        "round of '{}' is {}, less than of its parents",
+                        name, round
+                    ));
+                }
+                if witness != (round > self_parent_round) {
+                    violations.push(format!(
+                        "'{}' of round {} with self parent of round {}: witness {}",
+                        name, round, self_parent_round, witness
+                    ));
+                }
+            }
+        }
+    }
+    violations
+}
+
+fn disagreements<R: Reference>(in_order: &R, shuffled: &R, stream: &Stream) -> Vec<String> {
+    let mut mismatches = vec![];
+    for hash in stream.names.keys() {
+        match (in_order.fame(hash), shuffled.fame(hash)) {
+            (Some(a), Some(b))
+                if a != b
+                    && a != WitnessFamousness::Undecided
+                    && b != WitnessFamousness::Undecided =>
+            {
+                mismatches.push(format!(
+                    "fame of '{}': {:?} and {:?}",
+                    stream.name(hash),
+                    a,
+                    b
+                ))
+            }
+            _ => (),
+        }
+    }
+    let (a, b) = (in_order.order(), shuffled.order());
+    let common = a.len().min(b.len());
+    if a[..common] != b[..common] {
+        let names = |order: &[event::Hash]| {
+            order[..common]
+                .iter()
+                .map(|h| stream.name(h))
+                .collect::<Vec<_>>()
+        };
+        mismatches.push(format!(
+            "orders differ: {:?} and {:?}",
+            names(&a),
+            names(&b)
+        ));
+    }
+    mismatches
+}
+
+/// Random (but fixed for the stream) order with parents before children
+/// and geneses first
+fn shuffled_topologically(stream: &Stream, peers: usize) -> Vec<&ScenarioEvent> {
+    let mut rng = StdRng::seed_from_u64(stream.events.len() as u64);
+    let mut result: Vec<_> = stream.events[..peers].iter().collect();
+    let mut pushed: HashSet<_> = result.iter().map(|e| e.hash().clone()).collect();
+    let mut remaining: Vec<_> = stream.events[peers..].iter().collect();
+    while !remaining.is_empty() {
+        let ready: Vec<_> = (0..remaining.len())
+            .filter(|&i| match remaining[i].unsigned().fields().kind() {
+                event::Kind::Genesis(_) => unreachable!("Geneses are pushed first"),
+                event::Kind::Regular(parents) => {
+                    pushed.contains(&parents.self_parent) && pushed.contains(&parents.other_parent)
+                }
+            })
+            .collect();
+        let next = remaining.swap_remove(ready[rng.gen_range(0..ready.len())]);
+        pushed.insert(next.hash().clone());
+        result.push(next);
+    }
+    result
+}
+
+/// Encoded sync message, see
+/// [`wire`](crate::algorithm::datastructure::sync::wire)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WireVector {
+    pub name: String,
+    /// `jobs` or `sync_request`
+    pub kind: String,
+    pub version: u16,
+    pub description: String,
+    pub hex: String,
+}
+
+impl WireVector {
+    pub fn bytes(&self) -> Vec<u8> {
+        (0..self.hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&self.hex[i..i + 2], 16).expect("Vectors are valid hex"))
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct WireVectors {
+    vectors: Vec<WireVector>,
+}
+
+/// Messages of `Graph::new(7, 42u32, (), 999, ..)` (with `u64` peer ids)
+/// in every supported wire format version. Messages of [`WIRE_VERSION`]
+/// must be encoded exactly so, older ones must be decoded to the same.
+///
+/// [`WIRE_VERSION`]: crate::algorithm::datastructure::sync::wire::WIRE_VERSION
+pub fn wire_vectors() -> Vec<WireVector> {
+    let vectors: WireVectors = serde_json::from_str(include_str!(
+        "../../resources/conformance/wire_vectors.json"
+    ))
+    .expect("Wire vectors are valid");
+    vectors.vectors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::datastructure::sync::wire::{WireMessage, WIRE_VERSION};
+    use crate::algorithm::datastructure::sync::{Jobs, SyncRequest};
+    use crate::algorithm::datastructure::Graph;
+    use crate::algorithm::RoundNum;
+    use crate::algorithm::{IncrementalClock, MockSigner};
+    use crate::testing::differential::GraphReference;
+    use crate::testing::TestGraph;
+
+    #[test]
+    fn graph_conforms() {
+        let failures = run(GraphReference::new);
+        assert!(failures.is_empty(), "{:?}", failures);
+    }
+
+    /// Finalizes nothing
+    struct Stalled(GraphReference);
+
+    impl Reference for Stalled {
+        fn push(&mut self, event: &ScenarioEvent) -> Result<(), String> {
+            self.0.push(event)
+        }
+
+        fn round(&self, event: &event::Hash) -> Option<RoundNum> {
+            self.0.round(event)
+        }
+
+        fn fame(&self, event: &event::Hash) -> Option<WitnessFamousness> {
+            self.0.fame(event)
+        }
+
+        fn order(&self) -> Vec<event::Hash> {
+            vec![]
+        }
+    }
+
+    #[test]
+    fn missing_order_reported() {
+        let failure =
+            check_fixture(&fixture::fork(), |c| Stalled(GraphReference::new(c))).unwrap_err();
+        assert_eq!(failure.check, "fixture 'fork'");
+        assert!(failure.details[0].starts_with("order differs from position 0"));
+        // Properties hold for a stalled engine
+        check_properties(&random_scenario(1), |c| Stalled(GraphReference::new(c))).unwrap();
+    }
+
+    #[test]
+    fn wire_vectors_match() {
+        let graph: TestGraph<u32, u64> =
+            Graph::new(7, 42, (), 999, MockSigner::new(), IncrementalClock::new());
+        let jobs = graph.generate_sync_for(&8).unwrap();
+        let request = graph.sync_request();
+        for vector in wire_vectors() {
+            let bytes = vector.bytes();
+            match vector.kind.as_str() {
+                "jobs" => {
+                    assert_eq!(Jobs::from_wire(&bytes).unwrap(), jobs, "{}", vector.name);
+                    if vector.version == WIRE_VERSION {
+                        assert_eq!(jobs.to_wire().unwrap(), bytes, "{}", vector.name);
+                    }
+                }
+                "sync_request" => {
+                    assert_eq!(
+                        SyncRequest::from_wire(&bytes).unwrap(),
+                        request,
+                        "{}",
+                        vector.name
+                    );
+                    if vector.version == WIRE_VERSION {
+                        assert_eq!(request.to_wire().unwrap(), bytes, "{}", vector.name);
+                    }
+                }
+                kind => panic!("Unknown vector kind {}", kind),
+            }
+        }
+    }
+}
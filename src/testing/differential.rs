@@ -0,0 +1,326 @@
+//! Differential testing against another implementation of the consensus.
+//!
+//! Events of a scenario (described as a [`Fixture`], its `expected` is
+//! ignored) are pushed one by one both to this crate's graph and to a
+//! [`Reference`]. After each event rounds, fame of witnesses and the final
+//! order are compared. On the first difference the scenario is shrunk to
+//! a smaller one that still shows some difference and reported as
+//! [`Divergence`].
+
+use std::collections::HashMap;
+
+use super::fixture::Fixture;
+use super::shrink::shrink;
+use super::TestGraph;
+use crate::algorithm::datastructure::{Graph, WitnessFamousness};
+use crate::algorithm::event::{self, SignedEvent};
+use crate::algorithm::{IncrementalClock, MockSigner, RoundNum};
+
+pub type ScenarioEvent = SignedEvent<(), (), u64>;
+
+/// Implementation to compare with. Events are given in topological order,
+/// geneses first.
+pub trait Reference {
+    /// Error message if the event is rejected
+    fn push(&mut self, event: &ScenarioEvent) -> Result<(), String>;
+    fn round(&self, event: &event::Hash) -> Option<RoundNum>;
+    /// `None` if the event is not a witness
+    fn fame(&self, event: &event::Hash) -> Option<WitnessFamousness>;
+    /// Finalized events in consensus order
+    fn order(&self) -> Vec<event::Hash>;
+}
+
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    /// Number of events (geneses included) pushed when the difference
+    /// appeared in the original scenario
+    pub after_events: usize,
+    /// E.g. `round of 'b3': ours 2, reference Some(1)`
+    pub difference: String,
+    /// Scenario with a difference, see [`shrink`]
+    pub reproducer: Fixture,
+}
+
+/// `Err` with the first difference between this crate and references made
+/// by `make_reference` (a new one is needed for each replay during
+/// shrinking).
+///
+/// # Panics
+/// If the scenario does not build.
+pub fn check_against<R, F>(scenario: &Fixture, make_reference: F) -> Result<(), Box<Divergence>>
+where
+    R: Reference,
+    F: Fn() -> R,
+{
+    let Some((after_events, difference)) = Stream::new(scenario).first_divergence(&make_reference)
+    else {
+        return Ok(());
+    };
+    let prefix = Fixture {
+        events: scenario.events[..after_events.saturating_sub(scenario.peers.len())].to_vec(),
+        ..scenario.clone()
+    };
+    let reproducer = shrink(&prefix, |candidate| {
+        Stream::new(candidate)
+            .first_divergence(&make_reference)
+            .is_some()
+    });
+    Err(Box::new(Divergence {
+        after_events,
+        difference,
+        reproducer,
+    }))
+}
+
+/// This crate as a [`Reference`], e.g. to check a test harness for
+/// another implementation
+pub struct GraphReference {
+    graph: TestGraph<(), u64>,
+    order: Vec<event::Hash>,
+}
+
+impl GraphReference {
+    pub fn new(coin_frequency: usize) -> Self {
+        Self {
+            graph: Graph::new(
+                0,
+                (),
+                (),
+                coin_frequency,
+                MockSigner::new(),
+                IncrementalClock::new(),
+            ),
+            order: vec![],
+        }
+    }
+}
+
+impl Reference for GraphReference {
+    fn push(&mut self, event: &ScenarioEvent) -> Result<(), String> {
+        // Own genesis is created by the graph
+        if self.graph.event(event.hash()).is_none() {
+            let (unsigned, signature) = event.clone().into_parts();
+            self.graph
+                .push_event(unsigned, signature)
+                .map_err(|e| e.to_string())?;
+        }
+        while let Some(finalized) = self.graph.next_finalized_event() {
+            self.order.push(finalized.hash().clone());
+        }
+        Ok(())
+    }
+
+    fn round(&self, event: &event::Hash) -> Option<RoundNum> {
+        Some(self.graph.event_info(event)?.round)
+    }
+
+    fn fame(&self, event: &event::Hash) -> Option<WitnessFamousness> {
+        self.graph.event_info(event)?.witness
+    }
+
+    fn order(&self) -> Vec<event::Hash> {
+        self.order.clone()
+    }
+}
+
+/// Events of a scenario in the order of pushing
+pub(super) struct Stream {
+    coin_frequency: usize,
+    /// Geneses in the order of peers, then regular events
+    pub(super) events: Vec<ScenarioEvent>,
+    pub(super) names: HashMap<event::Hash, String>,
+}
+
+impl Stream {
+    pub(super) fn new(scenario: &Fixture) -> Self {
+        let built = scenario
+            .build()
+            .unwrap_or_else(|e| panic!("Scenario '{}' does not build: {}", scenario.name, e));
+        let names = scenario
+            .peers
+            .iter()
+            .map(|peer| format!("{}{}", super::GENESIS_PREFIX, peer))
+            .chain(scenario.events.iter().map(|e| e.name.clone()));
+        let mut events = vec![];
+        let mut hashes = HashMap::new();
+        for name in names {
+            let hash = built.hash(&name);
+            let event = built
+                .graph
+                .event(hash)
+                .expect("Built graph has named events");
+            events.push(event.inner().clone());
+            hashes.insert(hash.clone(), name);
+        }
+        Self {
+            coin_frequency: scenario.coin_frequency,
+            events,
+            names: hashes,
+        }
+    }
+
+    pub(super) fn name(&self, hash: &event::Hash) -> String {
+        self.names
+            .get(hash)
+            .cloned()
+            .unwrap_or_else(|| format!("{}", hash))
+    }
+
+    /// Number of pushed events and description of the first difference
+    fn first_divergence<R: Reference>(
+        &self,
+        make_reference: impl Fn() -> R,
+    ) -> Option<(usize, String)> {
+        let events: Vec<_> = self.events.iter().collect();
+        let mut ours: TestGraph<(), u64> = Graph::new(
+            0,
+            (),
+            (),
+            self.coin_frequency,
+            MockSigner::new(),
+            IncrementalClock::new(),
+        );
+        let mut reference = make_reference();
+        let mut ours_order = vec![];
+        for (i, &event) in events.iter().enumerate() {
+            // Own genesis is created by the graph
+            if i > 0 {
+                let (unsigned, signature) = event.clone().into_parts();
+                if let Err(e) = ours.push_event(unsigned, signature) {
+                    return Some((
+                        i + 1,
+                        format!("'{}' rejected by ours: {}", self.name(event.hash()), e),
+                    ));
+                }
+            }
+            if let Err(e) = reference.push(event) {
+                return Some((
+                    i + 1,
+                    format!("'{}' rejected by reference: {}", self.name(event.hash()), e),
+                ));
+            }
+            while let Some(finalized) = ours.next_finalized_event() {
+                ours_order.push(finalized.hash().clone());
+            }
+            if let Some(difference) = self.difference(&ours, &ours_order, &reference, &events[..=i])
+            {
+                return Some((i + 1, difference));
+            }
+        }
+        None
+    }
+
+    fn difference<R: Reference>(
+        &self,
+        ours: &TestGraph<(), u64>,
+        ours_order: &[event::Hash],
+        reference: &R,
+        pushed: &[&ScenarioEvent],
+    ) -> Option<String> {
+        for event in pushed {
+            let hash = event.hash();
+            let info = ours.event_info(hash).expect("Pushed events are known");
+            let reference_round = reference.round(hash);
+            if reference_round != Some(info.round) {
+                return Some(format!(
+                    "round of '{}': ours {}, reference {:?}",
+                    self.name(hash),
+                    info.round,
+                    reference_round
+                ));
+            }
+            let reference_fame = reference.fame(hash);
+            if reference_fame != info.witness {
+                return Some(format!(
+                    "fame of '{}': ours {:?}, reference {:?}",
+                    self.name(hash),
+                    info.witness,
+                    reference_fame
+                ));
+            }
+        }
+        let reference_order = reference.order();
+        if reference_order != ours_order {
+            let position = reference_order
+                .iter()
+                .zip(ours_order)
+                .position(|(r, o)| r != o)
+                .unwrap_or_else(|| reference_order.len().min(ours_order.len()));
+            let names = |order: &[event::Hash]| {
+                order[position..]
+                    .iter()
+                    .map(|hash| self.name(hash))
+                    .collect::<Vec<_>>()
+            };
+            return Some(format!(
+                "order from position {}: ours {:?}, reference {:?}",
+                position,
+                names(ours_order),
+                names(&reference_order)
+            ));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture;
+
+    /// [`GraphReference`] with rounds of events by `author` off by one
+    /// from round 2
+    struct Buggy {
+        inner: GraphReference,
+        author: u64,
+    }
+
+    impl Reference for Buggy {
+        fn push(&mut self, event: &ScenarioEvent) -> Result<(), String> {
+            self.inner.push(event)
+        }
+
+        fn round(&self, event: &event::Hash) -> Option<RoundNum> {
+            let info = self.inner.graph.event_info(event)?;
+            match info.round {
+                r if info.author == self.author && r >= 2 => Some(r + 1),
+                r => Some(r),
+            }
+        }
+
+        fn fame(&self, event: &event::Hash) -> Option<WitnessFamousness> {
+            self.inner.fame(event)
+        }
+
+        fn order(&self) -> Vec<event::Hash> {
+            self.inner.order()
+        }
+    }
+
+    fn buggy(author: u64) -> Buggy {
+        Buggy {
+            inner: GraphReference::new(999),
+            author,
+        }
+    }
+
+    #[test]
+    fn same_implementation_agrees() {
+        for scenario in [fixture::detailed_example(), fixture::fork()] {
+            check_against(&scenario, || GraphReference::new(999)).unwrap();
+        }
+    }
+
+    #[test]
+    fn divergence_reported() {
+        let scenario = fixture::fork();
+        let divergence = check_against(&scenario, || buggy(1)).unwrap_err();
+        assert!(divergence.difference.starts_with("round of 'm3'"));
+        let reproducer = &divergence.reproducer;
+        assert!(reproducer.events.len() <= divergence.after_events - scenario.peers.len());
+        assert!(reproducer.events.iter().any(|e| e.name == "m3"));
+        // Still diverges
+        assert!(check_against(reproducer, || buggy(1)).is_err());
+        assert!(check_against(reproducer, || GraphReference::new(999)).is_ok());
+    }
+}
@@ -0,0 +1,374 @@
+//! Graphs with known consensus outcome, for checking that changes of the
+//! algorithm keep its answers.
+//!
+//! A [`Fixture`] is a JSON document describing events in the terms of
+//! [`GraphBuilder`] together with the expected rounds, witnesses with their
+//! fame and the final order:
+//! ```json
+//! {
+//!   "name": "example",
+//!   "source": "where the graph and the answers come from",
+//!   "peers": ["a", "b"],
+//!   "coin_frequency": 999,
+//!   "events": [
+//!     { "name": "b1", "creator": "b", "other_parent": "a", "timestamp": 1 }
+//!   ],
+//!   "expected": {
+//!     "rounds": { "GENESIS_a": 0, "GENESIS_b": 0, "b1": 0 },
+//!     "witnesses": { "GENESIS_a": "undecided", "GENESIS_b": "undecided" },
+//!     "order": []
+//!   }
+//! }
+//! ```
+//! The graph is built by the first peer, peer ids are positions in `peers`
+//! and all payloads are `()`.
+//!
+//! Canonical fixtures live in `resources/fixtures`, see [`canonical`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+use super::{BuildError, BuiltGraph, GraphBuilder, TestGraph, GENESIS_PREFIX};
+use crate::algorithm::datastructure::export::json::JsonFame;
+use crate::algorithm::datastructure::Graph;
+use crate::algorithm::event;
+use crate::algorithm::RoundNum;
+use crate::Timestamp;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Fixture {
+    pub name: String,
+    /// Origin of the graph and of the expected outcome
+    #[serde(default)]
+    pub source: String,
+    pub peers: Vec<String>,
+    pub coin_frequency: usize,
+    /// In the order of insertion
+    pub events: Vec<FixtureEvent>,
+    pub expected: Outcome,
+}
+
+/// Arguments of [`GraphBuilder::event`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FixtureEvent {
+    pub name: String,
+    pub creator: String,
+    pub other_parent: String,
+    #[serde(default)]
+    pub timestamp: Timestamp,
+}
+
+/// Consensus on the graph, events are referred to by names
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Outcome {
+    /// Round of every event, geneses included
+    pub rounds: BTreeMap<String, RoundNum>,
+    /// Fame of every witness
+    pub witnesses: BTreeMap<String, JsonFame>,
+    /// Finalized events in consensus order
+    pub order: Vec<String>,
+}
+
+impl Fixture {
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Fixtures are serializable")
+    }
+
+    pub fn build(&self) -> Result<BuiltGraph<(), u64>, BuildError<u64>> {
+        let mut peers = self.peers.iter().zip(0..);
+        let (self_name, self_id) = peers.next().expect("Fixture has no peers");
+        let mut builder = GraphBuilder::new(self_name, self_id, (), self.coin_frequency);
+        for (name, id) in peers {
+            builder = builder.peer(name, id);
+        }
+        for event in &self.events {
+            builder = builder.timestamp(event.timestamp).event(
+                &event.name,
+                &event.creator,
+                &event.other_parent,
+            );
+        }
+        builder.build()
+    }
+
+    /// Graph of the fixture's peers with their geneses only, e.g. for
+    /// [`replay`]ing the events into
+    pub fn build_geneses(&self) -> Result<BuiltGraph<(), u64>, BuildError<u64>> {
+        Fixture {
+            events: vec![],
+            ..self.clone()
+        }
+        .build()
+    }
+
+    /// Outcome given by this implementation, e.g. for recording
+    /// `expected` of a new fixture
+    pub fn observe(&self) -> Result<Outcome, BuildError<u64>> {
+        let mut built = self.build()?;
+        let names: HashMap<_, _> = built
+            .events()
+            .map(|(name, hash)| (hash.clone(), name.to_owned()))
+            .collect();
+        Ok(outcome(&mut built.graph, &names))
+    }
+
+    /// Scenario with the structure of `graph`. Peers are named `p0`, `p1`,
+    /// ... in the order of ids starting from the graph's owner, events are
+    /// named `<peer>_<n>`. Payloads are not kept, so hashes and thus
+    /// tie-breaks of the order differ from the original.
+    pub fn from_graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>(
+        name: &str,
+        graph: &Graph<TPayload, TGenesisPayload, TPeerId, TSigner, TClock>,
+    ) -> Self
+    where
+        TPeerId: Eq + Hash + Ord + Clone + Debug,
+    {
+        let mut ids = graph.peers();
+        ids.sort();
+        // Observers have no events, their scenarios start from any member
+        if let Some(owner) = ids.iter().position(|id| Some(id) == graph.self_id()) {
+            ids.rotate_left(owner);
+        }
+        let peers: Vec<_> = (0..ids.len()).map(|i| format!("p{}", i)).collect();
+        let peer_names: HashMap<_, _> = ids.iter().zip(&peers).collect();
+
+        let mut infos: HashMap<_, _> = graph
+            .query()
+            .iter()
+            .map(|hash| {
+                let info = graph.event_info(hash).expect("Querying known events");
+                (hash.clone(), info)
+            })
+            .collect();
+        let mut names: HashMap<event::Hash, String> = HashMap::new();
+        let mut counts: HashMap<&String, usize> = HashMap::new();
+        let mut events = vec![];
+        // Kahn's algorithm, ties are broken by timestamp and hash for
+        // determinism
+        while !infos.is_empty() {
+            let ready = infos
+                .values()
+                .filter(|info| match &info.parents {
+                    None => true,
+                    Some(parents) => {
+                        names.contains_key(&parents.self_parent)
+                            && names.contains_key(&parents.other_parent)
+                    }
+                })
+                .min_by_key(|info| (info.timestamp, info.hash.clone()))
+                .expect("Graph is acyclic")
+                .hash
+                .clone();
+            let info = infos.remove(&ready).expect("Just found");
+            let peer = peer_names[&info.author];
+            let name = match &info.parents {
+                None => format!("{}{}", GENESIS_PREFIX, peer),
+                Some(parents) => {
+                    let count = counts.entry(peer).or_default();
+                    *count += 1;
+                    let name = format!("{}_{}", peer, count);
+                    events.push(FixtureEvent {
+                        name: name.clone(),
+                        creator: names[&parents.self_parent].clone(),
+                        other_parent: names[&parents.other_parent].clone(),
+                        timestamp: info.timestamp,
+                    });
+                    name
+                }
+            };
+            names.insert(ready, name);
+        }
+        let mut fixture = Fixture {
+            name: name.to_owned(),
+            source: "Exported from a graph".to_owned(),
+            peers,
+            coin_frequency: graph.coin_frequency(),
+            events,
+            expected: Outcome::default(),
+        };
+        fixture.expected = fixture.observe().expect("Exported graph builds");
+        fixture
+    }
+}
+
+/// Outcome of consensus on `graph`, whose events are all in `names`.
+/// Consumes the finalized events.
+pub(super) fn outcome(
+    graph: &mut TestGraph<(), u64>,
+    names: &HashMap<event::Hash, String>,
+) -> Outcome {
+    let mut outcome = Outcome::default();
+    for (hash, name) in names {
+        let info = graph
+            .event_info(hash)
+            .expect("Named events are in the graph");
+        outcome.rounds.insert(name.clone(), info.round);
+        if let Some(fame) = &info.witness {
+            outcome.witnesses.insert(name.clone(), fame.into());
+        }
+    }
+    while let Some(event) = graph.next_finalized_event() {
+        outcome.order.push(names[event.hash()].clone());
+    }
+    outcome
+}
+
+/// Push `events` of `reference` (built from their fixture) into `graph` one
+/// by one, calling `after` after each of them. Lets tests see a graph grow
+/// the way it does when syncing, see [`Fixture::build_geneses`].
+///
+/// # Panics
+/// If `graph` rejects an event.
+pub fn replay(
+    reference: &BuiltGraph<(), u64>,
+    events: &[FixtureEvent],
+    graph: &mut TestGraph<(), u64>,
+    mut after: impl FnMut(&mut TestGraph<(), u64>, &FixtureEvent),
+) {
+    for event in events {
+        let signed = reference
+            .graph
+            .event(reference.hash(&event.name))
+            .expect("Built events are in the graph");
+        let (unsigned, signature) = signed.inner().clone().into_parts();
+        graph
+            .push_event(unsigned, signature)
+            .unwrap_or_else(|e| panic!("'{}' rejected: {}", event.name, e));
+        after(graph, event);
+    }
+}
+
+/// Panics with the list of differences if the consensus on the fixture's
+/// graph is not the expected one
+pub fn assert_consensus_matches(fixture: &Fixture) {
+    let observed = fixture
+        .observe()
+        .unwrap_or_else(|e| panic!("Fixture '{}' does not build: {}", fixture.name, e));
+    let mismatches = differences(&fixture.expected, &observed);
+    if !mismatches.is_empty() {
+        panic!(
+            "Consensus on fixture '{}' does not match:\n{}",
+            fixture.name,
+            mismatches.join("\n")
+        );
+    }
+}
+
+/// Human-readable differences of `observed` from `expected`, empty if they
+/// are the same
+pub fn differences(expected: &Outcome, observed: &Outcome) -> Vec<String> {
+    let mut mismatches = vec![];
+    diff_maps("round", &expected.rounds, &observed.rounds, &mut mismatches);
+    diff_maps(
+        "fame",
+        &expected.witnesses,
+        &observed.witnesses,
+        &mut mismatches,
+    );
+    if expected.order != observed.order {
+        let position = expected
+            .order
+            .iter()
+            .zip(&observed.order)
+            .position(|(e, o)| e != o)
+            .unwrap_or_else(|| expected.order.len().min(observed.order.len()));
+        mismatches.push(format!(
+            "order differs from position {}: expected {:?}, got {:?}",
+            position,
+            &expected.order[position..],
+            &observed.order[position..]
+        ));
+    }
+    mismatches
+}
+
+fn diff_maps<T: PartialEq + Debug>(
+    what: &str,
+    expected: &BTreeMap<String, T>,
+    observed: &BTreeMap<String, T>,
+    mismatches: &mut Vec<String>,
+) {
+    for (name, value) in expected {
+        match observed.get(name) {
+            Some(observed) if observed == value => (),
+            observed => mismatches.push(format!(
+                "{} of '{}': expected {:?}, got {:?}",
+                what, name, value, observed
+            )),
+        }
+    }
+    for (name, value) in observed {
+        if !expected.contains_key(name) {
+            mismatches.push(format!(
+                "{} of '{}': expected none, got {:?}",
+                what, name, value
+            ));
+        }
+    }
+}
+
+fn load(json: &str) -> Fixture {
+    Fixture::from_json(json).expect("Canonical fixtures are valid")
+}
+
+/// Graph and answers from "Hashgraph consensus: detailed examples"
+/// (SWIRLDS-TR-2016-02). Events with equal consensus timestamps are
+/// ordered by signatures, so their order differs from the report.
+pub fn detailed_example() -> Fixture {
+    load(include_str!(
+        "../../resources/fixtures/detailed_example.json"
+    ))
+}
+
+/// Graph from the figures of the whitepaper (SWIRLDS-TR-2016-01)
+pub fn whitepaper() -> Fixture {
+    load(include_str!("../../resources/fixtures/whitepaper.json"))
+}
+
+/// Fork on top of a genesis, as in `resources/graph_fork.png`
+pub fn fork() -> Fixture {
+    load(include_str!("../../resources/fixtures/fork.json"))
+}
+
+/// Larger graph of random gossip between 5 members. The outcome is
+/// recorded from this implementation, so it only catches regressions.
+pub fn random_gossip() -> Fixture {
+    load(include_str!("../../resources/fixtures/random_gossip.json"))
+}
+
+pub fn canonical() -> Vec<Fixture> {
+    vec![detailed_example(), whitepaper(), fork(), random_gossip()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_fixtures_match() {
+        for fixture in canonical() {
+            assert_consensus_matches(&fixture);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "round of 'd2'")]
+    fn mismatch_reported() {
+        let mut fixture = detailed_example();
+        fixture.expected.rounds.insert("d2".to_owned(), 5);
+        assert_consensus_matches(&fixture);
+    }
+
+    #[test]
+    fn json_roundtrips() {
+        let fixture = whitepaper();
+        assert_eq!(Fixture::from_json(&fixture.to_json()).unwrap(), fixture);
+    }
+}
@@ -0,0 +1,461 @@
+//! Utilities for describing consensus scenarios in tests (`testing`
+//! feature).
+//!
+//! [`GraphBuilder`] builds a graph from named events, so that a scenario
+//! reads like a drawing of the hashgraph:
+//!
+//! ```
+//! use rust_hashgraph::testing::GraphBuilder;
+//!
+//! let built = GraphBuilder::new("a", 0, (), 999)
+//!     .peer("b", 1)
+//!     .event("b1", "b", "a") // b's event with a's genesis as other parent
+//!     .event("a1", "a", "b1")
+//!     .fork("b1_fork", "b1", "a1") // second event on top of b's genesis
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(built.graph.fork_siblings(built.hash("b1")).unwrap().len(), 2);
+//! assert_eq!(built.name(built.hash("a1")), Some("a1"));
+//! ```
+//!
+//! All members use [`MockSigner`] and events get explicit timestamps (0
+//! unless set with [`GraphBuilder::timestamp`]), so the same description
+//! always gives the same hashes.
+//!
+//! [`fixture`] has graphs with known consensus outcome, [`differential`]
+//! compares the consensus with another implementation and [`shrink`]
+//! minimizes failing scenarios. `conformance` (feature `conformance`)
+//! checks another implementation on its own, and `sessions` replays sync
+//! sessions recorded in older wire format versions.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::algorithm::codec::PayloadCodec;
+use crate::algorithm::datastructure::Graph;
+use crate::algorithm::event::{self, Parents, SignedEvent};
+use crate::algorithm::{IncrementalClock, MockSigner, PushError, Signer};
+use crate::Timestamp;
+
+#[cfg(any(test, feature = "conformance"))]
+pub mod conformance;
+pub mod differential;
+pub mod fixture;
+#[cfg(any(test, feature = "conformance"))]
+pub mod sessions;
+pub mod shrink;
+
+pub type TestGraph<TPayload, TPeerId> =
+    Graph<TPayload, (), TPeerId, MockSigner<TPeerId, ()>, IncrementalClock>;
+
+/// Variable with the seed for randomized tests, a number or `random`
+pub const SEED_VARIABLE: &str = "HASHGRAPH_SEED";
+
+/// Seed from [`SEED_VARIABLE`] if set, `default` otherwise. Tests stay
+/// deterministic by default, while failures seen with another seed can be
+/// reproduced exactly.
+///
+/// # Panics
+/// If the variable is neither a number nor `random`.
+pub fn seed_from_env(default: u64) -> u64 {
+    match std::env::var(SEED_VARIABLE) {
+        Ok(value) if value == "random" => rand::random(),
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} must be a number or 'random'", SEED_VARIABLE)),
+        Err(_) => default,
+    }
+}
+
+/// Push the events of `source` that `target` doesn't have, e.g. to replay
+/// a scenario into a graph with other settings.
+///
+/// # Panics
+/// If `target` rejects any of them.
+pub fn replay<TPayload, TPeerId>(
+    source: &TestGraph<TPayload, TPeerId>,
+    target: &mut TestGraph<TPayload, TPeerId>,
+) where
+    TPayload: PayloadCodec + Eq + Hash + Debug + Clone,
+    TPeerId: Serialize + Eq + Hash + Debug + Clone,
+{
+    let jobs = source
+        .generate_sync_for_summary(&target.summary())
+        .expect("own tips are known");
+    if let Err(e) = target.apply_sync_jobs(jobs) {
+        panic!("valid event is rejected: {e}");
+    }
+}
+
+/// Prefix of the names of geneses, e.g. `GENESIS_a` for peer `a`
+pub const GENESIS_PREFIX: &str = "GENESIS_";
+
+// Only constructed once per build, size does not matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Error, Debug)]
+pub enum BuildError<TPeerId> {
+    #[error("Unknown peer or event name '{0}'")]
+    UnknownName(String),
+    #[error("Name '{0}' is already used")]
+    NameClash(String),
+    #[error("Failed to push event '{name}': {source}")]
+    Push {
+        name: String,
+        source: PushError<TPeerId>,
+    },
+}
+
+struct Peer<TPeerId> {
+    id: TPeerId,
+    /// Genesis first, then in the order of addition
+    events: Vec<event::Hash>,
+}
+
+/// Builds a [`TestGraph`] from a description of its events. See the
+/// [module docs](self) for an example.
+///
+/// Errors are reported by [`build`](Self::build), steps after the first
+/// error are ignored.
+pub struct GraphBuilder<TPayload, TPeerId> {
+    graph: TestGraph<TPayload, TPeerId>,
+    peers: HashMap<String, Peer<TPeerId>>,
+    events: HashMap<String, event::Hash>,
+    payload: TPayload,
+    timestamp: Timestamp,
+    error: Option<BuildError<TPeerId>>,
+}
+
+impl<TPayload, TPeerId> GraphBuilder<TPayload, TPeerId>
+where
+    TPayload: PayloadCodec + Eq + Hash + Debug + Clone,
+    TPeerId: Serialize + Eq + Hash + Debug + Clone,
+{
+    /// Graph of peer `self_name`. `payload` is used for geneses and events
+    /// until changed with [`payload`](Self::payload).
+    pub fn new(
+        self_name: &str,
+        self_id: TPeerId,
+        payload: TPayload,
+        coin_frequency: usize,
+    ) -> Self {
+        let graph = Graph::new(
+            self_id.clone(),
+            payload.clone(),
+            (),
+            coin_frequency,
+            MockSigner::new(),
+            IncrementalClock::new(),
+        );
+        let genesis = graph.self_tip().unwrap().clone();
+        Self {
+            graph,
+            peers: HashMap::from([(
+                self_name.to_owned(),
+                Peer {
+                    id: self_id,
+                    events: vec![genesis.clone()],
+                },
+            )]),
+            events: HashMap::from([(format!("{}{}", GENESIS_PREFIX, self_name), genesis)]),
+            payload,
+            timestamp: 0,
+            error: None,
+        }
+    }
+
+    /// Add a member with its genesis
+    pub fn peer(self, name: &str, id: TPeerId) -> Self {
+        self.step(|b| b.try_peer(name, id))
+    }
+
+    /// Payload of the events added from now on
+    pub fn payload(mut self, payload: TPayload) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Timestamp of the events added from now on
+    pub fn timestamp(mut self, timestamp: Timestamp) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// Add event `name`. `creator` is either
+    /// * name of a peer, then its latest added event is the self parent;
+    /// * name of an event (`GENESIS_<peer>` for geneses), which becomes the
+    ///   self parent. If it already has a self child, this is a fork.
+    ///
+    /// `other_parent` is either name of a peer for its genesis or name of
+    /// an event.
+    pub fn event(self, name: &str, creator: &str, other_parent: &str) -> Self {
+        self.step(|b| {
+            let self_parent = match b.events.get(creator) {
+                Some(event) => event.clone(),
+                None => b
+                    .peers
+                    .get(creator)
+                    .map(|peer| peer.events.last().expect("geneses are added").clone())
+                    .ok_or_else(|| BuildError::UnknownName(creator.to_owned()))?,
+            };
+            b.try_event(name, self_parent, other_parent)
+        })
+    }
+
+    /// Add event `name` with the same self parent as `sibling`
+    pub fn fork(self, name: &str, sibling: &str, other_parent: &str) -> Self {
+        self.step(|b| {
+            let sibling_hash = b
+                .events
+                .get(sibling)
+                .ok_or_else(|| BuildError::UnknownName(sibling.to_owned()))?;
+            let event::Kind::Regular(parents) = b
+                .graph
+                .event(sibling_hash)
+                .expect("added events are in the graph")
+                .kind()
+            else {
+                return Err(BuildError::UnknownName(sibling.to_owned()));
+            };
+            let self_parent = parents.self_parent.clone();
+            b.try_event(name, self_parent, other_parent)
+        })
+    }
+
+    /// Several [`event`](Self::event)s, given as (`name`, `creator`,
+    /// `other_parent`)
+    pub fn events(self, events: &[(&str, &str, &str)]) -> Self {
+        events
+            .iter()
+            .fold(self, |b, &(name, creator, other_parent)| {
+                b.event(name, creator, other_parent)
+            })
+    }
+
+    pub fn build(self) -> Result<BuiltGraph<TPayload, TPeerId>, BuildError<TPeerId>> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let names = self
+            .events
+            .iter()
+            .map(|(name, hash)| (hash.clone(), name.clone()))
+            .collect();
+        Ok(BuiltGraph {
+            graph: self.graph,
+            peers: self.peers,
+            events: self.events,
+            names,
+        })
+    }
+
+    fn step<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut Self) -> Result<(), BuildError<TPeerId>>,
+    {
+        if self.error.is_none() {
+            if let Err(e) = f(&mut self) {
+                self.error = Some(e);
+            }
+        }
+        self
+    }
+
+    fn try_peer(&mut self, name: &str, id: TPeerId) -> Result<(), BuildError<TPeerId>> {
+        let genesis_name = format!("{}{}", GENESIS_PREFIX, name);
+        if self.peers.contains_key(name) {
+            return Err(BuildError::NameClash(name.to_owned()));
+        }
+        let genesis = self.sign_and_push(&genesis_name, event::Kind::Genesis(()), id.clone(), 0)?;
+        self.peers.insert(
+            name.to_owned(),
+            Peer {
+                id,
+                events: vec![genesis.clone()],
+            },
+        );
+        self.events.insert(genesis_name, genesis);
+        Ok(())
+    }
+
+    fn try_event(
+        &mut self,
+        name: &str,
+        self_parent: event::Hash,
+        other_parent: &str,
+    ) -> Result<(), BuildError<TPeerId>> {
+        if self.events.contains_key(name) {
+            return Err(BuildError::NameClash(name.to_owned()));
+        }
+        let other_parent = match self.peers.get(other_parent) {
+            Some(peer) => peer.events[0].clone(),
+            None => self
+                .events
+                .get(other_parent)
+                .ok_or_else(|| BuildError::UnknownName(other_parent.to_owned()))?
+                .clone(),
+        };
+        let author = self
+            .graph
+            .event(&self_parent)
+            .expect("added events are in the graph")
+            .author()
+            .clone();
+        let hash = self.sign_and_push(
+            name,
+            event::Kind::Regular(Parents {
+                self_parent,
+                other_parent,
+            }),
+            author.clone(),
+            self.timestamp,
+        )?;
+        self.peers
+            .values_mut()
+            .find(|peer| peer.id == author)
+            .expect("authors of added events are added")
+            .events
+            .push(hash.clone());
+        self.events.insert(name.to_owned(), hash);
+        Ok(())
+    }
+
+    fn sign_and_push(
+        &mut self,
+        name: &str,
+        kind: event::Kind<()>,
+        author: TPeerId,
+        timestamp: Timestamp,
+    ) -> Result<event::Hash, BuildError<TPeerId>> {
+        let push_error = |source| BuildError::Push {
+            name: name.to_owned(),
+            source,
+        };
+        let event = SignedEvent::new(self.payload.clone(), kind, author, timestamp, |h| {
+            MockSigner::<TPeerId, ()>::new().sign(h)
+        })
+        .map_err(|e| push_error(e.into()))?;
+        let hash = event.hash().clone();
+        let (unsigned, signature) = event.into_parts();
+        self.graph
+            .push_event(unsigned, signature)
+            .map_err(push_error)?;
+        Ok(hash)
+    }
+}
+
+/// Graph made by [`GraphBuilder`] with lookups by the names used there
+pub struct BuiltGraph<TPayload, TPeerId> {
+    pub graph: TestGraph<TPayload, TPeerId>,
+    peers: HashMap<String, Peer<TPeerId>>,
+    events: HashMap<String, event::Hash>,
+    names: HashMap<event::Hash, String>,
+}
+
+impl<TPayload, TPeerId> BuiltGraph<TPayload, TPeerId> {
+    /// Hash of the event named `name`.
+    ///
+    /// # Panics
+    /// If there is no such event, see [`get`](Self::get) for a
+    /// non-panicking version.
+    pub fn hash(&self, name: &str) -> &event::Hash {
+        self.get(name)
+            .unwrap_or_else(|| panic!("Unknown event name '{}'", name))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&event::Hash> {
+        self.events.get(name)
+    }
+
+    /// Name of the event, `GENESIS_<peer>` for geneses
+    pub fn name(&self, hash: &event::Hash) -> Option<&str> {
+        self.names.get(hash).map(String::as_str)
+    }
+
+    pub fn peer_id(&self, name: &str) -> Option<&TPeerId> {
+        self.peers.get(name).map(|peer| &peer.id)
+    }
+
+    /// Events of the peer: genesis, then others in the order of addition
+    pub fn peer_events(&self, name: &str) -> Option<&[event::Hash]> {
+        self.peers.get(name).map(|peer| peer.events.as_slice())
+    }
+
+    pub fn peer_names(&self) -> impl Iterator<Item = &str> {
+        self.peers.keys().map(String::as_str)
+    }
+
+    /// All events with their names
+    pub fn events(&self) -> impl Iterator<Item = (&str, &event::Hash)> {
+        self.events.iter().map(|(name, hash)| (name.as_str(), hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn two_peers() -> GraphBuilder<(), u64> {
+        GraphBuilder::new("a", 0, (), 999).peer("b", 1)
+    }
+
+    #[test]
+    fn lookups_work() {
+        let built = two_peers()
+            .events(&[("b1", "b", "a"), ("a1", "a", "b1"), ("b2", "b", "a1")])
+            .build()
+            .unwrap();
+        assert_eq!(built.peer_id("b"), Some(&1));
+        assert_eq!(
+            built.peer_events("b").unwrap(),
+            ["GENESIS_b", "b1", "b2"].map(|name| built.hash(name).clone())
+        );
+        let b2 = built.graph.event(built.hash("b2")).unwrap();
+        let event::Kind::Regular(parents) = b2.kind() else {
+            panic!("b2 is not genesis")
+        };
+        assert_eq!(built.name(&parents.self_parent), Some("b1"));
+        assert_eq!(built.name(&parents.other_parent), Some("a1"));
+        assert!(built.get("c1").is_none());
+    }
+
+    #[test]
+    fn fork_works() {
+        let built = two_peers()
+            .event("b1", "b", "a")
+            .payload(())
+            .timestamp(1)
+            .fork("b1_fork", "b1", "a")
+            .build()
+            .unwrap();
+        let mut siblings = built.graph.fork_siblings(built.hash("b1")).unwrap();
+        siblings.sort();
+        let mut expected = vec![built.hash("b1").clone(), built.hash("b1_fork").clone()];
+        expected.sort();
+        assert_eq!(siblings, expected);
+    }
+
+    #[test]
+    fn errors_reported() {
+        assert!(matches!(
+            two_peers().event("b1", "c", "a").build(),
+            Err(BuildError::UnknownName(name)) if name == "c"
+        ));
+        assert!(matches!(
+            two_peers().event("b1", "b", "a").event("b1", "a", "b").build(),
+            Err(BuildError::NameClash(name)) if name == "b1"
+        ));
+        assert!(matches!(
+            two_peers().peer("b", 2).build(),
+            Err(BuildError::NameClash(name)) if name == "b"
+        ));
+        // Same fields as `b1`
+        assert!(matches!(
+            two_peers().event("b1", "b", "a").fork("b2", "b1", "a").build(),
+            Err(BuildError::Push { name, source: PushError::EventAlreadyExists(_) }) if name == "b2"
+        ));
+    }
+}
@@ -0,0 +1,338 @@
+//! Sync sessions recorded by earlier versions (`conformance` feature).
+//!
+//! A [`GoldenSession`] is the sequence of encoded messages two nodes
+//! exchanged in a sync, in the wire format of one version. Sessions of
+//! every supported version are kept in `resources/conformance`. [`replay`]
+//! plays one side with the current code: it handles the messages of the
+//! other side and checks that its own requests and replies decode to what
+//! was recorded, so changes to the handling of [`SyncRequest`] and [`Jobs`]
+//! that break older peers are found before a release. [`fuzz`] does the
+//! same with damaged messages, which must be rejected without panics and
+//! without breaking the graph.
+//!
+//! Whenever [`WIRE_VERSION`] is bumped, a session of the new version is
+//! added from [`record_session`], and the older ones are kept.
+//!
+//! Both nodes use `u32` payloads and `u64` peer ids: the initiator is
+//! `Graph::new(8, 43, ..)` with one own event, the responder is
+//! `Graph::new(7, 42, ..)` with two.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use super::conformance::Failure;
+use super::TestGraph;
+use crate::algorithm::datastructure::sync::wire::{WireMessage, MIN_WIRE_VERSION, WIRE_VERSION};
+use crate::algorithm::datastructure::sync::{Jobs, SyncRequest};
+use crate::algorithm::datastructure::Graph;
+use crate::algorithm::{IncrementalClock, MockSigner};
+
+type SessionGraph = TestGraph<u32, u64>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Sends the first request
+    Initiator,
+    Responder,
+}
+
+impl Role {
+    fn other(self) -> Self {
+        match self {
+            Role::Initiator => Role::Responder,
+            Role::Responder => Role::Initiator,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SessionMessage {
+    pub from: Role,
+    /// `jobs` or `sync_request`
+    pub kind: String,
+    pub hex: String,
+}
+
+impl SessionMessage {
+    fn new(from: Role, kind: &str, bytes: &[u8]) -> Self {
+        Self {
+            from,
+            kind: kind.to_owned(),
+            hex: bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+        }
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        (0..self.hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&self.hex[i..i + 2], 16).expect("Sessions are valid hex"))
+            .collect()
+    }
+}
+
+/// See the [module docs](self)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GoldenSession {
+    pub name: String,
+    pub version: u16,
+    pub description: String,
+    pub messages: Vec<SessionMessage>,
+}
+
+#[derive(Deserialize)]
+struct GoldenSessions {
+    sessions: Vec<GoldenSession>,
+}
+
+/// Recorded sessions of all versions, oldest first
+pub fn golden_sessions() -> Vec<GoldenSession> {
+    let sessions: GoldenSessions = serde_json::from_str(include_str!(
+        "../../resources/conformance/sync_sessions.json"
+    ))
+    .expect("Sessions are valid");
+    sessions.sessions
+}
+
+fn node(role: Role) -> SessionGraph {
+    let (id, genesis_payload, events) = match role {
+        Role::Initiator => (8, 43, 1),
+        Role::Responder => (7, 42, 2),
+    };
+    let mut graph = Graph::new(
+        id,
+        genesis_payload,
+        (),
+        999,
+        MockSigner::new(),
+        IncrementalClock::new(),
+    );
+    for payload in 0..events {
+        let tip = graph.self_tip().unwrap().clone();
+        graph
+            .create_event(payload, tip)
+            .expect("Own events are valid");
+    }
+    graph
+}
+
+/// The session of the current version: the initiator asks, the responder
+/// replies, then the other way around
+pub fn record_session() -> GoldenSession {
+    let mut nodes = [node(Role::Initiator), node(Role::Responder)];
+    let mut messages = vec![];
+    for (asking, answering) in [(0, 1), (1, 0)] {
+        let request = nodes[asking].sync_request();
+        let jobs = nodes[answering]
+            .generate_sync_for_request(&request)
+            .expect("Tips are known");
+        let roles = [Role::Initiator, Role::Responder];
+        let encoded = request.to_wire().expect("Requests are encodable");
+        messages.push(SessionMessage::new(roles[asking], "sync_request", &encoded));
+        let encoded = jobs.to_wire().expect("Jobs are encodable");
+        messages.push(SessionMessage::new(roles[answering], "jobs", &encoded));
+        nodes[asking].apply_sync_jobs(jobs).expect("Jobs are valid");
+    }
+    GoldenSession {
+        name: format!("exchange_v{}", WIRE_VERSION),
+        version: WIRE_VERSION,
+        description: "Initiator asks, responder replies, then the other way around".to_owned(),
+        messages,
+    }
+}
+
+/// Play `role` against the other side's messages. Returns the graph and
+/// the first rejected message or, if `strict`, difference from the
+/// recorded messages of `role`.
+fn play(
+    role: Role,
+    messages: &[(Role, &str, Vec<u8>)],
+    strict: bool,
+) -> (SessionGraph, Result<(), String>) {
+    let mut graph = node(role);
+    let result = play_on(&mut graph, role, messages, strict);
+    (graph, result)
+}
+
+fn play_on(
+    graph: &mut SessionGraph,
+    role: Role,
+    messages: &[(Role, &str, Vec<u8>)],
+    strict: bool,
+) -> Result<(), String> {
+    let mut reply = None;
+    for (i, (from, kind, bytes)) in messages.iter().enumerate() {
+        match (*from == role, *kind) {
+            (false, "sync_request") => {
+                let mut request =
+                    SyncRequest::from_wire(bytes).map_err(|e| format!("message #{}: {}", i, e))?;
+                graph
+                    .apply_sync_request(&mut request)
+                    .map_err(|e| format!("message #{}: {}", i, e))?;
+                let jobs = graph
+                    .generate_sync_for_request(&request)
+                    .map_err(|e| format!("message #{}: {}", i, e))?;
+                reply = Some(jobs);
+            }
+            (false, "jobs") => {
+                let jobs = Jobs::from_wire(bytes).map_err(|e| format!("message #{}: {}", i, e))?;
+                graph
+                    .apply_sync_jobs(jobs)
+                    .map_err(|e| format!("message #{}: {}", i, e))?;
+            }
+            (true, "sync_request") => {
+                let recorded = SyncRequest::from_wire(bytes);
+                if strict && recorded.ok().as_ref() != Some(&graph.sync_request()) {
+                    return Err(format!("message #{}: request differs", i));
+                }
+            }
+            (true, "jobs") => {
+                let ours = reply
+                    .take()
+                    .ok_or_else(|| format!("message #{}: reply without a request", i))?;
+                let recorded = Jobs::from_wire(bytes);
+                if strict && recorded.ok().as_ref() != Some(&ours) {
+                    return Err(format!("message #{}: reply differs", i));
+                }
+            }
+            (_, kind) => return Err(format!("message #{}: unknown kind {}", i, kind)),
+        }
+    }
+    Ok(())
+}
+
+fn decoded(session: &GoldenSession) -> Vec<(Role, &str, Vec<u8>)> {
+    session
+        .messages
+        .iter()
+        .map(|m| (m.from, m.kind.as_str(), m.bytes()))
+        .collect()
+}
+
+/// Play `role` of the session with the current code, see the [module
+/// docs](self). Returns the graph at the end.
+pub fn replay(session: &GoldenSession, role: Role) -> Result<SessionGraph, Failure> {
+    let check = format!("session '{}' as {:?}", session.name, role);
+    if !(MIN_WIRE_VERSION..=WIRE_VERSION).contains(&session.version) {
+        return Err(Failure {
+            check,
+            details: vec![format!("version {} is not supported", session.version)],
+        });
+    }
+    let (graph, result) = play(role, &decoded(session), true);
+    let mut details: Vec<_> = result.err().into_iter().collect();
+    details.extend(graph.verify_integrity().iter().map(|v| v.to_string()));
+    match details.is_empty() {
+        true => Ok(graph),
+        false => Err(Failure { check, details }),
+    }
+}
+
+/// Play `role` against `cases` damaged versions of the other side's
+/// messages: flipped bits, truncated or repeated messages and other
+/// version numbers. Returns the cases that panicked or left the graph
+/// inconsistent.
+pub fn fuzz(session: &GoldenSession, role: Role, seed: u64, cases: u64) -> Vec<Failure> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let original = decoded(session);
+    let theirs: Vec<_> = (0..original.len())
+        .filter(|&i| original[i].0 == role.other())
+        .collect();
+    let mut failures = vec![];
+    for case in 0..cases {
+        let mut messages = original.clone();
+        let target = theirs[rng.gen_range(0..theirs.len())];
+        let bytes = &mut messages[target].2;
+        let mutation = match rng.gen_range(0..4) {
+            0 => {
+                let bit = rng.gen_range(0..bytes.len() * 8);
+                bytes[bit / 8] ^= 1 << (bit % 8);
+                format!("bit {} flipped", bit)
+            }
+            1 => {
+                let len = rng.gen_range(0..bytes.len());
+                bytes.truncate(len);
+                format!("truncated to {} bytes", len)
+            }
+            2 => {
+                let version = rng.gen_range(MIN_WIRE_VERSION..=WIRE_VERSION + 1);
+                bytes[..2].copy_from_slice(&version.to_le_bytes());
+                format!("version set to {}", version)
+            }
+            _ => {
+                let repeated = messages[target].clone();
+                messages.insert(target, repeated);
+                "repeated".to_owned()
+            }
+        };
+        let check = format!(
+            "session '{}' as {:?}, case {} (seed {})",
+            session.name, role, case, seed
+        );
+        let details = match catch_unwind(AssertUnwindSafe(|| play(role, &messages, false))) {
+            Ok((graph, _)) => graph
+                .verify_integrity()
+                .iter()
+                .map(|v| v.to_string())
+                .collect(),
+            Err(_) => vec!["panicked".to_owned()],
+        };
+        if !details.is_empty() {
+            let mut details = details;
+            details.insert(0, format!("message #{} {}", target, mutation));
+            failures.push(Failure { check, details });
+        }
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::seed_from_env;
+
+    #[test]
+    fn current_session_recorded() {
+        let current: Vec<_> = golden_sessions()
+            .into_iter()
+            .filter(|s| s.version == WIRE_VERSION)
+            .collect();
+        assert_eq!(current, vec![record_session()]);
+    }
+
+    #[test]
+    fn older_sessions_replayed() {
+        let sessions = golden_sessions();
+        assert!(sessions.iter().any(|s| s.version < WIRE_VERSION));
+        for session in sessions {
+            let initiator = replay(&session, Role::Initiator).unwrap();
+            let responder = replay(&session, Role::Responder).unwrap();
+            assert_eq!(initiator.summary(), responder.summary(), "{}", session.name);
+        }
+    }
+
+    #[test]
+    fn divergence_reported() {
+        let mut session = record_session();
+        // The responder replied with the initiator's events
+        session.messages[1].hex = session.messages[3].hex.clone();
+        let Err(failure) = replay(&session, Role::Responder) else {
+            panic!("Divergence not reported");
+        };
+        assert_eq!(failure.details, vec!["message #1: reply differs"]);
+    }
+
+    #[test]
+    fn damaged_messages_rejected() {
+        let seed = seed_from_env(0);
+        for session in golden_sessions() {
+            for role in [Role::Initiator, Role::Responder] {
+                let failures = fuzz(&session, role, seed, 64);
+                assert!(failures.is_empty(), "seed {}: {:?}", seed, failures);
+            }
+        }
+    }
+}
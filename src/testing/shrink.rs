@@ -0,0 +1,282 @@
+//! Minimization of failing scenarios.
+//!
+//! [`shrink`] removes events (together with their descendants) from a
+//! scenario while the failure persists, [`builder_snippet`] prints the
+//! result as [`GraphBuilder`](super::GraphBuilder) code to paste into a
+//! test.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::fixture::{self, Fixture, FixtureEvent};
+use super::{BuiltGraph, TestGraph};
+use crate::algorithm::datastructure::Graph;
+use crate::algorithm::event;
+use crate::algorithm::{IncrementalClock, MockSigner};
+
+/// Smallest found subset of `scenario` for which `fails` holds, with
+/// parents named explicitly and `expected` filled by this crate. Geneses
+/// are always kept.
+///
+/// # Panics
+/// If the scenario does not build or does not fail to begin with.
+pub fn shrink<F>(scenario: &Fixture, mut fails: F) -> Fixture
+where
+    F: FnMut(&Fixture) -> bool,
+{
+    let mut current = explicit(scenario);
+    assert!(
+        fails(&current),
+        "Scenario '{}' does not fail",
+        scenario.name
+    );
+    let mut reduced = true;
+    while reduced {
+        reduced = false;
+        let mut i = current.events.len();
+        while i > 0 {
+            i -= 1;
+            if i >= current.events.len() {
+                continue;
+            }
+            let candidate = without_descendants(&current, i);
+            if fails(&candidate) {
+                current = candidate;
+                reduced = true;
+            }
+        }
+    }
+    current.name = format!("{} (shrunk)", scenario.name);
+    current.expected = current
+        .observe()
+        .expect("Subsets closed under ancestry build");
+    current
+}
+
+/// Rust code building the scenario's graph
+pub fn builder_snippet(scenario: &Fixture) -> String {
+    let mut snippet = String::new();
+    let mut peers = scenario.peers.iter().enumerate();
+    if let Some((id, name)) = peers.next() {
+        writeln!(
+            snippet,
+            "GraphBuilder::new({:?}, {}u64, (), {})",
+            name, id, scenario.coin_frequency
+        )
+        .unwrap();
+    }
+    for (id, name) in peers {
+        writeln!(snippet, "    .peer({:?}, {})", name, id).unwrap();
+    }
+    let mut timestamp = 0;
+    for event in &scenario.events {
+        if event.timestamp != timestamp {
+            timestamp = event.timestamp;
+            writeln!(snippet, "    .timestamp({})", timestamp).unwrap();
+        }
+        writeln!(
+            snippet,
+            "    .event({:?}, {:?}, {:?})",
+            event.name, event.creator, event.other_parent
+        )
+        .unwrap();
+    }
+    snippet.push_str("    .build()\n    .unwrap()");
+    snippet
+}
+
+/// Whether pushing the events in a random (by `seed`) order, that still
+/// has parents first, gives another outcome than the listed order. A
+/// predicate for [`shrink`] when replicas disagree.
+pub fn order_dependent(scenario: &Fixture, seed: u64) -> bool {
+    let Ok(built) = scenario.build() else {
+        return false;
+    };
+    let names = names(&built);
+    let mut shuffled: TestGraph<(), u64> = Graph::new(
+        0,
+        (),
+        (),
+        scenario.coin_frequency,
+        MockSigner::new(),
+        IncrementalClock::new(),
+    );
+    let mut pushed: HashSet<_> = HashSet::from([shuffled.self_tip().unwrap().clone()]);
+    // Membership is known from the start
+    let mut remaining = vec![];
+    for hash in names.keys() {
+        let event = built
+            .graph
+            .event(hash)
+            .expect("Named events are in the graph");
+        match event.kind() {
+            event::Kind::Genesis(_) if !pushed.contains(hash) => {
+                let (unsigned, signature) = event.inner().clone().into_parts();
+                shuffled
+                    .push_event(unsigned, signature)
+                    .expect("Valid events are accepted");
+                pushed.insert(hash.clone());
+            }
+            event::Kind::Genesis(_) => (),
+            event::Kind::Regular(_) => remaining.push(event.inner().clone()),
+        }
+    }
+    remaining.sort_by(|a, b| a.hash().cmp(b.hash()));
+    let mut rng = StdRng::seed_from_u64(seed);
+    while !remaining.is_empty() {
+        let ready: Vec<_> = (0..remaining.len())
+            .filter(|&i| match remaining[i].unsigned().fields().kind() {
+                event::Kind::Genesis(_) => unreachable!("Geneses are pushed first"),
+                event::Kind::Regular(parents) => {
+                    pushed.contains(&parents.self_parent) && pushed.contains(&parents.other_parent)
+                }
+            })
+            .collect();
+        let next = remaining.swap_remove(ready[rng.gen_range(0..ready.len())]);
+        pushed.insert(next.hash().clone());
+        let (unsigned, signature) = next.into_parts();
+        shuffled
+            .push_event(unsigned, signature)
+            .expect("Valid events are accepted");
+    }
+    let Ok(in_order) = scenario.observe() else {
+        return false;
+    };
+    fixture::outcome(&mut shuffled, &names) != in_order
+}
+
+/// [`order_dependent`] for a few seeds
+pub fn replicas_disagree(scenario: &Fixture) -> bool {
+    (0..4).any(|seed| order_dependent(scenario, seed))
+}
+
+fn names(built: &BuiltGraph<(), u64>) -> HashMap<event::Hash, String> {
+    built
+        .events()
+        .map(|(name, hash)| (hash.clone(), name.to_owned()))
+        .collect()
+}
+
+/// Same scenario with parents of every event named explicitly, so that
+/// removal of events does not change parents of the others
+fn explicit(scenario: &Fixture) -> Fixture {
+    let built = scenario
+        .build()
+        .unwrap_or_else(|e| panic!("Scenario '{}' does not build: {}", scenario.name, e));
+    let names = names(&built);
+    let events = scenario
+        .events
+        .iter()
+        .map(|e| {
+            let event = built.graph.event(built.hash(&e.name)).expect("Just built");
+            let event::Kind::Regular(parents) = event.kind() else {
+                unreachable!("Scenario events are regular");
+            };
+            FixtureEvent {
+                name: e.name.clone(),
+                creator: names[&parents.self_parent].clone(),
+                other_parent: names[&parents.other_parent].clone(),
+                timestamp: e.timestamp,
+            }
+        })
+        .collect();
+    Fixture {
+        events,
+        ..scenario.clone()
+    }
+}
+
+/// `scenario` without its `i`th event and the event's descendants
+fn without_descendants(scenario: &Fixture, i: usize) -> Fixture {
+    let mut removed = HashSet::from([scenario.events[i].name.as_str()]);
+    let mut events = scenario.events[..i].to_vec();
+    for event in &scenario.events[i + 1..] {
+        if removed.contains(event.creator.as_str()) || removed.contains(event.other_parent.as_str())
+        {
+            removed.insert(&event.name);
+        } else {
+            events.push(event.clone());
+        }
+    }
+    Fixture {
+        events,
+        ..scenario.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::GraphBuilder;
+
+    #[test]
+    fn shrinks_to_needed_events() {
+        let scenario = fixture::detailed_example();
+        // Fails while `b2_1` is present
+        let shrunk = shrink(&scenario, |f| f.events.iter().any(|e| e.name == "b2_1"));
+        let names: Vec<_> = shrunk.events.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names.last(), Some(&"b2_1"));
+        let built = shrunk.build().unwrap();
+        // Only ancestors of `b2_1` are left
+        for name in &names {
+            assert!(built
+                .graph
+                .see(built.hash("b2_1"), built.hash(name))
+                .unwrap());
+        }
+    }
+
+    #[test]
+    fn snippet_builds_same_graph() {
+        let scenario = fixture::fork();
+        let snippet = builder_snippet(&explicit(&scenario));
+        assert!(snippet.starts_with("GraphBuilder::new(\"a\", 0u64, (), 999)\n    .peer(\"m\", 1)"));
+        assert!(snippet.contains(".event(\"m2_1\", \"m2_fork\", \"m2\")"));
+        assert!(snippet.contains("    .timestamp(1)\n    .event(\"m2_fork\""));
+        // What the snippet says, by hand
+        let built = GraphBuilder::new("a", 0u64, (), 999)
+            .peer("m", 1)
+            .event("a1_1", "GENESIS_a", "GENESIS_m")
+            .event("m2", "GENESIS_m", "a1_1")
+            .timestamp(1)
+            .event("m2_fork", "GENESIS_m", "a1_1")
+            .timestamp(0)
+            .event("m2_1", "m2_fork", "m2")
+            .build()
+            .unwrap();
+        assert_eq!(built.hash("m2_1"), scenario.build().unwrap().hash("m2_1"));
+    }
+
+    #[test]
+    fn canonical_fixtures_order_independent() {
+        for seed in 0..4 {
+            assert!(!order_dependent(&fixture::detailed_example(), seed));
+            assert!(!order_dependent(&fixture::fork(), seed));
+        }
+    }
+
+    #[test]
+    fn exported_graph_keeps_structure() {
+        let built = fixture::detailed_example().build().unwrap();
+        let exported = Fixture::from_graph("exported", &built.graph);
+        let rebuilt = exported.build().unwrap();
+        assert_eq!(
+            exported.events.len(),
+            fixture::detailed_example().events.len()
+        );
+        // Rounds and fame don't depend on hashes
+        let mut rounds: Vec<_> = exported.expected.rounds.values().collect();
+        let mut original: Vec<_> = fixture::detailed_example()
+            .expected
+            .rounds
+            .into_values()
+            .collect();
+        rounds.sort();
+        original.sort();
+        assert_eq!(rounds, original.iter().collect::<Vec<_>>());
+        assert_eq!(rebuilt.peer_events("p1").unwrap().len(), 12);
+    }
+}